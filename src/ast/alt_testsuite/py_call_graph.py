@@ -0,0 +1,21 @@
+def strip_comments(line):
+    return line.split("#")[0]
+
+
+def load_config(path):
+    with open(path) as f:
+        return f.read()
+
+
+def parse_config(text):
+    return [strip_comments(x) for x in text.splitlines()]
+
+
+def setup(path):
+    text = load_config(path)
+    return parse_config(text)
+
+
+def main():
+    lines = setup("config.txt")
+    print(len(lines))
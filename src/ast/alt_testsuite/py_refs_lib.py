@@ -0,0 +1,6 @@
+def compute_total(items):
+    return sum(items)
+
+
+def unrelated():
+    return 0
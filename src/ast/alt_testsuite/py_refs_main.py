@@ -0,0 +1,10 @@
+from py_refs_lib import compute_total
+
+
+def report(items):
+    total = compute_total(items)
+    print(total)
+
+
+def check(items):
+    return compute_total(items) > 10
@@ -0,0 +1,7 @@
+def compute_total(items):
+    """compute_total adds the items up"""
+    return sum(items)
+
+
+def compute_total_squared(items):
+    return compute_total(items) ** 2
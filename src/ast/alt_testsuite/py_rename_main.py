@@ -0,0 +1,8 @@
+from py_rename_lib import compute_total
+
+
+def report(items):
+    # compute_total is the one from the library
+    total = compute_total(items)
+    print("compute_total:", total)
+    return total
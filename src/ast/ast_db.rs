@@ -173,8 +173,19 @@ pub async fn doc_add(
     errors: &mut AstErrorStats,
 ) -> Result<(Vec<Arc<AstDefinition>>, String), String>
 {
-    let file_global_path = filesystem_path_to_double_colon_path(cpath);
     let (defs, language) = parse_anything_and_add_file_path(&cpath, text, errors)?;   // errors mostly "no such parser" here
+    Ok(doc_add_parsed(ast_index, cpath, defs, language).await)
+}
+
+// The writing half of doc_add(), for files parsed elsewhere
+pub async fn doc_add_parsed(
+    ast_index: Arc<AMutex<AstDB>>,
+    cpath: &String,
+    defs: Vec<AstDefinition>,
+    language: String,
+) -> (Vec<Arc<AstDefinition>>, String)
+{
+    let file_global_path = filesystem_path_to_double_colon_path(cpath);
     let db = ast_index.lock().await.sleddb.clone();
     let batch_arc = flush_sled_batch(ast_index.clone(), 1000).await;
     let mut batch = batch_arc.lock().await;
@@ -227,7 +238,7 @@ pub async fn doc_add(
     _increase_counter(ast_index.clone(), "counters|defs", added_defs).await;
     _increase_counter(ast_index.clone(), "counters|usages", added_usages).await;
 
-    Ok((defs.into_iter().map(Arc::new).collect(), language))
+    (defs.into_iter().map(Arc::new).collect(), language)
 }
 
 pub async fn doc_remove(ast_index: Arc<AMutex<AstDB>>, cpath: &String)
@@ -675,6 +686,58 @@ pub async fn usages(ast_index: Arc<AMutex<AstDB>>, full_official_path: String, l
     usages
 }
 
+pub async fn callees(ast_index: Arc<AMutex<AstDB>>, full_official_path: String) -> (Vec<(Arc<AstDefinition>, usize)>, usize)
+{
+    // Reverse of usages(): what full_official_path uses, returns (target definition, uline) and the number of usages that didn't resolve
+    let db = ast_index.lock().await.sleddb.clone();
+    let d_key = format!("d|{}", full_official_path);
+    let definition = match db.get(d_key.as_bytes()) {
+        Ok(Some(d_value)) => match serde_cbor::from_slice::<AstDefinition>(&d_value) {
+            Ok(definition) => definition,
+            Err(_) => return (vec![], 0),
+        },
+        _ => return (vec![], 0),
+    };
+
+    let mut targets: Vec<(String, usize)> = definition.usages.iter()
+        .filter(|u| !u.resolved_as.is_empty())
+        .map(|u| (u.resolved_as.clone(), u.uline))
+        .collect();
+    let cleanup_key = format!("resolve-cleanup|{}", full_official_path);
+    if let Ok(Some(cleanup_value)) = db.get(cleanup_key.as_bytes()) {
+        let all_saved_ulinks = serde_cbor::from_slice::<Vec<String>>(&cleanup_value).unwrap_or_default();
+        for u_key in all_saved_ulinks {
+            let uline: usize = match db.get(u_key.as_bytes()) {
+                Ok(Some(u_value)) => serde_cbor::from_slice(&u_value).unwrap_or(0),
+                _ => continue,
+            };
+            let parts: Vec<&str> = u_key.strip_prefix("u|").unwrap_or(&u_key).split(" ⚡ ").collect();
+            if parts.len() == 2 {
+                targets.push((parts[0].trim().to_string(), uline));
+            }
+        }
+    }
+    let guesswork_usages_cnt = definition.usages.iter()
+        .filter(|u| u.targets_for_guesswork.iter().any(|t| t.starts_with("?::")) || !u.resolved_as.is_empty())
+        .count();
+    let unresolved_cnt = guesswork_usages_cnt.saturating_sub(targets.len());
+
+    let mut result = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for (target, uline) in targets {
+        if !seen.insert(target.clone()) {
+            continue;
+        }
+        if let Ok(Some(d_value)) = db.get(format!("d|{}", target).as_bytes()) {
+            match serde_cbor::from_slice::<AstDefinition>(&d_value) {
+                Ok(target_def) => result.push((Arc::new(target_def), uline)),
+                Err(e) => tracing::error!("Failed to deserialize value for d|{}: {:?}", target, e),
+            }
+        }
+    }
+    (result, unresolved_cnt)
+}
+
 pub async fn definitions(ast_index: Arc<AMutex<AstDB>>, double_colon_path: &str) -> Vec<Arc<AstDefinition>>
 {
     let db = ast_index.lock().await.sleddb.clone();
@@ -714,6 +777,92 @@ pub async fn definitions(ast_index: Arc<AMutex<AstDB>>, double_colon_path: &str)
     defs
 }
 
+pub struct ReferencesAtPosition {
+    pub symbol: String,
+    pub targets: Vec<Arc<AstDefinition>>,      // more than one means the symbol is ambiguous
+    pub references: Vec<(usize, Arc<AstDefinition>, usize)>,  // (index in targets, where it's used, uline)
+    pub resolved_by_name_only: bool,
+}
+
+pub fn word_at_position(line_text: &str, col: usize) -> Option<String>
+{
+    let chars: Vec<char> = line_text.chars().collect();
+    let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+    // the cursor right after the last character still counts, editors do that all the time
+    let col = if col >= chars.len() || !is_word(&chars[col]) { col.checked_sub(1)? } else { col };
+    if col >= chars.len() || !is_word(&chars[col]) {
+        return None;
+    }
+    let start = chars[..col].iter().rposition(|c| !is_word(c)).map(|x| x + 1).unwrap_or(0);
+    let end = chars[col..].iter().position(|c| !is_word(c)).map(|x| x + col).unwrap_or(chars.len());
+    Some(chars[start..end].iter().collect())
+}
+
+pub async fn definitions_at_position(
+    ast_index: Arc<AMutex<AstDB>>,
+    cpath: &String,
+    file_text: &str,
+    line0: usize,
+    col0: usize,
+) -> Result<(String, Vec<Arc<AstDefinition>>, bool), String>
+{
+    // line0 and col0 start from 0, like in LSP; returns (symbol, targets, resolved_by_name_only)
+    let line_text = file_text.lines().nth(line0).ok_or(format!("line {} is outside of the file", line0))?;
+    let symbol = word_at_position(line_text, col0).ok_or(format!("no symbol at {}:{}", line0, col0))?;
+
+    let mut target_paths: Vec<String> = vec![];
+    // 1. cursor on a declaration in this file
+    let line1 = line0 + 1;
+    for def in doc_defs(ast_index.clone(), cpath).await {
+        if def.name() == symbol && def.decl_line1 <= line1 && line1 <= def.decl_line2 {
+            target_paths.push(def.path());
+        }
+    }
+    // 2. cursor on a usage the indexer has already resolved
+    if target_paths.is_empty() {
+        for (uline, target) in doc_usages(ast_index.clone(), cpath).await {
+            if uline == line0 && target.rsplit("::").next() == Some(symbol.as_str()) && !target_paths.contains(&target) {
+                target_paths.push(target);
+            }
+        }
+    }
+    let db = ast_index.lock().await.sleddb.clone();
+    let mut targets = vec![];
+    for path in target_paths {
+        if let Ok(Some(d_value)) = db.get(format!("d|{}", path).as_bytes()) {
+            match serde_cbor::from_slice::<AstDefinition>(&d_value) {
+                Ok(definition) => targets.push(Arc::new(definition)),
+                Err(e) => tracing::error!("Failed to deserialize value for d|{}: {:?}", path, e),
+            }
+        }
+    }
+    // 3. nothing resolved, guess by name
+    let resolved_by_name_only = targets.is_empty();
+    if resolved_by_name_only {
+        targets = definitions(ast_index.clone(), &symbol).await;
+    }
+    Ok((symbol, targets, resolved_by_name_only))
+}
+
+pub async fn references_at_position(
+    ast_index: Arc<AMutex<AstDB>>,
+    cpath: &String,
+    file_text: &str,
+    line0: usize,
+    col0: usize,
+    limit_n: usize,
+) -> Result<ReferencesAtPosition, String>
+{
+    let (symbol, targets, resolved_by_name_only) = definitions_at_position(ast_index.clone(), cpath, file_text, line0, col0).await?;
+    let mut references = vec![];
+    for (target_n, target) in targets.iter().enumerate() {
+        for (used_at_def, uline) in usages(ast_index.clone(), target.path(), limit_n).await {
+            references.push((target_n, used_at_def, uline));
+        }
+    }
+    Ok(ReferencesAtPosition { symbol, targets, references, resolved_by_name_only })
+}
+
 #[allow(dead_code)]
 pub async fn type_hierarchy(ast_index: Arc<AMutex<AstDB>>, language: String, subtree_of: String) -> String
 {
@@ -997,4 +1146,41 @@ mod tests {
             "Animal::age",
         ).await;
     }
+
+    #[tokio::test]
+    async fn test_references_at_position_py() {
+        init_tracing();
+        let ast_index = ast_index_init("".to_string(), 10, false).await;
+        let lib_path = "src/ast/alt_testsuite/py_refs_lib.py".to_string();
+        let main_path = "src/ast/alt_testsuite/py_refs_main.py".to_string();
+        let lib_text = read_file(&lib_path);
+        let main_text = read_file(&main_path);
+        let mut errstats = AstErrorStats::default();
+        doc_add(ast_index.clone(), &lib_path, &lib_text, &mut errstats).await.unwrap();
+        doc_add(ast_index.clone(), &main_path, &main_text, &mut errstats).await.unwrap();
+        let mut ucx = connect_usages_look_if_full_reset_needed(ast_index.clone()).await;
+        while connect_usages(ast_index.clone(), &mut ucx).await {}
+        flush_sled_batch(ast_index.clone(), 0).await;
+
+        assert_eq!(word_at_position("    total = compute_total(items)", 14), Some("compute_total".to_string()));
+        assert_eq!(word_at_position("def f():", 7), None);
+
+        // on the declaration in one file, usages in the other
+        let refs = references_at_position(ast_index.clone(), &lib_path, &lib_text, 0, 6, 100).await.unwrap();
+        assert_eq!(refs.symbol, "compute_total");
+        assert_eq!(refs.targets.len(), 1);
+        assert!(!refs.resolved_by_name_only);
+        let mut found: Vec<(String, String, usize)> = refs.references.iter().map(|(_, def, uline)| (def.cpath.clone(), def.name(), *uline)).collect();
+        found.sort();
+        assert_eq!(found, vec![
+            (main_path.clone(), "check".to_string(), 9),
+            (main_path.clone(), "report".to_string(), 4),
+        ]);
+
+        // on a usage, should arrive at the same definition
+        let refs2 = references_at_position(ast_index.clone(), &main_path, &main_text, 4, 14, 100).await.unwrap();
+        assert_eq!(refs2.targets.len(), 1);
+        assert_eq!(refs2.targets[0].path(), refs.targets[0].path());
+        assert_eq!(refs2.references.len(), 2);
+    }
 }
@@ -8,15 +8,88 @@ use tracing::info;
 use crate::files_in_workspace::Document;
 use crate::global_context::GlobalContext;
 
-use crate::ast::ast_structs::{AstDB, AstStatus, AstCounters, AstErrorStats};
-use crate::ast::ast_db::{ast_index_init, fetch_counters, doc_add, doc_remove, flush_sled_batch, ConnectUsageContext, connect_usages, connect_usages_look_if_full_reset_needed};
+use crate::ast::ast_structs::{AstDB, AstStatus, AstCounters, AstErrorStats, AstFileCoverage, AstCoverageReport};
+use crate::ast::ast_parse_pool::AstParsePool;
+use crate::ast::ast_db::{ast_index_init, fetch_counters, doc_add, doc_add_parsed, doc_remove, flush_sled_batch, ConnectUsageContext, connect_usages, connect_usages_look_if_full_reset_needed};
 
 
+const AST_TODO_MAX: usize = 5000;
+const AST_TODO_STALL_MS: u64 = 500;
+
 pub struct AstIndexService {
     pub ast_index: Arc<AMutex<AstDB>>,
     pub ast_status: Arc<AMutex<AstStatus>>,
     pub ast_sleeping_point: Arc<ANotify>,
     pub ast_todo: IndexSet<String>,
+    pub ast_todo_max: usize,
+    pub ast_coverage: Arc<AMutex<IndexMap<String, AstFileCoverage>>>,
+    pub ast_parse_pool: Arc<AstParsePool>,
+}
+
+fn no_parser_key(reason: &str) -> Option<String> {
+    // messages from get_ast_parser_by_filename() and get_ast_parser()
+    if let Some(ext) = reason.strip_prefix("not supported") {
+        let ext = ext.trim();
+        return Some(if ext.is_empty() { "(no extension)".to_string() } else { format!(".{}", ext) });
+    }
+    reason.strip_prefix("Unsupported language id: ").map(|lang| lang.to_string())
+}
+
+// has_errors and timed_out come from the same parse that produced the symbols, see AstErrorStats
+fn file_coverage_of(doc_add_result: &Result<(usize, String), String>, has_errors: bool, timed_out: bool) -> Option<AstFileCoverage>
+{
+    match doc_add_result {
+        Ok((defs_len, language)) => Some(AstFileCoverage {
+            language: language.clone(),
+            has_parser: true,
+            has_errors,
+            timed_out,
+            symbols: *defs_len,
+        }),
+        Err(reason) => no_parser_key(reason).map(|key| AstFileCoverage {
+            language: key,
+            has_parser: false,
+            has_errors: false,
+            timed_out: false,
+            symbols: 0,
+        }),
+    }
+}
+
+pub async fn doc_add_with_coverage(
+    ast_index: Arc<AMutex<AstDB>>,
+    cpath: &String,
+    text: &String,
+    errors: &mut AstErrorStats,
+) -> (Result<(usize, String), String>, Option<AstFileCoverage>)
+{
+    let (syntax_errors_before, timed_out_before) = (errors.files_with_syntax_errors, errors.files_timed_out);
+    let result = doc_add(ast_index, cpath, text, errors).await.map(|(defs, language)| (defs.len(), language));
+    let coverage = file_coverage_of(&result, errors.files_with_syntax_errors > syntax_errors_before, errors.files_timed_out > timed_out_before);
+    (result, coverage)
+}
+
+pub fn ast_coverage_report(files: &IndexMap<String, AstFileCoverage>) -> AstCoverageReport
+{
+    let mut report = AstCoverageReport::default();
+    for file in files.values() {
+        if file.has_parser {
+            let lang = report.languages.entry(file.language.clone()).or_default();
+            if file.timed_out {
+                // not parsed, so neither clean nor with errors
+                lang.files_timed_out += 1;
+                continue;
+            }
+            lang.files_parsed += 1;
+            lang.files_with_errors += file.has_errors as usize;
+            lang.symbols += file.symbols;
+        } else {
+            *report.no_parser.entry(file.language.clone()).or_insert(0) += 1;
+        }
+    }
+    report.languages.sort_keys();
+    report.no_parser.sort_keys();
+    report
 }
 
 async fn ast_indexer_thread(
@@ -33,33 +106,43 @@ async fn ast_indexer_thread(
     let mut stats_success_languages: IndexMap<String, usize> = IndexMap::new();
     let mut stats_parsing_errors = AstErrorStats::default();
     let mut ast_max_files_hit = false;
-    let (ast_index, ast_status, ast_sleeping_point) = {
+    let (ast_index, ast_status, ast_sleeping_point, ast_coverage, parse_pool) = {
         let ast_service_locked = ast_service.lock().await;
         (
             ast_service_locked.ast_index.clone(),
             ast_service_locked.ast_status.clone(),
             ast_service_locked.ast_sleeping_point.clone(),
+            ast_service_locked.ast_coverage.clone(),
+            ast_service_locked.ast_parse_pool.clone(),
         )
     };
     let ast_max_files = ast_index.lock().await.ast_max_files;  // cannot change
 
     loop {
-        let (cpath, left_todo_count) = {
+        let (cpaths, left_todo_count) = {
             let mut ast_service_locked = ast_service.lock().await;
-            let mut cpath;
+            let mut cpaths = vec![];
             let mut left_todo_count;
             loop {
-                cpath = ast_service_locked.ast_todo.pop();
+                let cpath = ast_service_locked.ast_todo.pop();
                 left_todo_count = ast_service_locked.ast_todo.len();
                 if left_todo_count < ast_max_files {
+                    cpaths.extend(cpath);
                     break;
                 }
                 ast_max_files_hit = true;
             }
-            (cpath, left_todo_count)
+            while !cpaths.is_empty() && cpaths.len() < parse_pool.workers_n {
+                match ast_service_locked.ast_todo.pop() {
+                    Some(cpath) => cpaths.push(cpath),
+                    None => break,
+                }
+            }
+            left_todo_count = ast_service_locked.ast_todo.len();
+            (cpaths, left_todo_count)
         };
 
-        if let Some(cpath) = cpath {
+        if !cpaths.is_empty() {
             reported_parse_stats = false;
             reported_connect_stats = false;
             if stats_parsed_cnt == 0 {
@@ -72,43 +155,73 @@ async fn ast_indexer_thread(
                     break;
                 }
             };
-            let mut doc = Document { doc_path: cpath.clone().into(), doc_text: None };
-
-            doc_remove(ast_index.clone(), &cpath).await;
-
-            match crate::files_in_workspace::get_file_text_from_memory_or_disk(gcx.clone(), &doc.doc_path).await {
-                Ok(file_text) => {
-                    doc.update_text(&file_text);
-                    let mut error_message: Option<String> = None;
-                    match doc.does_text_look_good() {
-                        Ok(_) => {
-                            let start_time = std::time::Instant::now();
-                            match doc_add(ast_index.clone(), &cpath, &file_text, &mut stats_parsing_errors).await {
-                                Ok((defs, language)) => {
-                                    let elapsed = start_time.elapsed().as_secs_f32();
-                                    if elapsed > 0.1 {
-                                        tracing::info!("{}/{} doc_add {:.3?}s {}", stats_parsed_cnt, (stats_parsed_cnt+left_todo_count), elapsed, crate::nicer_logs::last_n_chars(&cpath, 40));
-                                    }
-                                    stats_parsed_cnt += 1;
-                                    stats_symbols_cnt += defs.len();
-                                    *stats_success_languages.entry(language).or_insert(0) += 1;
-                                }
-                                Err(reason) => {
-                                    error_message = Some(reason);
-                                }
+
+            // Reading files and writing to the index go one by one, only parsing runs on the pool
+            let mut jobs = vec![];
+            for cpath in cpaths {
+                let mut doc = Document::new(&cpath.clone().into());
+                doc_remove(ast_index.clone(), &cpath).await;
+                let job = match crate::files_in_workspace::get_file_text_from_memory_or_disk(gcx.clone(), &doc.doc_path).await {
+                    Ok(file_text) => {
+                        doc.update_text(&file_text);
+                        match doc.does_text_look_good() {
+                            Ok(_) => {
+                                let (pool, cpath_clone) = (parse_pool.clone(), cpath.clone());
+                                let handle = tokio::spawn(async move { pool.parse_file(cpath_clone, file_text).await });
+                                Ok((std::time::Instant::now(), handle))
                             }
+                            Err(err) => Err(err.to_string()),
                         }
-                        Err(err) => {
-                            error_message = Some(err.to_string());
+                    }
+                    Err(_e) => {
+                        tracing::info!("deleting from index {} because cannot read it", crate::nicer_logs::last_n_chars(&cpath, 30));
+                        Err("cannot read file".to_string())
+                    }
+                };
+                jobs.push((cpath, job));
+            }
+
+            for (cpath, job) in jobs {
+                let mut file_coverage: Option<AstFileCoverage> = None;
+                match job {
+                    Ok((start_time, handle)) => {
+                        let (mut has_errors, mut timed_out) = (false, false);
+                        let doc_add_result = match handle.await {
+                            Ok((parse_result, errstats)) => {
+                                has_errors = errstats.files_with_syntax_errors > 0;
+                                timed_out = errstats.files_timed_out > 0;
+                                stats_parsing_errors.extend(errstats);
+                                let result = match parse_result {
+                                    Ok((defs, language)) => Ok(doc_add_parsed(ast_index.clone(), &cpath, defs, language).await),
+                                    Err(reason) => Err(reason),
+                                };
+                                result.map(|(defs, language)| (defs.len(), language))
+                            }
+                            Err(e) => Err(format!("parser failed: {}", e)),
+                        };
+                        file_coverage = file_coverage_of(&doc_add_result, has_errors, timed_out);
+                        match doc_add_result {
+                            Ok((defs_len, language)) => {
+                                let elapsed = start_time.elapsed().as_secs_f32();
+                                if elapsed > 0.1 {
+                                    tracing::info!("{}/{} doc_add {:.3?}s {}", stats_parsed_cnt, (stats_parsed_cnt+left_todo_count), elapsed, crate::nicer_logs::last_n_chars(&cpath, 40));
+                                }
+                                stats_parsed_cnt += 1;
+                                stats_symbols_cnt += defs_len;
+                                *stats_success_languages.entry(language).or_insert(0) += 1;
+                            }
+                            Err(reason) => {
+                                *stats_failure_reasons.entry(reason).or_insert(0) += 1;
+                            }
                         }
                     }
-                    if let Some(err) = error_message {
-                        *stats_failure_reasons.entry(err).or_insert(0) += 1;
+                    Err(reason) => {
+                        *stats_failure_reasons.entry(reason).or_insert(0) += 1;
                     }
                 }
-                Err(_e) => {
-                    tracing::info!("deleting from index {} because cannot read it", crate::nicer_logs::last_n_chars(&cpath, 30));
-                    *stats_failure_reasons.entry("cannot read file".to_string()).or_insert(0) += 1;
+                match file_coverage {
+                    Some(coverage) => { ast_coverage.lock().await.insert(cpath.clone(), coverage); }
+                    None => { ast_coverage.lock().await.shift_remove(&cpath); }
                 }
             }
 
@@ -318,6 +431,10 @@ pub async fn ast_service_init(ast_permanent: String, ast_max_files: usize) -> Ar
         ast_index,
         ast_status,
         ast_todo: IndexSet::new(),
+        // the indexer thread throws away anything above ast_max_files in the queue, stay below that
+        ast_todo_max: AST_TODO_MAX.min(ast_max_files.saturating_sub(1)).max(1),
+        ast_coverage: Arc::new(AMutex::new(IndexMap::new())),
+        ast_parse_pool: AstParsePool::from_cmdline_limits(),
     };
     Arc::new(AMutex::new(ast_service))
 }
@@ -338,14 +455,43 @@ pub async fn ast_indexer_start(
 
 pub async fn ast_indexer_enqueue_files(ast_service: Arc<AMutex<AstIndexService>>, cpaths: &Vec<String>, wake_up_indexer: bool)
 {
+    // The queue is a set, so the same path enqueued twice is parsed once. When the queue is full,
+    // wait for the indexer to make room instead of growing it: a big workspace opening enqueues
+    // everything at once. If the indexer doesn't make progress (not started, stuck), enqueue the
+    // rest anyway, a file must not be lost.
     let ast_status;
     let nonzero = cpaths.len() > 0;
-    {
-        let mut ast_service_locked = ast_service.lock().await;
-        ast_status = ast_service_locked.ast_status.clone();
-        for cpath in cpaths {
-            ast_service_locked.ast_todo.insert(cpath.clone());
+    let mut cpaths_iter = cpaths.iter().peekable();
+    let mut stalled = false;
+    let mut last_len = usize::MAX;
+    let mut last_progress_ts = std::time::Instant::now();
+    loop {
+        let sleeping_point = {
+            let mut ast_service_locked = ast_service.lock().await;
+            while let Some(cpath) = cpaths_iter.peek() {
+                if !stalled && ast_service_locked.ast_todo.len() >= ast_service_locked.ast_todo_max && !ast_service_locked.ast_todo.contains(*cpath) {
+                    break;
+                }
+                ast_service_locked.ast_todo.insert(cpaths_iter.next().unwrap().clone());
+            }
+            if cpaths_iter.peek().is_none() {
+                ast_status = ast_service_locked.ast_status.clone();
+                break;
+            }
+            let todo_len = ast_service_locked.ast_todo.len();
+            if todo_len < last_len {
+                last_len = todo_len;
+                last_progress_ts = std::time::Instant::now();
+            }
+            ast_service_locked.ast_sleeping_point.clone()
+        };
+        if last_progress_ts.elapsed() >= std::time::Duration::from_millis(AST_TODO_STALL_MS) {
+            tracing::warn!("AST queue is full and the indexer doesn't take files from it, enqueue {} more files anyway", cpaths_iter.len());
+            stalled = true;
+            continue;
         }
+        sleeping_point.notify_waiters();
+        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
     }
     {
         let mut status_locked = ast_status.lock().await;
@@ -361,3 +507,77 @@ pub async fn ast_indexer_enqueue_files(ast_service: Arc<AMutex<AstIndexService>>
         ast_service_locked.ast_sleeping_point.notify_waiters();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_enqueue_dedup_and_backpressure() {
+        let ast_service = ast_service_init("".to_string(), 100).await;
+        let cpaths: Vec<String> = ["a.py", "b.py", "a.py", "c.py", "b.py", "a.py"].iter().map(|x| x.to_string()).collect();
+        ast_indexer_enqueue_files(ast_service.clone(), &cpaths, false).await;
+        ast_indexer_enqueue_files(ast_service.clone(), &vec!["c.py".to_string()], false).await;
+        {
+            let ast_service_locked = ast_service.lock().await;
+            assert_eq!(ast_service_locked.ast_todo.iter().cloned().collect::<Vec<_>>(), vec!["a.py", "b.py", "c.py"]);
+            assert_eq!(ast_service_locked.ast_status.lock().await.astate, "indexing");
+        }
+
+        // full queue and no indexer thread to drain it: duplicates still coalesce, distinct files wait and then get in
+        ast_service.lock().await.ast_todo_max = 3;
+        let more: Vec<String> = ["a.py", "d.py", "e.py", "d.py"].iter().map(|x| x.to_string()).collect();
+        let t0 = std::time::Instant::now();
+        ast_indexer_enqueue_files(ast_service.clone(), &more, false).await;
+        assert!(t0.elapsed() >= std::time::Duration::from_millis(AST_TODO_STALL_MS));
+        let ast_service_locked = ast_service.lock().await;
+        assert_eq!(ast_service_locked.ast_todo.iter().cloned().collect::<Vec<_>>(), vec!["a.py", "b.py", "c.py", "d.py", "e.py"]);
+    }
+
+    #[tokio::test]
+    async fn test_coverage_per_language() {
+        let ast_service = ast_service_init("".to_string(), 100).await;
+        let (ast_index, ast_coverage) = {
+            let ast_service_locked = ast_service.lock().await;
+            (ast_service_locked.ast_index.clone(), ast_service_locked.ast_coverage.clone())
+        };
+        let testsuite = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/ast/alt_testsuite");
+        let fixture: Vec<(&str, String)> = vec![
+            ("/ws/goat_main.cpp", std::fs::read_to_string(testsuite.join("cpp_goat_main.cpp")).unwrap()),
+            ("/ws/goat_library.h", std::fs::read_to_string(testsuite.join("cpp_goat_library.h")).unwrap()),
+            ("/ws/goat_library.py", std::fs::read_to_string(testsuite.join("py_goat_library.py")).unwrap()),
+            ("/ws/broken.py", "def broken(:\n    return (\n".to_string()),
+            ("/ws/lib.rs", "pub struct Goat { age: i32 }\n\npub fn make_goat() -> Goat { Goat { age: 1 } }\n".to_string()),
+            ("/ws/README.md", "# Goats\n".to_string()),
+            ("/ws/notes.txt", "goats are nice\n".to_string()),
+            ("/ws/todo.txt", "more goats\n".to_string()),
+        ];
+        let mut errstats = AstErrorStats::default();
+        for (cpath, text) in fixture.iter() {
+            let (_, coverage) = doc_add_with_coverage(ast_index.clone(), &cpath.to_string(), text, &mut errstats).await;
+            ast_coverage.lock().await.insert(cpath.to_string(), coverage.unwrap());
+        }
+        // parsing a file again replaces its numbers
+        let (_, coverage) = doc_add_with_coverage(ast_index.clone(), &"/ws/lib.rs".to_string(), &fixture[4].1, &mut errstats).await;
+        ast_coverage.lock().await.insert("/ws/lib.rs".to_string(), coverage.unwrap());
+        // what the indexer records for a file that ran out of --ast-parse-timeout-ms
+        let (_, coverage) = doc_add_with_coverage(ast_index.clone(), &"/ws/generated.py".to_string(), &"x = 1\n".to_string(), &mut errstats).await;
+        ast_coverage.lock().await.insert("/ws/generated.py".to_string(), AstFileCoverage { timed_out: true, symbols: 0, ..coverage.unwrap() });
+
+        let report = ast_coverage_report(&*ast_coverage.lock().await);
+        assert_eq!(report.languages.keys().cloned().collect::<Vec<_>>(), vec!["cpp", "python", "rust"]);
+        assert_eq!(report.languages["cpp"].files_parsed, 2);
+        assert_eq!(report.languages["python"].files_parsed, 2);
+        assert_eq!(report.languages["python"].files_with_errors, 1);
+        assert_eq!(report.languages["python"].files_timed_out, 1, "a file that wasn't parsed is not a clean one");
+        assert_eq!(report.languages["rust"].files_parsed, 1);
+        assert_eq!(report.languages["rust"].files_with_errors, 0);
+        assert!(report.languages.values().all(|lang| lang.symbols > 0), "{:?}", report);
+        assert_eq!(report.no_parser, IndexMap::from([(".md".to_string(), 1), (".txt".to_string(), 2)]));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["no parser"][".txt"], 2);
+        assert_eq!(json["languages"]["cpp"]["files_parsed"], 2);
+        assert_eq!(json["languages"]["python"]["files_timed_out"], 1);
+    }
+}
@@ -342,12 +342,18 @@ pub fn parse_anything(
     let (mut parser, language_id) = get_ast_parser_by_filename(&path).map_err(|err| err.message)?;
     let language = language_id.to_string();
     if language == "python" {
-        let mut cx = crate::ast::parse_python::py_parse(text);
+        let mut cx = crate::ast::parse_python::py_parse(text, &path);
+        errors.files_with_syntax_errors += cx.has_syntax_errors as usize;
+        errors.files_timed_out += cx.timed_out as usize;
         return Ok((cx.ap.export_defs(cpath), "python".to_string()));
     }
     let file_global_path = vec!["file".to_string()];
 
-    let symbols = parser.parse(text, &path);
+    let Some((symbols, has_syntax_errors)) = parser.parse_with_syntax_errors(text, &path) else {
+        errors.files_timed_out += 1;
+        return Ok((vec![], language));
+    };
+    errors.files_with_syntax_errors += has_syntax_errors as usize;
     if symbols.len() > TOO_MANY_SYMBOLS_IN_FILE {
         return Err(format!("more than {} symbols, generated?", TOO_MANY_SYMBOLS_IN_FILE));
     }
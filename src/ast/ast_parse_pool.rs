@@ -0,0 +1,193 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{Notify as ANotify, Semaphore};
+
+use crate::ast::ast_structs::{AstDefinition, AstErrorStats};
+use crate::ast::ast_parse_anything::parse_anything_and_add_file_path;
+
+
+// Tree-sitter tree plus symbol instances while parsing, much more than the source itself
+const PARSE_BYTES_PER_SOURCE_BYTE: usize = 20;
+
+// from --ast-parse-workers and --ast-parse-memory-mb, 0 workers means half of the cores
+static PARSE_WORKERS: AtomicUsize = AtomicUsize::new(0);
+static PARSE_MEMORY_MB: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set_parse_limits(workers: usize, memory_mb: usize) {
+    PARSE_WORKERS.store(workers, Ordering::Relaxed);
+    PARSE_MEMORY_MB.store(memory_mb, Ordering::Relaxed);
+}
+
+pub fn parse_workers_default() -> usize {
+    let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(2);
+    (cores / 2).max(1)
+}
+
+pub struct AstParsePool {
+    pub workers_n: usize,
+    pub max_inflight_bytes: usize,  // 0 is no limit
+    workers: Semaphore,
+    inflight_bytes: AtomicUsize,
+    memory_released: ANotify,
+    running: AtomicUsize,
+    running_peak: AtomicUsize,
+}
+
+// Memory of a job while it parses, dropping it lets the next jobs in
+pub struct MemoryReservation {
+    pool: Arc<AstParsePool>,
+    bytes: usize,
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.pool.inflight_bytes.fetch_sub(self.bytes, Ordering::SeqCst);
+        self.pool.memory_released.notify_waiters();
+    }
+}
+
+impl AstParsePool {
+    pub fn new(workers_n: usize, max_inflight_bytes: usize) -> Arc<Self> {
+        let workers_n = workers_n.max(1);
+        Arc::new(AstParsePool {
+            workers_n,
+            max_inflight_bytes,
+            workers: Semaphore::new(workers_n),
+            inflight_bytes: AtomicUsize::new(0),
+            memory_released: ANotify::new(),
+            running: AtomicUsize::new(0),
+            running_peak: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn from_cmdline_limits() -> Arc<Self> {
+        let workers = match PARSE_WORKERS.load(Ordering::Relaxed) {
+            0 => parse_workers_default(),
+            n => n,
+        };
+        Self::new(workers, PARSE_MEMORY_MB.load(Ordering::Relaxed) * 1024 * 1024)
+    }
+
+    pub fn inflight_bytes(&self) -> usize {
+        self.inflight_bytes.load(Ordering::SeqCst)
+    }
+
+    pub fn running_peak(&self) -> usize {
+        self.running_peak.load(Ordering::SeqCst)
+    }
+
+    async fn reserve(self: &Arc<Self>, bytes: usize) -> MemoryReservation {
+        loop {
+            let released = self.memory_released.notified();
+            let inflight = self.inflight_bytes();
+            // a single file bigger than the limit still goes alone, otherwise it would wait forever
+            if self.max_inflight_bytes == 0 || inflight == 0 || inflight + bytes <= self.max_inflight_bytes {
+                self.inflight_bytes.fetch_add(bytes, Ordering::SeqCst);
+                return MemoryReservation { pool: self.clone(), bytes };
+            }
+            tokio::select! {
+                _ = released => {},
+                _ = tokio::time::sleep(tokio::time::Duration::from_millis(100)) => {},
+            }
+        }
+    }
+
+    // Runs a blocking job on one of workers_n threads, after the memory it needs fits under the limit
+    pub async fn run<T, F>(self: &Arc<Self>, estimate_bytes: usize, job: F) -> (T, MemoryReservation)
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let reservation = self.reserve(estimate_bytes).await;
+        let _permit = self.workers.acquire().await.expect("parse pool semaphore is never closed");
+        let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.running_peak.fetch_max(running, Ordering::SeqCst);
+        let result = tokio::task::spawn_blocking(job).await;
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        match result {
+            Ok(x) => (x, reservation),
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    // The reservation ends with the parse, not with the write: the indexer writes in file order, holding memory
+    // until then would let later files take the whole budget while it waits for an earlier one that can't start
+    pub async fn parse_file(
+        self: &Arc<Self>,
+        cpath: String,
+        text: String,
+    ) -> (Result<(Vec<AstDefinition>, String), String>, AstErrorStats) {
+        let estimate = text.len() * PARSE_BYTES_PER_SOURCE_BYTE;
+        let ((result, errstats), _reservation) = self.run(estimate, move || {
+            let mut errstats = AstErrorStats::default();
+            let result = parse_anything_and_add_file_path(&cpath, &text, &mut errstats);
+            (result, errstats)
+        }).await;
+        (result, errstats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_parse_jobs_respect_worker_count() {
+        let pool = AstParsePool::new(2, 0);
+        let running = Arc::new(AtomicUsize::new(0));
+        let observed_peak = Arc::new(AtomicUsize::new(0));
+        let mut handles = vec![];
+        for _ in 0..8 {
+            let (pool, running, observed_peak) = (pool.clone(), running.clone(), observed_peak.clone());
+            handles.push(tokio::spawn(async move {
+                pool.run(1000, move || {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    observed_peak.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(50));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                }).await
+            }));
+        }
+        for h in handles {
+            let (_, reservation) = h.await.unwrap();
+            drop(reservation);
+        }
+        assert_eq!(observed_peak.load(Ordering::SeqCst), 2);
+        assert_eq!(pool.running_peak(), 2);
+        assert_eq!(pool.inflight_bytes(), 0);
+
+        // memory limit: the second job waits until the first one's reservation is dropped
+        let pool = AstParsePool::new(4, 1500);
+        let (_, first) = pool.run(1000, || ()).await;
+        let pool2 = pool.clone();
+        let second = tokio::spawn(async move { pool2.run(1000, || ()).await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!second.is_finished());
+        drop(first);
+        let (_, second) = tokio::time::timeout(Duration::from_secs(2), second).await.unwrap().unwrap();
+        assert_eq!(pool.inflight_bytes(), 1000);
+        drop(second);
+
+        // a real file, the memory is free as soon as it's parsed
+        let (result, _errstats) = pool.parse_file("/ws/lib.py".to_string(), "class Goat:\n    def eat(self):\n        pass\n".to_string()).await;
+        let (_defs, language) = result.unwrap();
+        assert_eq!(language, "python");
+        assert_eq!(pool.inflight_bytes(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_big_files_in_one_batch_dont_deadlock() {
+        // each file needs most of the budget, awaiting them in order must not wait forever on the first one
+        let pool = AstParsePool::new(4, 1000 * PARSE_BYTES_PER_SOURCE_BYTE);
+        let handles = (0..4).map(|i| {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.parse_file(format!("/ws/big{}.py", i), format!("x{} = 1\n", i).repeat(100)).await })
+        }).collect::<Vec<_>>();
+        for handle in handles {
+            let (result, _) = tokio::time::timeout(Duration::from_secs(10), handle).await.expect("deadlock").unwrap();
+            assert!(result.is_ok());
+        }
+        assert_eq!(pool.inflight_bytes(), 0);
+    }
+}
@@ -79,6 +79,31 @@ pub struct AstStatus {
     pub ast_max_files_hit: bool,
 }
 
+// Per file, so a file parsed again replaces its previous numbers instead of adding to them
+#[derive(Clone, Debug)]
+pub struct AstFileCoverage {
+    pub language: String,    // for files without a parser, the extension or the language that has no parser
+    pub has_parser: bool,
+    pub has_errors: bool,    // tree-sitter produced ERROR or MISSING nodes
+    pub timed_out: bool,     // ran out of --ast-parse-timeout-ms, nothing is known about the file
+    pub symbols: usize,
+}
+
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct AstLanguageCoverage {
+    pub files_parsed: usize,
+    pub files_with_errors: usize,
+    pub files_timed_out: usize,
+    pub symbols: usize,
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct AstCoverageReport {
+    pub languages: indexmap::IndexMap<String, AstLanguageCoverage>,
+    #[serde(rename = "no parser")]
+    pub no_parser: indexmap::IndexMap<String, usize>,
+}
+
 pub struct AstCounters {
     pub counter_defs: i32,
     pub counter_usages: i32,
@@ -97,6 +122,8 @@ pub struct AstError {
 pub struct AstErrorStats {
     pub errors: Vec<AstError>,
     pub errors_counter: usize,
+    pub files_with_syntax_errors: usize,
+    pub files_timed_out: usize,
 }
 
 impl AstErrorStats {
@@ -115,6 +142,14 @@ impl AstErrorStats {
         }
         self.errors_counter += 1;
     }
+
+    pub fn extend(self: &mut AstErrorStats, other: AstErrorStats) {
+        let room = TOO_MANY_ERRORS.saturating_sub(self.errors.len());
+        self.errors.extend(other.errors.into_iter().take(room));
+        self.errors_counter += other.errors_counter;
+        self.files_with_syntax_errors += other.files_with_syntax_errors;
+        self.files_timed_out += other.files_timed_out;
+    }
 }
 
 impl Default for AstErrorStats {
@@ -122,6 +157,8 @@ impl Default for AstErrorStats {
         AstErrorStats {
             errors: Vec::new(),
             errors_counter: 0,
+            files_with_syntax_errors: 0,
+            files_timed_out: 0,
         }
     }
 }
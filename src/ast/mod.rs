@@ -13,6 +13,7 @@ pub mod treesitter;
 pub mod ast_structs;
 pub mod ast_parse_anything;
 pub mod ast_indexer_thread;
+pub mod ast_parse_pool;
 pub mod ast_db;
 
 pub mod linters;
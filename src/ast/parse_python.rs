@@ -1,9 +1,11 @@
+use std::path::PathBuf;
 use indexmap::IndexMap;
 use tree_sitter::{Node, Parser};
 use tree_sitter_python::language;
 
 use crate::ast::ast_structs::{AstDefinition, AstUsage, AstErrorStats};
 use crate::ast::treesitter::structs::SymbolType;
+use crate::ast::treesitter::parsers::parse_tree_or_skip;
 use crate::ast::parse_common::{ContextAnyParser, Thing, any_child_of_type, type_deindex, type_deindex_n, type_call, type_zerolevel_comma_split};
 
 const DEBUG: bool = false;
@@ -16,6 +18,8 @@ const DEBUG: bool = false;
 
 pub struct ContextPy {
     pub ap: ContextAnyParser,
+    pub has_syntax_errors: bool,
+    pub timed_out: bool,
 }
 
 fn debug_helper(cx: &ContextPy, args: std::fmt::Arguments) {
@@ -824,14 +828,24 @@ fn py_make_cx(code: &str) -> ContextPy
             alias: IndexMap::new(),
             star_imports: vec![],
         },
+        has_syntax_errors: false,
+        timed_out: false,
     };
     cx
 }
 
-pub fn py_parse(code: &str) -> ContextPy
+pub fn py_parse(code: &str, file_path: &PathBuf) -> ContextPy
 {
     let mut cx = py_make_cx(code);
-    let tree = cx.ap.sitter.parse(code, None).unwrap();
+    // skipped like in the other parsers: no definitions at all
+    let tree = match parse_tree_or_skip(&mut cx.ap.sitter, code, file_path) {
+        Some(tree) => tree,
+        None => {
+            cx.timed_out = true;
+            return cx;
+        }
+    };
+    cx.has_syntax_errors = tree.root_node().has_error();
     let path = vec!["root".to_string()];
     let mut pass_n = 1;
     loop {
@@ -871,7 +885,7 @@ mod tests {
 
     fn py_parse4test(code: &str) -> String
     {
-        let mut cx = py_parse(code);
+        let mut cx = py_parse(code, &PathBuf::from("test.py"));
         cx.ap.dump();
         let _ = cx.ap.export_defs("test");
         cx.ap.annotate_code("#")
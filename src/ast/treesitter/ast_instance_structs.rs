@@ -9,7 +9,6 @@ use std::fmt::Debug;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::{fs, io};
-use tokio::fs::read_to_string;
 use tree_sitter::{Point, Range};
 use uuid::Uuid;
 
@@ -173,12 +172,12 @@ impl SymbolInformation {
     }
 
     pub async fn get_content_from_file(&self) -> io::Result<String> {
-        let content = read_to_string(&self.file_path).await?;
+        let content = read_decoded(tokio::fs::read(&self.file_path).await?)?;
         self.get_content(&content)
     }
 
     pub fn get_content_from_file_blocked(&self) -> io::Result<String> {
-        let content = fs::read_to_string(&self.file_path)?;
+        let content = read_decoded(fs::read(&self.file_path)?)?;
         self.get_content(&content)
     }
 
@@ -191,16 +190,21 @@ impl SymbolInformation {
     }
 
     pub async fn get_declaration_content_from_file(&self) -> io::Result<String> {
-        let content = read_to_string(&self.file_path).await?;
+        let content = read_decoded(tokio::fs::read(&self.file_path).await?)?;
         self.get_declaration_content(&content)
     }
 
     pub fn get_declaration_content_from_file_blocked(&self) -> io::Result<String> {
-        let content = fs::read_to_string(&self.file_path)?;
+        let content = read_decoded(fs::read(&self.file_path)?)?;
         self.get_declaration_content(&content)
     }
 }
 
+// Same decoding as the indexer used, so the byte ranges point to the same text
+fn read_decoded(bytes: Vec<u8>) -> io::Result<String> {
+    crate::files_in_workspace::decode_file_bytes(bytes).map(|(text, _)| text).map_err(io::Error::other)
+}
+
 impl Default for AstSymbolFields {
     fn default() -> Self {
         AstSymbolFields {
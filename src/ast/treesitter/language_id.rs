@@ -113,6 +113,7 @@ impl From<&str> for LanguageId {
             "rust" => Self::Rust,
             "scala" => Self::Scala,
             "shellscript" => Self::Bash,
+            "sql" => Self::Sql,
             "swift" => Self::Swift,
             // "toml" => Self::Toml,
             "typescript" => Self::TypeScript,
@@ -145,6 +146,10 @@ impl From<Language> for LanguageId {
             Self::TypeScript
         } else if value == tree_sitter_typescript::language_tsx() {
             Self::TypeScriptReact
+        } else if value == tree_sitter_sequel::language() {
+            Self::Sql
+        } else if value == tree_sitter_go::language() {
+            Self::Go
         } else {
             Self::Unknown
         }
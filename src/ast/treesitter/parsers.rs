@@ -1,7 +1,10 @@
 use std::fmt::Display;
 use std::path::PathBuf;
+use std::sync::RwLock as StdRwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use tracing::error;
+use lazy_static::lazy_static;
+use tracing::{error, warn};
 
 use crate::ast::treesitter::ast_instance_structs::AstSymbolInstanceArc;
 use crate::ast::treesitter::language_id::LanguageId;
@@ -16,15 +19,31 @@ mod java;
 mod cpp;
 mod ts;
 mod js;
+mod sql;
+mod go;
 
 
+lazy_static! {
+    // from --language-extensions, checked before the built-in extensions
+    static ref EXTENSION_OVERRIDES: StdRwLock<Vec<(String, LanguageId)>> = StdRwLock::new(vec![]);
+}
+
+// from --ast-parse-timeout-ms, 0 means no limit
+static PARSE_TIMEOUT_MICROS: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct ParserError {
     pub message: String,
 }
 
 pub trait AstLanguageParser: Send {
-    fn parse(&mut self, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc>;
+    // The bool is whether the tree had ERROR or MISSING nodes, the coverage report counts those files.
+    // None when the parse ran out of --ast-parse-timeout-ms, that's not the same as a file without symbols
+    fn parse_with_syntax_errors(&mut self, code: &str, path: &PathBuf) -> Option<(Vec<AstSymbolInstanceArc>, bool)>;
+
+    fn parse(&mut self, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc> {
+        self.parse_with_syntax_errors(code, path).map(|(symbols, _)| symbols).unwrap_or_default()
+    }
 }
 
 fn internal_error<E: Display>(err: E) -> ParserError {
@@ -61,8 +80,16 @@ pub(crate) fn get_ast_parser(language_id: LanguageId) -> Result<Box<dyn AstLangu
             let parser = js::JSParser::new()?;
             Ok(Box::new(parser))
         }
+        LanguageId::Sql => {
+            let parser = sql::SqlParser::new()?;
+            Ok(Box::new(parser))
+        }
+        LanguageId::Go => {
+            let parser = go::GoParser::new()?;
+            Ok(Box::new(parser))
+        }
         LanguageId::TypeScriptReact => {
-            let parser = ts::TSParser::new()?; //quick fix untill we have a dedicated parser for TypeScriptReact
+            let parser = ts::TSParser::new_tsx()?;
             Ok(Box::new(parser))
         }
         other => Err(ParserError {
@@ -72,6 +99,70 @@ pub(crate) fn get_ast_parser(language_id: LanguageId) -> Result<Box<dyn AstLangu
 }
 
 
+pub fn tree_sitter_language(language_id: LanguageId) -> Option<tree_sitter::Language> {
+    match language_id {
+        LanguageId::Cpp => Some(tree_sitter_cpp::language()),
+        LanguageId::Python => Some(tree_sitter_python::language()),
+        LanguageId::Java => Some(tree_sitter_java::language()),
+        LanguageId::JavaScript => Some(tree_sitter_javascript::language()),
+        LanguageId::Rust => Some(tree_sitter_rust::language()),
+        LanguageId::TypeScript => Some(tree_sitter_typescript::language_typescript()),
+        LanguageId::TypeScriptReact => Some(tree_sitter_typescript::language_tsx()),
+        LanguageId::Sql => Some(tree_sitter_sequel::language()),
+        LanguageId::Go => Some(tree_sitter_go::language()),
+        _ => None,
+    }
+}
+
+const ALL_LANGUAGE_IDS: &[LanguageId] = &[
+    LanguageId::Apex, LanguageId::Bash, LanguageId::C, LanguageId::Cpp, LanguageId::CSharp, LanguageId::Css,
+    LanguageId::D, LanguageId::Elm, LanguageId::Go, LanguageId::Html, LanguageId::Kotlin, LanguageId::Java,
+    LanguageId::JavaScript, LanguageId::Lua, LanguageId::Ocaml, LanguageId::Php, LanguageId::Python, LanguageId::R,
+    LanguageId::Ruby, LanguageId::Rust, LanguageId::Scala, LanguageId::Sql, LanguageId::Swift,
+    LanguageId::TypeScript, LanguageId::TypeScriptReact,
+];
+
+pub fn tree_sitter_supported_languages() -> Vec<LanguageId> {
+    ALL_LANGUAGE_IDS.iter().copied().filter(|l| tree_sitter_language(*l).is_some()).collect()
+}
+
+// For the tools that walk the tree themselves, the error lists what they can work with
+pub fn tree_sitter_language_by_filename(filename: &PathBuf) -> Result<LanguageId, String> {
+    get_language_id_by_filename(filename).filter(|l| tree_sitter_language(*l).is_some()).ok_or(format!(
+        "there's no parser for this kind of file. Supported: {}",
+        tree_sitter_supported_languages().iter().map(|l| l.to_string()).collect::<Vec<_>>().join(", "),
+    ))
+}
+
+// Same timeout as the indexer, a tool shouldn't hang on a pathological file either
+pub fn parse_text_to_tree(language_id: LanguageId, text: &str) -> Result<tree_sitter::Tree, String> {
+    let language = tree_sitter_language(language_id).ok_or(format!("no parser for {}", language_id))?;
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&language).map_err(|e| e.to_string())?;
+    parse_tree_or_skip(&mut parser, text, &PathBuf::from(format!("<{} text>", language_id)))
+        .ok_or(format!("parsing this {} text takes too long, skipped", language_id))
+}
+
+pub fn set_parse_timeout_ms(timeout_ms: u64) {
+    PARSE_TIMEOUT_MICROS.store(timeout_ms.saturating_mul(1000), Ordering::Relaxed);
+}
+
+// None means the file took longer than the timeout and should be skipped, one pathological file shouldn't stall the indexer
+pub(crate) fn parse_tree_with_timeout(parser: &mut tree_sitter::Parser, code: &str, path: &PathBuf, timeout_micros: u64) -> Option<tree_sitter::Tree> {
+    parser.set_timeout_micros(timeout_micros);
+    let tree = parser.parse(code, None);
+    if tree.is_none() {
+        // otherwise the next parse() resumes this one instead of starting over
+        parser.reset();
+        warn!("{}: parsing takes longer than {}ms, skipped", crate::nicer_logs::last_n_chars(&path.display().to_string(), 50), timeout_micros / 1000);
+    }
+    tree
+}
+
+pub(crate) fn parse_tree_or_skip(parser: &mut tree_sitter::Parser, code: &str, path: &PathBuf) -> Option<tree_sitter::Tree> {
+    parse_tree_with_timeout(parser, code, path, PARSE_TIMEOUT_MICROS.load(Ordering::Relaxed))
+}
+
 pub fn get_ast_parser_by_filename(filename: &PathBuf) -> Result<(Box<dyn AstLanguageParser + 'static>, LanguageId), ParserError> {
     let suffix = filename.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
     let maybe_language_id = get_language_id_by_filename(filename);
@@ -84,7 +175,68 @@ pub fn get_ast_parser_by_filename(filename: &PathBuf) -> Result<(Box<dyn AstLang
     }
 }
 
+pub fn parse_extension_overrides(s: &str) -> Result<Vec<(String, LanguageId)>, String> {
+    // "pyi2=python,tsx.txt=typescriptreact"
+    let mut result = vec![];
+    for pair in s.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        let (ext, lang) = pair.split_once('=')
+            .ok_or(format!("cannot parse language extension {:?}, expected ext=language", pair))?;
+        let ext = ext.trim().trim_start_matches('.').to_lowercase();
+        if ext.is_empty() {
+            return Err(format!("empty extension in {:?}", pair));
+        }
+        let language_id = LanguageId::from(lang.trim());
+        if language_id == LanguageId::Unknown || get_ast_parser(language_id).is_err() {
+            return Err(format!("extension {:?} maps to {:?} which is not a language with a parser", ext, lang.trim()));
+        }
+        result.push((ext, language_id));
+    }
+    Ok(result)
+}
+
+pub fn set_extension_overrides(overrides: Vec<(String, LanguageId)>) {
+    *EXTENSION_OVERRIDES.write().unwrap() = overrides;
+}
+
+// Overrides are global, tests set them through this guard: one test at a time, and the previous ones are back on drop
+#[cfg(test)]
+pub struct ExtensionOverridesGuard {
+    previous: Vec<(String, LanguageId)>,
+    _serialized: std::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+impl ExtensionOverridesGuard {
+    pub fn set(overrides: Vec<(String, LanguageId)>) -> Self {
+        lazy_static! {
+            static ref OVERRIDES_TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        }
+        let serialized = OVERRIDES_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = std::mem::replace(&mut *EXTENSION_OVERRIDES.write().unwrap(), overrides);
+        ExtensionOverridesGuard { previous, _serialized: serialized }
+    }
+}
+
+#[cfg(test)]
+impl Drop for ExtensionOverridesGuard {
+    fn drop(&mut self) {
+        set_extension_overrides(std::mem::take(&mut self.previous));
+    }
+}
+
+pub fn get_language_id_by_extension_override(filename: &PathBuf) -> Option<LanguageId> {
+    // compares the whole file name, so multi-part extensions like "tsx.txt" work; the longest match wins
+    let file_name = filename.file_name()?.to_string_lossy().to_lowercase();
+    EXTENSION_OVERRIDES.read().unwrap().iter()
+        .filter(|(ext, _)| file_name.ends_with(&format!(".{}", ext)))
+        .max_by_key(|(ext, _)| ext.len())
+        .map(|(_, language_id)| *language_id)
+}
+
 pub fn get_language_id_by_filename(filename: &PathBuf) -> Option<LanguageId> {
+    if let Some(language_id) = get_language_id_by_extension_override(filename) {
+        return Some(language_id);
+    }
     let suffix = filename.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
     match suffix.as_str() {
         "cpp" | "cc" | "cxx" | "c++" | "c" | "h" | "hpp" | "hxx" | "hh" => Some(LanguageId::Cpp),
@@ -95,6 +247,8 @@ pub fn get_language_id_by_filename(filename: &PathBuf) -> Option<LanguageId> {
         "rs" => Some(LanguageId::Rust),
         "ts" => Some(LanguageId::TypeScript),
         "tsx" => Some(LanguageId::TypeScriptReact),
+        "sql" => Some(LanguageId::Sql),
+        "go" => Some(LanguageId::Go),
         _ => None
     }
 }
@@ -0,0 +1,715 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::string::ToString;
+use std::sync::Arc;
+
+#[cfg(test)]
+use itertools::Itertools;
+
+use parking_lot::RwLock;
+use similar::DiffableStr;
+use tree_sitter::{Node, Parser, Range};
+use tree_sitter_go::language;
+use uuid::Uuid;
+
+use crate::ast::treesitter::ast_instance_structs::{AstSymbolFields, AstSymbolInstanceArc, ClassFieldDeclaration, CommentDefinition, FunctionArg, FunctionCall, FunctionDeclaration, ImportDeclaration, ImportType, StructDeclaration, TypeDef, VariableDefinition, VariableUsage};
+use crate::ast::treesitter::language_id::LanguageId;
+use crate::ast::treesitter::parsers::{AstLanguageParser, internal_error, parse_tree_or_skip, ParserError};
+use crate::ast::treesitter::parsers::utils::{CandidateInfo, get_guid};
+use crate::ast::treesitter::structs::SymbolType;
+
+pub(crate) struct GoParser {
+    pub parser: Parser,
+}
+
+static GO_KEYWORDS: [&str; 25] = [
+    "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "for",
+    "func", "go", "goto", "if", "import", "interface", "map", "package", "range", "return",
+    "select", "struct", "switch", "type", "var",
+];
+
+static GO_POD_TYPES: [&str; 20] = [
+    "bool", "byte", "complex64", "complex128", "error", "float32", "float64", "int", "int8", "int16",
+    "int32", "int64", "rune", "string", "uint", "uint8", "uint16", "uint32", "uint64", "uintptr",
+];
+
+// Standard library paths have no dot in the first element ("fmt", "net/http"), third party ones start with a host
+fn go_import_type(path: &str) -> ImportType {
+    if path.starts_with("./") || path.starts_with("../") {
+        return ImportType::UserModule;
+    }
+    match path.split('/').next() {
+        Some(first) if !first.is_empty() && !first.contains('.') => ImportType::System,
+        _ => ImportType::Library,
+    }
+}
+
+pub fn parse_type(parent: &Node, code: &str) -> Option<TypeDef> {
+    let kind = parent.kind();
+    let text = code.slice(parent.byte_range()).to_string();
+    match kind {
+        "type_identifier" | "identifier" => {
+            let is_pod = GO_POD_TYPES.contains(&text.as_str());
+            return Some(TypeDef {
+                name: if is_pod { None } else { Some(text.clone()) },
+                inference_info: if is_pod { Some(text) } else { None },
+                inference_info_guid: None,
+                is_pod,
+                namespace: "".to_string(),
+                guid: None,
+                nested_types: vec![],
+            });
+        }
+        "qualified_type" => {
+            let mut decl = TypeDef::default();
+            if let Some(package) = parent.child_by_field_name("package") {
+                decl.namespace = code.slice(package.byte_range()).to_string();
+            }
+            if let Some(name) = parent.child_by_field_name("name") {
+                decl.name = Some(code.slice(name.byte_range()).to_string());
+            }
+            return Some(decl);
+        }
+        "pointer_type" | "parenthesized_type" => {
+            for i in 0..parent.child_count() {
+                let child = parent.child(i).unwrap();
+                if let Some(dtype) = parse_type(&child, code) {
+                    return Some(dtype);
+                }
+            }
+        }
+        "slice_type" | "array_type" | "channel_type" => {
+            let mut decl = TypeDef {
+                name: Some(if kind == "channel_type" { "chan" } else { "[]" }.to_string()),
+                ..TypeDef::default()
+            };
+            let element = parent.child_by_field_name("element").or(parent.child_by_field_name("value"));
+            if let Some(element) = element {
+                if let Some(dtype) = parse_type(&element, code) {
+                    decl.nested_types.push(dtype);
+                }
+            }
+            return Some(decl);
+        }
+        "map_type" => {
+            let mut decl = TypeDef {
+                name: Some("map".to_string()),
+                ..TypeDef::default()
+            };
+            for field in ["key", "value"] {
+                if let Some(child) = parent.child_by_field_name(field) {
+                    if let Some(dtype) = parse_type(&child, code) {
+                        decl.nested_types.push(dtype);
+                    }
+                }
+            }
+            return Some(decl);
+        }
+        "generic_type" => {
+            let mut decl = TypeDef::default();
+            if let Some(type_node) = parent.child_by_field_name("type") {
+                if let Some(dtype) = parse_type(&type_node, code) {
+                    decl.name = dtype.name;
+                    decl.namespace = dtype.namespace;
+                }
+            }
+            if let Some(arguments) = parent.child_by_field_name("type_arguments") {
+                for i in 0..arguments.child_count() {
+                    let child = arguments.child(i).unwrap();
+                    if let Some(dtype) = parse_type(&child, code) {
+                        decl.nested_types.push(dtype);
+                    }
+                }
+            }
+            return Some(decl);
+        }
+        "type_elem" => {
+            if let Some(child) = parent.child(0) {
+                return parse_type(&child, code);
+            }
+        }
+        "function_type" | "struct_type" | "interface_type" => {
+            return Some(TypeDef {
+                inference_info: Some(text),
+                ..TypeDef::default()
+            });
+        }
+        &_ => {}
+    }
+    None
+}
+
+// One parameter_declaration can name several arguments: `a, b int`
+fn parse_function_args(parent: &Node, code: &str) -> Vec<FunctionArg> {
+    let mut args = vec![];
+    for i in 0..parent.child_count() {
+        let child = parent.child(i).unwrap();
+        if !["parameter_declaration", "variadic_parameter_declaration"].contains(&child.kind()) {
+            continue;
+        }
+        let type_ = child.child_by_field_name("type").and_then(|t| parse_type(&t, code));
+        let mut names = vec![];
+        for j in 0..child.child_count() {
+            let name = child.child(j).unwrap();
+            if name.kind() == "identifier" {
+                names.push(code.slice(name.byte_range()).to_string());
+            }
+        }
+        if names.is_empty() {
+            args.push(FunctionArg { name: "".to_string(), type_ });
+        } else {
+            args.extend(names.into_iter().map(|name| FunctionArg { name, type_: type_.clone() }));
+        }
+    }
+    args
+}
+
+fn receiver_type_name(receiver: &Node, code: &str) -> Option<String> {
+    let args = parse_function_args(receiver, code);
+    args.first()?.type_.as_ref()?.name.clone()
+}
+
+impl GoParser {
+    pub fn new() -> Result<GoParser, ParserError> {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&language())
+            .map_err(internal_error)?;
+        Ok(GoParser { parser })
+    }
+
+    pub fn parse_struct_declaration<'a>(
+        &mut self,
+        info: &CandidateInfo<'a>,
+        code: &str,
+        candidates: &mut VecDeque<CandidateInfo<'a>>,
+    ) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut decl = StructDeclaration::default();
+
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.declaration_range = info.node.range();
+        decl.ast_fields.definition_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.guid = get_guid();
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+
+        if let Some(name_node) = info.node.child_by_field_name("name") {
+            decl.ast_fields.name = code.slice(name_node.byte_range()).to_string();
+        }
+        if let Some(type_parameters) = info.node.child_by_field_name("type_parameters") {
+            for i in 0..type_parameters.child_count() {
+                let child = type_parameters.child(i).unwrap();
+                if let Some(name) = child.child_by_field_name("name") {
+                    decl.template_types.push(TypeDef {
+                        name: Some(code.slice(name.byte_range()).to_string()),
+                        ..TypeDef::default()
+                    });
+                }
+            }
+        }
+
+        if let Some(body) = info.node.child_by_field_name("type") {
+            decl.ast_fields.definition_range = body.range();
+            decl.ast_fields.declaration_range = Range {
+                start_byte: decl.ast_fields.full_range.start_byte,
+                end_byte: decl.ast_fields.definition_range.start_byte,
+                start_point: decl.ast_fields.full_range.start_point,
+                end_point: decl.ast_fields.definition_range.start_point,
+            };
+            // struct fields sit in a field_declaration_list, interface methods are direct children
+            let mut members = vec![];
+            for i in 0..body.child_count() {
+                let child = body.child(i).unwrap();
+                if child.kind() == "field_declaration_list" {
+                    for j in 0..child.child_count() {
+                        members.push(child.child(j).unwrap());
+                    }
+                } else {
+                    members.push(child);
+                }
+            }
+            for member in members {
+                match member.kind() {
+                    "field_declaration" if member.child_by_field_name("name").is_none() => {
+                        // an embedded field has a type and no name, Go's way of composing a struct from another
+                        if let Some(dtype) = member.child_by_field_name("type").and_then(|t| parse_type(&t, code)) {
+                            decl.inherited_types.push(dtype);
+                        }
+                    }
+                    "field_declaration" | "method_spec" | "method_elem" => {
+                        candidates.push_back(CandidateInfo {
+                            ast_fields: decl.ast_fields.clone(),
+                            node: member,
+                            parent_guid: decl.ast_fields.guid.clone(),
+                        });
+                    }
+                    "type_elem" | "constraint_elem" => {
+                        if let Some(dtype) = parse_type(&member, code) {
+                            decl.inherited_types.push(dtype);
+                        }
+                    }
+                    &_ => {}
+                }
+            }
+        }
+
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    fn parse_field_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, _candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let mut dtype = TypeDef::default();
+        if let Some(type_node) = info.node.child_by_field_name("type") {
+            if let Some(type_) = parse_type(&type_node, code) {
+                dtype = type_;
+            }
+        }
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        for i in 0..info.node.child_count() {
+            let child = info.node.child(i).unwrap();
+            if child.kind() != "field_identifier" {
+                continue;
+            }
+            let mut decl = ClassFieldDeclaration::default();
+            decl.ast_fields.language = info.ast_fields.language;
+            decl.ast_fields.full_range = info.node.range();
+            decl.ast_fields.declaration_range = info.node.range();
+            decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+            decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+            decl.ast_fields.guid = get_guid();
+            decl.ast_fields.is_error = info.ast_fields.is_error;
+            decl.ast_fields.name = code.slice(child.byte_range()).to_string();
+            decl.type_ = dtype.clone();
+            symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        }
+        symbols
+    }
+
+    fn parse_variable_definition<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        // var_spec and const_spec carry name/type/value, short_var_declaration has left := right
+        let mut names = vec![];
+        if let Some(left) = info.node.child_by_field_name("left") {
+            for i in 0..left.child_count() {
+                let child = left.child(i).unwrap();
+                if child.kind() == "identifier" {
+                    names.push(child);
+                }
+            }
+        }
+        let mut cursor = info.node.walk();
+        names.extend(info.node.children_by_field_name("name", &mut cursor));
+        let type_ = info.node.child_by_field_name("type").and_then(|t| parse_type(&t, code)).unwrap_or_default();
+        let value = info.node.child_by_field_name("value").or(info.node.child_by_field_name("right"));
+
+        for name in names {
+            let mut decl = VariableDefinition::default();
+            decl.ast_fields.language = info.ast_fields.language;
+            decl.ast_fields.full_range = info.node.range();
+            decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+            decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+            decl.ast_fields.guid = get_guid();
+            decl.ast_fields.is_error = info.ast_fields.is_error;
+            decl.ast_fields.name = code.slice(name.byte_range()).to_string();
+            decl.type_ = type_.clone();
+            if let Some(value) = value {
+                decl.type_.inference_info = Some(code.slice(value.byte_range()).to_string());
+            }
+            symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        }
+        if let Some(value) = value {
+            candidates.push_back(CandidateInfo {
+                ast_fields: info.ast_fields.clone(),
+                node: value,
+                parent_guid: info.parent_guid.clone(),
+            });
+        }
+        symbols
+    }
+
+    pub fn parse_function_declaration<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut decl = FunctionDeclaration::default();
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.declaration_range = info.node.range();
+        decl.ast_fields.definition_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+        decl.ast_fields.guid = get_guid();
+
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+
+        if let Some(name_node) = info.node.child_by_field_name("name") {
+            decl.ast_fields.name = code.slice(name_node.byte_range()).to_string();
+        }
+        if let Some(type_parameters) = info.node.child_by_field_name("type_parameters") {
+            for i in 0..type_parameters.child_count() {
+                let child = type_parameters.child(i).unwrap();
+                if let Some(name) = child.child_by_field_name("name") {
+                    decl.template_types.push(TypeDef {
+                        name: Some(code.slice(name.byte_range()).to_string()),
+                        ..TypeDef::default()
+                    });
+                }
+            }
+        }
+        if let Some(parameters_node) = info.node.child_by_field_name("parameters") {
+            symbols.extend(self.find_error_usages(&parameters_node, code, &info.ast_fields.file_path, &decl.ast_fields.guid));
+            decl.ast_fields.declaration_range = Range {
+                start_byte: decl.ast_fields.full_range.start_byte,
+                end_byte: parameters_node.end_byte(),
+                start_point: decl.ast_fields.full_range.start_point,
+                end_point: parameters_node.end_position(),
+            };
+            decl.args = parse_function_args(&parameters_node, code);
+        }
+        if let Some(result) = info.node.child_by_field_name("result") {
+            decl.return_type = match result.kind() {
+                // several results `(int, error)` are kept as a tuple of nested types
+                "parameter_list" => Some(TypeDef {
+                    name: Some("tuple".to_string()),
+                    nested_types: parse_function_args(&result, code).into_iter().filter_map(|a| a.type_).collect(),
+                    ..TypeDef::default()
+                }),
+                _ => parse_type(&result, code),
+            };
+        }
+
+        if let Some(body_node) = info.node.child_by_field_name("body") {
+            decl.ast_fields.definition_range = body_node.range();
+            decl.ast_fields.declaration_range = Range {
+                start_byte: decl.ast_fields.full_range.start_byte,
+                end_byte: decl.ast_fields.definition_range.start_byte,
+                start_point: decl.ast_fields.full_range.start_point,
+                end_point: decl.ast_fields.definition_range.start_point,
+            };
+            candidates.push_back(CandidateInfo {
+                ast_fields: decl.ast_fields.clone(),
+                node: body_node,
+                parent_guid: decl.ast_fields.guid.clone(),
+            });
+        } else {
+            decl.ast_fields.declaration_range = decl.ast_fields.full_range;
+        }
+
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    pub fn parse_call_expression<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut decl = FunctionCall::default();
+        decl.ast_fields.language = info.ast_fields.language;
+        decl.ast_fields.full_range = info.node.range();
+        decl.ast_fields.file_path = info.ast_fields.file_path.clone();
+        decl.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        decl.ast_fields.guid = get_guid();
+        decl.ast_fields.is_error = info.ast_fields.is_error;
+        if let Some(caller_guid) = info.ast_fields.caller_guid.clone() {
+            decl.ast_fields.guid = caller_guid;
+        }
+        decl.ast_fields.caller_guid = Some(get_guid());
+
+        symbols.extend(self.find_error_usages(&info.node, code, &info.ast_fields.file_path, &info.parent_guid));
+
+        if let Some(function) = info.node.child_by_field_name("function") {
+            match function.kind() {
+                "identifier" => {
+                    decl.ast_fields.name = code.slice(function.byte_range()).to_string();
+                }
+                "selector_expression" => {
+                    if let Some(field) = function.child_by_field_name("field") {
+                        decl.ast_fields.name = code.slice(field.byte_range()).to_string();
+                    }
+                    if let Some(operand) = function.child_by_field_name("operand") {
+                        candidates.push_back(CandidateInfo {
+                            ast_fields: decl.ast_fields.clone(),
+                            node: operand,
+                            parent_guid: info.parent_guid.clone(),
+                        });
+                    }
+                }
+                _ => {
+                    decl.ast_fields.name = code.slice(function.byte_range()).to_string();
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: info.ast_fields.clone(),
+                        node: function,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+        }
+        if let Some(arguments) = info.node.child_by_field_name("arguments") {
+            let mut new_ast_fields = info.ast_fields.clone();
+            new_ast_fields.caller_guid = None;
+            for i in 0..arguments.child_count() {
+                let child = arguments.child(i).unwrap();
+                candidates.push_back(CandidateInfo {
+                    ast_fields: new_ast_fields.clone(),
+                    node: child,
+                    parent_guid: info.parent_guid.clone(),
+                });
+            }
+        }
+
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    fn parse_import_spec<'a>(&mut self, info: &CandidateInfo<'a>, code: &str) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let Some(path_node) = info.node.child_by_field_name("path") else { return symbols };
+        let path = code.slice(path_node.byte_range()).trim_matches(&['"', '`'][..]).to_string();
+        let mut def = ImportDeclaration::default();
+        def.ast_fields.language = info.ast_fields.language;
+        def.ast_fields.full_range = info.node.range();
+        def.ast_fields.file_path = info.ast_fields.file_path.clone();
+        def.ast_fields.parent_guid = Some(info.parent_guid.clone());
+        def.ast_fields.guid = get_guid();
+        def.path_components = path.split("/").map(|x| x.to_string()).collect();
+        def.import_type = go_import_type(&path);
+        if let Some(alias) = info.node.child_by_field_name("name") {
+            def.alias = Some(code.slice(alias.byte_range()).to_string());
+        }
+        symbols.push(Arc::new(RwLock::new(Box::new(def))));
+        symbols
+    }
+
+    fn parse_usages_<'a>(&mut self, info: &CandidateInfo<'a>, code: &str, candidates: &mut VecDeque<CandidateInfo<'a>>) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let kind = info.node.kind();
+        #[cfg(test)]
+        #[allow(unused)]
+            let text = code.slice(info.node.byte_range());
+        match kind {
+            "type_spec" => {
+                let is_struct = info.node.child_by_field_name("type")
+                    .map_or(false, |t| ["struct_type", "interface_type"].contains(&t.kind()));
+                if is_struct {
+                    symbols.extend(self.parse_struct_declaration(info, code, candidates));
+                }
+            }
+            "function_declaration" | "method_declaration" | "method_spec" | "method_elem" => {
+                symbols.extend(self.parse_function_declaration(info, code, candidates));
+            }
+            "field_declaration" => {
+                symbols.extend(self.parse_field_declaration(info, code, candidates));
+            }
+            "var_spec" | "const_spec" | "short_var_declaration" => {
+                symbols.extend(self.parse_variable_definition(info, code, candidates));
+            }
+            "call_expression" => {
+                symbols.extend(self.parse_call_expression(info, code, candidates));
+            }
+            "import_spec" => {
+                symbols.extend(self.parse_import_spec(info, code));
+            }
+            "identifier" => {
+                let name = code.slice(info.node.byte_range()).to_string();
+                if ["_", "nil", "true", "false", "iota"].contains(&name.as_str()) {
+                    return symbols;
+                }
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = name;
+                usage.ast_fields.language = info.ast_fields.language;
+                usage.ast_fields.full_range = info.node.range();
+                usage.ast_fields.file_path = info.ast_fields.file_path.clone();
+                usage.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.is_error = info.ast_fields.is_error;
+                if let Some(caller_guid) = info.ast_fields.caller_guid.clone() {
+                    usage.ast_fields.guid = caller_guid;
+                }
+                symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+            }
+            "selector_expression" => {
+                let operand = info.node.child_by_field_name("operand").unwrap();
+                let field = info.node.child_by_field_name("field").unwrap();
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = code.slice(field.byte_range()).to_string();
+                usage.ast_fields.language = info.ast_fields.language;
+                usage.ast_fields.full_range = info.node.range();
+                usage.ast_fields.file_path = info.ast_fields.file_path.clone();
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                usage.ast_fields.caller_guid = Some(get_guid());
+                if let Some(caller_guid) = info.ast_fields.caller_guid.clone() {
+                    usage.ast_fields.guid = caller_guid;
+                }
+                candidates.push_back(CandidateInfo {
+                    ast_fields: usage.ast_fields.clone(),
+                    node: operand,
+                    parent_guid: info.parent_guid.clone(),
+                });
+                symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+            }
+            "comment" => {
+                let mut def = CommentDefinition::default();
+                def.ast_fields.language = info.ast_fields.language;
+                def.ast_fields.full_range = info.node.range();
+                def.ast_fields.file_path = info.ast_fields.file_path.clone();
+                def.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                def.ast_fields.guid = get_guid();
+                def.ast_fields.is_error = info.ast_fields.is_error;
+                symbols.push(Arc::new(RwLock::new(Box::new(def))));
+            }
+            "ERROR" => {
+                let mut ast = info.ast_fields.clone();
+                ast.is_error = true;
+
+                for i in 0..info.node.child_count() {
+                    let child = info.node.child(i).unwrap();
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: ast.clone(),
+                        node: child,
+                        parent_guid: info.parent_guid.clone(),
+                    });
+                }
+            }
+            "package_clause" | "field_identifier" | "type_identifier" | "package_identifier" => {}
+            _ => {
+                for i in 0..info.node.child_count() {
+                    let child = info.node.child(i).unwrap();
+                    candidates.push_back(CandidateInfo {
+                        ast_fields: info.ast_fields.clone(),
+                        node: child,
+                        parent_guid: info.parent_guid.clone(),
+                    })
+                }
+            }
+        }
+        symbols
+    }
+
+    fn find_error_usages(&mut self, parent: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        for i in 0..parent.child_count() {
+            let child = parent.child(i).unwrap();
+            if child.kind() == "ERROR" {
+                symbols.extend(self.parse_error_usages(&child, code, path, parent_guid));
+            }
+        }
+        symbols
+    }
+
+    fn parse_error_usages(&mut self, parent: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        match parent.kind() {
+            "identifier" => {
+                let name = code.slice(parent.byte_range()).to_string();
+                if GO_KEYWORDS.contains(&name.as_str()) {
+                    return symbols;
+                }
+
+                let mut usage = VariableUsage::default();
+                usage.ast_fields.name = name;
+                usage.ast_fields.language = LanguageId::Go;
+                usage.ast_fields.full_range = parent.range();
+                usage.ast_fields.file_path = path.clone();
+                usage.ast_fields.parent_guid = Some(parent_guid.clone());
+                usage.ast_fields.guid = get_guid();
+                usage.ast_fields.is_error = true;
+                symbols.push(Arc::new(RwLock::new(Box::new(usage))));
+            }
+            &_ => {
+                for i in 0..parent.child_count() {
+                    let child = parent.child(i).unwrap();
+                    symbols.extend(self.parse_error_usages(&child, code, path, parent_guid));
+                }
+            }
+        }
+
+        symbols
+    }
+
+    // Methods are declared next to the type, not inside it: hang them under the struct of their receiver
+    fn attach_methods_to_receivers(&self, root: &Node, code: &str, symbols: &Vec<AstSymbolInstanceArc>) {
+        let mut receivers: HashMap<usize, String> = HashMap::new();
+        for i in 0..root.child_count() {
+            let child = root.child(i).unwrap();
+            if child.kind() != "method_declaration" {
+                continue;
+            }
+            if let Some(type_name) = child.child_by_field_name("receiver").and_then(|r| receiver_type_name(&r, code)) {
+                receivers.insert(child.start_byte(), type_name);
+            }
+        }
+        if receivers.is_empty() {
+            return;
+        }
+        let structs: HashMap<String, Uuid> = symbols.iter()
+            .filter(|s| s.read().symbol_type() == SymbolType::StructDeclaration)
+            .map(|s| (s.read().name().to_string(), s.read().guid().clone()))
+            .collect();
+        for symbol in symbols.iter() {
+            let mut sym = symbol.write();
+            if sym.symbol_type() != SymbolType::FunctionDeclaration {
+                continue;
+            }
+            let Some(type_name) = receivers.get(&sym.full_range().start_byte) else { continue };
+            if let Some(struct_guid) = structs.get(type_name) {
+                sym.fields_mut().parent_guid = Some(struct_guid.clone());
+            }
+        }
+    }
+
+    fn parse_(&mut self, parent: &Node, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = Default::default();
+        let mut ast_fields = AstSymbolFields::default();
+        ast_fields.file_path = path.clone();
+        ast_fields.is_error = false;
+        ast_fields.language = LanguageId::Go;
+
+        let mut candidates = VecDeque::from(vec![CandidateInfo {
+            ast_fields,
+            node: parent.clone(),
+            parent_guid: get_guid(),
+        }]);
+        while let Some(candidate) = candidates.pop_front() {
+            let symbols_l = self.parse_usages_(&candidate, code, &mut candidates);
+            symbols.extend(symbols_l);
+        }
+        self.attach_methods_to_receivers(parent, code, &symbols);
+
+        let guid_to_symbol_map = symbols.iter()
+            .map(|s| (s.clone().read().guid().clone(), s.clone())).collect::<HashMap<_, _>>();
+        for symbol in symbols.iter_mut() {
+            let guid = symbol.read().guid().clone();
+            if let Some(parent_guid) = symbol.read().parent_guid() {
+                if let Some(parent) = guid_to_symbol_map.get(parent_guid) {
+                    parent.write().fields_mut().childs_guid.push(guid);
+                }
+            }
+        }
+
+        #[cfg(test)]
+        for symbol in symbols.iter_mut() {
+            let mut sym = symbol.write();
+            sym.fields_mut().childs_guid = sym.fields_mut().childs_guid.iter()
+                .sorted_by_key(|x| {
+                    guid_to_symbol_map.get(*x).unwrap().read().full_range().start_byte
+                }).map(|x| x.clone()).collect();
+        }
+
+        symbols
+    }
+}
+
+impl AstLanguageParser for GoParser {
+    fn parse_with_syntax_errors(&mut self, code: &str, path: &PathBuf) -> Option<(Vec<AstSymbolInstanceArc>, bool)> {
+        let tree = parse_tree_or_skip(&mut self.parser, code, path)?;
+        let symbols = self.parse_(&tree.root_node(), code, path);
+        Some((symbols, tree.root_node().has_error()))
+    }
+}
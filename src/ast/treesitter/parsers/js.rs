@@ -11,7 +11,7 @@ use uuid::Uuid;
 
 use crate::ast::treesitter::ast_instance_structs::{AstSymbolFields, AstSymbolInstanceArc, ClassFieldDeclaration, CommentDefinition, FunctionArg, FunctionCall, FunctionDeclaration, ImportDeclaration, ImportType, StructDeclaration, TypeDef, VariableDefinition, VariableUsage};
 use crate::ast::treesitter::language_id::LanguageId;
-use crate::ast::treesitter::parsers::{AstLanguageParser, internal_error, ParserError};
+use crate::ast::treesitter::parsers::{AstLanguageParser, internal_error, parse_tree_or_skip, ParserError};
 use crate::ast::treesitter::parsers::utils::{CandidateInfo, get_guid};
 
 pub(crate) struct JSParser {
@@ -788,10 +788,10 @@ impl JSParser {
 }
 
 impl AstLanguageParser for JSParser {
-    fn parse(&mut self, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc> {
-        let tree = self.parser.parse(code, None).unwrap();
+    fn parse_with_syntax_errors(&mut self, code: &str, path: &PathBuf) -> Option<(Vec<AstSymbolInstanceArc>, bool)> {
+        let tree = parse_tree_or_skip(&mut self.parser, code, path)?;
         let symbols = self.parse_(&tree.root_node(), code, path);
-        symbols
+        Some((symbols, tree.root_node().has_error()))
     }
 }
 
@@ -13,7 +13,7 @@ use uuid::Uuid;
 
 use crate::ast::treesitter::ast_instance_structs::{AstSymbolFields, AstSymbolInstanceArc, ClassFieldDeclaration, CommentDefinition, FunctionArg, FunctionCall, FunctionDeclaration, ImportDeclaration, ImportType, StructDeclaration, SymbolInformation, TypeDef, VariableDefinition, VariableUsage};
 use crate::ast::treesitter::language_id::LanguageId;
-use crate::ast::treesitter::parsers::{AstLanguageParser, internal_error, ParserError};
+use crate::ast::treesitter::parsers::{AstLanguageParser, internal_error, parse_tree_or_skip, ParserError};
 use crate::ast::treesitter::parsers::utils::{CandidateInfo, get_children_guids, get_guid};
 use crate::ast::treesitter::skeletonizer::SkeletonFormatter;
 use crate::ast::treesitter::structs::SymbolType;
@@ -942,9 +942,9 @@ impl SkeletonFormatter for PythonSkeletonFormatter {
 }
 
 impl AstLanguageParser for PythonParser {
-    fn parse(&mut self, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc> {
-        let tree = self.parser.parse(code, None).unwrap();
+    fn parse_with_syntax_errors(&mut self, code: &str, path: &PathBuf) -> Option<(Vec<AstSymbolInstanceArc>, bool)> {
+        let tree = parse_tree_or_skip(&mut self.parser, code, path)?;
         let symbols = self.parse_(&tree.root_node(), code, path);
-        symbols
+        Some((symbols, tree.root_node().has_error()))
     }
 }
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[cfg(test)]
+use itertools::Itertools;
+
+use parking_lot::RwLock;
+use regex::Regex;
+use similar::DiffableStr;
+use tree_sitter::{Node, Parser, Point, Range};
+use tree_sitter_sequel::language;
+use uuid::Uuid;
+
+use crate::ast::treesitter::ast_instance_structs::{AstSymbolFields, AstSymbolInstanceArc, ClassFieldDeclaration, FunctionArg, FunctionDeclaration, StructDeclaration, TypeDef, VariableDefinition};
+use crate::ast::treesitter::language_id::LanguageId;
+use crate::ast::treesitter::parsers::{AstLanguageParser, internal_error, parse_tree_or_skip, ParserError};
+use crate::ast::treesitter::parsers::utils::get_guid;
+
+pub(crate) struct SqlParser {
+    pub parser: Parser,
+}
+
+lazy_static::lazy_static! {
+    static ref CREATE_ROUTINE: Regex = Regex::new(r#"(?i)\bcreate\s+(?:or\s+replace\s+)?(?:function|procedure)\s+(?:[\w"`\[\]]+\.)*([\w"`\[\]]+)"#).unwrap();
+}
+
+fn unquote(name: &str) -> String {
+    name.trim_matches(|c| c == '"' || c == '`' || c == '[' || c == ']').to_string()
+}
+
+fn children<'a>(node: &Node<'a>) -> Vec<Node<'a>> {
+    (0..node.child_count()).filter_map(|i| node.child(i)).collect()
+}
+
+fn object_reference_name(parent: &Node, code: &str) -> Option<String> {
+    // object_reference is [database .] [schema .] name, the table itself is the last identifier
+    let obj = children(parent).into_iter().find(|c| c.kind() == "object_reference")?;
+    let name_node = obj.child_by_field_name("name")
+        .or_else(|| children(&obj).into_iter().filter(|c| c.kind() == "identifier").last())?;
+    Some(unquote(code.slice(name_node.byte_range())))
+}
+
+fn sql_type(node: Option<Node>, code: &str) -> TypeDef {
+    match node {
+        Some(node) => TypeDef {
+            name: Some(code.slice(node.byte_range()).to_string()),
+            inference_info: None,
+            inference_info_guid: None,
+            is_pod: true,
+            namespace: "".to_string(),
+            guid: None,
+            nested_types: vec![],
+        },
+        None => TypeDef::default(),
+    }
+}
+
+fn point_at(code: &str, byte: usize) -> Point {
+    let before = &code[..byte];
+    let row = before.matches('\n').count();
+    let column = byte - before.rfind('\n').map(|x| x + 1).unwrap_or(0);
+    Point { row, column }
+}
+
+impl SqlParser {
+    pub fn new() -> Result<SqlParser, ParserError> {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&language())
+            .map_err(internal_error)?;
+        Ok(SqlParser { parser })
+    }
+
+    fn make_fields(&self, range: Range, path: &PathBuf, parent_guid: &Uuid, is_error: bool) -> AstSymbolFields {
+        let mut ast_fields = AstSymbolFields::default();
+        ast_fields.language = LanguageId::Sql;
+        ast_fields.file_path = path.clone();
+        ast_fields.full_range = range;
+        ast_fields.declaration_range = range;
+        ast_fields.definition_range = range;
+        ast_fields.parent_guid = Some(parent_guid.clone());
+        ast_fields.guid = get_guid();
+        ast_fields.is_error = is_error;
+        ast_fields
+    }
+
+    fn parse_create_table(&mut self, node: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid, is_error: bool) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let mut decl = StructDeclaration::default();
+        decl.ast_fields = self.make_fields(node.range(), path, parent_guid, is_error);
+        decl.ast_fields.name = object_reference_name(node, code).unwrap_or_default();
+
+        if let Some(columns) = children(node).into_iter().find(|c| c.kind() == "column_definitions") {
+            decl.ast_fields.definition_range = columns.range();
+            decl.ast_fields.declaration_range = Range {
+                start_byte: node.start_byte(),
+                end_byte: columns.start_byte(),
+                start_point: node.start_position(),
+                end_point: columns.start_position(),
+            };
+            for column in children(&columns).into_iter().filter(|c| c.kind() == "column_definition") {
+                let name_node = match column.child_by_field_name("name").or(column.child(0)) {
+                    Some(n) => n,
+                    None => continue,
+                };
+                let mut field = ClassFieldDeclaration::default();
+                field.ast_fields = self.make_fields(column.range(), path, &decl.ast_fields.guid, is_error);
+                field.ast_fields.name = unquote(code.slice(name_node.byte_range()));
+                field.type_ = sql_type(column.child_by_field_name("type"), code);
+                symbols.push(Arc::new(RwLock::new(Box::new(field))));
+            }
+        }
+        symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        symbols
+    }
+
+    fn parse_create_view(&mut self, node: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid, is_error: bool) -> Vec<AstSymbolInstanceArc> {
+        let mut decl = StructDeclaration::default();
+        decl.ast_fields = self.make_fields(node.range(), path, parent_guid, is_error);
+        decl.ast_fields.name = object_reference_name(node, code).unwrap_or_default();
+        if let Some(query) = children(node).into_iter().find(|c| c.kind() == "create_query") {
+            decl.ast_fields.definition_range = query.range();
+            decl.ast_fields.declaration_range = Range {
+                start_byte: node.start_byte(),
+                end_byte: query.start_byte(),
+                start_point: node.start_position(),
+                end_point: query.start_position(),
+            };
+        }
+        vec![Arc::new(RwLock::new(Box::new(decl)))]
+    }
+
+    fn parse_create_index(&mut self, node: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid, is_error: bool) -> Vec<AstSymbolInstanceArc> {
+        // CREATE INDEX name ON table (...), the index is a variable of the table "type"
+        let mut decl = VariableDefinition::default();
+        decl.ast_fields = self.make_fields(node.range(), path, parent_guid, is_error);
+        let name_node = node.child_by_field_name("column")
+            .or_else(|| children(node).into_iter().take_while(|c| c.kind() != "keyword_on").find(|c| c.kind() == "identifier"));
+        match name_node {
+            Some(name_node) => decl.ast_fields.name = unquote(code.slice(name_node.byte_range())),
+            None => return vec![],  // unnamed index, nothing to look up
+        }
+        if let Some(table) = object_reference_name(node, code) {
+            decl.type_ = TypeDef { name: Some(table), ..TypeDef::default() };
+        }
+        vec![Arc::new(RwLock::new(Box::new(decl)))]
+    }
+
+    fn parse_create_function(&mut self, node: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid, is_error: bool) -> Vec<AstSymbolInstanceArc> {
+        let mut decl = FunctionDeclaration::default();
+        decl.ast_fields = self.make_fields(node.range(), path, parent_guid, is_error);
+        decl.ast_fields.name = object_reference_name(node, code).unwrap_or_default();
+        if let Some(arguments) = children(node).into_iter().find(|c| c.kind() == "function_arguments") {
+            decl.ast_fields.declaration_range = Range {
+                start_byte: node.start_byte(),
+                end_byte: arguments.end_byte(),
+                start_point: node.start_position(),
+                end_point: arguments.end_position(),
+            };
+            for arg in children(&arguments).into_iter().filter(|c| c.kind() == "function_argument") {
+                let arg_children = children(&arg);
+                let name = arg_children.iter().find(|c| c.kind() == "identifier")
+                    .map(|n| unquote(code.slice(n.byte_range()))).unwrap_or_default();
+                let type_ = arg.child_by_field_name("type")
+                    .or_else(|| arg_children.iter().rev().find(|c| c.kind() != "identifier").cloned());
+                decl.args.push(FunctionArg { name, type_: Some(sql_type(type_, code)) });
+            }
+        }
+        if let Some(body) = children(node).into_iter().find(|c| c.kind() == "function_body") {
+            decl.ast_fields.definition_range = body.range();
+        }
+        decl.return_type = children(node).into_iter()
+            .skip_while(|c| c.kind() != "keyword_returns")
+            .nth(1)
+            .map(|t| sql_type(Some(t), code));
+        vec![Arc::new(RwLock::new(Box::new(decl)))]
+    }
+
+    fn parse_error_routines(&mut self, node: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid) -> Vec<AstSymbolInstanceArc> {
+        // procedures and dialect-specific functions are not in the grammar, they end up in ERROR nodes
+        let text = code.slice(node.byte_range());
+        let mut symbols: Vec<AstSymbolInstanceArc> = vec![];
+        let matches = CREATE_ROUTINE.captures_iter(text).collect::<Vec<_>>();
+        for (i, caps) in matches.iter().enumerate() {
+            let start_byte = node.start_byte() + caps.get(0).unwrap().start();
+            let end_byte = matches.get(i + 1)
+                .map(|next| node.start_byte() + next.get(0).unwrap().start())
+                .unwrap_or(node.end_byte());
+            let range = Range {
+                start_byte,
+                end_byte,
+                start_point: point_at(code, start_byte),
+                end_point: point_at(code, end_byte),
+            };
+            let mut decl = FunctionDeclaration::default();
+            decl.ast_fields = self.make_fields(range, path, parent_guid, true);
+            decl.ast_fields.name = unquote(caps.get(1).unwrap().as_str());
+            symbols.push(Arc::new(RwLock::new(Box::new(decl))));
+        }
+        symbols
+    }
+
+    fn parse_node(&mut self, node: &Node, code: &str, path: &PathBuf, parent_guid: &Uuid, is_error: bool) -> Vec<AstSymbolInstanceArc> {
+        match node.kind() {
+            "create_table" => self.parse_create_table(node, code, path, parent_guid, is_error),
+            "create_view" | "create_materialized_view" => self.parse_create_view(node, code, path, parent_guid, is_error),
+            "create_index" => self.parse_create_index(node, code, path, parent_guid, is_error),
+            "create_function" | "create_procedure" => self.parse_create_function(node, code, path, parent_guid, is_error),
+            "ERROR" => {
+                let mut symbols = vec![];
+                for child in children(node) {
+                    symbols.extend(self.parse_node(&child, code, path, parent_guid, true));
+                }
+                let known_names = symbols.iter().map(|s| s.read().name().to_string()).collect::<Vec<_>>();
+                symbols.extend(self.parse_error_routines(node, code, path, parent_guid).into_iter()
+                    .filter(|s| !known_names.contains(&s.read().name().to_string())));
+                symbols
+            }
+            _ => {
+                let mut symbols = vec![];
+                for child in children(node) {
+                    symbols.extend(self.parse_node(&child, code, path, parent_guid, is_error));
+                }
+                symbols
+            }
+        }
+    }
+
+    fn parse_(&mut self, parent: &Node, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc> {
+        let mut symbols = self.parse_node(parent, code, path, &get_guid(), false);
+        let guid_to_symbol_map = symbols.iter()
+            .map(|s| (s.clone().read().guid().clone(), s.clone())).collect::<HashMap<_, _>>();
+        for symbol in symbols.iter_mut() {
+            let guid = symbol.read().guid().clone();
+            if let Some(parent_guid) = symbol.read().parent_guid() {
+                if let Some(parent) = guid_to_symbol_map.get(parent_guid) {
+                    parent.write().fields_mut().childs_guid.push(guid);
+                }
+            }
+        }
+
+        #[cfg(test)]
+        for symbol in symbols.iter_mut() {
+            let mut sym = symbol.write();
+            sym.fields_mut().childs_guid = sym.fields_mut().childs_guid.iter()
+                .sorted_by_key(|x| {
+                    guid_to_symbol_map.get(*x).unwrap().read().full_range().start_byte
+                }).map(|x| x.clone()).collect();
+        }
+
+        symbols
+    }
+}
+
+impl AstLanguageParser for SqlParser {
+    fn parse_with_syntax_errors(&mut self, code: &str, path: &PathBuf) -> Option<(Vec<AstSymbolInstanceArc>, bool)> {
+        let tree = parse_tree_or_skip(&mut self.parser, code, path)?;
+        let symbols = self.parse_(&tree.root_node(), code, path);
+        Some((symbols, tree.root_node().has_error()))
+    }
+}
@@ -0,0 +1,37 @@
+package shapes
+
+import (
+	"fmt"
+	"math"
+
+	"github.com/acme/geometry/units"
+)
+
+// Point is a position on the plane
+type Point struct {
+	X, Y float64
+}
+
+type Circle struct {
+	Point
+	Radius float64
+	Unit   units.Length
+}
+
+type Shape interface {
+	Area() float64
+}
+
+func (c *Circle) Area() float64 {
+	return math.Pi * c.Radius * c.Radius
+}
+
+func (c Circle) Describe() string {
+	return fmt.Sprintf("circle at %v", c.Point)
+}
+
+func NewCircle(x, y, r float64) *Circle {
+	area := math.Pow(r, 2)
+	fmt.Println(area)
+	return &Circle{Point: Point{X: x, Y: y}, Radius: r}
+}
@@ -0,0 +1,17 @@
+-- initial schema
+CREATE TABLE users (
+    id BIGINT PRIMARY KEY,
+    email VARCHAR(255) NOT NULL UNIQUE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS public.orders (
+    id BIGINT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users (id),
+    total NUMERIC(10, 2) NOT NULL
+);
+
+CREATE INDEX orders_user_id_idx ON orders (user_id);
+
+CREATE VIEW user_totals AS
+SELECT user_id, SUM(total) AS total FROM orders GROUP BY user_id;
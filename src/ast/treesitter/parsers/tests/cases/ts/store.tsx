@@ -0,0 +1,29 @@
+import React, { useState as useLocalState } from "react";
+import { formatPrice } from "./format";
+
+export interface Item {
+    id: number;
+    title: string;
+}
+
+export class Store<T extends Item> {
+    private items: Map<number, T> = new Map();
+
+    add(item: T): void {
+        this.items.set(item.id, item);
+    }
+}
+
+const PriceTag = ({ value }: { value: number }) => {
+    return <span className="price">{formatPrice(value)}</span>;
+};
+
+export const ItemRow = (props: { item: Item }) => {
+    const [selected, setSelected] = useLocalState(false);
+    return <div onClick={() => setSelected(!selected)}>{props.item.title}<PriceTag value={props.item.id} /></div>;
+};
+
+export default function App() {
+    const store = new Store<Item>();
+    return <ItemRow item={{ id: 1, title: "goat" }} />;
+}
@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::ast::treesitter::ast_instance_structs::{FunctionDeclaration, ImportDeclaration, ImportType, StructDeclaration};
+    use crate::ast::treesitter::parsers::AstLanguageParser;
+    use crate::ast::treesitter::parsers::go::GoParser;
+    use crate::ast::treesitter::structs::SymbolType;
+
+    const SHAPES_GO_CODE: &str = include_str!("cases/go/shapes.go");
+
+    #[test]
+    fn structs_methods_and_imports_test() {
+        let mut parser: Box<dyn AstLanguageParser> = Box::new(GoParser::new().expect("GoParser::new"));
+        let path = PathBuf::from("file:///shapes/shapes.go");
+        let symbols = parser.parse(SHAPES_GO_CODE, &path);
+        let of_type = |t: SymbolType| symbols.iter().filter(|s| s.read().symbol_type() == t).map(|s| s.read().name().to_string()).collect::<Vec<_>>();
+        let find = |name: &str, t: SymbolType| symbols.iter().find(|s| s.read().name() == name && s.read().symbol_type() == t).expect(name).clone();
+
+        let mut structs = of_type(SymbolType::StructDeclaration);
+        structs.sort();
+        assert_eq!(structs, vec!["Circle", "Point", "Shape"]);
+        let mut functions = of_type(SymbolType::FunctionDeclaration);
+        functions.sort();
+        assert_eq!(functions, vec!["Area", "Area", "Describe", "NewCircle"]);
+
+        // methods hang under the struct of their receiver, pointer or not
+        let circle = find("Circle", SymbolType::StructDeclaration);
+        let circle_children = symbols.iter()
+            .filter(|s| s.read().parent_guid().as_ref() == Some(circle.read().guid()))
+            .map(|s| (s.read().name().to_string(), s.read().symbol_type()))
+            .collect::<Vec<_>>();
+        assert_eq!(circle_children.len(), 4, "{:?}", circle_children);
+        for expected in [("Radius", SymbolType::ClassFieldDeclaration), ("Unit", SymbolType::ClassFieldDeclaration), ("Area", SymbolType::FunctionDeclaration), ("Describe", SymbolType::FunctionDeclaration)] {
+            assert!(circle_children.contains(&(expected.0.to_string(), expected.1)), "{:?}", circle_children);
+        }
+        let circle_inherited = circle.read().as_any().downcast_ref::<StructDeclaration>().unwrap().inherited_types.iter()
+            .filter_map(|t| t.name.clone()).collect::<Vec<_>>();
+        assert_eq!(circle_inherited, vec!["Point"]);
+        assert_eq!(find("Point", SymbolType::StructDeclaration).read().childs_guid().len(), 2);
+
+        let new_circle = find("NewCircle", SymbolType::FunctionDeclaration);
+        assert_eq!(new_circle.read().as_any().downcast_ref::<FunctionDeclaration>().unwrap().args.len(), 3);
+        assert_eq!(find("Pow", SymbolType::FunctionCall).read().parent_guid().as_ref(), Some(new_circle.read().guid()));
+        assert_eq!(find("area", SymbolType::VariableDefinition).read().parent_guid().as_ref(), Some(new_circle.read().guid()));
+        assert!(of_type(SymbolType::FunctionCall).contains(&"Sprintf".to_string()));
+        assert!(of_type(SymbolType::VariableUsage).contains(&"Radius".to_string()));
+
+        let imports = symbols.iter()
+            .filter(|s| s.read().symbol_type() == SymbolType::ImportDeclaration)
+            .map(|s| {
+                let s = s.read();
+                let import = s.as_any().downcast_ref::<ImportDeclaration>().unwrap();
+                (import.path_components.join("/"), import.import_type.clone())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(imports, vec![
+            ("fmt".to_string(), ImportType::System),
+            ("math".to_string(), ImportType::System),
+            ("github.com/acme/geometry/units".to_string(), ImportType::Library),
+        ]);
+    }
+}
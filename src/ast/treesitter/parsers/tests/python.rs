@@ -4,7 +4,7 @@ mod tests {
     use std::path::PathBuf;
 
     use crate::ast::treesitter::language_id::LanguageId;
-    use crate::ast::treesitter::parsers::AstLanguageParser;
+    use crate::ast::treesitter::parsers::{get_ast_parser_by_filename, parse_extension_overrides, parse_tree_with_timeout, tree_sitter_language, ExtensionOverridesGuard, AstLanguageParser};
     use crate::ast::treesitter::parsers::python::PythonParser;
     use crate::ast::treesitter::parsers::tests::{base_declaration_formatter_test, base_parser_test, base_skeletonizer_test};
 
@@ -14,6 +14,40 @@ mod tests {
     const CALCULATOR_PY_DECLS: &str = include_str!("cases/python/calculator.py.decl_json");
     const MAIN_PY_SYMBOLS: &str = include_str!("cases/python/main.py.json");
 
+    #[test]
+    fn extension_override_test() {
+        assert!(parse_extension_overrides("pyq=klingon").is_err());
+        assert!(parse_extension_overrides("pyq").is_err());
+        let _overrides = ExtensionOverridesGuard::set(parse_extension_overrides("pyq=python, .calc.txt=python").unwrap());
+        let path = PathBuf::from("/tmp/calculator.calc.txt");
+        let (mut parser, language_id) = get_ast_parser_by_filename(&path).expect("overridden extension should have a parser");
+        assert_eq!(language_id, LanguageId::Python);
+        let symbols = parser.parse(CALCULATOR_PY_CODE, &path);
+        assert!(symbols.iter().any(|s| s.read().name() == "Calculator"));
+        assert!(get_ast_parser_by_filename(&PathBuf::from("/tmp/notes.txt")).is_err());
+        assert!(crate::files_in_workspace::is_path_to_enqueue_valid(&PathBuf::from("/tmp/x.pyq")).is_ok());
+
+        drop(_overrides);
+        assert!(get_ast_parser_by_filename(&path).is_err());
+    }
+
+    #[test]
+    fn parse_timeout_test() {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&tree_sitter_language(LanguageId::Python).unwrap()).unwrap();
+        let path = PathBuf::from("/tmp/generated.py");
+        // deep nesting, lots of work for the parser
+        let adversarial = format!("x = {}1{}\n", "[(".repeat(300_000), ")]".repeat(300_000)).repeat(4);
+        let t0 = std::time::Instant::now();
+        assert!(parse_tree_with_timeout(&mut parser, &adversarial, &path, 5_000).is_none());
+        assert!(t0.elapsed() < std::time::Duration::from_secs(2), "{:?}", t0.elapsed());
+
+        // the aborted parse doesn't leak into the next file
+        let tree = parse_tree_with_timeout(&mut parser, CALCULATOR_PY_CODE, &path, 1_000_000).expect("a normal file fits in the timeout");
+        assert!(!tree.root_node().has_error());
+        assert!(tree.root_node().utf8_text(CALCULATOR_PY_CODE.as_bytes()).unwrap().contains("class Calculator"));
+    }
+
     #[test]
     #[ignore]
     fn parser_test() {
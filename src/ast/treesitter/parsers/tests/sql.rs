@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::ast::treesitter::parsers::AstLanguageParser;
+    use crate::ast::treesitter::parsers::sql::SqlParser;
+    use crate::ast::treesitter::structs::SymbolType;
+
+    const INIT_SQL_CODE: &str = include_str!("cases/sql/0001_init.sql");
+
+    #[test]
+    fn tables_and_columns_test() {
+        let mut parser: Box<dyn AstLanguageParser> = Box::new(SqlParser::new().expect("SqlParser::new"));
+        let path = PathBuf::from("file:///migrations/0001_init.sql");
+        let symbols = parser.parse(INIT_SQL_CODE, &path);
+        let find = |name: &str| symbols.iter().find(|s| s.read().name() == name).expect(name).clone();
+
+        let users = find("users");
+        assert_eq!(users.read().symbol_type(), SymbolType::StructDeclaration);
+        assert_eq!(users.read().full_range().start_point.row, 1);
+        let users_columns = symbols.iter()
+            .filter(|s| s.read().parent_guid().as_ref() == Some(users.read().guid()))
+            .map(|s| (s.read().name().to_string(), s.read().symbol_type()))
+            .collect::<Vec<_>>();
+        assert_eq!(users_columns, vec![
+            ("id".to_string(), SymbolType::ClassFieldDeclaration),
+            ("email".to_string(), SymbolType::ClassFieldDeclaration),
+            ("created_at".to_string(), SymbolType::ClassFieldDeclaration),
+        ]);
+
+        let orders = find("orders");
+        assert_eq!(orders.read().symbol_type(), SymbolType::StructDeclaration);
+        assert_eq!(orders.read().childs_guid().len(), 3);
+        assert_eq!(find("total").read().parent_guid().as_ref(), Some(orders.read().guid()));
+
+        assert_eq!(find("orders_user_id_idx").read().symbol_type(), SymbolType::VariableDefinition);
+        assert_eq!(find("user_totals").read().symbol_type(), SymbolType::StructDeclaration);
+    }
+
+    #[test]
+    fn functions_and_procedures_test() {
+        let mut parser: Box<dyn AstLanguageParser> = Box::new(SqlParser::new().expect("SqlParser::new"));
+        let path = PathBuf::from("file:///migrations/0002_routines.sql");
+        let code = r#"CREATE OR REPLACE FUNCTION add_one(x INTEGER) RETURNS INTEGER AS $$ SELECT x + 1 $$ LANGUAGE sql;
+
+CREATE PROCEDURE archive_orders(IN cutoff DATE)
+BEGIN
+    DELETE FROM orders WHERE created_at < cutoff;
+END;
+"#;
+        let symbols = parser.parse(code, &path);
+        let functions = symbols.iter()
+            .filter(|s| s.read().symbol_type() == SymbolType::FunctionDeclaration)
+            .map(|s| s.read().name().to_string())
+            .collect::<Vec<_>>();
+        assert!(functions.contains(&"add_one".to_string()), "{:?}", functions);
+        assert!(functions.contains(&"archive_orders".to_string()), "{:?}", functions);
+    }
+}
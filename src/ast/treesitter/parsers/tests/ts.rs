@@ -3,10 +3,12 @@ mod tests {
     use std::fs::canonicalize;
     use std::path::PathBuf;
 
+    use crate::ast::treesitter::ast_instance_structs::{ImportDeclaration, StructDeclaration};
     use crate::ast::treesitter::language_id::LanguageId;
     use crate::ast::treesitter::parsers::AstLanguageParser;
     use crate::ast::treesitter::parsers::tests::{base_declaration_formatter_test, base_parser_test, base_skeletonizer_test};
     use crate::ast::treesitter::parsers::ts::TSParser;
+    use crate::ast::treesitter::structs::SymbolType;
 
     const MAIN_TS_CODE: &str = include_str!("cases/ts/main.ts");
     const MAIN_TS_SYMBOLS: &str = include_str!("cases/ts/main.ts.json");
@@ -15,6 +17,8 @@ mod tests {
     const PERSON_TS_SKELETON: &str = include_str!("cases/ts/person.ts.skeleton");
     const PERSON_TS_DECLS: &str = include_str!("cases/ts/person.ts.decl_json");
 
+    const STORE_TSX_CODE: &str = include_str!("cases/ts/store.tsx");
+
     #[test]
     fn parser_test() {
         let mut parser: Box<dyn AstLanguageParser> = Box::new(TSParser::new().expect("TSParser::new"));
@@ -38,4 +42,52 @@ mod tests {
         assert!(file.exists());
         base_declaration_formatter_test(&LanguageId::Java, &mut parser, &file, PERSON_TS_CODE, PERSON_TS_DECLS);
     }
+
+    #[test]
+    fn tsx_components_and_generics_test() {
+        let mut parser: Box<dyn AstLanguageParser> = Box::new(TSParser::new_tsx().expect("TSParser::new_tsx"));
+        let path = PathBuf::from("file:///store.tsx");
+        let symbols = parser.parse(STORE_TSX_CODE, &path);
+        let of_type = |t: SymbolType| symbols.iter().filter(|s| s.read().symbol_type() == t).map(|s| s.read().name().to_string()).collect::<Vec<_>>();
+        let find = |name: &str, t: SymbolType| symbols.iter().find(|s| s.read().name() == name && s.read().symbol_type() == t).expect(name).clone();
+        assert!(symbols.iter().all(|s| s.read().language() == &LanguageId::TypeScriptReact));
+
+        let mut structs = of_type(SymbolType::StructDeclaration);
+        structs.sort();
+        assert_eq!(structs, vec!["Item", "Store"]);
+        let store = find("Store", SymbolType::StructDeclaration);
+        let template_types = store.read().as_any().downcast_ref::<StructDeclaration>().unwrap().template_types.iter()
+            .filter_map(|t| t.name.clone()).collect::<Vec<_>>();
+        assert_eq!(template_types, vec!["T"]);
+
+        // arrow functions take the name of their variable, the anonymous default export is "default"
+        let functions = of_type(SymbolType::FunctionDeclaration);
+        for expected in ["add", "PriceTag", "ItemRow", "App"] {
+            assert!(functions.contains(&expected.to_string()), "{:?}", functions);
+        }
+        assert!(!of_type(SymbolType::VariableDefinition).contains(&"PriceTag".to_string()));
+        let item_row = find("ItemRow", SymbolType::FunctionDeclaration);
+        assert_eq!(item_row.read().full_range().start_point.row, 20);
+
+        // <PriceTag/> is a call of the component, <div> is not
+        let calls = of_type(SymbolType::FunctionCall);
+        assert!(calls.contains(&"PriceTag".to_string()) && calls.contains(&"ItemRow".to_string()), "{:?}", calls);
+        assert!(!calls.contains(&"div".to_string()) && !calls.contains(&"span".to_string()), "{:?}", calls);
+        assert_eq!(find("PriceTag", SymbolType::FunctionCall).read().parent_guid().as_ref(), Some(item_row.read().guid()));
+
+        let imports = symbols.iter()
+            .filter(|s| s.read().symbol_type() == SymbolType::ImportDeclaration)
+            .map(|s| {
+                let s = s.read();
+                let import = s.as_any().downcast_ref::<ImportDeclaration>().unwrap();
+                (s.name().to_string(), import.alias.clone())
+            })
+            .collect::<Vec<_>>();
+        assert!(imports.contains(&("useState".to_string(), Some("useLocalState".to_string()))), "{:?}", imports);
+        assert!(imports.contains(&("formatPrice".to_string(), None)), "{:?}", imports);
+
+        let mut default_export = TSParser::new_tsx().unwrap();
+        let anon = default_export.parse("export default class { run() {} }\n", &PathBuf::from("file:///anon.tsx"));
+        assert!(anon.iter().any(|s| s.read().name() == "default" && s.read().symbol_type() == SymbolType::StructDeclaration));
+    }
 }
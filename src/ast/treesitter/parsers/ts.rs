@@ -8,16 +8,17 @@ use parking_lot::RwLock;
 
 use similar::DiffableStr;
 use tree_sitter::{Node, Parser, Range};
-use tree_sitter_typescript::language_typescript as language;
+use tree_sitter_typescript::{language_tsx, language_typescript as language};
 use uuid::Uuid;
 
 use crate::ast::treesitter::ast_instance_structs::{AstSymbolFields, AstSymbolInstanceArc, ClassFieldDeclaration, CommentDefinition, FunctionArg, FunctionCall, FunctionDeclaration, ImportDeclaration, ImportType, StructDeclaration, TypeDef, VariableDefinition, VariableUsage};
 use crate::ast::treesitter::language_id::LanguageId;
-use crate::ast::treesitter::parsers::{AstLanguageParser, internal_error, ParserError};
+use crate::ast::treesitter::parsers::{AstLanguageParser, internal_error, parse_tree_or_skip, ParserError};
 use crate::ast::treesitter::parsers::utils::{CandidateInfo, get_guid};
 
 pub(crate) struct TSParser {
     pub parser: Parser,
+    pub language_id: LanguageId,
 }
 
 pub fn parse_type(parent: &Node, code: &str) -> Option<TypeDef> {
@@ -130,13 +131,28 @@ pub fn parse_type(parent: &Node, code: &str) -> Option<TypeDef> {
     None
 }
 
+fn is_default_export(node: &Node) -> bool {
+    node.parent().map_or(false, |parent| {
+        parent.kind() == "export_statement" && (0..parent.child_count()).any(|i| parent.child(i).unwrap().kind() == "default")
+    })
+}
+
 impl TSParser {
     pub fn new() -> Result<Self, ParserError> {
         let mut parser = Parser::new();
         parser
             .set_language(&language())
             .map_err(internal_error)?;
-        Ok(Self { parser })
+        Ok(Self { parser, language_id: LanguageId::TypeScript })
+    }
+
+    // Same symbols, the grammar also knows JSX
+    pub fn new_tsx() -> Result<Self, ParserError> {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&language_tsx())
+            .map_err(internal_error)?;
+        Ok(Self { parser, language_id: LanguageId::TypeScriptReact })
     }
 
     pub fn parse_struct_declaration<'a>(
@@ -159,6 +175,8 @@ impl TSParser {
 
         if let Some(name) = info.node.child_by_field_name("name") {
             decl.ast_fields.name = code.slice(name.byte_range()).to_string();
+        } else if is_default_export(&info.node) {
+            decl.ast_fields.name = "default".to_string();
         } else {
             decl.ast_fields.name = format!("anon-{}", decl.ast_fields.guid);
         }
@@ -264,6 +282,24 @@ impl TSParser {
         if let Some(name) = info.node.child_by_field_name("name") {
             decl.ast_fields.name = code.slice(name.byte_range()).to_string();
         }
+        // `const App = () => ...` is how components and helpers are usually written, it's a function named App
+        if let Some(value) = info.node.child_by_field_name("value") {
+            if ["arrow_function", "function_expression", "function"].contains(&value.kind()) && !decl.ast_fields.name.is_empty() {
+                let func_info = CandidateInfo {
+                    ast_fields: info.ast_fields.clone(),
+                    node: value,
+                    parent_guid: info.parent_guid.clone(),
+                };
+                let func_symbols = self.parse_function_declaration(&func_info, code, candidates);
+                if let Some(func) = func_symbols.last() {
+                    let mut func = func.write();
+                    func.fields_mut().name = decl.ast_fields.name.clone();
+                    func.fields_mut().full_range = info.node.range();
+                }
+                symbols.extend(func_symbols);
+                return symbols;
+            }
+        }
         if let Some(type_node) = info.node.child_by_field_name("type") {
             if let Some(type_) = parse_type(&type_node, code) {
                 decl.type_ = type_;
@@ -375,6 +411,8 @@ impl TSParser {
 
         if let Some(name) = info.node.child_by_field_name("name") {
             decl.ast_fields.name = code.slice(name.byte_range()).to_string();
+        } else if is_default_export(&info.node) {
+            decl.ast_fields.name = "default".to_string();
         }
 
         if let Some(type_parameters) = info.node.child_by_field_name("type_parameters") {
@@ -550,7 +588,7 @@ impl TSParser {
             "identifier" /*| "field_identifier"*/ => {
                 let mut usage = VariableUsage::default();
                 usage.ast_fields.file_path = path.clone();
-                usage.ast_fields.language = LanguageId::TypeScript;
+                usage.ast_fields.language = self.language_id;
                 usage.ast_fields.is_error = true;
                 usage.ast_fields.name = code.slice(parent.byte_range()).to_string();
                 usage.ast_fields.full_range = parent.range();
@@ -564,7 +602,7 @@ impl TSParser {
             "member_expression" => {
                 let mut usage = VariableUsage::default();
                 usage.ast_fields.file_path = path.clone();
-                usage.ast_fields.language = LanguageId::TypeScript;
+                usage.ast_fields.language = self.language_id;
                 usage.ast_fields.is_error = true;
                 if let Some(property) = parent.child_by_field_name("property") {
                     usage.ast_fields.name = code.slice(property.byte_range()).to_string();
@@ -652,6 +690,29 @@ impl TSParser {
                 }
                 symbols.push(Arc::new(RwLock::new(Box::new(usage))));
             }
+            "jsx_opening_element" | "jsx_self_closing_element" => {
+                // <Button/> calls the Button component, lowercase tags are plain html
+                let name = info.node.child_by_field_name("name").map(|n| code.slice(n.byte_range()).to_string()).unwrap_or_default();
+                if name.starts_with(|c: char| c.is_uppercase()) {
+                    let mut call = FunctionCall::default();
+                    call.ast_fields = AstSymbolFields::from_fields(&info.ast_fields);
+                    call.ast_fields.name = name.rsplit('.').next().unwrap_or(&name).to_string();
+                    call.ast_fields.full_range = info.node.range();
+                    call.ast_fields.parent_guid = Some(info.parent_guid.clone());
+                    call.ast_fields.guid = get_guid();
+                    symbols.push(Arc::new(RwLock::new(Box::new(call))));
+                }
+                for i in 0..info.node.child_count() {
+                    let child = info.node.child(i).unwrap();
+                    if child.kind() == "jsx_attribute" {
+                        candidates.push_back(CandidateInfo {
+                            ast_fields: info.ast_fields.clone(),
+                            node: child,
+                            parent_guid: info.parent_guid.clone(),
+                        });
+                    }
+                }
+            }
             "new_expression" => {
                 if let Some(constructor) = info.node.child_by_field_name("constructor") {
                     candidates.push_back(CandidateInfo {
@@ -781,7 +842,7 @@ impl TSParser {
         let mut ast_fields = AstSymbolFields::default();
         ast_fields.file_path = path.clone();
         ast_fields.is_error = false;
-        ast_fields.language = LanguageId::TypeScript;
+        ast_fields.language = self.language_id;
 
         let mut candidates = VecDeque::from(vec![CandidateInfo {
             ast_fields,
@@ -819,10 +880,10 @@ impl TSParser {
 }
 
 impl AstLanguageParser for TSParser {
-    fn parse(&mut self, code: &str, path: &PathBuf) -> Vec<AstSymbolInstanceArc> {
-        let tree = self.parser.parse(code, None).unwrap();
+    fn parse_with_syntax_errors(&mut self, code: &str, path: &PathBuf) -> Option<(Vec<AstSymbolInstanceArc>, bool)> {
+        let tree = parse_tree_or_skip(&mut self.parser, code, path)?;
         let symbols = self.parse_(&tree.root_node(), code, path);
-        symbols
+        Some((symbols, tree.root_node().has_error()))
     }
 }
 
@@ -7,7 +7,7 @@ use async_trait::async_trait;
 use tokio::sync::Mutex as AMutex;
 use tokio::sync::RwLock as ARwLock;
 
-use crate::call_validation::{ChatMessage, ContextFile, ContextEnum, SubchatParameters, PostprocessSettings};
+use crate::call_validation::{ChatMessage, ContextFile, ContextEnum, SubchatParameters, PostprocessSettings, ToolsPermissions};
 use crate::global_context::GlobalContext;
 
 use crate::at_commands::at_file::AtFile;
@@ -35,8 +35,9 @@ pub struct AtCommandsContext {
     pub at_commands: HashMap<String, Arc<AMutex<Box<dyn AtCommand + Send>>>>,  // a copy from static constant
     pub subchat_tool_parameters: IndexMap<String, SubchatParameters>,
     pub postprocess_parameters: PostprocessSettings,
+    pub tools_permissions: ToolsPermissions,  // from command line, requests can only narrow it
 
-    pub subchat_tx: Arc<AMutex<mpsc::UnboundedSender<serde_json::Value>>>, // one and only supported format for now {"tool_call_id": xx, "subchat_id": xx, "add_message": {...}}
+    pub subchat_tx: Arc<AMutex<mpsc::UnboundedSender<serde_json::Value>>>, // {"tool_call_id": xx, "subchat_id": xx, "add_message": {...}} or {"tool_call_id": xx, "tool_output_partial": {...}} followed by {"tool_call_id": xx, "tool_output_finished": {...}}
     pub subchat_rx: Arc<AMutex<mpsc::UnboundedReceiver<serde_json::Value>>>,
 }
 
@@ -51,6 +52,10 @@ impl AtCommandsContext {
         should_execute_remotely: bool,
     ) -> Self {
         let (tx, rx) = mpsc::unbounded_channel::<serde_json::Value>();
+        let tools_permissions = {
+            let gcx_locked = global_context.read().await;
+            ToolsPermissions::from_comma_separated(&gcx_locked.cmdline.tools_allow, &gcx_locked.cmdline.tools_deny)
+        };
         AtCommandsContext {
             global_context: global_context.clone(),
             n_ctx,
@@ -67,6 +72,7 @@ impl AtCommandsContext {
             at_commands: at_commands_dict(global_context.clone()).await,
             subchat_tool_parameters: IndexMap::new(),
             postprocess_parameters: PostprocessSettings::new(),
+            tools_permissions,
 
             subchat_tx: Arc::new(AMutex::new(tx)),
             subchat_rx: Arc::new(AMutex::new(rx)),
@@ -1,14 +1,16 @@
 use crate::at_commands::at_commands::{vec_context_file_to_context_tools, AtCommand, AtCommandsContext, AtParam};
 use async_trait::async_trait;
 use std::sync::Arc;
-use tokio::sync::Mutex as AMutex;
+use tokio::sync::{Mutex as AMutex, RwLock as ARwLock};
 use tracing::info;
 use crate::nicer_logs::last_n_chars;
 
 use crate::at_commands::execute_at::AtCommandMember;
 use crate::call_validation::{ContextEnum, ContextFile};
 use crate::caps::get_custom_embedding_api_key;
+use crate::global_context::GlobalContext;
 use crate::vecdb;
+use crate::vecdb::vdb_lance::workspace_scope_filter;
 use crate::vecdb::vdb_structs::VecdbSearch;
 
 
@@ -59,6 +61,14 @@ fn results2message(results: &Vec<vecdb::vdb_structs::VecdbRecord>) -> Vec<Contex
     vector_of_context_file
 }
 
+pub async fn active_workspace_folder_filter(gcx: Arc<ARwLock<GlobalContext>>) -> Option<String> {
+    let (active_file, workspace_folders) = {
+        let gcx_locked = gcx.read().await;
+        (gcx_locked.documents_state.active_file_path.clone(), gcx_locked.documents_state.workspace_folders.lock().unwrap().clone())
+    };
+    workspace_scope_filter(&active_file, &workspace_folders)
+}
+
 pub async fn execute_at_search(
     ccx: Arc<AMutex<AtCommandsContext>>,
     query: &String,
@@ -112,7 +122,9 @@ impl AtCommand for AtSearch {
             return Err("Cannot execute search: query is empty.".to_string());
         }
 
-        let vector_of_context_file = execute_at_search(ccx.clone(), &query, None).await?;
+        let gcx = ccx.lock().await.global_context.clone();
+        let filter = active_workspace_folder_filter(gcx).await;
+        let vector_of_context_file = execute_at_search(ccx.clone(), &query, filter).await?;
         let text = text_on_clip(&query, false);
         Ok((vec_context_file_to_context_tools(vector_of_context_file), text))
     }
@@ -178,7 +178,7 @@ fn find_content(html: String) -> String {
     html
 }
 
-async fn fetch_html(url: &str, timeout: Duration) -> Result<String, String> {
+pub async fn fetch_html(url: &str, timeout: Duration) -> Result<String, String> {
     let client = Client::builder()
         .timeout(timeout)
         .build()
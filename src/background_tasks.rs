@@ -45,6 +45,9 @@ pub async fn start_background_tasks(gcx: Arc<ARwLock<GlobalContext>>) -> Backgro
         #[cfg(feature="vecdb")]
         tokio::spawn(crate::vecdb::vdb_highlev::vecdb_background_reload(gcx.clone())),   // this in turn can create global_context::vec_db
         tokio::spawn(crate::integrations::sessions::remove_expired_sessions_background_task(gcx.clone())),
+        tokio::spawn(crate::idle_shutdown::idle_shutdown_background_task(gcx.clone())),
+        #[cfg(feature="vecdb")]
+        tokio::spawn(crate::knowledge::memdb_compaction_background_task(gcx.clone())),
     ]);
     let ast = gcx.clone().read().await.ast_service.clone();
     if let Some(ast_service) = ast {
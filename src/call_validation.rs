@@ -33,6 +33,14 @@ pub struct SamplingParameters {
     #[serde(default)]
     pub stop: Vec<String>,
     pub n: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<serde_json::Value>,  // "low" / "medium" / "high", or a thinking budget in tokens
+    #[serde(skip)]
+    pub reasoning_effort_style: String,  // from caps, see ModelRecord::reasoning_effort_style
+    #[serde(skip)]
+    pub image_style: String,  // from caps, see ModelRecord::image_style
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,  // dropped by the handlers if caps don't say supports_seed for the model
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -55,6 +63,8 @@ pub struct CodeCompletionPost {
     pub use_vecdb: bool,
     #[serde(default)]
     pub rag_tokens_n: usize,
+    #[serde(skip)]
+    pub warmup: bool,  // set by completion_warmup, nobody sees it: no metrics, telemetry, recent completions or prompt log
 }
 
 pub fn code_completion_post_validate(code_completion_post: CodeCompletionPost) -> axum::response::Result<(), ScratchError> {
@@ -155,6 +165,49 @@ fn is_empty_string(something: &String) -> bool {
     something.is_empty()
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ToolsPermissions {
+    #[serde(default)]
+    pub allow: Option<Vec<String>>,  // None means any tool that is not denied
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl ToolsPermissions {
+    pub fn from_comma_separated(allow: &str, deny: &str) -> Self {
+        let split = |s: &str| s.split(',').map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect::<Vec<_>>();
+        ToolsPermissions {
+            allow: if allow.trim().is_empty() { None } else { Some(split(allow)) },
+            deny: split(deny),
+        }
+    }
+
+    // a request can only take tools away, never add tools the server doesn't permit
+    pub fn narrowed_by(&self, other: &ToolsPermissions) -> ToolsPermissions {
+        let allow = match (&self.allow, &other.allow) {
+            (Some(a), Some(b)) => Some(a.iter().filter(|x| b.contains(x)).cloned().collect()),
+            (Some(a), None) => Some(a.clone()),
+            (None, Some(b)) => Some(b.clone()),
+            (None, None) => None,
+        };
+        let mut deny = self.deny.clone();
+        deny.extend(other.deny.iter().filter(|x| !self.deny.contains(x)).cloned());
+        ToolsPermissions { allow, deny }
+    }
+
+    pub fn check(&self, tool_name: &str) -> Result<(), String> {
+        if self.deny.iter().any(|x| x == tool_name) {
+            return Err(format!("tool use: {:?} is forbidden by the tools deny list", tool_name));
+        }
+        if let Some(allow) = &self.allow {
+            if !allow.iter().any(|x| x == tool_name) {
+                return Err(format!("tool use: {:?} is not in the tools allow list", tool_name));
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SubchatParameters {
     pub subchat_model: String,
@@ -187,11 +240,19 @@ pub struct ChatPost {
     #[serde(default)]
     pub tool_choice: Option<String>,
     #[serde(default)]
+    pub reasoning_effort: Option<serde_json::Value>,  // openai-style top level field, same as parameters.reasoning_effort
+    #[serde(default)]
+    pub seed: Option<u64>,  // openai-style top level field, same as parameters.seed
+    #[serde(default)]
     pub tools_confirmation: bool,
     #[serde(default)]
     pub only_deterministic_messages: bool,  // means don't sample from the model
     #[serde(default)]
+    pub self_critique: bool,  // the judge model from caps reviews the answer, one revision if it fails, not streamed token by token
+    #[serde(default)]
     pub subchat_tool_parameters: IndexMap<String, SubchatParameters>, // tool_name: {model, allowed_context, temperature}
+    #[serde(default)]
+    pub tools_permissions: ToolsPermissions,  // narrows --tools-allow / --tools-deny for this request
     #[serde(default="PostprocessSettings::new")]
     pub postprocess_parameters: PostprocessSettings,
     #[serde(default)]
@@ -261,6 +322,7 @@ pub struct PostprocessSettings {
     pub close_small_gaps: bool,
     pub take_floor: f32,                 // take/dont value
     pub max_files_n: usize,              // don't produce more than n files in output
+    pub max_tokens_per_file: usize,      // one big file can't take the whole budget, 0 means no cap
 }
 
 impl Default for PostprocessSettings {
@@ -280,6 +342,7 @@ impl PostprocessSettings {
             comments_propagate_up_coef: 0.99,
             take_floor: 0.0,
             max_files_n: 0,
+            max_tokens_per_file: 0,
         }
     }
 }
@@ -308,7 +371,8 @@ mod tests {
                 temperature: Some(0.1),
                 top_p: None,
                 stop: vec![],
-                n: None
+                n: None,
+                ..Default::default()
             },
             model: "".to_string(),
             scratchpad: "".to_string(),
@@ -317,6 +381,7 @@ mod tests {
             use_ast: true,
             use_vecdb: true,
             rag_tokens_n: 0,
+            warmup: false,
         };
         assert!(code_completion_post_validate(post).is_ok());
     }
@@ -339,6 +404,7 @@ mod tests {
                 top_p: None,
                 stop: vec![],
                 n: None,
+                ..Default::default()
             },
             model: "".to_string(),
             scratchpad: "".to_string(),
@@ -347,6 +413,7 @@ mod tests {
             use_ast: true,
             use_vecdb: true,
             rag_tokens_n: 0,
+            warmup: false,
         };
         assert!(code_completion_post_validate(post).is_ok());
     }
@@ -369,6 +436,7 @@ mod tests {
                 top_p: None,
                 stop: vec![],
                 n: None,
+                ..Default::default()
             },
             model: "".to_string(),
             scratchpad: "".to_string(),
@@ -377,6 +445,7 @@ mod tests {
             use_ast: true,
             use_vecdb: true,
             rag_tokens_n: 0,
+            warmup: false,
         };
         assert!(code_completion_post_validate(post).is_err());
     }
@@ -399,6 +468,7 @@ mod tests {
                 top_p: None,
                 stop: vec![],
                 n: None,
+                ..Default::default()
             },
             model: "".to_string(),
             scratchpad: "".to_string(),
@@ -407,7 +477,29 @@ mod tests {
             use_ast: true,
             use_vecdb: true,
             rag_tokens_n: 0,
+            warmup: false,
         };
         assert!(code_completion_post_validate(post).is_err());
     }
+
+    #[test]
+    fn test_forbidden_tool_rejected_regardless_of_request() {
+        let server = ToolsPermissions::from_comma_separated("", "shell, cmdline_run");
+        // the request tries to allow shell back, and also forbids web for itself
+        let request: ToolsPermissions = serde_json::from_value(serde_json::json!({
+            "allow": ["shell", "cat", "web"],
+            "deny": ["web"],
+        })).unwrap();
+        let effective = server.narrowed_by(&request);
+        assert!(effective.check("shell").unwrap_err().contains("forbidden"));
+        assert!(effective.check("web").is_err());
+        assert!(effective.check("tree").unwrap_err().contains("allow list"));
+        assert!(effective.check("cat").is_ok());
+
+        let server_allow = ToolsPermissions::from_comma_separated("cat,tree", "");
+        let effective = server_allow.narrowed_by(&ToolsPermissions { allow: Some(vec!["patch".to_string(), "cat".to_string()]), deny: vec![] });
+        assert!(effective.check("patch").is_err());
+        assert!(effective.check("cat").is_ok());
+        assert!(server_allow.narrowed_by(&ToolsPermissions::default()).check("tree").is_ok());
+    }
 }
@@ -18,8 +18,36 @@ use crate::known_models::KNOWN_MODELS;
 
 const CAPS_FILENAME: &str = "refact-caps";
 const CAPS_FILENAME_FALLBACK: &str = "coding_assistant_caps.json";
+const CAPS_LOCAL_OVERRIDE_FILENAME: &str = "caps-override.yaml";
 
 
+// Header values are often secrets (proxy auth, org ids), so Debug and Serialize show only the names
+#[derive(Deserialize, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct ExtraHeaders(pub HashMap<String, String>);
+
+impl ExtraHeaders {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn redacted(&self) -> std::collections::BTreeMap<&str, &str> {
+        self.0.keys().map(|k| (k.as_str(), "<redacted>")).collect()
+    }
+}
+
+impl std::fmt::Debug for ExtraHeaders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.redacted()).finish()
+    }
+}
+
+impl Serialize for ExtraHeaders {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.redacted().serialize(serializer)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ModelRecord {
     #[serde(default)]
@@ -32,12 +60,24 @@ pub struct ModelRecord {
     pub similar_models: Vec<String>,
     #[serde(default)]
     pub supports_tools: bool,
-    #[serde(default)]
+    #[serde(default, alias = "supports_vision")]
     pub supports_multimodality: bool,
     #[serde(default)]
     pub supports_clicks: bool,
     #[serde(default)]
     pub supports_agent: bool,
+    #[serde(default)]
+    pub supports_reasoning: bool,  // emits <think>...</think>, streamed separately as delta.reasoning
+    #[serde(default)]
+    pub supports_seed: bool,  // endpoint takes "seed", with temperature 0 the same request gives the same answer
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reasoning_effort_style: String,  // "openai" (reasoning_effort) or "anthropic" (thinking.budget_tokens), empty if the model doesn't take it
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub image_style: String,  // "openai" (image_url with a data URI, the default) or "anthropic" (image blocks with a base64 source)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub endpoint: String,  // overrides chat_endpoint / completion_endpoint for this model only
+    #[serde(default, skip_serializing_if = "ExtraHeaders::is_empty")]
+    pub extra_headers: ExtraHeaders,  // added to the caps-level extra_headers, the model wins on conflicts
 }
 
 #[derive(Debug, Deserialize)]
@@ -98,6 +138,8 @@ pub struct CodeAssistantCaps {
     pub chat_apikey: String,
     #[serde(default)]
     pub embedding_apikey: String,
+    #[serde(default, skip_serializing_if = "ExtraHeaders::is_empty")]
+    pub extra_headers: ExtraHeaders,  // sent to chat and completion endpoints, for gateways that want X-Org-Id or similar
 
     #[serde(default)]
     pub endpoint_chat_passthrough: String,
@@ -126,6 +168,9 @@ pub struct CodeAssistantCaps {
     #[serde(alias = "chat_model")]
     pub code_chat_default_model: String,
     #[serde(default)]
+    #[serde(alias = "judge_model")]
+    pub code_chat_judge_model: String,  // reviews answers of chat requests with self_critique, empty means self_critique is not available
+    #[serde(default)]
     pub models_dict_patch: HashMap<String, ModelRecord>,
     #[serde(default)]
     #[serde(alias = "default_embeddings_model")]
@@ -155,6 +200,11 @@ pub struct CodeAssistantCaps {
 
     #[serde(default = "default_support_metadata")]
     pub support_metadata: bool,
+
+    #[serde(default)]
+    pub disabled_scratchpads: Vec<String>,  // operators can forbid scratchpads without a new binary, for cost or quality reasons
+    #[serde(default)]
+    pub disabled_scratchpads_fallback: HashMap<String, String>,  // disabled scratchpad -> the one to use instead, no entry means an error
 }
 
 fn load_caps_from_buf(
@@ -192,6 +242,9 @@ fn load_caps_from_buf(
     if !r1.code_chat_default_model.is_empty() && !r1.running_models.contains(&r1.code_chat_default_model) {
         r1.running_models.push(r1.code_chat_default_model.clone());
     }
+    if !r1.code_chat_judge_model.is_empty() && !r1.running_models.contains(&r1.code_chat_judge_model) {
+        r1.running_models.push(r1.code_chat_judge_model.clone());
+    }
     if !r1.code_completion_default_model.is_empty() && !r1.running_models.contains(&r1.code_completion_default_model) {
         r1.running_models.push(r1.code_completion_default_model.clone());
     }
@@ -302,10 +355,21 @@ async fn load_caps_buf_from_file(
         caps_url = caps_path.to_string_lossy().into_owned();
         // info!("will use {} as the caps file", caps_url);
     }
+    read_caps_file(&caps_url)
+}
+
+fn read_caps_file(caps_url: &String) -> Result<(String, String), String> {
+    // a plain path or file:///path/to/caps.json, for setups without a server to fetch caps from
+    let caps_path = if caps_url.to_lowercase().starts_with("file://") {
+        let url = Url::parse(caps_url).map_err(|e| format!("failed to parse caps path '{}': {}", caps_url, e))?;
+        url.to_file_path().map_err(|_| format!("'{}' is not a local file path", caps_url))?
+    } else {
+        PathBuf::from(caps_url)
+    };
     let mut buffer = String::new();
-    let mut file = File::open(caps_url.clone()).map_err(|_| format!("failed to open file '{}'", caps_url))?;
-    file.read_to_string(&mut buffer).map_err(|_| format!("failed to read file '{}'", caps_url))?;
-    Ok((buffer, caps_url))
+    let mut file = File::open(&caps_path).map_err(|_| format!("failed to open file '{}'", caps_path.display()))?;
+    file.read_to_string(&mut buffer).map_err(|_| format!("failed to read file '{}'", caps_path.display()))?;
+    Ok((buffer, caps_path.to_string_lossy().into_owned()))
 }
 
 async fn load_caps_buf_from_url(
@@ -369,16 +433,51 @@ async fn load_caps_buf_from_url(
     Ok((buffer, caps_url))
 }
 
+pub fn merge_json(a: &mut Value, b: &Value) {
+    match (a, b) {
+        (Value::Object(a_map), Value::Object(b_map)) => {
+            for (k, v) in b_map {
+                merge_json(a_map.entry(k.clone()).or_insert(Value::Null), v);
+            }
+        }
+        (a, b) => *a = b.clone(),
+    }
+}
+
+fn caps_buf_to_json(buffer: &String) -> Result<Value, String> {
+    if buffer.trim_start().starts_with(&['{', '[']) {
+        serde_json::from_str(buffer).map_err(|e| format!("{}", e))
+    } else {
+        serde_yaml::from_str(buffer).map_err(|e| format!("{}", e))
+    }
+}
+
+fn apply_local_caps_override(buffer: &String, override_buffer: &String) -> Result<String, String> {
+    let mut caps_json = caps_buf_to_json(buffer).map_err(|e| format!("failed to parse caps: {}", e))?;
+    let override_json = caps_buf_to_json(override_buffer).map_err(|e| format!("failed to parse caps override: {}", e))?;
+    if override_json.is_null() {
+        return Ok(buffer.clone());
+    }
+    merge_json(&mut caps_json, &override_json);
+    serde_json::to_string(&caps_json).map_err(|e| format!("{}", e))
+}
+
 pub async fn load_caps(
     cmdline: crate::global_context::CommandLine,
     gcx: Arc<ARwLock<GlobalContext>>,
 ) -> Result<Arc<StdRwLock<CodeAssistantCaps>>, String> {
     let mut caps_url = cmdline.address_url.clone();
-    let buf: String;
-    if caps_url.to_lowercase() == "refact" || caps_url.starts_with("http") {
-        (buf, caps_url) = load_caps_buf_from_url(cmdline, gcx).await?
+    let mut buf: String;
+    let scheme_is_http = caps_url.to_lowercase().starts_with("http://") || caps_url.to_lowercase().starts_with("https://");
+    if caps_url.to_lowercase() == "refact" || scheme_is_http {
+        (buf, caps_url) = load_caps_buf_from_url(cmdline, gcx.clone()).await?
     } else {
-        (buf, caps_url) = load_caps_buf_from_file(cmdline, gcx).await?
+        (buf, caps_url) = load_caps_buf_from_file(cmdline, gcx.clone()).await?
+    }
+    let override_path = gcx.read().await.cache_dir.join(CAPS_LOCAL_OVERRIDE_FILENAME);
+    if let Ok(override_buf) = tokio::fs::read_to_string(&override_path).await {
+        info!("applying local caps override from {}", override_path.display());
+        buf = apply_local_caps_override(&buf, &override_buf)?;
     }
     load_caps_from_buf(&buf, &caps_url)
 }
@@ -413,6 +512,19 @@ fn apply_models_dict_patch(caps: &mut CodeAssistantCaps) {
         if rec_patched.supports_multimodality {
             rec.supports_multimodality = rec_patched.supports_multimodality;
         }
+        if rec_patched.supports_reasoning {
+            rec.supports_reasoning = rec_patched.supports_reasoning;
+        }
+        if !rec_patched.reasoning_effort_style.is_empty() {
+            rec.reasoning_effort_style = rec_patched.reasoning_effort_style.clone();
+        }
+        if !rec_patched.image_style.is_empty() {
+            rec.image_style = rec_patched.image_style.clone();
+        }
+        if rec_patched.supports_seed {
+            rec.supports_seed = rec_patched.supports_seed;
+        }
+        rec.extra_headers.0.extend(rec_patched.extra_headers.0.clone());
         if rec_patched.supports_tools {
             rec.supports_tools = rec_patched.supports_tools;
         }
@@ -438,13 +550,15 @@ fn _inherit_r1_from_r0(
 
         for (rec_name, rec) in r0.code_completion_models.iter() {
             if rec_name == &k_stripped || rec.similar_models.contains(&k_stripped) {
-                r1.code_completion_models.insert(k.to_string(), rec.clone());
+                let (endpoint, extra_headers) = r1.code_completion_models.get(k).map(|r| (r.endpoint.clone(), r.extra_headers.clone())).unwrap_or_default();
+                r1.code_completion_models.insert(k.to_string(), ModelRecord { endpoint, extra_headers, ..rec.clone() });
             }
         }
 
         for (rec_name, rec) in r0.code_chat_models.iter() {
             if rec_name == &k_stripped || rec.similar_models.contains(&k_stripped) {
-                r1.code_chat_models.insert(k.to_string(), rec.clone());
+                let (endpoint, extra_headers) = r1.code_chat_models.get(k).map(|r| (r.endpoint.clone(), r.extra_headers.clone())).unwrap_or_default();
+                r1.code_chat_models.insert(k.to_string(), ModelRecord { endpoint, extra_headers, ..rec.clone() });
             }
         }
     }
@@ -513,6 +627,50 @@ pub fn which_scratchpad_to_use<'a>(
     }
 }
 
+pub fn scratchpad_disabled_error(caps: &CodeAssistantCaps, scratchpad_name: &str) -> Option<String> {
+    if caps.disabled_scratchpads.iter().any(|x| x == scratchpad_name) {
+        Some(format!("Scratchpad '{}' is disabled in caps (disabled_scratchpads: {:?})", scratchpad_name, caps.disabled_scratchpads))
+    } else {
+        None
+    }
+}
+
+// which_scratchpad_to_use, then the fallback from caps if the operator disabled the choice
+pub fn which_allowed_scratchpad_to_use<'a>(
+    caps: &CodeAssistantCaps,
+    scratchpads: &'a HashMap<String, serde_json::Value>,
+    user_wants_scratchpad: &str,
+    default_scratchpad: &str,
+) -> Result<(String, &'a serde_json::Value), String> {
+    let (sname, patch) = which_scratchpad_to_use(scratchpads, user_wants_scratchpad, default_scratchpad)?;
+    let Some(disabled_error) = scratchpad_disabled_error(caps, &sname) else {
+        return Ok((sname, patch));
+    };
+    let fallback = caps.disabled_scratchpads_fallback.get(&sname).ok_or(disabled_error.clone())?;
+    if let Some(fallback_error) = scratchpad_disabled_error(caps, fallback) {
+        return Err(format!("{}, and its fallback: {}", disabled_error, fallback_error));
+    }
+    let fallback_patch = scratchpads.get(fallback).ok_or(format!(
+        "{}, fallback '{}' is not supported by the model, it supports {:?}", disabled_error, fallback, scratchpads.keys()
+    ))?;
+    info!("scratchpad '{}' is disabled in caps, using '{}' instead", sname, fallback);
+    Ok((fallback.clone(), fallback_patch))
+}
+
+pub fn seed_if_supported(
+    models: &HashMap<String, ModelRecord>,
+    model_name: &str,
+    seed: Option<u64>,
+) -> Option<u64> {
+    let seed = seed?;
+    let supports_seed = models.get(&strip_model_from_finetune(&model_name.to_string())).map(|rec| rec.supports_seed).unwrap_or(false);
+    if !supports_seed {
+        warn!("model {} doesn't take seed (no supports_seed in caps), ignoring seed={}", model_name, seed);
+        return None;
+    }
+    Some(seed)
+}
+
 pub async fn get_model_record(
     gcx: Arc<ARwLock<GlobalContext>>,
     model: &str,
@@ -559,3 +717,120 @@ running_models:   # all models mentioned in *_model are automatically running, b
 # telemetry_basic_dest: <your-telemetry-address>             # default: https://www.smallcloud.ai/v1/telemetry-basic
 # telemetry_basic_retrieve_my_own: <your-telemetry-address>  # default: https://www.smallcloud.ai/v1/telemetry-retrieve-my-own-stats
 "#;
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE_CAPS: &str = r#"{
+        "cloud_name": "test",
+        "endpoint_template": "https://inference.example.com/v1/completions",
+        "chat_endpoint": "https://inference.example.com/v1/chat/completions",
+        "code_chat_default_model": "gpt-4o",
+        "code_chat_models": {
+            "gpt-4o": {"n_ctx": 128000, "supports_tools": true},
+            "gpt-4o-mini": {"n_ctx": 128000, "supports_tools": true}
+        }
+    }"#;
+
+    #[test]
+    fn test_local_override_changes_one_model_endpoint() {
+        let override_yaml = r#"
+code_chat_models:
+  gpt-4o:
+    endpoint: "http://localhost:8080/v1/chat/completions"
+"#.to_string();
+        let merged = apply_local_caps_override(&BASE_CAPS.to_string(), &override_yaml).unwrap();
+        let caps_arc = load_caps_from_buf(&merged, &"https://inference.example.com/".to_string()).unwrap();
+        let caps = caps_arc.read().unwrap();
+
+        let overridden = caps.code_chat_models.get("gpt-4o").unwrap();
+        assert_eq!(overridden.endpoint, "http://localhost:8080/v1/chat/completions");
+
+        let untouched = caps.code_chat_models.get("gpt-4o-mini").unwrap();
+        assert_eq!(untouched.endpoint, "");
+        assert_eq!(untouched.n_ctx, 128000);
+        assert!(untouched.supports_tools);
+        assert_eq!(caps.chat_endpoint, "https://inference.example.com/v1/chat/completions");
+        assert_eq!(caps.code_chat_default_model, "gpt-4o");
+    }
+
+    #[test]
+    fn test_seed_only_for_models_that_support_it() {
+        let merged = BASE_CAPS.replace(r#""gpt-4o-mini": {"#, r#""local/llama": {"n_ctx": 8192, "supports_seed": true}, "gpt-4o-mini": {"#);
+        let caps_arc = load_caps_from_buf(&merged, &"https://inference.example.com/".to_string()).unwrap();
+        let caps = caps_arc.read().unwrap();
+        assert_eq!(seed_if_supported(&caps.code_chat_models, "local/llama", Some(7)), Some(7));
+        assert_eq!(seed_if_supported(&caps.code_chat_models, "gpt-4o", Some(7)), Some(7));  // from known models
+        assert_eq!(seed_if_supported(&caps.code_chat_models, "gpt-4o-mini", Some(7)), None);
+        assert_eq!(seed_if_supported(&caps.code_chat_models, "no-such-model", Some(7)), None);
+        assert_eq!(seed_if_supported(&caps.code_chat_models, "local/llama", None), None);
+    }
+
+    #[test]
+    fn test_disabled_scratchpad_errors_or_falls_back() {
+        let scratchpads: HashMap<String, Value> = HashMap::from([
+            ("REPLACE".to_string(), serde_json::json!({})),
+            ("FIM-PSM".to_string(), serde_json::json!({"fim_prefix": "<fim_prefix>"})),
+        ]);
+        let mut caps = CodeAssistantCaps::default();
+        assert_eq!(which_allowed_scratchpad_to_use(&caps, &scratchpads, "REPLACE", "FIM-PSM").unwrap().0, "REPLACE");
+
+        caps.disabled_scratchpads = vec!["REPLACE".to_string()];
+        let err = which_allowed_scratchpad_to_use(&caps, &scratchpads, "REPLACE", "FIM-PSM").unwrap_err();
+        assert!(err.contains("'REPLACE' is disabled"), "{}", err);
+        assert!(scratchpad_disabled_error(&caps, "REPLACE").is_some());
+        assert_eq!(which_allowed_scratchpad_to_use(&caps, &scratchpads, "", "FIM-PSM").unwrap().0, "FIM-PSM");
+
+        caps.disabled_scratchpads_fallback = HashMap::from([("REPLACE".to_string(), "FIM-PSM".to_string())]);
+        let (sname, patch) = which_allowed_scratchpad_to_use(&caps, &scratchpads, "REPLACE", "FIM-PSM").unwrap();
+        assert_eq!(sname, "FIM-PSM");
+        assert_eq!(patch["fim_prefix"], "<fim_prefix>");
+
+        // the fallback has to be supported by the model and not disabled itself
+        caps.disabled_scratchpads_fallback.insert("REPLACE".to_string(), "FIM-SPM".to_string());
+        assert!(which_allowed_scratchpad_to_use(&caps, &scratchpads, "REPLACE", "").unwrap_err().contains("not supported by the model"));
+        caps.disabled_scratchpads_fallback.insert("REPLACE".to_string(), "FIM-PSM".to_string());
+        caps.disabled_scratchpads.push("FIM-PSM".to_string());
+        assert!(which_allowed_scratchpad_to_use(&caps, &scratchpads, "REPLACE", "").unwrap_err().contains("fallback"));
+
+        // operators write it in caps json
+        let merged = BASE_CAPS.replace(r#""cloud_name": "test","#, r#""cloud_name": "test", "disabled_scratchpads": ["REPLACE"], "disabled_scratchpads_fallback": {"REPLACE": "FIM-PSM"},"#);
+        let caps_arc = load_caps_from_buf(&merged, &"https://inference.example.com/".to_string()).unwrap();
+        assert_eq!(caps_arc.read().unwrap().disabled_scratchpads_fallback.get("REPLACE").map(|x| x.as_str()), Some("FIM-PSM"));
+    }
+
+    #[test]
+    fn test_load_caps_from_local_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let caps_path = dir.path().join("caps.json");
+        std::fs::write(&caps_path, BASE_CAPS).unwrap();
+
+        let file_url = Url::from_file_path(&caps_path).unwrap().to_string();
+        assert!(file_url.starts_with("file://"));
+        for address in [caps_path.to_string_lossy().to_string(), file_url] {
+            let (buf, caps_url) = read_caps_file(&address).unwrap();
+            assert_eq!(PathBuf::from(&caps_url), caps_path);
+            let caps_arc = load_caps_from_buf(&buf, &caps_url).unwrap();
+            let caps = caps_arc.read().unwrap();
+            assert_eq!(caps.cloud_name, "test");
+            assert_eq!(caps.code_chat_default_model, "gpt-4o");
+        }
+
+        let err = read_caps_file(&dir.path().join("nope.json").to_string_lossy().to_string()).unwrap_err();
+        assert!(err.contains("failed to open file"), "{}", err);
+    }
+
+    #[test]
+    fn test_extra_headers_are_redacted() {
+        let headers: ExtraHeaders = serde_json::from_value(serde_json::json!({"X-Org-Id": "org-secret-42"})).unwrap();
+        assert_eq!(headers.0["X-Org-Id"], "org-secret-42");
+        let debug = format!("{:?}", headers);
+        let serialized = serde_json::to_string(&headers).unwrap();
+        for shown in [debug, serialized] {
+            assert!(shown.contains("X-Org-Id"), "{}", shown);
+            assert!(!shown.contains("org-secret-42"), "{}", shown);
+        }
+    }
+}
@@ -1,9 +1,13 @@
-use crate::call_validation::CodeCompletionPost;
+use crate::call_validation::{CodeCompletionPost, CursorPosition};
 use std::sync::Arc;
 use std::sync::RwLock as StdRwLock;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::time::Instant;
 
 use ropey::Rope;
+use serde::Serialize;
+use crate::privacy::{check_file_privacy, FilePrivacyLevel, PrivacySettings};
 // use tracing::info;
 
 const CACHE_ENTRIES: usize = 500;
@@ -19,6 +23,10 @@ pub struct CompletionSaveToCache {
     pub completion0_finish_reason: String,
     pub completion0_snippet_telemetry_id: Option<u64>,
     pub model: String,
+    pub cursor: CursorPosition,
+    pub multiline: bool,
+    pub started_at: Instant,
+    pub warmup: bool,
 }
 
 impl CompletionSaveToCache {
@@ -33,24 +41,75 @@ impl CompletionSaveToCache {
             completion0_finish_reason: String::new(),
             completion0_snippet_telemetry_id: None,
             model: post.model.clone(),
+            cursor: post.inputs.cursor.clone(),
+            multiline: post.inputs.multiline,
+            started_at: Instant::now(),
+            warmup: post.warmup,
         }
     }
 }
 
 
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentCompletion {
+    pub file: String,
+    pub line: i32,
+    pub character: i32,
+    pub multiline: bool,
+    pub model: String,
+    pub completion: String,
+    pub finish_reason: String,
+    pub cached: bool,
+    pub ms: u64,
+}
+
 #[derive(Debug)]
 pub struct CompletionCache {
     pub map: HashMap<(String, String), serde_json::Value>,
     pub in_added_order: Vec<(String, String)>,
+    pub recent: VecDeque<RecentCompletion>,
+    pub recent_max: usize,  // --debug-recent-completions, 0 keeps nothing
 }
 
 impl CompletionCache {
     pub fn new(
+        recent_max: usize,
     ) -> Self {
-        Self { map: HashMap::new(), in_added_order: Vec::new() }
+        Self { map: HashMap::new(), in_added_order: Vec::new(), recent: VecDeque::new(), recent_max }
+    }
+}
+
+pub fn recent_completion_put(
+    cache: Arc<StdRwLock<CompletionCache>>,
+    rec: RecentCompletion,
+) {
+    let mut cache_locked = cache.write().unwrap();
+    if cache_locked.recent_max == 0 {
+        return;
+    }
+    cache_locked.recent.push_back(rec);
+    while cache_locked.recent.len() > cache_locked.recent_max {
+        cache_locked.recent.pop_front();
     }
 }
 
+// Oldest first. Privacy could have changed since, the text of completions in blocked files is left out
+pub fn recent_completions_json(
+    cache: Arc<StdRwLock<CompletionCache>>,
+    privacy: Arc<PrivacySettings>,
+) -> serde_json::Value {
+    let recent: Vec<RecentCompletion> = cache.read().unwrap().recent.iter().cloned().collect();
+    serde_json::Value::Array(recent.into_iter().map(|rec| {
+        let blocked = check_file_privacy(privacy.clone(), Path::new(&rec.file), &FilePrivacyLevel::OnlySendToServersIControl).is_err();
+        let mut j = serde_json::json!(rec);
+        if blocked {
+            j["completion"] = serde_json::Value::Null;
+            j["omitted"] = serde_json::json!("privacy");
+        }
+        j
+    }).collect())
+}
+
 pub fn cache_get(
     cache: Arc<StdRwLock<CompletionCache>>,
     key: (String, String),
@@ -143,6 +202,19 @@ impl Drop for CompletionSaveToCache {
         if self.completion0_finish_reason.is_empty() { // error happened, no nothing happened (prompt only request)
             return;
         }
+        if !self.warmup {
+            recent_completion_put(self.cache_arc.clone(), RecentCompletion {
+                file: self.cursor.file.clone(),
+                line: self.cursor.line,
+                character: self.cursor.character,
+                multiline: self.multiline,
+                model: self.model.clone(),
+                completion: self.completion0_text.clone(),
+                finish_reason: self.completion0_finish_reason.clone(),
+                cached: false,
+                ms: self.started_at.elapsed().as_millis() as u64,
+            });
+        }
         let mut believe_chars = self.completion0_text.len();
         if self.completion0_finish_reason == "length" {
             // Model stopped because of max tokens, there is a continuation, so it's good for cache in the beginning, but don't believe it to the end.
@@ -173,3 +245,54 @@ impl Drop for CompletionSaveToCache {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::privacy::FilePrivacySettings;
+
+    fn complete(cache: Arc<StdRwLock<CompletionCache>>, file: &str, line: i32, text: &str) {
+        let post: CodeCompletionPost = serde_json::from_value(serde_json::json!({
+            "inputs": {
+                "sources": {file: "def f():\n    \n\n"},
+                "cursor": {"file": file, "line": line, "character": 4},
+                "multiline": false,
+            },
+            "model": "starcoder",
+        })).unwrap();
+        let mut data4cache = CompletionSaveToCache::new(cache, &post);
+        data4cache.completion0_text = text.to_string();
+        data4cache.completion0_finish_reason = "stop".to_string();
+    }
+
+    #[test]
+    fn test_recent_completions_in_order() {
+        let cache = Arc::new(StdRwLock::new(CompletionCache::new(2)));
+        complete(cache.clone(), "/project/a.py", 0, "pass");
+        complete(cache.clone(), "/project/a.py", 1, "return 1");
+        complete(cache.clone(), "/project/secrets/keys.py", 1, "KEY = 2");
+
+        let privacy = Arc::new(PrivacySettings {
+            privacy_rules: FilePrivacySettings {
+                only_send_to_servers_I_control: vec![],
+                blocked: vec!["*/secrets/*".to_string()],
+            },
+            web_allowed_domains: vec![],
+            loaded_ts: 0,
+        });
+        let recent = recent_completions_json(cache.clone(), privacy);
+        let recent = recent.as_array().unwrap();
+        // the ring keeps 2, the first completion is gone
+        assert_eq!(recent.len(), 2, "{:?}", recent);
+        assert_eq!(recent[0]["completion"], "return 1");
+        assert_eq!((recent[0]["line"].as_i64(), recent[0]["cached"].as_bool()), (Some(1), Some(false)));
+        assert_eq!(recent[0]["finish_reason"], "stop");
+        assert_eq!(recent[1]["file"], "/project/secrets/keys.py");
+        assert!(recent[1]["completion"].is_null());
+        assert_eq!(recent[1]["omitted"], "privacy");
+
+        let off = Arc::new(StdRwLock::new(CompletionCache::new(0)));
+        complete(off.clone(), "/project/a.py", 0, "pass");
+        assert!(off.read().unwrap().recent.is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex as AMutex;
+use tokio::sync::RwLock as ARwLock;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+use crate::call_validation::CodeCompletionPost;
+use crate::global_context::GlobalContext;
+
+
+// Opening a project restores a bunch of tabs at once, the delay lets a quick change or close cancel the warmup before it calls the model
+const WARMUP_DELAY: Duration = Duration::from_millis(500);
+const WARMUP_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+pub struct CompletionWarmup {
+    pub running: HashMap<PathBuf, JoinHandle<()>>,
+    pub started: VecDeque<Instant>,
+}
+
+impl CompletionWarmup {
+    // At most per_minute warmups in any 60 seconds, 0 is off
+    pub fn allow(&mut self, per_minute: usize, now: Instant) -> bool {
+        while let Some(t) = self.started.front() {
+            if now.duration_since(*t) < WARMUP_RATE_WINDOW {
+                break;
+            }
+            self.started.pop_front();
+        }
+        if per_minute == 0 || self.started.len() >= per_minute {
+            return false;
+        }
+        self.started.push_back(now);
+        true
+    }
+
+    pub fn cancel(&mut self, cpath: &PathBuf) {
+        if let Some(handle) = self.running.remove(cpath) {
+            if !handle.is_finished() {
+                info!("completion warmup for {} cancelled", crate::nicer_logs::last_n_chars(&cpath.display().to_string(), 30));
+            }
+            handle.abort();
+        }
+    }
+}
+
+// Without a cursor, the end of the file: new code is most often appended there
+pub fn likely_edit_point(text: &str) -> (usize, usize) {
+    let line = text.matches('\n').count();
+    let character = text.rsplit('\n').next().unwrap_or("").chars().count();
+    (line, character)
+}
+
+// The same post an IDE would send at this position, so the cache key matches the first real request
+pub fn warmup_post(cpath: &PathBuf, text: &str, cursor: Option<(usize, usize)>) -> CodeCompletionPost {
+    let (line, character) = cursor.unwrap_or_else(|| likely_edit_point(text));
+    let file = cpath.to_string_lossy().to_string();
+    let line_is_empty = text.split('\n').nth(line).map_or(true, |l| l.trim().is_empty());
+    let mut post: CodeCompletionPost = serde_json::from_value(serde_json::json!({
+        "inputs": {
+            "sources": {file.clone(): text},
+            "cursor": {"file": file, "line": line, "character": character},
+            "multiline": line_is_empty,
+        },
+        "stream": false,
+    })).unwrap();
+    post.warmup = true;
+    post
+}
+
+pub async fn warmup_run<F, Fut>(post: CodeCompletionPost, delay: Duration, complete: F)
+where
+    F: FnOnce(CodeCompletionPost) -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
+    tokio::time::sleep(delay).await;
+    let t0 = Instant::now();
+    let what = format!("{}:{}", crate::nicer_logs::last_n_chars(&post.inputs.cursor.file, 30), post.inputs.cursor.line);
+    match complete(post).await {
+        Ok(()) => info!("completion warmup {} done in {:.3}s", what, t0.elapsed().as_secs_f32()),
+        Err(e) => info!("completion warmup {} failed: {}", what, e),
+    }
+}
+
+pub async fn completion_warmup_on_open(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    cpath: &PathBuf,
+    text: &String,
+    cursor: Option<(usize, usize)>,
+) {
+    let (per_minute, warmup_arc) = {
+        let gcx_locked = gcx.read().await;
+        (gcx_locked.cmdline.completion_warmup_per_minute, gcx_locked.completion_warmup.clone())
+    };
+    if per_minute == 0 {
+        return;
+    }
+    let post = warmup_post(cpath, text, cursor);
+    let mut warmup = warmup_arc.lock().await;
+    warmup.cancel(cpath);
+    warmup.running.retain(|_, h| !h.is_finished());
+    if !warmup.allow(per_minute, Instant::now()) {
+        info!("completion warmup for {} skipped, more than {} per minute", crate::nicer_logs::last_n_chars(&cpath.display().to_string(), 30), per_minute);
+        return;
+    }
+    let gcx_clone = gcx.clone();
+    let handle = tokio::spawn(warmup_run(post, WARMUP_DELAY, |mut post| async move {
+        crate::http::routers::v1::code_completion::handle_v1_code_completion(gcx_clone, &mut post).await
+            .map(|_| ())
+            .map_err(|e| e.message)
+    }));
+    warmup.running.insert(cpath.clone(), handle);
+}
+
+pub async fn completion_warmup_cancel(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    cpath: &PathBuf,
+) {
+    let warmup_arc: Arc<AMutex<CompletionWarmup>> = {
+        let gcx_locked = gcx.read().await;
+        if gcx_locked.cmdline.completion_warmup_per_minute == 0 {
+            return;
+        }
+        gcx_locked.completion_warmup.clone()
+    };
+    warmup_arc.lock().await.cancel(cpath);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::RwLock as StdRwLock;
+    use crate::completion_cache::{cache_get, cache_key_from_post, CompletionCache, CompletionSaveToCache};
+
+    async fn fake_complete(cache: Arc<StdRwLock<CompletionCache>>, post: CodeCompletionPost) -> Result<(), String> {
+        // what the scratchpad does with a model answer
+        let mut data4cache = CompletionSaveToCache::new(cache, &post);
+        data4cache.completion0_text = "return a + b\n".to_string();
+        data4cache.completion0_finish_reason = "stop".to_string();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_warmup_on_open_fills_cache_at_cursor() {
+        let cache = Arc::new(StdRwLock::new(CompletionCache::new(0)));
+        let cpath = PathBuf::from("/project/math.py");
+        let text = "def add(a, b):\n    \n\nprint(add(1, 2))\n".to_string();
+
+        let post = warmup_post(&cpath, &text, Some((1, 4)));
+        warmup_run(post, Duration::from_millis(1), |post| fake_complete(cache.clone(), post)).await;
+
+        // the first real request from the IDE at the same cursor
+        let ide_post: CodeCompletionPost = serde_json::from_value(serde_json::json!({
+            "inputs": {
+                "sources": {"/project/math.py": text},
+                "cursor": {"file": "/project/math.py", "line": 1, "character": 4},
+                "multiline": true,
+            },
+        })).unwrap();
+        let cached = cache_get(cache.clone(), cache_key_from_post(&ide_post)).expect("no cache entry for the cursor");
+        assert_eq!(cached["choices"][0]["code_completion"], "return a + b\n");
+
+        // cancelled before the delay is over, the model is never called
+        let cache2 = Arc::new(StdRwLock::new(CompletionCache::new(0)));
+        let mut warmup = CompletionWarmup::default();
+        let post = warmup_post(&cpath, &text, None);
+        assert_eq!((post.inputs.cursor.line, post.inputs.cursor.character), (4, 0));
+        let cache2_clone = cache2.clone();
+        warmup.running.insert(cpath.clone(), tokio::spawn(warmup_run(post.clone(), Duration::from_millis(200), move |post| fake_complete(cache2_clone, post))));
+        warmup.cancel(&cpath);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(cache_get(cache2, cache_key_from_post(&post)).is_none());
+
+        let t0 = Instant::now();
+        assert!(warmup.allow(2, t0) && warmup.allow(2, t0));
+        assert!(!warmup.allow(2, t0 + Duration::from_secs(30)));
+        assert!(warmup.allow(2, t0 + Duration::from_secs(61)));
+        assert!(!CompletionWarmup::default().allow(0, t0));
+    }
+
+    #[tokio::test]
+    async fn test_warmup_on_open_goes_through_the_real_completion() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let model_calls = Arc::new(AtomicUsize::new(0));
+        let model_calls_clone = model_calls.clone();
+        let router = axum::Router::new().route("/v1/completions", axum::routing::post(move || async move {
+            model_calls_clone.fetch_add(1, Ordering::SeqCst);
+            serde_json::json!({"choices": [{"index": 0, "text": "return a + b", "finish_reason": "stop"}]}).to_string()
+        }));
+        let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0))).serve(router.into_make_service());
+        let endpoint = format!("http://{}/v1/completions", server.local_addr());
+        tokio::spawn(server);
+
+        let dir = tempfile::tempdir().unwrap();
+        let prompt_log = dir.path().join("prompts.jsonl");
+        let prompt_log_arg = prompt_log.to_string_lossy().to_string();
+        let gcx = crate::global_context::create_test_global_context_with_fim_model(dir.path(), &endpoint, &[
+            "--completion-warmup-per-minute", "5",
+            "--debug-recent-completions", "10",
+            "--prompt-log-path", &prompt_log_arg,
+        ]).await;
+
+        let text = "def add(a, b):\n    \n\nprint(add(1, 2))\n".to_string();
+        let cpath = dir.path().join("math.py");
+        std::fs::write(&cpath, &text).unwrap();
+        completion_warmup_on_open(gcx.clone(), &cpath, &text, Some((1, 4))).await;
+
+        let handle = gcx.read().await.completion_warmup.lock().await.running.remove(&cpath).expect("warmup is not running");
+        handle.await.unwrap();
+        assert_eq!(model_calls.load(Ordering::SeqCst), 1);
+
+        // nobody has seen this completion yet
+        let (cache, telemetry) = {
+            let gcx_locked = gcx.read().await;
+            (gcx_locked.completions_cache.clone(), gcx_locked.telemetry.clone())
+        };
+        assert!(cache.read().unwrap().recent.is_empty());
+        assert!(telemetry.read().unwrap().tele_net.is_empty());
+        assert!(telemetry.read().unwrap().tele_snippets.is_empty());
+        assert!(!prompt_log.exists());
+
+        // the first real request from the IDE at the same cursor is answered from the cache
+        let file = cpath.to_string_lossy().to_string();
+        let ide_post: CodeCompletionPost = serde_json::from_value(serde_json::json!({
+            "inputs": {
+                "sources": {file.clone(): text},
+                "cursor": {"file": file, "line": 1, "character": 4},
+                "multiline": true,
+            },
+        })).unwrap();
+        let cached = cache_get(cache, cache_key_from_post(&ide_post)).expect("no cache entry for the cursor");
+        assert_eq!(cached["choices"][0]["code_completion"], "return a + b");
+    }
+}
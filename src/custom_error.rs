@@ -1,25 +1,33 @@
 use std::error::Error;
 use tracing::error;
 use hyper::{Body, Response, StatusCode};
-use serde_json::json;
+use serde::Serialize;
+use serde_json::{json, Value};
 use std::fmt;
 use axum::Json;
 use axum::response::IntoResponse;
 
 
+// Goes to the client as "error_code" next to "detail", so editors can react without parsing the message
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ScratchErrorCode {
+    CapsUnavailable,
+    PrivacyBlocked,
+    RateLimited,
+    ModelNotFound,
+}
+
 #[derive(Debug, Clone)]
 pub struct ScratchError {
     pub status_code: StatusCode,
     pub message: String,
     pub telemetry_skip: bool,    // because already posted a better description directly
+    pub error_code: Option<ScratchErrorCode>,
 }
 
 impl IntoResponse for ScratchError {
     fn into_response(self) -> axum::response::Response {
-        let payload = json!({
-            "detail": self.message,
-        });
-        (self.status_code, Json(payload)).into_response()
+        (self.status_code, Json(self.to_json())).into_response()
     }
 }
 
@@ -38,6 +46,7 @@ impl ScratchError {
             status_code,
             message,
             telemetry_skip: false,
+            error_code: None,
         }
     }
 
@@ -46,11 +55,25 @@ impl ScratchError {
             status_code,
             message,
             telemetry_skip: true,
+            error_code: None,
+        }
+    }
+
+    pub fn with_code(mut self, error_code: ScratchErrorCode) -> Self {
+        self.error_code = Some(error_code);
+        self
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut payload = json!({"detail": self.message});
+        if let Some(error_code) = self.error_code {
+            payload["error_code"] = json!(error_code);
         }
+        payload
     }
 
     pub fn to_response(&self) -> Response<Body> {
-        let body = json!({"detail": self.message}).to_string();
+        let body = self.to_json().to_string();
         error!("client will see {}", body);
         let response = Response::builder()
             .status(self.status_code)
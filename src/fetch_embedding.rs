@@ -24,10 +24,37 @@ pub async fn get_embedding(
     }
 }
 
-const SLEEP_ON_BIG_BATCH: u64 = 9000;
-const SLEEP_ON_BATCH_ONE: u64 = 100;
+const BACKOFF_BIG_BATCH_MS: u64 = 1000;
+const BACKOFF_BATCH_ONE_MS: u64 = 100;
+const BACKOFF_MAX_MS: u64 = 16000;
 
 
+fn embedding_error_status(e: &str) -> Option<u16> {
+    // both styles put the http status right after these
+    for marker in ["bad status: ", "Failed to get a response: "] {
+        if let Some(pos) = e.find(marker) {
+            let digits: String = e[pos + marker.len()..].chars().take_while(|c| c.is_ascii_digit()).collect();
+            if let Ok(status) = digits.parse::<u16>() {
+                return Some(status);
+            }
+        }
+    }
+    None
+}
+
+// 4xx won't go away if we ask again, except for timeouts and rate limits
+pub fn embedding_error_is_permanent(e: &str) -> bool {
+    match embedding_error_status(e) {
+        Some(status) => (400..500).contains(&status) && status != 408 && status != 429,
+        None => false,
+    }
+}
+
+// The endpoint didn't like some input in the batch (too long, can't tokenize), other inputs in the same batch are fine
+fn embedding_error_is_about_input(e: &str) -> bool {
+    matches!(embedding_error_status(e), Some(400) | Some(413) | Some(422))
+}
+
 // HF often returns 500 errors for no reason
 pub async fn get_embedding_with_retry(
     client: Arc<AMutex<reqwest::Client>>,
@@ -51,20 +78,74 @@ pub async fn get_embedding_with_retry(
         ).await {
             Ok(embedding) => return Ok(embedding),
             Err(e) => {
-                if attempt_n >= max_retries {
+                if attempt_n >= max_retries || embedding_error_is_permanent(&e) {
                     return Err(e);
                 }
-                if text.len() > 1 {
+                let backoff_ms = if text.len() > 1 {
                     if e.contains("503") {
                         tracing::info!("normal sleep on 503");
                     } else {
                         tracing::warn!("will retry later, embedding model doesn't work: {}", e);
                     }
-                    tokio::time::sleep(tokio::time::Duration::from_millis(SLEEP_ON_BIG_BATCH)).await;
+                    BACKOFF_BIG_BATCH_MS
                 } else {
-                    tokio::time::sleep(tokio::time::Duration::from_millis(SLEEP_ON_BATCH_ONE)).await;
+                    BACKOFF_BATCH_ONE_MS
+                };
+                let backoff_ms = (backoff_ms << (attempt_n - 1).min(16)).min(BACKOFF_MAX_MS);
+                tokio::time::sleep(tokio::time::Duration::from_millis(backoff_ms)).await;
+            }
+        }
+    }
+}
+
+// Same as get_embedding_with_retry(), but one bad input doesn't take the whole batch down: a batch rejected
+// because of its input is split in halves until the bad items are found, the rest get their vectors.
+pub async fn get_embedding_per_item(
+    client: Arc<AMutex<reqwest::Client>>,
+    endpoint_embeddings_style: &String,
+    model_name: &String,
+    endpoint_template: &String,
+    text: Vec<String>,
+    api_key: &String,
+    max_retries: usize,
+) -> Vec<Result<Vec<f32>, String>> {
+    let mut results: Vec<Result<Vec<f32>, String>> = vec![Err("not embedded".to_string()); text.len()];
+    let mut ranges = vec![(0, text.len())];
+    while let Some((start, end)) = ranges.pop() {
+        if start >= end {
+            continue;
+        }
+        match get_embedding_with_retry(
+            client.clone(),
+            endpoint_embeddings_style,
+            model_name,
+            endpoint_template,
+            text[start..end].to_vec(),
+            api_key,
+            max_retries,
+        ).await {
+            Ok(vectors) if vectors.len() == end - start => {
+                for (i, vector) in vectors.into_iter().enumerate() {
+                    results[start + i] = Ok(vector);
+                }
+            }
+            Ok(vectors) => {
+                let e = format!("embedding model returned {} vectors for {} inputs", vectors.len(), end - start);
+                for r in results[start..end].iter_mut() {
+                    *r = Err(e.clone());
+                }
+            }
+            Err(e) if end - start > 1 && embedding_error_is_about_input(&e) => {
+                let middle = start + (end - start) / 2;
+                ranges.push((middle, end));
+                ranges.push((start, middle));
+            }
+            Err(e) => {
+                for r in results[start..end].iter_mut() {
+                    *r = Err(e.clone());
                 }
             }
         }
     }
+    results
 }
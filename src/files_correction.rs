@@ -190,6 +190,35 @@ async fn complete_path_with_project_dir(
     None
 }
 
+const FUZZY_PATH_CANDIDATES_MAX: usize = 10;
+
+fn path_components_normalized(p: &str) -> Vec<String> {
+    p.to_lowercase().split(|c| c == '/' || c == '\\').filter(|x| !x.is_empty() && *x != ".").map(|x| x.to_string()).collect()
+}
+
+pub fn levenshtein_path_candidates(
+    correction_candidate: &str,
+    paths: &Vec<String>,
+    threshold: f64,
+    top_n: usize,
+) -> Vec<(String, f64)> {
+    // compares with the tail of each path that has as many components as the candidate, separators don't matter
+    let wanted = path_components_normalized(correction_candidate);
+    if wanted.is_empty() {
+        return vec![];
+    }
+    let wanted_str = wanted.join("/");
+    let mut scored: Vec<(String, f64)> = paths.iter().filter_map(|p| {
+        let components = path_components_normalized(p);
+        let tail = components[components.len().saturating_sub(wanted.len())..].join("/");
+        let similarity = strsim::normalized_levenshtein(&wanted_str, &tail);
+        if similarity >= threshold { Some((p.clone(), similarity)) } else { None }
+    }).collect();
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.0.cmp(&b.0)));
+    scored.truncate(top_n.min(FUZZY_PATH_CANDIDATES_MAX));
+    scored
+}
+
 pub async fn correct_to_nearest_filename(
     gcx: Arc<ARwLock<GlobalContext>>,
     correction_candidate: &String,
@@ -212,7 +241,16 @@ pub async fn correct_to_nearest_filename(
 
     if fuzzy {
         info!("fuzzy search {:?}, cache_fuzzy_arc.len={}", correction_candidate, cache_fuzzy_arc.len());
-        return fuzzy_search(correction_candidate, cache_fuzzy_arc.iter().cloned(), top_n, &['/', '\\']);
+        let found = fuzzy_search(correction_candidate, cache_fuzzy_arc.iter().cloned(), top_n, &['/', '\\']);
+        if !found.is_empty() {
+            return found;
+        }
+        // typos and wrong separators break bigrams, try edit distance over the full paths
+        let threshold = gcx.read().await.cmdline.fuzzy_path_threshold;
+        let all_paths = paths_from_anywhere(gcx.clone()).await.into_iter().map(|x| x.to_string_lossy().to_string()).collect::<Vec<_>>();
+        let ranked = levenshtein_path_candidates(correction_candidate, &all_paths, threshold, top_n);
+        info!("levenshtein fallback {:?} threshold={} found {:?}", correction_candidate, threshold, ranked);
+        return ranked.into_iter().map(|(p, _)| p).collect();
     }
 
     return vec![];
@@ -428,6 +466,27 @@ mod tests {
         assert_eq!(cache_shortened_result_vec, expected_result, "The result should contain the expected paths, instead it found");
     }
 
+    #[test]
+    fn test_levenshtein_path_candidates() {
+        let paths = vec![
+            "/home/user/repo/src/tools/tool_cat.rs".to_string(),
+            "/home/user/repo/src/tools/tool_tree.rs".to_string(),
+            "/home/user/repo/src/files_correction.rs".to_string(),
+            "/home/user/repo/README.md".to_string(),
+        ];
+        let found = levenshtein_path_candidates("src\\tols\\tool_cat.rs", &paths, 0.75, 10);
+        assert_eq!(found[0].0, "/home/user/repo/src/tools/tool_cat.rs");
+        assert!(found[0].1 >= 0.9, "{:?}", found);
+        assert!(found.iter().all(|(_, sim)| *sim >= 0.75));
+
+        let found = levenshtein_path_candidates("files_corection.rs", &paths, 0.75, 10);
+        assert_eq!(found.iter().map(|x| x.0.as_str()).collect::<Vec<_>>(), vec!["/home/user/repo/src/files_correction.rs"]);
+
+        assert!(levenshtein_path_candidates("docker/compose.yaml", &paths, 0.75, 10).is_empty());
+        assert_eq!(levenshtein_path_candidates("tool_cat.rs", &paths, 0.0, 100).len(), 4);
+        assert_eq!(levenshtein_path_candidates("tool_cat.rs", &paths, 0.0, 2).len(), 2);
+    }
+
     #[test]
     fn test_shortify_paths_from_indexed() {
         let workspace_folders = vec![
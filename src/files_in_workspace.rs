@@ -1,6 +1,5 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::hash::Hash;
 use std::path::PathBuf;
 use std::sync::{Arc, Weak, Mutex as StdMutex};
 use std::time::Instant;
@@ -9,6 +8,7 @@ use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watche
 use notify::event::{CreateKind, DataChange, ModifyKind, RemoveKind};
 use ropey::Rope;
 use tokio::sync::{RwLock as ARwLock, Mutex as AMutex};
+use tower_lsp::lsp_types::{Position, TextDocumentContentChangeEvent};
 use walkdir::WalkDir;
 use which::which;
 use tracing::info;
@@ -16,15 +16,21 @@ use tracing::info;
 use crate::git::git_ls_files;
 use crate::global_context::GlobalContext;
 use crate::telemetry;
+use crate::ast::treesitter::parsers::get_language_id_by_extension_override;
 use crate::file_filter::{is_this_inside_blacklisted_dir, is_valid_file, BLACKLISTED_DIRS, SOURCE_FILE_EXTENSIONS};
 use crate::ast::ast_indexer_thread::ast_indexer_enqueue_files;
 use crate::privacy::{check_file_privacy, load_privacy_if_needed, PrivacySettings, FilePrivacyLevel};
 
 
-#[derive(Debug, Eq, Hash, PartialEq, Clone)]
+// Ranged edits that came ahead of their turn, more than that and the missing one is not coming
+const PENDING_CHANGES_MAX: usize = 16;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Document {
     pub doc_path: PathBuf,
     pub doc_text: Option<Rope>,
+    pub doc_version: Option<i32>,
+    pub pending_changes: BTreeMap<i32, Vec<TextDocumentContentChangeEvent>>,
 }
 
 pub async fn get_file_text_from_memory_or_disk(global_context: Arc<ARwLock<GlobalContext>>, file_path: &PathBuf) -> Result<String, String>
@@ -44,7 +50,7 @@ pub async fn get_file_text_from_memory_or_disk(global_context: Arc<ARwLock<Globa
 
 impl Document {
     pub fn new(doc_path: &PathBuf) -> Self {
-        Self { doc_path: doc_path.clone(),  doc_text: None }
+        Self { doc_path: doc_path.clone(),  doc_text: None, doc_version: None, pending_changes: BTreeMap::new() }
     }
 
     #[cfg(feature="vecdb")]
@@ -71,6 +77,62 @@ impl Document {
         self.doc_text = Some(Rope::from_str(text));
     }
 
+    pub fn apply_content_changes(&mut self, changes: &Vec<TextDocumentContentChangeEvent>) -> Result<(), String> {
+        if self.doc_text.is_none() && changes.first().map_or(false, |c| c.range.is_none()) {
+            self.doc_text = Some(Rope::new());
+        }
+        let rope = self.doc_text.as_mut().ok_or(format!("no text loaded in {}", self.doc_path.display()))?;
+        apply_content_changes_to_rope(rope, changes)
+    }
+
+    // A failed edit can leave the text half-changed, it doesn't match the editor anymore. Without text,
+    // readers go to the disk, and ranged edits keep failing until the text is loaded again.
+    pub fn apply_content_changes_or_mark_stale(&mut self, changes: &Vec<TextDocumentContentChangeEvent>) -> Result<(), String> {
+        let applied = self.apply_content_changes(changes);
+        if applied.is_err() {
+            self.doc_text = None;
+            self.pending_changes.clear();
+        }
+        applied
+    }
+
+    // tower-lsp handles notifications concurrently, so changes can come out of order. Each version goes up by one:
+    // an older or repeated version is dropped, a newer one waits until the versions before it are applied.
+    // Ok(false) means the text didn't change, Err means it doesn't match the editor anymore and was dropped.
+    pub fn apply_versioned_changes(&mut self, version: Option<i32>, changes: &Vec<TextDocumentContentChangeEvent>) -> Result<bool, String> {
+        let (Some(mut version), Some(current)) = (version, self.doc_version) else {
+            // nothing to order by, apply as it comes
+            self.apply_content_changes_or_mark_stale(changes)?;
+            self.doc_version = version;
+            return Ok(true);
+        };
+        if version <= current {
+            return Ok(false);
+        }
+        // the full text doesn't depend on the versions before it
+        let is_full_text = changes.iter().any(|c| c.range.is_none());
+        if version > current + 1 && !is_full_text {
+            self.pending_changes.insert(version, changes.clone());
+            if self.pending_changes.len() > PENDING_CHANGES_MAX {
+                self.doc_text = None;
+                self.pending_changes.clear();
+                return Err(format!("version {} never came, {} later ones are waiting for it", current + 1, PENDING_CHANGES_MAX + 1));
+            }
+            return Ok(false);
+        }
+        self.pending_changes.retain(|v, _| *v > version);
+        let mut changes = changes.clone();
+        loop {
+            self.apply_content_changes_or_mark_stale(&changes)?;
+            self.doc_version = Some(version);
+            match self.pending_changes.remove(&(version + 1)) {
+                Some(next) => { version += 1; changes = next; }
+                None => break,
+            }
+        }
+        Ok(true)
+    }
+
     #[cfg(feature="vecdb")]
     pub fn text_as_string(&self) -> Result<String, String> {
         if let Some(r) = &self.doc_text {
@@ -117,6 +179,42 @@ pub struct DocumentsState {
     pub fs_watcher: Arc<ARwLock<RecommendedWatcher>>,
 }
 
+// LSP counts characters in UTF-16 code units, and a character past the end of the line means the end of the line
+fn lsp_position_to_char_idx(rope: &Rope, pos: &Position) -> Result<usize, String> {
+    let line_n = pos.line as usize;
+    if line_n >= rope.len_lines() {
+        return Err(format!("line {} is out of the document, it has {} lines", line_n, rope.len_lines()));
+    }
+    let line = rope.line(line_n);
+    let mut line_len = line.len_chars();
+    while line_len > 0 && matches!(line.char(line_len - 1), '\n' | '\r') {
+        line_len -= 1;
+    }
+    let line_start = rope.line_to_char(line_n);
+    let line_start_utf16 = rope.char_to_utf16_cu(line_start);
+    let line_end_utf16 = rope.char_to_utf16_cu(line_start + line_len);
+    Ok(rope.utf16_cu_to_char((line_start_utf16 + pos.character as usize).min(line_end_utf16)))
+}
+
+// Changes go in order, each one's range is in the text left by the previous one. A change without a range is the whole text.
+pub fn apply_content_changes_to_rope(rope: &mut Rope, changes: &Vec<TextDocumentContentChangeEvent>) -> Result<(), String> {
+    for change in changes.iter() {
+        match &change.range {
+            None => *rope = Rope::from_str(&change.text),
+            Some(range) => {
+                let start = lsp_position_to_char_idx(rope, &range.start)?;
+                let end = lsp_position_to_char_idx(rope, &range.end)?;
+                if end < start {
+                    return Err(format!("range end {:?} is before its start {:?}", range.end, range.start));
+                }
+                rope.remove(start..end);
+                rope.insert(start, &change.text);
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn mem_overwrite_or_create_document(
     global_context: Arc<ARwLock<GlobalContext>>,
     document: Document
@@ -152,6 +250,18 @@ impl DocumentsState {
             fs_watcher: Arc::new(ARwLock::new(watcher)),
         }
     }
+
+    // A file outside the workspace is some library or a scratch buffer, RAG has nothing for it, the previous active file stays
+    pub fn set_active_file_if_in_workspace(&mut self, cpath: &PathBuf) -> bool {
+        let workspace_folders = self.workspace_folders.lock().unwrap().clone();
+        let in_workspace = workspace_folders.is_empty()
+            || workspace_folders.iter().any(|folder| cpath.starts_with(folder))
+            || self.memory_document_map.contains_key(cpath);
+        if in_workspace {
+            self.active_file_path = Some(cpath.clone());
+        }
+        in_workspace
+    }
 }
 
 pub async fn watcher_init(
@@ -182,14 +292,49 @@ pub async fn watcher_init(
     }
 }
 
+const BINARY_SNIFF_BYTES: usize = 8192;
+
+fn looks_binary(bytes: &[u8]) -> bool {
+    // NUL never appears in text, other control characters only rarely (form feed, escape for colors)
+    let head = &bytes[..bytes.len().min(BINARY_SNIFF_BYTES)];
+    if head.contains(&0) {
+        return true;
+    }
+    let control = head.iter().filter(|b| **b < 0x20 && !matches!(**b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b)).count();
+    control * 10 > head.len()
+}
+
+pub fn decode_file_bytes(bytes: Vec<u8>) -> Result<(String, Option<&'static str>), String> {
+    // Returns the text and, if it wasn't clean UTF-8, what we did about it
+    let bytes = match String::from_utf8(bytes) {
+        Ok(text) => return Ok((text, None)),
+        Err(e) => e.into_bytes(),
+    };
+    if looks_binary(&bytes) {
+        return Err("binary file".to_string());
+    }
+    let lossy = String::from_utf8_lossy(&bytes);
+    if lossy.chars().any(|c| !c.is_ascii() && c != char::REPLACEMENT_CHARACTER) {
+        // UTF-8 with a few broken bytes
+        return Ok((lossy.to_string(), Some("invalid UTF-8 bytes replaced")));
+    }
+    // No valid multibyte sequences at all: a legacy 8-bit encoding, latin-1 maps every byte to a char and is the most common one
+    Ok((bytes.iter().map(|b| *b as char).collect(), Some("not UTF-8, decoded as latin-1")))
+}
+
 async fn read_file_from_disk_without_privacy_check(
     path: &PathBuf,
 ) -> Result<Rope, String> {
-    tokio::fs::read_to_string(path).await
-        .map(|x|Rope::from_str(&x))
-        .map_err(|e|
-            format!("failed to read file {}: {}", crate::nicer_logs::last_n_chars(&path.display().to_string(), 30), e)
-        )
+    let bytes = tokio::fs::read(path).await.map_err(|e|
+        format!("failed to read file {}: {}", crate::nicer_logs::last_n_chars(&path.display().to_string(), 30), e)
+    )?;
+    let (text, note) = decode_file_bytes(bytes).map_err(|e|
+        format!("failed to read file {}: {}", crate::nicer_logs::last_n_chars(&path.display().to_string(), 30), e)
+    )?;
+    if let Some(note) = note {
+        tracing::warn!("{}: {}", crate::nicer_logs::last_n_chars(&path.display().to_string(), 30), note);
+    }
+    Ok(Rope::from_str(&text))
 }
 
 pub async fn read_file_from_disk(
@@ -418,6 +563,9 @@ pub async fn retrieve_files_in_workspace_folders(
 }
 
 pub fn is_path_to_enqueue_valid(path: &PathBuf) -> Result<(), String> {
+    if get_language_id_by_extension_override(path).is_some() {
+        return Ok(());
+    }
     let extension = path.extension().unwrap_or_default();
     if !SOURCE_FILE_EXTENSIONS.contains(&extension.to_str().unwrap_or_default()) {
         return Err(format!("Unsupported file extension {:?}", extension).into());
@@ -541,9 +689,11 @@ pub async fn on_did_open(
     cpath: &PathBuf,
     text: &String,
     _language_id: &String,
+    version: Option<i32>,
 ) {
     let mut doc = Document::new(cpath);
     doc.update_text(text);
+    doc.doc_version = version;
     info!("on_did_open {}", crate::nicer_logs::last_n_chars(&cpath.display().to_string(), 30));
     let (_doc_arc, dirty_arc, mark_dirty) = mem_overwrite_or_create_document(gcx.clone(), doc).await;
     if mark_dirty {
@@ -551,6 +701,20 @@ pub async fn on_did_open(
         *dirty_arc.lock().await = now;
     }
     gcx.write().await.documents_state.active_file_path = Some(cpath.clone());
+    crate::completion_warmup::completion_warmup_on_open(gcx.clone(), cpath, text, None).await;
+}
+
+pub async fn on_did_change_active_file(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    cpath: &PathBuf,
+) {
+    // editors with several panes don't always send open or change when the focus moves, they send this instead
+    let accepted = gcx.write().await.documents_state.set_active_file_if_in_workspace(cpath);
+    if accepted {
+        info!("on_did_change_active_file {}", crate::nicer_logs::last_n_chars(&cpath.display().to_string(), 30));
+    } else {
+        info!("on_did_change_active_file {} is not in the workspace, active file stays the same", crate::nicer_logs::last_n_chars(&cpath.display().to_string(), 30));
+    }
 }
 
 pub async fn on_did_close(
@@ -558,6 +722,7 @@ pub async fn on_did_close(
     cpath: &PathBuf,
 ) {
     info!("on_did_close {}", crate::nicer_logs::last_n_chars(&cpath.display().to_string(), 30));
+    crate::completion_warmup::completion_warmup_cancel(gcx.clone(), cpath).await;
     {
         let mut cx = gcx.write().await;
         if cx.documents_state.memory_document_map.remove(cpath).is_none() {
@@ -572,6 +737,7 @@ pub async fn on_did_change(
     text: &String,
 ) {
     let t0 = Instant::now();
+    crate::completion_warmup::completion_warmup_cancel(gcx.clone(), path).await;
     let (doc_arc, dirty_arc, mark_dirty) = {
         let mut doc = Document::new(path);
         doc.update_text(text);
@@ -584,6 +750,68 @@ pub async fn on_did_change(
         *dirty_arc.lock().await = now;
     }
 
+    after_document_changed(gcx.clone(), path, doc_arc, text).await;
+    info!("on_did_change {}, total time {:.3}s", crate::nicer_logs::last_n_chars(&path.to_string_lossy().to_string(), 30), t0.elapsed().as_secs_f32());
+}
+
+pub async fn on_did_change_incremental(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    path: &PathBuf,
+    version: Option<i32>,
+    changes: &Vec<TextDocumentContentChangeEvent>,
+) {
+    let t0 = Instant::now();
+    let doc_arc = gcx.read().await.documents_state.memory_document_map.get(path).cloned();
+    let Some(doc_arc) = doc_arc else {
+        // clients that only do full sync send the whole text without a range, the last one wins
+        if changes.iter().all(|c| c.range.is_none()) {
+            if let Some(last) = changes.last() {
+                on_did_change(gcx.clone(), path, &last.text).await;
+                if let Some(doc_arc) = gcx.read().await.documents_state.memory_document_map.get(path) {
+                    doc_arc.write().await.doc_version = version;
+                }
+            }
+        } else {
+            tracing::error!("on_did_change_incremental: {} was never opened, can't apply ranged edits to it", path.display());
+        }
+        return;
+    };
+    crate::completion_warmup::completion_warmup_cancel(gcx.clone(), path).await;
+    let applied = doc_arc.write().await.apply_versioned_changes(version, changes);
+    let text = match applied {
+        Ok(true) => doc_arc.read().await.doc_text.as_ref().map(|r| r.to_string()).unwrap_or_default(),
+        Ok(false) => {
+            info!("on_did_change_incremental {} version {:?} is already applied or waits for an earlier one", crate::nicer_logs::last_n_chars(&path.to_string_lossy().to_string(), 30), version);
+            return;
+        }
+        Err(e) => {
+            // an incremental client sends the full text only on open, so don't wait for it: the disk is
+            // the best guess there is, and it's exact again as soon as the file is saved
+            tracing::error!("on_did_change_incremental: {}: {}, reloading it from disk", path.display(), e);
+            let reloaded = match read_file_from_disk(load_privacy_if_needed(gcx.clone()).await, path).await {
+                Ok(rope) => rope,
+                Err(e) => {
+                    tracing::error!("on_did_change_incremental: can't reload {}: {}", path.display(), e);
+                    return;
+                }
+            };
+            let mut doc = doc_arc.write().await;
+            doc.doc_text = Some(reloaded.clone());
+            doc.doc_version = version.max(doc.doc_version);
+            reloaded.to_string()
+        }
+    };
+
+    after_document_changed(gcx.clone(), path, doc_arc, &text).await;
+    info!("on_did_change_incremental {}, {} changes, total time {:.3}s", crate::nicer_logs::last_n_chars(&path.to_string_lossy().to_string(), 30), changes.len(), t0.elapsed().as_secs_f32());
+}
+
+async fn after_document_changed(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    path: &PathBuf,
+    doc_arc: Arc<ARwLock<Document>>,
+    text: &String,
+) {
     gcx.write().await.documents_state.active_file_path = Some(path.clone());
 
     let mut go_ahead = true;
@@ -605,8 +833,6 @@ pub async fn on_did_change(
         &path.to_string_lossy().to_string(),
         text,
     ).await;
-
-    info!("on_did_change {}, total time {:.3}s", crate::nicer_logs::last_n_chars(&path.to_string_lossy().to_string(), 30), t0.elapsed().as_secs_f32());
 }
 
 pub async fn on_did_delete(gcx: Arc<ARwLock<GlobalContext>>, path: &PathBuf)
@@ -726,3 +952,133 @@ pub async fn file_watcher_event(event: Event, gcx_weak: Weak<ARwLock<GlobalConte
         _ => {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_latin1_and_binary_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let latin1 = tmp.path().join("legacy.c");
+        // "/* café à la crème */" in latin-1
+        let mut bytes = b"/* caf\xe9 \xe0 la cr\xe8me */\nint main() { return 0; }\n".to_vec();
+        std::fs::write(&latin1, &bytes).unwrap();
+        let text = read_file_from_disk_without_privacy_check(&latin1).await.unwrap().to_string();
+        assert_eq!(text, "/* café à la crème */\nint main() { return 0; }\n");
+
+        // mostly UTF-8 with one broken byte: keep the good characters
+        bytes = "// naïve\n".as_bytes().to_vec();
+        bytes.extend_from_slice(b"// broken \xff here\n");
+        let (text, note) = decode_file_bytes(bytes).unwrap();
+        assert_eq!(text, "// naïve\n// broken \u{FFFD} here\n");
+        assert!(note.is_some());
+
+        assert_eq!(decode_file_bytes("plain".as_bytes().to_vec()).unwrap(), ("plain".to_string(), None));
+
+        let binary = tmp.path().join("blob.bin");
+        std::fs::write(&binary, b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\xff\xfe").unwrap();
+        assert!(read_file_from_disk_without_privacy_check(&binary).await.unwrap_err().contains("binary file"));
+    }
+
+    fn ranged_change(line1: u32, character1: u32, line2: u32, character2: u32, text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(tower_lsp::lsp_types::Range::new(Position::new(line1, character1), Position::new(line2, character2))),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_ranged_edits_applied_to_rope() {
+        let mut rope = Rope::from_str("def hello():\n    print(\"héllo 🌍 world\")\n");
+        apply_content_changes_to_rope(&mut rope, &vec![
+            // 🌍 is two UTF-16 code units, "world" starts at 20 not 19
+            ranged_change(1, 20, 1, 25, "there"),
+            ranged_change(0, 4, 0, 9, "greet"),
+            // insertion at the very end, then a line past the end of line 0 is clamped to its end
+            ranged_change(2, 0, 2, 0, "greet()\n"),
+            ranged_change(0, 100, 0, 100, "  # entry"),
+        ]).unwrap();
+        assert_eq!(rope.to_string(), "def greet():  # entry\n    print(\"héllo 🌍 there\")\ngreet()\n");
+
+        // deleting across lines
+        apply_content_changes_to_rope(&mut rope, &vec![ranged_change(0, 12, 1, 4, " ")]).unwrap();
+        assert_eq!(rope.to_string(), "def greet(): print(\"héllo 🌍 there\")\ngreet()\n");
+
+        // a change without a range replaces everything
+        apply_content_changes_to_rope(&mut rope, &vec![TextDocumentContentChangeEvent { range: None, range_length: None, text: "x = 1\n".to_string() }]).unwrap();
+        assert_eq!(rope.to_string(), "x = 1\n");
+        assert!(apply_content_changes_to_rope(&mut rope, &vec![ranged_change(5, 0, 5, 1, "")]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_ranged_edit_falls_back_to_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.py");
+        std::fs::write(&path, "x = 1\n").unwrap();
+        let mut doc = Document::new(&path);
+        doc.update_text(&"x = 2\n".to_string());
+
+        // the first change applies, the second is out of range: the half-edited text must not stay around
+        let err = doc.apply_content_changes_or_mark_stale(&vec![ranged_change(0, 4, 0, 5, "3"), ranged_change(7, 0, 7, 1, "")]);
+        assert!(err.is_err());
+        assert!(doc.doc_text.is_none());
+        assert_eq!(read_file_from_disk(Arc::new(PrivacySettings::default()), &doc.doc_path).await.unwrap().to_string(), "x = 1\n");
+        // more ranged edits can't be applied to nothing, the text has to be loaded again
+        assert!(doc.apply_content_changes_or_mark_stale(&vec![ranged_change(0, 0, 0, 1, "y")]).is_err());
+        doc.update_text(&"x = 4\n".to_string());
+        doc.apply_content_changes_or_mark_stale(&vec![ranged_change(0, 4, 0, 5, "5")]).unwrap();
+        assert_eq!(doc.doc_text.as_ref().unwrap().to_string(), "x = 5\n");
+    }
+
+    #[test]
+    fn test_versioned_changes_applied_in_order() {
+        let mut doc = Document::new(&PathBuf::from("/tmp/main.py"));
+        doc.update_text(&"abc\n".to_string());
+        doc.doc_version = Some(1);
+
+        // version 3 came first, it waits for 2
+        assert_eq!(doc.apply_versioned_changes(Some(3), &vec![ranged_change(0, 2, 0, 2, "Y")]), Ok(false));
+        assert_eq!(doc.doc_text.as_ref().unwrap().to_string(), "abc\n");
+        assert_eq!(doc.apply_versioned_changes(Some(2), &vec![ranged_change(0, 1, 0, 1, "X")]), Ok(true));
+        assert_eq!(doc.doc_text.as_ref().unwrap().to_string(), "aXYbc\n");
+        assert_eq!(doc.doc_version, Some(3));
+        // a repeated or old version changes nothing
+        assert_eq!(doc.apply_versioned_changes(Some(3), &vec![ranged_change(0, 0, 0, 1, "")]), Ok(false));
+        assert_eq!(doc.apply_versioned_changes(Some(2), &vec![ranged_change(0, 0, 0, 1, "")]), Ok(false));
+        assert_eq!(doc.doc_text.as_ref().unwrap().to_string(), "aXYbc\n");
+
+        // a full text doesn't need the versions before it
+        let full = TextDocumentContentChangeEvent { range: None, range_length: None, text: "z\n".to_string() };
+        assert_eq!(doc.apply_versioned_changes(Some(7), &vec![full]), Ok(true));
+        assert_eq!((doc.doc_text.as_ref().unwrap().to_string(), doc.doc_version), ("z\n".to_string(), Some(7)));
+
+        // version 8 never comes
+        for v in 9..(9 + PENDING_CHANGES_MAX as i32) {
+            assert_eq!(doc.apply_versioned_changes(Some(v), &vec![ranged_change(0, 0, 0, 0, "q")]), Ok(false));
+        }
+        assert!(doc.apply_versioned_changes(Some(100), &vec![ranged_change(0, 0, 0, 0, "q")]).is_err());
+        assert!(doc.doc_text.is_none() && doc.pending_changes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_out_of_order_edit_reloads_from_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.py");
+        std::fs::write(&path, "x = 1\n").unwrap();
+        let gcx = crate::global_context::create_test_global_context(dir.path(), &[]).await;
+        on_did_open(gcx.clone(), &path, &"x = 2\n".to_string(), &"python".to_string(), Some(1)).await;
+
+        on_did_change_incremental(gcx.clone(), &path, Some(2), &vec![ranged_change(0, 4, 0, 5, "3")]).await;
+        assert_eq!(get_file_text_from_memory_or_disk(gcx.clone(), &path).await.unwrap(), "x = 3\n");
+
+        // this edit doesn't fit the text, the editor won't send the full text again: the file comes from disk
+        // and stays open, later edits apply to it
+        on_did_change_incremental(gcx.clone(), &path, Some(3), &vec![ranged_change(5, 0, 5, 1, "")]).await;
+        let doc_arc = gcx.read().await.documents_state.memory_document_map.get(&path).cloned().unwrap();
+        assert_eq!(doc_arc.read().await.doc_text.as_ref().map(|r| r.to_string()), Some("x = 1\n".to_string()));
+        on_did_change_incremental(gcx.clone(), &path, Some(4), &vec![ranged_change(0, 0, 0, 1, "y")]).await;
+        assert_eq!(get_file_text_from_memory_or_disk(gcx.clone(), &path).await.unwrap(), "y = 1\n");
+    }
+}
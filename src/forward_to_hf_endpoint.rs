@@ -1,13 +1,11 @@
-use reqwest::header::AUTHORIZATION;
-use reqwest::header::CONTENT_TYPE;
-use reqwest::header::HeaderMap;
-use reqwest::header::HeaderValue;
+use std::collections::HashMap;
 use reqwest_eventsource::EventSource;
 use serde_json::json;
 #[cfg(feature="vecdb")]
 use tokio::sync::Mutex as AMutex;
 
 use crate::call_validation::{ChatMeta, SamplingParameters};
+use crate::forward_to_openai_endpoint::endpoint_headers;
 
 // Idea: use USER_AGENT
 // let user_agent = format!("{NAME}/{VERSION}; rust/unknown; ide/{ide:?}");
@@ -16,6 +14,7 @@ use crate::call_validation::{ChatMeta, SamplingParameters};
 pub async fn forward_to_hf_style_endpoint(
     save_url: &mut String,
     bearer: String,
+    extra_headers: &HashMap<String, String>,
     model_name: &str,
     prompt: &str,
     client: &reqwest::Client,
@@ -25,11 +24,7 @@ pub async fn forward_to_hf_style_endpoint(
 ) -> Result<serde_json::Value, String> {
     let url = endpoint_template.replace("$MODEL", model_name);
     save_url.clone_from(&&url);
-    let mut headers = HeaderMap::new();
-    headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-    if !bearer.is_empty() {
-        headers.insert(AUTHORIZATION, HeaderValue::from_str(format!("Bearer {}", bearer).as_str()).unwrap());
-    }
+    let headers = endpoint_headers(&bearer, extra_headers, false)?;
     let params_string = serde_json::to_string(sampling_parameters).unwrap();
     let mut params_json = serde_json::from_str::<serde_json::Value>(&params_string).unwrap();
     params_json["return_full_text"] = serde_json::Value::Bool(false);
@@ -65,6 +60,7 @@ pub async fn forward_to_hf_style_endpoint(
 pub async fn forward_to_hf_style_endpoint_streaming(
     save_url: &mut String,
     bearer: String,
+    extra_headers: &HashMap<String, String>,
     model_name: &str,
     prompt: &str,
     client: &reqwest::Client,
@@ -74,11 +70,7 @@ pub async fn forward_to_hf_style_endpoint_streaming(
 ) -> Result<EventSource, String> {
     let url = endpoint_template.replace("$MODEL", model_name);
     save_url.clone_from(&&url);
-    let mut headers = HeaderMap::new();
-    headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-    if !bearer.is_empty() {
-        headers.insert(AUTHORIZATION, HeaderValue::from_str(format!("Bearer {}", bearer).as_str()).unwrap());
-    }
+    let headers = endpoint_headers(&bearer, extra_headers, false)?;
     let params_string = serde_json::to_string(sampling_parameters).unwrap();
     let mut params_json = serde_json::from_str::<serde_json::Value>(&params_string).unwrap();
     params_json["return_full_text"] = serde_json::Value::Bool(false);
@@ -153,7 +145,7 @@ pub async fn get_embedding_hf_style(
                 if body.is_empty() {
                     Err(format!("Failed to get a response: {:?}", status))
                 } else {
-                    Err(format!("Failed to get a response: {:?}", body))
+                    Err(format!("Failed to get a response: {:?} {:?}", status, body))
                 }
             }
         }
@@ -1,20 +1,103 @@
+use std::collections::HashMap;
 use reqwest::header::AUTHORIZATION;
 use reqwest::header::CONTENT_TYPE;
 use reqwest::header::USER_AGENT;
 use reqwest::header::HeaderMap;
+use reqwest::header::HeaderName;
 use reqwest::header::HeaderValue;
 use reqwest_eventsource::EventSource;
 use serde_json::json;
 #[cfg(feature="vecdb")]
 use tokio::sync::Mutex as AMutex;
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::call_validation::{ChatMeta, SamplingParameters};
 
 
+const ANTHROPIC_THINKING_BUDGET_MIN: u64 = 1024;
+
+pub fn reasoning_effort_fields(
+    style: &str,
+    effort: &serde_json::Value,
+    max_new_tokens: usize,
+) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    let mut fields = serde_json::Map::new();
+    match style {
+        "openai" => {
+            let level = effort.as_str().unwrap_or_default();
+            if !["low", "medium", "high"].contains(&level) {
+                return Err(format!("reasoning_effort for this model should be \"low\", \"medium\" or \"high\", got {}", effort));
+            }
+            fields.insert("reasoning_effort".to_string(), json!(level));
+        }
+        "anthropic" => {
+            let budget = match effort {
+                serde_json::Value::String(s) if s == "low" => ANTHROPIC_THINKING_BUDGET_MIN,
+                serde_json::Value::String(s) if s == "medium" => 4096,
+                serde_json::Value::String(s) if s == "high" => 16384,
+                serde_json::Value::String(s) => s.parse::<u64>().map_err(|_| format!("reasoning_effort should be low, medium, high or a number of tokens, got {:?}", s))?,
+                serde_json::Value::Number(n) => n.as_u64().ok_or(format!("reasoning_effort should be a positive number of tokens, got {}", n))?,
+                _ => return Err(format!("reasoning_effort should be low, medium, high or a number of tokens, got {}", effort)),
+            };
+            if budget < ANTHROPIC_THINKING_BUDGET_MIN {
+                return Err(format!("thinking budget should be at least {} tokens, got {}", ANTHROPIC_THINKING_BUDGET_MIN, budget));
+            }
+            if budget >= max_new_tokens as u64 {
+                return Err(format!("thinking budget {} should be less than max_tokens {}", budget, max_new_tokens));
+            }
+            fields.insert("thinking".to_string(), json!({"type": "enabled", "budget_tokens": budget}));
+        }
+        _ => return Err(format!("model doesn't support reasoning_effort (reasoning_effort_style={:?} in caps)", style)),
+    }
+    Ok(fields)
+}
+
+fn apply_reasoning_effort(data: &mut serde_json::Value, sampling_parameters: &SamplingParameters) -> Result<(), String> {
+    if let Some(effort) = &sampling_parameters.reasoning_effort {
+        if sampling_parameters.reasoning_effort_style.is_empty() {
+            return Ok(());
+        }
+        let fields = reasoning_effort_fields(&sampling_parameters.reasoning_effort_style, effort, sampling_parameters.max_new_tokens)?;
+        // neither o-series nor extended thinking accept a custom temperature
+        data.as_object_mut().unwrap().remove("temperature");
+        data.as_object_mut().unwrap().extend(fields);
+    }
+    Ok(())
+}
+
+// extra_headers come from caps, they can't replace the api key, but they can carry auth if there's no api key
+pub fn endpoint_headers(bearer: &str, extra_headers: &HashMap<String, String>, user_agent: bool) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
+    if !bearer.is_empty() {
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(format!("Bearer {}", bearer).as_str()).unwrap());
+    }
+    if user_agent {
+        headers.insert(USER_AGENT, HeaderValue::from_str(format!("refact-lsp {}", crate::version::build_info::PKG_VERSION).as_str()).unwrap());
+    }
+    for (name, value) in extra_headers.iter() {
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| format!("extra_headers in caps: invalid header name {:?}", name))?;
+        if name == AUTHORIZATION && !bearer.is_empty() {
+            warn!("extra_headers in caps: {} ignored, the api key goes there", name);
+            continue;
+        }
+        // the value is not in the message, it might be a secret
+        let value = HeaderValue::from_str(value).map_err(|_| format!("extra_headers in caps: invalid value for {}", name))?;
+        headers.insert(name, value);
+    }
+    if let Some((name, request_id)) = crate::http::utils::request_id_header() {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&request_id)) {
+            headers.insert(name, value);
+        }
+    }
+    Ok(headers)
+}
+
 pub async fn forward_to_openai_style_endpoint(
     save_url: &mut String,
     bearer: String,
+    extra_headers: &HashMap<String, String>,
     model_name: &str,
     prompt: &str,
     client: &reqwest::Client,
@@ -26,14 +109,7 @@ pub async fn forward_to_openai_style_endpoint(
     let is_passthrough = prompt.starts_with("PASSTHROUGH ");
     let url = if !is_passthrough { endpoint_template.replace("$MODEL", model_name) } else { endpoint_chat_passthrough.clone() };
     save_url.clone_from(&&url);
-    let mut headers = HeaderMap::new();
-    headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-    if !bearer.is_empty() {
-        headers.insert(AUTHORIZATION, HeaderValue::from_str(format!("Bearer {}", bearer).as_str()).unwrap());
-    }
-    if meta.is_some() {
-        headers.insert(USER_AGENT, HeaderValue::from_str(format!("refact-lsp {}", crate::version::build_info::PKG_VERSION).as_str()).unwrap());
-    }
+    let headers = endpoint_headers(&bearer, extra_headers, meta.is_some())?;
     let mut data = json!({
         "model": model_name,
         "stream": false,
@@ -55,9 +131,13 @@ pub async fn forward_to_openai_style_endpoint(
             data["n"] = serde_json::Value::from(n);
         }
     }
+    if let Some(seed) = sampling_parameters.seed {
+        data["seed"] = serde_json::Value::from(seed);
+    }
     info!("NOT STREAMING TEMP {}", sampling_parameters.temperature.unwrap());
+    apply_reasoning_effort(&mut data, sampling_parameters)?;
     if is_passthrough {
-        passthrough_messages_to_json(&mut data, prompt, model_name);
+        passthrough_messages_to_json(&mut data, prompt, model_name, &sampling_parameters.image_style)?;
     } else {
         data["prompt"] = serde_json::Value::String(prompt.to_string());
         data["echo"] = serde_json::Value::Bool(false);
@@ -95,6 +175,7 @@ pub async fn forward_to_openai_style_endpoint(
 pub async fn forward_to_openai_style_endpoint_streaming(
     save_url: &mut String,
     bearer: String,
+    extra_headers: &HashMap<String, String>,
     model_name: &str,
     prompt: &str,
     client: &reqwest::Client,
@@ -106,14 +187,7 @@ pub async fn forward_to_openai_style_endpoint_streaming(
     let is_passthrough = prompt.starts_with("PASSTHROUGH ");
     let url = if !is_passthrough { endpoint_template.replace("$MODEL", model_name) } else { endpoint_chat_passthrough.clone() };
     save_url.clone_from(&&url);
-    let mut headers = HeaderMap::new();
-    headers.insert(CONTENT_TYPE, HeaderValue::from_str("application/json").unwrap());
-    if !bearer.is_empty() {
-        headers.insert(AUTHORIZATION, HeaderValue::from_str(format!("Bearer {}", bearer).as_str()).unwrap());
-    }
-    if meta.is_some() {
-        headers.insert(USER_AGENT, HeaderValue::from_str(format!("refact-lsp {}", crate::version::build_info::PKG_VERSION).as_str()).unwrap());
-    }
+    let headers = endpoint_headers(&bearer, extra_headers, meta.is_some())?;
 
     let mut data = json!({
         "model": model_name,
@@ -128,9 +202,13 @@ pub async fn forward_to_openai_style_endpoint_streaming(
     if let Some(n) = sampling_parameters.n{
         data["n"] = serde_json::Value::from(n);
     }
+    if let Some(seed) = sampling_parameters.seed {
+        data["seed"] = serde_json::Value::from(seed);
+    }
     info!("STREAMING TEMP {}", sampling_parameters.temperature.unwrap());
+    apply_reasoning_effort(&mut data, sampling_parameters)?;
     if is_passthrough {
-        passthrough_messages_to_json(&mut data, prompt, model_name);
+        passthrough_messages_to_json(&mut data, prompt, model_name, &sampling_parameters.image_style)?;
     } else {
         data["prompt"] = serde_json::Value::String(prompt.to_string());
     }
@@ -150,17 +228,53 @@ fn passthrough_messages_to_json(
     data: &mut serde_json::Value,
     prompt: &str,
     model_name: &str,
-) {
+    image_style: &str,
+) -> Result<(), String> {
     assert!(prompt.starts_with("PASSTHROUGH "));
     let messages_str = &prompt[12..];
     let big_json: serde_json::Value = serde_json::from_str(&messages_str).unwrap();
 
     data["messages"] = big_json["messages"].clone();
+    translate_images(&mut data["messages"], image_style)?;
     if let Some(tools) = big_json.get("tools") {
         if model_name != "o1-mini" {
             data["tools"] = tools.clone();
         }
     }
+    Ok(())
+}
+
+// (media_type, base64 data) from an openai image_url part or an anthropic image part
+fn image_part_b64(part: &serde_json::Value) -> Option<(String, String)> {
+    match part.get("type").and_then(|t| t.as_str()) {
+        Some("image_url") => {
+            let url = part["image_url"]["url"].as_str()?;
+            let (media_type, data) = url.strip_prefix("data:")?.split_once(";base64,")?;
+            Some((media_type.to_string(), data.to_string()))
+        }
+        Some("image") if part["source"]["type"] == "base64" => {
+            Some((part["source"]["media_type"].as_str()?.to_string(), part["source"]["data"].as_str()?.to_string()))
+        }
+        _ => None,
+    }
+}
+
+// Messages hold images the openai way, rewrites them into what the model's endpoint takes
+pub fn translate_images(messages: &mut serde_json::Value, image_style: &str) -> Result<(), String> {
+    let Some(messages) = messages.as_array_mut() else { return Ok(()) };
+    for message in messages.iter_mut() {
+        let Some(parts) = message.get_mut("content").and_then(|c| c.as_array_mut()) else { continue };
+        for part in parts.iter_mut() {
+            let Some((media_type, data)) = image_part_b64(part) else { continue };
+            *part = match image_style {
+                "" | "openai" if part["type"] == "image_url" => continue,  // keeps detail
+                "" | "openai" => json!({"type": "image_url", "image_url": {"url": format!("data:{};base64,{}", media_type, data)}}),
+                "anthropic" => json!({"type": "image", "source": {"type": "base64", "media_type": media_type, "data": data}}),
+                _ => return Err(format!("unknown image_style {:?} in caps, should be \"openai\" or \"anthropic\"", image_style)),
+            };
+        }
+    }
+    Ok(())
 }
 
 #[cfg(feature="vecdb")]
@@ -236,3 +350,181 @@ pub async fn get_embedding_openai_style(
     }
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params_with_effort(style: &str, effort: serde_json::Value, max_new_tokens: usize) -> SamplingParameters {
+        SamplingParameters {
+            max_new_tokens,
+            temperature: Some(0.2),
+            reasoning_effort: Some(effort),
+            reasoning_effort_style: style.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reasoning_effort_openai() {
+        let mut data = json!({"model": "o3-mini", "temperature": 0.2});
+        apply_reasoning_effort(&mut data, &params_with_effort("openai", json!("high"), 4096)).unwrap();
+        assert_eq!(data, json!({"model": "o3-mini", "reasoning_effort": "high"}));
+
+        assert!(reasoning_effort_fields("openai", &json!("extreme"), 4096).is_err());
+        assert!(reasoning_effort_fields("openai", &json!(2000), 4096).is_err());
+    }
+
+    #[test]
+    fn test_reasoning_effort_anthropic() {
+        let mut data = json!({"model": "claude-3-7-sonnet", "temperature": 0.2});
+        apply_reasoning_effort(&mut data, &params_with_effort("anthropic", json!(2000), 8192)).unwrap();
+        assert_eq!(data, json!({"model": "claude-3-7-sonnet", "thinking": {"type": "enabled", "budget_tokens": 2000}}));
+
+        let fields = reasoning_effort_fields("anthropic", &json!("medium"), 8192).unwrap();
+        assert_eq!(fields["thinking"]["budget_tokens"], 4096);
+        assert!(reasoning_effort_fields("anthropic", &json!(512), 8192).unwrap_err().contains("at least 1024"));
+        assert!(reasoning_effort_fields("anthropic", &json!("high"), 8192).unwrap_err().contains("less than max_tokens"));
+    }
+
+    #[test]
+    fn test_reasoning_effort_not_sent_to_unsupported_models() {
+        let mut data = json!({"model": "gpt-4o", "temperature": 0.2});
+        apply_reasoning_effort(&mut data, &params_with_effort("", json!("high"), 4096)).unwrap();
+        assert_eq!(data, json!({"model": "gpt-4o", "temperature": 0.2}));
+    }
+
+    // One completion on the mockito server, tests add matchers for what has to be sent before create()
+    fn completion_mock() -> mockito::Mock {
+        mockito::mock("POST", "/v1/completions")
+            .with_header("content-type", "application/json")
+            .with_body(json!({"choices": [{"index": 0, "text": "42", "finish_reason": "stop"}]}).to_string())
+    }
+
+    fn completion_url() -> String {
+        format!("{}/v1/completions", mockito::server_url())
+    }
+
+    const PIXEL_PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mP8z8DwHwAFBQIAX8jx0gAAAABJRU5ErkJggg==";
+
+    fn prompt_with_image() -> String {
+        format!("PASSTHROUGH {}", json!({"messages": [
+            {"role": "system", "content": "You are a helpful assistant"},
+            {"role": "user", "content": [
+                {"type": "text", "text": "What is on the screenshot?"},
+                {"type": "image_url", "image_url": {"url": format!("data:image/png;base64,{}", PIXEL_PNG), "detail": "high"}},
+            ]},
+        ]}))
+    }
+
+    #[test]
+    fn test_images_openai_style() {
+        let mut data = json!({"model": "gpt-4o"});
+        passthrough_messages_to_json(&mut data, &prompt_with_image(), "gpt-4o", "").unwrap();
+        assert_eq!(data["messages"][1]["content"][1], json!({"type": "image_url", "image_url": {"url": format!("data:image/png;base64,{}", PIXEL_PNG), "detail": "high"}}));
+        assert_eq!(data["messages"][1]["content"][0]["text"], "What is on the screenshot?");
+
+        // an anthropic-shaped image sent to an openai model is turned back into a data URI
+        let mut messages = json!([{"role": "user", "content": [{"type": "image", "source": {"type": "base64", "media_type": "image/jpeg", "data": "AAAA"}}]}]);
+        translate_images(&mut messages, "openai").unwrap();
+        assert_eq!(messages[0]["content"][0], json!({"type": "image_url", "image_url": {"url": "data:image/jpeg;base64,AAAA"}}));
+    }
+
+    #[test]
+    fn test_images_anthropic_style() {
+        let mut data = json!({"model": "claude-3-5-sonnet"});
+        passthrough_messages_to_json(&mut data, &prompt_with_image(), "claude-3-5-sonnet", "anthropic").unwrap();
+        assert_eq!(data["messages"][1]["content"][1], json!({"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": PIXEL_PNG}}));
+        assert_eq!(data["messages"][0]["content"], "You are a helpful assistant");
+
+        let mut messages = json!([{"role": "user", "content": [{"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}]}]);
+        assert!(translate_images(&mut messages, "gemini").unwrap_err().contains("unknown image_style"));
+    }
+
+    #[tokio::test]
+    async fn test_seed_is_forwarded() {
+        let upstream = completion_mock()
+            .match_body(mockito::Matcher::PartialJson(json!({"seed": 1234, "temperature": 0.0})))
+            .create();
+        let params = SamplingParameters {
+            max_new_tokens: 10,
+            temperature: Some(0.0),
+            seed: Some(1234),
+            ..Default::default()
+        };
+        let mut save_url = String::new();
+        forward_to_openai_style_endpoint(
+            &mut save_url,
+            "".to_string(),
+            &HashMap::new(),
+            "gpt-4o",
+            "def hello():",
+            &reqwest::Client::new(),
+            &completion_url(),
+            &"".to_string(),
+            &params,
+            None,
+        ).await.unwrap();
+        upstream.assert();
+    }
+
+    #[tokio::test]
+    async fn test_extra_headers_are_forwarded() {
+        // the api key stays, an extra Authorization only goes out when there's no api key
+        let upstream = completion_mock()
+            .match_header("x-org-id", "org-42")
+            .match_header("anthropic-beta", "prompt-caching-2024-07-31")
+            .match_header("authorization", "Bearer sk-test")
+            .create();
+        let extra_headers = HashMap::from([
+            ("X-Org-Id".to_string(), "org-42".to_string()),
+            ("anthropic-beta".to_string(), "prompt-caching-2024-07-31".to_string()),
+            ("Authorization".to_string(), "Basic c2VjcmV0".to_string()),
+        ]);
+        let params = SamplingParameters { max_new_tokens: 10, temperature: Some(0.0), ..Default::default() };
+        let mut save_url = String::new();
+        forward_to_openai_style_endpoint(
+            &mut save_url,
+            "sk-test".to_string(),
+            &extra_headers,
+            "gpt-4o",
+            "def hello():",
+            &reqwest::Client::new(),
+            &completion_url(),
+            &"".to_string(),
+            &params,
+            None,
+        ).await.unwrap();
+        upstream.assert();
+
+        let headers = endpoint_headers("", &extra_headers, false).unwrap();
+        assert_eq!(headers.get(AUTHORIZATION).unwrap(), "Basic c2VjcmV0");
+        assert!(endpoint_headers("", &HashMap::from([("bad header".to_string(), "x".to_string())]), false).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_request_id_echoed_and_forwarded() {
+        use axum::routing::post;
+        let upstream = completion_mock().match_header("x-request-id", "gw-7f3a").create();
+        let upstream_url = completion_url();
+        let router = axum::Router::new()
+            .route("/v1/code-completion", post(move || async move {
+                let params = SamplingParameters { max_new_tokens: 10, temperature: Some(0.0), ..Default::default() };
+                let mut save_url = String::new();
+                forward_to_openai_style_endpoint(&mut save_url, "".to_string(), &HashMap::new(), "gpt-4o", "def hello():",
+                    &reqwest::Client::new(), &upstream_url, &"".to_string(), &params, None).await.unwrap().to_string()
+            }))
+            .layer(axum::middleware::from_fn_with_state(HeaderName::from_static("x-request-id"), crate::http::utils::request_id_middleware));
+        let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0))).serve(router.into_make_service());
+        let url = format!("http://{}/v1/code-completion", server.local_addr());
+        tokio::spawn(server);
+
+        let response = reqwest::Client::new().post(&url).header("X-Request-Id", "gw-7f3a").send().await.unwrap();
+        assert_eq!(response.headers().get("x-request-id").unwrap(), "gw-7f3a");
+        upstream.assert();
+
+        // outside of a request nothing is added
+        assert!(endpoint_headers("", &HashMap::new(), false).unwrap().get("x-request-id").is_none());
+    }
+
+}
@@ -18,7 +18,7 @@ use tracing::{error, info};
 use crate::ast::ast_indexer_thread::AstIndexService;
 use crate::caps::CodeAssistantCaps;
 use crate::completion_cache::CompletionCache;
-use crate::custom_error::ScratchError;
+use crate::custom_error::{ScratchError, ScratchErrorCode};
 use crate::files_in_workspace::DocumentsState;
 use crate::integrations::docker::docker_ssh_tunnel_utils::SshTunnel;
 use crate::integrations::sessions::IntegrationSession;
@@ -63,6 +63,12 @@ pub struct CommandLine {
     pub ast_max_files: usize,
     #[structopt(long, default_value="", help="Give it a path for AST database to make it permanent, if there is the database already, process starts without parsing all the files (careful). This quick start is helpful for automated solution search.")]
     pub ast_permanent: String,
+    #[structopt(long, default_value="5000", help="Skip a file if parsing it for AST takes longer than that, in milliseconds. 0 means no limit.")]
+    pub ast_parse_timeout_ms: u64,
+    #[structopt(long, default_value="0", help="Threads parsing files for AST at the same time, 0 means half of the CPU cores.")]
+    pub ast_parse_workers: usize,
+    #[structopt(long, default_value="1024", help="Approximate memory for files being parsed and their symbols not yet written to the AST index, in megabytes. Parsing waits when it's used up. 0 means no limit.")]
+    pub ast_parse_memory_mb: usize,
 
     #[cfg(feature="vecdb")]
     #[structopt(long, help="Use vector database. Give it LSP workspace folders or a jsonl, it also needs an embedding model.")]
@@ -76,6 +82,15 @@ pub struct CommandLine {
     #[cfg(feature="vecdb")]
     #[structopt(long, default_value="", help="Set VecDB storage path manually.")]
     pub vecdb_force_path: String,
+    #[cfg(feature="vecdb")]
+    #[structopt(long, default_value="off", help="Strip comments before vectorization so license headers and commented-out code don't pollute search: off, all, or keep-docstrings.")]
+    pub vecdb_strip_comments: String,
+    #[cfg(feature="vecdb")]
+    #[structopt(long, default_value="", help="Comma-separated globs, only matching files get vectorized, for example \"*/src/*\". AST indexes all files anyway.")]
+    pub vecdb_include: String,
+    #[cfg(feature="vecdb")]
+    #[structopt(long, default_value="", help="Comma-separated globs of files to keep out of vector search, for example \"*/tests/*,*/vendor/*,*.md\". AST indexes them anyway.")]
+    pub vecdb_exclude: String,
 
     #[structopt(long, short="f", default_value="", help="A path to jsonl file with {\"path\": ...} on each line, files will immediately go to VecDB and AST.")]
     pub files_jsonl_path: String,
@@ -98,6 +113,54 @@ pub struct CommandLine {
 
     #[structopt(long, default_value="", help="Specify the variables.yaml, this also disables the global variables.yaml")]
     pub variables_yaml: String,
+
+    #[structopt(long, default_value="0", help="Exit after that many minutes without completion, chat or LSP requests, 0 means never. Requests in progress are not interrupted.")]
+    pub idle_shutdown_minutes: u64,
+
+    #[structopt(long, default_value="360", help="Run VACUUM and ANALYZE on the memories database that often, in minutes, only when there were no requests for a few minutes. 0 disables it.")]
+    pub memdb_compact_minutes: u64,
+
+    #[structopt(long, default_value="10", help="On shutdown, wait that many seconds for streaming completions and chats to finish, then cut them off.")]
+    pub shutdown_grace_secs: u64,
+
+    #[structopt(long, default_value="", help="Directory for logs, tokenizers, telemetry and other caches, instead of ~/.cache/refact. REFACT_CACHE_DIR env variable works too, the command line flag wins.")]
+    pub cache_dir: String,
+
+    #[structopt(long, default_value="", help="Treat extra file extensions as a known language for indexing and parsing, for example \"pyi2=python,tsx.txt=typescriptreact\"")]
+    pub language_extensions: String,
+
+    #[structopt(long, default_value="", help="Comma separated tool names the model is allowed to call, empty means all tools. Requests can narrow it down further.")]
+    pub tools_allow: String,
+
+    #[structopt(long, default_value="", help="Comma separated tool names the model is never allowed to call, for example \"shell,cmdline_run\"")]
+    pub tools_deny: String,
+
+    #[structopt(long, default_value="auto", help="Reject tool calls with file paths outside of workspace folders and --allowed-dirs: on, off, or auto that means on together with --inside-container.")]
+    pub confine_paths: String,
+    #[structopt(long, default_value="", help="Comma separated extra directories tools can read when paths are confined, in addition to workspace folders.")]
+    pub allowed_dirs: String,
+
+    #[structopt(long, default_value="0.75", help="How similar a wrong file path must be to a workspace file (0..1, edit distance) to be suggested as a correction when nothing else matches.")]
+    pub fuzzy_path_threshold: f64,
+
+    #[structopt(long, default_value="20", help="How many rounds of tool calls the model can make before it has to answer the user, counted since the last user message. 0 means no limit.")]
+    pub max_tool_rounds: usize,
+    #[structopt(long, default_value="You've used up all the tool calls for this turn. Don't call any more tools, write the final answer using what you've found so far, and say what's left undone.", help="A message the model gets when --max-tool-rounds is reached.")]
+    pub max_tool_rounds_message: String,
+
+    #[structopt(long, default_value="", help="Append prompt, response and context of every completion and chat to this local JSONL file, to build evaluation datasets. Off if empty, nothing is sent anywhere.")]
+    pub prompt_log_path: String,
+    #[structopt(long, default_value="100", help="Rotate the --prompt-log-path file when it grows over that many megabytes, one older file is kept as <path>.1")]
+    pub prompt_log_max_mb: u64,
+
+    #[structopt(long, default_value="0", help="Keep that many last completions in memory and show them in GET /v1/completions/recent, to debug completion quality. 0 disables the endpoint.")]
+    pub debug_recent_completions: usize,
+
+    #[structopt(long, default_value="0", help="Precompute a completion when a file is opened, so the first completion there comes from the cache. At most that many per minute, 0 is off.")]
+    pub completion_warmup_per_minute: usize,
+
+    #[structopt(long, default_value="X-Request-Id", help="Take the request id from this HTTP header, or generate one, then log it, return it in the response and send it to the model endpoints. Empty string turns it off.")]
+    pub request_id_header: String,
 }
 
 impl CommandLine {
@@ -151,6 +214,7 @@ pub struct GlobalContext {
     pub tokenizer_map: HashMap< String, Arc<StdRwLock<Tokenizer>>>,
     pub tokenizer_download_lock: Arc<AMutex<bool>>,
     pub completions_cache: Arc<StdRwLock<CompletionCache>>,
+    pub completion_warmup: Arc<AMutex<crate::completion_warmup::CompletionWarmup>>,
     pub telemetry: Arc<StdRwLock<telemetry_structs::Storage>>,
     #[cfg(feature="vecdb")]
     pub vec_db: Arc<AMutex<Option<crate::vecdb::vdb_highlev::VecDb>>>,
@@ -159,6 +223,8 @@ pub struct GlobalContext {
     pub vec_db_error: String,
     pub ast_service: Option<Arc<AMutex<AstIndexService>>>,
     pub ask_shutdown_sender: Arc<StdMutex<std::sync::mpsc::Sender<String>>>,
+    pub activity: Arc<crate::idle_shutdown::ActivityTracker>,
+    pub file_locks: Arc<crate::tools::tool_patch_aux::file_locks::FileLocks>,
     pub documents_state: DocumentsState,
     pub at_commands_preview_cache: Arc<AMutex<AtCommandsPreviewCache>>,
     pub privacy_settings: Arc<PrivacySettings>,
@@ -201,7 +267,7 @@ pub async fn try_load_caps_quickly_if_not_present(
     gcx: Arc<ARwLock<GlobalContext>>,
     max_age_seconds: u64,
 ) -> Result<Arc<StdRwLock<CodeAssistantCaps>>, ScratchError> {
-    let cmdline = CommandLine::from_args();  // XXX make it Arc and don't reload all the time
+    let cmdline = gcx.read().await.cmdline.clone();
 
     let caps_reading_lock: Arc<AMutex<bool>> = gcx.read().await.caps_reading_lock.clone();
     let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
@@ -229,7 +295,7 @@ pub async fn try_load_caps_quickly_if_not_present(
             }
             if caps_last_attempted_ts + CAPS_RELOAD_BACKOFF > now {
                 let gcx_locked = gcx.write().await;
-                return Err(ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, gcx_locked.caps_last_error.clone()));
+                return Err(ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, gcx_locked.caps_last_error.clone()).with_code(ScratchErrorCode::CapsUnavailable));
             }
         }
 
@@ -250,7 +316,7 @@ pub async fn try_load_caps_quickly_if_not_present(
                 Err(e) => {
                     error!("caps fetch failed: {:?}", e);
                     gcx_locked.caps_last_error = format!("caps fetch failed: {}", e);
-                    return Err(ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, gcx_locked.caps_last_error.clone()));
+                    return Err(ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, gcx_locked.caps_last_error.clone()).with_code(ScratchErrorCode::CapsUnavailable));
                 }
             }
         }
@@ -329,13 +395,60 @@ pub async fn block_until_signal(
     }
 }
 
+pub fn resolve_cache_dir(
+    cmdline_cache_dir: &str,
+    env_cache_dir: Option<String>,
+    default_cache_dir: PathBuf,
+) -> Result<PathBuf, String> {
+    let cache_dir = if !cmdline_cache_dir.is_empty() {
+        crate::files_correction::to_pathbuf_normalize(cmdline_cache_dir)
+    } else if let Some(env_cache_dir) = env_cache_dir.filter(|x| !x.is_empty()) {
+        crate::files_correction::to_pathbuf_normalize(&env_cache_dir)
+    } else {
+        default_cache_dir
+    };
+    std::fs::create_dir_all(&cache_dir).map_err(|e| {
+        format!("cannot create cache dir {}: {}, use --cache-dir or REFACT_CACHE_DIR to point to a writable location", cache_dir.display(), e)
+    })?;
+    Ok(cache_dir)
+}
+
 pub async fn create_global_context(
-    cache_dir: PathBuf,
+    default_cache_dir: PathBuf,
     config_dir: PathBuf,
 ) -> (Arc<ARwLock<GlobalContext>>, std::sync::mpsc::Receiver<String>, Arc<AtomicBool>, CommandLine) {
     let cmdline = CommandLine::from_args();
+    // before anything touches the cache dir, logs are not set up yet so stderr it is
+    let cache_dir = match resolve_cache_dir(&cmdline.cache_dir, std::env::var("REFACT_CACHE_DIR").ok(), default_cache_dir) {
+        Ok(cache_dir) => cache_dir,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    match crate::ast::treesitter::parsers::parse_extension_overrides(&cmdline.language_extensions) {
+        Ok(overrides) => crate::ast::treesitter::parsers::set_extension_overrides(overrides),
+        Err(e) => {
+            eprintln!("--language-extensions: {}", e);
+            std::process::exit(1);
+        }
+    }
+    crate::ast::treesitter::parsers::set_parse_timeout_ms(cmdline.ast_parse_timeout_ms);
+    crate::ast::ast_parse_pool::set_parse_limits(cmdline.ast_parse_workers, cmdline.ast_parse_memory_mb);
     let (ask_shutdown_sender, ask_shutdown_receiver) = std::sync::mpsc::channel::<String>();
     let shutdown_flag = Arc::new(AtomicBool::new(false));
+    let cx = global_context_from_cmdline(cmdline.clone(), cache_dir, config_dir, ask_shutdown_sender).await;
+    let gcx = Arc::new(ARwLock::new(cx));
+    crate::files_in_workspace::watcher_init(gcx.clone()).await;
+    (gcx, ask_shutdown_receiver, shutdown_flag, cmdline)
+}
+
+async fn global_context_from_cmdline(
+    cmdline: CommandLine,
+    cache_dir: PathBuf,
+    config_dir: PathBuf,
+    ask_shutdown_sender: std::sync::mpsc::Sender<String>,
+) -> GlobalContext {
     let mut http_client_builder = reqwest::Client::builder();
     if cmdline.insecure {
         http_client_builder = http_client_builder.danger_accept_invalid_certs(true)
@@ -347,7 +460,7 @@ pub async fn create_global_context(
         let path = crate::files_correction::canonical_path(&cmdline.workspace_folder);
         workspace_dirs = vec![path];
     }
-    let cx = GlobalContext {
+    GlobalContext {
         cmdline: cmdline.clone(),
         http_client,
         http_client_slowdown: Arc::new(Semaphore::new(2)),
@@ -359,7 +472,8 @@ pub async fn create_global_context(
         caps_last_attempted_ts: 0,
         tokenizer_map: HashMap::new(),
         tokenizer_download_lock: Arc::new(AMutex::<bool>::new(false)),
-        completions_cache: Arc::new(StdRwLock::new(CompletionCache::new())),
+        completions_cache: Arc::new(StdRwLock::new(CompletionCache::new(cmdline.debug_recent_completions))),
+        completion_warmup: Arc::new(AMutex::new(crate::completion_warmup::CompletionWarmup::default())),
         telemetry: Arc::new(StdRwLock::new(telemetry_structs::Storage::new())),
         #[cfg(feature="vecdb")]
         vec_db: Arc::new(AMutex::new(None)),
@@ -368,16 +482,54 @@ pub async fn create_global_context(
         vec_db_error: String::new(),
         ast_service: None,
         ask_shutdown_sender: Arc::new(StdMutex::new(ask_shutdown_sender)),
+        activity: Arc::new(crate::idle_shutdown::ActivityTracker::new(std::time::Instant::now())),
+        file_locks: Arc::new(crate::tools::tool_patch_aux::file_locks::FileLocks::default()),
         documents_state: DocumentsState::new(workspace_dirs).await,
         at_commands_preview_cache: Arc::new(AMutex::new(AtCommandsPreviewCache::new())),
         privacy_settings: Arc::new(PrivacySettings::default()),
         integration_sessions: HashMap::new(),
         codelens_cache: Arc::new(AMutex::new(crate::http::routers::v1::code_lens::CodeLensCache::default())),
         docker_ssh_tunnel: Arc::new(AMutex::new(None)),
-    };
-    let gcx = Arc::new(ARwLock::new(cx));
-    crate::files_in_workspace::watcher_init(gcx.clone()).await;
-    (gcx, ask_shutdown_receiver, shutdown_flag, cmdline)
+    }
+}
+
+// Same as the real one, but with default command line flags plus `args`, no file watcher and no shutdown listener.
+// Cache (unless --cache-dir says otherwise) and config live in `dir`, workspace_folder in args becomes the workspace.
+#[cfg(test)]
+pub async fn create_test_global_context(dir: &std::path::Path, args: &[&str]) -> Arc<ARwLock<GlobalContext>> {
+    let cmdline = CommandLine::from_iter(std::iter::once("refact-lsp").chain(args.iter().copied()));
+    let (ask_shutdown_sender, _) = std::sync::mpsc::channel::<String>();
+    let cache_dir = resolve_cache_dir(&cmdline.cache_dir, None, dir.join("cache")).unwrap();
+    let config_dir = dir.join("config");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    Arc::new(ARwLock::new(global_context_from_cmdline(cmdline, cache_dir, config_dir, ask_shutdown_sender).await))
+}
+
+// A test context with caps in `dir/caps.json` and one FIM-PSM completion model "test/fim" served at `endpoint`,
+// its tokenizer is the dummy one with the FIM tokens added, so nothing is downloaded.
+#[cfg(test)]
+pub async fn create_test_global_context_with_fim_model(dir: &std::path::Path, endpoint: &str, args: &[&str]) -> Arc<ARwLock<GlobalContext>> {
+    use std::str::FromStr;
+    use tokenizers::AddedToken;
+    const DUMMY_TOKENIZER: &str = include_str!("ast/dummy_tokenizer.json");
+
+    let caps_path = dir.join("caps.json");
+    std::fs::write(&caps_path, serde_json::json!({
+        "cloud_name": "test",
+        "endpoint_template": endpoint,
+        "code_completion_default_model": "test/fim",
+        "code_completion_models": {
+            "test/fim": {"n_ctx": 2048, "supports_scratchpads": {"FIM-PSM": {}}, "default_scratchpad": "FIM-PSM"},
+        },
+    }).to_string()).unwrap();
+    let caps_path_str = caps_path.to_string_lossy().to_string();
+    let args: Vec<&str> = ["--address-url", caps_path_str.as_str()].into_iter().chain(args.iter().copied()).collect();
+    let gcx = create_test_global_context(dir, &args).await;
+
+    let mut tokenizer = Tokenizer::from_str(DUMMY_TOKENIZER).unwrap();
+    tokenizer.add_special_tokens(&["<fim_prefix>", "<fim_suffix>", "<fim_middle>", "<|endoftext|>"].map(|t| AddedToken::from(t, true)));
+    gcx.write().await.tokenizer_map.insert("test/fim".to_string(), Arc::new(StdRwLock::new(tokenizer)));
+    gcx
 }
 
 pub async fn is_metadata_supported(gcx: Arc<ARwLock<GlobalContext>>) -> bool {
@@ -389,3 +541,70 @@ pub async fn is_metadata_supported(gcx: Arc<ARwLock<GlobalContext>>) -> bool {
     }
     false
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_cache_dir_override() {
+        let tmp = tempfile::tempdir().unwrap();
+        let default_dir = tmp.path().join("default");
+        let env_dir = tmp.path().join("from_env");
+        let flag_dir = tmp.path().join("from_flag");
+
+        let dir = resolve_cache_dir("", None, default_dir.clone()).unwrap();
+        assert_eq!(dir, default_dir);
+
+        let dir = resolve_cache_dir("", Some(env_dir.to_string_lossy().to_string()), default_dir.clone()).unwrap();
+        assert_eq!(dir, crate::files_correction::to_pathbuf_normalize(&env_dir.to_string_lossy().to_string()));
+        assert!(dir.is_dir());
+
+        let dir = resolve_cache_dir(&flag_dir.to_string_lossy(), Some(env_dir.to_string_lossy().to_string()), default_dir.clone()).unwrap();
+        assert_eq!(dir, crate::files_correction::to_pathbuf_normalize(&flag_dir.to_string_lossy().to_string()));
+        assert!(dir.is_dir());
+    }
+
+    #[tokio::test]
+    async fn test_cache_dir_override_used_for_integration_configs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let custom_cache = tmp.path().join("ci").join("refact-cache");
+        let gcx = create_test_global_context(tmp.path(), &["--cache-dir", &custom_cache.to_string_lossy()]).await;
+        let (cache_dir, config_dir) = {
+            let gcx_locked = gcx.read().await;
+            (gcx_locked.cache_dir.clone(), gcx_locked.config_dir.clone())
+        };
+        assert_eq!(cache_dir, crate::files_correction::to_pathbuf_normalize(&custom_cache.to_string_lossy().to_string()));
+        assert!(cache_dir.is_dir());
+        assert!(!tmp.path().join("cache").exists());
+
+        // variables.yaml left in the cache by an old version is migrated from the overridden dir, and the integration sees it
+        std::fs::write(cache_dir.join("variables.yaml"), "GH_TOKEN_VAR: token_from_cache\n").unwrap();
+        std::fs::create_dir_all(config_dir.join("integrations.d")).unwrap();
+        std::fs::write(config_dir.join("integrations.d").join("github.yaml"), "GH_TOKEN: $GH_TOKEN_VAR\n").unwrap();
+        migrate_to_config_folder(&config_dir, &cache_dir).await.unwrap();
+        assert!(!cache_dir.join("variables.yaml").exists());
+        assert!(config_dir.join("variables.yaml").exists());
+
+        let mut error_log = vec![];
+        let vars = crate::integrations::setting_up_integrations::get_vars_for_replacements(gcx.clone(), &mut error_log).await;
+        let records = crate::integrations::setting_up_integrations::read_integrations_d(
+            &vec![], &config_dir, &"".to_string(), &vars, &["github"], &mut error_log,
+        );
+        assert!(error_log.is_empty());
+        let github: Vec<_> = records.iter().filter(|r| r.integr_config_exists).collect();
+        assert_eq!(github.len(), 1);
+        assert_eq!(github[0].config_unparsed["GH_TOKEN"], "token_from_cache");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_cache_dir_uncreatable() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file_in_the_way = tmp.path().join("not_a_dir");
+        std::fs::write(&file_in_the_way, "x").unwrap();
+        let err = resolve_cache_dir(&file_in_the_way.join("cache").to_string_lossy(), None, tmp.path().to_path_buf()).unwrap_err();
+        assert!(err.contains("cannot create cache dir"), "{}", err);
+    }
+}
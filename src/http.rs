@@ -1,12 +1,14 @@
+use std::future::Future;
 use std::io::Write;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
+use std::time::Duration;
 
-use axum::{Extension, http::{StatusCode, Uri}, response::IntoResponse};
+use axum::{Extension, http::{HeaderName, StatusCode, Uri}, response::IntoResponse};
 use hyper::Server;
-use tokio::sync::RwLock as ARwLock;
+use tokio::sync::{Notify, RwLock as ARwLock};
 use tokio::task::JoinHandle;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use reqwest::{Client, Response};
 use serde::Serialize;
 
@@ -14,22 +16,43 @@ use crate::global_context::GlobalContext;
 use crate::http::routers::make_refact_http_server;
 
 pub mod routers;
-mod utils;
+pub mod utils;
+pub mod websocket;
 
 async fn handler_404(path: Uri) -> impl IntoResponse {
     info!("404 {}", path);
     (StatusCode::NOT_FOUND, format!("no handler for {}", path))
 }
 
+// Once the shutdown signal arrives hyper stops accepting connections and waits for the running requests,
+// streaming ones included, for as long as they take. This puts a limit on that wait.
+pub async fn serve_until_drained<F>(
+    server: F,
+    shutdown_started: Arc<Notify>,
+    grace: Duration,
+) -> Result<(), String>
+where
+    F: Future<Output = hyper::Result<()>>,
+{
+    let deadline = async {
+        shutdown_started.notified().await;
+        info!("shutting down, waiting up to {}s for requests in progress", grace.as_secs());
+        tokio::time::sleep(grace).await;
+    };
+    tokio::select! {
+        resp = server => resp.map_err(|e| format!("HTTP server error: {}", e)),
+        _ = deadline => Err(format!("requests still running after {}s grace period, cutting them off", grace.as_secs())),
+    }
+}
 
 pub async fn start_server(
     global_context: Arc<ARwLock<GlobalContext>>,
     ask_shutdown_receiver: std::sync::mpsc::Receiver<String>,
     shutdown_flag: Arc<AtomicBool>
 ) -> Option<JoinHandle<()>> {
-    let (port, is_inside_container) = {
+    let (port, is_inside_container, grace, request_id_header) = {
         let gcx_locked= global_context.read().await;
-        (gcx_locked.cmdline.http_port, gcx_locked.cmdline.inside_container)
+        (gcx_locked.cmdline.http_port, gcx_locked.cmdline.inside_container, Duration::from_secs(gcx_locked.cmdline.shutdown_grace_secs), gcx_locked.cmdline.request_id_header.clone())
     };
     if port == 0 {
         return None
@@ -43,15 +66,26 @@ pub async fn start_server(
         match builder {
             Ok(builder) => {
                 info!("HTTP server listening on {}", addr);
-                let router = make_refact_http_server().layer(Extension(global_context.clone()));
+                let mut router = make_refact_http_server();
+                if !request_id_header.trim().is_empty() {
+                    match HeaderName::from_bytes(request_id_header.trim().as_bytes()) {
+                        Ok(header_name) => router = router.layer(axum::middleware::from_fn_with_state(header_name, utils::request_id_middleware)),
+                        Err(_) => warn!("--request-id-header {:?} is not a valid header name, request ids are off", request_id_header),
+                    }
+                }
+                let router = router.layer(Extension(global_context.clone()));
+                let shutdown_started = Arc::new(Notify::new());
+                let shutdown_started_clone = shutdown_started.clone();
                 let server = builder
                     .serve(router.into_make_service())
-                    .with_graceful_shutdown(crate::global_context::block_until_signal(ask_shutdown_receiver, shutdown_flag));
-                let resp = server.await.map_err(|e| format!("HTTP server error: {}", e));
-                if let Err(e) = resp {
-                    error!("server error: {}", e);
-                } else {
-                    info!("clean shutdown");
+                    .with_graceful_shutdown(async move {
+                        crate::global_context::block_until_signal(ask_shutdown_receiver, shutdown_flag).await;
+                        shutdown_started_clone.notify_one();
+                    });
+                // main() saves telemetry after this returns, so it's never starved by a stuck stream
+                match serve_until_drained(server, shutdown_started, grace).await {
+                    Ok(()) => info!("clean shutdown"),
+                    Err(e) => warn!("{}", e),
                 }
             }
             Err(e) => {
@@ -89,4 +123,48 @@ pub async fn http_post<T: Serialize>(
     body: &T,
 ) -> Result<(), String> {
     _make_http_post(url, body).await.map(|_| ())
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::routing::get;
+
+    async fn slow_server(handler_delay: Duration, grace: Duration) -> (String, tokio::sync::oneshot::Sender<()>, JoinHandle<Result<(), String>>) {
+        let router = Router::new().route("/slow", get(move || async move {
+            tokio::time::sleep(handler_delay).await;
+            "done"
+        }));
+        let server = Server::try_bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0))).unwrap()
+            .serve(router.into_make_service());
+        let url = format!("http://{}/slow", server.local_addr());
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown_started = Arc::new(Notify::new());
+        let shutdown_started_clone = shutdown_started.clone();
+        let server = server.with_graceful_shutdown(async move {
+            let _ = shutdown_rx.await;
+            shutdown_started_clone.notify_one();
+        });
+        (url, shutdown_tx, tokio::spawn(serve_until_drained(server, shutdown_started, grace)))
+    }
+
+    #[tokio::test]
+    async fn test_request_in_flight_finishes_within_grace() {
+        let (url, shutdown, server) = slow_server(Duration::from_millis(300), Duration::from_secs(5)).await;
+        let request = tokio::spawn(async move { reqwest::get(&url).await?.text().await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.send(()).unwrap();
+        assert_eq!(request.await.unwrap().unwrap(), "done");
+        assert_eq!(server.await.unwrap(), Ok(()));
+
+        // a request that outlives the grace period doesn't hold the shutdown
+        let (url, shutdown, server) = slow_server(Duration::from_secs(60), Duration::from_millis(200)).await;
+        let _request = tokio::spawn(async move { reqwest::get(&url).await?.text().await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.send(()).unwrap();
+        let drained = tokio::time::timeout(Duration::from_secs(5), server).await.expect("shutdown took longer than the grace period");
+        assert!(drained.unwrap().is_err());
+    }
+}
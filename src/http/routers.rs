@@ -2,9 +2,12 @@ use axum::Router;
 use axum::routing::get;
 
 use crate::http::handler_404;
+use crate::http::websocket::handle_ws;
 
 pub mod v1;
 pub mod info;
+pub mod metrics;
+pub mod openapi;
 
 
 pub fn make_refact_http_server() -> Router {
@@ -12,4 +15,7 @@ pub fn make_refact_http_server() -> Router {
         .fallback(handler_404)
         .nest("/v1", v1::make_v1_router())
         .route("/build_info", get(info::handle_info))
+        .route("/metrics", get(metrics::handle_metrics))
+        .route("/openapi.json", get(openapi::handle_openapi_json))
+        .route("/ws", get(handle_ws))
 }
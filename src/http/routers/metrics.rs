@@ -0,0 +1,20 @@
+use axum::Extension;
+use hyper::{Body, Response};
+
+use crate::custom_error::ScratchError;
+use crate::global_context::SharedGlobalContext;
+
+
+pub async fn handle_metrics(
+    Extension(gcx): Extension<SharedGlobalContext>,
+) -> axum::response::Result<Response<Body>, ScratchError> {
+    let ast_service = gcx.read().await.ast_service.clone();
+    let ast_queue_depth = match ast_service {
+        Some(ast_service) => Some(ast_service.lock().await.ast_todo.len()),
+        None => None,
+    };
+    Ok(Response::builder()
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(Body::from(crate::metrics::render_metrics(&crate::metrics::METRICS, ast_queue_depth)))
+        .unwrap())
+}
@@ -0,0 +1,256 @@
+use axum::http::Response;
+use hyper::Body;
+
+use crate::custom_error::ScratchError;
+
+
+// Maintained by hand, the test below checks every path here is still routed. Not every route is described,
+// only the ones third-party clients are expected to use.
+const OPENAPI_YAML: &str = r####"
+openapi: "3.0.3"
+info:
+  title: "refact-lsp HTTP API"
+  description: "Code completion, chat with tools, and the context around them. Errors come back as {\"detail\": \"...\"} with a 4xx or 5xx status."
+paths:
+  /v1/ping:
+    get:
+      summary: "Returns --ping-message, to check it's the right process"
+      responses:
+        "200": { description: "The ping message", content: { text/plain: { schema: { type: string } } } }
+  /v1/caps:
+    get:
+      summary: "Capabilities: models, their context sizes, default models, endpoints"
+      responses:
+        "200": { description: "Caps", content: { application/json: { schema: { type: object } } } }
+        "500": { $ref: "#/components/responses/Error" }
+  /v1/code-completion:
+    post:
+      summary: "Complete code at the cursor, fill-in-the-middle. With stream=true the answer is server-sent events."
+      requestBody:
+        required: true
+        content: { application/json: { schema: { $ref: "#/components/schemas/CodeCompletionPost" } } }
+      responses:
+        "200": { description: "Completion", content: { application/json: { schema: { $ref: "#/components/schemas/CodeCompletionResponse" } }, text/event-stream: { schema: { type: string } } } }
+        "400": { $ref: "#/components/responses/Error" }
+  /v1/complete/batch:
+    post:
+      summary: "Several non-streaming completions in one request, results come in the order of requests"
+      requestBody:
+        required: true
+        content: { application/json: { schema: { type: array, maxItems: 16, items: { $ref: "#/components/schemas/CodeCompletionPost" } } } }
+      responses:
+        "200": { description: "One completion or one error per request", content: { application/json: { schema: { type: array, items: { type: object } } } } }
+        "400": { $ref: "#/components/responses/Error" }
+  /v1/code-completion-prompt:
+    post:
+      summary: "The prompt a completion would use, without calling the model"
+      requestBody:
+        required: true
+        content: { application/json: { schema: { $ref: "#/components/schemas/CodeCompletionPost" } } }
+      responses:
+        "200": { description: "Prompt", content: { application/json: { schema: { type: object, properties: { prompt: { type: string } } } } } }
+        "400": { $ref: "#/components/responses/Error" }
+  /v1/completions/recent:
+    get:
+      summary: "Last completions for debugging, only with --debug-recent-completions"
+      responses:
+        "200": { description: "Oldest first", content: { application/json: { schema: { type: object, properties: { recent: { type: array, items: { type: object } } } } } } }
+        "404": { $ref: "#/components/responses/Error" }
+  /v1/chat:
+    post:
+      summary: "Chat, the model can call tools. Streams server-sent events when stream is true."
+      requestBody:
+        required: true
+        content: { application/json: { schema: { $ref: "#/components/schemas/ChatPost" } } }
+      responses:
+        "200": { description: "Chat response", content: { application/json: { schema: { type: object } }, text/event-stream: { schema: { type: string } } } }
+        "400": { $ref: "#/components/responses/Error" }
+  /v1/chat/completions:
+    post:
+      summary: "Same as /v1/chat, under the OpenAI-compatible path"
+      requestBody:
+        required: true
+        content: { application/json: { schema: { $ref: "#/components/schemas/ChatPost" } } }
+      responses:
+        "200": { description: "Chat response", content: { application/json: { schema: { type: object } }, text/event-stream: { schema: { type: string } } } }
+        "400": { $ref: "#/components/responses/Error" }
+  /v1/tools:
+    get:
+      summary: "Tools available to the model, in the OpenAI function calling format"
+      responses:
+        "200": { description: "Tools", content: { application/json: { schema: { type: array, items: { type: object } } } } }
+  /v1/tools-execute:
+    post:
+      summary: "Run the tool calls of the last assistant message"
+      requestBody:
+        required: true
+        content: { application/json: { schema: { type: object, required: [messages, n_ctx, maxgen, model_name], properties: { messages: { type: array, items: { $ref: "#/components/schemas/ChatMessage" } }, n_ctx: { type: integer }, maxgen: { type: integer }, model_name: { type: string } } } } }
+      responses:
+        "200": { description: "Messages with tool results", content: { application/json: { schema: { type: object, properties: { messages: { type: array, items: { $ref: "#/components/schemas/ChatMessage" } }, tools_ran: { type: boolean } } } } } }
+        "400": { $ref: "#/components/responses/Error" }
+  /v1/tokenize:
+    post:
+      summary: "Split text into tokens of a model, with their offsets in chars"
+      requestBody:
+        required: true
+        content: { application/json: { schema: { type: object, required: [text], properties: { model: { type: string }, text: { type: string } } } } }
+      responses:
+        "200": { description: "Tokens", content: { application/json: { schema: { type: object } } } }
+        "400": { $ref: "#/components/responses/Error" }
+  /v1/rag-status:
+    get:
+      summary: "Indexing progress of AST and vector database"
+      responses:
+        "200": { description: "Status", content: { application/json: { schema: { type: object } } } }
+  /v1/ast-status:
+    get:
+      summary: "AST index status"
+      responses:
+        "200": { description: "Status", content: { application/json: { schema: { type: object } } } }
+  /v1/ast-parse-file:
+    post:
+      summary: "Parse one file with the same parser as indexing and return its symbols, the file doesn't have to exist"
+      requestBody:
+        required: true
+        content: { application/json: { schema: { type: object, required: [path, content], properties: { path: { type: string, description: "Only the extension matters" }, content: { type: string } } } } }
+      responses:
+        "200": { description: "Symbols with name, kind, full_range, declaration_range, parent_guid and is_error", content: { application/json: { schema: { type: object } } } }
+        "400": { $ref: "#/components/responses/Error" }
+        "422": { $ref: "#/components/responses/Error" }
+  /build_info:
+    get:
+      summary: "Version and build information"
+      responses:
+        "200": { description: "Build info", content: { application/json: { schema: { type: object, additionalProperties: { type: string } } } } }
+  /metrics:
+    get:
+      summary: "Prometheus metrics"
+      responses:
+        "200": { description: "Metrics in the text exposition format", content: { text/plain: { schema: { type: string } } } }
+  /openapi.json:
+    get:
+      summary: "This document"
+      responses:
+        "200": { description: "OpenAPI document", content: { application/json: { schema: { type: object } } } }
+components:
+  responses:
+    Error:
+      description: "Something went wrong, detail says what"
+      content: { application/json: { schema: { type: object, properties: { detail: { type: string } } } } }
+  schemas:
+    SamplingParameters:
+      type: object
+      properties:
+        max_new_tokens: { type: integer }
+        temperature: { type: number }
+        top_p: { type: number }
+        stop: { type: array, items: { type: string } }
+        n: { type: integer }
+        seed: { type: integer, description: "Only sent to models with supports_seed in caps" }
+        reasoning_effort: { description: "low, medium, high, or a thinking budget in tokens" }
+    CursorPosition:
+      type: object
+      required: [file, line, character]
+      properties:
+        file: { type: string }
+        line: { type: integer, description: "Starts from 0" }
+        character: { type: integer, description: "Starts from 0" }
+    CodeCompletionPost:
+      type: object
+      required: [inputs]
+      properties:
+        inputs:
+          type: object
+          required: [sources, cursor, multiline]
+          properties:
+            sources: { type: object, additionalProperties: { type: string }, description: "File name to text, the cursor file must be there" }
+            cursor: { $ref: "#/components/schemas/CursorPosition" }
+            multiline: { type: boolean }
+        parameters: { $ref: "#/components/schemas/SamplingParameters" }
+        model: { type: string, description: "Empty means the default completion model from caps" }
+        scratchpad: { type: string }
+        stream: { type: boolean }
+        no_cache: { type: boolean }
+        use_ast: { type: boolean }
+        rag_tokens_n: { type: integer }
+    CodeCompletionResponse:
+      type: object
+      properties:
+        choices:
+          type: array
+          items:
+            type: object
+            properties:
+              index: { type: integer }
+              code_completion: { type: string }
+              finish_reason: { type: string }
+        model: { type: string }
+        cached: { type: boolean }
+        snippet_telemetry_id: { type: integer }
+    ChatMessage:
+      type: object
+      required: [role, content]
+      properties:
+        role: { type: string, enum: [system, user, assistant, tool, context_file, plain_text] }
+        content: { description: "A string, or a list of text and image elements" }
+        tool_calls: { type: array, items: { type: object } }
+        tool_call_id: { type: string }
+    ChatPost:
+      type: object
+      required: [messages]
+      properties:
+        messages: { type: array, items: { $ref: "#/components/schemas/ChatMessage" } }
+        parameters: { $ref: "#/components/schemas/SamplingParameters" }
+        model: { type: string }
+        stream: { type: boolean }
+        temperature: { type: number }
+        max_tokens: { type: integer }
+        n: { type: integer }
+        tools: { type: array, items: { type: object } }
+        tool_choice: { type: string }
+        reasoning_effort: { description: "OpenAI-style, same as parameters.reasoning_effort" }
+        seed: { type: integer }
+"####;
+
+pub fn openapi_document() -> serde_json::Value {
+    let mut doc: serde_json::Value = serde_yaml::from_str(OPENAPI_YAML).expect("OPENAPI_YAML is broken");
+    doc["info"]["version"] = serde_json::Value::String(crate::version::build_info::PKG_VERSION.to_string());
+    doc
+}
+
+pub async fn handle_openapi_json() -> axum::response::Result<Response<Body>, ScratchError> {
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string_pretty(&openapi_document()).unwrap()))
+        .unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_document_lists_completion_route() {
+        let text = serde_json::to_string(&openapi_document()).unwrap();
+        let doc: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(doc["openapi"], "3.0.3");
+        assert!(!doc["info"]["version"].as_str().unwrap().is_empty());
+        let paths = doc["paths"].as_object().unwrap();
+        assert!(paths["/v1/code-completion"]["post"]["requestBody"].is_object());
+
+        // every path in the document is actually routed
+        let v1_routes = include_str!("v1.rs");
+        let root_routes = include_str!("../routers.rs");
+        for path in paths.keys() {
+            let routed = match path.strip_prefix("/v1") {
+                Some(sub) => v1_routes.contains(&format!(".route(\"{}\",", sub)),
+                None => root_routes.contains(&format!(".route(\"{}\",", path)),
+            };
+            assert!(routed, "{} is in the OpenAPI document but not routed", path);
+        }
+        // and every $ref points to something
+        for cap in regex::Regex::new(r##""\$ref":"#/components/(\w+)/(\w+)""##).unwrap().captures_iter(&text) {
+            assert!(doc["components"][&cap[1]][&cap[2]].is_object(), "dangling $ref to {}/{}", &cap[1], &cap[2]);
+        }
+    }
+}
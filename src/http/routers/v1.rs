@@ -9,12 +9,12 @@ use hyper::Body;
 use hyper::Response;
 use tower_http::cors::CorsLayer;
 
-use crate::{telemetry_get, telemetry_post};
+use crate::{telemetry_get, telemetry_get_query, telemetry_post};
 use crate::custom_error::ScratchError;
 use crate::global_context::SharedGlobalContext;
-use crate::http::routers::v1::code_completion::{handle_v1_code_completion_web, handle_v1_code_completion_prompt};
+use crate::http::routers::v1::code_completion::{handle_v1_code_completion_web, handle_v1_code_completion_batch, handle_v1_code_completion_prompt, handle_v1_completions_recent};
 use crate::http::routers::v1::code_lens::handle_v1_code_lens;
-use crate::http::routers::v1::ast::{handle_v1_ast_file_dump, handle_v1_ast_file_symbols, handle_v1_ast_status};
+use crate::http::routers::v1::ast::{handle_v1_ast_file_dump, handle_v1_ast_file_symbols, handle_v1_ast_parse_file, handle_v1_ast_references, handle_v1_ast_status, handle_v1_ast_status_per_language};
 use crate::http::routers::v1::at_commands::{handle_v1_command_completion, handle_v1_command_preview, handle_v1_at_command_execute};
 use crate::http::routers::v1::at_tools::{handle_v1_tools, handle_v1_tools_check_if_confirmation_needed, handle_v1_tools_execute};
 use crate::http::routers::v1::caps::handle_v1_caps;
@@ -25,6 +25,7 @@ use crate::http::routers::v1::dashboard::get_dashboard_plots;
 use crate::http::routers::v1::docker::{handle_v1_docker_container_action, handle_v1_docker_container_list};
 use crate::http::routers::v1::git::handle_v1_git_commit;
 use crate::http::routers::v1::graceful_shutdown::handle_v1_graceful_shutdown;
+use crate::http::routers::v1::log_level::handle_v1_log_level;
 use crate::http::routers::v1::snippet_accepted::handle_v1_snippet_accepted;
 use crate::http::routers::v1::telemetry_network::handle_v1_telemetry_network;
 use crate::http::routers::v1::telemetry_chat::handle_v1_telemetry_chat;
@@ -38,6 +39,7 @@ use crate::http::routers::v1::patch::{handle_v1_patch_apply_all, handle_v1_patch
 use crate::http::routers::v1::subchat::{handle_v1_subchat, handle_v1_subchat_single};
 use crate::http::routers::v1::sync_files::handle_v1_sync_files_extract_tar;
 use crate::http::routers::v1::system_prompt::handle_v1_prepend_system_prompt_and_maybe_more_initial_messages;
+use crate::http::routers::v1::tokenize::handle_v1_tokenize;
 
 #[cfg(feature="vecdb")]
 use crate::http::routers::v1::vecdb::{handle_v1_vecdb_search, handle_v1_vecdb_status};
@@ -56,6 +58,7 @@ pub mod caps;
 mod docker;
 mod git;
 pub mod graceful_shutdown;
+mod log_level;
 mod dashboard;
 pub mod links;
 pub mod lsp_like_handlers;
@@ -76,14 +79,18 @@ pub mod handlers_memdb;
 #[cfg(feature="vecdb")]
 pub mod vecdb;
 mod v1_integrations;
+mod tokenize;
 
 
 pub fn make_v1_router() -> Router {
     let builder = Router::new()
         .route("/ping", telemetry_get!(handle_v1_ping))
         .route("/graceful-shutdown", telemetry_get!(handle_v1_graceful_shutdown))
+        .route("/log-level", telemetry_post!(handle_v1_log_level))
 
         .route("/code-completion", telemetry_post!(handle_v1_code_completion_web))
+        .route("/complete/batch", telemetry_post!(handle_v1_code_completion_batch))
+        .route("/completions/recent", telemetry_get!(handle_v1_completions_recent))
         .route("/code-lens", telemetry_post!(handle_v1_code_lens))
 
         .route("/chat", telemetry_post!(handle_v1_chat))
@@ -94,6 +101,7 @@ pub fn make_v1_router() -> Router {
         .route("/snippet-accepted", telemetry_post!(handle_v1_snippet_accepted))
 
         .route("/caps", telemetry_get!(handle_v1_caps))
+        .route("/tokenize", telemetry_post!(handle_v1_tokenize))
 
         .route("/tools", telemetry_get!(handle_v1_tools))
         .route("/tools-check-if-confirmation-needed", telemetry_post!(handle_v1_tools_check_if_confirmation_needed))
@@ -107,7 +115,10 @@ pub fn make_v1_router() -> Router {
 
         .route("/ast-file-symbols", telemetry_post!(handle_v1_ast_file_symbols))
         .route("/ast-file-dump", telemetry_post!(handle_v1_ast_file_dump))
+        .route("/ast-parse-file", telemetry_post!(handle_v1_ast_parse_file))
         .route("/ast-status", telemetry_get!(handle_v1_ast_status))
+        .route("/ast/status", telemetry_get!(handle_v1_ast_status_per_language))
+        .route("/ast/references", telemetry_get_query!(handle_v1_ast_references))
 
         .route("/rag-status", telemetry_get!(handle_v1_rag_status))
         .route("/config-path", telemetry_get!(handle_v1_config_path))
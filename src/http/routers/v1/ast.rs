@@ -1,5 +1,6 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use axum::Extension;
+use axum::extract::Query;
 use axum::response::Result;
 use hyper::{Body, Response, StatusCode};
 use serde::{Deserialize, Serialize};
@@ -7,7 +8,9 @@ use url::Url;
 use serde_json::json;
 use uuid::Uuid;
 
+use crate::ast::treesitter::parsers::get_ast_parser_by_filename;
 use crate::custom_error::ScratchError;
+use crate::file_filter::LARGE_FILE_SIZE_THRESHOLD;
 use crate::files_in_workspace::{Document, get_file_text_from_memory_or_disk};
 use crate::global_context::SharedGlobalContext;
 use crate::postprocessing::pp_context_files::pp_color_lines;
@@ -34,11 +37,24 @@ struct AstFileUrlPost {
     file_url: Url,
 }
 
+#[derive(Deserialize, Clone)]
+pub struct AstReferencesQuery {
+    path: String,
+    line: usize,
+    col: usize,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct FileNameOnlyPost {
     file_name: String,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+struct AstParseFilePost {
+    path: String,
+    content: String,
+}
+
 
 pub async fn handle_v1_ast_file_dump(
     Extension(global_context): Extension<SharedGlobalContext>,
@@ -163,3 +179,228 @@ pub async fn handle_v1_ast_status(
         }
     }
 }
+
+pub async fn handle_v1_ast_status_per_language(
+    Extension(global_context): Extension<SharedGlobalContext>,
+    _: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    let ast_service = global_context.read().await.ast_service.clone().ok_or(
+        ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, "ast module is turned off".to_string())
+    )?;
+    let ast_coverage = ast_service.lock().await.ast_coverage.clone();
+    let report = crate::ast::ast_indexer_thread::ast_coverage_report(&*ast_coverage.lock().await);
+    let json_string = serde_json::to_string_pretty(&report).map_err(|e| {
+        ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("JSON serialization problem: {}", e))
+    })?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(json_string))
+        .unwrap())
+}
+
+fn symbol_range_in_line(line_text: &str, symbol: &str, uline: usize) -> serde_json::Value {
+    // usages only know the line, find the symbol in it to give editors a precise range
+    let (start, end) = match line_text.find(symbol) {
+        Some(pos) => {
+            let start = line_text[..pos].encode_utf16().count();
+            (start, start + symbol.encode_utf16().count())
+        }
+        None => (0, line_text.encode_utf16().count()),
+    };
+    json!({
+        "start": {"line": uline, "character": start},
+        "end": {"line": uline, "character": end},
+    })
+}
+
+pub async fn handle_v1_ast_references(
+    Extension(global_context): Extension<SharedGlobalContext>,
+    Query(params): Query<AstReferencesQuery>,
+) -> Result<Response<Body>, ScratchError> {
+    let ast_service = global_context.read().await.ast_service.clone()
+        .ok_or(ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, "ast module is turned off".to_string()))?;
+    let ast_index = ast_service.lock().await.ast_index.clone();
+
+    let candidates = crate::files_correction::correct_to_nearest_filename(global_context.clone(), &params.path, false, 1).await;
+    if candidates.len() != 1 {
+        return Err(ScratchError::new(StatusCode::NOT_FOUND, format!("file not found or ambiguous, candidates {:?}", candidates)));
+    }
+    let cpath = candidates[0].clone();
+    let file_text = get_file_text_from_memory_or_disk(global_context.clone(), &cpath.clone().into()).await
+        .map_err(|e| ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let refs = crate::ast::ast_db::references_at_position(ast_index, &cpath, &file_text, params.line, params.col, 1000).await
+        .map_err(|e| ScratchError::new(StatusCode::BAD_REQUEST, e))?;
+
+    let mut file_texts: HashMap<String, String> = HashMap::from([(cpath.clone(), file_text.to_string())]);
+    let mut references = vec![];
+    for (target_n, used_at_def, uline) in refs.references.iter() {
+        if !file_texts.contains_key(&used_at_def.cpath) {
+            let text = get_file_text_from_memory_or_disk(global_context.clone(), &used_at_def.cpath.clone().into()).await.unwrap_or_default();
+            file_texts.insert(used_at_def.cpath.clone(), text.to_string());
+        }
+        let line_text = file_texts.get(&used_at_def.cpath).and_then(|t| t.lines().nth(*uline)).unwrap_or("");
+        references.push(json!({
+            "file": used_at_def.cpath,
+            "range": symbol_range_in_line(line_text, &refs.symbol, *uline),
+            "used_in": used_at_def.path_drop0(),
+            "definition_n": target_n,
+        }));
+    }
+
+    let mut notes = vec![];
+    if refs.targets.is_empty() {
+        notes.push(format!("no definition found for `{}`, the file might not be indexed yet", refs.symbol));
+    } else if refs.targets.len() > 1 {
+        notes.push(format!("`{}` is ambiguous, {} definitions match, references for all of them are listed", refs.symbol, refs.targets.len()));
+    }
+    if refs.resolved_by_name_only && !refs.targets.is_empty() {
+        notes.push("the symbol under cursor is not resolved, definitions were found by name only".to_string());
+    }
+    notes.push("only references the indexer could resolve are listed, dynamic calls and unindexed files are not covered".to_string());
+
+    let definitions: Vec<serde_json::Value> = refs.targets.iter().map(|def| json!({
+        "file": def.cpath,
+        "path": def.path_drop0(),
+        "line1": def.full_line1(),
+        "line2": def.full_line2(),
+    })).collect();
+    let result = json!({
+        "symbol": refs.symbol,
+        "definitions": definitions,
+        "references": references,
+        "ambiguous": refs.targets.len() > 1,
+        "notes": notes,
+    });
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string_pretty(&result).unwrap()))
+        .unwrap())
+}
+
+fn range_json(range: &tree_sitter::Range) -> serde_json::Value {
+    json!({
+        "start_byte": range.start_byte,
+        "end_byte": range.end_byte,
+        "start_point": {"row": range.start_point.row, "column": range.start_point.column},
+        "end_point": {"row": range.end_point.row, "column": range.end_point.column},
+    })
+}
+
+fn ast_parse_file(body_bytes: &[u8]) -> Result<serde_json::Value, ScratchError> {
+    let post = serde_json::from_slice::<AstParseFilePost>(body_bytes).map_err(|e| {
+        ScratchError::new(StatusCode::BAD_REQUEST, format!("JSON problem: {}", e))
+    })?;
+    // same parser choice and size limit as indexing, the file doesn't have to exist
+    if post.content.len() as u64 > LARGE_FILE_SIZE_THRESHOLD {
+        return Err(ScratchError::new(StatusCode::PAYLOAD_TOO_LARGE, format!("{} is {} bytes, files over {} bytes are not parsed", post.path, post.content.len(), LARGE_FILE_SIZE_THRESHOLD)));
+    }
+    let path = std::path::PathBuf::from(&post.path);
+    let (mut parser, language_id) = get_ast_parser_by_filename(&path)
+        .map_err(|e| ScratchError::new(StatusCode::BAD_REQUEST, format!("cannot parse {}: {}", post.path, e.message)))?;
+    let (symbols, has_syntax_errors) = parser.parse_with_syntax_errors(&post.content, &path)
+        .ok_or(ScratchError::new(StatusCode::UNPROCESSABLE_ENTITY, format!("{} takes longer than --ast-parse-timeout-ms to parse", post.path)))?;
+    if has_syntax_errors && symbols.iter().all(|s| s.read().is_error()) {
+        return Err(ScratchError::new(StatusCode::UNPROCESSABLE_ENTITY, format!("{} has only syntax errors, {} error nodes", post.path, symbols.len())));
+    }
+    let symbols_json: Vec<serde_json::Value> = symbols.iter().map(|s| {
+        let s = s.read();
+        json!({
+            "guid": s.guid(),
+            "name": s.name(),
+            "kind": s.symbol_type().to_string(),
+            "full_range": range_json(s.full_range()),
+            "declaration_range": range_json(s.declaration_range()),
+            "parent_guid": s.parent_guid(),
+            "is_error": s.is_error(),
+        })
+    }).collect();
+    Ok(json!({
+        "path": post.path,
+        "language": language_id.to_string(),
+        "symbols": symbols_json,
+    }))
+}
+
+pub async fn handle_v1_ast_parse_file(
+    Extension(_global_context): Extension<SharedGlobalContext>,
+    body_bytes: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    ast_parse_file_response(body_bytes).await
+}
+
+async fn ast_parse_file_response(body_bytes: hyper::body::Bytes) -> Result<Response<Body>, ScratchError> {
+    // parsing is CPU work, up to the parse timeout, it shouldn't hold up other requests
+    let result = tokio::task::spawn_blocking(move || ast_parse_file(&body_bytes))
+        .await
+        .map_err(|e| ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("parse task failed: {}", e)))??;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string_pretty(&result).unwrap()))
+        .unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ast_parse_file_python_and_unsupported() {
+        let body = json!({"path": "/ws/goat.py", "content": "class Goat:\n    def eat(self, grass):\n        return grass\n"});
+        let result = ast_parse_file(body.to_string().as_bytes()).unwrap();
+        assert_eq!(result["language"], "python");
+        let symbols = result["symbols"].as_array().unwrap();
+        let goat = symbols.iter().find(|s| s["name"] == "Goat").expect("Goat");
+        assert_eq!(goat["kind"], "StructDeclaration");
+        assert_eq!(goat["is_error"], false);
+        assert_eq!(goat["full_range"]["start_point"]["row"], 0);
+        let eat = symbols.iter().find(|s| s["name"] == "eat" && s["kind"] == "FunctionDeclaration").expect("eat");
+        assert_eq!(eat["parent_guid"], goat["guid"]);
+        assert_eq!(eat["declaration_range"]["start_point"]["row"], 1);
+
+        let body = json!({"path": "/ws/data.foo", "content": "whatever"});
+        let err = ast_parse_file(body.to_string().as_bytes()).unwrap_err();
+        assert_eq!(err.status_code, StatusCode::BAD_REQUEST);
+        assert!(err.message.contains("foo"), "{}", err.message);
+
+        let err = ast_parse_file(b"{\"path\": \"/ws/goat.py\"}").unwrap_err();
+        assert_eq!(err.status_code, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_ast_parse_file_route_status_codes() {
+        use axum::routing::post;
+        use tower::ServiceExt;
+        let router = axum::Router::new().route("/v1/ast-parse-file", post(ast_parse_file_response));
+        let call = |body: String| {
+            let router = router.clone();
+            async move {
+                let request = hyper::Request::post("/v1/ast-parse-file").body(Body::from(body)).unwrap();
+                let response = router.oneshot(request).await.unwrap();
+                let status = response.status();
+                let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&bytes).unwrap())
+            }
+        };
+
+        let (status, result) = call(json!({"path": "/ws/goat.py", "content": "def eat(grass):\n    return grass\n"}).to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(result["symbols"].as_array().unwrap().iter().any(|s| s["name"] == "eat"), "{}", result);
+
+        let (status, result) = call(json!({"path": "/ws/data.foo", "content": "whatever"}).to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(result["detail"].as_str().unwrap().contains("foo"), "{}", result);
+        let (status, _) = call("not json".to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, result) = call(json!({"path": "/ws/goat.py", "content": "}}}}"}).to_string()).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(result["detail"].as_str().unwrap().contains("only syntax errors"), "{}", result);
+
+        let big = "x = 1\n".repeat(LARGE_FILE_SIZE_THRESHOLD as usize / 6 + 1);
+        let (status, _) = call(json!({"path": "/ws/big.py", "content": big}).to_string()).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
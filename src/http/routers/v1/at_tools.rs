@@ -10,7 +10,7 @@ use tokio::sync::{Mutex as AMutex, RwLock as ARwLock};
 
 use crate::at_commands::at_commands::AtCommandsContext;
 use crate::cached_tokenizers;
-use crate::call_validation::{ChatMessage, ChatToolCall, PostprocessSettings, SubchatParameters};
+use crate::call_validation::{ChatMessage, ChatToolCall, PostprocessSettings, SubchatParameters, ToolsPermissions};
 use crate::http::routers::v1::chat::CHAT_TOP_N;
 use crate::tools::tools_description::{tool_description_list_from_yaml, tools_merged_and_filtered, MatchConfirmDenyResult};
 use crate::custom_error::ScratchError;
@@ -53,6 +53,8 @@ pub struct ToolsExecutePost {
     pub chat_id: String,
     pub style: Option<String>,
     pub tools_confirmation: bool,
+    #[serde(default)]
+    pub tools_permissions: ToolsPermissions,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -194,6 +196,7 @@ pub async fn handle_v1_tools_execute(
     ).await;
     ccx.subchat_tool_parameters = tools_execute_post.subchat_tool_parameters.clone();
     ccx.postprocess_parameters = tools_execute_post.postprocess_parameters.clone();
+    ccx.tools_permissions = ccx.tools_permissions.narrowed_by(&tools_execute_post.tools_permissions);
     let ccx_arc = Arc::new(AMutex::new(ccx));
 
     let mut at_tools = tools_merged_and_filtered(gcx.clone(), false).await.map_err(|e|{
@@ -10,7 +10,7 @@ use serde_json::Value;
 
 use crate::call_validation::{ChatContent, ChatMessage, ChatPost, ChatMode};
 use crate::caps::CodeAssistantCaps;
-use crate::custom_error::ScratchError;
+use crate::custom_error::{ScratchError, ScratchErrorCode};
 use crate::at_commands::at_commands::AtCommandsContext;
 use crate::global_context::{is_metadata_supported, GlobalContext, SharedGlobalContext};
 use crate::integrations::docker::docker_container_manager::docker_container_check_status_or_start;
@@ -66,7 +66,8 @@ pub async fn lookup_chat_scratchpad(
             &chat_post.model,
             &caps_locked.code_chat_default_model,
         )?;
-    let (sname, patch) = crate::caps::which_scratchpad_to_use(
+    let (sname, patch) = crate::caps::which_allowed_scratchpad_to_use(
+        &caps_locked,
         &recommended_model_record.supports_scratchpads,
         &chat_post.scratchpad,
         &recommended_model_record.default_scratchpad,
@@ -114,6 +115,7 @@ async fn _chat(
     body_bytes: &hyper::body::Bytes,
     allow_at: bool
 ) -> Result<Response<Body>, ScratchError> {
+    crate::metrics::inc(&crate::metrics::METRICS.chat_requests);
     let mut chat_post: ChatPost = serde_json::from_slice::<ChatPost>(&body_bytes).map_err(|e| {
         tracing::warn!("chat handler cannot parse input:\n{:?}", body_bytes);
         ScratchError::new(StatusCode::BAD_REQUEST, format!("JSON problem: {}", e))
@@ -149,7 +151,7 @@ async fn _chat(
         caps.clone(),
         &chat_post,
     ).await.map_err(|e| {
-        ScratchError::new(StatusCode::BAD_REQUEST, format!("{}", e))
+        ScratchError::new(StatusCode::BAD_REQUEST, format!("{}", e)).with_code(ScratchErrorCode::ModelNotFound)
     })?;
     if chat_post.parameters.max_new_tokens == 0 {
         chat_post.parameters.max_new_tokens = chat_post.max_tokens;
@@ -158,8 +160,34 @@ async fn _chat(
         chat_post.parameters.max_new_tokens = 1024;
     }
     chat_post.parameters.n = chat_post.n;
+    if chat_post.parameters.reasoning_effort.is_none() {
+        chat_post.parameters.reasoning_effort = chat_post.reasoning_effort.clone();
+    }
+    if let Some(effort) = chat_post.parameters.reasoning_effort.clone() {
+        let style = caps.read().unwrap().code_chat_models.get(&model_name).map(|rec| rec.reasoning_effort_style.clone()).unwrap_or_default();
+        if style.is_empty() {
+            tracing::warn!("model {} doesn't take reasoning_effort, ignoring {}", model_name, effort);
+            chat_post.parameters.reasoning_effort = None;
+        } else {
+            crate::forward_to_openai_endpoint::reasoning_effort_fields(&style, &effort, chat_post.parameters.max_new_tokens)
+                .map_err(|e| ScratchError::new(StatusCode::BAD_REQUEST, e))?;
+        }
+        chat_post.parameters.reasoning_effort_style = style;
+    }
+    let seed = chat_post.parameters.seed.or(chat_post.seed);
+    chat_post.parameters.seed = crate::caps::seed_if_supported(&caps.read().unwrap().code_chat_models, &model_name, seed);
     chat_post.parameters.temperature = Some(chat_post.parameters.temperature.unwrap_or(chat_post.temperature.unwrap_or(0.2)));
+    chat_post.parameters.image_style = caps.read().unwrap().code_chat_models.get(&model_name).map(|rec| rec.image_style.clone()).unwrap_or_default();
     chat_post.model = model_name.clone();
+    let judge_model = if chat_post.self_critique && !chat_post.only_deterministic_messages {
+        let judge_model = caps.read().unwrap().code_chat_judge_model.clone();
+        if judge_model.is_empty() || !caps.read().unwrap().running_models.contains(&judge_model) {
+            return Err(ScratchError::new(StatusCode::BAD_REQUEST, format!("self_critique needs a judge model, set judge_model in caps, now it's {:?}", judge_model)));
+        }
+        Some(judge_model)
+    } else {
+        None
+    };
 
     // extra validation to catch {"query": "Frog", "scope": "workspace"}{"query": "Toad", "scope": "workspace"}
     let re = regex::Regex::new(r"\{.*?\}").unwrap();
@@ -167,7 +195,7 @@ async fn _chat(
         if !supports_multimodality {
             if let ChatContent::Multimodal(content) = &message.content {
                 if content.iter().any(|el| el.is_image()) {
-                    return Err(ScratchError::new(StatusCode::BAD_REQUEST, format!("model '{}' does not support multimodality", model_name)));
+                    return Err(ScratchError::new(StatusCode::BAD_REQUEST, format!("model '{}' can't see images, remove them or pick a model with supports_vision in caps", model_name)));
                 }
             }
             message.content = ChatContent::SimpleText(message.content.content_text_only());
@@ -223,6 +251,31 @@ async fn _chat(
     // SYSTEM PROMPT WAS HERE
 
 
+    let mut ccx = AtCommandsContext::new(
+        gcx.clone(),
+        n_ctx,
+        CHAT_TOP_N,
+        false,
+        messages.clone(),
+        chat_post.meta.chat_id.clone(),
+        should_execute_remotely,
+    ).await;
+    ccx.subchat_tool_parameters = chat_post.subchat_tool_parameters.clone();
+    ccx.postprocess_parameters = chat_post.postprocess_parameters.clone();
+    ccx.tools_permissions = ccx.tools_permissions.narrowed_by(&chat_post.tools_permissions);
+    let ccx_arc = Arc::new(AMutex::new(ccx));
+
+    if let Some(judge_model) = judge_model {
+        // the critique builds a scratchpad for each generation, with the same tools and @-commands, not this one
+        return crate::self_critique::chat_with_self_critique(
+            ccx_arc.clone(),
+            &chat_post,
+            &judge_model,
+            messages,
+            allow_at,
+        ).await;
+    }
+
     // chat_post.stream = Some(false);  // for debugging 400 errors that are hard to debug with streaming (because "data: " is not present and the error message is ignored by the library)
     let mut scratchpad = crate::scratchpads::create_chat_scratchpad(
         gcx.clone(),
@@ -251,18 +304,6 @@ async fn _chat(
     //     ));
     //     let _ = std::fs::write(&notes_path, serde_json::to_string_pretty(&chat_post.messages).unwrap());
     // }
-    let mut ccx = AtCommandsContext::new(
-        gcx.clone(),
-        n_ctx,
-        CHAT_TOP_N,
-        false,
-        messages.clone(),
-        chat_post.meta.chat_id.clone(),
-        should_execute_remotely,
-    ).await;
-    ccx.subchat_tool_parameters = chat_post.subchat_tool_parameters.clone();
-    ccx.postprocess_parameters = chat_post.postprocess_parameters.clone();
-    let ccx_arc = Arc::new(AMutex::new(ccx));
 
     if chat_post.stream.is_some() && !chat_post.stream.unwrap() {
         crate::restream::scratchpad_interaction_not_stream(
@@ -272,7 +313,8 @@ async fn _chat(
             model_name,
             &mut chat_post.parameters,
             chat_post.only_deterministic_messages,
-            meta
+            meta,
+            false,
         ).await
     } else {
         crate::restream::scratchpad_interaction_stream(
@@ -1,5 +1,7 @@
+use std::future::Future;
 use std::sync::Arc;
 use std::sync::RwLock as StdRwLock;
+use futures::StreamExt;
 use tokio::sync::RwLock as ARwLock;
 use tokio::sync::Mutex as AMutex;
 
@@ -11,15 +13,17 @@ use crate::call_validation::{CodeCompletionPost, code_completion_post_validate};
 use crate::caps;
 use crate::caps::CodeAssistantCaps;
 use crate::completion_cache;
-use crate::custom_error::ScratchError;
+use crate::custom_error::{ScratchError, ScratchErrorCode};
 use crate::global_context::GlobalContext;
-use crate::privacy::{check_file_privacy, load_privacy_if_needed};
+use crate::privacy::{check_file_privacy_for_request, load_privacy_if_needed};
 use crate::files_correction::canonical_path;
 use crate::scratchpads;
 use crate::at_commands::at_commands::AtCommandsContext;
 
 
 const CODE_COMPLETION_TOP_N: usize = 5;
+const CODE_COMPLETION_BATCH_MAX: usize = 16;
+const CODE_COMPLETION_BATCH_CONCURRENCY: usize = 4;
 
 async fn _lookup_code_completion_scratchpad(
     caps: Arc<StdRwLock<CodeAssistantCaps>>,
@@ -42,7 +46,8 @@ async fn _lookup_code_completion_scratchpad(
             &caps_locked.multiline_code_completion_default_model,
         )?
     };
-    let (sname, patch) = caps::which_scratchpad_to_use(
+    let (sname, patch) = caps::which_allowed_scratchpad_to_use(
+        &caps_locked,
         &modelrec.supports_scratchpads,
         &code_completion_post.scratchpad,
         &modelrec.default_scratchpad,
@@ -60,11 +65,15 @@ pub async fn handle_v1_code_completion(
     gcx: Arc<ARwLock<GlobalContext>>,
     code_completion_post: &mut CodeCompletionPost,
 ) -> Result<Response<Body>, ScratchError> {
+    let warmup = code_completion_post.warmup;
+    if !warmup {
+        crate::metrics::inc(&crate::metrics::METRICS.completion_requests);
+    }
+    let started_at = std::time::Instant::now();
     code_completion_post_validate(code_completion_post.clone())?;
 
     let cpath = canonical_path(&code_completion_post.inputs.cursor.file);
-    check_file_privacy(load_privacy_if_needed(gcx.clone()).await, &cpath, &crate::privacy::FilePrivacyLevel::OnlySendToServersIControl)
-        .map_err(|e| ScratchError::new(StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    check_file_privacy_for_request(load_privacy_if_needed(gcx.clone()).await, &cpath, &crate::privacy::FilePrivacyLevel::OnlySendToServersIControl)?;
 
     let caps = crate::global_context::try_load_caps_quickly_if_not_present(gcx.clone(), 0).await?;
     let maybe = _lookup_code_completion_scratchpad(
@@ -75,12 +84,13 @@ pub async fn handle_v1_code_completion(
     if maybe.is_err() {
         // On error, this will also invalidate caps each 10 seconds, allows to overcome empty caps situation
         let _ = crate::global_context::try_load_caps_quickly_if_not_present(gcx.clone(), 10).await;
-        return Err(ScratchError::new(StatusCode::BAD_REQUEST, format!("{}", maybe.unwrap_err())))
+        return Err(ScratchError::new(StatusCode::BAD_REQUEST, format!("{}", maybe.unwrap_err())).with_code(ScratchErrorCode::ModelNotFound))
     }
     let (model_name, scratchpad_name, scratchpad_patch, n_ctx) = maybe.unwrap();
     if code_completion_post.parameters.max_new_tokens == 0 {
         code_completion_post.parameters.max_new_tokens = 50;
     }
+    code_completion_post.parameters.seed = crate::caps::seed_if_supported(&caps.read().unwrap().code_completion_models, &model_name, code_completion_post.parameters.seed);
     if code_completion_post.model == "" {
         code_completion_post.model = model_name.clone();
     }
@@ -98,12 +108,29 @@ pub async fn handle_v1_code_completion(
         let cached_maybe = completion_cache::cache_get(cache_arc.clone(), cache_key.clone());
         if let Some(cached_json_value) = cached_maybe {
             // info!("cache hit for key {:?}", cache_key.clone());
+            if !warmup {
+                crate::metrics::inc(&crate::metrics::METRICS.completion_cache_hits);
+                completion_cache::recent_completion_put(cache_arc.clone(), completion_cache::RecentCompletion {
+                    file: code_completion_post.inputs.cursor.file.clone(),
+                    line: code_completion_post.inputs.cursor.line,
+                    character: code_completion_post.inputs.cursor.character,
+                    multiline: code_completion_post.inputs.multiline,
+                    model: code_completion_post.model.clone(),
+                    completion: cached_json_value["choices"][0]["code_completion"].as_str().unwrap_or_default().to_string(),
+                    finish_reason: cached_json_value["choices"][0]["finish_reason"].as_str().unwrap_or_default().to_string(),
+                    cached: true,
+                    ms: started_at.elapsed().as_millis() as u64,
+                });
+            }
             if !code_completion_post.stream {
                 return crate::restream::cached_not_stream(&cached_json_value).await;
             } else {
                 return crate::restream::cached_stream(&cached_json_value).await;
             }
         }
+        if !warmup {
+            crate::metrics::inc(&crate::metrics::METRICS.completion_cache_misses);
+        }
     }
 
     let ast_service_opt = gcx.read().await.ast_service.clone();
@@ -130,7 +157,7 @@ pub async fn handle_v1_code_completion(
         false,
     ).await));
     if !code_completion_post.stream {
-        crate::restream::scratchpad_interaction_not_stream(ccx.clone(), &mut scratchpad, "completion".to_string(), model_name, &mut code_completion_post.parameters, false, None).await
+        crate::restream::scratchpad_interaction_not_stream(ccx.clone(), &mut scratchpad, "completion".to_string(), model_name, &mut code_completion_post.parameters, false, None, warmup).await
     } else {
         crate::restream::scratchpad_interaction_stream(ccx.clone(), scratchpad, "completion-stream".to_string(), model_name, code_completion_post.parameters.clone(), false, None).await
     }
@@ -146,6 +173,82 @@ pub async fn handle_v1_code_completion_web(
     handle_v1_code_completion(gcx.clone(), &mut code_completion_post).await
 }
 
+pub async fn complete_batch_in_order<F, Fut>(
+    posts: Vec<CodeCompletionPost>,
+    concurrency: usize,
+    complete_one: F,
+) -> Vec<serde_json::Value>
+where
+    F: Fn(CodeCompletionPost) -> Fut,
+    Fut: Future<Output = serde_json::Value>,
+{
+    // buffered() runs up to `concurrency` at once, but yields results in the order of requests
+    futures::stream::iter(posts.into_iter().map(complete_one))
+        .buffered(concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await
+}
+
+async fn _code_completion_to_json(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    mut post: CodeCompletionPost,
+) -> serde_json::Value {
+    post.stream = false;
+    let response = match handle_v1_code_completion(gcx, &mut post).await {
+        Ok(response) => response,
+        Err(e) => return serde_json::json!({"detail": e.message, "status": e.status_code.as_u16()}),
+    };
+    let status = response.status();
+    let body_bytes = match hyper::body::to_bytes(response.into_body()).await {
+        Ok(b) => b,
+        Err(e) => return serde_json::json!({"detail": format!("cannot read completion: {}", e), "status": 500}),
+    };
+    match serde_json::from_slice::<serde_json::Value>(&body_bytes) {
+        Ok(v) if status.is_success() => v,
+        Ok(v) => serde_json::json!({"detail": v.get("detail").cloned().unwrap_or(v), "status": status.as_u16()}),
+        Err(e) => serde_json::json!({"detail": format!("completion is not json: {}", e), "status": 500}),
+    }
+}
+
+pub async fn handle_v1_code_completion_batch(
+    Extension(gcx): Extension<Arc<ARwLock<GlobalContext>>>,
+    body_bytes: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    // Several cursor positions in one round-trip, each one is a normal non-streaming completion with its own cache lookup.
+    // A failed position doesn't fail the batch, its slot gets {"detail": ..., "status": ...} instead.
+    let posts = serde_json::from_slice::<Vec<CodeCompletionPost>>(&body_bytes).map_err(|e|
+        ScratchError::new(StatusCode::BAD_REQUEST, format!("JSON problem: {}", e))
+    )?;
+    if posts.len() > CODE_COMPLETION_BATCH_MAX {
+        return Err(ScratchError::new(StatusCode::BAD_REQUEST, format!("too many completions in a batch: {}, max is {}", posts.len(), CODE_COMPLETION_BATCH_MAX)));
+    }
+    let results = complete_batch_in_order(posts, CODE_COMPLETION_BATCH_CONCURRENCY, |post| _code_completion_to_json(gcx.clone(), post)).await;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&results).unwrap()))
+        .unwrap())
+}
+
+pub async fn handle_v1_completions_recent(
+    Extension(gcx): Extension<Arc<ARwLock<GlobalContext>>>,
+    _: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    let (enabled, cache_arc) = {
+        let gcx_locked = gcx.read().await;
+        (gcx_locked.cmdline.debug_recent_completions > 0, gcx_locked.completions_cache.clone())
+    };
+    if !enabled {
+        return Err(ScratchError::new(StatusCode::NOT_FOUND, "recent completions are not kept, start with --debug-recent-completions N".to_string()));
+    }
+    let recent = completion_cache::recent_completions_json(cache_arc, load_privacy_if_needed(gcx.clone()).await);
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string_pretty(&serde_json::json!({"recent": recent})).unwrap()))
+        .unwrap())
+}
+
 pub async fn handle_v1_code_completion_prompt(
     Extension(gcx): Extension<Arc<ARwLock<GlobalContext>>>,
     body_bytes: hyper::body::Bytes,
@@ -157,13 +260,12 @@ pub async fn handle_v1_code_completion_prompt(
     code_completion_post_validate(post.clone())?;
 
     let cpath = canonical_path(&post.inputs.cursor.file);
-    check_file_privacy(load_privacy_if_needed(gcx.clone()).await, &cpath, &crate::privacy::FilePrivacyLevel::OnlySendToServersIControl)
-        .map_err(|e| ScratchError::new(StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    check_file_privacy_for_request(load_privacy_if_needed(gcx.clone()).await, &cpath, &crate::privacy::FilePrivacyLevel::OnlySendToServersIControl)?;
 
     let caps = crate::global_context::try_load_caps_quickly_if_not_present(gcx.clone(), 0).await?;
     let maybe = _lookup_code_completion_scratchpad(caps.clone(), &post, post.inputs.multiline).await;
     if maybe.is_err() {
-        return Err(ScratchError::new(StatusCode::BAD_REQUEST, format!("{}", maybe.unwrap_err())))
+        return Err(ScratchError::new(StatusCode::BAD_REQUEST, format!("{}", maybe.unwrap_err())).with_code(ScratchErrorCode::ModelNotFound))
     }
     let (model_name, scratchpad_name, scratchpad_patch, n_ctx) = maybe.unwrap();
 
@@ -209,3 +311,30 @@ pub async fn handle_v1_code_completion_prompt(
         .unwrap();
     return Ok(response);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_complete_batch_in_order() {
+        let posts: Vec<CodeCompletionPost> = (0..3).map(|line| serde_json::from_value(serde_json::json!({
+            "inputs": {
+                "sources": {"a.py": "def f():\n    pass\n\n"},
+                "cursor": {"file": "a.py", "line": line, "character": 0},
+                "multiline": false,
+            },
+        })).unwrap()).collect();
+        // the first position is the slowest one, the order of results should still follow the order of requests
+        let results = complete_batch_in_order(posts, 3, |post| async move {
+            let line = post.inputs.cursor.line;
+            tokio::time::sleep(tokio::time::Duration::from_millis(30 * (3 - line as u64))).await;
+            serde_json::json!({"line": line})
+        }).await;
+        assert_eq!(results, vec![
+            serde_json::json!({"line": 0}),
+            serde_json::json!({"line": 1}),
+            serde_json::json!({"line": 2}),
+        ]);
+    }
+}
@@ -17,6 +17,8 @@ struct MemAddRequest {
     project: String,
     payload: String,
     origin: String,   // TODO: upgrade to serde_json::Value
+    #[serde(default)]
+    tags: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -37,6 +39,8 @@ struct MemQuery {
     #[allow(unused)]
     project: String,
     top_n: usize,
+    #[serde(default)]
+    tag: String,
 }
 
 pub async fn handle_mem_add(
@@ -55,7 +59,8 @@ pub async fn handle_mem_add(
         &post.goal,
         &post.project,
         &post.payload,
-        &post.origin
+        &post.origin,
+        &post.tags,
     ).await.map_err(|e| {
         ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e))
     })?;
@@ -147,10 +152,11 @@ pub async fn handle_mem_query(
         ScratchError::new(StatusCode::BAD_REQUEST, format!("JSON problem: {}", e))
     })?;
 
-    let memories = crate::vecdb::vdb_highlev::memories_search(
+    let memories = crate::vecdb::vdb_highlev::memories_search_with_tag(
         gcx.clone(),
         &post.goal,
         post.top_n,
+        Some(post.tag.as_str()).filter(|t| !t.trim().is_empty()),
     ).await.map_err(|e| {
         ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("{e}"))
     })?;
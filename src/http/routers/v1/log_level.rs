@@ -0,0 +1,37 @@
+use axum::Extension;
+use axum::response::Result;
+use hyper::{Body, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::custom_error::ScratchError;
+use crate::global_context::SharedGlobalContext;
+
+
+#[derive(Deserialize)]
+struct LogLevelPost {
+    filter: String,
+}
+
+pub async fn handle_v1_log_level(
+    Extension(gcx): Extension<SharedGlobalContext>,
+    body_bytes: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    // Inside a container the server listens on 0.0.0.0, otherwise on 127.0.0.1 only. Changing what gets
+    // logged is for whoever runs the binary locally, not for the network.
+    if gcx.read().await.cmdline.inside_container {
+        return Err(ScratchError::new(StatusCode::FORBIDDEN, "log level can be changed only when listening on loopback".to_string()));
+    }
+    let post = serde_json::from_slice::<LogLevelPost>(&body_bytes).map_err(|e|
+        ScratchError::new(StatusCode::UNPROCESSABLE_ENTITY, format!("JSON problem: {}", e))
+    )?;
+    let applied = crate::nicer_logs::set_log_filter(&post.filter).map_err(|e|
+        ScratchError::new(StatusCode::BAD_REQUEST, e)
+    )?;
+    tracing::info!("log filter changed to {:?}", applied);
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(json!({"success": true, "filter": applied}).to_string()))
+        .unwrap())
+}
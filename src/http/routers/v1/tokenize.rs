@@ -0,0 +1,91 @@
+use axum::Extension;
+use axum::response::Result;
+use hyper::{Body, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokenizers::Tokenizer;
+
+use crate::custom_error::ScratchError;
+use crate::global_context::{try_load_caps_quickly_if_not_present, SharedGlobalContext};
+
+
+#[derive(Deserialize)]
+struct TokenizePost {
+    #[serde(default)]
+    model: String,
+    text: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TokenWithOffsets {
+    pub id: u32,
+    pub text: String,
+    pub start: usize,   // in chars, not bytes, editors count this way
+    pub end: usize,
+}
+
+pub fn tokenize_with_offsets(tokenizer: &Tokenizer, text: &str) -> Result<Vec<TokenWithOffsets>, String> {
+    let encoding = tokenizer.encode_char_offsets(text, false).map_err(|e| format!("tokenizer failed: {}", e))?;
+    let chars: Vec<char> = text.chars().collect();
+    Ok(encoding.get_ids().iter().zip(encoding.get_offsets().iter()).map(|(id, (start, end))| {
+        let (start, end) = ((*start).min(chars.len()), (*end).min(chars.len()));
+        TokenWithOffsets {
+            id: *id,
+            text: chars[start..end.max(start)].iter().collect(),
+            start,
+            end,
+        }
+    }).collect())
+}
+
+pub async fn handle_v1_tokenize(
+    Extension(gcx): Extension<SharedGlobalContext>,
+    body_bytes: hyper::body::Bytes,
+) -> Result<Response<Body>, ScratchError> {
+    let post = serde_json::from_slice::<TokenizePost>(&body_bytes).map_err(|e|
+        ScratchError::new(StatusCode::UNPROCESSABLE_ENTITY, format!("JSON problem: {}", e))
+    )?;
+    let caps = try_load_caps_quickly_if_not_present(gcx.clone(), 0).await?;
+    let model_name = if post.model.is_empty() {
+        caps.read().unwrap().code_chat_default_model.clone()
+    } else {
+        post.model.clone()
+    };
+    if model_name.is_empty() {
+        return Err(ScratchError::new(StatusCode::BAD_REQUEST, "no `model` given and there's no default chat model".to_string()));
+    }
+    let tokenizer = crate::cached_tokenizers::cached_tokenizer(caps.clone(), gcx.clone(), model_name.clone()).await.map_err(|e|
+        ScratchError::new(StatusCode::EXPECTATION_FAILED, format!("no tokenizer for model {}: {}", model_name, e))
+    )?;
+    let tokens = tokenize_with_offsets(&tokenizer.read().unwrap(), &post.text).map_err(|e|
+        ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, e)
+    )?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(json!({"model": model_name, "count": tokens.len(), "tokens": tokens}).to_string()))
+        .unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use super::*;
+
+    const DUMMY_TOKENIZER: &str = include_str!("../../../ast/dummy_tokenizer.json");
+
+    #[test]
+    fn test_tokenize_offsets_cover_input() {
+        let tokenizer = Tokenizer::from_str(DUMMY_TOKENIZER).unwrap();
+        let text = "def f(x):\n    return x+1";
+        let tokens = tokenize_with_offsets(&tokenizer, text).unwrap();
+        assert!(!tokens.is_empty());
+        assert_eq!(tokens[0].start, 0);
+        assert_eq!(tokens.last().unwrap().end, text.chars().count());
+        for pair in tokens.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start, "{:?}", tokens);
+        }
+        assert_eq!(tokens.iter().map(|t| t.text.clone()).collect::<String>(), text);
+        assert_eq!(tokens[0], TokenWithOffsets { id: 3, text: "d".to_string(), start: 0, end: 1 });
+    }
+}
@@ -1,8 +1,12 @@
 use std::future::Future;
 use std::pin::Pin;
-use tracing::{info, error};
+use std::task::{Context, Poll};
+use futures::Stream;
+use tracing::{info, error, Instrument};
 use axum::Extension;
-use axum::http::{Method, Uri};
+use axum::extract::State;
+use axum::http::{HeaderName, HeaderValue, Method, Request, Uri};
+use axum::middleware::Next;
 use hyper::{Body, Response};
 use crate::custom_error::ScratchError;
 use crate::global_context::SharedGlobalContext;
@@ -22,7 +26,10 @@ pub async fn telemetry_wrapper(func: impl Fn(Extension<SharedGlobalContext>, hyp
     if !spam {
         info!("\n--- HTTP {} starts ---\n", handler_name);
     }
+    // pings and status polling don't count as activity, otherwise an open IDE keeps us alive forever
+    let activity_guard = if !spam { Some(ex.read().await.activity.request_started()) } else { None };
     let t0 = std::time::Instant::now();
+    let latency_timer = crate::metrics::RequestLatencyTimer::start(path.path());
     let result = Box::pin(func(ex.clone(), body_bytes)).await;
     if let Err(e) = result {
         if !e.telemetry_skip {
@@ -41,7 +48,68 @@ pub async fn telemetry_wrapper(func: impl Fn(Extension<SharedGlobalContext>, hyp
     if !spam {
         info!("{} completed {}ms", path, t0.elapsed().as_millis());
     }
-    return Ok(result.unwrap());
+    return Ok(response_with_guard(result.unwrap(), (activity_guard, latency_timer)));
+}
+
+// Streaming handlers return long before the body is sent, the guard lives until the body ends or the client goes away
+pub struct BodyWithGuard<G> {
+    body: Body,
+    guard: Option<G>,
+}
+
+impl<G: Unpin> Stream for BodyWithGuard<G> {
+    type Item = Result<hyper::body::Bytes, hyper::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let polled = Pin::new(&mut self.body).poll_next(cx);
+        if let Poll::Ready(None) = polled {
+            self.guard.take();
+        }
+        polled
+    }
+}
+
+pub fn response_with_guard<G: Send + Unpin + 'static>(response: Response<Body>, guard: G) -> Response<Body> {
+    let (parts, body) = response.into_parts();
+    Response::from_parts(parts, Body::wrap_stream(BodyWithGuard { body, guard: Some(guard) }))
+}
+
+tokio::task_local! {
+    // (header name, request id) of the HTTP request being handled
+    static REQUEST_ID: (String, String);
+}
+
+const REQUEST_ID_MAX_LEN: usize = 128;
+
+pub fn request_id_header() -> Option<(String, String)> {
+    REQUEST_ID.try_with(|x| x.clone()).ok()
+}
+
+// Streams are polled by hyper after the handler returns, outside of the middleware, they carry the id over with this
+pub async fn with_request_id<F: Future>(request_id: Option<(String, String)>, fut: F) -> F::Output {
+    match request_id {
+        Some(request_id) => REQUEST_ID.scope(request_id, fut).await,
+        None => fut.await,
+    }
+}
+
+// Takes the id from the gateway or makes a new one, puts it into the logs and the response, forwarders send it upstream
+pub async fn request_id_middleware(
+    State(header_name): State<HeaderName>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> axum::response::Response {
+    let request_id = req.headers().get(&header_name)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty() && s.len() <= REQUEST_ID_MAX_LEN)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let span = tracing::info_span!("http", request_id = %request_id);
+    let mut response = REQUEST_ID.scope((header_name.to_string(), request_id.clone()), next.run(req).instrument(span)).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(header_name, value);
+    }
+    response
 }
 
 #[macro_export]
@@ -73,3 +141,65 @@ macro_rules! telemetry_get {
         })
     };
 }
+
+// For GET handlers that take their arguments from the query string: $name(Extension, Query<T>), T: Clone
+#[macro_export]
+macro_rules! telemetry_get_query {
+    (
+        $name:ident
+    ) => {
+        get(|path, method, ex, query, body_bytes| async {
+            let tmp = move |ex: Extension<SharedGlobalContext>, _body_bytes: hyper::body::Bytes|
+            -> Pin<Box<dyn Future<Output=Result<Response<Body>, ScratchError>> + Send>> {
+                Box::pin($name(ex, Clone::clone(&query)))
+            };
+            telemetry_wrapper(tmp, path, method, ex, body_bytes).await
+        })
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use crate::idle_shutdown::ActivityTracker;
+
+    #[tokio::test]
+    async fn test_open_stream_keeps_activity() {
+        let tracker = Arc::new(ActivityTracker::new(Instant::now()));
+        let (mut sender, body) = Body::channel();
+        let response = response_with_guard(Response::new(body), tracker.request_started());
+        // the handler has returned, the stream is still open
+        let far_future = || Instant::now() + Duration::from_secs(3600);
+        assert!(!tracker.is_idle(far_future(), Duration::from_secs(60)));
+
+        let reader = tokio::spawn(async move { hyper::body::to_bytes(response.into_body()).await.unwrap() });
+        sender.send_data(hyper::body::Bytes::from("data: {}\n\n")).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!tracker.is_idle(far_future(), Duration::from_secs(60)));
+
+        drop(sender);
+        assert_eq!(reader.await.unwrap(), "data: {}\n\n");
+        assert!(tracker.is_idle(far_future(), Duration::from_secs(60)));
+    }
+
+    #[tokio::test]
+    async fn test_stream_latency_recorded_at_end_of_body() {
+        let handler = "/v1/test-stream-latency";
+        let (mut sender, body) = Body::channel();
+        let response = response_with_guard(Response::new(body), crate::metrics::RequestLatencyTimer::start(handler));
+        let observed = || crate::metrics::METRICS.request_latency.lock().unwrap().get(handler).map(|h| (h.count, h.sum));
+        assert_eq!(observed(), None);
+
+        let reader = tokio::spawn(async move { hyper::body::to_bytes(response.into_body()).await.unwrap() });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        sender.send_data(hyper::body::Bytes::from("data: [DONE]\n\n")).await.unwrap();
+        assert_eq!(observed(), None);
+        drop(sender);
+        reader.await.unwrap();
+        let (count, sum) = observed().unwrap();
+        assert_eq!(count, 1);
+        assert!(sum >= 0.1, "{}", sum);
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use axum::Extension;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::response::IntoResponse;
+use futures::{Future, SinkExt, StreamExt};
+use hyper::{Body, Response, StatusCode};
+use hyper::body::HttpBody;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::custom_error::ScratchError;
+use crate::global_context::SharedGlobalContext;
+
+
+// One socket carries many requests, every frame has request_id:
+//   client -> {"request_id": "r1", "kind": "chat", "body": {...same as POST /v1/chat...}}
+//   client -> {"request_id": "r1", "cancel": true}
+//   server -> {"request_id": "r1", "delta": {...same as SSE data...}}  many times
+//   server -> {"request_id": "r1", "response": {...}}                   for non-streaming requests
+//   server -> {"request_id": "r1", "error": "..."}
+//   server -> {"request_id": "r1", "done": true}                        always last, unless cancelled
+//   server -> {"request_id": "r1", "cancelled": true}
+
+pub type WsDispatchFuture = Pin<Box<dyn Future<Output = Result<Response<Body>, ScratchError>> + Send>>;
+pub type WsDispatch = Arc<dyn Fn(String, hyper::body::Bytes) -> WsDispatchFuture + Send + Sync>;
+
+#[derive(Deserialize)]
+struct WsRequestFrame {
+    request_id: String,
+    #[serde(default)]
+    kind: String,
+    #[serde(default)]
+    body: Value,
+    #[serde(default)]
+    cancel: bool,
+}
+
+pub async fn handle_ws(
+    ws: WebSocketUpgrade,
+    Extension(gcx): Extension<SharedGlobalContext>,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| ws_connection(socket, gcx))
+}
+
+fn dispatch_using_http_handlers(gcx: SharedGlobalContext) -> WsDispatch {
+    Arc::new(move |kind: String, body_bytes: hyper::body::Bytes| -> WsDispatchFuture {
+        let gcx = gcx.clone();
+        Box::pin(async move {
+            let activity_guard = gcx.read().await.activity.request_started();
+            let response = match kind.as_str() {
+                "chat" => crate::http::routers::v1::chat::handle_v1_chat(Extension(gcx), body_bytes).await,
+                "chat_completions" => crate::http::routers::v1::chat::handle_v1_chat_completions(Extension(gcx), body_bytes).await,
+                "completion" => crate::http::routers::v1::code_completion::handle_v1_code_completion_web(Extension(gcx), body_bytes).await,
+                _ => Err(ScratchError::new(StatusCode::BAD_REQUEST, format!("unknown request kind {:?}, use chat, chat_completions or completion", kind))),
+            }?;
+            Ok(crate::http::utils::response_with_guard(response, activity_guard))
+        })
+    })
+}
+
+async fn ws_connection(socket: WebSocket, gcx: SharedGlobalContext) {
+    let (mut ws_tx, mut ws_rx) = socket.split();
+    let (incoming_tx, incoming_rx) = mpsc::unbounded_channel::<String>();
+    let (outgoing_tx, mut outgoing_rx) = mpsc::unbounded_channel::<String>();
+    info!("websocket connected");
+
+    let writer = tokio::spawn(async move {
+        while let Some(text) = outgoing_rx.recv().await {
+            if ws_tx.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+    let session = tokio::spawn(ws_session(incoming_rx, outgoing_tx, dispatch_using_http_handlers(gcx)));
+
+    while let Some(msg) = ws_rx.next().await {
+        match msg {
+            Ok(Message::Text(text)) => { let _ = incoming_tx.send(text); },
+            Ok(Message::Close(_)) | Err(_) => break,
+            Ok(_) => {},
+        }
+    }
+    drop(incoming_tx);
+    let _ = session.await;
+    writer.abort();
+    info!("websocket disconnected");
+}
+
+pub async fn ws_session(
+    mut incoming: mpsc::UnboundedReceiver<String>,
+    outgoing: mpsc::UnboundedSender<String>,
+    dispatch: WsDispatch,
+) {
+    let mut running: HashMap<String, JoinHandle<()>> = HashMap::new();
+    while let Some(text) = incoming.recv().await {
+        running.retain(|_, handle| !handle.is_finished());
+        let frame = match serde_json::from_str::<WsRequestFrame>(&text) {
+            Ok(frame) => frame,
+            Err(e) => {
+                let _ = outgoing.send(json!({"request_id": "", "error": format!("JSON problem: {}", e)}).to_string());
+                continue;
+            }
+        };
+        if frame.cancel {
+            if let Some(handle) = running.remove(&frame.request_id) {
+                handle.abort();
+                let _ = outgoing.send(json!({"request_id": frame.request_id, "cancelled": true}).to_string());
+            }
+            continue;
+        }
+        if running.contains_key(&frame.request_id) {
+            let _ = outgoing.send(json!({"request_id": frame.request_id, "error": "request_id is already in use"}).to_string());
+            continue;
+        }
+        let handle = tokio::spawn(ws_run_request(frame.request_id.clone(), frame.kind, frame.body, outgoing.clone(), dispatch.clone()));
+        running.insert(frame.request_id, handle);
+    }
+    for (_, handle) in running {
+        handle.abort();
+    }
+}
+
+async fn ws_run_request(
+    request_id: String,
+    kind: String,
+    body: Value,
+    outgoing: mpsc::UnboundedSender<String>,
+    dispatch: WsDispatch,
+) {
+    let body_bytes = hyper::body::Bytes::from(serde_json::to_vec(&body).unwrap_or_default());
+    let mut response_body = match dispatch(kind, body_bytes).await {
+        Ok(response) => response.into_body(),
+        Err(e) => {
+            let _ = outgoing.send(json!({"request_id": request_id, "error": e.message}).to_string());
+            let _ = outgoing.send(json!({"request_id": request_id, "done": true}).to_string());
+            return;
+        }
+    };
+
+    let mut pending = String::new();
+    let mut saw_sse = false;
+    while let Some(chunk) = response_body.data().await {
+        match chunk {
+            Ok(bytes) => {
+                pending.push_str(&String::from_utf8_lossy(&bytes));
+                for frame in sse_drain_frames(&request_id, &mut pending, &mut saw_sse) {
+                    let _ = outgoing.send(frame.to_string());
+                }
+            }
+            Err(e) => {
+                warn!("websocket request {} body error: {}", request_id, e);
+                let _ = outgoing.send(json!({"request_id": request_id, "error": e.to_string()}).to_string());
+                break;
+            }
+        }
+    }
+    if !saw_sse && !pending.trim().is_empty() {
+        let response: Value = serde_json::from_str(&pending).unwrap_or(Value::String(pending.clone()));
+        let _ = outgoing.send(json!({"request_id": request_id, "response": response}).to_string());
+    }
+    let _ = outgoing.send(json!({"request_id": request_id, "done": true}).to_string());
+}
+
+pub fn sse_drain_frames(request_id: &str, pending: &mut String, saw_sse: &mut bool) -> Vec<Value> {
+    // restream produces "data: {json}\n\n" events, non-streaming handlers produce plain json, keep that in pending
+    let mut frames = vec![];
+    if !*saw_sse && !pending.starts_with("data:") {
+        return frames;
+    }
+    *saw_sse = true;
+    while let Some(pos) = pending.find("\n\n") {
+        let event = pending[..pos].to_string();
+        pending.drain(..pos + 2);
+        for line in event.lines() {
+            let data = match line.strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => continue,
+            };
+            if data == "[DONE]" || data.is_empty() {
+                continue;
+            }
+            let delta: Value = serde_json::from_str(data).unwrap_or(Value::String(data.to_string()));
+            frames.push(json!({"request_id": request_id, "delta": delta}));
+        }
+    }
+    frames
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn recv_frame(rx: &mut mpsc::UnboundedReceiver<String>) -> Value {
+        let text = tokio::time::timeout(tokio::time::Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        serde_json::from_str(&text).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ws_chat_streams_deltas_then_done() {
+        let dispatch: WsDispatch = Arc::new(|kind: String, _body: hyper::body::Bytes| -> WsDispatchFuture {
+            Box::pin(async move {
+                assert_eq!(kind, "chat");
+                let sse = "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n\
+                           data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n\
+                           data: [DONE]\n\n";
+                Ok(Response::builder().body(Body::from(sse)).unwrap())
+            })
+        });
+        let (in_tx, in_rx) = mpsc::unbounded_channel::<String>();
+        let (out_tx, mut out_rx) = mpsc::unbounded_channel::<String>();
+        let session = tokio::spawn(ws_session(in_rx, out_tx, dispatch));
+
+        in_tx.send(json!({"request_id": "r1", "kind": "chat", "body": {"messages": []}}).to_string()).unwrap();
+        let f1 = recv_frame(&mut out_rx).await;
+        let f2 = recv_frame(&mut out_rx).await;
+        let f3 = recv_frame(&mut out_rx).await;
+        assert_eq!(f1["request_id"], "r1");
+        assert_eq!(f1["delta"]["choices"][0]["delta"]["content"], "Hel");
+        assert_eq!(f2["delta"]["choices"][0]["delta"]["content"], "lo");
+        assert_eq!(f3, json!({"request_id": "r1", "done": true}));
+
+        drop(in_tx);
+        session.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ws_cancel_aborts_matching_request() {
+        let dispatch: WsDispatch = Arc::new(|_kind: String, _body: hyper::body::Bytes| -> WsDispatchFuture {
+            Box::pin(async move {
+                let (mut sender, body) = Body::channel();
+                tokio::spawn(async move {
+                    let _ = sender.send_data(hyper::body::Bytes::from("data: {\"n\":1}\n\n")).await;
+                    tokio::time::sleep(tokio::time::Duration::from_secs(3600)).await;
+                });
+                Ok(Response::builder().body(body).unwrap())
+            })
+        });
+        let (in_tx, in_rx) = mpsc::unbounded_channel::<String>();
+        let (out_tx, mut out_rx) = mpsc::unbounded_channel::<String>();
+        let _session = tokio::spawn(ws_session(in_rx, out_tx, dispatch));
+
+        in_tx.send(json!({"request_id": "slow", "kind": "chat", "body": {}}).to_string()).unwrap();
+        let f1 = recv_frame(&mut out_rx).await;
+        assert_eq!(f1["delta"]["n"], 1);
+        in_tx.send(json!({"request_id": "slow", "cancel": true}).to_string()).unwrap();
+        let f2 = recv_frame(&mut out_rx).await;
+        assert_eq!(f2, json!({"request_id": "slow", "cancelled": true}));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_completion_keeps_activity_until_body_ends() {
+        // the model answers token by token, as fast as the test says
+        let (upstream_tx, upstream_rx) = mpsc::unbounded_channel::<String>();
+        let upstream_rx = Arc::new(tokio::sync::Mutex::new(Some(upstream_rx)));
+        let router = axum::Router::new().route("/v1/completions", axum::routing::post(move || {
+            let upstream_rx = upstream_rx.clone();
+            async move {
+                let rx = upstream_rx.lock().await.take().unwrap();
+                let events = futures::stream::unfold(rx, |mut rx| async move {
+                    rx.recv().await.map(|event| (Ok::<_, std::convert::Infallible>(event), rx))
+                });
+                Response::builder().header("Content-Type", "text/event-stream").body(Body::wrap_stream(events)).unwrap()
+            }
+        }));
+        let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0))).serve(router.into_make_service());
+        let url = format!("http://{}/v1/completions", server.local_addr());
+        tokio::spawn(server);
+
+        let dir = tempfile::tempdir().unwrap();
+        let gcx = crate::global_context::create_test_global_context_with_fim_model(dir.path(), &url, &[]).await;
+        let file = dir.path().join("f.py").to_string_lossy().to_string();
+        let body = json!({
+            "inputs": {
+                "sources": {file.clone(): "def f():\n    \n"},
+                "cursor": {"file": file, "line": 1, "character": 4},
+                "multiline": true,
+            },
+            "stream": true,
+            "no_cache": true,
+        });
+        let activity = gcx.read().await.activity.clone();
+        let is_idle = || activity.is_idle(std::time::Instant::now() + std::time::Duration::from_secs(3600), std::time::Duration::from_secs(60));
+
+        let dispatch = dispatch_using_http_handlers(gcx.clone());
+        let response = dispatch("completion".to_string(), hyper::body::Bytes::from(body.to_string())).await.unwrap();
+        // the handler has returned, the completion is still streaming
+        assert!(!is_idle());
+        let mut response_body = response.into_body();
+        upstream_tx.send(format!("data: {}\n\n", json!({"choices": [{"index": 0, "text": "tok", "finish_reason": null}]}))).unwrap();
+        loop {
+            let chunk = tokio::time::timeout(std::time::Duration::from_secs(3), response_body.data()).await.unwrap().unwrap().unwrap();
+            if String::from_utf8_lossy(&chunk).contains("tok") {
+                break;
+            }
+        }
+        assert!(!is_idle());
+
+        upstream_tx.send(format!("data: {}\n\n", json!({"choices": [{"index": 0, "text": "", "finish_reason": "stop"}]}))).unwrap();
+        upstream_tx.send("data: [DONE]\n\n".to_string()).unwrap();
+        drop(upstream_tx);
+        while let Some(chunk) = tokio::time::timeout(std::time::Duration::from_secs(3), response_body.data()).await.unwrap() {
+            chunk.unwrap();
+        }
+        assert!(is_idle());
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::{Arc, Mutex as StdMutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock as ARwLock;
+use tracing::{info, warn};
+
+use crate::global_context::GlobalContext;
+
+
+const IDLE_CHECK_EVERY: Duration = Duration::from_secs(30);
+
+pub struct ActivityTracker {
+    last_activity: StdMutex<Instant>,
+    in_flight: AtomicUsize,
+}
+
+// Held while a request runs, a request that is still running is never idle
+pub struct ActivityGuard {
+    tracker: Arc<ActivityTracker>,
+}
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        self.tracker.touch(Instant::now());
+        self.tracker.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ActivityTracker {
+    pub fn new(now: Instant) -> Self {
+        ActivityTracker {
+            last_activity: StdMutex::new(now),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn touch(&self, now: Instant) {
+        let mut last_activity = self.last_activity.lock().unwrap();
+        if now > *last_activity {
+            *last_activity = now;
+        }
+    }
+
+    pub fn request_started(self: &Arc<Self>) -> ActivityGuard {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        self.touch(Instant::now());
+        ActivityGuard { tracker: self.clone() }
+    }
+
+    pub fn is_idle(&self, now: Instant, idle_timeout: Duration) -> bool {
+        if self.in_flight.load(Ordering::SeqCst) > 0 {
+            return false;
+        }
+        let last_activity = *self.last_activity.lock().unwrap();
+        now.saturating_duration_since(last_activity) >= idle_timeout
+    }
+}
+
+pub fn idle_shutdown_check(
+    tracker: &ActivityTracker,
+    now: Instant,
+    idle_timeout: Duration,
+    ask_shutdown_sender: &StdMutex<std::sync::mpsc::Sender<String>>,
+) -> bool {
+    if !tracker.is_idle(now, idle_timeout) {
+        return false;
+    }
+    info!("no activity for {}s, asking for shutdown", idle_timeout.as_secs());
+    if let Err(e) = ask_shutdown_sender.lock().unwrap().send("idle-shutdown".to_string()) {
+        // LSP-only mode doesn't listen to ask_shutdown_receiver, see FIXME in main.rs
+        warn!("idle shutdown signal not delivered: {}, exiting", e);
+        std::process::exit(0);
+    }
+    true
+}
+
+pub async fn idle_shutdown_background_task(gcx: Arc<ARwLock<GlobalContext>>) {
+    let (idle_minutes, tracker, ask_shutdown_sender) = {
+        let gcx_locked = gcx.read().await;
+        (gcx_locked.cmdline.idle_shutdown_minutes, gcx_locked.activity.clone(), gcx_locked.ask_shutdown_sender.clone())
+    };
+    if idle_minutes == 0 {
+        return;
+    }
+    let idle_timeout = Duration::from_secs(idle_minutes * 60);
+    loop {
+        tokio::time::sleep(IDLE_CHECK_EVERY).await;
+        if idle_shutdown_check(&tracker, Instant::now(), idle_timeout, &ask_shutdown_sender) {
+            return;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_past_threshold_triggers_shutdown() {
+        let t0 = Instant::now();
+        let tracker = Arc::new(ActivityTracker::new(t0));
+        let (sender, receiver) = std::sync::mpsc::channel::<String>();
+        let sender = StdMutex::new(sender);
+        let idle_timeout = Duration::from_secs(10 * 60);
+
+        assert!(!idle_shutdown_check(&tracker, t0 + Duration::from_secs(9 * 60), idle_timeout, &sender));
+        assert!(receiver.try_recv().is_err());
+
+        // a long request in flight keeps the process alive past the threshold
+        let guard = tracker.request_started();
+        assert!(!idle_shutdown_check(&tracker, t0 + Duration::from_secs(60 * 60), idle_timeout, &sender));
+        assert!(receiver.try_recv().is_err());
+        drop(guard);
+
+        let t1 = Instant::now();
+        assert!(!idle_shutdown_check(&tracker, t1 + Duration::from_secs(5 * 60), idle_timeout, &sender));
+        assert!(idle_shutdown_check(&tracker, t1 + Duration::from_secs(11 * 60), idle_timeout, &sender));
+        assert_eq!(receiver.try_recv().unwrap(), "idle-shutdown");
+    }
+}
@@ -29,13 +29,17 @@ use headless_chrome::protocol::cdp::Emulation;
 use headless_chrome::protocol::cdp::types::Event;
 use headless_chrome::protocol::cdp::DOM::Enable as DOMEnable;
 use headless_chrome::protocol::cdp::CSS::Enable as CSSEnable;
+use headless_chrome::protocol::cdp::Accessibility;
+use headless_chrome::protocol::cdp::Network;
+use headless_chrome::protocol::cdp::Browser as CdpBrowser;
 use serde::{Deserialize, Serialize};
 
 use base64::Engine;
 use std::io::Cursor;
+use headless_chrome::protocol::cdp::Runtime;
 use headless_chrome::protocol::cdp::Runtime::RemoteObject;
 use image::imageops::FilterType;
-use image::{ImageFormat, ImageReader};
+use image::{DynamicImage, ImageFormat, ImageReader};
 
 
 #[derive(Clone, Serialize, Deserialize, Debug, Default)]
@@ -66,6 +70,10 @@ pub struct SettingsChrome {
     pub tablet_window_height: String,
     #[serde(default)]
     pub tablet_scale_factor: String,
+    #[serde(default)]
+    pub screenshot_full_resolution: bool,
+    #[serde(default)]
+    pub max_images_per_message: String,
 }
 
 #[derive(Default)]
@@ -102,6 +110,7 @@ pub struct ChromeTab {
     tab_id: String,
     screenshot_scale_factor: f64,
     tab_log: Arc<Mutex<Vec<String>>>,
+    isolated_world_context_id: Option<u32>,  // for eval_isolated, gone after a navigation
 }
 
 impl ChromeTab {
@@ -112,6 +121,7 @@ impl ChromeTab {
             tab_id: tab_id.clone(),
             screenshot_scale_factor: 1.0,
             tab_log: Arc::new(Mutex::new(Vec::new())),
+            isolated_world_context_id: None,
         }
     }
     pub fn state_string(&self) -> String {
@@ -213,6 +223,7 @@ impl Tool for ToolChrome {
             Some(v) => return Err(format!("argument `commands` is not a string: {:?}", v)),
             None => return Err("Missing argument `commands`".to_string())
         };
+        let on_error = OnError::from_arg(args.get("on_error"))?;
 
         let session_hashmap_key = get_session_hashmap_key("chrome", &chat_id);
         let mut tool_log = setup_chrome_session(gcx.clone(), &self.settings_chrome, &session_hashmap_key).await?;
@@ -224,25 +235,21 @@ impl Tool for ToolChrome {
                 .clone()
         };
 
-        let mut mutlimodal_els = vec![];
-        for command in commands_str.lines().map(|s| s.trim()).collect::<Vec<&str>>() {
-            let parsed_command = match parse_single_command(&command.to_string()) {
-                Ok(command) => command,
-                Err(e) => {
-                    tool_log.push(format!("Failed to parse command `{}`: {}.", command, e));
-                    break
-                }
-            };
-            match chrome_command_exec(&parsed_command, command_session.clone(), &self.settings_chrome, gcx.clone(), &chat_id).await {
-                Ok((execute_log, command_multimodal_els)) => {
-                    tool_log.extend(execute_log);
-                    mutlimodal_els.extend(command_multimodal_els);
-                },
-                Err(e) => {
-                    tool_log.push(format!("Failed to execute command `{}`: {}.", command, e));
-                    break
-                }
-            };
+        let settings_chrome = &self.settings_chrome;
+        let chat_id = &chat_id;
+        let (batch_log, mutlimodal_els) = run_command_batch(commands_str, on_error, |parsed_command| {
+            let command_session = command_session.clone();
+            let gcx = gcx.clone();
+            async move {
+                chrome_command_exec(&parsed_command, command_session, settings_chrome, gcx, chat_id).await
+            }
+        }).await;
+        tool_log.extend(batch_log);
+
+        let max_images = settings_chrome.max_images_per_message.parse::<usize>().unwrap_or(MAX_IMAGES_PER_MESSAGE_DEFAULT);
+        let (mutlimodal_els, dropped) = keep_last_images(mutlimodal_els, max_images);
+        if dropped > 0 {
+            tool_log.push(format!("{} older screenshots were dropped, only the last {} are attached (max_images_per_message).", dropped, max_images));
         }
 
         let mut content= vec![];
@@ -265,18 +272,28 @@ impl Tool for ToolChrome {
     fn tool_description(&self) -> ToolDesc {
         let mut supported_commands = vec![
             "open_tab <tab_id> <desktop|mobile|tablet>",
-            "navigate_to <tab_id> <uri>",
+            "navigate_to <tab_id> <uri> [--wait-for <element_selector>]  (after loading, waits up to 10 seconds for the element, good for single page apps)",
             "scroll_to <tab_id> <element_selector>",
             "screenshot <tab_id>",
+            "screenshot_fullpage <tab_id>",
             "html <tab_id> <element_selector>",
+            "get_html <tab_id> [<element_selector>] [--no-scripts]  (live outerHTML, the whole page without a selector, --no-scripts empties script and style tags)",
             "reload <tab_id>",
             "press_key <tab_id> <KeyName> [<Alt|Ctrl|Meta|Shift>,...]",
             "type_text_at <tab_id> <text>",
+            "fill_form <tab_id> <json>  (a JSON object of element_selector to value, for example '{\"#email\": \"a@b.c\", \"#agree\": true, \"select[name=country]\": \"DE\"}', works with text inputs, textareas, selects, checkboxes and radios)",
             "tab_log <tab_id>",
             "eval <tab_id> <expression>",
+            "eval_isolated <tab_id> <expression>",
             "styles <tab_id> <element_selector> <property_filter>",
             "wait_for <tab_id> <1-5>",
             "click_at_element <tab_id> <element_selector>",
+            "a11y_tree <tab_id> [<element_selector>]",
+            "throttle_network <tab_id> <offline|slow3g|fast3g|none>",
+            "clear_tab_state <tab_id>",
+            "set_geolocation <tab_id> <latitude> <longitude> [<accuracy_meters>]",
+            "set_timezone <tab_id> <IANA timezone, for example Europe/Berlin>",
+            "set_locale <tab_id> <language tag, for example de-DE>  (Accept-Language and navigator.language, applies to navigations after it)",
         ];
         if self.supports_clicks {
             supported_commands.extend(vec![
@@ -297,6 +314,10 @@ impl Tool for ToolChrome {
                 name: "commands".to_string(),
                 param_type: "string".to_string(),
                 description,
+            }, ToolParam {
+                name: "on_error".to_string(),
+                param_type: "string".to_string(),
+                description: "What to do when a command fails: `stop` skips the rest of the commands, `continue` runs them anyway. Default is `stop`.".to_string(),
             }],
             parameters_required: vec!["commands".to_string()],
         }
@@ -312,6 +333,77 @@ impl Tool for ToolChrome {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OnError {
+    Stop,
+    Continue,
+}
+
+impl OnError {
+    fn from_arg(arg: Option<&Value>) -> Result<Self, String> {
+        match arg {
+            None | Some(Value::Null) => Ok(OnError::Stop),
+            Some(Value::String(s)) => match s.trim() {
+                "" | "stop" => Ok(OnError::Stop),
+                "continue" => Ok(OnError::Continue),
+                other => Err(format!("argument `on_error` should be `stop` or `continue`, got {:?}", other)),
+            },
+            Some(v) => Err(format!("argument `on_error` is not a string: {:?}", v)),
+        }
+    }
+}
+
+const MAX_IMAGES_PER_MESSAGE_DEFAULT: usize = 4;
+
+// Every image costs the model a lot of tokens, a long batch of screenshots keeps only the most recent ones
+fn keep_last_images(els: Vec<MultimodalElement>, max_images: usize) -> (Vec<MultimodalElement>, usize) {
+    let images_n = els.iter().filter(|el| el.is_image()).count();
+    let mut to_drop = images_n.saturating_sub(max_images);
+    let dropped = to_drop;
+    let kept = els.into_iter().filter(|el| {
+        if to_drop > 0 && el.is_image() {
+            to_drop -= 1;
+            return false;
+        }
+        true
+    }).collect();
+    (kept, dropped)
+}
+
+// A command that can't be parsed or fails is logged, then the batch either ends there or moves on to the next command
+async fn run_command_batch<F, Fut>(
+    commands_str: &str,
+    on_error: OnError,
+    mut exec: F,
+) -> (Vec<String>, Vec<MultimodalElement>)
+where
+    F: FnMut(Command) -> Fut,
+    Fut: Future<Output = Result<(Vec<String>, Vec<MultimodalElement>), String>>,
+{
+    let mut tool_log = vec![];
+    let mut mutlimodal_els = vec![];
+    for command in commands_str.lines().map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let result = match parse_single_command(&command.to_string()) {
+            Ok(parsed_command) => exec(parsed_command).await
+                .map_err(|e| format!("Failed to execute command `{}`: {}.", command, e)),
+            Err(e) => Err(format!("Failed to parse command `{}`: {}.", command, e)),
+        };
+        match result {
+            Ok((execute_log, command_multimodal_els)) => {
+                tool_log.extend(execute_log);
+                mutlimodal_els.extend(command_multimodal_els);
+            },
+            Err(e) => {
+                tool_log.push(e);
+                if on_error == OnError::Stop {
+                    break
+                }
+            }
+        }
+    }
+    (tool_log, mutlimodal_els)
+}
+
 async fn setup_chrome_session(
     gcx: Arc<ARwLock<GlobalContext>>,
     args: &SettingsChrome,
@@ -393,10 +485,38 @@ async fn setup_chrome_session(
     Ok(setup_log)
 }
 
+// full page screenshots keep the width readable, pages taller than this many widths get cut
+const FULLPAGE_MAX_HEIGHT_IN_WIDTHS: u32 = 4;
+
+// Returns the image to send and the scale factor clicks should be divided by, None if the tab keeps its current one
+fn screenshot_downscale(
+    image: DynamicImage,
+    capture_beyond_viewport: bool,
+    full_resolution: bool,
+) -> (DynamicImage, Option<f64>) {
+    if full_resolution {
+        return (image, Some(1.0));
+    }
+    let max_dimension = 800.0;
+    let scale_factor = if capture_beyond_viewport {
+        max_dimension / image.width() as f32
+    } else {
+        max_dimension / std::cmp::max(image.width(), image.height()) as f32
+    };
+    if scale_factor < 1.0 {
+        // NOTE: the tool operates on resized image well without a special model notification
+        let (nwidth, nheight) = (scale_factor * image.width() as f32, scale_factor * image.height() as f32);
+        (image.resize(nwidth as u32, nheight as u32, FilterType::Lanczos3), Some(scale_factor as f64))
+    } else {
+        (image, None)
+    }
+}
+
 async fn screenshot_jpeg_base64(
     tab: Arc<AMutex<ChromeTab>>,
     capture_beyond_viewport: bool,
-) -> Result<MultimodalElement, String> {
+    full_resolution: bool,
+) -> Result<(MultimodalElement, Option<String>), String> {
     let jpeg_base64_data = {
         let tab_lock = tab.lock().await;
         tab_lock.headless_tab.call_method(Page::CaptureScreenshot {
@@ -413,21 +533,28 @@ async fn screenshot_jpeg_base64(
     let reader = ImageReader::with_format(Cursor::new(data), ImageFormat::Jpeg);
     let mut image = reader.decode().map_err(|e| e.to_string())?;
 
-    let max_dimension = 800.0;
-    let scale_factor = max_dimension / std::cmp::max(image.width(), image.height()) as f32;
-    if scale_factor < 1.0 {
-        // NOTE: the tool operates on resized image well without a special model notification
-        let (nwidth, nheight) = (scale_factor * image.width() as f32, scale_factor * image.height() as f32);
-        image = image.resize(nwidth as u32, nheight as u32, FilterType::Lanczos3);
+    let mut note = None;
+    let max_height = image.width() * FULLPAGE_MAX_HEIGHT_IN_WIDTHS;
+    if capture_beyond_viewport && image.height() > max_height {
+        note = Some(format!(
+            "the page is {}px tall, only the top {}px are shown, use scroll_to and screenshot to see the rest",
+            image.height(), max_height
+        ));
+        image = image.crop_imm(0, 0, image.width(), max_height);
+    }
+
+    let (image, scale_factor) = screenshot_downscale(image, capture_beyond_viewport, full_resolution);
+    if let Some(scale_factor) = scale_factor {
         // NOTE: we should store screenshot_scale_factor for every resized screenshot, not for a tab!
         let mut tab_lock = tab.lock().await;
-        tab_lock.screenshot_scale_factor = scale_factor as f64;
+        tab_lock.screenshot_scale_factor = scale_factor;
     }
 
     data = Vec::new();
     image.write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg).map_err(|e| e.to_string())?;
 
-    MultimodalElement::new("image/jpeg".to_string(), base64::prelude::BASE64_STANDARD.encode(data))
+    let multimodal_el = MultimodalElement::new("image/jpeg".to_string(), base64::prelude::BASE64_STANDARD.encode(data))?;
+    Ok((multimodal_el, note))
 }
 
 fn get_inner_html(
@@ -495,6 +622,118 @@ fn get_inner_html(
     Ok(result.value.unwrap().to_string())
 }
 
+fn format_a11y_tree(
+    nodes: &Vec<Value>,
+    root_backend_node_id: Option<u64>,
+    limit_chars: usize,
+) -> Result<String, String> {
+    // nodes are CDP AXNode objects, print "role name" with indentation, nodes that are ignored or
+    // have neither a meaningful role nor a name are skipped and their children go one level up
+    let by_id: HashMap<&str, &Value> = nodes.iter()
+        .filter_map(|n| Some((n.get("nodeId")?.as_str()?, n)))
+        .collect();
+    let root = match root_backend_node_id {
+        Some(backend_id) => nodes.iter()
+            .find(|n| n.get("backendDOMNodeId").and_then(|x| x.as_u64()) == Some(backend_id))
+            .ok_or("element is not in the accessibility tree")?,
+        None => nodes.iter()
+            .find(|n| n.get("parentId").is_none())
+            .ok_or("accessibility tree is empty")?,
+    };
+    let ax_value = |n: &Value, field: &str| -> String {
+        n.get(field).and_then(|v| v.get("value")).map(|v| match v {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }).unwrap_or_default()
+    };
+
+    let mut out = String::new();
+    let mut truncated = false;
+    let mut stack: Vec<(&Value, usize)> = vec![(root, 0)];
+    while let Some((node, depth)) = stack.pop() {
+        let role = ax_value(node, "role");
+        let name = ax_value(node, "name");
+        let ignored = node.get("ignored").and_then(|x| x.as_bool()).unwrap_or(false);
+        let boring = ignored || (name.trim().is_empty() && ["generic", "none", "InlineTextBox", ""].contains(&role.as_str()));
+        let child_depth = if boring { depth } else { depth + 1 };
+        if !boring {
+            let line = if name.trim().is_empty() {
+                format!("{}{}\n", "  ".repeat(depth), role)
+            } else {
+                format!("{}{} {:?}\n", "  ".repeat(depth), role, name.trim())
+            };
+            if out.len() + line.len() > limit_chars {
+                truncated = true;
+                break;
+            }
+            out.push_str(&line);
+        }
+        let child_ids = node.get("childIds").and_then(|x| x.as_array()).cloned().unwrap_or_default();
+        for child_id in child_ids.iter().rev() {
+            if let Some(child) = child_id.as_str().and_then(|id| by_id.get(id)) {
+                stack.push((*child, child_depth));
+            }
+        }
+    }
+    if truncated {
+        out.push_str(&format!("...truncated at {} chars, give a selector to see a part of the page\n", limit_chars));
+    }
+    Ok(out)
+}
+
+const ISOLATED_WORLD_NAME: &str = "refact_agent";
+
+fn isolated_world_context_id(tab: &mut ChromeTab, recreate: bool) -> Result<u32, String> {
+    if let (Some(context_id), false) = (tab.isolated_world_context_id, recreate) {
+        return Ok(context_id);
+    }
+    let frame_tree = tab.headless_tab.call_method(Page::GetFrameTree(None)).map_err(|e| e.to_string())?.frame_tree;
+    let world = tab.headless_tab.call_method(Page::CreateIsolatedWorld {
+        frame_id: frame_tree.frame.id,
+        world_name: Some(ISOLATED_WORLD_NAME.to_string()),
+        grant_univeral_access: Some(false),
+    }).map_err(|e| e.to_string())?;
+    tab.isolated_world_context_id = Some(world.execution_context_id);
+    Ok(world.execution_context_id)
+}
+
+// Same DOM as the page, separate JS globals: page scripts can't see or break what runs here
+fn evaluate_in_isolated_world(tab: &mut ChromeTab, expression: &str) -> Result<RemoteObject, String> {
+    let evaluate = |tab: &ChromeTab, context_id: u32| tab.headless_tab.call_method(Runtime::Evaluate {
+        expression: expression.to_string(),
+        object_group: None,
+        include_command_line_api: None,
+        silent: None,
+        context_id: Some(context_id),
+        return_by_value: None,
+        generate_preview: Some(true),
+        user_gesture: None,
+        await_promise: None,
+        throw_on_side_effect: None,
+        timeout: None,
+        disable_breaks: None,
+        repl_mode: None,
+        allow_unsafe_eval_blocked_by_csp: None,
+        unique_context_id: None,
+        serialization_options: None,
+    });
+    let context_id = isolated_world_context_id(tab, false)?;
+    let evaluated = match evaluate(tab, context_id) {
+        Ok(evaluated) => evaluated,
+        // the page navigated since, the world went away with the old document
+        Err(e) if e.to_string().contains("context") => {
+            let context_id = isolated_world_context_id(tab, true)?;
+            evaluate(tab, context_id).map_err(|e| e.to_string())?
+        },
+        Err(e) => return Err(e.to_string()),
+    };
+    if let Some(exception) = evaluated.exception_details {
+        let description = exception.exception.and_then(|e| e.description).unwrap_or(exception.text);
+        return Err(format!("exception thrown: {}", description));
+    }
+    Ok(evaluated.result)
+}
+
 fn format_remote_object(
     remote_object: &RemoteObject,
 ) -> String {
@@ -625,7 +864,10 @@ enum Command {
     NavigateTo(NavigateToArgs),
     ScrollTo(TabElementArgs),
     Screenshot(TabArgs),
+    ScreenshotFullpage(TabArgs),
     Html(TabElementArgs),
+    GetHtml(GetHtmlArgs),
+    FillForm(FillFormArgs),
     Reload(TabArgs),
     ClickAtPoint(ClickAtPointArgs),
     ClickAtElement(TabElementArgs),
@@ -633,8 +875,15 @@ enum Command {
     PressKey(PressKeyArgs),
     TabLog(TabArgs),
     Eval(EvalArgs),
+    EvalIsolated(EvalArgs),
     Styles(StylesArgs),
     WaitFor(WaitForArgs),
+    A11yTree(A11yTreeArgs),
+    ThrottleNetwork(ThrottleNetworkArgs),
+    ClearTabState(TabArgs),
+    SetGeolocation(SetGeolocationArgs),
+    SetTimezone(SetTimezoneArgs),
+    SetLocale(SetLocaleArgs),
 }
 
 async fn chrome_command_exec(
@@ -677,7 +926,15 @@ async fn chrome_command_exec(
                     Ok::<(), String>(())
                 } {
                     Ok(_) => {
-                        format!("navigate_to successful: {}", tab_lock.state_string())
+                        // SPAs render after the navigation is done, the page is ready when the selector is there
+                        let waited = match &args.wait_for_selector {
+                            Some(selector) => match tab_lock.headless_tab.wait_for_element_with_custom_timeout(selector, NAVIGATE_WAIT_FOR_SELECTOR_TIMEOUT) {
+                                Ok(_) => format!(" Selector `{}` appeared.", selector),
+                                Err(_) => format!(" Selector `{}` didn't appear in {} seconds, the page might be still loading.", selector, NAVIGATE_WAIT_FOR_SELECTOR_TIMEOUT.as_secs()),
+                            },
+                            None => String::new(),
+                        };
+                        format!("navigate_to successful: {}{}", tab_lock.state_string(), waited)
                     },
                     Err(e) => {
                         format!("navigate_to `{}` failed: {}. If you're trying to open a local file, add a file:// prefix.", args.uri, e.to_string())
@@ -717,8 +974,8 @@ async fn chrome_command_exec(
             };
             let log = {
                 // NOTE: this operation is not atomic, unfortunately
-                match screenshot_jpeg_base64(tab.clone(), false).await {
-                    Ok(multimodal_el) => {
+                match screenshot_jpeg_base64(tab.clone(), false, settings_chrome.screenshot_full_resolution).await {
+                    Ok((multimodal_el, _)) => {
                         multimodal_els.push(multimodal_el);
                         let tab_lock = tab.lock().await;
                         format!("Made a screenshot of {}", tab_lock.state_string())
@@ -731,6 +988,30 @@ async fn chrome_command_exec(
             };
             tool_log.push(log);
         },
+        Command::ScreenshotFullpage(args) => {
+            let tab = {
+                let mut chrome_session_locked = chrome_session.lock().await;
+                let chrome_session = chrome_session_locked.as_any_mut().downcast_mut::<ChromeSession>().ok_or("Failed to downcast to ChromeSession")?;
+                session_get_tab_arc(chrome_session, &args.tab_id).await?
+            };
+            let log = {
+                match screenshot_jpeg_base64(tab.clone(), true, settings_chrome.screenshot_full_resolution).await {
+                    Ok((multimodal_el, note)) => {
+                        multimodal_els.push(multimodal_el);
+                        let tab_lock = tab.lock().await;
+                        match note {
+                            Some(note) => format!("Made a full page screenshot of {}, {}", tab_lock.state_string(), note),
+                            None => format!("Made a full page screenshot of {}", tab_lock.state_string()),
+                        }
+                    },
+                    Err(e) => {
+                        let tab_lock = tab.lock().await;
+                        format!("Full page screenshot failed for {}: {}", tab_lock.state_string(), e.to_string())
+                    },
+                }
+            };
+            tool_log.push(log);
+        },
         Command::Html(args) => {
             let tab = {
                 let mut chrome_session_locked = chrome_session.lock().await;
@@ -763,6 +1044,48 @@ async fn chrome_command_exec(
             };
             tool_log.push(log);
         },
+        Command::GetHtml(args) => {
+            let tab = {
+                let mut chrome_session_locked = chrome_session.lock().await;
+                let chrome_session = chrome_session_locked.as_any_mut().downcast_mut::<ChromeSession>().ok_or("Failed to downcast to ChromeSession")?;
+                session_get_tab_arc(chrome_session, &args.tab_id).await?
+            };
+            let log = {
+                let tab_lock = tab.lock().await;
+                let what = match &args.selector {
+                    Some(selector) => format!("`{}`", selector),
+                    None => "the page".to_string(),
+                };
+                match {
+                    let remote_object = tab_lock.headless_tab.evaluate(&get_html_expression(&args.selector, args.strip_scripts), false).map_err(|e| e.to_string())?;
+                    let evaluated = remote_object.value.as_ref().and_then(|v| v.as_str()).unwrap_or_default().to_string();
+                    format_get_html(&what, &evaluated, GET_HTML_MAX_CHARS)
+                } {
+                    Ok(html) => format!("html of {} at {}:\n{}", what, tab_lock.state_string(), html),
+                    Err(e) => format!("get_html failed at {}: {}", tab_lock.state_string(), e),
+                }
+            };
+            tool_log.push(log);
+        },
+        Command::FillForm(args) => {
+            let tab = {
+                let mut chrome_session_locked = chrome_session.lock().await;
+                let chrome_session = chrome_session_locked.as_any_mut().downcast_mut::<ChromeSession>().ok_or("Failed to downcast to ChromeSession")?;
+                session_get_tab_arc(chrome_session, &args.tab_id).await?
+            };
+            let log = {
+                let tab_lock = tab.lock().await;
+                match {
+                    let remote_object = tab_lock.headless_tab.evaluate(&fill_form_expression(&args.fields), false).map_err(|e| e.to_string())?;
+                    let evaluated = remote_object.value.as_ref().and_then(|v| v.as_str()).unwrap_or_default().to_string();
+                    format_fill_form(&evaluated)
+                } {
+                    Ok(report) => format!("fill_form at {}:\n{}", tab_lock.state_string(), report),
+                    Err(e) => format!("fill_form failed at {}: {}", tab_lock.state_string(), e),
+                }
+            };
+            tool_log.push(log);
+        },
         Command::Reload(args) => {
             let tab = {
                 let mut chrome_session_locked = chrome_session.lock().await;
@@ -921,6 +1244,21 @@ async fn chrome_command_exec(
             };
             tool_log.push(log);
         },
+        Command::EvalIsolated(args) => {
+            let tab = {
+                let mut chrome_session_locked = chrome_session.lock().await;
+                let chrome_session = chrome_session_locked.as_any_mut().downcast_mut::<ChromeSession>().ok_or("Failed to downcast to ChromeSession")?;
+                session_get_tab_arc(chrome_session, &args.tab_id).await?
+            };
+            let log = {
+                let mut tab_lock = tab.lock().await;
+                match evaluate_in_isolated_world(&mut tab_lock, &args.expression) {
+                    Ok(remote_object) => format_remote_object(&remote_object),
+                    Err(e) => format!("eval_isolated failed at {}: {}", tab_lock.state_string(), e),
+                }
+            };
+            tool_log.push(log);
+        },
         Command::Styles(args) => {
             let tab = {
                 let mut chrome_session_locked = chrome_session.lock().await;
@@ -959,6 +1297,45 @@ async fn chrome_command_exec(
             };
             tool_log.push(log);
         },
+        Command::A11yTree(args) => {
+            let tab = {
+                let mut chrome_session_locked = chrome_session.lock().await;
+                let chrome_session = chrome_session_locked.as_any_mut().downcast_mut::<ChromeSession>().ok_or("Failed to downcast to ChromeSession")?;
+                session_get_tab_arc(chrome_session, &args.tab_id).await?
+            };
+            let log = {
+                let tab_lock = tab.lock().await;
+                let what = match &args.selector {
+                    Some(selector) => format!("`{}`", selector),
+                    None => "the page".to_string(),
+                };
+                match {
+                    tab_lock.headless_tab.call_method(Accessibility::Enable(None)).map_err(|e| e.to_string())?;
+                    let root_backend_node_id = match &args.selector {
+                        Some(selector) => {
+                            let element = tab_lock.headless_tab.find_element(selector).map_err(|e| e.to_string())?;
+                            Some(element.backend_node_id as u64)
+                        },
+                        None => None,
+                    };
+                    let tree = tab_lock.headless_tab.call_method(Accessibility::GetFullAXTree { depth: None, frame_id: None })
+                        .map_err(|e| e.to_string())?;
+                    let nodes = match serde_json::to_value(&tree).map_err(|e| e.to_string())?.get("nodes") {
+                        Some(Value::Array(nodes)) => nodes.clone(),
+                        _ => vec![],
+                    };
+                    format_a11y_tree(&nodes, root_backend_node_id, 5000)
+                } {
+                    Ok(tree_str) => {
+                        format!("accessibility tree of {} at {}:\n{}", what, tab_lock.state_string(), tree_str)
+                    },
+                    Err(e) => {
+                        format!("can't get accessibility tree of {} at {}: {}", what, tab_lock.state_string(), e)
+                    },
+                }
+            };
+            tool_log.push(log);
+        },
         Command::WaitFor(args) => {
             let tab = {
                 let mut chrome_session_locked = chrome_session.lock().await;
@@ -975,6 +1352,179 @@ async fn chrome_command_exec(
             };
             tool_log.push(log);
         },
+        Command::ThrottleNetwork(args) => {
+            let tab = {
+                let mut chrome_session_locked = chrome_session.lock().await;
+                let chrome_session = chrome_session_locked.as_any_mut().downcast_mut::<ChromeSession>().ok_or("Failed to downcast to ChromeSession")?;
+                session_get_tab_arc(chrome_session, &args.tab_id).await?
+            };
+            let log = {
+                let tab_lock = tab.lock().await;
+                let (offline, latency, download_throughput, upload_throughput) = args.preset.conditions();
+                match {
+                    tab_lock.headless_tab.call_method(Network::Enable {
+                        max_total_buffer_size: None,
+                        max_resource_buffer_size: None,
+                        max_post_data_size: None,
+                        report_direct_socket_traffic: None,
+                        enable_durable_messages: None,
+                    }).map_err(|e| e.to_string())?;
+                    tab_lock.headless_tab.call_method(Network::EmulateNetworkConditions {
+                        offline,
+                        latency,
+                        download_throughput,
+                        upload_throughput,
+                        connection_type: None,
+                        packet_loss: None,
+                        packet_queue_length: None,
+                        packet_reordering: None,
+                    }).map_err(|e| e.to_string())?;
+                    Ok::<(), String>(())
+                } {
+                    Ok(_) => match args.preset {
+                        NetworkPreset::None => format!("throttle_network none at {}: network emulation is cleared", tab_lock.state_string()),
+                        NetworkPreset::Offline => format!("throttle_network offline at {}: the tab has no network now", tab_lock.state_string()),
+                        _ => format!("throttle_network {} at {}: latency {}ms, download {} bytes/s, upload {} bytes/s",
+                            args.preset.name(), tab_lock.state_string(), latency, download_throughput, upload_throughput),
+                    },
+                    Err(e) => {
+                        format!("throttle_network {} failed at {}: {}", args.preset.name(), tab_lock.state_string(), e)
+                    },
+                }
+            };
+            tool_log.push(log);
+        },
+        Command::ClearTabState(args) => {
+            let tab = {
+                let mut chrome_session_locked = chrome_session.lock().await;
+                let chrome_session = chrome_session_locked.as_any_mut().downcast_mut::<ChromeSession>().ok_or("Failed to downcast to ChromeSession")?;
+                session_get_tab_arc(chrome_session, &args.tab_id).await?
+            };
+            let log = {
+                let tab_lock = tab.lock().await;
+                let origin = tab_lock.headless_tab.get_url();
+                match {
+                    // without urls getCookies returns cookies visible to the current page, that's the tab's origin
+                    let cookies = tab_lock.headless_tab.get_cookies().map_err(|e| e.to_string())?;
+                    let cookies_n = cookies.len();
+                    if !cookies.is_empty() {
+                        tab_lock.headless_tab.delete_cookies(cookies.into_iter().map(|c| Network::DeleteCookies {
+                            name: c.name,
+                            url: None,
+                            domain: Some(c.domain),
+                            path: Some(c.path),
+                            partition_key: None,
+                        }).collect()).map_err(|e| e.to_string())?;
+                    }
+                    // storage access throws on opaque origins like about:blank, that's not an error for us
+                    let storage_n = tab_lock.headless_tab.evaluate(
+                        "(() => { try { const n = localStorage.length + sessionStorage.length; localStorage.clear(); sessionStorage.clear(); return n; } catch (e) { return 0; } })()",
+                        false,
+                    ).map_err(|e| e.to_string())?.value.and_then(|v| v.as_u64()).unwrap_or(0);
+                    let log_lines_n = {
+                        let mut tab_log = tab_lock.tab_log.lock().unwrap();
+                        let n = tab_log.len();
+                        tab_log.clear();
+                        n
+                    };
+                    tab_lock.headless_tab.navigate_to("about:blank").map_err(|e| e.to_string())?;
+                    tab_lock.headless_tab.wait_until_navigated().map_err(|e| e.to_string())?;
+                    Ok::<(usize, u64, usize), String>((cookies_n, storage_n, log_lines_n))
+                } {
+                    Ok((cookies_n, storage_n, log_lines_n)) => {
+                        format!("clear_tab_state at {}: was at `{}`, deleted {} cookies, cleared {} localStorage/sessionStorage keys, dropped {} tab_log lines, navigated to about:blank",
+                            tab_lock.state_string(), origin, cookies_n, storage_n, log_lines_n)
+                    },
+                    Err(e) => {
+                        format!("clear_tab_state failed at {}: {}", tab_lock.state_string(), e)
+                    },
+                }
+            };
+            tool_log.push(log);
+        },
+        Command::SetGeolocation(args) => {
+            let tab = {
+                let mut chrome_session_locked = chrome_session.lock().await;
+                let chrome_session = chrome_session_locked.as_any_mut().downcast_mut::<ChromeSession>().ok_or("Failed to downcast to ChromeSession")?;
+                session_get_tab_arc(chrome_session, &args.tab_id).await?
+            };
+            let log = {
+                let tab_lock = tab.lock().await;
+                match {
+                    // the override alone isn't enough, navigator.geolocation asks for a permission nobody can click in headless mode
+                    tab_lock.headless_tab.call_method(CdpBrowser::GrantPermissions {
+                        permissions: vec![CdpBrowser::PermissionType::Geolocation],
+                        origin: None,
+                        browser_context_id: None,
+                    }).map_err(|e| e.to_string())?;
+                    tab_lock.headless_tab.call_method(Emulation::SetGeolocationOverride {
+                        latitude: Some(args.latitude),
+                        longitude: Some(args.longitude),
+                        accuracy: Some(args.accuracy),
+                    }).map_err(|e| e.to_string())?;
+                    Ok::<(), String>(())
+                } {
+                    Ok(_) => format!("set_geolocation at {}: latitude {}, longitude {}, accuracy {}m",
+                        tab_lock.state_string(), args.latitude, args.longitude, args.accuracy),
+                    Err(e) => format!("set_geolocation failed at {}: {}", tab_lock.state_string(), e),
+                }
+            };
+            tool_log.push(log);
+        },
+        Command::SetTimezone(args) => {
+            let tab = {
+                let mut chrome_session_locked = chrome_session.lock().await;
+                let chrome_session = chrome_session_locked.as_any_mut().downcast_mut::<ChromeSession>().ok_or("Failed to downcast to ChromeSession")?;
+                session_get_tab_arc(chrome_session, &args.tab_id).await?
+            };
+            let log = {
+                let tab_lock = tab.lock().await;
+                match tab_lock.headless_tab.call_method(Emulation::SetTimezoneOverride {
+                    timezone_id: args.timezone.clone(),
+                }) {
+                    Ok(_) => format!("set_timezone at {}: the tab is in {} now", tab_lock.state_string(), args.timezone),
+                    // the name looked right but chrome's ICU doesn't know it, don't show the CDP error dump
+                    Err(_) => format!("set_timezone failed at {}: unknown timezone `{}`, use an IANA name like Europe/Berlin or America/New_York",
+                        tab_lock.state_string(), args.timezone),
+                }
+            };
+            tool_log.push(log);
+        },
+        Command::SetLocale(args) => {
+            let tab = {
+                let mut chrome_session_locked = chrome_session.lock().await;
+                let chrome_session = chrome_session_locked.as_any_mut().downcast_mut::<ChromeSession>().ok_or("Failed to downcast to ChromeSession")?;
+                session_get_tab_arc(chrome_session, &args.tab_id).await?
+            };
+            let log = {
+                let tab_lock = tab.lock().await;
+                let language = args.locale.split('-').next().unwrap_or_default();
+                let accept_language = format!("{},{};q=0.9", args.locale, language);
+                match {
+                    tab_lock.headless_tab.set_extra_http_headers(HashMap::from([("Accept-Language", accept_language.as_str())])).map_err(|e| e.to_string())?;
+                    // chrome refuses a second override while one is in effect, so clear it first
+                    tab_lock.headless_tab.call_method(Emulation::SetLocaleOverride { locale: None }).map_err(|e| e.to_string())?;
+                    tab_lock.headless_tab.call_method(Emulation::SetLocaleOverride { locale: Some(args.locale.clone()) }).map_err(|e| e.to_string())?;
+                    // the locale override is for Intl, navigator.language still follows the browser, pages usually read that one;
+                    // configurable, so the script from a later set_locale can redefine it
+                    tab_lock.headless_tab.call_method(Page::AddScriptToEvaluateOnNewDocument {
+                        source: format!(
+                            "Object.defineProperty(navigator, 'language', {{get: () => {:?}, configurable: true}}); Object.defineProperty(navigator, 'languages', {{get: () => [{:?}, {:?}], configurable: true}});",
+                            args.locale, args.locale, language,
+                        ),
+                        world_name: None,
+                        include_command_line_api: None,
+                        run_immediately: None,
+                    }).map_err(|e| e.to_string())?;
+                    Ok::<(), String>(())
+                } {
+                    Ok(_) => format!("set_locale at {}: Accept-Language is {} and navigator.language is {} for the next navigations, reload to see this page in it",
+                        tab_lock.state_string(), accept_language, args.locale),
+                    Err(e) => format!("set_locale failed at {}: {}", tab_lock.state_string(), e),
+                }
+            };
+            tool_log.push(log);
+        },
     }
 
     Ok((tool_log, multimodal_els))
@@ -995,8 +1545,11 @@ struct OpenTabArgs {
 struct NavigateToArgs {
     uri: String,
     tab_id: String,
+    wait_for_selector: Option<String>,
 }
 
+const NAVIGATE_WAIT_FOR_SELECTOR_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Debug)]
 struct ClickAtPointArgs {
     point: Point,
@@ -1041,6 +1594,301 @@ struct WaitForArgs {
     seconds: f64,
 }
 
+#[derive(Debug)]
+struct A11yTreeArgs {
+    tab_id: String,
+    selector: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NetworkPreset {
+    Offline,
+    Slow3G,
+    Fast3G,
+    None,
+}
+
+impl NetworkPreset {
+    fn from_name(s: &str) -> Result<Self, String> {
+        match s {
+            "offline" => Ok(NetworkPreset::Offline),
+            "slow3g" => Ok(NetworkPreset::Slow3G),
+            "fast3g" => Ok(NetworkPreset::Fast3G),
+            "none" => Ok(NetworkPreset::None),
+            _ => Err(format!("unknown network preset: {}. Should be `offline`, `slow3g`, `fast3g` or `none`.", s)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            NetworkPreset::Offline => "offline",
+            NetworkPreset::Slow3G => "slow3g",
+            NetworkPreset::Fast3G => "fast3g",
+            NetworkPreset::None => "none",
+        }
+    }
+
+    // (offline, latency ms, download bytes/s, upload bytes/s), same numbers as DevTools presets, -1 means no limit
+    fn conditions(&self) -> (bool, f64, f64, f64) {
+        match self {
+            NetworkPreset::Offline => (true, 0.0, -1.0, -1.0),
+            NetworkPreset::Slow3G => (false, 2000.0, 50000.0, 50000.0),
+            NetworkPreset::Fast3G => (false, 562.5, 180000.0, 84375.0),
+            NetworkPreset::None => (false, 0.0, -1.0, -1.0),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ThrottleNetworkArgs {
+    tab_id: String,
+    preset: NetworkPreset,
+}
+
+#[derive(Debug)]
+struct SetGeolocationArgs {
+    tab_id: String,
+    latitude: f64,
+    longitude: f64,
+    accuracy: f64,
+}
+
+#[derive(Debug)]
+struct SetTimezoneArgs {
+    tab_id: String,
+    timezone: String,
+}
+
+#[derive(Debug)]
+struct SetLocaleArgs {
+    tab_id: String,
+    locale: String,
+}
+
+#[derive(Debug)]
+struct GetHtmlArgs {
+    tab_id: String,
+    selector: Option<String>,
+    strip_scripts: bool,
+}
+
+const GET_HTML_MAX_CHARS: usize = 20000;
+
+// The rendered DOM as it is now, not what the server sent. The result is a JSON string, so that
+// "nothing matches" and "bad selector" are not confused with an element that is really empty.
+fn get_html_expression(selector: &Option<String>, strip_scripts: bool) -> String {
+    let selector_js = selector.as_ref().map(|s| serde_json::to_string(s).unwrap()).unwrap_or("null".to_string());
+    format!(r#"(() => {{
+    let el;
+    try {{
+        el = {selector} === null ? document.documentElement : document.querySelector({selector});
+    }} catch (e) {{
+        return JSON.stringify({{error: e.message}});
+    }}
+    if (!el) return JSON.stringify({{found: false}});
+    if ({strip}) {{
+        el = el.cloneNode(true);
+        el.querySelectorAll('script, style').forEach(s => {{ s.textContent = ''; }});
+        if (el.matches('script, style')) el.textContent = '';
+    }}
+    return JSON.stringify({{found: true, html: el.outerHTML}});
+}})()"#, selector = selector_js, strip = strip_scripts)
+}
+
+fn format_get_html(what: &str, evaluated: &str, max_chars: usize) -> Result<String, String> {
+    let result: Value = serde_json::from_str(evaluated).map_err(|e| format!("unexpected result from the page: {}", e))?;
+    if let Some(error) = result.get("error").and_then(|e| e.as_str()) {
+        return Err(format!("bad selector {}: {}", what, error));
+    }
+    if !result.get("found").and_then(|f| f.as_bool()).unwrap_or(false) {
+        return Err(format!("no element matches {}, check the selector or wait_for the page to render it", what));
+    }
+    let html = result.get("html").and_then(|h| h.as_str()).unwrap_or_default();
+    let total_chars = html.chars().count();
+    if total_chars <= max_chars {
+        return Ok(html.to_string());
+    }
+    let shown: String = html.chars().take(max_chars).collect();
+    Ok(format!("{}\n...truncated, {} of {} chars shown, use a narrower selector or --no-scripts", shown, max_chars, total_chars))
+}
+
+#[derive(Debug)]
+struct FillFormArgs {
+    tab_id: String,
+    fields: serde_json::Map<String, Value>,
+}
+
+fn parse_fill_form_fields(json: &str) -> Result<serde_json::Map<String, Value>, String> {
+    let fields = match serde_json::from_str::<Value>(json) {
+        Ok(Value::Object(fields)) => fields,
+        Ok(_) => return Err("fill_form wants a JSON object of element_selector to value".to_string()),
+        Err(e) => return Err(format!("fill_form fields are not valid JSON: {}, remember to quote the whole object", e)),
+    };
+    if fields.is_empty() {
+        return Err("fill_form got no fields".to_string());
+    }
+    for (selector, value) in fields.iter() {
+        let ok = match value {
+            Value::String(_) | Value::Number(_) | Value::Bool(_) => true,
+            Value::Array(values) => values.iter().all(|v| v.is_string()),  // multiple select
+            _ => false,
+        };
+        if !ok {
+            return Err(format!("value for `{}` should be a string, a number, true/false, or a list of strings for a multiple select", selector));
+        }
+    }
+    Ok(fields)
+}
+
+// Sets values through the native setter and dispatches input and change, so frameworks like React see the change.
+// A radio selector may match the whole group, the radio with the given value gets checked.
+fn fill_form_expression(fields: &serde_json::Map<String, Value>) -> String {
+    format!(r#"(() => {{
+    const fields = {fields};
+    const results = [];
+    const fire = (el) => {{
+        el.dispatchEvent(new Event('input', {{bubbles: true}}));
+        el.dispatchEvent(new Event('change', {{bubbles: true}}));
+    }};
+    const truthy = (v) => v === true || v === 'true' || v === 'on' || v === 'yes' || v === 1;
+    for (const [selector, value] of Object.entries(fields)) {{
+        let els;
+        try {{
+            els = Array.from(document.querySelectorAll(selector));
+        }} catch (e) {{
+            results.push({{selector, ok: false, error: 'bad selector: ' + e.message}});
+            continue;
+        }}
+        if (els.length === 0) {{
+            results.push({{selector, ok: false, not_found: true}});
+            continue;
+        }}
+        const el = els[0];
+        const tag = el.tagName.toLowerCase();
+        const type = (el.getAttribute('type') || '').toLowerCase();
+        try {{
+            if (tag === 'input' && type === 'radio') {{
+                const radio = els.length === 1 && typeof value === 'boolean' ? el : els.find(r => r.value === String(value));
+                if (!radio) {{
+                    results.push({{selector, ok: false, kind: 'radio', error: 'no radio with value ' + JSON.stringify(value) + ', there are ' + JSON.stringify(els.map(r => r.value))}});
+                    continue;
+                }}
+                radio.checked = value !== false;
+                fire(radio);
+                results.push({{selector, ok: true, kind: 'radio', value: radio.value}});
+            }} else if (tag === 'input' && type === 'checkbox') {{
+                el.checked = truthy(value);
+                fire(el);
+                results.push({{selector, ok: true, kind: 'checkbox', value: el.checked}});
+            }} else if (tag === 'select') {{
+                const wanted = (Array.isArray(value) ? value : [value]).map(String);
+                const options = Array.from(el.options);
+                const missing = wanted.filter(w => !options.some(o => o.value === w || o.text.trim() === w));
+                if (missing.length > 0) {{
+                    results.push({{selector, ok: false, kind: 'select', error: 'no option ' + JSON.stringify(missing) + ', there are ' + JSON.stringify(options.map(o => o.value))}});
+                    continue;
+                }}
+                options.forEach(o => {{ o.selected = wanted.includes(o.value) || wanted.includes(o.text.trim()); }});
+                fire(el);
+                results.push({{selector, ok: true, kind: 'select', value: Array.from(el.selectedOptions).map(o => o.value).join(',')}});
+            }} else if (tag === 'input' || tag === 'textarea') {{
+                if (el.disabled || el.readOnly) {{
+                    results.push({{selector, ok: false, kind: tag, error: 'the field is disabled or read-only'}});
+                    continue;
+                }}
+                const setter = Object.getOwnPropertyDescriptor(Object.getPrototypeOf(el), 'value').set;
+                setter.call(el, String(value));
+                fire(el);
+                results.push({{selector, ok: true, kind: type || tag, value: el.value}});
+            }} else if (el.isContentEditable) {{
+                el.textContent = String(value);
+                fire(el);
+                results.push({{selector, ok: true, kind: 'contenteditable', value: el.textContent}});
+            }} else {{
+                results.push({{selector, ok: false, kind: tag, error: 'not a form field'}});
+            }}
+        }} catch (e) {{
+            results.push({{selector, ok: false, kind: tag, error: e.message}});
+        }}
+    }}
+    return JSON.stringify(results);
+}})()"#, fields = serde_json::to_string(fields).unwrap())
+}
+
+fn format_fill_form(evaluated: &str) -> Result<String, String> {
+    let results: Vec<Value> = serde_json::from_str(evaluated).map_err(|e| format!("unexpected result from the page: {}", e))?;
+    let mut lines = vec![];
+    let mut not_found = vec![];
+    for r in results.iter() {
+        let selector = r.get("selector").and_then(|s| s.as_str()).unwrap_or_default();
+        let kind = r.get("kind").and_then(|k| k.as_str()).unwrap_or("?");
+        if r.get("ok").and_then(|ok| ok.as_bool()).unwrap_or(false) {
+            let value = match r.get("value") {
+                Some(Value::String(s)) => s.clone(),
+                Some(v) => v.to_string(),
+                None => String::new(),
+            };
+            lines.push(format!("OK `{}` ({}) = {:?}", selector, kind, value));
+        } else if r.get("not_found").and_then(|n| n.as_bool()).unwrap_or(false) {
+            lines.push(format!("NOT FOUND `{}`", selector));
+            not_found.push(format!("`{}`", selector));
+        } else {
+            lines.push(format!("FAILED `{}` ({}): {}", selector, kind, r.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error")));
+        }
+    }
+    let failed_n = results.len() - lines.iter().filter(|l| l.starts_with("OK ")).count();
+    lines.push(format!("{} of {} fields filled", results.len() - failed_n, results.len()));
+    if !not_found.is_empty() {
+        lines.push(format!("not found: {}, check the selectors or wait_for the page to render them", not_found.join(", ")));
+    }
+    Ok(lines.join("\n"))
+}
+
+const GEOLOCATION_DEFAULT_ACCURACY: f64 = 100.0;
+const TIMEZONE_AREAS: &[&str] = &[
+    "Africa", "America", "Antarctica", "Arctic", "Asia", "Atlantic", "Australia", "Europe", "Indian", "Pacific", "Etc",
+];
+
+fn parse_coordinate(s: &str, name: &str, limit: f64) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(x) if x.is_finite() && x.abs() <= limit => Ok(x),
+        Ok(_) => Err(format!("{} should be between -{} and {}, got {}", name, limit, limit, s)),
+        Err(_) => Err(format!("{} is not a number: {:?}", name, s)),
+    }
+}
+
+fn parse_accuracy(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(x) if x.is_finite() && x >= 0.0 => Ok(x),
+        _ => Err(format!("accuracy should be a non-negative number of meters, got {:?}", s)),
+    }
+}
+
+// Catches typos and offsets like "+03:00" early, chrome has the final say on whether the zone exists
+fn validate_timezone(tz: &str) -> Result<String, String> {
+    let well_formed = tz == "UTC" || tz.split_once('/').map(|(area, location)| {
+        TIMEZONE_AREAS.contains(&area) && !location.is_empty()
+            && location.chars().all(|c| c.is_ascii_alphanumeric() || "_-+/".contains(c))
+    }).unwrap_or(false);
+    if !well_formed {
+        return Err(format!("unknown timezone `{}`, use an IANA name like Europe/Berlin, America/New_York or UTC", tz));
+    }
+    Ok(tz.to_string())
+}
+
+// BCP 47 shape: a 2-3 letter language, then subtags like script, region or variant, "de", "pt-BR", "zh-Hant-TW", "es-419"
+fn validate_language_tag(tag: &str) -> Result<String, String> {
+    let mut subtags = tag.split('-');
+    let language = subtags.next().unwrap_or_default();
+    let well_formed = (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|s| (1..=8).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric()));
+    if !well_formed {
+        let hint = if tag.contains('_') { format!(", with a dash: {}", tag.replace('_', "-")) } else { String::new() };
+        return Err(format!("bad language tag `{}`, use something like en, de-DE or zh-Hant-TW{}", tag, hint));
+    }
+    Ok(tag.to_string())
+}
+
 fn parse_single_command(command: &String) -> Result<Command, String> {
     let args = shell_words::split(&command).map_err(|e| e.to_string())?;
     if args.is_empty() {
@@ -1075,8 +1923,19 @@ fn parse_single_command(command: &String) -> Result<Command, String> {
                     Ok(Command::NavigateTo(NavigateToArgs {
                         uri: uri.clone(),
                         tab_id: tab_id.clone(),
+                        wait_for_selector: None,
                     }))
                 },
+                [tab_id, uri, flag, selector] if flag == "--wait-for" => {
+                    Ok(Command::NavigateTo(NavigateToArgs {
+                        uri: uri.clone(),
+                        tab_id: tab_id.clone(),
+                        wait_for_selector: Some(selector.clone()),
+                    }))
+                },
+                [_tab_id, _uri, flag] if flag == "--wait-for" => {
+                    Err("Missing the selector after --wait-for".to_string())
+                },
                 _ => {
                     Err("Missing one or several arguments `tab_id`, `uri`".to_string())
                 }
@@ -1107,6 +1966,18 @@ fn parse_single_command(command: &String) -> Result<Command, String> {
                 }
             }
         },
+        "screenshot_fullpage" => {
+            match parsed_args.as_slice() {
+                [tab_id] => {
+                    Ok(Command::ScreenshotFullpage(TabArgs {
+                        tab_id: tab_id.clone(),
+                    }))
+                },
+                _ => {
+                    Err("Missing one or several arguments `tab_id`".to_string())
+                }
+            }
+        },
         "html" => {
             match parsed_args.as_slice() {
                 [tab_id, selector] => {
@@ -1120,6 +1991,29 @@ fn parse_single_command(command: &String) -> Result<Command, String> {
                 }
             }
         },
+        "get_html" => {
+            let strip_scripts = parsed_args.iter().any(|a| a == "--no-scripts");
+            let positional: Vec<String> = parsed_args.iter().filter(|a| *a != "--no-scripts").cloned().collect();
+            match positional.as_slice() {
+                [tab_id] => {
+                    Ok(Command::GetHtml(GetHtmlArgs {
+                        tab_id: tab_id.clone(),
+                        selector: None,
+                        strip_scripts,
+                    }))
+                },
+                [tab_id, selector] => {
+                    Ok(Command::GetHtml(GetHtmlArgs {
+                        tab_id: tab_id.clone(),
+                        selector: Some(selector.clone()),
+                        strip_scripts,
+                    }))
+                },
+                _ => {
+                    Err("Missing one or several arguments `tab_id`, optional `selector` and `--no-scripts`.".to_string())
+                }
+            }
+        },
         "reload" => {
             match parsed_args.as_slice() {
                 [tab_id] => {
@@ -1233,6 +2127,19 @@ fn parse_single_command(command: &String) -> Result<Command, String> {
                 }
             }
         },
+        "eval_isolated" => {
+            match parsed_args.as_slice() {
+                [tab_id, expression] => {
+                    Ok(Command::EvalIsolated(EvalArgs {
+                        expression: expression.clone(),
+                        tab_id: tab_id.clone(),
+                    }))
+                },
+                _ => {
+                    Err("Missing one or several arguments `tab_id`, `expression`.".to_string())
+                }
+            }
+        },
         "styles" => {
             match parsed_args.as_slice() {
                 [tab_id, selector, property_filter] => {
@@ -1261,6 +2168,107 @@ fn parse_single_command(command: &String) -> Result<Command, String> {
                 }
             }
         },
+        "a11y_tree" => {
+            match parsed_args.as_slice() {
+                [tab_id] => {
+                    Ok(Command::A11yTree(A11yTreeArgs {
+                        tab_id: tab_id.clone(),
+                        selector: None,
+                    }))
+                },
+                [tab_id, selector] => {
+                    Ok(Command::A11yTree(A11yTreeArgs {
+                        tab_id: tab_id.clone(),
+                        selector: Some(selector.clone()),
+                    }))
+                },
+                _ => {
+                    Err("Missing one or several arguments `tab_id`, optional `selector`.".to_string())
+                }
+            }
+        },
+        "throttle_network" => {
+            match parsed_args.as_slice() {
+                [tab_id, preset_str] => {
+                    Ok(Command::ThrottleNetwork(ThrottleNetworkArgs {
+                        tab_id: tab_id.clone(),
+                        preset: NetworkPreset::from_name(preset_str)?,
+                    }))
+                },
+                _ => {
+                    Err("Missing one or several arguments `tab_id`, `<offline|slow3g|fast3g|none>`.".to_string())
+                }
+            }
+        },
+        "clear_tab_state" => {
+            match parsed_args.as_slice() {
+                [tab_id] => {
+                    Ok(Command::ClearTabState(TabArgs {
+                        tab_id: tab_id.clone(),
+                    }))
+                },
+                _ => {
+                    Err("Missing argument `tab_id`".to_string())
+                }
+            }
+        },
+        "set_geolocation" => {
+            match parsed_args.as_slice() {
+                [tab_id, latitude, longitude, rest @ ..] if rest.len() <= 1 => {
+                    Ok(Command::SetGeolocation(SetGeolocationArgs {
+                        tab_id: tab_id.clone(),
+                        latitude: parse_coordinate(latitude, "latitude", 90.0)?,
+                        longitude: parse_coordinate(longitude, "longitude", 180.0)?,
+                        accuracy: match rest.first() {
+                            Some(accuracy) => parse_accuracy(accuracy)?,
+                            None => GEOLOCATION_DEFAULT_ACCURACY,
+                        },
+                    }))
+                },
+                _ => {
+                    Err("Missing one or several arguments `tab_id`, `latitude`, `longitude`, optional `accuracy`.".to_string())
+                }
+            }
+        },
+        "set_timezone" => {
+            match parsed_args.as_slice() {
+                [tab_id, timezone] => {
+                    Ok(Command::SetTimezone(SetTimezoneArgs {
+                        tab_id: tab_id.clone(),
+                        timezone: validate_timezone(timezone)?,
+                    }))
+                },
+                _ => {
+                    Err("Missing one or several arguments `tab_id`, `timezone`.".to_string())
+                }
+            }
+        },
+        "set_locale" => {
+            match parsed_args.as_slice() {
+                [tab_id, locale] => {
+                    Ok(Command::SetLocale(SetLocaleArgs {
+                        tab_id: tab_id.clone(),
+                        locale: validate_language_tag(locale)?,
+                    }))
+                },
+                _ => {
+                    Err("Missing one or several arguments `tab_id`, `locale`.".to_string())
+                }
+            }
+        },
+        "fill_form" => {
+            match parsed_args.as_slice() {
+                [tab_id, json] => {
+                    Ok(Command::FillForm(FillFormArgs {
+                        tab_id: tab_id.clone(),
+                        fields: parse_fill_form_fields(json)?,
+                    }))
+                },
+                _ => {
+                    Err("Missing one or several arguments `tab_id`, `json`, quote the JSON object as one argument.".to_string())
+                }
+            }
+        },
         _ => Err(format!("Unknown command: {:?}.", command_name)),
     }
 }
@@ -1328,6 +2336,15 @@ fields:
     f_type: string_short
     f_desc: "Scale factor of the browser window in tablet mode."
     f_extra: true
+  screenshot_full_resolution:
+    f_type: bool
+    f_desc: "Send screenshots at native resolution instead of downscaling them to 800px. Pixel-accurate, but a screenshot costs several times more tokens."
+    f_extra: true
+  max_images_per_message:
+    f_type: string_short
+    f_desc: "How many screenshots one tool call can attach, older ones are dropped. Default is 4."
+    f_placeholder: "4"
+    f_extra: true
 available:
   on_your_laptop_possible: true
   when_isolated_possible: true
@@ -1366,3 +2383,193 @@ docker:
           content: |
             🔧 Your job is to modify chrome config in the current file to connect through websockets to the container, use docker tool to inspect the container if needed. Current config file: %CURRENT_CONFIG%.
 "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn fake_exec(cmd: Command) -> Result<(Vec<String>, Vec<MultimodalElement>), String> {
+        match cmd {
+            Command::Screenshot(_) => Err("tab_id 1 is not opened".to_string()),
+            cmd => Ok((vec![format!("ok {:?}", cmd)], vec![])),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_stop_vs_continue() {
+        let commands = "reload 1\nscreenshot 1\nfly_to 1 moon\ntab_log 1";
+
+        let (log, _) = run_command_batch(commands, OnError::Stop, fake_exec).await;
+        assert_eq!(log.len(), 2, "{:?}", log);
+        assert!(log[0].starts_with("ok Reload"), "{:?}", log);
+        assert_eq!(log[1], "Failed to execute command `screenshot 1`: tab_id 1 is not opened.");
+
+        let (log, _) = run_command_batch(commands, OnError::Continue, fake_exec).await;
+        assert_eq!(log.len(), 4, "{:?}", log);
+        assert!(log[0].starts_with("ok Reload"), "{:?}", log);
+        assert_eq!(log[1], "Failed to execute command `screenshot 1`: tab_id 1 is not opened.");
+        assert!(log[2].starts_with("Failed to parse command `fly_to 1 moon`"), "{:?}", log);
+        assert!(log[3].starts_with("ok TabLog"), "{:?}", log);
+
+        assert_eq!(OnError::from_arg(None), Ok(OnError::Stop));
+        assert_eq!(OnError::from_arg(Some(&Value::String("continue".to_string()))), Ok(OnError::Continue));
+        assert!(OnError::from_arg(Some(&Value::String("retry".to_string()))).is_err());
+    }
+
+    // the tab_id goes into the image, to tell the screenshots apart
+    async fn screenshot_exec(cmd: Command) -> Result<(Vec<String>, Vec<MultimodalElement>), String> {
+        let Command::Screenshot(args) = cmd else { return Ok((vec![format!("ok {:?}", cmd)], vec![])) };
+        let image = MultimodalElement::new("image/jpeg".to_string(), base64::prelude::BASE64_STANDARD.encode(args.tab_id.as_bytes()))?;
+        Ok((vec![format!("screenshot of tab {}", args.tab_id)], vec![image]))
+    }
+
+    #[tokio::test]
+    async fn test_screenshots_over_the_cap_keep_the_most_recent() {
+        let commands = "screenshot 1\nscreenshot 2\nreload 3\nscreenshot 3\nscreenshot 4\nscreenshot 5\nscreenshot 6";
+        let (log, els) = run_command_batch(commands, OnError::Stop, screenshot_exec).await;
+        assert_eq!(els.len(), 6, "{:?}", log);
+
+        let (kept, dropped) = keep_last_images(els, MAX_IMAGES_PER_MESSAGE_DEFAULT);
+        assert_eq!(dropped, 2);
+        let kept_tabs: Vec<String> = kept.iter()
+            .map(|el| String::from_utf8(base64::prelude::BASE64_STANDARD.decode(&el.m_content).unwrap()).unwrap())
+            .collect();
+        assert_eq!(kept_tabs, vec!["3", "4", "5", "6"]);
+
+        let (kept, dropped) = keep_last_images(kept, 10);
+        assert_eq!((kept.len(), dropped), (4, 0));
+    }
+
+    #[test]
+    fn test_parse_geolocation_and_timezone() {
+        match parse_single_command(&"set_geolocation 1 52.52 13.405".to_string()).unwrap() {
+            Command::SetGeolocation(args) => {
+                assert_eq!((args.latitude, args.longitude, args.accuracy), (52.52, 13.405, GEOLOCATION_DEFAULT_ACCURACY));
+            },
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+        match parse_single_command(&"set_geolocation 1 -33.86 151.2 5".to_string()).unwrap() {
+            Command::SetGeolocation(args) => assert_eq!(args.accuracy, 5.0),
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+        let err = parse_single_command(&"set_geolocation 1 95 13".to_string()).unwrap_err();
+        assert!(err.contains("latitude should be between -90 and 90"), "{}", err);
+        let err = parse_single_command(&"set_geolocation 1 52 east".to_string()).unwrap_err();
+        assert!(err.contains("longitude is not a number"), "{}", err);
+        assert!(parse_single_command(&"set_geolocation 1 52 13 -1".to_string()).is_err());
+
+        match parse_single_command(&"set_timezone 1 America/Argentina/Buenos_Aires".to_string()).unwrap() {
+            Command::SetTimezone(args) => assert_eq!(args.timezone, "America/Argentina/Buenos_Aires"),
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+        assert!(parse_single_command(&"set_timezone 1 UTC".to_string()).is_ok());
+        for bad in ["Mars/Olympus_Mons", "+03:00", "Berlin", "Europe/"] {
+            let err = parse_single_command(&format!("set_timezone 1 {}", bad)).unwrap_err();
+            assert!(err.starts_with("unknown timezone"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_parse_navigate_to_wait_for() {
+        match parse_single_command(&"navigate_to 1 https://example.com".to_string()).unwrap() {
+            Command::NavigateTo(args) => assert_eq!((args.uri.as_str(), args.wait_for_selector), ("https://example.com", None)),
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+        match parse_single_command(&"navigate_to 1 https://example.com --wait-for \"#app .ready\"".to_string()).unwrap() {
+            Command::NavigateTo(args) => {
+                assert_eq!(args.tab_id, "1");
+                assert_eq!(args.wait_for_selector, Some("#app .ready".to_string()));
+            },
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+        assert!(parse_single_command(&"navigate_to 1 https://example.com --wait-for".to_string()).unwrap_err().contains("selector"));
+    }
+
+    #[test]
+    fn test_parse_set_locale() {
+        for good in ["de", "de-DE", "pt-BR", "zh-Hant-TW", "es-419"] {
+            match parse_single_command(&format!("set_locale 1 {}", good)).unwrap() {
+                Command::SetLocale(args) => assert_eq!(args.locale, good),
+                cmd => panic!("unexpected {:?}", cmd),
+            }
+        }
+        let err = parse_single_command(&"set_locale 1 de_DE".to_string()).unwrap_err();
+        assert!(err.contains("with a dash: de-DE"), "{}", err);
+        for bad in ["german", "d", "de-", "de-DE-toolongsubtag", "12-DE"] {
+            assert!(parse_single_command(&format!("set_locale 1 {}", bad)).is_err(), "{}", bad);
+        }
+        assert!(parse_single_command(&"set_locale 1".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_parse_get_html() {
+        match parse_single_command(&"get_html 1".to_string()).unwrap() {
+            Command::GetHtml(args) => assert_eq!((args.tab_id.as_str(), args.selector, args.strip_scripts), ("1", None, false)),
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+        match parse_single_command(&"get_html 1 --no-scripts \"form#login input[name='user']\"".to_string()).unwrap() {
+            Command::GetHtml(args) => {
+                assert_eq!(args.selector.as_deref(), Some("form#login input[name='user']"));
+                assert!(args.strip_scripts);
+                assert!(get_html_expression(&args.selector, true).contains(r#"document.querySelector("form#login input[name='user']")"#));
+            },
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+        assert!(parse_single_command(&"get_html".to_string()).is_err());
+        assert!(parse_single_command(&"get_html 1 div span".to_string()).is_err());
+
+        assert_eq!(format_get_html("`#app`", r#"{"found":true,"html":"<div id=\"app\"></div>"}"#, 100).unwrap(), "<div id=\"app\"></div>");
+        let err = format_get_html("`#nope`", r#"{"found":false}"#, 100).unwrap_err();
+        assert!(err.contains("no element matches `#nope`"), "{}", err);
+        let err = format_get_html("`div[`", r#"{"error":"'div[' is not a valid selector."}"#, 100).unwrap_err();
+        assert!(err.contains("bad selector `div[`"), "{}", err);
+        let long = format_get_html("the page", r#"{"found":true,"html":"<p>0123456789</p>"}"#, 5).unwrap();
+        assert!(long.starts_with("<p>01\n...truncated, 5 of 17 chars shown"), "{}", long);
+    }
+
+    #[test]
+    fn test_parse_fill_form() {
+        match parse_single_command(&r##"fill_form 1 '{"#email": "a@b.c", "#agree": true, "select[name=tags]": ["a", "b"]}'"##.to_string()).unwrap() {
+            Command::FillForm(args) => {
+                assert_eq!(args.tab_id, "1");
+                assert_eq!(args.fields.keys().cloned().collect::<Vec<_>>(), vec!["#email", "#agree", "select[name=tags]"]);
+                assert!(fill_form_expression(&args.fields).contains(r##"const fields = {"#email":"a@b.c","#agree":true,"select[name=tags]":["a","b"]};"##));
+            },
+            cmd => panic!("unexpected {:?}", cmd),
+        }
+        assert!(parse_single_command(&"fill_form 1 '[1, 2]'".to_string()).unwrap_err().contains("JSON object"));
+        assert!(parse_single_command(&"fill_form 1 '{\"#a\": '".to_string()).unwrap_err().contains("not valid JSON"));
+        assert!(parse_single_command(&"fill_form 1 '{}'".to_string()).unwrap_err().contains("no fields"));
+        assert!(parse_single_command(&"fill_form 1 '{\"#a\": {\"b\": 1}}'".to_string()).unwrap_err().contains("`#a`"));
+        assert!(parse_single_command(&"fill_form 1".to_string()).is_err());
+
+        let report = format_fill_form(r##"[
+            {"selector": "#email", "ok": true, "kind": "email", "value": "a@b.c"},
+            {"selector": "#agree", "ok": true, "kind": "checkbox", "value": true},
+            {"selector": "#size", "ok": false, "kind": "radio", "error": "no radio with value \"XL\""},
+            {"selector": "#phone", "ok": false, "not_found": true}
+        ]"##).unwrap();
+        assert_eq!(report, "OK `#email` (email) = \"a@b.c\"\nOK `#agree` (checkbox) = \"true\"\nFAILED `#size` (radio): no radio with value \"XL\"\nNOT FOUND `#phone`\n2 of 4 fields filled\nnot found: `#phone`, check the selectors or wait_for the page to render them");
+    }
+
+    #[test]
+    fn test_screenshot_full_resolution_is_not_resized() {
+        let native = DynamicImage::new_rgb8(1600, 900);
+
+        let (image, scale_factor) = screenshot_downscale(native.clone(), false, true);
+        assert_eq!((image.width(), image.height()), (1600, 900));
+        assert_eq!(scale_factor, Some(1.0));
+
+        let (image, scale_factor) = screenshot_downscale(native.clone(), true, true);
+        assert_eq!((image.width(), image.height()), (1600, 900));
+        assert_eq!(scale_factor, Some(1.0));
+
+        let (image, scale_factor) = screenshot_downscale(native, false, false);
+        assert_eq!((image.width(), image.height()), (800, 450));
+        assert_eq!(scale_factor, Some(0.5));
+
+        let (image, scale_factor) = screenshot_downscale(DynamicImage::new_rgb8(640, 480), false, false);
+        assert_eq!((image.width(), image.height()), (640, 480));
+        assert_eq!(scale_factor, None);
+    }
+}
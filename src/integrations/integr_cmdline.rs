@@ -11,11 +11,13 @@ use tracing::info;
 
 use crate::at_commands::at_commands::AtCommandsContext;
 use crate::tools::tools_description::{ToolParam, Tool, ToolDesc};
+use crate::tools::tools_execute::ToolOutputStreamer;
 use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
 use crate::postprocessing::pp_command_output::{CmdlineOutputFilter, output_mini_postprocessing};
 use crate::integrations::integr_abstract::{IntegrationTrait, IntegrationCommon, IntegrationConfirmation};
 use crate::integrations::utils::{serialize_num_to_str, deserialize_str_to_num, serialize_opt_num_to_str, deserialize_str_to_opt_num};
 use crate::integrations::setting_up_integrations::YamlError;
+use crate::integrations::process_io_utils::wait_with_output_streaming;
 
 
 #[derive(Deserialize, Serialize, Clone, Default)]
@@ -168,21 +170,26 @@ pub async fn execute_blocking_command(
     command_workdir: &String,
     env_variables: &HashMap<String, String>,
     project_dirs: Vec<PathBuf>,
+    streamer: &mut Option<ToolOutputStreamer>,
 ) -> Result<String, String> {
     info!("EXEC workdir {:?}:\n{:?}", command_workdir, command);
 
     let command_future = async {
         let mut cmd = create_command_from_string(command, command_workdir, env_variables, project_dirs)?;
         let t0 = tokio::time::Instant::now();
-        let result = cmd
+        let result = match cmd
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .output()
-            .await;
+            .kill_on_drop(true)
+            .spawn()
+        {
+            Ok(child) => wait_with_output_streaming(child, streamer).await,
+            Err(e) => Err(e.to_string()),
+        };
         let duration = t0.elapsed();
         info!("EXEC: /finished in {:?}", duration);
 
-        let output = match result {
+        let (stdout_bytes, stderr_bytes, status) = match result {
             Ok(output) => output,
             Err(e) => {
                 let msg = format!("cannot run command: '{}'. workdir: '{}'. Error: {}", &command, command_workdir, e);
@@ -191,11 +198,11 @@ pub async fn execute_blocking_command(
             }
         };
 
-        let stdout = output_mini_postprocessing(&cfg.output_filter, &String::from_utf8_lossy(&output.stdout).to_string());
-        let stderr = output_mini_postprocessing(&cfg.output_filter, &String::from_utf8_lossy(&output.stderr).to_string());
+        let stdout = output_mini_postprocessing(&cfg.output_filter, &String::from_utf8_lossy(&stdout_bytes).to_string());
+        let stderr = output_mini_postprocessing(&cfg.output_filter, &String::from_utf8_lossy(&stderr_bytes).to_string());
 
         let mut out = format_output(&stdout, &stderr);
-        let exit_code = output.status.code().unwrap_or_default();
+        let exit_code = status.code().unwrap_or_default();
         out.push_str(&format!("The command was running {:.3}s, finished with exit code {exit_code}\n", duration.as_secs_f64()));
         Ok(out)
     };
@@ -252,7 +259,12 @@ impl Tool for ToolCmdline {
         let env_variables = crate::integrations::setting_up_integrations::get_vars_for_replacements(gcx.clone(), &mut error_log).await;
         let project_dirs = crate::files_correction::get_project_dirs(gcx.clone()).await;
 
-        let tool_output = execute_blocking_command(&command, &self.cfg, &workdir, &env_variables, project_dirs).await?;
+        let mut streamer = Some(ToolOutputStreamer::new(ccx.clone(), tool_call_id).await);
+        let tool_output_maybe = execute_blocking_command(&command, &self.cfg, &workdir, &env_variables, project_dirs, &mut streamer).await;
+        if let Some(streamer) = streamer {
+            streamer.finished().await;
+        }
+        let tool_output = tool_output_maybe?;
 
         let result = vec![ContextEnum::ChatMessage(ChatMessage {
             role: "tool".to_string(),
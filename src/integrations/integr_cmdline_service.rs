@@ -1,9 +1,9 @@
 use std::any::Any;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::future::Future;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as StdMutex};
 use std::process::Stdio;
-use tokio::io::BufReader;
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::sync::{Mutex as AMutex, RwLock as ARwLock};
 use async_trait::async_trait;
 use process_wrap::tokio::*;
@@ -21,6 +21,7 @@ use crate::integrations::setting_up_integrations::YamlError;
 
 
 const REALLY_HORRIBLE_ROUNDTRIP: u64 = 3000;   // 3000 should be a really bad ping via internet, just in rare case it's a remote port
+const SERVICE_OUTPUT_MAX_LINES: usize = 5000;
 
 #[derive(Default)]
 pub struct ToolService {
@@ -75,17 +76,85 @@ impl IntegrationTrait for ToolService {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceOutputLine {
+    pub is_stderr: bool,
+    pub text: String,
+}
+
+// Output of a running service, collected all the time so logs can be read later, not only at the moment of a tool call
+#[derive(Default)]
+pub struct ServiceOutputBuffer {
+    lines: VecDeque<ServiceOutputLine>,
+    lines_total: usize,
+    lines_checked_out: usize,
+}
+
+impl ServiceOutputBuffer {
+    pub fn push(&mut self, is_stderr: bool, text: String) {
+        self.lines.push_back(ServiceOutputLine { is_stderr, text });
+        self.lines_total += 1;
+        if self.lines.len() > SERVICE_OUTPUT_MAX_LINES {
+            self.lines.pop_front();
+        }
+    }
+
+    pub fn tail(&self, n: usize) -> Vec<ServiceOutputLine> {
+        self.lines.iter().skip(self.lines.len().saturating_sub(n)).cloned().collect()
+    }
+
+    pub fn take_since_last_checkout(&mut self) -> (String, String) {
+        let new_n = (self.lines_total - self.lines_checked_out).min(self.lines.len());
+        self.lines_checked_out = self.lines_total;
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        for line in self.tail(new_n) {
+            let out = if line.is_stderr { &mut stderr } else { &mut stdout };
+            out.push_str(&line.text);
+            out.push('\n');
+        }
+        (stdout, stderr)
+    }
+}
+
+pub fn spawn_output_collector(
+    stdout: BufReader<tokio::process::ChildStdout>,
+    stderr: BufReader<tokio::process::ChildStderr>,
+    buffer: Arc<StdMutex<ServiceOutputBuffer>>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut stdout_lines = stdout.lines();
+        let mut stderr_lines = stderr.lines();
+        let (mut stdout_done, mut stderr_done) = (false, false);
+        while !stdout_done || !stderr_done {
+            tokio::select! {
+                line = stdout_lines.next_line(), if !stdout_done => match line {
+                    Ok(Some(line)) => buffer.lock().unwrap().push(false, line),
+                    _ => stdout_done = true,
+                },
+                line = stderr_lines.next_line(), if !stderr_done => match line {
+                    Ok(Some(line)) => buffer.lock().unwrap().push(true, line),
+                    _ => stderr_done = true,
+                },
+            }
+        }
+    })
+}
+
 pub struct CmdlineSession {
     cmdline_string: String,
     cmdline_workdir: String,
     cmdline_process: Box<dyn TokioChildWrapper>,
-    #[allow(dead_code)]
-    cmdline_stdout: BufReader<tokio::process::ChildStdout>,
-    #[allow(dead_code)]
-    cmdline_stderr: BufReader<tokio::process::ChildStderr>,
+    output: Arc<StdMutex<ServiceOutputBuffer>>,
+    output_collector: tokio::task::JoinHandle<()>,
     service_name: String,
 }
 
+impl CmdlineSession {
+    pub fn output(&self) -> Arc<StdMutex<ServiceOutputBuffer>> {
+        self.output.clone()
+    }
+}
+
 impl IntegrationSession for CmdlineSession {
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
@@ -97,6 +166,7 @@ impl IntegrationSession for CmdlineSession {
         Box::new(async {
             tracing::info!("SERVICE STOP workdir {}:\n{:?}", self.cmdline_workdir, self.cmdline_string);
             let t0 = tokio::time::Instant::now();
+            self.output_collector.abort();
             match Box::into_pin(self.cmdline_process.kill()).await {
                 Ok(_) => {
                     format!("Success, it took {:.3}s to stop it.\n\n", t0.elapsed().as_secs_f64())
@@ -138,7 +208,7 @@ async fn execute_background_command(
         let mut session_locked = session_arc.lock().await;
         let session = session_locked.as_any_mut().downcast_mut::<CmdlineSession>().unwrap();
         actions_log.push_str(&format!("Currently the service is running.\nworkdir: {}\ncommand line: {}\n\n", session.cmdline_workdir, session.cmdline_string));
-        let (stdout_out, stderr_out) = get_stdout_and_stderr(100, &mut session.cmdline_stdout, &mut session.cmdline_stderr).await?;
+        let (stdout_out, stderr_out) = session.output.lock().unwrap().take_since_last_checkout();
         let filtered_stdout = output_mini_postprocessing(&cfg.output_filter, &stdout_out);
         let filtered_stderr = output_mini_postprocessing(&cfg.output_filter, &stderr_out);
         actions_log.push_str(&format!("Here are stdin/stderr since the last checking out on the service:\n{}\n\n", format_output(&filtered_stdout, &filtered_stderr)));
@@ -243,12 +313,21 @@ async fn execute_background_command(
         actions_log.push_str(&out);
 
         if exit_code == -100000 {
+            let output = Arc::new(StdMutex::new(ServiceOutputBuffer::default()));
+            {
+                // startup output is already in actions_log, but should be in the logs too
+                let mut output_locked = output.lock().unwrap();
+                accumulated_stdout.lines().for_each(|line| output_locked.push(false, line.to_string()));
+                accumulated_stderr.lines().for_each(|line| output_locked.push(true, line.to_string()));
+                output_locked.take_since_last_checkout();
+            }
+            let output_collector = spawn_output_collector(stdout_reader, stderr_reader, output.clone());
             let session: Box<dyn IntegrationSession> = Box::new(CmdlineSession {
                 cmdline_process: process,
                 cmdline_string: command_str,
                 cmdline_workdir: cmdline_workdir.clone(),
-                cmdline_stdout: stdout_reader,
-                cmdline_stderr: stderr_reader,
+                output,
+                output_collector,
                 service_name: service_name.to_string(),
             });
             gcx.write().await.integration_sessions.insert(session_key.to_string(), Arc::new(AMutex::new(session)));
@@ -2,26 +2,122 @@ use crate::at_commands::at_commands::AtCommandsContext;
 use crate::call_validation::ContextEnum;
 use crate::call_validation::{ChatContent, ChatMessage, ChatUsage};
 use crate::integrations::go_to_configuration_message;
-use crate::tools::tools_description::Tool;
+use crate::integrations::sql_migrations::{apply_migration, memdb_connection, migrations_always_ask, rollback_down_to_confirm, rollback_migration, sql_command_to_match, sql_down_arg, sql_query_arg, SqlMode};
+use crate::tools::tools_description::{match_command_against_rules, MatchConfirmDeny, Tool};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::any::Any;
 use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
-use tokio::process::Command;
+use std::time::{Duration, Instant};
+use mysql_async::prelude::Queryable;
 use tokio::sync::Mutex as AMutex;
 use crate::integrations::integr_abstract::{IntegrationCommon, IntegrationConfirmation, IntegrationTrait};
+use crate::integrations::sessions::{get_session_hashmap_key, IntegrationSession};
 
 
-#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
 pub struct SettingsMysql {
-    #[serde(default)]
-    pub mysql_binary_path: String,
     pub host: String,
     pub port: String,
     pub user: String,
     pub password: String,
     pub database: String,
+    #[serde(default)]
+    pub ssl_mode: String,
+}
+
+const MYSQL_QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+const MYSQL_SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+// One pool per chat, kept between tool calls so the model doesn't pay for a handshake on each query
+struct MysqlSession {
+    pool: mysql_async::Pool,
+    settings: SettingsMysql,
+    last_used: Instant,
+}
+
+impl IntegrationSession for MysqlSession
+{
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn is_expired(&self) -> bool {
+        self.last_used.elapsed() > MYSQL_SESSION_IDLE_TIMEOUT
+    }
+    fn try_stop(&mut self) -> Box<dyn Future<Output = String> + Send + '_> {
+        let pool = self.pool.clone();
+        Box::new(async move {
+            match pool.disconnect().await {
+                Ok(_) => "".to_string(),
+                Err(e) => format!("mysql pool didn't disconnect cleanly: {}", e),
+            }
+        })
+    }
+}
+
+// Same names as `mysql --ssl-mode`, PREFERRED can't fall back to plain text here so it's not accepted
+fn ssl_opts_from_mode(ssl_mode: &str) -> Result<Option<mysql_async::SslOpts>, String> {
+    match ssl_mode.trim().to_uppercase().as_str() {
+        "" | "DISABLED" => Ok(None),
+        "REQUIRED" => Ok(Some(mysql_async::SslOpts::default()
+            .with_danger_accept_invalid_certs(true)
+            .with_danger_skip_domain_validation(true))),
+        "VERIFY_CA" => Ok(Some(mysql_async::SslOpts::default().with_danger_skip_domain_validation(true))),
+        "VERIFY_IDENTITY" => Ok(Some(mysql_async::SslOpts::default())),
+        other => Err(format!("ssl_mode {:?} is not supported, use DISABLED, REQUIRED, VERIFY_CA or VERIFY_IDENTITY", other)),
+    }
+}
+
+fn mysql_pool_from_settings(settings: &SettingsMysql) -> Result<mysql_async::Pool, String> {
+    let port = match settings.port.trim() {
+        "" => 3306,
+        p => p.parse::<u16>().map_err(|_| format!("{}, port {:?} is not a number", go_to_configuration_message("mysql"), p))?,
+    };
+    let opts = mysql_async::OptsBuilder::default()
+        .ip_or_hostname(settings.host.clone())
+        .tcp_port(port)
+        .user(Some(settings.user.clone()))
+        .pass(Some(settings.password.clone()))
+        .db_name(if settings.database.is_empty() { None } else { Some(settings.database.clone()) })
+        .ssl_opts(ssl_opts_from_mode(&settings.ssl_mode)?);
+    Ok(mysql_async::Pool::new(opts))
+}
+
+fn mysql_value_to_string(value: &mysql_async::Value) -> String {
+    use mysql_async::Value;
+    match value {
+        Value::NULL => "NULL".to_string(),
+        Value::Bytes(b) => String::from_utf8_lossy(b).to_string(),
+        Value::Int(i) => i.to_string(),
+        Value::UInt(u) => u.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Double(d) => d.to_string(),
+        Value::Date(y, m, d, 0, 0, 0, 0) => format!("{:04}-{:02}-{:02}", y, m, d),
+        Value::Date(y, m, d, h, mi, s, 0) => format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", y, m, d, h, mi, s),
+        Value::Date(y, m, d, h, mi, s, us) => format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}", y, m, d, h, mi, s, us),
+        Value::Time(neg, days, h, mi, s, us) => {
+            let sign = if *neg { "-" } else { "" };
+            let hours = *days as u64 * 24 + *h as u64;
+            if *us == 0 { format!("{}{:02}:{:02}:{:02}", sign, hours, mi, s) } else { format!("{}{:02}:{:02}:{:02}.{:06}", sign, hours, mi, s, us) }
+        }
+    }
+}
+
+// Tab separated with a header, the same as `mysql -e` prints in batch mode
+fn format_result_set(columns: &[String], rows: &[Vec<String>], affected_rows: u64) -> String {
+    if columns.is_empty() {
+        return format!("OK, {} rows affected\n", affected_rows);
+    }
+    let mut out = columns.join("\t");
+    out.push('\n');
+    for row in rows {
+        out.push_str(&row.join("\t"));
+        out.push('\n');
+    }
+    out
 }
 
 #[derive(Default)]
@@ -76,44 +172,79 @@ impl IntegrationTrait for ToolMysql {
 }
 
 impl ToolMysql {
-  async fn run_mysql_command(&self, query: &str) -> Result<String, String> {
-      let mut mysql_command = self.settings_mysql.mysql_binary_path.clone();
-      if mysql_command.is_empty() {
-          mysql_command = "mysql".to_string();
-      }
-      let output_future = Command::new(mysql_command)
-          .arg("-h")
-          .arg(&self.settings_mysql.host)
-          .arg("-P")
-          .arg(&self.settings_mysql.port)
-          .arg("-u")
-          .arg(&self.settings_mysql.user)
-          .arg(format!("-p{}", &self.settings_mysql.password))
-          .arg(&self.settings_mysql.database)
-          .arg("-e")
-          .arg(query)
-          .stdin(std::process::Stdio::null())
-          .output();
-      if let Ok(output) = tokio::time::timeout(tokio::time::Duration::from_millis(10_000), output_future).await {
-          if output.is_err() {
-              let err_text = format!("{}", output.unwrap_err());
-              tracing::error!("mysql didn't work:\n{}\n{}", query, err_text);
-              return Err(format!("{}, mysql failed:\n{}", go_to_configuration_message("mysql"), err_text));
-          }
-          let output = output.unwrap();
-          if output.status.success() {
-              Ok(String::from_utf8_lossy(&output.stdout).to_string())
-          } else {
-              // XXX: limit stderr, can be infinite
-              let stderr_string = String::from_utf8_lossy(&output.stderr);
-              tracing::error!("mysql didn't work:\n{}\n{}", query, stderr_string);
-              Err(format!("{}, mysql failed:\n{}", go_to_configuration_message("mysql"), stderr_string))
-          }
-      } else {
-          tracing::error!("mysql timed out:\n{}", query);
-          Err("mysql command timed out".to_string())
-      }
-  }
+    fn migrations_target(&self) -> String {
+        format!("mysql {}@{}:{}/{}", self.settings_mysql.user, self.settings_mysql.host, self.settings_mysql.port, self.settings_mysql.database)
+    }
+
+    async fn session_pool(&self, ccx: Arc<AMutex<AtCommandsContext>>) -> Result<mysql_async::Pool, String> {
+        let (gcx, chat_id) = {
+            let ccx_lock = ccx.lock().await;
+            (ccx_lock.global_context.clone(), ccx_lock.chat_id.clone())
+        };
+        let session_hashmap_key = get_session_hashmap_key("mysql", &chat_id);
+        // look up and insert under one write lock, otherwise two parallel calls both make a pool and one of them leaks
+        let (pool, stale_session) = {
+            let mut gcx_locked = gcx.write().await;
+            let mut stale_session = None;
+            if let Some(session) = gcx_locked.integration_sessions.get(&session_hashmap_key).cloned() {
+                let mut session_locked = session.lock().await;
+                let mysql_session = session_locked.as_any_mut().downcast_mut::<MysqlSession>().ok_or("Failed to downcast to MysqlSession")?;
+                // the config might have been edited since the pool was made
+                if mysql_session.settings == self.settings_mysql {
+                    mysql_session.last_used = Instant::now();
+                    return Ok(mysql_session.pool.clone());
+                }
+                stale_session = Some(session.clone());
+            }
+            // Pool::new() doesn't connect, it's fine to call under the lock
+            let pool = mysql_pool_from_settings(&self.settings_mysql)?;
+            let session: Box<dyn IntegrationSession> = Box::new(MysqlSession {
+                pool: pool.clone(),
+                settings: self.settings_mysql.clone(),
+                last_used: Instant::now(),
+            });
+            gcx_locked.integration_sessions.insert(session_hashmap_key, Arc::new(AMutex::new(session)));
+            (pool, stale_session)
+        };
+        // disconnecting talks to the server, not under the lock
+        if let Some(session) = stale_session {
+            Box::into_pin(session.lock().await.try_stop()).await;
+        }
+        Ok(pool)
+    }
+
+    async fn run_mysql_command(pool: &mysql_async::Pool, query: &str) -> Result<String, String> {
+        // migrations come as one BEGIN; ...; COMMIT; string, a connection that failed in the middle is reset when it goes back to the pool
+        let run = async {
+            let mut conn = pool.get_conn().await?;
+            let mut result = conn.query_iter(query).await?;
+            let mut out = String::new();
+            while !result.is_empty() {
+                let affected_rows = result.affected_rows();
+                // from the result set and not from the first row, a SELECT that found nothing still prints its header
+                let columns = result.columns()
+                    .map(|columns| columns.iter().map(|c| c.name_str().to_string()).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                let rows: Vec<mysql_async::Row> = result.collect().await?;
+                let values = rows.iter()
+                    .map(|r| (0..r.len()).map(|i| r.as_ref(i).map(mysql_value_to_string).unwrap_or_default()).collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+                out.push_str(&format_result_set(&columns, &values, affected_rows));
+            }
+            Ok::<String, mysql_async::Error>(out)
+        };
+        match tokio::time::timeout(MYSQL_QUERY_TIMEOUT, run).await {
+            Ok(Ok(out)) => Ok(out),
+            Ok(Err(e)) => {
+                tracing::error!("mysql didn't work:\n{}\n{}", query, e);
+                Err(format!("{}, mysql failed:\n{}", go_to_configuration_message("mysql"), e))
+            }
+            Err(_) => {
+                tracing::error!("mysql timed out:\n{}", query);
+                Err("mysql query timed out".to_string())
+            }
+        }
+    }
 }
 
 #[async_trait]
@@ -122,18 +253,30 @@ impl Tool for ToolMysql {
 
     async fn tool_execute(
         &mut self,
-        _ccx: Arc<AMutex<AtCommandsContext>>,
+        ccx: Arc<AMutex<AtCommandsContext>>,
         tool_call_id: &String,
         args: &HashMap<String, Value>,
     ) -> Result<(bool, Vec<ContextEnum>), String> {
-        let query = match args.get("query") {
-            Some(Value::String(v)) => v.clone(),
-            Some(v) => return Err(format!("argument `query` is not a string: {:?}", v)),
-            None => return Err("no `query` argument found".to_string()),
+        let mode = SqlMode::from_args(args)?;
+        let pool = self.session_pool(ccx.clone()).await?;
+        let pool_ref = &pool;
+        let exec = |sql: String| async move { Self::run_mysql_command(pool_ref, &sql).await };
+        let result = match mode {
+            SqlMode::Query => Self::run_mysql_command(&pool, &sql_query_arg(args)?).await?,
+            SqlMode::Migrate => {
+                let down = sql_down_arg(args)?;
+                let store = match down {
+                    Some(_) => Some(memdb_connection(ccx.lock().await.global_context.clone()).await?),
+                    None => None,
+                };
+                apply_migration(store, &self.migrations_target(), &sql_query_arg(args)?, down.as_deref(), exec).await?
+            }
+            SqlMode::Rollback => {
+                let store = memdb_connection(ccx.lock().await.global_context.clone()).await?;
+                rollback_migration(store, &self.migrations_target(), exec).await?
+            }
         };
 
-        let result = self.run_mysql_command(&query).await?;
-
         let mut results = vec![];
         results.push(ContextEnum::ChatMessage(ChatMessage {
             role: "tool".to_string(),
@@ -149,12 +292,20 @@ impl Tool for ToolMysql {
         &self,
         args: &HashMap<String, Value>,
     ) -> Result<String, String> {
-        let query = match args.get("query") {
-            Some(Value::String(v)) => v.clone(),
-            Some(v) => return Err(format!("argument `query` is not a string: {:?}", v)),
-            None => return Err("no `query` argument found".to_string()),
-        };
-        Ok(format!("mysql {}", query))
+        sql_command_to_match("mysql", args, None)
+    }
+
+    async fn match_against_confirm_deny(
+        &self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        args: &HashMap<String, Value>,
+    ) -> Result<MatchConfirmDeny, String> {
+        let gcx = ccx.lock().await.global_context.clone();
+        let rollback_down = rollback_down_to_confirm(gcx, args, &self.migrations_target()).await;
+        let command_to_match = sql_command_to_match("mysql", args, rollback_down.as_deref()).map_err(|e| {
+            format!("Error getting tool command to match: {}", e)
+        })?;
+        Ok(migrations_always_ask(match_command_against_rules(&command_to_match, self.confirm_deny_rules()), args))
     }
 
     fn tool_depends_on(&self) -> Vec<String> {
@@ -201,11 +352,11 @@ fields:
   database:
     f_type: string_short
     f_placeholder: "mysql"
-  mysql_binary_path:
-    f_type: string_long
-    f_desc: "If it can't find a path to `mysql` you can provide it here, leave blank if not sure."
-    f_placeholder: "mysql"
-    f_label: "MYSQL Binary Path"
+  ssl_mode:
+    f_type: string_short
+    f_desc: "DISABLED, REQUIRED, VERIFY_CA or VERIFY_IDENTITY, the same as mysql --ssl-mode. Leave blank for no TLS."
+    f_placeholder: "REQUIRED"
+    f_label: "SSL Mode"
     f_extra: true
 description: |
   The Mysql tool is for the AI model to call, when it wants to look at data inside your database, or make any changes.
@@ -216,7 +367,7 @@ available:
   on_your_laptop_possible: true
   when_isolated_possible: true
 confirmation:
-  ask_user_default: []
+  ask_user_default: ["mysql*[!SELECT]*"]
   deny_default: []
 smartlinks:
   - sl_label: "Test"
@@ -258,3 +409,33 @@ docker:
           content: |
             🔧 Your job is to modify mysql connection config in the current file to match the variables from the container, use docker tool to inspect the container if needed. Current config file: %CURRENT_CONFIG%.
 "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mysql_settings_and_result_format() {
+        // old configs still have mysql_binary_path, ssl_mode is optional
+        let settings: SettingsMysql = serde_json::from_value(serde_json::json!({
+            "host": "127.0.0.1", "port": "3306", "user": "root", "password": "x", "database": "shop", "mysql_binary_path": "/usr/bin/mysql",
+        })).unwrap();
+        assert_eq!(settings.ssl_mode, "");
+        assert!(ssl_opts_from_mode(&settings.ssl_mode).unwrap().is_none());
+        assert!(ssl_opts_from_mode("verify_identity").unwrap().is_some());
+        assert!(ssl_opts_from_mode("PREFERRED").is_err());
+        assert!(mysql_pool_from_settings(&SettingsMysql { port: "abc".to_string(), ..settings.clone() }).is_err());
+
+        assert_eq!(mysql_value_to_string(&mysql_async::Value::NULL), "NULL");
+        assert_eq!(mysql_value_to_string(&mysql_async::Value::Bytes(b"goat".to_vec())), "goat");
+        assert_eq!(mysql_value_to_string(&mysql_async::Value::Date(2024, 3, 9, 0, 0, 0, 0)), "2024-03-09");
+        assert_eq!(mysql_value_to_string(&mysql_async::Value::Time(true, 1, 2, 3, 4, 0)), "-26:03:04");
+
+        let columns = vec!["id".to_string(), "name".to_string()];
+        let rows = vec![vec!["1".to_string(), "goat".to_string()], vec!["2".to_string(), "NULL".to_string()]];
+        assert_eq!(format_result_set(&columns, &rows, 0), "id\tname\n1\tgoat\n2\tNULL\n");
+        // a SELECT that found nothing
+        assert_eq!(format_result_set(&columns, &[], 0), "id\tname\n");
+        assert_eq!(format_result_set(&[], &[], 3), "OK, 3 rows affected\n");
+    }
+}
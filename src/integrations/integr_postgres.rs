@@ -2,7 +2,8 @@ use crate::at_commands::at_commands::AtCommandsContext;
 use crate::call_validation::ContextEnum;
 use crate::call_validation::{ChatContent, ChatMessage, ChatUsage};
 use crate::integrations::go_to_configuration_message;
-use crate::tools::tools_description::Tool;
+use crate::integrations::sql_migrations::{apply_migration, memdb_connection, migrations_always_ask, rollback_down_to_confirm, rollback_migration, sql_command_to_match, sql_down_arg, sql_query_arg, SqlMode};
+use crate::tools::tools_description::{match_command_against_rules, MatchConfirmDeny, Tool};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -75,8 +76,71 @@ impl IntegrationTrait for ToolPostgres {
     }
 }
 
+const PSQL_RECONNECT_ATTEMPTS: usize = 3;
+const PSQL_RECONNECT_DELAY_MS: u64 = 1000;
+
+fn is_connection_error(stderr: &str) -> bool {
+    // psql connects anew for each query, these happen before the query is sent, so running it again is safe
+    let stderr = stderr.to_lowercase();
+    (stderr.contains("connection to server") && stderr.contains("failed")) ||
+        stderr.contains("could not connect to server") ||
+        stderr.contains("the database system is starting up") ||
+        stderr.contains("the database system is shutting down")
+}
+
+fn is_connection_lost(stderr: &str) -> bool {
+    // the query might have run already, the server just couldn't report back
+    let stderr = stderr.to_lowercase();
+    stderr.contains("server closed the connection unexpectedly") ||
+        stderr.contains("terminating connection") ||
+        stderr.contains("no connection to the server")
+}
+
+fn is_read_only_query(sql: &str) -> bool {
+    // a single SELECT, SHOW or plain EXPLAIN, anything else might change data if it runs twice
+    let sql = sql.trim().trim_end_matches(';').trim().to_lowercase();
+    if sql.contains(';') {
+        return false;
+    }
+    let words: Vec<&str> = sql.split_whitespace().collect();
+    match words.first() {
+        Some(&"select") => !words.contains(&"into") && !words.contains(&"update"),
+        Some(&"show") => true,
+        Some(&"explain") => !words.contains(&"analyze") && !words.iter().any(|w| w.starts_with("(analyze")),
+        _ => false,
+    }
+}
+
+fn is_safe_to_retry(stderr: &str, query: &str) -> bool {
+    is_connection_error(stderr) || (is_connection_lost(stderr) && is_read_only_query(query))
+}
+
 impl ToolPostgres {
+    fn migrations_target(&self) -> String {
+        format!("postgres {}@{}:{}/{}", self.settings_postgres.user, self.settings_postgres.host, self.settings_postgres.port, self.settings_postgres.database)
+    }
+
     async fn run_psql_command(&self, query: &str) -> Result<String, String> {
+        let mut attempt = 1;
+        loop {
+            match self.run_psql_once(query).await {
+                Err((true, stderr_string)) if attempt < PSQL_RECONNECT_ATTEMPTS => {
+                    tracing::info!("postgres connection failed, reconnecting in {}ms, attempt {}/{}:\n{}", PSQL_RECONNECT_DELAY_MS, attempt + 1, PSQL_RECONNECT_ATTEMPTS, stderr_string.trim());
+                    tokio::time::sleep(tokio::time::Duration::from_millis(PSQL_RECONNECT_DELAY_MS)).await;
+                    attempt += 1;
+                }
+                Ok(stdout) => {
+                    if attempt > 1 {
+                        tracing::info!("postgres reconnected after {} attempts", attempt);
+                    }
+                    return Ok(stdout);
+                }
+                Err((_, err)) => return Err(err),
+            }
+        }
+    }
+
+    async fn run_psql_once(&self, query: &str) -> Result<String, (bool, String)> {
         let mut psql_command = self.settings_postgres.psql_binary_path.clone();
         if psql_command.is_empty() {
             psql_command = "psql".to_string();
@@ -97,7 +161,7 @@ impl ToolPostgres {
             if output.is_err() {
                 let err_text = format!("{}", output.unwrap_err());
                 tracing::error!("psql didn't work:\n{}\n{}", query, err_text);
-                return Err(format!("{}, psql failed:\n{}", go_to_configuration_message("postgres"), err_text));
+                return Err((false, format!("{}, psql failed:\n{}", go_to_configuration_message("postgres"), err_text)));
             }
             let output = output.unwrap();
             if output.status.success() {
@@ -106,11 +170,11 @@ impl ToolPostgres {
                 // XXX: limit stderr, can be infinite
                 let stderr_string = String::from_utf8_lossy(&output.stderr);
                 tracing::error!("psql didn't work:\n{}\n{}", query, stderr_string);
-                Err(format!("{}, psql failed:\n{}", go_to_configuration_message("postgres"), stderr_string))
+                Err((is_safe_to_retry(&stderr_string, query), format!("{}, psql failed:\n{}", go_to_configuration_message("postgres"), stderr_string)))
             }
         } else {
             tracing::error!("psql timed out:\n{}", query);
-            Err("psql command timed out".to_string())
+            Err((false, "psql command timed out".to_string()))
         }
     }
 }
@@ -121,18 +185,29 @@ impl Tool for ToolPostgres {
 
     async fn tool_execute(
         &mut self,
-        _ccx: Arc<AMutex<AtCommandsContext>>,
+        ccx: Arc<AMutex<AtCommandsContext>>,
         tool_call_id: &String,
         args: &HashMap<String, Value>,
     ) -> Result<(bool, Vec<ContextEnum>), String> {
-        let query = match args.get("query") {
-            Some(Value::String(v)) => v.clone(),
-            Some(v) => return Err(format!("argument `query` is not a string: {:?}", v)),
-            None => return Err("no `query` argument found".to_string()),
+        let mode = SqlMode::from_args(args)?;
+        let this = &*self;
+        let exec = |sql: String| async move { this.run_psql_command(&sql).await };
+        let result = match mode {
+            SqlMode::Query => self.run_psql_command(&sql_query_arg(args)?).await?,
+            SqlMode::Migrate => {
+                let down = sql_down_arg(args)?;
+                let store = match down {
+                    Some(_) => Some(memdb_connection(ccx.lock().await.global_context.clone()).await?),
+                    None => None,
+                };
+                apply_migration(store, &self.migrations_target(), &sql_query_arg(args)?, down.as_deref(), exec).await?
+            }
+            SqlMode::Rollback => {
+                let store = memdb_connection(ccx.lock().await.global_context.clone()).await?;
+                rollback_migration(store, &self.migrations_target(), exec).await?
+            }
         };
 
-        let result = self.run_psql_command(&query).await?;
-
         let mut results = vec![];
         results.push(ContextEnum::ChatMessage(ChatMessage {
             role: "tool".to_string(),
@@ -148,12 +223,20 @@ impl Tool for ToolPostgres {
         &self,
         args: &HashMap<String, Value>,
     ) -> Result<String, String> {
-        let query = match args.get("query") {
-            Some(Value::String(v)) => v.clone(),
-            Some(v) => return Err(format!("argument `query` is not a string: {:?}", v)),
-            None => return Err("no `query` argument found".to_string()),
-        };
-        Ok(format!("psql {}", query))
+        sql_command_to_match("psql", args, None)
+    }
+
+    async fn match_against_confirm_deny(
+        &self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        args: &HashMap<String, Value>,
+    ) -> Result<MatchConfirmDeny, String> {
+        let gcx = ccx.lock().await.global_context.clone();
+        let rollback_down = rollback_down_to_confirm(gcx, args, &self.migrations_target()).await;
+        let command_to_match = sql_command_to_match("psql", args, rollback_down.as_deref()).map_err(|e| {
+            format!("Error getting tool command to match: {}", e)
+        })?;
+        Ok(migrations_always_ask(match_command_against_rules(&command_to_match, self.confirm_deny_rules()), args))
     }
 
     fn tool_depends_on(&self) -> Vec<String> {
@@ -260,3 +343,59 @@ docker:
 "#;
 
 // To think about: PGPASSWORD PGHOST PGUSER PGPORT PGDATABASE maybe tell the model to set that in variables.yaml as well
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_reconnect_after_dropped_connection() {
+        use std::os::unix::fs::PermissionsExt;
+        // fake psql: the server is gone for the first call, back for the second one
+        let dir = tempfile::tempdir().unwrap();
+        let fake_psql = dir.path().join("psql");
+        let marker = dir.path().join("was-down");
+        let marker_lost = dir.path().join("runs");
+        std::fs::write(&fake_psql, format!(r#"#!/bin/sh
+if [ ! -f "{marker}" ]; then
+    touch "{marker}"
+    echo 'psql: error: connection to server at "127.0.0.1", port 5432 failed: server closed the connection unexpectedly' >&2
+    exit 2
+fi
+echo " answer"
+echo "--------"
+echo "      42"
+"#, marker = marker.display())).unwrap();
+        std::fs::set_permissions(&fake_psql, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let tool = ToolPostgres {
+            settings_postgres: SettingsPostgres {
+                psql_binary_path: fake_psql.to_string_lossy().to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = tool.run_psql_command("SELECT 42 AS answer").await.unwrap();
+        assert!(result.contains("42"), "{}", result);
+        assert!(marker.exists());
+
+        // errors in the query itself are not retried
+        std::fs::write(&fake_psql, "#!/bin/sh\necho 'ERROR:  relation \"frogs\" does not exist' >&2\nexit 1\n").unwrap();
+        let err = tool.run_psql_command("SELECT * FROM frogs").await.unwrap_err();
+        assert!(err.contains("does not exist"), "{}", err);
+        assert!(!is_connection_error(&err));
+
+        // the connection dropped while the query was running: a SELECT runs again, an INSERT doesn't
+        let lost = "server closed the connection unexpectedly\n\tThis probably means the server terminated abnormally\n";
+        assert!(is_safe_to_retry(lost, "SELECT count(*) FROM frogs;"));
+        assert!(!is_safe_to_retry(lost, "INSERT INTO frogs VALUES (1)"));
+        assert!(!is_safe_to_retry(lost, "SELECT 1; DELETE FROM frogs"));
+        assert!(!is_safe_to_retry(lost, "SELECT * INTO frogs_copy FROM frogs"));
+        assert!(!is_safe_to_retry(lost, "EXPLAIN ANALYZE DELETE FROM frogs"));
+        std::fs::write(&marker_lost, "").ok();
+        std::fs::write(&fake_psql, format!("#!/bin/sh\necho run >> \"{}\"\necho '{}' >&2\nexit 2\n", marker_lost.display(), lost.lines().next().unwrap())).unwrap();
+        assert!(tool.run_psql_command("INSERT INTO frogs VALUES (1)").await.is_err());
+        assert_eq!(std::fs::read_to_string(&marker_lost).unwrap().lines().count(), 1, "a non-idempotent statement was sent twice");
+    }
+}
@@ -17,7 +17,8 @@ use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
 use crate::postprocessing::pp_command_output::CmdlineOutputFilter;
 use crate::integrations::integr_abstract::{IntegrationCommon, IntegrationTrait};
 use crate::integrations::setting_up_integrations::YamlError;
-use crate::tools::tools_execute::command_should_be_denied;
+use crate::tools::tools_execute::{command_should_be_denied, ToolOutputStreamer};
+use crate::integrations::process_io_utils::wait_with_output_streaming;
 
 
 #[derive(Deserialize, Serialize, Clone, Default)]
@@ -96,14 +97,20 @@ impl Tool for ToolShell {
         let mut error_log = Vec::<YamlError>::new();
         let env_variables = crate::integrations::setting_up_integrations::get_vars_for_replacements(gcx.clone(), &mut error_log).await;
 
-        let tool_output = execute_shell_command(
+        let mut streamer = Some(ToolOutputStreamer::new(ccx.clone(), tool_call_id).await);
+        let tool_output_maybe = execute_shell_command(
             &command,
             &workdir_maybe,
             timeout,
             &self.cfg.output_filter,
             &env_variables,
             gcx.clone(),
-        ).await?;
+            &mut streamer,
+        ).await;
+        if let Some(streamer) = streamer {
+            streamer.finished().await;
+        }
+        let tool_output = tool_output_maybe?;
 
         let result = vec![ContextEnum::ChatMessage(ChatMessage {
             role: "tool".to_string(),
@@ -194,7 +201,21 @@ pub async fn execute_shell_command(
     output_filter: &CmdlineOutputFilter,
     env_variables: &HashMap<String, String>,
     gcx: Arc<ARwLock<GlobalContext>>,
+    streamer: &mut Option<ToolOutputStreamer>,
 ) -> Result<String, String> {
+    execute_shell_command_with_exit_code(command, workdir_maybe, timeout, output_filter, env_variables, gcx, streamer).await.map(|(out, _)| out)
+}
+
+// Same, for callers that decide something based on the exit code, the output mentions it anyway
+pub async fn execute_shell_command_with_exit_code(
+    command: &str,
+    workdir_maybe: &Option<PathBuf>,
+    timeout: u64,
+    output_filter: &CmdlineOutputFilter,
+    env_variables: &HashMap<String, String>,
+    gcx: Arc<ARwLock<GlobalContext>>,
+    streamer: &mut Option<ToolOutputStreamer>,
+) -> Result<(String, i32), String> {
     let shell = if cfg!(target_os = "windows") { "powershell.exe" } else { "sh" };
     let shell_arg = if cfg!(target_os = "windows") { "-Command" } else { "-c" };
     let mut cmd = Command::new(shell);
@@ -215,26 +236,28 @@ pub async fn execute_shell_command(
     cmd.stdin(Stdio::null());
     cmd.stdout(Stdio::piped());
     cmd.stderr(Stdio::piped());
+    cmd.kill_on_drop(true);
 
     let t0 = tokio::time::Instant::now();
     tracing::info!("SHELL: running command directory {:?}\n{:?}", workdir_maybe, command);
-    let output = tokio::time::timeout(tokio::time::Duration::from_secs(timeout), cmd.output())
+    let child = cmd.spawn().map_err(|e| format!("Failed to execute command: {}", e))?;
+    let (stdout_bytes, stderr_bytes, status) = tokio::time::timeout(tokio::time::Duration::from_secs(timeout), wait_with_output_streaming(child, streamer))
         .await
         .map_err(|_| format!("Command timed out after {} seconds", timeout))?
         .map_err(|e| format!("Failed to execute command: {}", e))?;
     let duration = t0.elapsed();
     tracing::info!("SHELL: /finished in {:.3}s", duration.as_secs_f64());
 
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let stdout = String::from_utf8_lossy(&stdout_bytes).to_string();
+    let stderr = String::from_utf8_lossy(&stderr_bytes).to_string();
 
     let filtered_stdout = crate::postprocessing::pp_command_output::output_mini_postprocessing(output_filter, &stdout);
     let filtered_stderr = crate::postprocessing::pp_command_output::output_mini_postprocessing(output_filter, &stderr);
 
     let mut out = crate::integrations::integr_cmdline::format_output(&filtered_stdout, &filtered_stderr);
-    let exit_code = output.status.code().unwrap_or_default();
+    let exit_code = status.code().unwrap_or_default();
     out.push_str(&format!("The command was running {:.3}s, finished with exit code {exit_code}\n", duration.as_secs_f64()));
-    Ok(out)
+    Ok((out, exit_code))
 }
 
 fn parse_args(args: &HashMap<String, Value>) -> Result<(String, Option<PathBuf>), String> {
@@ -15,6 +15,7 @@ pub mod integr_pdb;
 pub mod integr_chrome;
 pub mod integr_postgres;
 pub mod integr_mysql;
+pub mod sql_migrations;
 pub mod integr_cmdline;
 pub mod integr_cmdline_service;
 pub mod integr_shell;
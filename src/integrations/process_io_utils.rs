@@ -5,6 +5,8 @@ use tokio::time::Duration;
 use std::time::Instant;
 use tracing::error;
 
+use crate::tools::tools_execute::ToolOutputStreamer;
+
 
 pub async fn write_to_stdin_and_flush(stdin: &mut ChildStdin, text_to_write: &str) -> Result<(), String>
 {
@@ -73,6 +75,68 @@ pub async fn blocking_read_until_token_or_timeout<
     Ok((String::from_utf8_lossy(&output).to_string(), String::from_utf8_lossy(&error).to_string(), have_the_token))
 }
 
+async fn _stream_complete_lines(pending: &mut Vec<u8>, streamer: &mut Option<ToolOutputStreamer>, flush_all: bool) {
+    let cut = if flush_all {
+        pending.len()
+    } else {
+        match pending.iter().rposition(|b| *b == b'\n') {
+            Some(pos) => pos + 1,
+            None => return,
+        }
+    };
+    let ready = pending.drain(..cut).collect::<Vec<u8>>();
+    if let Some(streamer) = streamer.as_mut() {
+        if !ready.is_empty() {
+            streamer.partial(&String::from_utf8_lossy(&ready)).await;
+        }
+    }
+}
+
+// Like child.wait_with_output(), but forwards complete lines to the streamer as soon as they appear
+pub async fn wait_with_output_streaming(
+    mut child: tokio::process::Child,
+    streamer: &mut Option<ToolOutputStreamer>,
+) -> Result<(Vec<u8>, Vec<u8>, std::process::ExitStatus), String> {
+    let mut stdout = child.stdout.take().ok_or("stdout is not piped".to_string())?;
+    let mut stderr = child.stderr.take().ok_or("stderr is not piped".to_string())?;
+    let mut output = Vec::new();
+    let mut error = Vec::new();
+    let mut pending = Vec::new();
+    let mut output_buf = [0u8; 4096];
+    let mut error_buf = [0u8; 4096];
+    let (mut stdout_done, mut stderr_done) = (false, false);
+
+    while !stdout_done || !stderr_done {
+        tokio::select! {
+            stdout_result = stdout.read(&mut output_buf), if !stdout_done => {
+                match stdout_result {
+                    Ok(0) => stdout_done = true,
+                    Ok(bytes_read) => {
+                        output.extend_from_slice(&output_buf[..bytes_read]);
+                        pending.extend_from_slice(&output_buf[..bytes_read]);
+                    },
+                    Err(e) => return Err(format!("Error reading from stdout: {}", e)),
+                }
+            },
+            stderr_result = stderr.read(&mut error_buf), if !stderr_done => {
+                match stderr_result {
+                    Ok(0) => stderr_done = true,
+                    Ok(bytes_read) => {
+                        error.extend_from_slice(&error_buf[..bytes_read]);
+                        pending.extend_from_slice(&error_buf[..bytes_read]);
+                    },
+                    Err(e) => return Err(format!("Error reading from stderr: {}", e)),
+                }
+            },
+        }
+        _stream_complete_lines(&mut pending, streamer, false).await;
+    }
+    _stream_complete_lines(&mut pending, streamer, true).await;
+
+    let status = child.wait().await.map_err(|e| format!("Error waiting for the process: {}", e))?;
+    Ok((output, error, status))
+}
+
 pub async fn is_someone_listening_on_that_tcp_port(port: u16, timeout: tokio::time::Duration) -> bool {
     match tokio::time::timeout(timeout, TcpStream::connect(&format!("127.0.0.1:{}", port))).await {
         Ok(Ok(_)) => true,    // Connection successful
@@ -110,3 +174,48 @@ pub fn last_n_lines(msg: &str, n: usize) -> String {
 
     output
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::process::Stdio;
+    use tokio::sync::{mpsc, Mutex as AMutex};
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn test_long_output_streams_multiple_chunks() {
+        let (tx, mut rx) = mpsc::unbounded_channel::<serde_json::Value>();
+        let mut streamer = Some(ToolOutputStreamer {
+            subchat_tx: Arc::new(AMutex::new(tx)),
+            tool_call_id: "call_123".to_string(),
+            chunks_sent: 0,
+        });
+        let child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg("seq 1 5000")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let (stdout, _stderr, status) = wait_with_output_streaming(child, &mut streamer).await.unwrap();
+        streamer.unwrap().finished().await;
+        assert!(status.success());
+
+        let mut messages = vec![];
+        while let Ok(message) = rx.try_recv() {
+            messages.push(message);
+        }
+        assert!(messages.len() > 2, "expected multiple partial chunks, got {}", messages.len());
+        assert!(messages.iter().all(|m| m["tool_call_id"] == "call_123"));
+
+        let (last, partials) = messages.split_last().unwrap();
+        assert_eq!(last["tool_output_finished"]["chunks_total"], partials.len());
+        let mut collected = String::new();
+        for (i, m) in partials.iter().enumerate() {
+            assert_eq!(m["tool_output_partial"]["chunk_n"], i);
+            collected.push_str(m["tool_output_partial"]["content"].as_str().unwrap());
+        }
+        assert_eq!(collected, String::from_utf8_lossy(&stdout));
+    }
+}
@@ -1,4 +1,4 @@
-// use std::path::PathBuf;
+use std::path::PathBuf;
 use std::sync::Arc;
 use indexmap::IndexMap;
 use tokio::sync::RwLock as ARwLock;
@@ -6,6 +6,7 @@ use tokio::sync::RwLock as ARwLock;
 use crate::tools::tools_description::Tool;
 use crate::global_context::GlobalContext;
 use crate::integrations::integr_abstract::IntegrationTrait;
+use crate::integrations::setting_up_integrations::{IntegrationRecord, integration_record_scope};
 
 pub async fn load_integration_tools(
     gcx: Arc<ARwLock<GlobalContext>>,
@@ -30,6 +31,31 @@ pub async fn load_integration_tools(
     tools
 }
 
+pub fn pick_records_for_active_path(
+    records: Vec<IntegrationRecord>,
+    active_path: &Option<PathBuf>,
+    is_inside_container: bool,
+) -> Vec<IntegrationRecord> {
+    // several records can define the same integration: per-project, scoped with applies_to, global fallback; keep the most specific one
+    // among those that exist and are enabled where we run
+    let mut best: IndexMap<String, (usize, IntegrationRecord)> = IndexMap::new();
+    for rec in records {
+        let enabled_here = if is_inside_container { rec.when_isolated } else { rec.on_your_laptop };
+        if !rec.integr_config_exists || !enabled_here {
+            continue;
+        }
+        let scope = match integration_record_scope(&rec, active_path) {
+            Some(scope) => scope,
+            None => continue,
+        };
+        match best.get(&rec.integr_name) {
+            Some((best_scope, _)) if *best_scope >= scope => {},
+            _ => { best.insert(rec.integr_name.clone(), (scope, rec)); }
+        }
+    }
+    best.into_values().map(|(_, rec)| rec).collect()
+}
+
 pub async fn load_integrations(
     gcx: Arc<ARwLock<GlobalContext>>,
     allow_experimental: bool,
@@ -44,6 +70,7 @@ pub async fn load_integrations(
     let mut error_log: Vec<crate::integrations::setting_up_integrations::YamlError> = Vec::new();
     let lst: Vec<&str> = crate::integrations::integrations_list(allow_experimental);
     let vars_for_replacements = crate::integrations::setting_up_integrations::get_vars_for_replacements(gcx.clone(), &mut error_log).await;
+    let active_path = gcx.read().await.documents_state.active_file_path.clone().or(active_project_path.clone());
     let records = crate::integrations::setting_up_integrations::read_integrations_d(
         &config_dirs,
         &global_config_dir,
@@ -52,12 +79,10 @@ pub async fn load_integrations(
         &lst,
         &mut error_log,
     );
+    let records = pick_records_for_active_path(records, &active_path, is_inside_container);
 
     let mut integrations_map = IndexMap::new();
     for rec in records {
-        if !is_inside_container && !rec.on_your_laptop { continue; }
-        if is_inside_container && !rec.when_isolated { continue; }
-        if !rec.integr_config_exists { continue; }
         let mut integr = match crate::integrations::integration_from_name(&rec.integr_name) {
             Ok(x) => x,
             Err(e) => {
@@ -88,3 +113,64 @@ pub async fn load_integrations(
 
     (integrations_map, error_log)
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn write_github_yaml(config_dir: &PathBuf, token: &str, applies_to: &str) {
+        std::fs::create_dir_all(config_dir.join("integrations.d")).unwrap();
+        std::fs::write(
+            config_dir.join("integrations.d").join("github.yaml"),
+            format!("GH_TOKEN: {}\n{}", token, applies_to),
+        ).unwrap();
+    }
+
+    #[test]
+    fn test_integration_picked_by_active_folder() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_a = tmp.path().join("repo_a");
+        let repo_b = tmp.path().join("repo_b");
+        let global_config_dir = tmp.path().join("home").join(".config").join("refact");
+        write_github_yaml(&repo_a.join(".refact"), "token_a", "");
+        write_github_yaml(&repo_b.join(".refact"), "token_b", "");
+        write_github_yaml(&global_config_dir, "token_global", "");
+
+        let mut error_log = vec![];
+        let records = crate::integrations::setting_up_integrations::read_integrations_d(
+            &vec![repo_a.join(".refact"), repo_b.join(".refact")],
+            &global_config_dir,
+            &"".to_string(),
+            &HashMap::new(),
+            &["github"],
+            &mut error_log,
+        );
+        assert!(error_log.is_empty());
+
+        let token_for = |active_path: Option<PathBuf>| {
+            let picked = pick_records_for_active_path(records.clone(), &active_path, false);
+            assert_eq!(picked.len(), 1);
+            picked[0].config_unparsed["GH_TOKEN"].as_str().unwrap().to_string()
+        };
+        assert_eq!(token_for(Some(repo_a.join("src").join("main.rs"))), "token_a");
+        assert_eq!(token_for(Some(repo_b.join("lib.py"))), "token_b");
+        assert_eq!(token_for(Some(tmp.path().join("elsewhere").join("x.rs"))), "token_global");
+    }
+
+    #[test]
+    fn test_global_integration_with_applies_to() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_a = tmp.path().join("repo_a");
+        let global_config_dir = tmp.path().join("home").join(".config").join("refact");
+        write_github_yaml(&global_config_dir, "token_scoped", &format!("applies_to:\n  - {}\n", repo_a.display()));
+
+        let mut error_log = vec![];
+        let records = crate::integrations::setting_up_integrations::read_integrations_d(
+            &vec![], &global_config_dir, &"".to_string(), &HashMap::new(), &["github"], &mut error_log,
+        );
+        assert_eq!(pick_records_for_active_path(records.clone(), &Some(repo_a.join("main.rs")), false).len(), 1);
+        assert!(pick_records_for_active_path(records.clone(), &Some(tmp.path().join("repo_b").join("main.rs")), false).is_empty());
+    }
+}
@@ -32,6 +32,7 @@ pub struct IntegrationRecord {
     pub when_isolated: bool,
     pub ask_user: Vec<String>,
     pub deny: Vec<String>,
+    pub applies_to: Vec<String>,
     #[serde(skip_serializing)]
     pub config_unparsed: serde_json::Value,
 }
@@ -262,9 +263,45 @@ pub fn read_integrations_d(
         }
     }
 
+    // 6. Fill applies_to, relative folders are relative to the project
+    for rec in &mut result {
+        let applies_to = match rec.config_unparsed.get("applies_to") {
+            Some(serde_json::Value::String(s)) => vec![s.clone()],
+            Some(_) => get_array_of_str_or_empty(&rec.config_unparsed, "/applies_to"),
+            None => vec![],
+        };
+        rec.applies_to = applies_to.into_iter().filter(|x| !x.trim().is_empty()).map(|folder| {
+            let folder_path = PathBuf::from(folder.trim());
+            if folder_path.is_relative() && !rec.project_path.is_empty() {
+                PathBuf::from(&rec.project_path).join(folder_path).to_string_lossy().to_string()
+            } else {
+                folder_path.to_string_lossy().to_string()
+            }
+        }).collect();
+    }
+
     result
 }
 
+pub fn integration_record_scope(rec: &IntegrationRecord, active_path: &Option<PathBuf>) -> Option<usize> {
+    // None if the record doesn't apply, otherwise how specific it is: the longer the matching folder the better, global is 0
+    if !rec.applies_to.is_empty() {
+        let active_path = active_path.as_ref()?;
+        return rec.applies_to.iter()
+            .filter(|folder| active_path.starts_with(folder))
+            .map(|folder| folder.len())
+            .max();
+    }
+    if rec.project_path.is_empty() {
+        return Some(0);
+    }
+    match active_path {
+        Some(active_path) if active_path.starts_with(&rec.project_path) => Some(rec.project_path.len()),
+        Some(_) => None,
+        None => Some(rec.project_path.len()),
+    }
+}
+
 pub async fn get_vars_for_replacements(
     gcx: Arc<ARwLock<GlobalContext>>,
     error_log: &mut Vec<YamlError>,
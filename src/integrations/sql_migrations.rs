@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use parking_lot::Mutex as ParkMutex;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use tokio::sync::RwLock as ARwLock;
+
+use crate::global_context::GlobalContext;
+use crate::tools::tools_description::{MatchConfirmDeny, MatchConfirmDenyResult};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlMode {
+    Query,
+    Migrate,    // `query` is the up statement, runs in a transaction, `down` is kept for a later rollback
+    Rollback,   // runs the stored down of the latest migration that isn't rolled back yet
+}
+
+impl SqlMode {
+    pub fn from_args(args: &HashMap<String, Value>) -> Result<SqlMode, String> {
+        match args.get("mode") {
+            Some(Value::String(s)) => match s.trim() {
+                "" | "query" => Ok(SqlMode::Query),
+                "migrate" => Ok(SqlMode::Migrate),
+                "rollback" => Ok(SqlMode::Rollback),
+                _ => Err(format!("argument `mode` should be \"query\", \"migrate\" or \"rollback\", got {:?}", s)),
+            },
+            Some(v) => Err(format!("argument `mode` is not a string: {:?}", v)),
+            None => Ok(SqlMode::Query),
+        }
+    }
+}
+
+pub fn sql_query_arg(args: &HashMap<String, Value>) -> Result<String, String> {
+    match args.get("query") {
+        Some(Value::String(v)) => Ok(v.clone()),
+        Some(v) => Err(format!("argument `query` is not a string: {:?}", v)),
+        None => Err("no `query` argument found".to_string()),
+    }
+}
+
+pub fn sql_down_arg(args: &HashMap<String, Value>) -> Result<Option<String>, String> {
+    match args.get("down") {
+        Some(Value::String(v)) if !v.trim().is_empty() => Ok(Some(v.clone())),
+        Some(Value::String(_)) | None => Ok(None),
+        Some(v) => Err(format!("argument `down` is not a string: {:?}", v)),
+    }
+}
+
+// rollback_down is what a rollback is going to run, the user confirms the statement and deny rules see it
+pub fn sql_command_to_match(binary: &str, args: &HashMap<String, Value>, rollback_down: Option<&str>) -> Result<String, String> {
+    Ok(match SqlMode::from_args(args)? {
+        SqlMode::Query => format!("{} {}", binary, sql_query_arg(args)?),
+        SqlMode::Migrate => format!("{} migrate {}", binary, sql_query_arg(args)?),
+        SqlMode::Rollback => match rollback_down {
+            Some(down) => format!("{} rollback {}", binary, down),
+            None => format!("{} rollback", binary),
+        },
+    })
+}
+
+// None if memdb is not there or there's nothing to roll back, the rollback itself will say why
+pub async fn rollback_down_to_confirm(gcx: Arc<ARwLock<GlobalContext>>, args: &HashMap<String, Value>, target: &str) -> Option<String> {
+    if SqlMode::from_args(args).ok()? != SqlMode::Rollback {
+        return None;
+    }
+    let store = memdb_connection(gcx).await.ok()?;
+    let latest = migration_latest(&store.lock(), target).ok()?;
+    latest.map(|(_, _, down)| down)
+}
+
+// Migrations change the schema, so they ask even if the ask_user rules let the same query through, deny rules still win
+pub fn migrations_always_ask(verdict: MatchConfirmDeny, args: &HashMap<String, Value>) -> MatchConfirmDeny {
+    let is_migration = SqlMode::from_args(args).map(|m| m != SqlMode::Query).unwrap_or(false);
+    if is_migration && matches!(verdict.result, MatchConfirmDenyResult::PASS) {
+        return MatchConfirmDeny {
+            result: MatchConfirmDenyResult::CONFIRMATION,
+            command: verdict.command,
+            rule: "migrate and rollback always ask".to_string(),
+        };
+    }
+    verdict
+}
+
+fn in_transaction(sql: &str) -> String {
+    let sql = sql.trim();
+    let semicolon = if sql.ends_with(';') { "" } else { ";" };
+    format!("BEGIN;\n{}{}\nCOMMIT;\n", sql, semicolon)
+}
+
+fn migrations_create_table(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sql_migrations (
+            migration_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            target TEXT NOT NULL,
+            up_sql TEXT NOT NULL,
+            down_sql TEXT NOT NULL,
+            applied_ts INTEGER NOT NULL,
+            rolled_back INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    ).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn migration_save(conn: &Connection, target: &str, up: &str, down: &str) -> Result<i64, String> {
+    migrations_create_table(conn)?;
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
+    conn.execute(
+        "INSERT INTO sql_migrations (target, up_sql, down_sql, applied_ts) VALUES (?1, ?2, ?3, ?4)",
+        params![target, up, down, now],
+    ).map_err(|e| e.to_string())?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn migration_latest(conn: &Connection, target: &str) -> Result<Option<(i64, String, String)>, String> {
+    migrations_create_table(conn)?;
+    conn.query_row(
+        "SELECT migration_id, up_sql, down_sql FROM sql_migrations WHERE target = ?1 AND rolled_back = 0 ORDER BY migration_id DESC LIMIT 1",
+        params![target],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    ).optional().map_err(|e| e.to_string())
+}
+
+fn migration_mark_rolled_back(conn: &Connection, migration_id: i64) -> Result<(), String> {
+    conn.execute("UPDATE sql_migrations SET rolled_back = 1 WHERE migration_id = ?1", params![migration_id]).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// `target` tells databases apart, a rollback in one never runs a down stored for another
+pub async fn apply_migration<F, Fut>(
+    store: Option<Arc<ParkMutex<Connection>>>,
+    target: &str,
+    up: &str,
+    down: Option<&str>,
+    exec: F,
+) -> Result<String, String>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<String, String>>,
+{
+    if down.is_some() && store.is_none() {
+        return Err("can't keep the `down` statement, memdb is not available, migration is not applied".to_string());
+    }
+    let output = exec(in_transaction(up)).await?;
+    match (down, store) {
+        (Some(down), Some(store)) => {
+            let migration_id = migration_save(&store.lock(), target, up.trim(), down.trim())?;
+            Ok(format!("{}\nMigration #{} applied. To undo it call again with mode=\"rollback\", it will run:\n{}\n", output, migration_id, down.trim()))
+        }
+        _ => Ok(format!("{}\nMigration applied. There was no `down`, so mode=\"rollback\" can't undo it.\n", output)),
+    }
+}
+
+pub async fn rollback_migration<F, Fut>(
+    store: Arc<ParkMutex<Connection>>,
+    target: &str,
+    exec: F,
+) -> Result<String, String>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<String, String>>,
+{
+    let latest = migration_latest(&store.lock(), target)?;
+    let (migration_id, up, down) = latest.ok_or(format!("no migrations with a `down` to roll back for {}", target))?;
+    let output = exec(in_transaction(&down)).await?;
+    migration_mark_rolled_back(&store.lock(), migration_id)?;
+    Ok(format!("{}\nMigration #{} rolled back:\n{}\nundone with:\n{}\n", output, migration_id, up, down))
+}
+
+#[cfg(feature="vecdb")]
+pub async fn memdb_connection(gcx: Arc<ARwLock<GlobalContext>>) -> Result<Arc<ParkMutex<Connection>>, String> {
+    let vec_db = gcx.read().await.vec_db.clone();
+    let memdb = {
+        let vec_db_guard = vec_db.lock().await;
+        vec_db_guard.as_ref().ok_or("memdb is not initialized, vecdb is off or still starting".to_string())?.memdb.clone()
+    };
+    let conn = memdb.lock().await.conn.clone();
+    Ok(conn)
+}
+
+#[cfg(not(feature="vecdb"))]
+pub async fn memdb_connection(_gcx: Arc<ARwLock<GlobalContext>>) -> Result<Arc<ParkMutex<Connection>>, String> {
+    Err("memdb is not available in this build".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_create_table_migration_and_rollback() {
+        let database = Arc::new(ParkMutex::new(Connection::open_in_memory().unwrap()));
+        let store = Arc::new(ParkMutex::new(Connection::open_in_memory().unwrap()));
+        let exec = |sql: String| {
+            let database = database.clone();
+            async move {
+                let db = database.lock();
+                // like psql or mysql exiting on error, the open transaction goes away
+                db.execute_batch(&sql).map(|_| "OK".to_string()).map_err(|e| { let _ = db.execute_batch("ROLLBACK"); e.to_string() })
+            }
+        };
+        let table_exists = |name: &str| database.lock()
+            .query_row("SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name=?1", params![name], |row| row.get::<_, i64>(0))
+            .unwrap() == 1;
+
+        let report = apply_migration(Some(store.clone()), "db1", "CREATE TABLE frogs (name TEXT)", Some("DROP TABLE frogs;"), exec).await.unwrap();
+        assert!(report.contains("Migration #1 applied"), "{}", report);
+        assert!(table_exists("frogs"));
+
+        // the other database has nothing to roll back
+        assert!(rollback_migration(store.clone(), "db2", exec).await.unwrap_err().contains("no migrations"));
+
+        let down = migration_latest(&store.lock(), "db1").unwrap().map(|(_, _, down)| down);
+        let args = HashMap::from([("mode".to_string(), Value::String("rollback".to_string()))]);
+        assert_eq!(sql_command_to_match("psql", &args, down.as_deref()).unwrap(), "psql rollback DROP TABLE frogs;");
+        let report = rollback_migration(store.clone(), "db1", exec).await.unwrap();
+        assert!(report.contains("Migration #1 rolled back"), "{}", report);
+        assert!(!table_exists("frogs"));
+        assert!(rollback_migration(store.clone(), "db1", exec).await.is_err());
+
+        // a failing up changes nothing and stores nothing
+        let err = apply_migration(Some(store.clone()), "db1", "CREATE TABLE toads (name TEXT); INSERT INTO nowhere VALUES (1)", Some("DROP TABLE toads"), exec).await.unwrap_err();
+        assert!(err.contains("nowhere"), "{}", err);
+        assert!(!table_exists("toads"));
+        assert!(migration_latest(&store.lock(), "db1").unwrap().is_none());
+
+        assert!(apply_migration(None, "db1", "CREATE TABLE toads (name TEXT)", Some("DROP TABLE toads"), exec).await.is_err());
+    }
+}
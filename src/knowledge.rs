@@ -16,7 +16,7 @@ use lance::dataset::{WriteMode, WriteParams};
 use reqwest::Client;
 use vectordb::database::Database;
 use tempfile::TempDir;
-use tokio::sync::Mutex as AMutex;
+use tokio::sync::{Mutex as AMutex, RwLock as ARwLock};
 use tokio::time::Instant;
 use vectordb::table::Table;
 
@@ -24,6 +24,7 @@ use crate::vecdb::vdb_cache::VecDBCache;
 use crate::vecdb::vdb_lance::cosine_distance;
 use crate::vecdb::vdb_structs::{MemoRecord, SimpleTextHashVector, VecdbConstants, VecDbStatus};
 use crate::ast::chunk_utils::official_text_hashing_function;
+use crate::global_context::GlobalContext;
 
 
 pub struct MemoriesDatabase {
@@ -48,11 +49,33 @@ fn map_row_to_memo_record(row: &rusqlite::Row) -> rusqlite::Result<MemoRecord> {
         mstat_correct: row.get(6)?,
         mstat_relevant: row.get(7)?,
         mstat_times_used: row.get(8)?,
+        m_tags: tags_from_column(&row.get::<_, String>(9)?),
     })
 }
 
 fn fields_ordered() -> String {
-    "memid,m_type,m_goal,m_project,m_payload,m_origin,mstat_correct,mstat_relevant,mstat_times_used".to_string()
+    "memid,m_type,m_goal,m_project,m_payload,m_origin,mstat_correct,mstat_relevant,mstat_times_used,m_tags".to_string()
+}
+
+// "Architecture", " architecture " and "architecture" are the same tag, a comma can't be part of one
+pub fn normalize_tags(tags: &Vec<String>) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+    for tag in tags.iter().flat_map(|t| t.split(',')) {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !result.contains(&tag) {
+            result.push(tag);
+        }
+    }
+    result
+}
+
+// Stored as "architecture,decision", untagged memories from before tags existed have ''
+fn tags_to_column(tags: &Vec<String>) -> String {
+    normalize_tags(tags).join(",")
+}
+
+fn tags_from_column(column: &str) -> Vec<String> {
+    column.split(',').filter(|t| !t.is_empty()).map(|t| t.to_string()).collect()
 }
 
 impl MemoriesDatabase {
@@ -105,11 +128,12 @@ impl MemoriesDatabase {
             dirty_everything: true,
         };
         db._permdb_create_table(reset_memory)?;
-        db._migrate_add_m_origin()?;
+        db._migrate_add_column("m_origin", "TEXT NOT NULL DEFAULT 'refact-standard'")?;
+        db._migrate_add_column("m_tags", "TEXT NOT NULL DEFAULT ''")?;
         Ok(db)
     }
 
-    fn _migrate_add_m_origin(&self) -> Result<(), String> {
+    fn _migrate_add_column(&self, column: &str, definition: &str) -> Result<(), String> {
         let conn = self.conn.lock();
         let mut stmt = conn.prepare("PRAGMA table_info(memories)").map_err(|e| e.to_string())?;
         let column_exists = stmt.query_map([], |row| {
@@ -118,10 +142,10 @@ impl MemoriesDatabase {
         })
             .map_err(|e| e.to_string())?
             .filter_map(|result| result.ok())
-            .any(|column_name| column_name == "m_origin");
+            .any(|column_name| column_name == column);
 
         if !column_exists {
-            conn.execute("ALTER TABLE memories ADD COLUMN m_origin TEXT NOT NULL DEFAULT 'refact-standard'", [])
+            conn.execute(&format!("ALTER TABLE memories ADD COLUMN {} {}", column, definition), [])
                 .map_err(|e| e.to_string())?;
         }
         Ok(())
@@ -142,14 +166,15 @@ impl MemoriesDatabase {
                 m_origin TEXT NOT NULL,
                 mstat_correct REAL NOT NULL DEFAULT 0,
                 mstat_relevant REAL NOT NULL DEFAULT 0,
-                mstat_times_used INTEGER NOT NULL DEFAULT 0
+                mstat_times_used INTEGER NOT NULL DEFAULT 0,
+                m_tags TEXT NOT NULL DEFAULT ''
             )",
             [],
         ).map_err(|e| e.to_string())?;
         Ok(())
     }
 
-    pub fn permdb_add(&self, mem_type: &str, goal: &str, project: &str, payload: &str, m_origin: &str) -> Result<String, String> {
+    pub fn permdb_add(&self, mem_type: &str, goal: &str, project: &str, payload: &str, m_origin: &str, m_tags: &Vec<String>) -> Result<String, String> {
         fn generate_memid() -> String {
             rand::thread_rng()
                 .sample_iter(&rand::distributions::Uniform::new(0, 16))
@@ -161,8 +186,8 @@ impl MemoriesDatabase {
         let conn = self.conn.lock();
         let memid = generate_memid();
         conn.execute(
-            "INSERT INTO memories (memid, m_type, m_goal, m_project, m_payload, m_origin) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![memid, mem_type, goal, project, payload, m_origin],
+            "INSERT INTO memories (memid, m_type, m_goal, m_project, m_payload, m_origin, m_tags) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![memid, mem_type, goal, project, payload, m_origin, tags_to_column(m_tags)],
         ).map_err(|e| e.to_string())?;
         Ok(memid)
     }
@@ -238,6 +263,19 @@ impl MemoriesDatabase {
         rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
     }
 
+    // Most used first, that's the best guess of what matters without a query to rank by
+    pub fn permdb_select_by_tag(&self, tag: &str) -> Result<Vec<MemoRecord>, String> {
+        let tag = normalize_tags(&vec![tag.to_string()]).into_iter().next().ok_or("tag is empty".to_string())?;
+        let conn = self.conn.lock();
+        let query = format!(
+            "SELECT {} FROM memories WHERE instr(',' || m_tags || ',', ',' || ?1 || ',') > 0 ORDER BY mstat_times_used DESC",
+            fields_ordered()
+        );
+        let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params![tag], map_row_to_memo_record).map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+    }
+
     pub async fn permdb_fillout_records(&self, input_records: Vec<MemoRecord>) -> Result<Vec<MemoRecord>, String> {
         let t0 = Instant::now();
         let conn = self.conn.lock();
@@ -266,6 +304,7 @@ impl MemoriesDatabase {
                     record.mstat_correct = db_record.mstat_correct;
                     record.mstat_relevant = db_record.mstat_relevant;
                     record.mstat_times_used = db_record.mstat_times_used;
+                    record.m_tags = db_record.m_tags.clone();
                     Some(record)
                 } else {
                     tracing::warn!("permdb_memids2records() not found memid={}", record.memid);
@@ -321,15 +360,20 @@ pub async fn lance_search(
     memdb: Arc<AMutex<MemoriesDatabase>>,
     embedding: &Vec<f32>,
     top_n: usize,
+    only_memids: Option<&Vec<String>>,
 ) -> vectordb::error::Result<Vec<MemoRecord>> {
     let (my_memories_table, my_schema_arc) = {
         let memdb_locked = memdb.lock().await;
         (memdb_locked.memories_table.clone(), memdb_locked.schema_arc.clone())
     };
+    // memids are generated hex, safe to put into the filter as they are
+    let filter = only_memids.map(|memids| format!("memid IN ({})", memids.iter().map(|m| format!("'{}'", m)).join(",")));
     let query = my_memories_table
         .clone()
         .search(Some(Float32Array::from(embedding.clone())))
         .column("thevec")
+        .prefilter(filter.is_some())
+        .filter(filter)
         .limit(top_n)
         .use_index(true)
         .execute().await?
@@ -345,6 +389,33 @@ pub async fn lance_search(
     }
 }
 
+pub async fn memories_search_by_embedding(
+    memdb: Arc<AMutex<MemoriesDatabase>>,
+    embedding: &Vec<f32>,
+    top_n: usize,
+    only_memids: Option<&Vec<String>>,
+) -> Result<Vec<MemoRecord>, String> {
+    fn calculate_score(distance: f32, _times_used: i32) -> f32 {
+        distance
+        // distance - (times_used as f32) * 0.01
+    }
+
+    if only_memids.map(|m| m.is_empty()).unwrap_or(false) {
+        return Ok(vec![]);
+    }
+    let lance_results = match lance_search(memdb.clone(), embedding, top_n, only_memids).await {
+        Ok(res) => res,
+        Err(err) => { return Err(err.to_string()) }
+    };
+    let mut results: Vec<MemoRecord> = memdb.lock().await.permdb_fillout_records(lance_results).await?;
+    results.sort_by(|a, b| {
+        let score_a = calculate_score(a.distance, a.mstat_times_used);
+        let score_b = calculate_score(b.distance, b.mstat_times_used);
+        score_a.partial_cmp(&score_b).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Ok(results)
+}
+
 async fn recall_dirty_memories_and_mark_them_not_dirty(
     memdb: Arc<AMutex<MemoriesDatabase>>,
 ) -> Result<(Vec<String>, Vec<SimpleTextHashVector>), String> {
@@ -449,7 +520,19 @@ pub async fn vectorize_dirty_memories(
         cache_locked.cache_add_new_records(temp_vec).await.map_err(|e| format!("Failed to update cache: {}", e))?;
     }
 
-    // Save to lance
+    lance_add_memory_vectors(memdb.clone(), memids, &todo).await
+}
+
+// Memories are searched by m_goal, each of todo[] must have a vector already
+pub async fn lance_add_memory_vectors(
+    memdb: Arc<AMutex<MemoriesDatabase>>,
+    memids: Vec<String>,
+    todo: &Vec<SimpleTextHashVector>,
+) -> Result<(), String> {
+    let (embedding_size, my_schema_arc) = {
+        let memdb_locked = memdb.lock().await;
+        (memdb_locked.vecdb_constants.embedding_size, memdb_locked.schema_arc.clone())
+    };
     fn make_emb_data(records: &Vec<SimpleTextHashVector>, embedding_size: i32) -> Result<ArrayData, String> {
         let vec_trait = Arc::new(Field::new("item", DataType::Float32, true));
         let mut emb_builder: Vec<f32> = vec![];
@@ -476,12 +559,11 @@ pub async fn vectorize_dirty_memories(
         }
     }
 
-    let vectors: ArrayData = match make_emb_data(&todo, my_constants.embedding_size) {
+    let vectors: ArrayData = match make_emb_data(todo, embedding_size) {
         Ok(res) => res,
         Err(err) => return Err(format!("{:?}", err))
     };
 
-    let my_schema_arc = memdb.lock().await.schema_arc.clone();
     let data_batches_iter = RecordBatchIterator::new(
         vec![RecordBatch::try_new(
             my_schema_arc.clone(),
@@ -503,6 +585,92 @@ pub async fn vectorize_dirty_memories(
         ).await
     };
     info!("updated {} memories in the database:\n{:?}", todo.len(), data_res);
-
     Ok(())
 }
+
+
+const COMPACTION_CHECK_EVERY: std::time::Duration = std::time::Duration::from_secs(60);
+const COMPACTION_IDLE_BEFORE: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+fn sqlite_size_bytes(conn: &Connection) -> Result<i64, String> {
+    conn.query_row("SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()", [], |row| row.get(0))
+        .map_err(|e| e.to_string())
+}
+
+// Returns the database size before and after, None if skipped because someone is in the middle of a transaction
+pub fn sqlite_compact(conn: &Connection) -> Result<Option<(i64, i64)>, String> {
+    if !conn.is_autocommit() {
+        return Ok(None);
+    }
+    let size_before = sqlite_size_bytes(conn)?;
+    conn.execute_batch("VACUUM; ANALYZE;").map_err(|e| format!("VACUUM failed: {}", e))?;
+    // in WAL mode the main file only shrinks after a checkpoint
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(())).map_err(|e| format!("checkpoint failed: {}", e))?;
+    let size_after = sqlite_size_bytes(conn)?;
+    Ok(Some((size_before, size_after)))
+}
+
+pub async fn memdb_compaction_background_task(gcx: Arc<ARwLock<GlobalContext>>) {
+    let (compact_minutes, tracker) = {
+        let gcx_locked = gcx.read().await;
+        (gcx_locked.cmdline.memdb_compact_minutes, gcx_locked.activity.clone())
+    };
+    if compact_minutes == 0 {
+        return;
+    }
+    let compact_every = std::time::Duration::from_secs(compact_minutes * 60);
+    let mut last_compaction = std::time::Instant::now();
+    loop {
+        tokio::time::sleep(COMPACTION_CHECK_EVERY).await;
+        let now = std::time::Instant::now();
+        if now.duration_since(last_compaction) < compact_every || !tracker.is_idle(now, COMPACTION_IDLE_BEFORE) {
+            continue;
+        }
+        let vec_db = gcx.read().await.vec_db.clone();
+        let conn = match vec_db.lock().await.as_ref() {
+            Some(db) => db.memdb.lock().await.conn.clone(),
+            None => continue,
+        };
+        last_compaction = now;
+        match tokio::task::spawn_blocking(move || sqlite_compact(&conn.lock())).await {
+            Ok(Ok(Some((size_before, size_after)))) => info!("memdb compacted: {} -> {} bytes, reclaimed {}", size_before, size_after, size_before - size_after),
+            Ok(Ok(None)) => info!("memdb compaction skipped, a transaction is active"),
+            Ok(Err(e)) => tracing::warn!("memdb compaction failed: {}", e),
+            Err(e) => tracing::warn!("memdb compaction task failed: {}", e),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vacuum_after_deletes_reduces_file_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let dbpath = dir.path().join("memories.sqlite");
+        let conn = Connection::open(&dbpath).unwrap();
+        let _: String = conn.query_row("PRAGMA journal_mode=WAL", [], |row| row.get(0)).unwrap();
+        conn.execute_batch("CREATE TABLE memories (memid TEXT PRIMARY KEY, m_payload TEXT NOT NULL)").unwrap();
+        let payload = "x".repeat(4000);
+        for i in 0..500 {
+            conn.execute("INSERT INTO memories (memid, m_payload) VALUES (?1, ?2)", params![format!("m{}", i), payload]).unwrap();
+        }
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(())).unwrap();
+        conn.execute("DELETE FROM memories WHERE memid != 'm0'", []).unwrap();
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(())).unwrap();
+        let file_size_before = std::fs::metadata(&dbpath).unwrap().len();
+
+        conn.execute_batch("BEGIN").unwrap();
+        assert_eq!(sqlite_compact(&conn).unwrap(), None);
+        conn.execute_batch("COMMIT").unwrap();
+
+        let (size_before, size_after) = sqlite_compact(&conn).unwrap().unwrap();
+        assert!(size_after < size_before / 10, "{} -> {}", size_before, size_after);
+        let file_size_after = std::fs::metadata(&dbpath).unwrap().len();
+        assert!(file_size_after < file_size_before / 10, "{} -> {}", file_size_before, file_size_after);
+        let left: i64 = conn.query_row("SELECT COUNT(*) FROM memories", [], |row| row.get(0)).unwrap();
+        assert_eq!(left, 1);
+    }
+}
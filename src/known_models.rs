@@ -314,6 +314,7 @@ pub const KNOWN_MODELS: &str = r####"
             "supports_tools": true,
             "supports_multimodality": true,
             "supports_agent": true,
+            "supports_seed": true,
             "supports_scratchpads": {
                 "PASSTHROUGH": {
                 }
@@ -328,6 +329,7 @@ pub const KNOWN_MODELS: &str = r####"
             "n_ctx": 128000,
             "supports_tools": true,
             "supports_multimodality": true,
+            "supports_seed": true,
             "supports_scratchpads": {
                 "PASSTHROUGH": {
                 }
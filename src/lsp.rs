@@ -13,9 +13,10 @@ use tower_lsp::jsonrpc::{Error, Result};
 use tower_lsp::lsp_types::*;
 use tracing::{error, info};
 
-use crate::call_validation::{CodeCompletionInputs, CodeCompletionPost, CursorPosition, SamplingParameters};
+use crate::call_validation::{CodeCompletionInputs, CodeCompletionPost, CursorPosition, DiffChunk, SamplingParameters};
+use crate::diffs::{apply_diff_chunks_to_text, correct_and_validate_chunks, ApplyDiffOutput};
 use crate::files_in_workspace;
-use crate::files_in_workspace::{on_did_change, on_did_delete};
+use crate::files_in_workspace::{on_did_change_incremental, on_did_delete};
 use crate::global_context::{CommandLine, GlobalContext};
 use crate::http::routers::v1::code_completion::handle_v1_code_completion;
 use crate::telemetry::snippets_collection;
@@ -71,6 +72,11 @@ pub struct ChangeActiveFile {
     pub uri: Url,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApplyEditParams {
+    pub chunks: Vec<DiffChunk>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TestHeadTailAddedText {
     pub text_a: String,
@@ -118,6 +124,124 @@ pub struct SuccessRes {
     pub success: bool,
 }
 
+const APPLY_EDIT_MAX_FUZZY_N: usize = 10;
+
+fn path_to_url(path: &str) -> std::result::Result<Url, String> {
+    Url::from_file_path(path).map_err(|_| format!("cannot convert {:?} to a file url", path))
+}
+
+fn utf16_len(s: &str) -> u32 {
+    s.encode_utf16().count() as u32
+}
+
+fn line_start_position(lines: &Vec<&str>, line_n: usize) -> Position {
+    // LSP has no position after the last line if the file doesn't end with a newline, use end of the last line instead
+    if line_n >= lines.len() {
+        if let Some(last) = lines.last() {
+            if !last.ends_with('\n') {
+                return Position::new((lines.len() - 1) as u32, utf16_len(last));
+            }
+        }
+    }
+    Position::new(line_n as u32, 0)
+}
+
+fn text_edits_between(old_text: &str, new_text: &str) -> Vec<TextEdit> {
+    let old_lines: Vec<&str> = old_text.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new_text.split_inclusive('\n').collect();
+    let diff = similar::TextDiff::from_lines(old_text, new_text);
+    let mut edits = vec![];
+    for group in diff.grouped_ops(0) {
+        let (first, last) = match (group.first(), group.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => continue,
+        };
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        edits.push(TextEdit {
+            range: Range::new(line_start_position(&old_lines, old_range.start), line_start_position(&old_lines, old_range.end)),
+            new_text: new_lines[new_range].concat(),
+        });
+    }
+    edits
+}
+
+fn text_document_edit(uri: Url, edits: Vec<TextEdit>) -> DocumentChangeOperation {
+    DocumentChangeOperation::Edit(TextDocumentEdit {
+        text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+        edits: edits.into_iter().map(OneOf::Left).collect(),
+    })
+}
+
+pub fn diff_chunks_to_workspace_edit(
+    chunks: &Vec<DiffChunk>,
+    file_texts: &HashMap<String, String>,
+) -> std::result::Result<WorkspaceEdit, String> {
+    // edits are grouped per file and placed where the file is first mentioned, add/rename/remove keep the chunk order
+    let mut operations = vec![];
+    let mut files_done = std::collections::HashSet::new();
+    for chunk in chunks.iter() {
+        match chunk.file_action.as_str() {
+            "edit" => {
+                if !files_done.insert(chunk.file_name.clone()) {
+                    continue;
+                }
+                let file_text = file_texts.get(&chunk.file_name)
+                    .ok_or(format!("no text for {:?}", chunk.file_name))?;
+                let file_chunks = chunks.iter().enumerate()
+                    .filter(|(_, c)| c.file_action == "edit" && c.file_name == chunk.file_name)
+                    .collect::<Vec<_>>();
+                let (results, outputs) = apply_diff_chunks_to_text(file_text, file_chunks, vec![], APPLY_EDIT_MAX_FUZZY_N);
+                for (chunk_id, output) in outputs.iter() {
+                    if let ApplyDiffOutput::Err(e) = output {
+                        return Err(format!("chunk {} for {:?} cannot be applied: {}", chunk_id, chunk.file_name, e));
+                    }
+                }
+                let new_text = results.into_iter().find_map(|r| r.file_text).unwrap_or(file_text.clone());
+                let edits = text_edits_between(file_text, &new_text);
+                if !edits.is_empty() {
+                    operations.push(text_document_edit(path_to_url(&chunk.file_name)?, edits));
+                }
+            },
+            "add" => {
+                let uri = path_to_url(&chunk.file_name)?;
+                operations.push(DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                    uri: uri.clone(),
+                    options: Some(CreateFileOptions { overwrite: Some(false), ignore_if_exists: Some(false) }),
+                    annotation_id: None,
+                })));
+                if !chunk.lines_add.is_empty() {
+                    operations.push(text_document_edit(uri, vec![TextEdit {
+                        range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+                        new_text: chunk.lines_add.clone(),
+                    }]));
+                }
+            },
+            "rename" => {
+                let old_name = chunk.file_name_rename.clone().ok_or(format!("rename of {:?} has no file_name_rename", chunk.file_name))?;
+                operations.push(DocumentChangeOperation::Op(ResourceOp::Rename(RenameFile {
+                    old_uri: path_to_url(&old_name)?,
+                    new_uri: path_to_url(&chunk.file_name)?,
+                    options: Some(RenameFileOptions { overwrite: Some(false), ignore_if_exists: Some(false) }),
+                    annotation_id: None,
+                })));
+            },
+            "remove" => {
+                operations.push(DocumentChangeOperation::Op(ResourceOp::Delete(DeleteFile {
+                    uri: path_to_url(&chunk.file_name)?,
+                    options: Some(DeleteFileOptions { recursive: Some(!chunk.is_file), ignore_if_not_exists: Some(false), annotation_id: None }),
+                })));
+            },
+            other => return Err(format!("unknown file_action {:?}", other)),
+        }
+    }
+    Ok(WorkspaceEdit {
+        changes: None,
+        document_changes: Some(DocumentChanges::Operations(operations)),
+        change_annotations: None,
+    })
+}
+
 impl LspBackend {
     async fn flat_params_to_code_completion_post(&self, params: &CompletionParams1) -> Result<CodeCompletionPost> {
         let path = crate::files_correction::canonical_path(&params.text_document_position.text_document.uri.to_file_path().unwrap_or_default().display().to_string());
@@ -143,6 +267,7 @@ impl LspBackend {
                 top_p: None,
                 stop: vec![],
                 n: None,
+                ..Default::default()
             },
             model: "".to_string(),
             scratchpad: "".to_string(),
@@ -151,10 +276,12 @@ impl LspBackend {
             use_ast: false,
             use_vecdb: false,
             rag_tokens_n: 0,
+            warmup: false,
         })
     }
 
     pub async fn get_completions(&self, params: CompletionParams1) -> Result<CompletionRes> {
+        let _activity_guard = self.gcx.read().await.activity.request_started();
         let mut post = self.flat_params_to_code_completion_post(&params).await?;
 
         let res = handle_v1_code_completion(self.gcx.clone(), &mut post)
@@ -180,6 +307,27 @@ impl LspBackend {
         Ok(SuccessRes { success: true })
     }
 
+    pub async fn did_change_active_file(&self, params: ChangeActiveFile) {
+        let cpath = crate::files_correction::canonical_path(&params.uri.to_file_path().unwrap_or_default().display().to_string());
+        files_in_workspace::on_did_change_active_file(self.gcx.clone(), &cpath).await;
+    }
+
+    pub async fn apply_edit(&self, params: ApplyEditParams) -> Result<WorkspaceEdit> {
+        // the client applies the result, nothing is written to disk here
+        let mut chunks = params.chunks;
+        correct_and_validate_chunks(self.gcx.clone(), &mut chunks).await.map_err(internal_error)?;
+        let mut file_texts = HashMap::new();
+        for c in chunks.iter().filter(|c| c.file_action == "edit") {
+            if file_texts.contains_key(&c.file_name) {
+                continue;
+            }
+            let text = files_in_workspace::get_file_text_from_memory_or_disk(self.gcx.clone(), &PathBuf::from(&c.file_name)).await
+                .map_err(internal_error)?;
+            file_texts.insert(c.file_name.clone(), text.to_string());
+        }
+        diff_chunks_to_workspace_edit(&chunks, &file_texts).map_err(internal_error)
+    }
+
     async fn ping_http_server(&self) -> Result<()> {
         let (port, http_client) = {
             let gcx_locked = self.gcx.write().await;
@@ -254,8 +402,9 @@ impl LanguageServer for LspBackend {
                 version: Some(VERSION.to_owned()),
             }),
             capabilities: ServerCapabilities {
+                // clients that can't do ranged edits keep sending the full text, on_did_change_incremental takes both
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                    TextDocumentSyncKind::INCREMENTAL,
                 )),
                 completion_provider: Some(completion_options),
                 workspace: Some(WorkspaceServerCapabilities {
@@ -285,6 +434,7 @@ impl LanguageServer for LspBackend {
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        self.gcx.read().await.activity.touch(std::time::Instant::now());
         let cpath = crate::files_correction::canonical_path(&params.text_document.uri.to_file_path().unwrap_or_default().display().to_string());
         if cpath.to_string_lossy().contains("keybindings.json") {
             return;
@@ -293,7 +443,8 @@ impl LanguageServer for LspBackend {
             self.gcx.clone(),
             &cpath,
             &params.text_document.text,
-            &params.text_document.language_id
+            &params.text_document.language_id,
+            Some(params.text_document.version),
         ).await
     }
 
@@ -309,11 +460,13 @@ impl LanguageServer for LspBackend {
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        self.gcx.read().await.activity.touch(std::time::Instant::now());
         let path = crate::files_correction::canonical_path(&params.text_document.uri.to_file_path().unwrap_or_default().display().to_string());
-        on_did_change(
+        on_did_change_incremental(
             self.gcx.clone(),
             &path,
-            &params.content_changes[0].text  // TODO: This text could be just a part of the whole file (if range is not none)
+            Some(params.text_document.version),
+            &params.content_changes,
         ).await
     }
 
@@ -375,6 +528,8 @@ async fn build_lsp_service(
         .custom_method("refact/getCompletions", LspBackend::get_completions)
         .custom_method("refact/acceptCompletion", LspBackend::accept_snippet)
         .custom_method("refact/setActiveDocument", LspBackend::set_active_document)
+        .custom_method("refact/didChangeActiveFile", LspBackend::did_change_active_file)
+        .custom_method("refact/applyEdit", LspBackend::apply_edit)
         .finish();
     (lsp_service, socket)
 }
@@ -436,3 +591,61 @@ pub async fn spawn_lsp_task(
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(file_name: &str, file_action: &str, line1: usize, line2: usize, lines_remove: &str, lines_add: &str) -> DiffChunk {
+        DiffChunk {
+            file_name: file_name.to_string(),
+            file_action: file_action.to_string(),
+            line1,
+            line2,
+            lines_remove: lines_remove.to_string(),
+            lines_add: lines_add.to_string(),
+            file_name_rename: None,
+            is_file: true,
+            application_details: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_diff_chunks_to_workspace_edit() {
+        let file_texts = HashMap::from([
+            ("/tmp/a.py".to_string(), "import os\ndef f():\n    return 1\n\nprint(f())\n".to_string()),
+            ("/tmp/b.py".to_string(), "x = 1\ny = 2".to_string()),
+        ]);
+        let chunks = vec![
+            chunk("/tmp/a.py", "edit", 3, 4, "    return 1\n", "    return 2\n"),
+            chunk("/tmp/b.py", "edit", 2, 3, "y = 2\n", "y = 3\n"),
+            chunk("/tmp/c.py", "add", 1, 1, "", "z = 0\n"),
+        ];
+        let edit = diff_chunks_to_workspace_edit(&chunks, &file_texts).unwrap();
+        let operations = match edit.document_changes {
+            Some(DocumentChanges::Operations(operations)) => operations,
+            _ => panic!("expected document change operations"),
+        };
+        let expected = vec![
+            text_document_edit(Url::from_file_path("/tmp/a.py").unwrap(), vec![TextEdit {
+                range: Range::new(Position::new(2, 0), Position::new(3, 0)),
+                new_text: "    return 2\n".to_string(),
+            }]),
+            // b.py has no trailing newline, the range ends at the end of the last line
+            text_document_edit(Url::from_file_path("/tmp/b.py").unwrap(), vec![TextEdit {
+                range: Range::new(Position::new(1, 0), Position::new(1, 5)),
+                new_text: "y = 3".to_string(),
+            }]),
+            DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                uri: Url::from_file_path("/tmp/c.py").unwrap(),
+                options: Some(CreateFileOptions { overwrite: Some(false), ignore_if_exists: Some(false) }),
+                annotation_id: None,
+            })),
+            text_document_edit(Url::from_file_path("/tmp/c.py").unwrap(), vec![TextEdit {
+                range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+                new_text: "z = 0\n".to_string(),
+            }]),
+        ];
+        assert_eq!(operations, expected);
+    }
+}
@@ -26,6 +26,7 @@ mod caps;
 mod telemetry;
 mod global_context;
 mod background_tasks;
+mod idle_shutdown;
 mod yaml_configs;
 
 mod file_filter;
@@ -41,11 +42,14 @@ mod knowledge;
 
 mod ast;
 mod subchat;
+mod self_critique;
 mod at_commands;
 mod tools;
 mod diffs;
 mod postprocessing;
 mod completion_cache;
+mod completion_warmup;
+mod metrics;
 mod cached_tokenizers;
 mod known_models;
 mod scratchpad_abstract;
@@ -56,6 +60,7 @@ mod fetch_embedding;
 mod forward_to_hf_endpoint;
 mod forward_to_openai_endpoint;
 mod restream;
+mod prompt_log;
 
 mod call_validation;
 mod dashboard;
@@ -73,9 +78,10 @@ async fn main() {
     let cpu_num = std::thread::available_parallelism().unwrap().get();
     rayon::ThreadPoolBuilder::new().num_threads(cpu_num / 2).build_global().unwrap();
     let home_dir = to_pathbuf_normalize(&home::home_dir().ok_or(()).expect("failed to find home dir").to_string_lossy().to_string());
-    let cache_dir = home_dir.join(".cache").join("refact");
+    let default_cache_dir = home_dir.join(".cache").join("refact");
     let config_dir = home_dir.join(".config").join("refact");
-    let (gcx, ask_shutdown_receiver, shutdown_flag, cmdline) = global_context::create_global_context(cache_dir.clone(), config_dir.clone()).await;
+    let (gcx, ask_shutdown_receiver, shutdown_flag, cmdline) = global_context::create_global_context(default_cache_dir, config_dir.clone()).await;
+    let cache_dir = gcx.read().await.cache_dir.clone();
     let mut writer_is_stderr = false;
     let (logs_writer, _guard) = if cmdline.logs_stderr {
         writer_is_stderr = true;
@@ -98,11 +104,12 @@ async fn main() {
     let my_layer = nicer_logs::CustomLayer::new(
         logs_writer.clone(),
         writer_is_stderr,
-        if cmdline.verbose { Level::DEBUG } else { Level::INFO },
+        Level::TRACE,  // levels are decided by the filter below, it can be changed at runtime via /v1/log-level
         Level::ERROR,
         cmdline.lsp_stdin_stdout == 0
     );
     let _tracing = tracing_subscriber::registry()
+        .with(nicer_logs::reloadable_log_filter(if cmdline.verbose { "debug" } else { "info" }))
         .with(my_layer)
         .init();
 
@@ -0,0 +1,180 @@
+use std::fmt::Write;
+use std::sync::Mutex as StdMutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use indexmap::IndexMap;
+
+
+const LATENCY_BUCKETS_SECONDS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+#[derive(Default, Clone)]
+pub struct Histogram {
+    pub bucket_counts: Vec<u64>,  // not cumulative, one per LATENCY_BUCKETS_SECONDS
+    pub count: u64,
+    pub sum: f64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, value: f64) {
+        if self.bucket_counts.len() != LATENCY_BUCKETS_SECONDS.len() {
+            self.bucket_counts = vec![0; LATENCY_BUCKETS_SECONDS.len()];
+        }
+        if let Some(i) = LATENCY_BUCKETS_SECONDS.iter().position(|b| value <= *b) {
+            self.bucket_counts[i] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    pub completion_requests: AtomicU64,
+    pub chat_requests: AtomicU64,
+    pub completion_cache_hits: AtomicU64,
+    pub completion_cache_misses: AtomicU64,
+    pub vecdb_searches: AtomicU64,
+    pub stream_client_disconnects: AtomicU64,
+    pub upstream_errors: StdMutex<IndexMap<String, u64>>,  // by model
+    pub request_latency: StdMutex<IndexMap<String, Histogram>>,  // by http handler
+}
+
+lazy_static::lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+pub fn inc(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn inc_upstream_error(model: &str) {
+    *METRICS.upstream_errors.lock().unwrap().entry(model.to_string()).or_insert(0) += 1;
+}
+
+pub fn observe_request_latency(handler: &str, seconds: f64) {
+    METRICS.request_latency.lock().unwrap().entry(handler.to_string()).or_default().observe(seconds);
+}
+
+// Observes the latency when dropped, for streams that is when the last event is sent
+pub struct RequestLatencyTimer {
+    handler: String,
+    t0: std::time::Instant,
+}
+
+impl RequestLatencyTimer {
+    pub fn start(handler: &str) -> Self {
+        RequestLatencyTimer { handler: handler.to_string(), t0: std::time::Instant::now() }
+    }
+}
+
+impl Drop for RequestLatencyTimer {
+    fn drop(&mut self) {
+        observe_request_latency(&self.handler, self.t0.elapsed().as_secs_f64());
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+pub fn render_metrics(m: &Metrics, ast_queue_depth: Option<usize>) -> String {
+    // Prometheus text exposition format, version 0.0.4
+    let mut out = String::new();
+    let counters = [
+        ("refact_completion_requests_total", "Code completion requests.", &m.completion_requests),
+        ("refact_chat_requests_total", "Chat requests.", &m.chat_requests),
+        ("refact_completion_cache_hits_total", "Code completions answered from the cache.", &m.completion_cache_hits),
+        ("refact_completion_cache_misses_total", "Code completions not found in the cache.", &m.completion_cache_misses),
+        ("refact_vecdb_searches_total", "Vector database searches.", &m.vecdb_searches),
+        ("refact_stream_client_disconnects_total", "Streams the client dropped before the end.", &m.stream_client_disconnects),
+    ];
+    for (name, help, counter) in counters.iter() {
+        write_header(&mut out, name, help, "counter");
+        let _ = writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed));
+    }
+
+    let hits = m.completion_cache_hits.load(Ordering::Relaxed);
+    let misses = m.completion_cache_misses.load(Ordering::Relaxed);
+    write_header(&mut out, "refact_completion_cache_hit_ratio", "Share of code completions answered from the cache.", "gauge");
+    let ratio = if hits + misses > 0 { hits as f64 / (hits + misses) as f64 } else { 0.0 };
+    let _ = writeln!(out, "refact_completion_cache_hit_ratio {}", ratio);
+
+    if let Some(depth) = ast_queue_depth {
+        write_header(&mut out, "refact_ast_queue_depth", "Files waiting to be parsed by the AST indexer.", "gauge");
+        let _ = writeln!(out, "refact_ast_queue_depth {}", depth);
+    }
+
+    write_header(&mut out, "refact_upstream_errors_total", "Errors from the model endpoints, by model.", "counter");
+    for (model, n) in m.upstream_errors.lock().unwrap().iter() {
+        let _ = writeln!(out, "refact_upstream_errors_total{{model=\"{}\"}} {}", escape_label(model), n);
+    }
+
+    write_header(&mut out, "refact_http_request_duration_seconds", "HTTP request latency, by handler.", "histogram");
+    for (handler, h) in m.request_latency.lock().unwrap().iter() {
+        let handler = escape_label(handler);
+        let mut cumulative = 0;
+        for (bucket, n) in LATENCY_BUCKETS_SECONDS.iter().zip(h.bucket_counts.iter()) {
+            cumulative += n;
+            let _ = writeln!(out, "refact_http_request_duration_seconds_bucket{{handler=\"{}\",le=\"{}\"}} {}", handler, bucket, cumulative);
+        }
+        let _ = writeln!(out, "refact_http_request_duration_seconds_bucket{{handler=\"{}\",le=\"+Inf\"}} {}", handler, h.count);
+        let _ = writeln!(out, "refact_http_request_duration_seconds_sum{{handler=\"{}\"}} {}", handler, h.sum);
+        let _ = writeln!(out, "refact_http_request_duration_seconds_count{{handler=\"{}\"}} {}", handler, h.count);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics_exposition_format() {
+        let m = Metrics::default();
+        inc(&m.completion_requests);
+        inc(&m.completion_requests);
+        inc(&m.completion_cache_hits);
+        inc(&m.completion_cache_misses);
+        m.upstream_errors.lock().unwrap().insert("gpt-4o".to_string(), 3);
+        {
+            let mut latency = m.request_latency.lock().unwrap();
+            let h = latency.entry("/v1/code-completion".to_string()).or_default();
+            h.observe(0.02);
+            h.observe(0.3);
+            h.observe(100.0);
+        }
+        let text = render_metrics(&m, Some(7));
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert!(lines.contains(&"# TYPE refact_completion_requests_total counter"));
+        assert!(lines.contains(&"refact_completion_requests_total 2"));
+        assert!(lines.contains(&"refact_completion_cache_hit_ratio 0.5"));
+        assert!(lines.contains(&"refact_ast_queue_depth 7"));
+        assert!(lines.contains(&"refact_upstream_errors_total{model=\"gpt-4o\"} 3"));
+        assert!(lines.contains(&"refact_http_request_duration_seconds_bucket{handler=\"/v1/code-completion\",le=\"0.025\"} 1"));
+        assert!(lines.contains(&"refact_http_request_duration_seconds_bucket{handler=\"/v1/code-completion\",le=\"0.5\"} 2"));
+        assert!(lines.contains(&"refact_http_request_duration_seconds_bucket{handler=\"/v1/code-completion\",le=\"+Inf\"} 3"));
+        assert!(lines.contains(&"refact_http_request_duration_seconds_count{handler=\"/v1/code-completion\"} 3"));
+
+        // every sample line is `name{labels} value`, every family has HELP and TYPE before its samples
+        let mut typed: Vec<String> = vec![];
+        for line in lines.iter() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                typed.push(rest.split(' ').next().unwrap().to_string());
+                continue;
+            }
+            if line.starts_with("# HELP ") {
+                continue;
+            }
+            let (name_and_labels, value) = line.rsplit_once(' ').unwrap();
+            assert!(value.parse::<f64>().is_ok(), "bad value in {:?}", line);
+            let name = name_and_labels.split('{').next().unwrap();
+            assert!(name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'), "bad name in {:?}", line);
+            assert!(typed.iter().any(|t| name.starts_with(t.as_str())), "no TYPE for {:?}", line);
+        }
+    }
+}
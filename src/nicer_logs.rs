@@ -1,9 +1,39 @@
 use std::io::Write;
+use std::sync::Mutex as StdMutex;
 
 use tracing::{Level, Subscriber};
-use tracing_subscriber::{self, Layer};
+use tracing_subscriber::{self, EnvFilter, Layer, Registry};
 use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::layer::Context;
+use tracing_subscriber::reload;
+
+
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+lazy_static::lazy_static! {
+    static ref LOG_FILTER_HANDLE: StdMutex<Option<LogFilterHandle>> = StdMutex::new(None);
+}
+
+pub fn reloadable_log_filter(directives: &str) -> reload::Layer<EnvFilter, Registry> {
+    // the handle is kept to change the filter later, see set_log_filter()
+    let (layer, handle) = reload::Layer::new(EnvFilter::new(directives));
+    *LOG_FILTER_HANDLE.lock().unwrap() = Some(handle);
+    layer
+}
+
+pub fn set_log_filter_on(handle: &LogFilterHandle, directives: &str) -> Result<String, String> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| format!("bad filter {:?}: {}", directives, e))?;
+    let applied = filter.to_string();
+    handle.reload(filter).map_err(|e| format!("cannot change the log filter: {}", e))?;
+    Ok(applied)
+}
+
+pub fn set_log_filter(directives: &str) -> Result<String, String> {
+    match LOG_FILTER_HANDLE.lock().unwrap().as_ref() {
+        Some(handle) => set_log_filter_on(handle, directives),
+        None => Err("log filter is not reloadable in this process".to_string()),
+    }
+}
 
 
 pub struct CustomLayer<W> {
@@ -104,3 +134,39 @@ pub fn last_n_chars(msg: &String, n: usize) -> String {
     }
     return last_n_chars.replace("\n", "\\n");
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use tracing_subscriber::layer::SubscriberExt;
+    use super::*;
+
+    struct CollectLayer(Arc<StdMutex<Vec<String>>>);
+
+    impl<S: Subscriber> Layer<S> for CollectLayer {
+        fn on_event(&self, event: &tracing::Event, _: Context<S>) {
+            self.0.lock().unwrap().push(format!("{} {}", event.metadata().level(), event.metadata().target()));
+        }
+    }
+
+    #[test]
+    fn test_log_filter_reload() {
+        let collected = Arc::new(StdMutex::new(vec![]));
+        let (filter, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let subscriber = tracing_subscriber::registry().with(filter).with(CollectLayer(collected.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!(target: "refact_lsp::vecdb", "hidden");
+            tracing::info!(target: "refact_lsp::vecdb", "shown");
+            set_log_filter_on(&handle, "info,refact_lsp::vecdb=debug").unwrap();
+            tracing::debug!(target: "refact_lsp::vecdb", "shown");
+            tracing::debug!(target: "refact_lsp::ast", "hidden");
+            assert!(set_log_filter_on(&handle, "refact_lsp::vecdb=nonsense").is_err());
+            tracing::debug!(target: "refact_lsp::vecdb", "the bad filter is not applied, still shown");
+        });
+        assert_eq!(*collected.lock().unwrap(), vec![
+            "INFO refact_lsp::vecdb".to_string(),
+            "DEBUG refact_lsp::vecdb".to_string(),
+            "DEBUG refact_lsp::vecdb".to_string(),
+        ]);
+    }
+}
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 use std::sync::RwLock;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use tracing::{info, warn};
 use tokenizers::Tokenizer;
 use tokio::sync::RwLock as ARwLock;
@@ -255,11 +255,22 @@ async fn pp_limit_and_merge(
     let mut lines_take_cnt = 0;
     let mut files_mentioned_set = HashSet::new();
     let mut files_mentioned_sequence = vec![];
+    let mut tokens_per_file: HashMap<String, usize> = HashMap::new();
+    let mut files_capped: HashSet<String> = HashSet::new();
     for line_ref in lines_by_useful.iter_mut() {
         if !line_ref.take_ignoring_floor && line_ref.useful <= settings.take_floor {
             continue;
         }
         let mut ntokens = count_tokens(&tokenizer.read().unwrap(), &line_ref.line_content);
+        if settings.max_tokens_per_file > 0 {
+            // whole lines or nothing, the rest of the budget goes to other files
+            let file_tokens = tokens_per_file.entry(line_ref.file_ref.cpath.clone()).or_insert(0);
+            if *file_tokens + ntokens > settings.max_tokens_per_file {
+                files_capped.insert(line_ref.file_ref.cpath.clone());
+                continue;
+            }
+            *file_tokens += ntokens;
+        }
 
         if !files_mentioned_set.contains(&line_ref.file_ref.cpath) {
             if files_mentioned_set.len() >= settings.max_files_n {
@@ -323,7 +334,9 @@ async fn pp_limit_and_merge(
             out.push_str("\n");
             prev_line = i;
         }
-        if last_line > prev_line + 1 {
+        if files_capped.contains(&cpath) {
+            out.push_str(&format!("... (truncated to {} tokens)\n", settings.max_tokens_per_file));
+        } else if last_line > prev_line + 1 {
             out.push_str("...\n");
         }
         if DEBUG >= 2 {
@@ -372,3 +385,57 @@ pub async fn postprocess_context_files(
         settings
     ).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const DUMMY_TOKENIZER: &str = include_str!("../ast/dummy_tokenizer.json");
+
+    fn lines_of(cpath: &str, text: &str, useful: f32) -> Vec<FileLine> {
+        let file_ref = Arc::new(PPFile {
+            symbols_sorted_by_path_len: vec![],
+            file_content: text.to_string(),
+            cpath: cpath.to_string(),
+            cpath_symmetry_breaker: 0.0,
+            shorter_path: cpath.to_string(),
+        });
+        text.lines().enumerate().map(|(line_n, line)| FileLine {
+            file_ref: file_ref.clone(),
+            line_n,
+            line_content: line.to_string(),
+            useful,
+            color: "".to_string(),
+            take: false,
+            take_ignoring_floor: true,
+        }).collect()
+    }
+
+    async fn merge(max_tokens_per_file: usize) -> Vec<ContextFile> {
+        let huge = (0..300).map(|i| format!("    total = total + price * quantity  # step {}\n", i)).collect::<String>();
+        let mut lines_in_files = IndexMap::new();
+        lines_in_files.insert("huge.py".to_string(), lines_of("huge.py", &huge, 100.0));
+        lines_in_files.insert("cart.py".to_string(), lines_of("cart.py", "class Cart:\n    items = []\n", 90.0));
+        lines_in_files.insert("price.py".to_string(), lines_of("price.py", "def price(item):\n    return item.cost\n", 80.0));
+        let tokenizer = Arc::new(RwLock::new(Tokenizer::from_str(DUMMY_TOKENIZER).unwrap()));
+        let settings = PostprocessSettings { max_files_n: 5, max_tokens_per_file, ..PostprocessSettings::new() };
+        pp_limit_and_merge(&mut lines_in_files, tokenizer, 1000, false, &settings).await
+    }
+
+    #[tokio::test]
+    async fn test_oversized_snippet_is_truncated() {
+        // without a cap the huge file takes the whole budget
+        let uncapped = merge(0).await;
+        assert_eq!(uncapped.iter().map(|cf| cf.file_name.as_str()).collect::<Vec<_>>(), vec!["huge.py"]);
+
+        let capped = merge(200).await;
+        assert_eq!(capped.iter().map(|cf| cf.file_name.as_str()).collect::<Vec<_>>(), vec!["huge.py", "cart.py", "price.py"]);
+        let huge = &capped[0].file_content;
+        assert!(huge.ends_with("... (truncated to 200 tokens)\n"), "{}", huge);
+        assert!(huge.starts_with("    total = total + price * quantity  # step 0\n"), "{}", huge);
+        // whole lines only
+        assert!(huge.lines().all(|l| l.starts_with("    total = total + price * quantity  # step ") || l.starts_with("...")), "{}", huge);
+        assert_eq!(capped[1].file_content, "class Cart:\n    items = []\n");
+    }
+}
@@ -6,8 +6,10 @@ use tokio::time::Duration;
 use tokio::fs;
 use tracing::error;
 use glob::Pattern;
+use hyper::StatusCode;
 use std::time::SystemTime;
 
+use crate::custom_error::{ScratchError, ScratchErrorCode};
 use crate::global_context::GlobalContext;
 
 
@@ -22,6 +24,8 @@ pub enum FilePrivacyLevel {
 pub struct PrivacySettings {
     pub privacy_rules: FilePrivacySettings,
     #[serde(default)]
+    pub web_allowed_domains: Vec<String>,
+    #[serde(default)]
     pub loaded_ts: u64,
 }
 
@@ -39,6 +43,7 @@ impl Default for PrivacySettings {
                 blocked: vec!["*".to_string()],
                 only_send_to_servers_I_control: vec![],
             },
+            web_allowed_domains: vec![],
             loaded_ts: 0,
         }
     }
@@ -116,6 +121,32 @@ pub fn check_file_privacy(privacy_settings: Arc<PrivacySettings>, path: &Path, m
     Ok(())
 }
 
+// Same check for HTTP handlers, the client gets 422 with error_code PrivacyBlocked
+pub fn check_file_privacy_for_request(privacy_settings: Arc<PrivacySettings>, path: &Path, min_allowed_privacy_level: &FilePrivacyLevel) -> Result<(), ScratchError>
+{
+    check_file_privacy(privacy_settings, path, min_allowed_privacy_level).map_err(|e| {
+        ScratchError::new(StatusCode::UNPROCESSABLE_ENTITY, format!("{}: {}", path.display(), e)).with_code(ScratchErrorCode::PrivacyBlocked)
+    })
+}
+
+pub fn check_web_domain_allowed(privacy_settings: Arc<PrivacySettings>, url: &str) -> Result<(), String>
+{
+    // empty list means any domain is fine, "example.com" also allows "docs.example.com"
+    if privacy_settings.web_allowed_domains.is_empty() {
+        return Ok(());
+    }
+    let parsed = url::Url::parse(url).map_err(|e| format!("cannot parse url {:?}: {}", url, e))?;
+    let host = parsed.host_str().unwrap_or_default().to_lowercase();
+    let allowed = privacy_settings.web_allowed_domains.iter().any(|domain| {
+        let domain = domain.trim().trim_start_matches("*.").to_lowercase();
+        host == domain || host.ends_with(&format!(".{}", domain))
+    });
+    if !allowed {
+        return Err(format!("domain {:?} is not in web_allowed_domains in privacy.yaml", host));
+    }
+    Ok(())
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -130,6 +161,7 @@ mod tests {
                 only_send_to_servers_I_control: vec!["*.pem".to_string(), "*/semi_private_dir/*.md".to_string()],
                 blocked: vec!["*.pem".to_string(), "*/secret_dir/*".to_string(), "secret_passwords.txt".to_string()],
             },
+            web_allowed_domains: vec![],
             loaded_ts: 0,
         });
 
@@ -162,6 +194,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_web_domain_allowed() {
+        let privacy_settings = Arc::new(PrivacySettings {
+            web_allowed_domains: vec!["docs.rs".to_string(), "*.python.org".to_string()],
+            ..PrivacySettings::default()
+        });
+        assert!(check_web_domain_allowed(privacy_settings.clone(), "https://docs.rs/serde/latest/serde/").is_ok());
+        assert!(check_web_domain_allowed(privacy_settings.clone(), "https://docs.python.org/3/library/os.html").is_ok());
+        assert!(check_web_domain_allowed(privacy_settings.clone(), "https://notdocs.rs/").is_err());
+        assert!(check_web_domain_allowed(privacy_settings.clone(), "https://example.com/").is_err());
+        assert!(check_web_domain_allowed(Arc::new(PrivacySettings::default()), "https://example.com/").is_ok());
+    }
+
     #[test]
     fn test_privacy_minimum() {
         let privacy_settings = Arc::new(PrivacySettings {
@@ -169,6 +214,7 @@ mod tests {
                 only_send_to_servers_I_control: vec!["*.cat.txt".to_string(), "*.md".to_string(), "*/.venv/*".to_string(), "**/tests_dir/**/*".to_string()],
                 blocked: vec!["*/make.png".to_string(), "*.txt".to_string()],
             },
+            web_allowed_domains: vec![],
             loaded_ts: 0,
         });
 
@@ -202,12 +248,28 @@ mod tests {
             }
         }
     }
-}
-
-
-
-
-
 
+    #[test]
+    fn test_privacy_blocked_error_code() {
+        let privacy_settings = Arc::new(PrivacySettings {
+            privacy_rules: FilePrivacySettings {
+                only_send_to_servers_I_control: vec![],
+                blocked: vec!["*/secret_dir/*".to_string()],
+            },
+            web_allowed_domains: vec![],
+            loaded_ts: 0,
+        });
+        let current_dir = std::env::current_dir().unwrap();
 
+        let err = check_file_privacy_for_request(privacy_settings.clone(), &current_dir.join("secret_dir/keys.py"), &FilePrivacyLevel::OnlySendToServersIControl).unwrap_err();
+        assert_eq!(err.status_code, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(err.error_code, Some(ScratchErrorCode::PrivacyBlocked));
+        let body = err.to_json();
+        assert_eq!(body["error_code"], "PrivacyBlocked");
+        assert!(body["detail"].as_str().unwrap().contains("privacy level Blocked"), "{}", body);
 
+        assert!(check_file_privacy_for_request(privacy_settings.clone(), &current_dir.join("main.py"), &FilePrivacyLevel::OnlySendToServersIControl).is_ok());
+        // errors without a code keep the old body
+        assert_eq!(ScratchError::new(StatusCode::BAD_REQUEST, "oops".to_string()).to_json(), serde_json::json!({"detail": "oops"}));
+    }
+}
@@ -0,0 +1,156 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+use serde_json::{json, Value};
+use tokio::sync::RwLock as ARwLock;
+
+use crate::global_context::{CommandLine, GlobalContext};
+use crate::privacy::{check_file_privacy, load_privacy_if_needed, FilePrivacyLevel, PrivacySettings};
+
+
+// Requests run in parallel, one line must not end up in the middle of another
+static PROMPT_LOG_WRITE_LOCK: StdMutex<()> = StdMutex::new(());
+
+// Local JSONL of (prompt, response, context) for building eval datasets. Nothing leaves the machine,
+// and it has nothing to do with telemetry: off unless --prompt-log-path is set.
+pub struct PromptLogSink {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+}
+
+impl PromptLogSink {
+    pub fn from_cmdline(cmdline: &CommandLine) -> Option<PromptLogSink> {
+        if cmdline.prompt_log_path.trim().is_empty() {
+            return None;
+        }
+        Some(PromptLogSink {
+            path: crate::files_correction::canonical_path(cmdline.prompt_log_path.trim()),
+            max_bytes: cmdline.prompt_log_max_mb.max(1) * 1024 * 1024,
+        })
+    }
+
+    // When the next record doesn't fit, the file becomes <path>.1 (the older .1 is gone) and a new one starts
+    pub fn append(&self, record: &Value) -> Result<(), String> {
+        let line = format!("{}\n", serde_json::to_string(record).map_err(|e| e.to_string())?);
+        let _lock = PROMPT_LOG_WRITE_LOCK.lock().unwrap();
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("cannot create {}: {}", parent.display(), e))?;
+        }
+        let size = std::fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 > self.max_bytes {
+            std::fs::rename(&self.path, rotated_path(&self.path)).map_err(|e| format!("cannot rotate {}: {}", self.path.display(), e))?;
+        }
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)
+            .map_err(|e| format!("cannot open {}: {}", self.path.display(), e))?;
+        file.write_all(line.as_bytes()).map_err(|e| format!("cannot write {}: {}", self.path.display(), e))
+    }
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+// What the user saw: code_completion for completions, delta or message content for chat, only the first choice
+pub fn response_text(value: &Value) -> String {
+    let Some(choices) = value.get("choices").and_then(|c| c.as_array()) else { return String::new() };
+    choices.iter()
+        .filter(|c| c.get("index").and_then(|i| i.as_u64()).unwrap_or(0) == 0)
+        .filter_map(|c| {
+            c.get("code_completion")
+                .or(c.pointer("/delta/content"))
+                .or(c.pointer("/message/content"))
+                .or(c.get("text"))
+                .and_then(|t| t.as_str())
+        })
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+// None if the context has a file privacy settings block, the prompt has its text so the whole record is skipped
+pub fn prompt_log_record(
+    privacy: Arc<PrivacySettings>,
+    scope: &str,
+    model: &str,
+    prompt: &str,
+    response: &str,
+    context_used: &Value,
+) -> Option<Value> {
+    let attached_files = context_used.get("attached_files").and_then(|f| f.as_array()).cloned().unwrap_or_default();
+    for f in attached_files.iter() {
+        let file_name = f.get("file_name").and_then(|n| n.as_str()).unwrap_or_default();
+        if check_file_privacy(privacy.clone(), Path::new(file_name), &FilePrivacyLevel::OnlySendToServersIControl).is_err() {
+            tracing::info!("prompt log: skipping a record with {} in the context, privacy", file_name);
+            return None;
+        }
+    }
+    Some(json!({
+        "ts": std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs_f64(),
+        "scope": scope,
+        "model": model,
+        "prompt": prompt,
+        "response": response,
+        "context_used": context_used,
+    }))
+}
+
+pub async fn prompt_log_maybe(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    scope: &str,
+    model: &str,
+    prompt: &str,
+    response: &str,
+    context_used: &Value,
+) {
+    let Some(sink) = PromptLogSink::from_cmdline(&gcx.read().await.cmdline) else { return };
+    let privacy = load_privacy_if_needed(gcx.clone()).await;
+    if let Some(record) = prompt_log_record(privacy, scope, model, prompt, response, context_used) {
+        if let Err(e) = sink.append(&record) {
+            tracing::error!("prompt log: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::privacy::FilePrivacySettings;
+
+    #[test]
+    fn test_completion_writes_one_jsonl_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = PromptLogSink { path: dir.path().join("logs/prompts.jsonl"), max_bytes: 1024 * 1024 };
+        let privacy = Arc::new(PrivacySettings {
+            privacy_rules: FilePrivacySettings {
+                only_send_to_servers_I_control: vec![],
+                blocked: vec!["*/secrets/*".to_string()],
+            },
+            web_allowed_domains: vec![],
+            loaded_ts: 0,
+        });
+        let model_says = json!({"choices": [{"index": 0, "code_completion": "return a + b", "finish_reason": "stop"}]});
+        let context_used = json!({"attached_files": [{"file_name": "/project/src/math.py", "file_content": "def add(a, b):", "line1": 1, "line2": 1}]});
+
+        let record = prompt_log_record(privacy.clone(), "completion", "starcoder", "<fim_prefix>def add(a, b):\n    ", &response_text(&model_says), &context_used).unwrap();
+        sink.append(&record).unwrap();
+
+        let text = std::fs::read_to_string(&sink.path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let parsed: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(parsed["response"], "return a + b");
+        assert_eq!(parsed["prompt"], "<fim_prefix>def add(a, b):\n    ");
+        assert_eq!(parsed["context_used"]["attached_files"][0]["file_name"], "/project/src/math.py");
+
+        // a blocked file in the context, nothing is written
+        let blocked_context = json!({"attached_files": [{"file_name": "/project/secrets/keys.py", "file_content": "KEY = 1"}]});
+        assert!(prompt_log_record(privacy.clone(), "completion", "starcoder", "KEY = ", "2", &blocked_context).is_none());
+
+        // rotation: the full file moves to .1 and the new record starts a new file
+        let small_sink = PromptLogSink { path: sink.path.clone(), max_bytes: text.len() as u64 + 10 };
+        small_sink.append(&record).unwrap();
+        assert_eq!(std::fs::read_to_string(&sink.path).unwrap().lines().count(), 1);
+        assert_eq!(std::fs::read_to_string(rotated_path(&sink.path)).unwrap(), text);
+    }
+}
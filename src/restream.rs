@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock as StdRwLock};
 use tokio::sync::Mutex as AMutex;
 use tokio::sync::RwLock as ARwLock;
@@ -5,13 +6,13 @@ use tokio::sync::mpsc;
 use async_stream::stream;
 use futures::StreamExt;
 use hyper::{Body, Response, StatusCode};
-use reqwest_eventsource::Event;
+use reqwest_eventsource::{Event, EventSource};
 use reqwest_eventsource::Error as REError;
 use serde_json::{json, Value};
 use tracing::info;
 
 use crate::call_validation::{ChatMeta, SamplingParameters};
-use crate::custom_error::ScratchError;
+use crate::custom_error::{ScratchError, ScratchErrorCode};
 use crate::nicer_logs;
 use crate::scratchpad_abstract::{FinishReason, ScratchpadAbstract};
 use crate::telemetry::telemetry_structs;
@@ -23,7 +24,7 @@ async fn _get_endpoint_and_stuff_from_model_name(
     gcx: Arc<ARwLock<crate::global_context::GlobalContext>>,
     caps: Arc<StdRwLock<crate::caps::CodeAssistantCaps>>,
     model_name: String,
-) -> (String, String, String, String)
+) -> (String, String, String, String, HashMap<String, String>)
 {
     let (
         custom_apikey,
@@ -31,27 +32,35 @@ async fn _get_endpoint_and_stuff_from_model_name(
         custom_endpoint_style,
         mut endpoint_template,
         custom_endpoint_template,
-        endpoint_chat_passthrough
+        endpoint_chat_passthrough,
+        extra_headers,
     ) = {
         let caps_locked = caps.read().unwrap();
         let is_chat = caps_locked.code_chat_models.contains_key(&model_name);
+        let model_rec = if is_chat { caps_locked.code_chat_models.get(&model_name) } else { caps_locked.code_completion_models.get(&model_name) };
+        let mut extra_headers = caps_locked.extra_headers.0.clone();
+        extra_headers.extend(model_rec.map(|r| r.extra_headers.0.clone()).unwrap_or_default());
         if is_chat {
+            let model_endpoint = caps_locked.code_chat_models.get(&model_name).map(|r| r.endpoint.clone()).unwrap_or_default();
             (
                 caps_locked.chat_apikey.clone(),
                 caps_locked.endpoint_style.clone(),      // abstract
                 caps_locked.chat_endpoint_style.clone(), // chat-specific
                 caps_locked.endpoint_template.clone(),   // abstract
-                caps_locked.chat_endpoint.clone(),       // chat-specific
-                caps_locked.endpoint_chat_passthrough.clone(),
+                if model_endpoint.is_empty() { caps_locked.chat_endpoint.clone() } else { model_endpoint.clone() },  // chat-specific
+                if model_endpoint.is_empty() { caps_locked.endpoint_chat_passthrough.clone() } else { model_endpoint },
+                extra_headers,
             )
         } else {
+            let model_endpoint = caps_locked.code_completion_models.get(&model_name).map(|r| r.endpoint.clone()).unwrap_or_default();
             (
                 caps_locked.completion_apikey.clone(),
                 caps_locked.endpoint_style.clone(),             // abstract
                 caps_locked.completion_endpoint_style.clone(),  // completion-specific
                 caps_locked.endpoint_template.clone(),          // abstract
-                caps_locked.completion_endpoint.clone(),        // completion-specific
+                if model_endpoint.is_empty() { caps_locked.completion_endpoint.clone() } else { model_endpoint },  // completion-specific
                 "".to_string(),
+                extra_headers,
             )
         }
     };
@@ -67,6 +76,7 @@ async fn _get_endpoint_and_stuff_from_model_name(
         endpoint_template,
         endpoint_style,
         endpoint_chat_passthrough,
+        extra_headers,
     )
 }
 
@@ -78,14 +88,15 @@ pub async fn scratchpad_interaction_not_stream_json(
     model_name: String,
     parameters: &SamplingParameters,  // includes n
     only_deterministic_messages: bool,
-    meta: Option<ChatMeta>
+    meta: Option<ChatMeta>,
+    warmup: bool,  // a completion nobody asked for yet, leaves no trace in metrics and telemetry
 ) -> Result<serde_json::Value, ScratchError> {
     let t2 = std::time::SystemTime::now();
     let gcx = ccx.lock().await.global_context.clone();
     let (client, caps, tele_storage, slowdown_arc) = {
         let gcx_locked = gcx.write().await;
         let caps = gcx_locked.caps.clone()
-            .ok_or(ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, "No caps available".to_string()))?;
+            .ok_or(ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, "No caps available".to_string()).with_code(ScratchErrorCode::CapsUnavailable))?;
         (
             gcx_locked.http_client.clone(),
             caps,
@@ -98,6 +109,7 @@ pub async fn scratchpad_interaction_not_stream_json(
         endpoint_template,
         endpoint_style,
         endpoint_chat_passthrough,
+        extra_headers,
     ) = _get_endpoint_and_stuff_from_model_name(gcx.clone(), caps.clone(), model_name.clone()).await;
 
     let mut save_url: String = String::new();
@@ -109,6 +121,7 @@ pub async fn scratchpad_interaction_not_stream_json(
         crate::forward_to_hf_endpoint::forward_to_hf_style_endpoint(
             &mut save_url,
             bearer.clone(),
+            &extra_headers,
             &model_name,
             &prompt,
             &client,
@@ -120,6 +133,7 @@ pub async fn scratchpad_interaction_not_stream_json(
         crate::forward_to_openai_endpoint::forward_to_openai_style_endpoint(
             &mut save_url,
             bearer.clone(),
+            &extra_headers,
             &model_name,
             &prompt,
             &client,
@@ -129,20 +143,27 @@ pub async fn scratchpad_interaction_not_stream_json(
             meta
         ).await
     }.map_err(|e| {
-        tele_storage.write().unwrap().tele_net.push(telemetry_structs::TelemetryNetwork::new(
-                save_url.clone(),
-                scope.clone(),
-                false,
-                e.to_string(),
-            ));
-        ScratchError::new_but_skip_telemetry(StatusCode::INTERNAL_SERVER_ERROR, format!("forward_to_endpoint: {}", e))
+        if !warmup {
+            crate::metrics::inc_upstream_error(&model_name);
+            tele_storage.write().unwrap().tele_net.push(telemetry_structs::TelemetryNetwork::new(
+                    save_url.clone(),
+                    scope.clone(),
+                    false,
+                    e.to_string(),
+                ));
+        }
+        let err = ScratchError::new_but_skip_telemetry(StatusCode::INTERNAL_SERVER_ERROR, format!("forward_to_endpoint: {}", e));
+        // forward_to_*_endpoint put the upstream status into the message as "status=NNN"
+        if e.to_string().contains("status=429") { err.with_code(ScratchErrorCode::RateLimited) } else { err }
     })?;
-    tele_storage.write().unwrap().tele_net.push(telemetry_structs::TelemetryNetwork::new(
-        save_url.clone(),
-        scope.clone(),
-        true,
-        "".to_string(),
-    ));
+    if !warmup {
+        tele_storage.write().unwrap().tele_net.push(telemetry_structs::TelemetryNetwork::new(
+            save_url.clone(),
+            scope.clone(),
+            true,
+            "".to_string(),
+        ));
+    }
     info!("forward to endpoint {:.2}ms, url was {}", t2.elapsed().unwrap().as_millis() as f64, save_url);
     crate::global_context::look_for_piggyback_fields(gcx.clone(), &model_says).await;
 
@@ -220,6 +241,9 @@ pub async fn scratchpad_interaction_not_stream_json(
         }
 
     } else if let Some(err) = model_says.get("error") {
+        if !warmup {
+            crate::metrics::inc_upstream_error(&model_name);
+        }
         return Err(ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR,
             format!("{}", err)
         ));
@@ -255,7 +279,8 @@ pub async fn scratchpad_interaction_not_stream(
     model_name: String,
     parameters: &mut SamplingParameters,
     only_deterministic_messages: bool,
-    meta: Option<ChatMeta>
+    meta: Option<ChatMeta>,
+    warmup: bool,
 ) -> Result<Response<Body>, ScratchError> {
     let t1 = std::time::Instant::now();
     let prompt = scratchpad.prompt(
@@ -267,6 +292,7 @@ pub async fn scratchpad_interaction_not_stream(
     info!("scratchpad_interaction_not_stream prompt {:?}", t1.elapsed());
 
     let t2 = std::time::SystemTime::now();
+    let (scope_for_log, model_name_for_log) = (scope.clone(), model_name.clone());
     let mut scratchpad_response_json = scratchpad_interaction_not_stream_json(
         ccx.clone(),
         scratchpad,
@@ -275,9 +301,15 @@ pub async fn scratchpad_interaction_not_stream(
         model_name,
         parameters,
         only_deterministic_messages,
-        meta
+        meta,
+        warmup,
     ).await?;
     scratchpad_response_json["created"] = json!(t2.duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as f64 / 1000.0);
+    if !only_deterministic_messages && !warmup {
+        let gcx = ccx.lock().await.global_context.clone();
+        let response = crate::prompt_log::response_text(&scratchpad_response_json);
+        crate::prompt_log::prompt_log_maybe(gcx, &scope_for_log, &model_name_for_log, &prompt, &response, &scratchpad.context_used()).await;
+    }
 
     try_insert_usage(&mut scratchpad_response_json);
 
@@ -290,6 +322,35 @@ pub async fn scratchpad_interaction_not_stream(
     return Ok(response);
 }
 
+// Hyper drops the response body when the client goes away, and the stream with everything it owns, the EventSource
+// too: that is what closes the upstream connection. This wrapper only notices it, logs it and counts it.
+pub struct UpstreamStream {
+    pub event_source: EventSource,
+    model_name: String,
+    finished: bool,
+}
+
+impl UpstreamStream {
+    pub fn new(event_source: EventSource, model_name: &str) -> Self {
+        UpstreamStream { event_source, model_name: model_name.to_string(), finished: false }
+    }
+
+    pub fn finish(&mut self) {
+        self.finished = true;
+        self.event_source.close();
+    }
+}
+
+impl Drop for UpstreamStream {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        info!("client disconnected mid-stream, the upstream request to {} goes away with it", self.model_name);
+        crate::metrics::inc(&crate::metrics::METRICS.stream_client_disconnects);
+    }
+}
+
 pub async fn scratchpad_interaction_stream(
     ccx: Arc<AMutex<AtCommandsContext>>,
     mut scratchpad: Box<dyn ScratchpadAbstract>,
@@ -300,6 +361,7 @@ pub async fn scratchpad_interaction_stream(
     meta: Option<ChatMeta>
 ) -> Result<Response<Body>, ScratchError> {
     let t1 = std::time::SystemTime::now();
+    let request_id = crate::http::utils::request_id_header();
     let evstream = stream! {
         let my_scratchpad: &mut Box<dyn ScratchpadAbstract> = &mut scratchpad;
         let mut my_parameters = parameters.clone();
@@ -321,6 +383,7 @@ pub async fn scratchpad_interaction_stream(
             endpoint_template,
             endpoint_style,
             endpoint_chat_passthrough,
+            extra_headers,
         ) = _get_endpoint_and_stuff_from_model_name(gcx.clone(), caps.clone(), model_name.clone()).await;
 
         let t0 = std::time::Instant::now();
@@ -395,20 +458,22 @@ pub async fn scratchpad_interaction_stream(
             }
             // info!("prompt: {:?}", prompt);
             let event_source_maybe = if endpoint_style == "hf" {
-                crate::forward_to_hf_endpoint::forward_to_hf_style_endpoint_streaming(
+                crate::http::utils::with_request_id(request_id.clone(), crate::forward_to_hf_endpoint::forward_to_hf_style_endpoint_streaming(
                     &mut save_url,
                     bearer.clone(),
+                    &extra_headers,
                     &model_name,
                     prompt.as_str(),
                     &client,
                     &endpoint_template,
                     &parameters,
                     meta
-                ).await
+                )).await
             } else {
-                crate::forward_to_openai_endpoint::forward_to_openai_style_endpoint_streaming(
+                crate::http::utils::with_request_id(request_id.clone(), crate::forward_to_openai_endpoint::forward_to_openai_style_endpoint_streaming(
                     &mut save_url,
                     bearer.clone(),
+                    &extra_headers,
                     &model_name,
                     prompt.as_str(),
                     &client,
@@ -416,12 +481,13 @@ pub async fn scratchpad_interaction_stream(
                     &endpoint_chat_passthrough,
                     &parameters,
                     meta
-                ).await
+                )).await
             };
-            let mut event_source = match event_source_maybe {
-                Ok(event_source) => event_source,
+            let mut upstream = match event_source_maybe {
+                Ok(event_source) => UpstreamStream::new(event_source, &model_name),
                 Err(e) => {
                     let e_str = format!("forward_to_endpoint: {:?}", e);
+                    crate::metrics::inc_upstream_error(&model_name);
                     tele_storage.write().unwrap().tele_net.push(telemetry_structs::TelemetryNetwork::new(
                         save_url.clone(),
                         scope.clone(),
@@ -436,8 +502,9 @@ pub async fn scratchpad_interaction_stream(
             };
             let mut was_correct_output_even_if_error = false;
             let mut last_finish_reason = FinishReason::None;
+            let mut response_for_log = String::new();
             // let mut test_countdown = 250;
-            while let Some(event) = event_source.next().await {
+            while let Some(event) = upstream.event_source.next().await {
                 match event {
                     Ok(Event::Open) => {},
                     Ok(Event::Message(message)) => {
@@ -459,6 +526,7 @@ pub async fn scratchpad_interaction_stream(
                                 }
                                 try_insert_usage(&mut value);
                                 value["created"] = json!(t1.duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as f64 / 1000.0);
+                                response_for_log.push_str(&crate::prompt_log::response_text(&value));
                                 let value_str = format!("data: {}\n\n", serde_json::to_string(&value).unwrap());
                                 // let last_60_chars: String = crate::nicer_logs::first_n_chars(&value_str, 60);
                                 // info!("yield: {:?}", last_60_chars);
@@ -479,8 +547,10 @@ pub async fn scratchpad_interaction_stream(
                             // "restream error: Stream ended"
                             break;
                         }
+                        let mut rate_limited = false;
                         let problem_str = match err {
                             REError::InvalidStatusCode(err, resp) => {
+                                rate_limited = err.as_u16() == 429;
                                 let text = resp.text().await.unwrap();
                                 let mut res = format!("{} with details = {:?}", err, text);
                                 if let Ok(value) = serde_json::from_str::<Value>(&text) {
@@ -495,6 +565,7 @@ pub async fn scratchpad_interaction_stream(
                             }
                         };
                         tracing::error!("restream error: {}\n", problem_str);
+                        crate::metrics::inc_upstream_error(&model_name);
                         {
                             tele_storage.write().unwrap().tele_net.push(telemetry_structs::TelemetryNetwork::new(
                                 save_url.clone(),
@@ -503,12 +574,18 @@ pub async fn scratchpad_interaction_stream(
                                 problem_str.clone(),
                             ));
                         }
-                        yield Result::<_, String>::Ok(serde_json::to_string(&json!({"detail": problem_str})).unwrap());
-                        event_source.close();
+                        upstream.finish();
+                        let mut problem_json = json!({"detail": problem_str});
+                        if rate_limited {
+                            // same error_code as the non-streaming path, editors back off on it
+                            problem_json["error_code"] = json!(ScratchErrorCode::RateLimited);
+                        }
+                        yield Result::<_, String>::Ok(serde_json::to_string(&problem_json).unwrap());
                         return;
                     },
                 }
             }
+            upstream.finish();
 
             let mut value = my_scratchpad.streaming_finished(last_finish_reason)?;
             value["created"] = json!(t1.duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as f64 / 1000.0);
@@ -516,6 +593,7 @@ pub async fn scratchpad_interaction_stream(
             let value_str = format!("data: {}\n\n", serde_json::to_string(&value).unwrap());
             info!("yield final: {:?}", value_str);
             yield Result::<_, String>::Ok(value_str);
+            crate::prompt_log::prompt_log_maybe(gcx.clone(), &scope, &model_name, &prompt, &response_for_log, &my_scratchpad.context_used()).await;
             break;
         }
         info!("yield: [DONE]");
@@ -655,3 +733,119 @@ pub async fn cached_stream(
        .unwrap();
     return Ok(response);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use hyper::body::HttpBody;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Streams a token every 50ms forever, the flag goes up when the connection is closed from our side
+    async fn endless_sse_server(closed: Arc<AtomicBool>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1/completions", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let mut head = vec![];
+            while !String::from_utf8_lossy(&head).contains("\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                head.extend_from_slice(&buf[..n]);
+            }
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n").await.unwrap();
+            loop {
+                tokio::select! {
+                    n = stream.read(&mut buf) => {
+                        if n.map_or(true, |n| n == 0) {
+                            break;
+                        }
+                    },
+                    _ = tokio::time::sleep(std::time::Duration::from_millis(50)) => {
+                        let chunk = format!("data: {}\n\n", json!({"choices": [{"index": 0, "text": "tok", "finish_reason": null}]}));
+                        if stream.write_all(chunk.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    },
+                }
+            }
+            closed.store(true, Ordering::SeqCst);
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_client_drop_closes_upstream_and_is_counted() {
+        let closed = Arc::new(AtomicBool::new(false));
+        let url = endless_sse_server(closed.clone()).await;
+        let disconnects_before = crate::metrics::METRICS.stream_client_disconnects.load(Ordering::Relaxed);
+        let dir = tempfile::tempdir().unwrap();
+        let gcx = crate::global_context::create_test_global_context_with_fim_model(dir.path(), &url, &[]).await;
+        let cpath = dir.path().join("f.py");
+        let text = "def f():\n    \n".to_string();
+        std::fs::write(&cpath, &text).unwrap();
+        let file = cpath.to_string_lossy().to_string();
+        let mut post: crate::call_validation::CodeCompletionPost = serde_json::from_value(json!({
+            "inputs": {
+                "sources": {file.clone(): text},
+                "cursor": {"file": file, "line": 1, "character": 4},
+                "multiline": true,
+            },
+            "stream": true,
+            "no_cache": true,
+        })).unwrap();
+
+        // the whole path an IDE request takes, scratchpad_interaction_stream at the end of it
+        let response = crate::http::routers::v1::code_completion::handle_v1_code_completion(gcx.clone(), &mut post).await.unwrap();
+        let mut body = response.into_body();
+        loop {
+            let chunk = tokio::time::timeout(std::time::Duration::from_secs(3), body.data()).await
+                .expect("no tokens from upstream").unwrap().unwrap();
+            if String::from_utf8_lossy(&chunk).contains("tok") {
+                break;
+            }
+        }
+        assert!(!closed.load(Ordering::SeqCst));
+
+        drop(body);  // the editor cancelled the completion
+        let t0 = std::time::Instant::now();
+        while !closed.load(Ordering::SeqCst) && t0.elapsed() < std::time::Duration::from_secs(3) {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert!(closed.load(Ordering::SeqCst), "upstream connection is still open after the client went away");
+        assert!(crate::metrics::METRICS.stream_client_disconnects.load(Ordering::Relaxed) > disconnects_before);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_while_streaming() {
+        let router = axum::Router::new().route("/v1/completions", axum::routing::post(|| async {
+            (StatusCode::TOO_MANY_REQUESTS, json!({"detail": "slow down"}).to_string())
+        }));
+        let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0))).serve(router.into_make_service());
+        let url = format!("http://{}/v1/completions", server.local_addr());
+        tokio::spawn(server);
+
+        let dir = tempfile::tempdir().unwrap();
+        let gcx = crate::global_context::create_test_global_context_with_fim_model(dir.path(), &url, &[]).await;
+        let cpath = dir.path().join("f.py");
+        let text = "def f():\n    \n".to_string();
+        std::fs::write(&cpath, &text).unwrap();
+        let file = cpath.to_string_lossy().to_string();
+        let mut post: crate::call_validation::CodeCompletionPost = serde_json::from_value(json!({
+            "inputs": {
+                "sources": {file.clone(): text},
+                "cursor": {"file": file, "line": 1, "character": 4},
+                "multiline": true,
+            },
+            "stream": true,
+            "no_cache": true,
+        })).unwrap();
+
+        let response = crate::http::routers::v1::code_completion::handle_v1_code_completion(gcx.clone(), &mut post).await.unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8_lossy(&body);
+        let error_line = body.split("\n\n").find(|x| x.contains("slow down")).expect(&body);
+        let error_json: Value = serde_json::from_str(error_line.trim_start_matches("data: ")).unwrap();
+        assert_eq!(error_json["error_code"], "RateLimited", "{}", body);
+    }
+}
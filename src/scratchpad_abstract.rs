@@ -8,6 +8,7 @@ use serde_json::Value;
 
 use crate::at_commands::at_commands::AtCommandsContext;
 use crate::call_validation::SamplingParameters;
+use crate::scratchpads::completon_rag::ContextTemplate;
 
 use tracing::warn;
 
@@ -113,6 +114,11 @@ pub trait ScratchpadAbstract: Send {
     fn response_spontaneous(&mut self) -> Result<Vec<Value>, String>;
 
     fn streaming_finished(&mut self, finish_reason: FinishReason) -> Result<Value, String>;
+
+    // Context that went into the prompt, for the prompt log, completion scratchpads have it
+    fn context_used(&self) -> Value {
+        Value::Null
+    }
 }
 
 // aggregate this struct to make scratchpad implementation easier
@@ -122,12 +128,15 @@ pub struct HasTokenizerAndEot {
     pub eot: String,
     pub eos: String,
     pub context_format: String,
+    pub context_template: Option<ContextTemplate>,  // overrides the built-in template of context_format
     pub rag_ratio: f64,
+    pub rag_max_snippets: usize,        // 0 means the postprocessing default
+    pub rag_snippet_max_tokens: usize,  // 0 means a snippet can take the whole RAG budget
 }
 
 impl HasTokenizerAndEot {
     pub fn new(tokenizer: Arc<RwLock<Tokenizer>>) -> Self {
-        HasTokenizerAndEot { tokenizer, eot: String::new(), eos: String::new(), context_format: String::new(), rag_ratio: 0.5}
+        HasTokenizerAndEot { tokenizer, eot: String::new(), eos: String::new(), context_format: String::new(), context_template: None, rag_ratio: 0.5, rag_max_snippets: 0, rag_snippet_max_tokens: 0}
     }
 
     pub fn count_tokens(
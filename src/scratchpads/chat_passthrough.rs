@@ -10,8 +10,9 @@ use crate::at_commands::execute_at::{run_at_commands_locally, run_at_commands_re
 use crate::at_commands::at_commands::AtCommandsContext;
 use crate::call_validation::{ChatMessage, ChatPost, SamplingParameters};
 use crate::scratchpad_abstract::{FinishReason, HasTokenizerAndEot, ScratchpadAbstract};
+use crate::scratchpads::chat_utils_deltadelta::{put_reasoning_into_delta, split_reasoning_in_openai_chunk, ReasoningSplitter};
 use crate::scratchpads::chat_utils_limit_history::limit_messages_history;
-use crate::scratchpads::scratchpad_utils::HasRagResults;
+use crate::scratchpads::scratchpad_utils::{enforce_tool_rounds_cap, HasRagResults};
 use crate::scratchpads::chat_utils_prompts::prepend_the_right_system_prompt_and_maybe_more_initial_messages;
 use crate::scratchpads::passthrough_convert_messages::convert_messages_to_openai_format;
 use crate::tools::tools_description::{tool_description_list_from_yaml, tools_merged_and_filtered};
@@ -59,6 +60,7 @@ pub struct ChatPassthrough {
     pub allow_at: bool,
     pub supports_tools: bool,
     pub supports_clicks: bool,
+    pub reasoning: Option<ReasoningSplitter>,
 }
 
 impl ChatPassthrough {
@@ -79,6 +81,7 @@ impl ChatPassthrough {
             allow_at,
             supports_tools,
             supports_clicks,
+            reasoning: None,
         }
     }
 }
@@ -122,6 +125,15 @@ impl ScratchpadAbstract for ChatPassthrough {
                 run_tools_locally(ccx.clone(), &mut at_tools, self.t.tokenizer.clone(), sampling_parameters_to_patch.max_new_tokens, &messages, &mut self.has_rag_results, &style, self.post.tools_confirmation).await?
             }
         };
+        let tool_rounds_exhausted = if self.supports_tools {
+            let (max_tool_rounds, max_tool_rounds_message) = {
+                let gcx_locked = gcx.read().await;
+                (gcx_locked.cmdline.max_tool_rounds, gcx_locked.cmdline.max_tool_rounds_message.clone())
+            };
+            enforce_tool_rounds_cap(&mut messages, max_tool_rounds, &max_tool_rounds_message)
+        } else {
+            false
+        };
         let limited_msgs = limit_messages_history(&self.t, &messages, undroppable_msg_n, sampling_parameters_to_patch.max_new_tokens, n_ctx).unwrap_or_else(|e| {
             error!("error limiting messages: {}", e);
             vec![]
@@ -134,7 +146,9 @@ impl ScratchpadAbstract for ChatPassthrough {
             "messages": converted_messages,
         });
 
-        if self.supports_tools {
+        if self.supports_tools && tool_rounds_exhausted {
+            info!("max tool rounds reached, no tools this time");
+        } else if self.supports_tools {
             let post_tools = self.post.tools.as_ref().and_then(|tools| {
                 if tools.is_empty() {
                     None
@@ -145,13 +159,14 @@ impl ScratchpadAbstract for ChatPassthrough {
 
             let mut tools = if let Some(t) = post_tools {
                 // here we only use names from the tools in `post`
+                let tools_permissions = ccx.lock().await.tools_permissions.clone();
                 let turned_on = t.iter().filter_map(|x| {
                     if let Value::Object(map) = x {
                         map.get("function").and_then(|f| f.get("name")).and_then(|name| name.as_str().map(|s| s.to_string()))
                     } else {
                         None
                     }
-                }).collect::<Vec<String>>();
+                }).filter(|name| tools_permissions.check(name).is_ok()).collect::<Vec<String>>();
                 let allow_experimental = gcx.read().await.cmdline.experimental;
                 // and take descriptions of tools from the official source
                 let tool_descriptions = tool_description_list_from_yaml(at_tools, &turned_on, allow_experimental).await?;
@@ -204,7 +219,11 @@ impl ScratchpadAbstract for ChatPassthrough {
         json: &Value,
         finish_reason: FinishReason,
     ) -> Result<(Value, FinishReason), String> {
-        Ok((json.clone(), finish_reason))
+        let mut json = json.clone();
+        if let Some(splitter) = &mut self.reasoning {
+            split_reasoning_in_openai_chunk(splitter, &mut json);
+        }
+        Ok((json, finish_reason))
     }
 
     fn response_spontaneous(&mut self) -> Result<Vec<Value>, String>  {
@@ -229,7 +248,11 @@ impl ScratchpadAbstract for ChatPassthrough {
     }
 
     fn streaming_finished(&mut self, finish_reason: FinishReason) -> Result<Value, String> {
-        let json_choices = self.delta_sender.feed_delta("assistant", &json!({}), &finish_reason, None);
+        let mut json_choices = self.delta_sender.feed_delta("assistant", &json!({}), &finish_reason, None);
+        if let Some(splitter) = &mut self.reasoning {
+            let (reasoning, content) = splitter.flush();
+            put_reasoning_into_delta(&mut json_choices[0]["delta"], reasoning, content);
+        }
         Ok(json!({
             "choices": json_choices,
             "object": "chat.completion.chunk",
@@ -11,6 +11,7 @@ pub struct DeltaDeltaChatStreamer {
     pub finished: bool,
     pub stop_list: Vec<String>,
     pub role: String,
+    pub reasoning: Option<ReasoningSplitter>,  // Some for models with supports_reasoning in caps
 }
 
 impl DeltaDeltaChatStreamer {
@@ -21,6 +22,7 @@ impl DeltaDeltaChatStreamer {
             finished: false,
             stop_list: Vec::new(),
             role: String::new(),
+            reasoning: None,
         }
     }
 
@@ -33,12 +35,21 @@ impl DeltaDeltaChatStreamer {
         let mut json_choices = Vec::<Value>::new();
         for (i, x) in choices.iter().enumerate() {
             let s = cut_result(&x, &self.stop_list);
+            let mut json_message = serde_json::json!({
+                "role": self.role.clone(),
+                "content": s.clone()
+            });
+            if self.reasoning.is_some() {
+                let mut splitter = ReasoningSplitter::new();
+                let (mut reasoning, mut content) = splitter.feed(&s);
+                let (reasoning_tail, content_tail) = splitter.flush();
+                reasoning.push_str(&reasoning_tail);
+                content.push_str(&content_tail);
+                put_reasoning_into_delta(&mut json_message, reasoning, content);
+            }
             json_choices.push(serde_json::json!({
                 "index": i,
-                "message": {
-                    "role": self.role.clone(),
-                    "content": s.clone()
-                },
+                "message": json_message,
                 "finish_reason": finish_reasons[i].to_string(),
             }));
         }
@@ -50,30 +61,22 @@ impl DeltaDeltaChatStreamer {
     }
 
     pub fn response_streaming(&mut self, delta: String, finish_reason: FinishReason) -> Result<(Value, FinishReason), String> {
-        // let prev_delta = self.delta2;
         assert!(!self.finished, "already finished");
         self.delta2 = self.delta1.clone();
         self.delta1 = delta.clone();
-        let json_choices;
-        if !delta.is_empty() {
-            json_choices = serde_json::json!([{
-                "index": 0,
-                "delta": {
-                    "role": self.role.clone(),
-                    "content": self.delta2
-                },
-                "finish_reason": finish_reason.to_json_val()
-            }]);
-        } else {
-            json_choices = serde_json::json!([{
-                "index": 0,
-                "delta": {
-                    "role": self.role.clone(),
-                    "content": self.delta2
-                },
-                "finish_reason": finish_reason.to_json_val()
-            }]);
+        let mut json_delta = serde_json::json!({
+            "role": self.role.clone(),
+            "content": self.delta2
+        });
+        if let Some(splitter) = &mut self.reasoning {
+            let (reasoning, content) = splitter.feed(&self.delta2);
+            put_reasoning_into_delta(&mut json_delta, reasoning, content);
         }
+        let json_choices = serde_json::json!([{
+            "index": 0,
+            "delta": json_delta,
+            "finish_reason": finish_reason.to_json_val()
+        }]);
         Ok((serde_json::json!({"choices": json_choices}), finish_reason))
     }
 
@@ -82,19 +85,98 @@ impl DeltaDeltaChatStreamer {
         self.finished = true;
         self.delta2 = self.delta1.clone();
         let leftovers = self.delta2.clone();
+        let mut json_delta = serde_json::json!({
+            "role": self.role.clone(),
+            "content": cut_result(&leftovers, &self.stop_list),
+        });
+        if let Some(splitter) = &mut self.reasoning {
+            let (mut reasoning, mut content) = splitter.feed(&cut_result(&leftovers, &self.stop_list));
+            let (reasoning_tail, content_tail) = splitter.flush();
+            reasoning.push_str(&reasoning_tail);
+            content.push_str(&content_tail);
+            put_reasoning_into_delta(&mut json_delta, reasoning, content);
+        }
         Ok(serde_json::json!({
             "choices": [{
                 "index": 0,
-                "delta": {
-                    "role": self.role.clone(),
-                    "content": cut_result(&leftovers, &self.stop_list),
-                },
+                "delta": json_delta,
                 "finish_reason": finish_reason.to_json_val()
             }],
         }))
     }
 }
 
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+#[derive(Debug, Default)]
+pub struct ReasoningSplitter {
+    // Reasoning models wrap their thinking in <think>...</think>, tags can arrive split between deltas,
+    // so a tail that looks like the beginning of a tag is held back until the next delta.
+    inside_think: bool,
+    pending: String,
+}
+
+impl ReasoningSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // returns (reasoning, content)
+    pub fn feed(&mut self, delta: &str) -> (String, String) {
+        let mut text = std::mem::take(&mut self.pending) + delta;
+        let mut reasoning = String::new();
+        let mut content = String::new();
+        loop {
+            let tag = if self.inside_think { THINK_CLOSE } else { THINK_OPEN };
+            let out = if self.inside_think { &mut reasoning } else { &mut content };
+            if let Some(pos) = text.find(tag) {
+                out.push_str(&text[..pos]);
+                text = text[pos + tag.len()..].to_string();
+                self.inside_think = !self.inside_think;
+                continue;
+            }
+            let keep = (1..tag.len()).rev().find(|k| text.ends_with(&tag[..*k])).unwrap_or(0);
+            out.push_str(&text[..text.len() - keep]);
+            self.pending = text[text.len() - keep..].to_string();
+            break;
+        }
+        (reasoning, content)
+    }
+
+    pub fn flush(&mut self) -> (String, String) {
+        let pending = std::mem::take(&mut self.pending);
+        if self.inside_think { (pending, String::new()) } else { (String::new(), pending) }
+    }
+}
+
+pub fn put_reasoning_into_delta(json_delta: &mut Value, reasoning: String, content: String) {
+    json_delta["content"] = Value::String(content);
+    if !reasoning.is_empty() {
+        json_delta["reasoning"] = Value::String(reasoning);
+    }
+}
+
+pub fn split_reasoning_in_openai_chunk(splitter: &mut ReasoningSplitter, json: &mut Value) {
+    // some providers already separate it as "reasoning_content", the rest sends <think> inside content
+    let delta = match json.pointer_mut("/choices/0/delta") {
+        Some(delta) if delta.is_object() => delta,
+        _ => return,
+    };
+    let content = delta.get("content").and_then(|x| x.as_str()).unwrap_or("").to_string();
+    let mut reasoning = match delta.as_object_mut().unwrap().remove("reasoning_content") {
+        Some(Value::String(s)) => s,
+        _ => String::new(),
+    };
+    let (reasoning_split, content_split) = splitter.feed(&content);
+    reasoning.push_str(&reasoning_split);
+    if delta.get("content").map_or(false, |x| x.is_string()) || !content_split.is_empty() {
+        put_reasoning_into_delta(delta, reasoning, content_split);
+    } else if !reasoning.is_empty() {
+        delta["reasoning"] = Value::String(reasoning);
+    }
+}
+
 fn cut_result(text: &str, local_stop_list: &Vec<String>) -> String {
     let mut cut_at = vec![];
     for t in local_stop_list {
@@ -109,3 +191,61 @@ fn cut_result(text: &str, local_stop_list: &Vec<String>) -> String {
     let ans = text.split_at(cut_at).0.to_string();
     ans.replace("\r", "")
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reasoning_splitter_tags_across_deltas() {
+        let mut splitter = ReasoningSplitter::new();
+        let mut reasoning = String::new();
+        let mut content = String::new();
+        for delta in ["<thi", "nk>Let me ", "think, 2*21 ", "is 42</th", "ink>\nThe answer", " is 42 <b>", "</b>"] {
+            let (r, c) = splitter.feed(delta);
+            reasoning.push_str(&r);
+            content.push_str(&c);
+        }
+        let (r, c) = splitter.flush();
+        reasoning.push_str(&r);
+        content.push_str(&c);
+        assert_eq!(reasoning, "Let me think, 2*21 is 42");
+        assert_eq!(content, "\nThe answer is 42 <b></b>");
+    }
+
+    #[test]
+    fn test_deltadelta_streams_reasoning_field() {
+        let mut dd = DeltaDeltaChatStreamer::new();
+        dd.role = "assistant".to_string();
+        dd.reasoning = Some(ReasoningSplitter::new());
+        let mut reasoning = String::new();
+        let mut content = String::new();
+        let mut collect = |json: &Value| {
+            let delta = &json["choices"][0]["delta"];
+            reasoning.push_str(delta.get("reasoning").and_then(|x| x.as_str()).unwrap_or(""));
+            content.push_str(delta["content"].as_str().unwrap());
+        };
+        for delta in ["<think>", "hmm", "</think>", "Hello", " world"] {
+            let (json, _) = dd.response_streaming(delta.to_string(), FinishReason::None).unwrap();
+            collect(&json);
+        }
+        collect(&dd.streaming_finished(FinishReason::Stop).unwrap());
+        assert_eq!(reasoning, "hmm");
+        assert_eq!(content, "Hello world");
+    }
+
+    #[test]
+    fn test_split_reasoning_in_openai_chunk() {
+        let mut splitter = ReasoningSplitter::new();
+        let mut chunk1 = serde_json::json!({"choices": [{"index": 0, "delta": {"content": "<think>plan"}}]});
+        let mut chunk2 = serde_json::json!({"choices": [{"index": 0, "delta": {"content": "</think>Done"}}]});
+        let mut chunk3 = serde_json::json!({"choices": [{"index": 0, "delta": {"reasoning_content": "native", "content": null}}]});
+        split_reasoning_in_openai_chunk(&mut splitter, &mut chunk1);
+        split_reasoning_in_openai_chunk(&mut splitter, &mut chunk2);
+        split_reasoning_in_openai_chunk(&mut splitter, &mut chunk3);
+        assert_eq!(chunk1["choices"][0]["delta"], serde_json::json!({"content": "", "reasoning": "plan"}));
+        assert_eq!(chunk2["choices"][0]["delta"], serde_json::json!({"content": "Done"}));
+        assert_eq!(chunk3["choices"][0]["delta"], serde_json::json!({"content": null, "reasoning": "native"}));
+    }
+}
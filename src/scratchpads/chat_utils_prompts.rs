@@ -13,6 +13,8 @@ use crate::scratchpads::scratchpad_utils::HasRagResults;
 use crate::call_validation::{ChatMessage, ChatContent, ChatMode};
 
 
+const PROJECT_SYSTEM_PROMPT_MAX_CHARS: usize = 8000;  // about 2000 tokens, the system message is in the token budget like any other
+
 pub async fn get_default_system_prompt(
     gcx: Arc<ARwLock<GlobalContext>>,
     chat_mode: ChatMode,
@@ -159,6 +161,44 @@ pub async fn system_prompt_add_workspace_info(
     system_prompt
 }
 
+fn project_dir_for_active_file(workspace_dirs: &[PathBuf], active_file_path: &Option<PathBuf>) -> Option<PathBuf> {
+    // the deepest workspace folder containing the active file, nested folders are possible
+    let containing = active_file_path.as_ref().and_then(|active_file| {
+        workspace_dirs.iter().filter(|dir| active_file.starts_with(dir)).max_by_key(|dir| dir.components().count())
+    });
+    containing.or(workspace_dirs.first()).cloned()
+}
+
+pub fn read_project_system_prompt(workspace_dirs: &[PathBuf], active_file_path: &Option<PathBuf>) -> Option<String> {
+    let project_dir = project_dir_for_active_file(workspace_dirs, active_file_path)?;
+    let prompt_path = project_dir.join(".refact").join("system_prompt.md");
+    let text = match fs::read_to_string(&prompt_path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            tracing::error!("cannot read {}: {}", prompt_path.display(), e);
+            return None;
+        }
+    };
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    if text.chars().count() > PROJECT_SYSTEM_PROMPT_MAX_CHARS {
+        tracing::warn!("{} is longer than {} characters, the rest is cut", prompt_path.display(), PROJECT_SYSTEM_PROMPT_MAX_CHARS);
+        let cut: String = text.chars().take(PROJECT_SYSTEM_PROMPT_MAX_CHARS).collect();
+        return Some(format!("{}\n...(truncated)", cut));
+    }
+    Some(text.to_string())
+}
+
+pub fn system_prompt_add_project_prompt(system_prompt: &String, project_prompt: &Option<String>) -> String {
+    match project_prompt {
+        Some(project_prompt) => format!("{}\n\nInstructions for this project:\n{}\n", system_prompt.trim_end(), project_prompt),
+        None => system_prompt.clone(),
+    }
+}
+
 pub async fn prepend_the_right_system_prompt_and_maybe_more_initial_messages(
     gcx: Arc<ARwLock<GlobalContext>>,
     mut messages: Vec<call_validation::ChatMessage>,
@@ -191,6 +231,12 @@ pub async fn prepend_the_right_system_prompt_and_maybe_more_initial_messages(
             let system_message_content = system_prompt_add_workspace_info(gcx.clone(),
                 &get_default_system_prompt(gcx.clone(), chat_meta.chat_mode.clone()).await
             ).await;
+            let project_prompt = {
+                let workspace_dirs = crate::files_correction::get_project_dirs(gcx.clone()).await;
+                let active_file_path = gcx.read().await.documents_state.active_file_path.clone();
+                read_project_system_prompt(&workspace_dirs, &active_file_path)
+            };
+            let system_message_content = system_prompt_add_project_prompt(&system_message_content, &project_prompt);
             let msg = ChatMessage {
                 role: "system".to_string(),
                 content: ChatContent::SimpleText(system_message_content),
@@ -242,3 +288,75 @@ pub async fn prepend_system_prompt_and_maybe_more_initial_messages_from_remote(
 
     Ok(response.messages)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_system_prompt_from_active_workspace_folder() {
+        let tmp = tempfile::tempdir().unwrap();
+        let frontend = tmp.path().join("frontend");
+        let backend = tmp.path().join("backend");
+        for (dir, text) in [(&frontend, "Use React hooks, no classes."), (&backend, "A \"ledger\" is a per-customer list of invoices.\n")] {
+            fs::create_dir_all(dir.join(".refact")).unwrap();
+            fs::write(dir.join(".refact").join("system_prompt.md"), text).unwrap();
+        }
+        let workspace_dirs = vec![frontend.clone(), backend.clone()];
+
+        let active_file = Some(backend.join("src").join("ledger.py"));
+        let project_prompt = read_project_system_prompt(&workspace_dirs, &active_file);
+        let system_prompt = system_prompt_add_project_prompt(&"You are a coding assistant.\n".to_string(), &project_prompt);
+        assert!(system_prompt.starts_with("You are a coding assistant."), "{}", system_prompt);
+        assert!(system_prompt.contains("A \"ledger\" is a per-customer list of invoices."), "{}", system_prompt);
+        assert!(!system_prompt.contains("React"), "{}", system_prompt);
+
+        // no active file: the first folder
+        assert_eq!(read_project_system_prompt(&workspace_dirs, &None).unwrap(), "Use React hooks, no classes.");
+        // no file: the global prompt alone
+        fs::remove_file(frontend.join(".refact").join("system_prompt.md")).unwrap();
+        let project_prompt = read_project_system_prompt(&workspace_dirs, &Some(frontend.join("app.tsx")));
+        assert_eq!(system_prompt_add_project_prompt(&"base".to_string(), &project_prompt), "base");
+
+        fs::write(backend.join(".refact").join("system_prompt.md"), "x".repeat(PROJECT_SYSTEM_PROMPT_MAX_CHARS * 2)).unwrap();
+        let long = read_project_system_prompt(&workspace_dirs, &active_file).unwrap();
+        assert!(long.len() < PROJECT_SYSTEM_PROMPT_MAX_CHARS + 100 && long.ends_with("(truncated)"));
+    }
+
+    #[tokio::test]
+    async fn test_project_system_prompt_in_the_chat_messages() {
+        let tmp = tempfile::tempdir().unwrap();
+        let frontend = tmp.path().join("frontend");
+        let backend = tmp.path().join("backend");
+        for (dir, text) in [(&frontend, "Use React hooks, no classes."), (&backend, "A \"ledger\" is a per-customer list of invoices.")] {
+            fs::create_dir_all(dir.join(".refact")).unwrap();
+            fs::write(dir.join(".refact").join("system_prompt.md"), text).unwrap();
+        }
+        let gcx = crate::global_context::create_test_global_context_with_fim_model(tmp.path(), "http://127.0.0.1:1/v1/completions", &[]).await;
+        {
+            let mut gcx_locked = gcx.write().await;
+            *gcx_locked.documents_state.workspace_folders.lock().unwrap() = vec![frontend.clone(), backend.clone()];
+            gcx_locked.documents_state.active_file_path = Some(backend.join("src").join("ledger.py"));
+        }
+        let base_prompt = get_default_system_prompt(gcx.clone(), ChatMode::AGENT).await;
+        assert!(!base_prompt.is_empty());
+
+        let chat_meta = call_validation::ChatMeta { chat_mode: ChatMode::AGENT, ..Default::default() };
+        let mut stream_back_to_user = HasRagResults::new();
+        let messages = prepend_the_right_system_prompt_and_maybe_more_initial_messages(
+            gcx.clone(),
+            vec![ChatMessage::new("user".to_string(), "how are ledgers stored?".to_string())],
+            &chat_meta,
+            &mut stream_back_to_user,
+        ).await;
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, "system");
+        let system_prompt = messages[0].content.content_text_only();
+        // the global prompt stays, the project one from the active file's folder is added to it
+        assert!(system_prompt.starts_with(base_prompt.trim_end().lines().next().unwrap()), "{}", system_prompt);
+        assert!(system_prompt.contains("Instructions for this project:\nA \"ledger\" is a per-customer list of invoices."), "{}", system_prompt);
+        assert!(!system_prompt.contains("React"), "{}", system_prompt);
+        assert_eq!(stream_back_to_user.in_json.len(), 1);
+    }
+}
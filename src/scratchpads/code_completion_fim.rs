@@ -15,7 +15,7 @@ use crate::call_validation::{CodeCompletionPost, SamplingParameters};
 use crate::global_context::GlobalContext;
 use crate::completion_cache;
 use crate::scratchpad_abstract::{FinishReason, HasTokenizerAndEot, ScratchpadAbstract};
-use crate::scratchpads::completon_rag::retrieve_ast_based_extra_context;
+use crate::scratchpads::completon_rag::{context_format_from_patch, retrieve_ast_based_extra_context};
 use crate::telemetry::snippets_collection;
 use crate::telemetry::telemetry_structs;
 
@@ -83,13 +83,19 @@ impl ScratchpadAbstract for FillInTheMiddleScratchpad {
         _agentic_tools: bool,
     ) -> Result<(), String> {
         // That will work for some models (starcoder) without patching
+        if let Some(order) = patch.get("fim_order").and_then(|x| x.as_str()) {
+            self.order = order.to_uppercase();
+        }
+        if self.order != "PSM" && self.order != "SPM" {
+            return Err(format!("fim_order \"{}\" not recognized, should be PSM or SPM", self.order));
+        }
         self.fim_prefix = patch.get("fim_prefix").and_then(|x| x.as_str()).unwrap_or("<fim_prefix>").to_string();
         self.fim_suffix = patch.get("fim_suffix").and_then(|x| x.as_str()).unwrap_or("<fim_suffix>").to_string();
         self.fim_middle = patch.get("fim_middle").and_then(|x| x.as_str()).unwrap_or("<fim_middle>").to_string();
         self.extra_stop_tokens = patch.get("extra_stop_tokens").map(|x| x.as_array().unwrap().into_iter().map(|x| x.as_str().unwrap().to_string()).collect::<Vec<String>>()).unwrap_or(vec![]);
         self.t.eot = patch.get("eot").and_then(|x| x.as_str()).unwrap_or("<|endoftext|>").to_string();
         self.t.eos = patch.get("eos").and_then(|x| x.as_str()).unwrap_or("").to_string();
-        self.t.context_format = patch.get("context_format").and_then(|x| x.as_str()).unwrap_or_default().to_string();
+        context_format_from_patch(&mut self.t, patch)?;
         self.t.rag_ratio = patch.get("rag_ratio").and_then(|x| x.as_f64()).unwrap_or(0.5);
         self.t.assert_one_token(&self.fim_prefix.as_str())?;
         self.t.assert_one_token(&self.fim_suffix.as_str())?;
@@ -206,34 +212,11 @@ impl ScratchpadAbstract for FillInTheMiddleScratchpad {
 
         let before = before.into_iter().rev().collect::<Vec<_>>().join("");
         info!("{} FIM prompt {} tokens used < limit {}", crate::nicer_logs::last_n_chars(&cpath.display().to_string(), 30), tokens_used, limit);
-        let mut prompt: String;
-        if self.order == "PSM" {
-            prompt = format!(
-                "{}{}{}{}{}{}{}{}",
-                self.t.eos,
-                self.fim_prefix,
-                before,
-                cursor_line1,
-                self.fim_suffix,
-                cursor_line2,
-                after,
-                self.fim_middle
-            );
-        } else if self.order == "SPM" {
-            prompt = format!(
-                "{}{}{}{}{}{}{}{}",
-                self.t.eos,
-                self.fim_suffix,
-                cursor_line2,
-                after,
-                self.fim_prefix,
-                before,
-                cursor_line1,
-                self.fim_middle,
-            );
-        } else {
-            return Err(format!("order \"{}\" not recognized", self.order));
-        }
+        let mut prompt = fim_prompt_assemble(
+            &self.order, &self.t.eos, &self.fim_prefix, &self.fim_suffix, &self.fim_middle,
+            &format!("{}{}", before, cursor_line1),
+            &format!("{}{}", cursor_line2, after),
+        )?;
         let fim_ms = fim_t0.elapsed().as_millis() as i32;
         self.context_used["fim_ms"] = Value::from(fim_ms);
         self.context_used["n_ctx".to_string()] = Value::from(n_ctx as i64);
@@ -356,6 +339,27 @@ impl ScratchpadAbstract for FillInTheMiddleScratchpad {
             "snippet_telemetry_id": self.data4cache.completion0_snippet_telemetry_id,
         }))
     }
+
+    fn context_used(&self) -> Value {
+        self.context_used.clone()
+    }
+}
+
+// PSM: prefix, then suffix, the model writes the middle; SPM puts the suffix first, some models were trained that way
+pub fn fim_prompt_assemble(
+    order: &str,
+    eos: &str,
+    fim_prefix: &str,
+    fim_suffix: &str,
+    fim_middle: &str,
+    before_cursor: &str,
+    after_cursor: &str,
+) -> Result<String, String> {
+    match order {
+        "PSM" => Ok(format!("{eos}{fim_prefix}{before_cursor}{fim_suffix}{after_cursor}{fim_middle}")),
+        "SPM" => Ok(format!("{eos}{fim_suffix}{after_cursor}{fim_prefix}{before_cursor}{fim_middle}")),
+        _ => Err(format!("order \"{}\" not recognized", order)),
+    }
 }
 
 fn _cut_result(text: &str, eot_token: &str, multiline: bool, extra_stop_tokens: &Vec<String>) -> String {
@@ -386,3 +390,20 @@ fn _cut_result(text: &str, eot_token: &str, multiline: bool, extra_stop_tokens:
     let ans = text.split_at(cut_at).0.to_string();
     ans.replace("\r", "")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fim_prompt_custom_tokens() {
+        // qwen2.5-coder style tokens
+        let psm = fim_prompt_assemble("PSM", "", "<|fim_prefix|>", "<|fim_suffix|>", "<|fim_middle|>", "def f(x):\n    return ", "\n\nprint(f(1))\n").unwrap();
+        assert_eq!(psm, "<|fim_prefix|>def f(x):\n    return <|fim_suffix|>\n\nprint(f(1))\n<|fim_middle|>");
+
+        let spm = fim_prompt_assemble("SPM", "<s>", "<PRE>", "<SUF>", "<MID>", "a = ", "\nb = 2\n").unwrap();
+        assert_eq!(spm, "<s><SUF>\nb = 2\n<PRE>a = <MID>");
+
+        assert!(fim_prompt_assemble("MSP", "", "<p>", "<s>", "<m>", "", "").is_err());
+    }
+}
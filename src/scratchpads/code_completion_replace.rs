@@ -1,7 +1,7 @@
 use crate::ast::ast_indexer_thread::{ast_indexer_block_until_finished, ast_indexer_enqueue_files, AstIndexService};
 use crate::at_commands::at_commands::AtCommandsContext;
 use crate::call_validation::{
-    ChatContent, ChatMessage, CodeCompletionPost, CursorPosition, SamplingParameters,
+    ChatContent, ChatMessage, CodeCompletionPost, ContextFile, CursorPosition, SamplingParameters,
 };
 use crate::completion_cache;
 use crate::global_context::GlobalContext;
@@ -24,7 +24,7 @@ use tokio::sync::RwLock as ARwLock;
 use tracing::{info, warn};
 use crate::ast::ast_db::doc_defs;
 use crate::ast::ast_structs::AstDefinition;
-use crate::scratchpads::completon_rag::retrieve_ast_based_extra_context;
+use crate::scratchpads::completon_rag::{context_format_from_patch, rag_max_files_n, retrieve_ast_based_context_files, retrieve_ast_based_extra_context};
 
 const DEBUG: bool = false;
 const SYSTEM_PROMPT: &str = r#"You are given a code file, <BLOCK_OF_CODE> from that file and an extra context from other files.
@@ -46,6 +46,12 @@ const CURSORFILE_MIN_TOKENS: usize = 128;
 const MAX_NEW_TOKENS: usize = 1024;  // it's quite high since we want to avoid having a stripped message
 const TEMPERATURE_INITIAL: f32 = 0.2;
 const TEMPERATURE_NOCACHE: f32 = 0.6;
+// context_format that gives each RAG snippet, the cursor file and the block to rewrite a message of its own
+const CONTEXT_FORMAT_CHAT_MESSAGES: &str = "chat-messages";
+const MESSAGE_OVERHEAD_TOKENS: usize = 3;  // role and separators, the same 3 as for the system prompt
+const SNIPPET_MESSAGE_RESERVE_TOKENS: usize = 40;  // label, file name and fences around each snippet
+const RAG_SNIPPET_LABEL: &str = "Extra context from another file, use it but do not rewrite it.";
+const CURSOR_FILE_LABEL: &str = "The file being edited, for reference.";
 
 #[derive(Debug, Clone)]
 pub struct SubBlock {
@@ -382,6 +388,46 @@ fn unfence_the_last_code_block(text: &String) -> Option<String> {
     blocks.iter().last().cloned()
 }
 
+// Stops at the first snippet that doesn't fit, the labels and per-message overhead count towards tokens_limit
+fn rag_snippet_messages(
+    t: &HasTokenizerAndEot,
+    context_files: &Vec<ContextFile>,
+    tokens_limit: usize,
+) -> Result<(Vec<ChatMessage>, usize), String> {
+    let mut messages = vec![];
+    let mut tokens_used = 0;
+    for f in context_files.iter() {
+        let text = format!(
+            "{RAG_SNIPPET_LABEL}\nFilename: {}:{}-{}\n```\n{}\n```",
+            f.file_name, f.line1, f.line2, f.file_content
+        );
+        let tokens_n = t.count_tokens(&text)? as usize + MESSAGE_OVERHEAD_TOKENS;
+        if tokens_used + tokens_n > tokens_limit {
+            break;
+        }
+        tokens_used += tokens_n;
+        messages.push(ChatMessage {
+            role: "user".to_string(),
+            content: ChatContent::SimpleText(text),
+            ..Default::default()
+        });
+    }
+    Ok((messages, tokens_used))
+}
+
+fn cursor_file_and_subblock_messages(file_content: &str, subblock_prompt: &str, split: bool) -> Vec<ChatMessage> {
+    let texts = if split {
+        vec![format!("{CURSOR_FILE_LABEL}\n{file_content}"), subblock_prompt.to_string()]
+    } else {
+        vec![format!("{file_content}\n{subblock_prompt}")]
+    };
+    texts.into_iter().map(|text| ChatMessage {
+        role: "user".to_string(),
+        content: ChatContent::SimpleText(text),
+        ..Default::default()
+    }).collect()
+}
+
 fn process_n_choices(
     subblock: &mut Option<SubBlock>,
     choices: &Vec<String>,
@@ -636,11 +682,7 @@ impl ScratchpadAbstract for CodeCompletionReplaceScratchpad {
             .and_then(|x| x.as_str())
             .unwrap_or("")
             .to_string();
-        self.t.context_format = patch
-            .get("context_format")
-            .and_then(|x| x.as_str())
-            .unwrap_or_default()
-            .to_string();
+        context_format_from_patch(&mut self.t, patch)?;
         self.t.rag_ratio = patch
             .get("rag_ratio")
             .and_then(|x| x.as_f64())
@@ -829,6 +871,10 @@ impl ScratchpadAbstract for CodeCompletionReplaceScratchpad {
     fn streaming_finished(&mut self, _finish_reason: FinishReason) -> Result<Value, String> {
         Err("not implemented".to_string())
     }
+
+    fn context_used(&self) -> Value {
+        self.context_used.clone()
+    }
 }
 
 pub struct CodeCompletionReplacePassthroughScratchpad {
@@ -876,11 +922,7 @@ impl ScratchpadAbstract for CodeCompletionReplacePassthroughScratchpad {
         _exploration_tools: bool,
         _agentic_tools: bool,
     ) -> Result<(), String> {
-        self.t.context_format = patch
-            .get("context_format")
-            .and_then(|x| x.as_str())
-            .unwrap_or_default()
-            .to_string();
+        context_format_from_patch(&mut self.t, patch)?;
         self.t.rag_ratio = patch
             .get("rag_ratio")
             .and_then(|x| x.as_f64())
@@ -899,6 +941,7 @@ impl ScratchpadAbstract for CodeCompletionReplacePassthroughScratchpad {
         };
         let completion_t0 = Instant::now();
         let use_rag = self.t.rag_ratio > 0.0 && self.post.use_ast && self.ast_service.is_some();
+        let split_messages = self.t.context_format == CONTEXT_FORMAT_CHAT_MESSAGES;
         sampling_parameters_to_patch.max_new_tokens = MAX_NEW_TOKENS;
         sampling_parameters_to_patch.temperature = if !self.post.no_cache { Some(TEMPERATURE_INITIAL) } else { Some(TEMPERATURE_NOCACHE) };
         sampling_parameters_to_patch.stop = vec![self.t.eot.clone()];
@@ -947,7 +990,11 @@ impl ScratchpadAbstract for CodeCompletionReplacePassthroughScratchpad {
             0
         };
         let subblock_required_tokens = SUBBLOCK_REQUIRED_TOKENS;
-        let cursor_file_available_tokens = available_tokens.saturating_sub(subblock_required_tokens);
+        let mut cursor_file_available_tokens = available_tokens.saturating_sub(subblock_required_tokens);
+        if split_messages {
+            cursor_file_available_tokens = cursor_file_available_tokens
+                .saturating_sub(self.t.count_tokens(CURSOR_FILE_LABEL)? as usize + MESSAGE_OVERHEAD_TOKENS);
+        }
         if cursor_file_available_tokens <= CURSORFILE_MIN_TOKENS {
             return Err(format!("not enough tokens for the cursor file: {cursor_file_available_tokens} <= {CURSORFILE_MIN_TOKENS}"));
         }
@@ -975,23 +1022,41 @@ impl ScratchpadAbstract for CodeCompletionReplacePassthroughScratchpad {
                 let ccx_locked = ccx.lock().await;
                 ccx_locked.postprocess_parameters.clone()
             };
-            let extra_context = retrieve_ast_based_extra_context(
-                self.global_context.clone(),
-                self.ast_service.clone(),
-                &self.t,
-                &cpath,
-                &self.post.inputs.cursor,
-                (line1 as i32, line2 as i32),
-                pp_settings,
-                rag_tokens_n,
-                &mut self.context_used
-            ).await;
-            if !extra_context.is_empty() {
-                messages.push(ChatMessage {
-                    role: "user".to_string(),
-                    content: ChatContent::SimpleText(extra_context),
-                    ..Default::default()
-                });
+            if split_messages {
+                // leave room for the labels, so the snippets still fit into rag_tokens_n after wrapping
+                let files_n = rag_max_files_n(&self.t, &pp_settings);
+                let context_files = retrieve_ast_based_context_files(
+                    self.global_context.clone(),
+                    self.ast_service.clone(),
+                    &self.t,
+                    &cpath,
+                    &self.post.inputs.cursor,
+                    (line1 as i32, line2 as i32),
+                    pp_settings,
+                    rag_tokens_n.saturating_sub(files_n * SNIPPET_MESSAGE_RESERVE_TOKENS),
+                    &mut self.context_used
+                ).await;
+                let (rag_messages, _rag_tokens_used) = rag_snippet_messages(&self.t, &context_files, rag_tokens_n)?;
+                messages.extend(rag_messages);
+            } else {
+                let extra_context = retrieve_ast_based_extra_context(
+                    self.global_context.clone(),
+                    self.ast_service.clone(),
+                    &self.t,
+                    &cpath,
+                    &self.post.inputs.cursor,
+                    (line1 as i32, line2 as i32),
+                    pp_settings,
+                    rag_tokens_n,
+                    &mut self.context_used
+                ).await;
+                if !extra_context.is_empty() {
+                    messages.push(ChatMessage {
+                        role: "user".to_string(),
+                        content: ChatContent::SimpleText(extra_context),
+                        ..Default::default()
+                    });
+                }
             }
         }
         self.cursor_subblock = Some(subblock);
@@ -1001,14 +1066,11 @@ impl ScratchpadAbstract for CodeCompletionReplacePassthroughScratchpad {
             Some("\n".to_string())
         };
         // Editing file and the subblock within it to rewrite by the model
-        messages.push(ChatMessage {
-            role: "user".to_string(),
-            content: ChatContent::SimpleText(format!(
-                "{file_content}\n{}",
-                self.cursor_subblock.as_ref().unwrap().prompt()?
-            )),
-            ..Default::default()
-        });
+        messages.extend(cursor_file_and_subblock_messages(
+            &file_content,
+            &self.cursor_subblock.as_ref().unwrap().prompt()?,
+            split_messages,
+        ));
 
         let json_messages = &serde_json::to_string(&json!({
             "messages":  messages.iter().map(|x| { x.into_value(&None) }).collect::<Vec<_>>(),
@@ -1087,4 +1149,60 @@ impl ScratchpadAbstract for CodeCompletionReplacePassthroughScratchpad {
     fn streaming_finished(&mut self, _finish_reason: FinishReason) -> Result<Value, String> {
         Err("not implemented".to_string())
     }
+
+    fn context_used(&self) -> Value {
+        self.context_used.clone()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMMY_TOKENIZER: &str = include_str!("../ast/dummy_tokenizer.json");
+
+    fn context_file(file_name: &str, file_content: &str) -> ContextFile {
+        ContextFile {
+            file_name: file_name.to_string(),
+            file_content: file_content.to_string(),
+            line1: 1,
+            line2: file_content.lines().count(),
+            symbols: vec![],
+            gradient_type: -1,
+            usefulness: 100.,
+        }
+    }
+
+    #[test]
+    fn test_chat_messages_context_format() {
+        // one token per character
+        let t = HasTokenizerAndEot::new(Arc::new(StdRwLock::new(std::str::FromStr::from_str(DUMMY_TOKENIZER).unwrap())));
+        let files = vec![
+            context_file("src/db.py", "def connect():\n    pass"),
+            context_file("src/models.py", "class Order:\n    total: int"),
+            context_file("src/big.py", &"x = 1\n".repeat(100)),
+        ];
+        let (rag, tokens_used) = rag_snippet_messages(&t, &files, 400).unwrap();
+        assert_eq!(rag.len(), 2, "the third snippet doesn't fit");
+        let counted: usize = rag.iter().map(|m| t.count_tokens(&m.content.content_text_only()).unwrap() as usize + MESSAGE_OVERHEAD_TOKENS).sum();
+        assert_eq!(tokens_used, counted);
+        assert!(tokens_used <= 400);
+        for (m, f) in rag.iter().zip(files.iter()) {
+            let text = m.content.content_text_only();
+            assert_eq!(m.role, "user");
+            assert!(text.starts_with(RAG_SNIPPET_LABEL), "{}", text);
+            assert!(text.contains(&format!("Filename: {}:1-2", f.file_name)), "{}", text);
+            assert!(text.contains(&f.file_content), "{}", text);
+        }
+
+        let split = cursor_file_and_subblock_messages("File name:\nmain.py", "<BLOCK_OF_CDDE>:\n```\nx = <CURSOR>\n```", true);
+        assert_eq!(split.iter().map(|m| m.role.as_str()).collect::<Vec<_>>(), vec!["user", "user"]);
+        assert_eq!(split[0].content.content_text_only(), format!("{}\nFile name:\nmain.py", CURSOR_FILE_LABEL));
+        assert!(split[1].content.content_text_only().starts_with("<BLOCK_OF_CDDE>"));
+
+        let joined = cursor_file_and_subblock_messages("File name:\nmain.py", "<BLOCK_OF_CDDE>", false);
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0].content.content_text_only(), "File name:\nmain.py\n<BLOCK_OF_CDDE>");
+    }
 }
@@ -1,3 +1,9 @@
+use std::path::PathBuf;
+
+use crate::ast::treesitter::language_id::LanguageId;
+use crate::ast::treesitter::parsers::{parse_text_to_tree, tree_sitter_language_by_filename};
+
+
 enum ParserState {
     Normal,
     InSingleLineComment,
@@ -188,6 +194,83 @@ pub fn parse_comments(text: &str, extension: &str) -> Vec<Comment> {
     comments
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommentStripping {
+    Off,
+    All,
+    KeepDocstrings,
+}
+
+impl CommentStripping {
+    pub fn from_arg(s: &str) -> Result<CommentStripping, String> {
+        match s.trim() {
+            "" | "off" => Ok(CommentStripping::Off),
+            "all" => Ok(CommentStripping::All),
+            "keep-docstrings" => Ok(CommentStripping::KeepDocstrings),
+            _ => Err(format!("unknown comment stripping {:?}, use off, all or keep-docstrings", s)),
+        }
+    }
+}
+
+fn is_doc_comment(text: &str) -> bool {
+    text.starts_with("/**") || text.starts_with("///") || text.starts_with("//!")
+}
+
+// Byte ranges of what to cut out, straight from the syntax tree, so "//" or "#" inside a string literal is never a comment
+fn collect_comment_ranges(node: tree_sitter::Node, text: &str, language_id: LanguageId, stripping: CommentStripping, ranges: &mut Vec<std::ops::Range<usize>>) {
+    if node.kind().contains("comment") {
+        let keep = stripping == CommentStripping::KeepDocstrings && is_doc_comment(&text[node.byte_range()]);
+        if !keep {
+            ranges.push(node.byte_range());
+        }
+        return;
+    }
+    // Python docstrings are string literals that make a statement on their own
+    if language_id == LanguageId::Python && node.kind() == "expression_statement" && node.named_child_count() == 1 {
+        if let Some(string) = node.named_child(0).filter(|c| c.kind() == "string") {
+            if stripping == CommentStripping::All {
+                ranges.push(string.byte_range());
+            }
+            return;
+        }
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_comment_ranges(child, text, language_id, stripping, ranges);
+    }
+}
+
+// Comments are cut out but their newlines stay, so line numbers of the code don't move.
+// Files without a tree-sitter grammar are left as they are.
+pub fn strip_comments(text: &str, path: &PathBuf, stripping: CommentStripping) -> String {
+    if stripping == CommentStripping::Off {
+        return text.to_string();
+    }
+    let Ok(language_id) = tree_sitter_language_by_filename(path) else { return text.to_string() };
+    let tree = match parse_text_to_tree(language_id, text) {
+        Ok(tree) => tree,
+        Err(e) => {
+            tracing::warn!("comments in {} are not stripped: {}", path.display(), e);
+            return text.to_string();
+        }
+    };
+    let mut ranges = vec![];
+    collect_comment_ranges(tree.root_node(), text, language_id, stripping, &mut ranges);
+
+    let mut result = String::with_capacity(text.len());
+    let mut pos = 0;
+    for range in ranges {
+        if range.start < pos {
+            continue;
+        }
+        result.push_str(&text[pos..range.start]);
+        result.push_str(&"\n".repeat(text[range.clone()].matches('\n').count()));
+        pos = range.end;
+    }
+    result.push_str(&text[pos..]);
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -400,4 +483,53 @@ mod tests {
         assert_eq!(comments[1].start_line, 3);
         assert_eq!(comments[1].end_line, 3);
     }
+
+    #[test]
+    fn test_license_header_stripped_before_vectorization() {
+        let code = r#"/*
+ * Copyright (c) 2024 Example Corp. All rights reserved.
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at http://www.apache.org/licenses/LICENSE-2.0
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS.
+ */
+/** Parses the retry policy from the config. */
+int parse_retry_policy(const char *cfg) {
+    // int old = legacy_parse(cfg);
+    return atoi(cfg);  // seconds
+}
+"#;
+        let c_path = PathBuf::from("retry.c");
+        let py_path = PathBuf::from("one.py");
+        assert_eq!(strip_comments(code, &c_path, CommentStripping::Off), code);
+
+        let stripped = strip_comments(code, &c_path, CommentStripping::All);
+        assert!(!stripped.contains("License"), "{}", stripped);
+        assert!(!stripped.contains("legacy_parse"), "{}", stripped);
+        assert!(!stripped.contains("retry policy from"), "{}", stripped);
+        assert!(stripped.contains("int parse_retry_policy(const char *cfg) {"), "{}", stripped);
+        assert!(stripped.contains("    return atoi(cfg);"), "{}", stripped);
+        // the code is all that's left to embed, on the same lines as before
+        assert_eq!(stripped.lines().count(), code.lines().count());
+        assert_eq!(stripped.lines().nth(9), Some("int parse_retry_policy(const char *cfg) {"));
+        assert!(stripped.trim().starts_with("int parse_retry_policy"), "{}", stripped);
+
+        let with_docs = strip_comments(code, &c_path, CommentStripping::KeepDocstrings);
+        assert!(!with_docs.contains("License"), "{}", with_docs);
+        assert!(with_docs.contains("/** Parses the retry policy from the config. */"), "{}", with_docs);
+
+        let py = "# Copyright 2024 Example Corp\n# SPDX-License-Identifier: MIT\ndef f():\n    \"\"\"Returns one.\"\"\"\n    return 1  # one\n";
+        let py_stripped = strip_comments(py, &py_path, CommentStripping::KeepDocstrings);
+        assert_eq!(py_stripped, "\n\ndef f():\n    \"\"\"Returns one.\"\"\"\n    return 1  \n");
+
+        // comment markers inside string literals are code, and so is a literal with the same text as a comment
+        let tricky = "s = \"# one\"  # one\nurl = 'http://example.com'\n";
+        assert_eq!(strip_comments(tricky, &py_path, CommentStripping::All), "s = \"# one\"  \nurl = 'http://example.com'\n");
+        let tricky_c = "const char *u = \"http://x /* y */\"; // real\n";
+        assert_eq!(strip_comments(tricky_c, &c_path, CommentStripping::All), "const char *u = \"http://x /* y */\"; \n");
+        assert_eq!(strip_comments("# kept\n", &PathBuf::from("run.sh"), CommentStripping::All), "# kept\n");
+        assert!(CommentStripping::from_arg("sometimes").is_err());
+    }
+
 }
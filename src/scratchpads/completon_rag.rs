@@ -4,6 +4,7 @@ use crate::call_validation::{ContextFile, CursorPosition, PostprocessSettings};
 use crate::global_context::GlobalContext;
 use crate::postprocessing::pp_context_files::postprocess_context_files;
 use crate::scratchpad_abstract::HasTokenizerAndEot;
+use serde::Deserialize;
 use serde_json::{json, Value};
 use std::collections::HashSet;
 use std::path::PathBuf;
@@ -17,10 +18,13 @@ use tracing::info;
 const DEBUG: bool = false;
 
 const TAKE_USAGES_AROUND_CURSOR: usize = 20;
+const NOT_ACTIVE_FILE_DOWNGRADE: f32 = 0.9;
+const RAG_DEFAULT_MAX_FILES_N: usize = 5;
 
 async fn _render_context_files(
     gcx: Arc<ARwLock<GlobalContext>>,
     context_format: &String,
+    context_template: &Option<ContextTemplate>,
     postprocessed_messages: &Vec<ContextFile>,
     cursor_filepath: &PathBuf,
 ) -> String {
@@ -44,36 +48,92 @@ async fn _render_context_files(
                 cursor_filepath.to_string_lossy().to_string(),
             )
         };
-    let mut context_files_prompt = String::new();
-    match context_format.as_str() {
-        "starcoder" => {
-            context_files_prompt.push_str(&format!("<repo_name>{}\n", repo_name));
-            for m in postprocessed_messages {
-                context_files_prompt
-                    .push_str(&format!("<file_sep>{}\n{}", m.file_name, m.file_content));
-            }
-            format!("{context_files_prompt}<file_sep>{cursor_filepath_stripped}\n")
-        }
-        "qwen2.5" => {
-            context_files_prompt.push_str(&format!("<|repo_name|>{}\n", repo_name));
-            for m in postprocessed_messages {
-                context_files_prompt
-                    .push_str(&format!("<|file_sep|>{}\n{}", m.file_name, m.file_content));
-            }
-            format!("{context_files_prompt}<|file_sep|>{cursor_filepath_stripped}\n")
+    let template = match context_template.clone().or_else(|| builtin_context_template(context_format)) {
+        Some(template) => template,
+        None => {
+            tracing::warn!("context_format \"{}\" not recognized", context_format);
+            return "".to_string();
         }
-        "chat" => {
-            for m in postprocessed_messages {
-                context_files_prompt
-                    .push_str(&format!("Filename: {}\nUseful content:\n```\n{}\n```\n\n", m.file_name, m.file_content));
+    };
+    render_context_template(&template, &repo_name, &cursor_filepath_stripped, postprocessed_messages)
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct ContextTemplate {
+    // placeholders: %repo% %cursor_file% in prefix and suffix, %file% %range% %code% in snippet
+    #[serde(default)]
+    pub prefix: String,
+    pub snippet: String,
+    #[serde(default)]
+    pub suffix: String,
+}
+
+fn builtin_context_template(context_format: &str) -> Option<ContextTemplate> {
+    let (prefix, snippet, suffix) = match context_format {
+        "starcoder" => ("<repo_name>%repo%\n", "<file_sep>%file%\n%code%", "<file_sep>%cursor_file%\n"),
+        "qwen2.5" => ("<|repo_name|>%repo%\n", "<|file_sep|>%file%\n%code%", "<|file_sep|>%cursor_file%\n"),
+        // chat-messages puts each snippet in its own message where the scratchpad supports it, this is the fallback
+        "chat" | "chat-messages" => ("", "Filename: %file%\nUseful content:\n```\n%code%\n```\n\n", ""),
+        _ => return None,
+    };
+    Some(ContextTemplate { prefix: prefix.to_string(), snippet: snippet.to_string(), suffix: suffix.to_string() })
+}
+
+fn fill_placeholders(template: &str, values: &[(&str, &str)]) -> String {
+    // one pass, so code that happens to contain "%file%" stays as is
+    let mut result = String::new();
+    let mut rest = template;
+    'outer: while let Some(pos) = rest.find('%') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        for (name, value) in values {
+            let placeholder = format!("%{}%", name);
+            if rest.starts_with(&placeholder) {
+                result.push_str(value);
+                rest = &rest[placeholder.len()..];
+                continue 'outer;
             }
-            context_files_prompt
-        }
-        _ => {
-            tracing::warn!("context_format \"{}\" not recognized", context_format);
-            "".to_string()
         }
+        result.push('%');
+        rest = &rest[1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+pub fn render_context_template(
+    template: &ContextTemplate,
+    repo_name: &str,
+    cursor_file: &str,
+    context_files: &Vec<ContextFile>,
+) -> String {
+    let around = [("repo", repo_name), ("cursor_file", cursor_file)];
+    let mut result = fill_placeholders(&template.prefix, &around);
+    for m in context_files {
+        let range = format!("{}-{}", m.line1, m.line2);
+        result.push_str(&fill_placeholders(&template.snippet, &[
+            ("file", m.file_name.as_str()),
+            ("range", range.as_str()),
+            ("code", m.file_content.as_str()),
+        ]));
+    }
+    result.push_str(&fill_placeholders(&template.suffix, &around));
+    result
+}
+
+pub fn context_format_from_patch(t: &mut HasTokenizerAndEot, patch: &Value) -> Result<(), String> {
+    t.context_format = patch.get("context_format").and_then(|x| x.as_str()).unwrap_or_default().to_string();
+    t.context_template = match patch.get("context_template") {
+        Some(x) => Some(serde_json::from_value::<ContextTemplate>(x.clone())
+            .map_err(|e| format!("cannot parse context_template in the model patch: {}", e))?),
+        None => None,
+    };
+    if t.context_template.is_some() && t.context_format.is_empty() {
+        t.context_format = "custom".to_string();  // non-empty context_format turns RAG on
     }
+    t.rag_max_snippets = patch.get("rag_max_snippets").and_then(|x| x.as_u64()).unwrap_or(0) as usize;
+    t.rag_snippet_max_tokens = patch.get("rag_snippet_max_tokens").and_then(|x| x.as_u64()).unwrap_or(0) as usize;
+    Ok(())
 }
 
 async fn _cursor_position_to_context_file(
@@ -153,6 +213,16 @@ async fn _cursor_position_to_context_file(
     output
 }
 
+// The file the user is looking at is the most likely to be relevant, postprocessing fills the budget in usefulness order
+pub fn prefer_active_file(context_files: &mut Vec<ContextFile>, active_file_path: &Option<PathBuf>) {
+    let Some(active) = active_file_path else { return };
+    let is_active = |cf: &ContextFile| PathBuf::from(&cf.file_name) == *active;
+    for cf in context_files.iter_mut().filter(|cf| !is_active(cf) && cf.usefulness > 0.0) {
+        cf.usefulness *= NOT_ACTIVE_FILE_DOWNGRADE;
+    }
+    context_files.sort_by_key(|cf| !is_active(cf));
+}
+
 pub async fn retrieve_ast_based_extra_context(
     gcx: Arc<ARwLock<GlobalContext>>,
     ast_service: Option<Arc<AMutex<AstIndexService>>>,
@@ -164,10 +234,54 @@ pub async fn retrieve_ast_based_extra_context(
     rag_tokens_n: usize,
     context_used: &mut Value,
 ) -> String {
+    let postprocessed_messages = retrieve_ast_based_context_files(
+        gcx.clone(),
+        ast_service,
+        t,
+        cpath,
+        pos,
+        subblock_to_ignore_range,
+        pp_settings,
+        rag_tokens_n,
+        context_used,
+    ).await;
+    _render_context_files(
+        gcx.clone(),
+        &t.context_format,
+        &t.context_template,
+        &postprocessed_messages,
+        cpath,
+    )
+    .await
+}
+
+// rag_max_snippets from the scratchpad patch wins over the postprocess settings
+pub fn rag_max_files_n(t: &HasTokenizerAndEot, pp_settings: &PostprocessSettings) -> usize {
+    if t.rag_max_snippets > 0 {
+        t.rag_max_snippets
+    } else if pp_settings.max_files_n > 0 {
+        pp_settings.max_files_n
+    } else {
+        RAG_DEFAULT_MAX_FILES_N
+    }
+}
+
+pub async fn retrieve_ast_based_context_files(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    ast_service: Option<Arc<AMutex<AstIndexService>>>,
+    t: &HasTokenizerAndEot,
+    cpath: &PathBuf,
+    pos: &CursorPosition,
+    subblock_to_ignore_range: (i32, i32),
+    pp_settings: PostprocessSettings,
+    rag_tokens_n: usize,
+    context_used: &mut Value,
+) -> Vec<ContextFile> {
     info!(" -- ast-based rag search starts --");
     let mut pp_settings = pp_settings;
-    if pp_settings.max_files_n == 0 {
-        pp_settings.max_files_n = 5;
+    pp_settings.max_files_n = rag_max_files_n(t, &pp_settings);
+    if t.rag_snippet_max_tokens > 0 {
+        pp_settings.max_tokens_per_file = t.rag_snippet_max_tokens;
     }
 
     let rag_t0 = Instant::now();
@@ -184,6 +298,9 @@ pub async fn retrieve_ast_based_extra_context(
         vec![]
     };
 
+    let active_file_path = gcx.read().await.documents_state.active_file_path.clone();
+    prefer_active_file(&mut ast_context_file_vec, &active_file_path);
+
     let to_buckets_ms = rag_t0.elapsed().as_millis() as i32;
     if subblock_to_ignore_range.0 != i32::MAX && subblock_to_ignore_range.1 != i32::MIN {
         // disable (usefulness==-1) the FIM region around the cursor from getting into the results
@@ -233,13 +350,7 @@ pub async fn retrieve_ast_based_extra_context(
             .collect(),
     );
     context_used["rag_ms"] = Value::from(rag_ms);
-    _render_context_files(
-        gcx.clone(),
-        &t.context_format,
-        &postprocessed_messages,
-        &cpath,
-    )
-    .await
+    postprocessed_messages
 }
 
 //     // context["cursor_symbols"] = Value::Array(search_traces.cursor_symbols.iter()
@@ -247,3 +358,77 @@ pub async fn retrieve_ast_based_extra_context(
 //     // context["bucket_usage_of_same_stuff"] = Value::Array(search_traces.bucket_usage_of_same_stuff.iter()
 //     // context["bucket_high_overlap"] = Value::Array(search_traces.bucket_high_overlap.iter()
 //     // context["bucket_imports"] = Value::Array(search_traces.bucket_imports.iter()
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context_files() -> Vec<ContextFile> {
+        vec![ContextFile {
+            file_name: "src/lib.rs".to_string(),
+            file_content: "fn add(a: i32, b: i32) -> i32 { a + b } // %file%".to_string(),
+            line1: 3,
+            line2: 5,
+            symbols: vec![],
+            gradient_type: -1,
+            usefulness: 100.,
+        }]
+    }
+
+    #[test]
+    fn test_custom_context_template() {
+        let chat = builtin_context_template("chat").unwrap();
+        assert_eq!(
+            render_context_template(&chat, "repo", "src/main.rs", &context_files()),
+            "Filename: src/lib.rs\nUseful content:\n```\nfn add(a: i32, b: i32) -> i32 { a + b } // %file%\n```\n\n",
+        );
+
+        let mut t = HasTokenizerAndEot::new(Arc::new(std::sync::RwLock::new(
+            tokenizers::Tokenizer::new(tokenizers::models::bpe::BPE::default())
+        )));
+        context_format_from_patch(&mut t, &json!({
+            "context_template": {
+                "prefix": "# repo %repo%\n",
+                "snippet": "# %file%:%range%\n%code%\n",
+                "suffix": "# %cursor_file%\n",
+            }
+        })).unwrap();
+        assert_eq!(t.context_format, "custom");
+        assert_eq!(
+            render_context_template(t.context_template.as_ref().unwrap(), "repo", "src/main.rs", &context_files()),
+            "# repo repo\n# src/lib.rs:3-5\nfn add(a: i32, b: i32) -> i32 { a + b } // %file%\n# src/main.rs\n",
+        );
+
+        assert!(context_format_from_patch(&mut t, &json!({"context_template": {"prefix": "no snippet"}})).is_err());
+    }
+
+    fn def_at(file_name: &str, symbol: &str) -> ContextFile {
+        ContextFile {
+            file_name: file_name.to_string(),
+            file_content: "".to_string(),
+            line1: 1,
+            line2: 3,
+            symbols: vec![symbol.to_string()],
+            gradient_type: -1,
+            usefulness: 100.,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_active_file_notification_changes_context_order() {
+        let workspace = PathBuf::from("/home/user/shop");
+        let mut documents_state = crate::files_in_workspace::DocumentsState::new(vec![workspace.clone()]).await;
+        let mut context = vec![def_at("/home/user/shop/cart.py", "Cart"), def_at("/home/user/shop/orders.py", "Order")];
+
+        // the user focused the orders pane without typing there
+        assert!(documents_state.set_active_file_if_in_workspace(&workspace.join("orders.py")));
+        // then looked at a library file, it's not in the workspace and doesn't count
+        assert!(!documents_state.set_active_file_if_in_workspace(&PathBuf::from("/usr/lib/python3/json/__init__.py")));
+        assert_eq!(documents_state.active_file_path, Some(workspace.join("orders.py")));
+
+        prefer_active_file(&mut context, &documents_state.active_file_path);
+        assert_eq!(context.iter().map(|cf| cf.symbols[0].as_str()).collect::<Vec<_>>(), vec!["Order", "Cart"]);
+        assert_eq!(context[0].usefulness, 100.);
+        assert!(context[1].usefulness < 100.);
+    }
+}
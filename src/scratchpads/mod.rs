@@ -13,9 +13,9 @@ pub mod chat_utils_prompts;
 pub mod scratchpad_utils;
 pub mod code_completion_replace;
 pub mod multimodality;
-mod comments_parser;
+pub mod comments_parser;
 mod passthrough_convert_messages;
-mod completon_rag;
+pub mod completon_rag;
 
 use crate::ast::ast_indexer_thread::AstIndexService;
 use crate::call_validation::{ChatMessage, CodeCompletionPost};
@@ -23,6 +23,7 @@ use crate::call_validation::ChatPost;
 use crate::global_context::GlobalContext;
 use crate::caps::CodeAssistantCaps;
 use crate::scratchpad_abstract::ScratchpadAbstract;
+use crate::scratchpads::chat_utils_deltadelta::ReasoningSplitter;
 use crate::completion_cache;
 use crate::telemetry::telemetry_structs;
 use crate::cached_tokenizers;
@@ -43,6 +44,9 @@ pub async fn create_code_completion_scratchpad(
     ast_module: Option<Arc<AMutex<AstIndexService>>>,
 ) -> Result<Box<dyn ScratchpadAbstract>, String> {
     let mut result: Box<dyn ScratchpadAbstract>;
+    if let Some(e) = crate::caps::scratchpad_disabled_error(&caps.read().unwrap(), scratchpad_name) {
+        return Err(e);
+    }
     let tokenizer_arc: Arc<StdRwLock<Tokenizer>> = cached_tokenizers::cached_tokenizer(caps, global_context.clone(), model_name_for_tokenizer).await?;
     if scratchpad_name == "FIM-PSM" {
         result = Box::new(code_completion_fim::FillInTheMiddleScratchpad::new(
@@ -52,6 +56,11 @@ pub async fn create_code_completion_scratchpad(
         result = Box::new(code_completion_fim::FillInTheMiddleScratchpad::new(
             tokenizer_arc, &post, "SPM".to_string(), cache_arc, tele_storage, ast_module, global_context.clone()
         ))
+    } else if scratchpad_name == "FIM" {
+        // tokens and "fim_order" come from the patch in caps, PSM if not set
+        result = Box::new(code_completion_fim::FillInTheMiddleScratchpad::new(
+            tokenizer_arc, &post, "PSM".to_string(), cache_arc, tele_storage, ast_module, global_context.clone()
+        ))
     } else if scratchpad_name == "REPLACE" {
         result = Box::new(code_completion_replace::CodeCompletionReplaceScratchpad::new(
             tokenizer_arc, &post, cache_arc, tele_storage, ast_module, global_context.clone()
@@ -81,19 +90,31 @@ pub async fn create_chat_scratchpad(
     supports_clicks: bool,
 ) -> Result<Box<dyn ScratchpadAbstract>, String> {
     let mut result: Box<dyn ScratchpadAbstract>;
+    if let Some(e) = crate::caps::scratchpad_disabled_error(&caps.read().unwrap(), scratchpad_name) {
+        return Err(e);
+    }
+    let supports_reasoning = caps.read().unwrap().code_chat_models.get(&model_name_for_tokenizer)
+        .map(|rec| rec.supports_reasoning).unwrap_or(false);
+    let reasoning_splitter = || if supports_reasoning { Some(ReasoningSplitter::new()) } else { None };
     let tokenizer_arc = cached_tokenizers::cached_tokenizer(caps, global_context.clone(), model_name_for_tokenizer).await?;
     if scratchpad_name == "CHAT-GENERIC" {
-        result = Box::new(chat_generic::GenericChatScratchpad::new(
+        let mut scratchpad = chat_generic::GenericChatScratchpad::new(
             tokenizer_arc.clone(), post, messages, allow_at
-        ));
+        );
+        scratchpad.dd.reasoning = reasoning_splitter();
+        result = Box::new(scratchpad);
     } else if scratchpad_name == "CHAT-LLAMA2" {
-        result = Box::new(chat_llama2::ChatLlama2::new(
+        let mut scratchpad = chat_llama2::ChatLlama2::new(
             tokenizer_arc.clone(), post, messages, allow_at
-        ));
+        );
+        scratchpad.dd.reasoning = reasoning_splitter();
+        result = Box::new(scratchpad);
     } else if scratchpad_name == "PASSTHROUGH" {
-        result = Box::new(chat_passthrough::ChatPassthrough::new(
+        let mut scratchpad = chat_passthrough::ChatPassthrough::new(
             tokenizer_arc.clone(), post, messages, allow_at, supports_tools, supports_clicks
-        ));
+        );
+        scratchpad.reasoning = reasoning_splitter();
+        result = Box::new(scratchpad);
     } else {
         return Err(format!("This rust binary doesn't have chat scratchpad \"{}\" compiled in", scratchpad_name));
     }
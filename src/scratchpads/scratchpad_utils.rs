@@ -4,6 +4,7 @@ use regex::Regex;
 use serde_json::Value;
 use tokenizers::Tokenizer;
 
+use crate::call_validation::ChatMessage;
 use crate::postprocessing::pp_context_files::RESERVE_FOR_QUESTION_AND_FOLLOWUP;
 
 
@@ -99,10 +100,28 @@ pub fn calculate_image_tokens_openai(image_string: &String, detail: &str) -> Res
     }
 }
 
+pub fn tool_rounds_in_current_turn(messages: &Vec<ChatMessage>) -> usize {
+    // the turn starts at the last message the user typed, each assistant message with tool calls after it is one round
+    messages.iter().rev()
+        .take_while(|m| m.role != "user")
+        .filter(|m| m.role == "assistant" && m.tool_calls.as_ref().map_or(false, |calls| !calls.is_empty()))
+        .count()
+}
+
+pub fn enforce_tool_rounds_cap(messages: &mut Vec<ChatMessage>, max_tool_rounds: usize, stop_message: &str) -> bool {
+    // returns true if the model should get no more tools in this turn, 0 means no limit
+    if max_tool_rounds == 0 || tool_rounds_in_current_turn(messages) < max_tool_rounds {
+        return false;
+    }
+    messages.push(ChatMessage::new("cd_instruction".to_string(), stop_message.to_string()));
+    true
+}
+
 // cargo test scratchpads::scratchpad_utils
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::call_validation::{ChatToolCall, ChatToolFunction};
 
     #[test]
     fn test_calculate_image_tokens_by_dimensions_openai() {
@@ -130,4 +149,50 @@ mod tests {
         let non_matching_url = "https://example.com/image.png";
         assert_eq!(parse_image_b64_from_image_url_openai(non_matching_url), None);
     }
+
+    fn assistant_with_tool_call(n: usize) -> ChatMessage {
+        ChatMessage {
+            role: "assistant".to_string(),
+            tool_calls: Some(vec![ChatToolCall {
+                id: format!("call_{}", n),
+                function: ChatToolFunction { arguments: "{}".to_string(), name: "cat".to_string() },
+                tool_type: "function".to_string(),
+            }]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tool_rounds_cap_stops_the_loop() {
+        let max_tool_rounds = 3;
+        let stop_message = "Stop calling tools and write the final answer.";
+        let mut messages = vec![
+            ChatMessage::new("system".to_string(), "You are a helpful assistant.".to_string()),
+            ChatMessage::new("user".to_string(), "first question".to_string()),
+            assistant_with_tool_call(100),
+            ChatMessage::new("assistant".to_string(), "first answer".to_string()),
+            ChatMessage::new("user".to_string(), "fix the bug".to_string()),
+        ];
+        // the model keeps calling tools, each round the scratchpad sees the history grow
+        let mut rounds_allowed = 0;
+        for n in 0..10 {
+            if enforce_tool_rounds_cap(&mut messages, max_tool_rounds, stop_message) {
+                break;
+            }
+            rounds_allowed += 1;
+            messages.push(assistant_with_tool_call(n));
+            messages.push(ChatMessage { role: "tool".to_string(), tool_call_id: format!("call_{}", n), ..Default::default() });
+        }
+        assert_eq!(rounds_allowed, max_tool_rounds);
+        assert_eq!(tool_rounds_in_current_turn(&messages), max_tool_rounds);
+        let last = messages.last().unwrap();
+        assert_eq!(last.role, "cd_instruction");
+        assert_eq!(last.content.content_text_only(), stop_message);
+
+        // a new user message starts a new turn
+        messages.push(ChatMessage::new("user".to_string(), "thanks, now the tests".to_string()));
+        assert_eq!(tool_rounds_in_current_turn(&messages), 0);
+        assert!(!enforce_tool_rounds_cap(&mut messages, max_tool_rounds, stop_message));
+        assert!(!enforce_tool_rounds_cap(&mut vec![assistant_with_tool_call(0)], 0, stop_message));
+    }
 }
@@ -0,0 +1,187 @@
+use std::future::Future;
+use std::sync::Arc;
+use hyper::{Body, Response, StatusCode};
+use serde_json::{json, Value};
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::call_validation::{ChatMessage, ChatPost};
+use crate::custom_error::ScratchError;
+use crate::subchat::{chat_post_interaction, subchat_single};
+
+
+const JUDGE_MAX_NEW_TOKENS: usize = 1024;
+
+const JUDGE_PROMPT: &str = r#"You review the work of a coding assistant. Below is the task it was given and its final answer.
+Decide if the answer solves the task correctly and completely. Check the code for bugs, missed requirements and unsafe changes.
+
+Reply with PASS or FAIL on the first line, nothing else on that line.
+After a FAIL, list concrete suggestions on how to fix the answer, one per line. Don't rewrite the answer yourself.
+
+TASK:
+%TASK%
+
+ANSWER:
+%ANSWER%
+"#;
+
+const REVISE_PROMPT: &str = "A reviewer checked your answer and found problems:\n%SUGGESTIONS%\n\nRevise your answer taking this into account. If you disagree with a point, say why.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JudgeVerdict {
+    pub passed: bool,
+    pub suggestions: String,
+}
+
+pub fn judge_messages(task: &str, answer: &str) -> Vec<ChatMessage> {
+    vec![ChatMessage::new("user".to_string(), JUDGE_PROMPT.replace("%TASK%", task).replace("%ANSWER%", answer))]
+}
+
+// Models like to say "**PASS**" or "Verdict: FAIL", so the first word that is either counts
+pub fn parse_judge_verdict(judge_says: &str) -> Result<JudgeVerdict, String> {
+    let mut lines = judge_says.trim().lines();
+    let first_line = lines.next().unwrap_or("").to_uppercase();
+    let verdict = first_line
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find(|w| *w == "PASS" || *w == "FAIL")
+        .ok_or(format!("judge didn't start with PASS or FAIL: {:?}", crate::nicer_logs::first_n_chars(&judge_says.to_string(), 100)))?;
+    Ok(JudgeVerdict {
+        passed: verdict == "PASS",
+        suggestions: lines.collect::<Vec<_>>().join("\n").trim().to_string(),
+    })
+}
+
+fn last_user_text(messages: &Vec<ChatMessage>) -> String {
+    messages.iter().rev().find(|m| m.role == "user").map(|m| m.content.content_text_only()).unwrap_or_default()
+}
+
+// At most one critique and one revision, the revision is returned as is even if the judge wouldn't like it either.
+// Tool calls are an intermediate step and are not judged, the client runs them and comes back with another request.
+pub async fn generate_with_self_critique<G, GFut, J, JFut>(
+    messages: Vec<ChatMessage>,
+    generate: G,
+    judge: J,
+) -> Result<(Vec<ChatMessage>, Option<JudgeVerdict>), String>
+where
+    G: Fn(Vec<ChatMessage>) -> GFut,
+    GFut: Future<Output = Result<Vec<ChatMessage>, String>>,
+    J: Fn(String, String) -> JFut,
+    JFut: Future<Output = Result<JudgeVerdict, String>>,
+{
+    let task = last_user_text(&messages);
+    let generated = generate(messages).await?;
+    let answer = match generated.last() {
+        Some(m) if m.role == "assistant" && m.tool_calls.as_ref().map_or(true, |calls| calls.is_empty()) => m.content.content_text_only(),
+        _ => return Ok((generated, None)),
+    };
+    let verdict = match judge(task, answer).await {
+        Ok(verdict) => verdict,
+        Err(e) => {
+            // the answer is still good to return, the critique is a bonus
+            tracing::warn!("self-critique: judge failed, returning the answer unreviewed: {}", e);
+            return Ok((generated, None));
+        }
+    };
+    if verdict.passed {
+        return Ok((generated, Some(verdict)));
+    }
+    let mut revise_messages = generated;
+    revise_messages.push(ChatMessage::new("user".to_string(), REVISE_PROMPT.replace("%SUGGESTIONS%", &verdict.suggestions)));
+    let revised = generate(revise_messages).await?;
+    Ok((revised, Some(verdict)))
+}
+
+// The draft and the revision go through the chat scratchpad of the request itself, with its tools and @-commands.
+// The judge is a plain subchat without tools, all it sees is the task and the answer.
+pub async fn chat_with_self_critique(
+    ccx: Arc<AMutex<AtCommandsContext>>,
+    chat_post: &ChatPost,
+    judge_model: &str,
+    messages: Vec<ChatMessage>,
+    allow_at: bool,
+) -> Result<Response<Body>, ScratchError> {
+    let model_name = chat_post.model.as_str();
+    let stream = chat_post.stream != Some(false);
+    let messages_n = messages.len();
+    let generate = |msgs: Vec<ChatMessage>| {
+        let ccx = ccx.clone();
+        async move {
+            let choices = chat_post_interaction(ccx, chat_post, msgs, allow_at).await?;
+            choices.into_iter().next().ok_or("model returned no choices".to_string())
+        }
+    };
+    let judge = |task: String, answer: String| {
+        let ccx = ccx.clone();
+        async move {
+            let choices = subchat_single(ccx, judge_model, judge_messages(&task, &answer), vec![], None, false, Some(0.0), Some(JUDGE_MAX_NEW_TOKENS), 1, None, None, None).await?;
+            let judge_says = choices.into_iter().next().and_then(|c| c.last().cloned()).ok_or("judge returned nothing".to_string())?;
+            parse_judge_verdict(&judge_says.content.content_text_only())
+        }
+    };
+    let (result, verdict) = generate_with_self_critique(messages, generate, judge).await
+        .map_err(|e| ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    // The draft and the critique stay on the server, the client sees the final answer and the verdict
+    let answer = result.last().filter(|_| result.len() > messages_n).cloned()
+        .ok_or(ScratchError::new(StatusCode::INTERNAL_SERVER_ERROR, "model returned no answer".to_string()))?;
+    let critique = verdict.map(|v| json!({"passed": v.passed, "suggestions": v.suggestions, "judge_model": judge_model}));
+    let message_field = if stream { "delta" } else { "message" };
+    let value: Value = json!({
+        "choices": [{"index": 0, message_field: answer, "finish_reason": "stop"}],
+        "model": model_name,
+        "created": std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as f64 / 1000.0,
+        "self_critique": critique,
+    });
+    if stream {
+        crate::restream::cached_stream(&value).await
+    } else {
+        crate::restream::cached_not_stream(&value).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn mock_model(calls: &AtomicUsize) -> impl Fn(Vec<ChatMessage>) -> std::future::Ready<Result<Vec<ChatMessage>, String>> + '_ {
+        move |mut msgs: Vec<ChatMessage>| {
+            let n = calls.fetch_add(1, Ordering::SeqCst);
+            let answer = if n == 0 { "def add(a, b): return a - b" } else { "def add(a, b): return a + b" };
+            msgs.push(ChatMessage::new("assistant".to_string(), answer.to_string()));
+            std::future::ready(Ok(msgs))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failing_critique_triggers_one_revision() {
+        let task = vec![ChatMessage::new("user".to_string(), "write add(a, b)".to_string())];
+
+        let (generate_calls, judge_calls) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let failing_judge = |task: String, answer: String| {
+            judge_calls.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(task, "write add(a, b)");
+            assert_eq!(answer, "def add(a, b): return a - b");
+            std::future::ready(parse_judge_verdict("**FAIL**\n- add subtracts, use +\n"))
+        };
+        let (result, verdict) = generate_with_self_critique(task.clone(), mock_model(&generate_calls), failing_judge).await.unwrap();
+        assert_eq!(generate_calls.load(Ordering::SeqCst), 2);
+        assert_eq!(judge_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(verdict, Some(JudgeVerdict { passed: false, suggestions: "- add subtracts, use +".to_string() }));
+        assert_eq!(result.last().unwrap().content.content_text_only(), "def add(a, b): return a + b");
+        assert!(result[2].content.content_text_only().contains("- add subtracts, use +"));
+
+        let (generate_calls, judge_calls) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let passing_judge = |_task: String, _answer: String| {
+            judge_calls.fetch_add(1, Ordering::SeqCst);
+            std::future::ready(parse_judge_verdict("Verdict: PASS"))
+        };
+        let (result, verdict) = generate_with_self_critique(task.clone(), mock_model(&generate_calls), passing_judge).await.unwrap();
+        assert_eq!(generate_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(judge_calls.load(Ordering::SeqCst), 1);
+        assert!(verdict.unwrap().passed);
+        assert_eq!(result.len(), 2);
+
+        assert!(parse_judge_verdict("Looks good to me").is_err());
+    }
+}
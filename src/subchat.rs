@@ -57,6 +57,7 @@ async fn create_chat_post_and_scratchpad(
             top_p: None,
             stop: vec![],
             n: Some(n),
+            ..Default::default()
         },
         model: model_name.to_string(),
         scratchpad: "".to_string(),
@@ -134,7 +135,8 @@ async fn chat_interaction_non_stream(
         chat_post.model.clone(),
         &chat_post.parameters,   // careful: includes n
         chat_post.only_deterministic_messages,
-        meta
+        meta,
+        false,
     ).await.map_err(|e| {
         warn!("network error communicating with the model (2): {:?}", e);
         format!("network error communicating with the model (2): {:?}", e)
@@ -246,6 +248,46 @@ async fn chat_interaction(
     ).await?)
 }
 
+// Unlike subchat_single(), this goes through the same scratchpad as /v1/chat does for this post: the tools
+// as the client sent them, customized ones included, and @-commands expanded when allow_at is set
+pub async fn chat_post_interaction(
+    ccx: Arc<AMutex<AtCommandsContext>>,
+    chat_post: &ChatPost,
+    messages: Vec<ChatMessage>,
+    allow_at: bool,
+) -> Result<Vec<Vec<ChatMessage>>, String> {
+    let gcx = ccx.lock().await.global_context.clone();
+    let caps = try_load_caps_quickly_if_not_present(gcx.clone(), 0).await.map_err(|e| {
+        warn!("no caps: {:?}", e);
+        "no caps".to_string()
+    })?;
+    let mut chat_post = chat_post.clone();
+    chat_post.messages = messages.iter().map(|x| json!(x)).collect();
+    chat_post.stream = Some(false);
+    let (model_name, scratchpad_name, scratchpad_patch, _n_ctx, supports_tools, _supports_multimodality, supports_clicks) = lookup_chat_scratchpad(
+        caps.clone(),
+        &chat_post,
+    ).await?;
+    let spad = crate::scratchpads::create_chat_scratchpad(
+        gcx.clone(),
+        caps,
+        model_name,
+        &mut chat_post,
+        &messages,
+        &scratchpad_name,
+        &scratchpad_patch,
+        allow_at,
+        supports_tools,
+        supports_clicks,
+    ).await?;
+    let chat_response_msgs = chat_interaction(ccx.clone(), spad, &mut chat_post).await?;
+    Ok(chat_response_msgs.into_iter().map(|new_msgs| {
+        let mut extended_msgs = messages.clone();
+        extended_msgs.extend(new_msgs);
+        extended_msgs
+    }).collect())
+}
+
 fn update_usage_from_messages(usage: &mut ChatUsage, messages: &Vec<Vec<ChatMessage>>) {
     // even if n_choices > 1, usage is identical in each Vec<ChatMessage>, so we could take the first one
     if let Some(message_0) = messages.get(0) {
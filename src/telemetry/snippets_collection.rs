@@ -75,7 +75,7 @@ pub fn snippet_register_from_data4cache(
 ) {
     // Convenience function: snippet_telemetry_id should be returned inside a cached answer as well, so there's
     // typically a combination of the two
-    if data4cache.completion0_finish_reason.is_empty() {
+    if data4cache.completion0_finish_reason.is_empty() || ss.post.warmup {
         return;
     }
     data4cache.completion0_snippet_telemetry_id = Some(snippet_register(&ss, data4cache.completion0_text.clone(), context_used));
@@ -1,13 +1,41 @@
 pub mod tools_description;
 pub mod tools_execute;
+pub mod tools_utils;
+pub mod scan_files_streaming;
 
 mod tool_ast_definition;
 mod tool_ast_reference;
+mod tool_call_graph;
+mod tool_rename_symbol;
 pub mod tool_patch_aux;
 mod tool_web;
+mod tool_docs;
 mod tool_tree;
 mod tool_relevant_files;
 mod tool_cat;
+mod tool_token_count;
+mod tool_detect_tools;
+mod tool_jwt;
+mod tool_run_doc_examples;
+mod tool_git_branch;
+mod tool_service_logs;
+mod tool_complexity;
+mod tool_module_graph;
+mod tool_syntax_check;
+mod tool_dir_overview;
+mod tool_extract_strings;
+mod tool_deps;
+mod tool_coverage_gaps;
+mod tool_secret_scan;
+mod tool_grep;
+mod tool_todos;
+mod tool_bisect_diff;
+mod tool_compare_symbols;
+mod tool_json_query;
+mod tool_diagram;
+mod tool_goto_definition;
+mod tool_parse_stacktrace;
+mod tool_commit_message;
 
 mod tool_deep_thinking;
 
@@ -16,5 +44,7 @@ mod tool_search;
 #[cfg(feature="vecdb")]
 mod tool_knowledge;
 #[cfg(feature="vecdb")]
+mod tool_search_memory;
+#[cfg(feature="vecdb")]
 mod tool_locate_search;
 pub mod tool_patch;
@@ -0,0 +1,148 @@
+use std::future::Future;
+use std::path::PathBuf;
+use serde_json::{json, Value};
+
+use crate::tools::tools_execute::ToolOutputStreamer;
+
+
+// For tools that scan many files: each result goes to the user as one JSON line as soon as it's found,
+// the last line is {"summary": {...}}. Without a streamer it only collects, the final tool message is the same either way.
+pub struct JsonLinesOutput {
+    pub streamer: Option<ToolOutputStreamer>,
+    pub lines_sent: usize,
+}
+
+impl JsonLinesOutput {
+    pub fn new(streamer: Option<ToolOutputStreamer>) -> Self {
+        JsonLinesOutput { streamer, lines_sent: 0 }
+    }
+
+    pub async fn line(&mut self, value: &Value) {
+        if let Some(streamer) = self.streamer.as_mut() {
+            streamer.partial(&format!("{}\n", value)).await;
+            self.lines_sent += 1;
+        }
+    }
+
+    pub async fn finish(&mut self, summary: &Value) {
+        self.line(&json!({"summary": summary})).await;
+        if let Some(streamer) = self.streamer.take() {
+            streamer.finished().await;
+        }
+    }
+}
+
+// Stops after `max_results`, the summary has "truncated": true then and counts only the files scanned so far
+pub async fn scan_files_json_lines<F, Fut>(
+    files: &Vec<PathBuf>,
+    mut scan_one: F,
+    max_results: usize,
+    output: &mut JsonLinesOutput,
+) -> (Vec<Value>, Value)
+where
+    F: FnMut(PathBuf) -> Fut,
+    Fut: Future<Output = Result<Vec<Value>, String>>,
+{
+    let mut results = vec![];
+    let mut errors_n = 0;
+    let mut files_scanned = 0;
+    let mut truncated = false;
+    for path in files.iter() {
+        if results.len() >= max_results {
+            truncated = true;
+            break;
+        }
+        files_scanned += 1;
+        match scan_one(path.clone()).await {
+            Ok(found) => {
+                let room = max_results - results.len();
+                truncated = found.len() > room;
+                for item in found.into_iter().take(room) {
+                    output.line(&item).await;
+                    results.push(item);
+                }
+            }
+            Err(e) => {
+                errors_n += 1;
+                output.line(&json!({"path": path.to_string_lossy(), "error": e})).await;
+            }
+        }
+    }
+    let mut summary = json!({"files_scanned": files_scanned, "matches": results.len(), "errors": errors_n});
+    if truncated {
+        summary["truncated"] = json!(true);
+    }
+    output.finish(&summary).await;
+    (results, summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::sync::{mpsc, Mutex as AMutex};
+
+    #[tokio::test]
+    async fn test_scan_emits_lines_before_completion() {
+        let (tx, rx) = mpsc::unbounded_channel::<Value>();
+        let rx = Arc::new(AMutex::new(rx));
+        let mut output = JsonLinesOutput::new(Some(ToolOutputStreamer {
+            subchat_tx: Arc::new(AMutex::new(tx)),
+            tool_call_id: "call_scan".to_string(),
+            chunks_sent: 0,
+        }));
+        let files: Vec<PathBuf> = (0..200).map(|i| PathBuf::from(format!("/project/file_{}.py", i))).collect();
+
+        // while file N is being scanned, the lines for earlier files must already be out
+        let seen_during_scan = Arc::new(AMutex::new(vec![]));
+        let (results, summary) = scan_files_json_lines(&files, |path| {
+            let rx = rx.clone();
+            let seen_during_scan = seen_during_scan.clone();
+            async move {
+                let mut got = 0;
+                while rx.lock().await.try_recv().is_ok() {
+                    got += 1;
+                }
+                seen_during_scan.lock().await.push(got);
+                let name = path.to_string_lossy().to_string();
+                if name.ends_with("_13.py") {
+                    return Err("permission denied".to_string());
+                }
+                Ok(vec![json!({"path": name, "line": 1, "text": "# TODO"})])
+            }
+        }, 1000, &mut output).await;
+
+        assert_eq!(results.len(), 199);
+        assert_eq!(summary, json!({"files_scanned": 200, "matches": 199, "errors": 1}));
+        let seen = seen_during_scan.lock().await.clone();
+        assert_eq!(seen[0], 0);
+        assert!(seen[1..].iter().all(|n| *n == 1), "each file should see exactly the previous file's line: {:?}", seen);
+
+        let mut tail = vec![];
+        while let Ok(m) = rx.lock().await.try_recv() {
+            tail.push(m);
+        }
+        let (finished, partials) = tail.split_last().unwrap();
+        assert_eq!(finished["tool_output_finished"]["chunks_total"], output.lines_sent);
+        let last_line: Value = serde_json::from_str(partials.last().unwrap()["tool_output_partial"]["content"].as_str().unwrap().trim()).unwrap();
+        assert_eq!(last_line["summary"]["matches"], 199);
+    }
+
+    #[tokio::test]
+    async fn test_scan_stops_at_max_results() {
+        let mut output = JsonLinesOutput::new(None);
+        let files: Vec<PathBuf> = (0..100).map(|i| PathBuf::from(format!("/project/file_{}.py", i))).collect();
+        let scanned = Arc::new(AMutex::new(0));
+        let (results, summary) = scan_files_json_lines(&files, |path| {
+            let scanned = scanned.clone();
+            async move {
+                *scanned.lock().await += 1;
+                let name = path.to_string_lossy().to_string();
+                Ok((1..=3).map(|line| json!({"path": name, "line": line, "text": "x"})).collect())
+            }
+        }, 10, &mut output).await;
+        assert_eq!(results.len(), 10);
+        assert_eq!(*scanned.lock().await, 4);
+        assert_eq!(summary, json!({"files_scanned": 4, "matches": 10, "errors": 0, "truncated": true}));
+    }
+}
@@ -0,0 +1,505 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum, DiffChunk};
+use crate::diffs::{apply_diff_chunks_to_text, ApplyDiffOutput};
+use crate::files_correction::{canonical_path, get_active_project_path, get_project_dirs, to_pathbuf_normalize};
+use crate::files_in_workspace::read_file_from_disk;
+use crate::integrations::integr_abstract::IntegrationConfirmation;
+use crate::integrations::integr_shell::execute_shell_command_with_exit_code;
+use crate::postprocessing::pp_command_output::CmdlineOutputFilter;
+use crate::privacy::{check_file_privacy, load_privacy_if_needed, FilePrivacyLevel, PrivacySettings};
+use crate::tools::tools_description::Tool;
+
+
+const BISECT_DEFAULT_TIMEOUT_SECS: u64 = 120;   // one test run
+const BISECT_MAX_RUNS: usize = 64;
+const MAX_FUZZY_N: usize = 10;
+
+pub struct ToolBisectDiff;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BisectResult {
+    pub hunks: Vec<usize>,     // indexes into the parsed hunks
+    pub runs: usize,
+    pub minimal: bool,         // false if it ran out of BISECT_MAX_RUNS before it could prove it
+}
+
+// Hunks of a unified diff (git diff, diff -u) as edit chunks. Context lines go to both sides,
+// that's how a chunk finds its place if other hunks before it are not applied.
+pub fn parse_unified_diff(diff: &str, root: &PathBuf) -> Result<Vec<DiffChunk>, String> {
+    let mut chunks: Vec<DiffChunk> = vec![];
+    let mut file_name: Option<String> = None;
+    let mut current: Option<DiffChunk> = None;
+    let (mut old_left, mut new_left) = (0, 0);   // lines until the end of the current hunk, from its header
+    for line in diff.lines() {
+        if let Some(chunk) = current.as_mut() {
+            match line.chars().next() {
+                Some('-') => {
+                    chunk.lines_remove.push_str(&format!("{}\n", &line[1..]));
+                    old_left -= 1;
+                }
+                Some('+') => {
+                    chunk.lines_add.push_str(&format!("{}\n", &line[1..]));
+                    new_left -= 1;
+                }
+                Some(' ') | None => {
+                    let text = line.get(1..).unwrap_or_default();
+                    chunk.lines_remove.push_str(&format!("{}\n", text));
+                    chunk.lines_add.push_str(&format!("{}\n", text));
+                    old_left -= 1;
+                    new_left -= 1;
+                }
+                _ => {}  // \ No newline at end of file
+            }
+            if old_left <= 0 && new_left <= 0 {
+                chunks.extend(current.take());
+            }
+            continue;
+        }
+        if line.starts_with("--- ") {
+            file_name = None;
+            continue;
+        }
+        if let Some(path) = line.strip_prefix("+++ ") {
+            let path = path.split('\t').next().unwrap_or_default().trim();
+            if path == "/dev/null" {
+                return Err("the diff deletes a file, bisect works with edits of existing files only".to_string());
+            }
+            let path = path.strip_prefix("b/").unwrap_or(path);
+            file_name = Some(root.join(path).to_string_lossy().to_string());
+            continue;
+        }
+        if line.starts_with("@@") {
+            let file_name = file_name.clone().ok_or(format!("hunk without a file: {:?}", line))?;
+            let bad_header = || format!("can't parse hunk header {:?}", line);
+            let mut ranges = line.trim_start_matches('@').trim().split(' ');
+            let parse_range = |range: &str| -> Option<(usize, i64)> {
+                let (start, count) = range.split_once(',').unwrap_or((range, "1"));
+                Some((start.parse().ok()?, count.parse().ok()?))
+            };
+            let (start, count) = ranges.next().and_then(|r| parse_range(r.trim_start_matches('-'))).ok_or_else(bad_header)?;
+            let (_, new_count) = ranges.next().and_then(|r| parse_range(r.trim_start_matches('+'))).ok_or_else(bad_header)?;
+            (old_left, new_left) = (count, new_count);
+            if start == 0 && count == 0 {
+                return Err(format!("the diff adds {}, bisect works with edits of existing files only", file_name));
+            }
+            current = Some(DiffChunk {
+                file_name,
+                file_action: "edit".to_string(),
+                line1: start.max(1),
+                line2: start.max(1) + count as usize,
+                is_file: true,
+                ..Default::default()
+            });
+        }
+    }
+    chunks.extend(current.take());
+    Ok(chunks)
+}
+
+// The tool writes these files many times, "+++ b/../../.bashrc" or a blocked file should never get there
+pub fn check_diff_paths(
+    chunks: &mut Vec<DiffChunk>,
+    project_dirs: &Vec<PathBuf>,
+    privacy: Arc<PrivacySettings>,
+) -> Result<(), String> {
+    for c in chunks.iter_mut() {
+        if PathBuf::from(&c.file_name).canonicalize().is_err() {
+            return Err(format!("{} doesn't exist, bisect works with edits of existing files only", c.file_name));
+        }
+        let path = canonical_path(&c.file_name);
+        if !project_dirs.iter().any(|dir| path.starts_with(dir)) {
+            return Err(format!("{} is outside of the project dirs, bisect only edits files in the project", path.display()));
+        }
+        check_file_privacy(privacy.clone(), &path, &FilePrivacyLevel::OnlySendToServersIControl)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        c.file_name = path.to_string_lossy().to_string();
+    }
+    Ok(())
+}
+
+// Dry run: texts of the files with only the chosen hunks applied, nothing touches the disk
+pub fn texts_with_hunks(
+    originals: &HashMap<String, String>,
+    chunks: &Vec<DiffChunk>,
+    subset: &Vec<usize>,
+) -> Result<HashMap<String, String>, String> {
+    let mut texts = originals.clone();
+    for (file_name, text) in texts.iter_mut() {
+        let to_apply: Vec<(usize, &DiffChunk)> = subset.iter().map(|i| (*i, &chunks[*i])).filter(|(_, c)| &c.file_name == file_name).collect();
+        if to_apply.is_empty() {
+            continue;
+        }
+        let (results, outputs) = apply_diff_chunks_to_text(text, to_apply, vec![], MAX_FUZZY_N);
+        if let Some((i, ApplyDiffOutput::Err(e))) = outputs.iter().find(|(_, out)| matches!(out, ApplyDiffOutput::Err(_))) {
+            return Err(format!("hunk #{} doesn't apply to {}: {}", i + 1, file_name, e));
+        }
+        if let Some(new_text) = results.into_iter().next().and_then(|r| r.file_text) {
+            *text = new_text;
+        }
+    }
+    Ok(texts)
+}
+
+async fn fails_cached<F, Fut>(
+    fails: &mut F,
+    cache: &mut HashMap<Vec<usize>, bool>,
+    runs: &mut usize,
+    subset: &Vec<usize>,
+) -> Result<Option<bool>, String>
+where
+    F: FnMut(Vec<usize>) -> Fut,
+    Fut: Future<Output = Result<bool, String>>,
+{
+    if let Some(failed) = cache.get(subset) {
+        return Ok(Some(*failed));
+    }
+    if *runs >= BISECT_MAX_RUNS {
+        return Ok(None);
+    }
+    *runs += 1;
+    let failed = fails(subset.clone()).await?;
+    cache.insert(subset.clone(), failed);
+    Ok(Some(failed))
+}
+
+// Delta debugging: split the failing set in n parts, keep a part or a complement that still fails,
+// split finer when none does. Ends with a set where dropping any single hunk makes the test pass.
+pub async fn bisect_hunks<F, Fut>(hunks_n: usize, mut fails: F) -> Result<BisectResult, String>
+where
+    F: FnMut(Vec<usize>) -> Fut,
+    Fut: Future<Output = Result<bool, String>>,
+{
+    let mut cache = HashMap::new();
+    let mut runs = 0;
+    let mut current: Vec<usize> = (0..hunks_n).collect();
+    if fails_cached(&mut fails, &mut cache, &mut runs, &current).await? != Some(true) {
+        return Err("the test passes with the whole diff applied, nothing to bisect".to_string());
+    }
+    if fails_cached(&mut fails, &mut cache, &mut runs, &vec![]).await? != Some(false) {
+        return Err("the test fails without the diff too, the failure is not caused by it".to_string());
+    }
+
+    let mut n = 2;
+    while current.len() >= 2 {
+        let part_size = (current.len() + n - 1) / n;
+        let parts: Vec<Vec<usize>> = current.chunks(part_size).map(|p| p.to_vec()).collect();
+        let mut candidates: Vec<(Vec<usize>, usize)> = parts.iter().map(|p| (p.clone(), 2)).collect();
+        if parts.len() > 2 {
+            candidates.extend(parts.iter().map(|p| (current.iter().filter(|i| !p.contains(i)).cloned().collect(), (n - 1).max(2))));
+        }
+        let mut reduced = false;
+        for (candidate, next_n) in candidates {
+            match fails_cached(&mut fails, &mut cache, &mut runs, &candidate).await? {
+                Some(true) => {
+                    current = candidate;
+                    n = next_n;
+                    reduced = true;
+                    break;
+                }
+                Some(false) => {}
+                None => return Ok(BisectResult { hunks: current, runs, minimal: false }),
+            }
+        }
+        if !reduced {
+            if n >= current.len() {
+                break;
+            }
+            n = (n * 2).min(current.len());
+        }
+    }
+    Ok(BisectResult { hunks: current, runs, minimal: true })
+}
+
+fn write_texts(texts: &HashMap<String, String>) -> Result<(), String> {
+    for (file_name, text) in texts {
+        std::fs::write(file_name, text).map_err(|e| format!("can't write {}: {}", file_name, e))?;
+    }
+    Ok(())
+}
+
+// A cancelled tool call (the user stopped the chat) drops the future in the middle of the bisect,
+// the files must go back even then
+struct RestoreOnDrop<'a> {
+    originals: &'a HashMap<String, String>,
+    restored: bool,
+}
+
+impl RestoreOnDrop<'_> {
+    fn restore(&mut self) -> Result<(), String> {
+        self.restored = true;
+        write_texts(self.originals)
+    }
+}
+
+impl Drop for RestoreOnDrop<'_> {
+    fn drop(&mut self) {
+        if !self.restored {
+            if let Err(e) = write_texts(self.originals) {
+                tracing::error!("bisect was cancelled and the files weren't restored: {}", e);
+            }
+        }
+    }
+}
+
+pub fn format_bisect_result(chunks: &Vec<DiffChunk>, result: &BisectResult) -> String {
+    let mut report = format!(
+        "The test fails with {} of {} hunks applied, found in {} test runs{}:\n\n",
+        result.hunks.len(), chunks.len(), result.runs,
+        if result.minimal { "" } else { ", stopped at the limit of runs, the set might not be minimal" },
+    );
+    for i in result.hunks.iter() {
+        let c = &chunks[*i];
+        report.push_str(&format!("hunk #{} {}:{}-{}\n", i + 1, c.file_name, c.line1, c.line2.saturating_sub(1).max(c.line1)));
+        for line in c.lines_remove.lines() {
+            report.push_str(&format!("-{}\n", line));
+        }
+        for line in c.lines_add.lines() {
+            report.push_str(&format!("+{}\n", line));
+        }
+        report.push_str("\n");
+    }
+    report.push_str("The files are restored to how they were before the bisect.\n");
+    report
+}
+
+fn parse_args(args: &HashMap<String, Value>) -> Result<(String, String, Option<String>, u64), String> {
+    let get = |name: &str| -> Result<Option<String>, String> {
+        match args.get(name) {
+            Some(Value::String(s)) if !s.trim().is_empty() => Ok(Some(s.clone())),
+            Some(Value::String(_)) | None => Ok(None),
+            Some(v) => Err(format!("argument `{}` is not a string: {:?}", name, v)),
+        }
+    };
+    let diff = get("diff")?.ok_or("argument `diff` is missing".to_string())?;
+    let test_command = get("test_command")?.ok_or("argument `test_command` is missing".to_string())?.trim().to_string();
+    let timeout = match args.get("timeout") {
+        Some(Value::Number(n)) => n.as_u64().ok_or(format!("argument `timeout` should be a positive number of seconds: {}", n))?,
+        Some(Value::String(s)) if !s.trim().is_empty() => s.trim().parse::<u64>().map_err(|_| format!("argument `timeout` should be a number of seconds: {:?}", s))?,
+        _ => BISECT_DEFAULT_TIMEOUT_SECS,
+    };
+    Ok((diff, test_command, get("workdir")?.map(|s| s.trim().to_string()), timeout))
+}
+
+#[async_trait]
+impl Tool for ToolBisectDiff {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let (diff, test_command, workdir_arg, timeout) = parse_args(args)?;
+        let gcx = ccx.lock().await.global_context.clone();
+        let workdir = match workdir_arg {
+            Some(w) => to_pathbuf_normalize(&w),
+            None => get_active_project_path(gcx.clone()).await.ok_or("no active project, pass `workdir`".to_string())?,
+        };
+        if !workdir.is_dir() {
+            return Err(format!("workdir {} doesn't exist", workdir.display()));
+        }
+
+        let mut chunks = parse_unified_diff(&diff, &workdir)?;
+        if chunks.is_empty() {
+            return Err("no hunks found in the diff, it should be a unified diff like `git diff` prints".to_string());
+        }
+        let privacy = load_privacy_if_needed(gcx.clone()).await;
+        check_diff_paths(&mut chunks, &get_project_dirs(gcx.clone()).await, privacy.clone())?;
+        let mut originals = HashMap::new();
+        for c in chunks.iter() {
+            if !originals.contains_key(&c.file_name) {
+                let text = read_file_from_disk(privacy.clone(), &PathBuf::from(&c.file_name)).await?.to_string();
+                originals.insert(c.file_name.clone(), text);
+            }
+        }
+        let all: Vec<usize> = (0..chunks.len()).collect();
+        texts_with_hunks(&originals, &chunks, &all)
+            .map_err(|e| format!("{}\nThe diff should apply to the files as they are now, revert it first if it's already applied.", e))?;
+
+        let file_locks = gcx.read().await.file_locks.clone();
+        let locked = file_locks.lock_paths(originals.keys().map(PathBuf::from)).await;
+        let mut restore_guard = RestoreOnDrop { originals: &originals, restored: false };
+        let result = bisect_hunks(chunks.len(), |subset| {
+            let texts = texts_with_hunks(&originals, &chunks, &subset);
+            let (test_command, workdir, gcx) = (test_command.clone(), workdir.clone(), gcx.clone());
+            async move {
+                write_texts(&texts?)?;
+                match execute_shell_command_with_exit_code(&test_command, &Some(workdir), timeout, &CmdlineOutputFilter::default(), &HashMap::new(), gcx, &mut None).await {
+                    Ok((_, exit_code)) => {
+                        tracing::info!("bisect: hunks {:?} exit code {}", subset, exit_code);
+                        Ok(exit_code != 0)
+                    }
+                    Err(e) if e.contains("timed out") => Ok(true),  // hanging is a failure too
+                    Err(e) => Err(e),
+                }
+            }
+        }).await;
+        let restored = restore_guard.restore();
+        drop(restore_guard);
+        drop(locked);
+        restored.map_err(|e| format!("{}\nFailed to restore the files after the bisect, check them!", e))?;
+        let result = result?;
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(format_bisect_result(&chunks, &result)),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn command_to_match_against_confirm_deny(
+        &self,
+        args: &HashMap<String, Value>,
+    ) -> Result<String, String> {
+        let (diff, test_command, _, _) = parse_args(args)?;
+        // the user should see what is going to be rewritten, not only what runs
+        let mut files: Vec<String> = vec![];
+        for c in parse_unified_diff(&diff, &PathBuf::new())? {
+            if !files.contains(&c.file_name) {
+                files.push(c.file_name);
+            }
+        }
+        Ok(format!("bisect_diff {} (rewrites {})", test_command, files.join(", ")))
+    }
+
+    // writes files and runs the test many times, always ask
+    fn confirm_deny_rules(&self) -> Option<IntegrationConfirmation> {
+        Some(IntegrationConfirmation {
+            ask_user: vec!["bisect_diff*".to_string()],
+            deny: vec![],
+        })
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str = "fn parse(s: &str) -> i32 {\n    s.trim().parse().unwrap_or(0)\n}\n\nfn double(x: i32) -> i32 {\n    x * 2\n}\n\nfn greet() -> String {\n    \"hello\".to_string()\n}\n\nfn limit() -> usize {\n    10\n}\n";
+
+    const DIFF: &str = "diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,3 @@
+-fn parse(s: &str) -> i32 {
++fn parse(s: &str) -> i64 {
+     s.trim().parse().unwrap_or(0)
+ }
+@@ -5,3 +5,3 @@
+ fn double(x: i32) -> i32 {
+-    x * 2
++    x.checked_mul(2).expect(\"overflow\")
+ }
+@@ -9,3 +9,3 @@
+ fn greet() -> String {
+-    \"hello\".to_string()
++    \"hello, world\".to_string()
+ }
+@@ -13,3 +13,3 @@
+ fn limit() -> usize {
+-    10
++    20
+ }
+";
+
+    #[tokio::test]
+    async fn test_bisect_isolates_the_failing_hunk() {
+        let root = PathBuf::from("/project");
+        let chunks = parse_unified_diff(DIFF, &root).unwrap();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[1].file_name, "/project/src/lib.rs");
+        assert_eq!((chunks[1].line1, chunks[1].line2), (5, 8));
+
+        let originals = HashMap::from([("/project/src/lib.rs".to_string(), ORIGINAL.to_string())]);
+        let all_applied = texts_with_hunks(&originals, &chunks, &vec![0, 1, 2, 3]).unwrap();
+        assert!(all_applied["/project/src/lib.rs"].contains("\"hello, world\""));
+
+        // the mock test fails when `expect` made it into the code, each run is a dry run
+        let mut tested: Vec<Vec<usize>> = vec![];
+        let result = bisect_hunks(chunks.len(), |subset| {
+            tested.push(subset.clone());
+            let texts = texts_with_hunks(&originals, &chunks, &subset);
+            async move { Ok(texts?["/project/src/lib.rs"].contains(".expect(")) }
+        }).await.unwrap();
+        assert_eq!(result.hunks, vec![1]);
+        assert!(result.minimal);
+        assert_eq!(result.runs, tested.len());
+        assert!(result.runs < 2 + 2 * chunks.len(), "{:?}", tested);
+
+        let report = format_bisect_result(&chunks, &result);
+        assert!(report.contains("1 of 4 hunks"), "{}", report);
+        assert!(report.contains("hunk #2 /project/src/lib.rs:5-7"), "{}", report);
+        assert!(report.contains("+    x.checked_mul(2).expect(\"overflow\")"), "{}", report);
+
+        // the failure has nothing to do with the diff
+        let err = bisect_hunks(chunks.len(), |_| async { Ok(true) }).await.unwrap_err();
+        assert!(err.contains("fails without the diff"), "{}", err);
+    }
+
+    #[test]
+    fn test_diff_paths_stay_in_the_project() {
+        let tmp = tempfile::tempdir().unwrap();
+        let project = canonical_path(&tmp.path().join("project").to_string_lossy());
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        std::fs::write(project.join("src/lib.rs"), ORIGINAL).unwrap();
+        std::fs::write(project.join("src/secret.rs"), ORIGINAL).unwrap();
+        std::fs::write(tmp.path().join("outside.rs"), ORIGINAL).unwrap();
+        let privacy = Arc::new(PrivacySettings {
+            privacy_rules: crate::privacy::FilePrivacySettings {
+                only_send_to_servers_I_control: vec![],
+                blocked: vec!["*/secret.rs".to_string()],
+            },
+            ..PrivacySettings::default()
+        });
+        let project_dirs = vec![project.clone()];
+
+        let mut chunks = parse_unified_diff(DIFF, &project).unwrap();
+        check_diff_paths(&mut chunks, &project_dirs, privacy.clone()).unwrap();
+        assert_eq!(chunks[0].file_name, project.join("src/lib.rs").to_string_lossy());
+
+        let mut chunks = parse_unified_diff(&DIFF.replace("b/src/lib.rs", "b/src/../../outside.rs"), &project).unwrap();
+        let err = check_diff_paths(&mut chunks, &project_dirs, privacy.clone()).unwrap_err();
+        assert!(err.contains("outside of the project dirs"), "{}", err);
+
+        let mut chunks = parse_unified_diff(&DIFF.replace("b/src/lib.rs", "b/src/secret.rs"), &project).unwrap();
+        let err = check_diff_paths(&mut chunks, &project_dirs, privacy.clone()).unwrap_err();
+        assert!(err.contains("privacy"), "{}", err);
+
+        let args = HashMap::from([
+            ("diff".to_string(), Value::String(DIFF.to_string())),
+            ("test_command".to_string(), Value::String("cargo test".to_string())),
+        ]);
+        assert_eq!(ToolBisectDiff.command_to_match_against_confirm_deny(&args).unwrap(), "bisect_diff cargo test (rewrites src/lib.rs)");
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_bisect_restores_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file_name = tmp.path().join("lib.rs").to_string_lossy().to_string();
+        std::fs::write(&file_name, ORIGINAL).unwrap();
+        let originals = HashMap::from([(file_name.clone(), ORIGINAL.to_string())]);
+
+        let bisect = async {
+            let _restore_guard = RestoreOnDrop { originals: &originals, restored: false };
+            std::fs::write(&file_name, "half applied").unwrap();
+            std::future::pending::<()>().await;
+        };
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(50), bisect).await.is_err());
+        assert_eq!(std::fs::read_to_string(&file_name).unwrap(), ORIGINAL);
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::ast::ast_structs::{AstDB, AstDefinition, SymbolType};
+use crate::tools::tools_description::Tool;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::tools::tool_ast_definition::there_are_definitions_with_similar_names_though;
+
+
+const CALL_GRAPH_DEFAULT_DEPTH: usize = 2;
+const CALL_GRAPH_MAX_DEPTH: usize = 4;
+const CALL_GRAPH_MAX_LINES: usize = 80;
+const CALL_GRAPH_FANOUT_LIMIT: usize = 15;
+
+pub struct ToolCallGraph;
+
+pub struct CallGraphLine {
+    pub level: usize,
+    pub def: Arc<AstDefinition>,
+    pub uline: usize,
+}
+
+pub struct CallGraphSide {
+    pub lines: Vec<CallGraphLine>,
+    pub unresolved_cnt: usize,
+    pub truncated: bool,
+}
+
+fn is_callable(def: &AstDefinition) -> bool {
+    def.symbol_type == SymbolType::FunctionDeclaration || def.symbol_type == SymbolType::StructDeclaration
+}
+
+pub async fn call_graph_side(
+    ast_index: Arc<AMutex<AstDB>>,
+    root_path: String,
+    depth: usize,
+    want_callers: bool,
+    max_lines: usize,
+) -> CallGraphSide {
+    let mut side = CallGraphSide { lines: vec![], unresolved_cnt: 0, truncated: false };
+    let mut visited = HashSet::from([root_path.clone()]);
+    // depth-first, stack holds (path, level), children pushed in reverse to keep the natural order
+    let mut stack: Vec<(String, usize)> = vec![(root_path, 0)];
+    while let Some((path, level)) = stack.pop() {
+        if level >= depth {
+            continue;
+        }
+        let mut neighbours = if want_callers {
+            crate::ast::ast_db::usages(ast_index.clone(), path.clone(), 100).await
+        } else {
+            let (callees, unresolved_cnt) = crate::ast::ast_db::callees(ast_index.clone(), path.clone()).await;
+            side.unresolved_cnt += unresolved_cnt;
+            callees.into_iter().filter(|(def, _)| is_callable(def)).collect()
+        };
+        if neighbours.len() > CALL_GRAPH_FANOUT_LIMIT {
+            neighbours.truncate(CALL_GRAPH_FANOUT_LIMIT);
+            side.truncated = true;
+        }
+        let mut to_push = vec![];
+        for (def, uline) in neighbours {
+            if side.lines.len() >= max_lines {
+                side.truncated = true;
+                return side;
+            }
+            let def_path = def.path();
+            let first_time = visited.insert(def_path.clone());
+            side.lines.push(CallGraphLine { level: level + 1, def, uline });
+            if first_time {
+                to_push.push((def_path, level + 1));
+            }
+        }
+        stack.extend(to_push.into_iter().rev());
+    }
+    side
+}
+
+fn print_side(side: &CallGraphSide, short_paths: &HashMap<String, String>, want_callers: bool) -> String {
+    let mut out = String::new();
+    for line in side.lines.iter() {
+        let short_path = short_paths.get(&line.def.cpath).unwrap_or(&line.def.cpath);
+        let indent = "  ".repeat(line.level);
+        if want_callers {
+            out.push_str(&format!("{}{} calls it at {}:{}\n", indent, line.def.path_drop0(), short_path, line.uline + 1));
+        } else {
+            out.push_str(&format!("{}{} defined at {}:{}-{}\n", indent, line.def.path_drop0(), short_path, line.def.full_line1(), line.def.full_line2()));
+        }
+    }
+    if side.lines.is_empty() {
+        out.push_str("  (none found)\n");
+    }
+    if side.truncated {
+        out.push_str("  ...output truncated, ask for a smaller depth or a more specific symbol\n");
+    }
+    out
+}
+
+fn parse_depth(args: &HashMap<String, Value>) -> Result<usize, String> {
+    let depth = match args.get("depth") {
+        Some(Value::Number(n)) => n.as_u64().ok_or(format!("argument `depth` is not a positive integer: {:?}", n))? as usize,
+        Some(Value::String(s)) => s.parse::<usize>().map_err(|_| format!("argument `depth` is not a positive integer: {:?}", s))?,
+        Some(v) => return Err(format!("argument `depth` is not a number: {:?}", v)),
+        None => CALL_GRAPH_DEFAULT_DEPTH,
+    };
+    Ok(depth.clamp(1, CALL_GRAPH_MAX_DEPTH))
+}
+
+#[async_trait]
+impl Tool for ToolCallGraph {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let mut corrections = false;
+        let symbol = match args.get("symbol") {
+            Some(Value::String(s)) => s.replace('.', "::"),
+            Some(v) => return Err(format!("argument `symbol` is not a string: {:?}", v)),
+            None => return Err("argument `symbol` is missing".to_string()),
+        };
+        let depth = parse_depth(args)?;
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let ast_service = gcx.read().await.ast_service.clone()
+            .ok_or("attempt to use call_graph with no ast turned on".to_string())?;
+        let ast_index = ast_service.lock().await.ast_index.clone();
+        crate::ast::ast_indexer_thread::ast_indexer_block_until_finished(ast_service.clone(), 20_000, true).await;
+
+        let defs = crate::ast::ast_db::definitions(ast_index.clone(), &symbol).await;
+        let tool_message = if let Some(def) = defs.first() {
+            let callers = call_graph_side(ast_index.clone(), def.path(), depth, true, CALL_GRAPH_MAX_LINES / 2).await;
+            let callees = call_graph_side(ast_index.clone(), def.path(), depth, false, CALL_GRAPH_MAX_LINES / 2).await;
+
+            let mut cpaths = vec![def.cpath.clone()];
+            cpaths.extend(callers.lines.iter().chain(callees.lines.iter()).map(|x| x.def.cpath.clone()));
+            cpaths.sort();
+            cpaths.dedup();
+            let short = crate::files_correction::shortify_paths(gcx.clone(), &cpaths).await;
+            let short_paths: HashMap<String, String> = cpaths.into_iter().zip(short.into_iter()).collect();
+
+            let mut msg = format!(
+                "Call graph for {} defined at {}:{}-{}, depth {}\n",
+                def.path_drop0(),
+                short_paths.get(&def.cpath).unwrap_or(&def.cpath),
+                def.full_line1(),
+                def.full_line2(),
+                depth,
+            );
+            if defs.len() > 1 {
+                msg.push_str(&format!("Note: {} more definitions match `{}`, showing the first one only\n", defs.len() - 1, symbol));
+            }
+            msg.push_str("\nCallers:\n");
+            msg.push_str(&print_side(&callers, &short_paths, true));
+            msg.push_str("\nCallees:\n");
+            msg.push_str(&print_side(&callees, &short_paths, false));
+            if callees.unresolved_cnt > 0 {
+                msg.push_str(&format!("\nResolution is incomplete: {} references could not be resolved (library code, dynamic dispatch, or not indexed), the graph may be missing edges.\n", callees.unresolved_cnt));
+            }
+            msg
+        } else {
+            corrections = true;
+            there_are_definitions_with_similar_names_though(ast_index, &symbol).await
+        };
+
+        Ok((corrections, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(tool_message),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec!["ast".to_string()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ast_db::{ast_index_init, doc_add, connect_usages, connect_usages_look_if_full_reset_needed, flush_sled_batch, definitions};
+    use crate::ast::ast_structs::AstErrorStats;
+
+    #[tokio::test]
+    async fn test_call_graph_py() {
+        let ast_index = ast_index_init("".to_string(), 10, false).await;
+        let cpath = "src/ast/alt_testsuite/py_call_graph.py".to_string();
+        let text = std::fs::read_to_string(&cpath).unwrap();
+        let mut errstats = AstErrorStats::default();
+        doc_add(ast_index.clone(), &cpath, &text, &mut errstats).await.unwrap();
+        let mut ucx = connect_usages_look_if_full_reset_needed(ast_index.clone()).await;
+        while connect_usages(ast_index.clone(), &mut ucx).await {}
+        flush_sled_batch(ast_index.clone(), 0).await;
+
+        let setup = definitions(ast_index.clone(), "setup").await;
+        assert_eq!(setup.len(), 1);
+
+        let callers = call_graph_side(ast_index.clone(), setup[0].path(), 2, true, 40).await;
+        let caller_names: Vec<String> = callers.lines.iter().map(|x| x.def.name()).collect();
+        assert!(caller_names.contains(&"main".to_string()), "callers: {:?}", caller_names);
+
+        let callees = call_graph_side(ast_index.clone(), setup[0].path(), 1, false, 40).await;
+        let callee_names: Vec<String> = callees.lines.iter().map(|x| x.def.name()).collect();
+        assert!(callee_names.contains(&"load_config".to_string()), "callees: {:?}", callee_names);
+        assert!(callee_names.contains(&"parse_config".to_string()), "callees: {:?}", callee_names);
+        assert!(!callee_names.contains(&"main".to_string()));
+
+        let deep = call_graph_side(ast_index.clone(), setup[0].path(), 2, false, 40).await;
+        let deep_names: Vec<String> = deep.lines.iter().map(|x| x.def.name()).collect();
+        assert!(deep_names.contains(&"strip_comments".to_string()), "deep callees: {:?}", deep_names);
+    }
+}
@@ -22,6 +22,7 @@ pub struct ToolCat;
 
 
 const CAT_MAX_IMAGES_CNT: usize = 1;
+const SYMBOLS_PER_TOKEN: f32 = 3.5;
 
 pub fn parse_skeleton_from_args(args: &HashMap<String, Value>) -> Result<bool, String> {
     Ok(match args.get("skeleton") {
@@ -40,6 +41,71 @@ pub fn parse_skeleton_from_args(args: &HashMap<String, Value>) -> Result<bool, S
     })
 }
 
+fn parse_line_from_args(args: &HashMap<String, Value>, name: &str) -> Result<Option<usize>, String> {
+    match args.get(name) {
+        Some(Value::Number(n)) => n.as_u64().map(|x| Some(x as usize)).ok_or(format!("argument `{}` is not a positive integer: {:?}", name, n)),
+        Some(Value::String(s)) if s.trim().is_empty() => Ok(None),
+        Some(Value::String(s)) => s.trim().parse::<usize>().map(Some).map_err(|_| format!("argument `{}` is not a positive integer: {:?}", name, s)),
+        Some(Value::Null) | None => Ok(None),
+        Some(v) => Err(format!("argument `{}` is not a positive integer: {:?}", name, v)),
+    }
+}
+
+fn parse_line_numbers_from_args(args: &HashMap<String, Value>) -> Result<bool, String> {
+    Ok(match args.get("line_numbers") {
+        Some(Value::Bool(b)) => *b,
+        Some(Value::String(s)) if s == "true" => true,
+        Some(Value::String(s)) if s == "false" || s.is_empty() => false,
+        Some(v) => return Err(format!("argument `line_numbers` is not a bool: {:?}", v)),
+        None => false,
+    })
+}
+
+pub fn cat_line_range(text: &str, line1: Option<usize>, line2: Option<usize>, line_numbers: bool, char_limit: usize) -> (String, usize, usize, Option<String>) {
+    // line1 and line2 are 1-based and inclusive, out of range values are clamped and the returned note says so,
+    // lines that don't fit into char_limit are cut off and the note tells where to continue
+    let lines: Vec<&str> = text.lines().collect();
+    let total = lines.len();
+    if total == 0 {
+        return ("".to_string(), 0, 0, if line1.is_some() || line2.is_some() { Some("the file is empty".to_string()) } else { None });
+    }
+    let want1 = line1.unwrap_or(1);
+    let want2 = line2.unwrap_or(total);
+    let mut l1 = want1.clamp(1, total);
+    let l2 = want2.clamp(1, total);
+    if l1 > l2 {
+        l1 = l2;
+    }
+    let mut note = if (l1, l2) != (want1, want2) {
+        Some(format!("asked for lines {}-{}, but the file has {} lines, showing {}-{}", want1, want2, total, l1, l2))
+    } else {
+        None
+    };
+    let width = l2.to_string().len();
+    let mut out = String::new();
+    let mut last_shown = l2;
+    for (i, line) in lines[l1 - 1..l2].iter().enumerate() {
+        let formatted = if line_numbers {
+            format!("{:>width$} | {}\n", l1 + i, line, width = width)
+        } else {
+            format!("{}\n", line)
+        };
+        if out.len() + formatted.len() > char_limit {
+            let cut = if i == 0 {
+                last_shown = l1;
+                format!("line {} alone doesn't fit into the context, nothing shown", l1)
+            } else {
+                last_shown = l1 + i - 1;
+                format!("lines {}-{} don't fit into the context, showing {}-{}, call again with line1={} for the rest", l1, l2, l1, last_shown, last_shown + 1)
+            };
+            note = Some(note.map(|n| format!("{}; {}", n, cut)).unwrap_or(cut));
+            break;
+        }
+        out.push_str(&formatted);
+    }
+    (out, l1, last_shown, note)
+}
+
 #[async_trait]
 impl Tool for ToolCat {
     fn as_any(&self) -> &dyn std::any::Any { self }
@@ -74,9 +140,46 @@ impl Tool for ToolCat {
             None => vec![],
         };
         let skeleton = parse_skeleton_from_args(args)?;
+        let line1 = parse_line_from_args(args, "line1")?;
+        let line2 = parse_line_from_args(args, "line2")?;
+        let line_numbers = parse_line_numbers_from_args(args)?;
         ccx.lock().await.pp_skeleton = skeleton;
 
-        let (filenames_present, symbols_not_found, not_found_messages, context_enums, multimodal) = paths_and_symbols_to_cat(ccx.clone(), paths, symbols).await;
+        let (filenames_present, symbols_not_found, mut not_found_messages, mut context_enums, multimodal) = paths_and_symbols_to_cat(ccx.clone(), paths, symbols).await;
+
+        // whole files with a range or line numbers go into the tool message as is, postprocessing would cut them differently
+        let mut verbatim_files = vec![];
+        // clamped ranges and cut off files, the read itself went fine so these are not corrections
+        let mut notes = vec![];
+        if line1.is_some() || line2.is_some() || line_numbers {
+            let (gcx, tokens_for_rag) = {
+                let ccx_locked = ccx.lock().await;
+                (ccx_locked.global_context.clone(), ccx_locked.tokens_for_rag)
+            };
+            let mut keep = vec![];
+            let mut texts = vec![];
+            for ce in context_enums.into_iter() {
+                match ce {
+                    ContextEnum::ContextFile(cf) if cf.symbols.is_empty() => {
+                        match get_file_text_from_memory_or_disk(gcx.clone(), &PathBuf::from(&cf.file_name)).await {
+                            Ok(text) => texts.push((cf.file_name, text)),
+                            Err(e) => not_found_messages.push(format!("{}: {}", cf.file_name, e)),
+                        }
+                    }
+                    other => keep.push(other),
+                }
+            }
+            context_enums = keep;
+            // the same share run_tools gives to context files, they would have been postprocessed to fit into it
+            let char_limit = (tokens_for_rag / 2) * SYMBOLS_PER_TOKEN as usize / texts.len().max(1);
+            for (file_name, text) in texts {
+                let (slice, l1, l2, note) = cat_line_range(&text, line1, line2, line_numbers, char_limit);
+                if let Some(note) = note {
+                    notes.push(format!("{}: {}", file_name, note));
+                }
+                verbatim_files.push(format!("File {}:{}-{}\n```\n{}```", file_name, l1, l2, slice));
+            }
+        }
 
         let mut content = "".to_string();
         if !filenames_present.is_empty() {
@@ -90,6 +193,12 @@ impl Tool for ToolCat {
             content.push_str(&format!("Problems:\n{}\n\n", not_found_messages.join("\n\n")));
             corrections = true;
         }
+        if !notes.is_empty() {
+            content.push_str(&format!("Notes:\n{}\n\n", notes.join("\n")));
+        }
+        for f in verbatim_files.iter() {
+            content.push_str(&format!("{}\n\n", f));
+        }
 
         let mut results = context_enums;
         let content = if multimodal.is_empty() {
@@ -312,3 +421,82 @@ pub async fn paths_and_symbols_to_cat(
     }
     (filenames_present, symbols_not_found, not_found_messages, context_enums, multimodal)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n";
+
+    #[test]
+    fn test_cat_full_file() {
+        let (out, l1, l2, note) = cat_line_range(TEXT, None, None, false, usize::MAX);
+        assert_eq!(out, TEXT);
+        assert_eq!((l1, l2, note), (1, 4, None));
+        let (out, _, _, _) = cat_line_range(TEXT, None, None, true, usize::MAX);
+        assert_eq!(out, "1 | fn main() {\n2 |     let x = 1;\n3 |     println!(\"{}\", x);\n4 | }\n");
+    }
+
+    #[test]
+    fn test_cat_line_range() {
+        let (out, l1, l2, note) = cat_line_range(TEXT, Some(2), Some(3), true, usize::MAX);
+        assert_eq!(out, "2 |     let x = 1;\n3 |     println!(\"{}\", x);\n");
+        assert_eq!((l1, l2, note), (2, 3, None));
+        let (out, _, _, _) = cat_line_range(TEXT, Some(4), None, false, usize::MAX);
+        assert_eq!(out, "}\n");
+    }
+
+    #[test]
+    fn test_cat_line_range_out_of_range() {
+        let (out, l1, l2, note) = cat_line_range(TEXT, Some(3), Some(100), true, usize::MAX);
+        assert_eq!(out, "3 |     println!(\"{}\", x);\n4 | }\n");
+        assert_eq!((l1, l2), (3, 4));
+        assert_eq!(note.unwrap(), "asked for lines 3-100, but the file has 4 lines, showing 3-4");
+        let (out, l1, l2, note) = cat_line_range(TEXT, Some(10), Some(20), false, usize::MAX);
+        assert_eq!((out.as_str(), l1, l2), ("}\n", 4, 4));
+        assert!(note.is_some());
+        let (out, _, _, note) = cat_line_range("", Some(1), Some(2), true, usize::MAX);
+        assert_eq!((out.as_str(), note.unwrap().as_str()), ("", "the file is empty"));
+    }
+
+    #[test]
+    fn test_cat_line_range_over_budget() {
+        // "1 | fn main() {\n" and "2 |     let x = 1;\n" are 35 chars, the third line doesn't fit
+        let (out, l1, l2, note) = cat_line_range(TEXT, None, None, true, 40);
+        assert_eq!(out, "1 | fn main() {\n2 |     let x = 1;\n");
+        assert_eq!((l1, l2), (1, 2));
+        assert_eq!(note.unwrap(), "lines 1-4 don't fit into the context, showing 1-2, call again with line1=3 for the rest");
+        let (out, _, l2, note) = cat_line_range(TEXT, Some(3), Some(100), false, 10);
+        assert_eq!((out.as_str(), l2), ("", 3));
+        assert_eq!(note.unwrap(), "asked for lines 3-100, but the file has 4 lines, showing 3-4; line 3 alone doesn't fit into the context, nothing shown");
+    }
+
+    #[tokio::test]
+    async fn test_clamped_range_is_not_a_correction() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        let path = project.join("main.rs");
+        std::fs::write(&path, TEXT).unwrap();
+        let gcx = crate::global_context::create_test_global_context(dir.path(), &["--workspace-folder", &project.to_string_lossy()]).await;
+        let ccx = Arc::new(AMutex::new(AtCommandsContext::new(gcx.clone(), 8192, 5, false, vec![], "".to_string(), false).await));
+        ccx.lock().await.tokens_for_rag = 4096;
+        let args = HashMap::from([
+            ("paths".to_string(), Value::String(path.to_string_lossy().to_string())),
+            ("line1".to_string(), Value::String("3".to_string())),
+            ("line2".to_string(), Value::String("100".to_string())),
+        ]);
+        let (corrections, results) = ToolCat {}.tool_execute(ccx, &"call_cat".to_string(), &args).await.unwrap();
+        let content = match results.last() {
+            Some(ContextEnum::ChatMessage(m)) => m.content.content_text_only(),
+            _ => panic!("expected the tool message last"),
+        };
+        // run_tools would throw away the file and ask the model to call again if this was a correction
+        assert!(!corrections, "{}", content);
+        assert!(content.contains("Notes:\n"), "{}", content);
+        assert!(content.contains("asked for lines 3-100, but the file has 4 lines, showing 3-4"), "{}", content);
+        assert!(!content.contains("Problems:"), "{}", content);
+        assert!(content.contains("println!"), "{}", content);
+    }
+
+}
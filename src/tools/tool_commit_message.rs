@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_active_project_path;
+use crate::files_in_workspace::detect_vcs_for_a_file_path;
+use crate::privacy::{check_file_privacy, load_privacy_if_needed, FilePrivacyLevel, PrivacySettings};
+use crate::tools::tool_git_branch::git;
+use crate::tools::tools_description::Tool;
+
+
+const COMMIT_MESSAGE_MAX_FILES_IN_SUBJECT: usize = 3;
+const COMMIT_MESSAGE_DIFF_MAX_CHARS: usize = 6000;
+
+pub struct ToolCommitMessage;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StagedFile {
+    pub status: char,     // A M D, as in git diff --name-status
+    pub path: String,
+    pub added: usize,
+    pub removed: usize,
+}
+
+fn is_docs(path: &str) -> bool {
+    path.ends_with(".md") || path.ends_with(".rst") || path.ends_with(".txt") || path.starts_with("docs/") || path.contains("/docs/")
+}
+
+fn is_test(path: &str) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    path.starts_with("tests/") || path.contains("/tests/") || path.contains("/test/") || file_name.starts_with("test_") || file_name.contains("_test.") || file_name.contains(".test.") || file_name.contains(".spec.")
+}
+
+fn is_build(path: &str) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    path.starts_with(".github/") || ["Cargo.toml", "Cargo.lock", "package.json", "package-lock.json", "pyproject.toml", "setup.py", "requirements.txt", "Makefile", "Dockerfile"].contains(&file_name)
+}
+
+// Only a guess from file names, the diff is returned too so the model can fix it
+fn commit_type(files: &Vec<StagedFile>) -> &'static str {
+    if files.iter().all(|f| is_docs(&f.path)) {
+        "docs"
+    } else if files.iter().all(|f| is_test(&f.path)) {
+        "test"
+    } else if files.iter().all(|f| is_build(&f.path)) {
+        "build"
+    } else if files.iter().any(|f| f.status == 'A' && !is_test(&f.path) && !is_docs(&f.path)) {
+        "feat"
+    } else {
+        "fix"
+    }
+}
+
+// The deepest directory all files share, src and lib say nothing so they don't count; a single file is its own scope
+fn commit_scope(files: &Vec<StagedFile>) -> Option<String> {
+    if files.len() == 1 {
+        let file_name = files[0].path.rsplit('/').next().unwrap_or(&files[0].path);
+        return Some(file_name.split('.').next().unwrap_or(file_name).to_string()).filter(|s| !s.is_empty());
+    }
+    let dirs: Vec<Vec<&str>> = files.iter().map(|f| {
+        let mut parts: Vec<&str> = f.path.split('/').collect();
+        parts.pop();
+        parts
+    }).collect();
+    let mut common: Vec<&str> = dirs[0].clone();
+    for d in dirs.iter().skip(1) {
+        let n = common.iter().zip(d.iter()).take_while(|(a, b)| a == b).count();
+        common.truncate(n);
+    }
+    common.into_iter().rev().find(|p| !["src", "lib", "app", "pkg"].contains(p)).map(|p| p.to_string())
+}
+
+fn commit_subject(files: &Vec<StagedFile>) -> String {
+    let verb = if files.iter().all(|f| f.status == 'A') {
+        "add"
+    } else if files.iter().all(|f| f.status == 'D') {
+        "remove"
+    } else {
+        "update"
+    };
+    let mut names: Vec<&str> = files.iter().map(|f| f.path.rsplit('/').next().unwrap_or(&f.path)).collect();
+    names.dedup();
+    let mut subject = format!("{} {}", verb, names.iter().take(COMMIT_MESSAGE_MAX_FILES_IN_SUBJECT).cloned().collect::<Vec<_>>().join(", "));
+    if names.len() > COMMIT_MESSAGE_MAX_FILES_IN_SUBJECT {
+        subject.push_str(&format!(" and {} more", names.len() - COMMIT_MESSAGE_MAX_FILES_IN_SUBJECT));
+    }
+    subject
+}
+
+pub fn draft_commit_message(files: &Vec<StagedFile>, hint: Option<&str>) -> String {
+    let subject = hint.map(|h| h.trim().trim_end_matches('.').to_string()).filter(|h| !h.is_empty()).unwrap_or_else(|| commit_subject(files));
+    let header = match commit_scope(files) {
+        Some(scope) => format!("{}({}): {}", commit_type(files), scope, subject),
+        None => format!("{}: {}", commit_type(files), subject),
+    };
+    let body = files.iter()
+        .map(|f| format!("- {} {} (+{} -{})", f.status, f.path, f.added, f.removed))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("{}\n\n{}\n", header, body)
+}
+
+fn parse_staged(name_status: &str, numstat: &str) -> Vec<StagedFile> {
+    // numstat shows "-" for binary files
+    let stats: HashMap<&str, (usize, usize)> = numstat.lines().filter_map(|l| {
+        let mut parts = l.splitn(3, '\t');
+        let (added, removed, path) = (parts.next()?, parts.next()?, parts.next()?);
+        Some((path, (added.parse().unwrap_or(0), removed.parse().unwrap_or(0))))
+    }).collect();
+    name_status.lines().filter_map(|l| {
+        let parts: Vec<&str> = l.split('\t').collect();
+        let status = parts.first()?.chars().next()?;
+        let path = *parts.get(1)?;
+        let (added, removed) = stats.get(path).cloned().unwrap_or((0, 0));
+        Some(StagedFile { status, path: path.to_string(), added, removed })
+    }).collect()
+}
+
+pub async fn commit_message_for_staged(repo: &Path, privacy: Arc<PrivacySettings>, hint: Option<&str>) -> Result<String, String> {
+    let name_status = git(repo, &["diff", "--cached", "--no-renames", "--name-status"]).await?;
+    if name_status.trim().is_empty() {
+        let unstaged = git(repo, &["status", "--porcelain"]).await?;
+        if unstaged.trim().is_empty() {
+            return Ok("Nothing is staged and the working tree is clean, there is nothing to commit.".to_string());
+        }
+        return Ok(format!("Nothing is staged, `git add` the files that belong to the commit first. Changes that are not staged:\n{}", unstaged));
+    }
+    let numstat = git(repo, &["diff", "--cached", "--no-renames", "--numstat"]).await?;
+    // blocked files stay out of the message and the diff, the diff is asked for the allowed paths only
+    let (files, hidden): (Vec<StagedFile>, Vec<StagedFile>) = parse_staged(&name_status, &numstat).into_iter()
+        .partition(|f| check_file_privacy(privacy.clone(), &repo.join(&f.path), &FilePrivacyLevel::OnlySendToServersIControl).is_ok());
+    if files.is_empty() {
+        return Ok(format!("All {} staged files are hidden by the privacy settings, can't draft a commit message for them.", hidden.len()));
+    }
+    let mut diff_args = vec!["diff", "--cached", "--no-color", "--"];
+    diff_args.extend(files.iter().map(|f| f.path.as_str()));
+    let diff = git(repo, &diff_args).await?;
+    let mut report = format!("Draft commit message for {} staged files, nothing was committed:\n\n```\n{}```\n\n", files.len(), draft_commit_message(&files, hint));
+    if !hidden.is_empty() {
+        report.push_str(&format!("{} more staged files are hidden by the privacy settings, they are not in the message or the diff.\n\n", hidden.len()));
+    }
+    report.push_str("The type and scope are guessed from file names. Check them against the diff below, and make the subject say why the change was made.\n\n");
+    report.push_str("```diff\n");
+    if diff.len() > COMMIT_MESSAGE_DIFF_MAX_CHARS {
+        let cut = (0..=COMMIT_MESSAGE_DIFF_MAX_CHARS).rev().find(|i| diff.is_char_boundary(*i)).unwrap_or(0);
+        report.push_str(&diff[..cut]);
+        report.push_str(&format!("\n...diff is {} chars, the rest is cut\n", diff.len()));
+    } else {
+        report.push_str(&diff);
+    }
+    report.push_str("```\n");
+    Ok(report)
+}
+
+#[async_trait]
+impl Tool for ToolCommitMessage {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let hint = match args.get("hint") {
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(Value::Null) | None => None,
+            Some(v) => return Err(format!("argument `hint` is not a string: {:?}", v)),
+        };
+        let gcx = ccx.lock().await.global_context.clone();
+        let project_path: PathBuf = get_active_project_path(gcx.clone()).await.ok_or("no workspace folder is open".to_string())?;
+        let repo = match detect_vcs_for_a_file_path(&project_path).await {
+            Some((repo, "git")) => repo,
+            _ => return Err(format!("{} is not inside a git repository", project_path.display())),
+        };
+        let privacy = load_privacy_if_needed(gcx.clone()).await;
+        let report = commit_message_for_staged(&repo, privacy, hint.as_deref()).await?;
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_commit_message_from_staged_changes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let privacy = Arc::new(PrivacySettings::default());
+        std::fs::create_dir_all(dir.join("src/parser")).unwrap();
+        git(&dir, &["init", "-q", "-b", "main"]).await.unwrap();
+        git(&dir, &["config", "user.name", "test"]).await.unwrap();
+        git(&dir, &["config", "user.email", "test@example.com"]).await.unwrap();
+        std::fs::write(dir.join("src/parser/lexer.rs"), "fn lex() {}\n").unwrap();
+        git(&dir, &["add", "."]).await.unwrap();
+        git(&dir, &["commit", "-q", "-m", "first"]).await.unwrap();
+
+        let report = commit_message_for_staged(&dir, privacy.clone(), None).await.unwrap();
+        assert!(report.starts_with("Nothing is staged and the working tree is clean"), "{}", report);
+        std::fs::write(dir.join("src/parser/lexer.rs"), "fn lex() {}\nfn peek() {}\n").unwrap();
+        let report = commit_message_for_staged(&dir, privacy.clone(), None).await.unwrap();
+        assert!(report.starts_with("Nothing is staged, `git add`"), "{}", report);
+        assert!(report.contains("src/parser/lexer.rs"), "{}", report);
+
+        std::fs::write(dir.join("src/parser/tokens.rs"), "pub enum Token {}\n").unwrap();
+        git(&dir, &["add", "."]).await.unwrap();
+        let report = commit_message_for_staged(&dir, privacy.clone(), None).await.unwrap();
+        assert!(report.contains("Draft commit message for 2 staged files"), "{}", report);
+        assert!(report.contains("feat(parser): update lexer.rs, tokens.rs\n"), "{}", report);
+        assert!(report.contains("- M src/parser/lexer.rs (+1 -0)"), "{}", report);
+        assert!(report.contains("- A src/parser/tokens.rs (+1 -0)"), "{}", report);
+        assert!(report.contains("+pub enum Token {}"), "{}", report);
+        // read-only, still staged and not committed
+        assert_eq!(git(&dir, &["rev-list", "--count", "HEAD"]).await.unwrap().trim(), "1");
+
+        let files = vec![StagedFile { status: 'M', path: "README.md".to_string(), added: 3, removed: 1 }];
+        assert_eq!(draft_commit_message(&files, Some("Explain the install steps.")), "docs(README): Explain the install steps\n\n- M README.md (+3 -1)\n");
+    }
+
+    #[tokio::test]
+    async fn test_blocked_staged_files_stay_out_of_the_diff() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        git(&dir, &["init", "-q", "-b", "main"]).await.unwrap();
+        std::fs::write(dir.join("app.py"), "print('hi')\n").unwrap();
+        std::fs::write(dir.join("secrets.env"), "API_KEY=hunter2\n").unwrap();
+        git(&dir, &["add", "."]).await.unwrap();
+        let privacy = Arc::new(PrivacySettings {
+            privacy_rules: crate::privacy::FilePrivacySettings {
+                only_send_to_servers_I_control: vec![],
+                blocked: vec!["*.env".to_string()],
+            },
+            ..PrivacySettings::default()
+        });
+
+        let report = commit_message_for_staged(&dir, privacy.clone(), None).await.unwrap();
+        assert!(report.contains("Draft commit message for 1 staged files"), "{}", report);
+        assert!(report.contains("+print('hi')"), "{}", report);
+        assert!(report.contains("1 more staged files are hidden"), "{}", report);
+        assert!(!report.contains("secrets.env") && !report.contains("hunter2"), "{}", report);
+
+        git(&dir, &["rm", "-q", "--cached", "app.py"]).await.unwrap();
+        let report = commit_message_for_staged(&dir, privacy, None).await.unwrap();
+        assert!(report.starts_with("All 1 staged files are hidden"), "{}", report);
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+use tree_sitter::Node;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::ast::ast_structs::AstDefinition;
+use crate::ast::treesitter::language_id::LanguageId;
+use crate::ast::treesitter::parsers::{parse_text_to_tree, tree_sitter_language_by_filename};
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_in_workspace::get_file_text_from_memory_or_disk;
+use crate::tools::tool_ast_definition::there_are_definitions_with_similar_names_though;
+use crate::tools::tools_description::Tool;
+use crate::tools::tools_utils::node_text;
+
+
+pub struct ToolCompareSymbols;
+
+// One node of the tree in pre-order, two symbols are equivalent when these sequences are equal
+#[derive(Debug, Clone, PartialEq)]
+struct ShapeNode {
+    depth: usize,
+    kind: String,
+    text: String,       // leaves only, inner nodes are described by their children
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolDifference {
+    pub row1: usize,    // starts from 0, relative to the first line of each symbol
+    pub row2: usize,
+    pub line1: String,
+    pub line2: String,
+}
+
+// A method cut out of a class is indented, python doesn't parse that
+fn dedent(code: &str) -> String {
+    let indent = code.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    code.lines().map(|l| l.get(indent..).unwrap_or(l.trim_start())).collect::<Vec<_>>().join("\n")
+}
+
+// The symbol's own name is not compared, the point is to find out if two differently named things do the same.
+// With ignore_names every identifier becomes the order it first appeared in, so a consistent rename is equivalent,
+// and swapping two variables is not.
+fn shape_of(language_id: LanguageId, code: &str, ignore_names: bool) -> Result<Vec<(ShapeNode, usize)>, String> {
+    let tree = parse_text_to_tree(language_id, code)?;
+
+    let mut own_name = None;
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if let Some(name) = node.child_by_field_name("name") {
+            own_name = Some(name.id());
+            break;
+        }
+        for i in (0..node.child_count()).rev() {
+            stack.push(node.child(i).unwrap());
+        }
+    }
+
+    let mut names: HashMap<String, usize> = HashMap::new();
+    let mut shape = vec![];
+    let mut stack = vec![(tree.root_node(), 0)];
+    while let Some((node, depth)) = stack.pop() {
+        if node.kind().contains("comment") || Some(node.id()) == own_name {
+            continue;
+        }
+        let text = if node.child_count() > 0 {
+            String::new()
+        } else if ignore_names && node.kind() == "identifier" {
+            let n = names.len();
+            format!("${}", names.entry(node_text(&node, code).to_string()).or_insert(n))
+        } else {
+            node_text(&node, code).to_string()
+        };
+        shape.push((ShapeNode { depth, kind: node.kind().to_string(), text }, node.start_position().row));
+        for i in (0..node.child_count()).rev() {
+            stack.push((node.child(i).unwrap(), depth + 1));
+        }
+    }
+    Ok(shape)
+}
+
+// Whitespace and comments never matter, tree-sitter doesn't put whitespace in the tree and comments are skipped.
+// None means equivalent, otherwise the first place where the trees part ways.
+pub fn compare_structure(language_id: LanguageId, code1: &str, code2: &str, ignore_names: bool) -> Result<Option<SymbolDifference>, String> {
+    let (code1, code2) = (dedent(code1), dedent(code2));
+    let shape1 = shape_of(language_id, &code1, ignore_names)?;
+    let shape2 = shape_of(language_id, &code2, ignore_names)?;
+    let first_mismatch = (0..shape1.len().max(shape2.len()))
+        .find(|&i| shape1.get(i).map(|s| &s.0) != shape2.get(i).map(|s| &s.0));
+    let Some(i) = first_mismatch else { return Ok(None) };
+
+    let describe = |code: &str, shape: &Vec<(ShapeNode, usize)>| match shape.get(i) {
+        Some((_, row)) => (*row, code.lines().nth(*row).unwrap_or("").trim().to_string()),
+        None => (code.lines().count().saturating_sub(1), "(ends here)".to_string()),
+    };
+    let (row1, line1) = describe(&code1, &shape1);
+    let (row2, line2) = describe(&code2, &shape2);
+    Ok(Some(SymbolDifference { row1, row2, line1, line2 }))
+}
+
+pub fn format_compare_report(
+    name1: &str,
+    location1: (&str, usize, usize),
+    name2: &str,
+    location2: (&str, usize, usize),
+    difference: &Option<SymbolDifference>,
+    ignore_names: bool,
+) -> String {
+    let ignored = if ignore_names { "whitespace, comments and names" } else { "whitespace and comments" };
+    let mut report = format!(
+        "`{}` at {}:{}-{}\n`{}` at {}:{}-{}\n",
+        name1, location1.0, location1.1, location1.2,
+        name2, location2.0, location2.1, location2.2,
+    );
+    match difference {
+        None => report.push_str(&format!("Structurally equivalent, {} ignored.\n", ignored)),
+        Some(d) => {
+            report.push_str(&format!("Different ({} ignored), the first difference:\n", ignored));
+            report.push_str(&format!("{}:{}: {}\n", location1.0, location1.1 + d.row1, d.line1));
+            report.push_str(&format!("{}:{}: {}\n", location2.0, location2.1 + d.row2, d.line2));
+            if !ignore_names {
+                report.push_str("If the symbols only differ in variable names, call again with ignore_names=true.\n");
+            }
+        }
+    }
+    report
+}
+
+async fn find_one_definition(
+    ccx: Arc<AMutex<AtCommandsContext>>,
+    symbol: &str,
+) -> Result<Arc<AstDefinition>, String> {
+    let gcx = ccx.lock().await.global_context.clone();
+    let ast_service = gcx.read().await.ast_service.clone().ok_or("attempt to use compare_symbols with no ast turned on".to_string())?;
+    let ast_index = ast_service.lock().await.ast_index.clone();
+    crate::ast::ast_indexer_thread::ast_indexer_block_until_finished(ast_service.clone(), 20_000, true).await;
+    let symbol = symbol.replace('.', "::");
+    let defs = crate::ast::ast_db::definitions(ast_index.clone(), &symbol).await;
+    match defs.len() {
+        0 => Err(there_are_definitions_with_similar_names_though(ast_index, &symbol).await),
+        1 => Ok(defs[0].clone()),
+        _ => Err(format!(
+            "`{}` is ambiguous, use a longer path, for example:\n{}\n",
+            symbol,
+            defs.iter().take(10).map(|d| format!("{} at {}:{}", d.path_drop0(), d.cpath, d.full_line1())).collect::<Vec<_>>().join("\n")
+        )),
+    }
+}
+
+fn symbol_arg(args: &HashMap<String, Value>, name: &str) -> Result<String, String> {
+    match args.get(name) {
+        Some(Value::String(s)) if !s.trim().is_empty() => Ok(s.trim().to_string()),
+        Some(v) if !v.is_string() => Err(format!("argument `{}` is not a string: {:?}", name, v)),
+        _ => Err(format!("Missing argument `{}`", name)),
+    }
+}
+
+#[async_trait]
+impl Tool for ToolCompareSymbols {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let symbol1 = symbol_arg(args, "symbol1")?;
+        let symbol2 = symbol_arg(args, "symbol2")?;
+        let ignore_names = match args.get("ignore_names") {
+            Some(Value::Bool(b)) => *b,
+            Some(Value::String(s)) => s.trim() == "true",
+            _ => false,
+        };
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let def1 = find_one_definition(ccx.clone(), &symbol1).await?;
+        let def2 = find_one_definition(ccx.clone(), &symbol2).await?;
+        let mut codes = vec![];
+        let mut language_ids = vec![];
+        for def in [&def1, &def2] {
+            let language_id = tree_sitter_language_by_filename(&PathBuf::from(&def.cpath))
+                .map_err(|e| format!("cannot compare {}: {}", def.cpath, e))?;
+            // checks privacy
+            let text = get_file_text_from_memory_or_disk(gcx.clone(), &PathBuf::from(&def.cpath)).await?;
+            let code = text.lines()
+                .skip(def.full_line1().saturating_sub(1))
+                .take(def.full_line2().saturating_sub(def.full_line1()) + 1)
+                .collect::<Vec<_>>()
+                .join("\n");
+            codes.push(code);
+            language_ids.push(language_id);
+        }
+        if language_ids[0] != language_ids[1] {
+            return Err(format!("`{}` is {} and `{}` is {}, only symbols in the same language can be compared", symbol1, language_ids[0], symbol2, language_ids[1]));
+        }
+        let difference = compare_structure(language_ids[0], &codes[0], &codes[1], ignore_names)?;
+
+        let short_paths = crate::files_correction::shortify_paths(gcx.clone(), &vec![def1.cpath.clone(), def2.cpath.clone()]).await;
+        let report = format_compare_report(
+            &def1.path_drop0(), (&short_paths[0], def1.full_line1(), def1.full_line2()),
+            &def2.path_drop0(), (&short_paths[1], def2.full_line1(), def2.full_line2()),
+            &difference,
+            ignore_names,
+        );
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec!["ast".to_string()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOTAL: &str = r#"fn total(xs: &[i32]) -> i32 {
+    let mut s = 0;
+    for x in xs {
+        s += x; // running sum
+    }
+    s
+}"#;
+
+    const TOTAL_REFORMATTED: &str = r#"fn sum_all(xs:&[i32])->i32{ let mut s=0;
+    /* no overflow checks */ for x in xs { s+=x; } s }"#;
+
+    const TOTAL_SUBTRACTS: &str = r#"fn total(xs: &[i32]) -> i32 {
+    let mut s = 0;
+    for x in xs {
+        s -= x;
+    }
+    s
+}"#;
+
+    const TOTAL_RENAMED: &str = r#"fn total(items: &[i32]) -> i32 {
+    let mut acc = 0;
+    for item in items {
+        acc += item;
+    }
+    acc
+}"#;
+
+    #[test]
+    fn test_formatting_is_equivalent_logic_is_not() {
+        assert_eq!(compare_structure(LanguageId::Rust, TOTAL, TOTAL_REFORMATTED, false).unwrap(), None);
+
+        let difference = compare_structure(LanguageId::Rust, TOTAL, TOTAL_SUBTRACTS, false).unwrap().unwrap();
+        assert_eq!((difference.row1, difference.line1.as_str()), (3, "s += x; // running sum"));
+        assert_eq!((difference.row2, difference.line2.as_str()), (3, "s -= x;"));
+        let report = format_compare_report("total", ("src/a.rs", 10, 16), "total", ("src/b.rs", 1, 7), &Some(difference), false);
+        assert!(report.contains("Different"), "{}", report);
+        assert!(report.contains("src/a.rs:13: s += x; // running sum"), "{}", report);
+        assert!(report.contains("src/b.rs:4: s -= x;"), "{}", report);
+
+        // a consistent rename is only equivalent when asked for
+        assert!(compare_structure(LanguageId::Rust, TOTAL, TOTAL_RENAMED, false).unwrap().is_some());
+        assert_eq!(compare_structure(LanguageId::Rust, TOTAL, TOTAL_RENAMED, true).unwrap(), None);
+        assert!(compare_structure(LanguageId::Rust, TOTAL_SUBTRACTS, TOTAL_RENAMED, true).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_indented_python_methods() {
+        let method1 = "    def area(self):\n        return self.w * self.h\n";
+        let method2 = "        def area(self):\n            # cached elsewhere\n            return self.w*self.h\n";
+        assert_eq!(compare_structure(LanguageId::Python, method1, method2, false).unwrap(), None);
+    }
+}
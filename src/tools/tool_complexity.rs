@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+use tree_sitter::Node;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::at_commands::at_file::{file_repair_candidates, return_one_candidate_or_a_good_error};
+use crate::ast::treesitter::language_id::LanguageId;
+use crate::ast::treesitter::parsers::{parse_text_to_tree, tree_sitter_language_by_filename};
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_project_dirs;
+use crate::files_in_workspace::get_file_text_from_memory_or_disk;
+use crate::tools::tools_description::Tool;
+use crate::tools::tools_utils::node_text;
+
+
+const COMPLEXITY_DEFAULT_THRESHOLD: usize = 10;
+
+// Lambdas and closures are not here, their branches count towards the function they are written in
+const FUNCTION_KINDS: &[&str] = &[
+    "function_definition",              // python, cpp
+    "function_item",                    // rust
+    "function_declaration", "generator_function_declaration", "method_definition",
+    "function", "function_expression", "arrow_function",      // js, ts
+    "method_declaration", "constructor_declaration",          // java, go
+];
+
+// Each case of a switch and each arm of a match counts, as in the classic McCabe number
+const DECISION_KINDS: &[&str] = &[
+    "if_statement", "elif_clause", "if_expression", "if_let_expression",
+    "for_statement", "for_in_statement", "enhanced_for_statement", "for_range_loop", "for_expression",
+    "while_statement", "while_expression", "do_statement",
+    "except_clause", "catch_clause",
+    "case_clause", "switch_case", "case_statement", "switch_label", "match_arm",
+    "expression_case", "type_case", "communication_case",   // go
+    "conditional_expression", "ternary_expression",
+    "if_clause", "for_in_clause",       // python comprehensions
+];
+
+const BOOLEAN_OPERATORS: &[&str] = &["&&", "||", "and", "or"];
+
+pub struct ToolComplexity;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionComplexity {
+    pub name: String,
+    pub line1: usize,
+    pub line2: usize,
+    pub complexity: usize,
+}
+
+fn is_decision(node: &Node) -> bool {
+    if node.is_named() {
+        DECISION_KINDS.contains(&node.kind())
+    } else {
+        BOOLEAN_OPERATORS.contains(&node.kind())
+    }
+}
+
+// js has an anonymous `function` keyword token inside every function, it's not a function itself
+fn is_function(node: &Node) -> bool {
+    node.is_named() && FUNCTION_KINDS.contains(&node.kind())
+}
+
+fn function_name(node: &Node, text: &str) -> String {
+    if let Some(name) = node.child_by_field_name("name") {
+        return node_text(&name, text).to_string();
+    }
+    // cpp: function_definition -> function_declarator -> identifier, maybe through pointer_declarator
+    if let Some(mut declarator) = node.child_by_field_name("declarator") {
+        while let Some(inner) = declarator.child_by_field_name("declarator") {
+            declarator = inner;
+        }
+        return node_text(&declarator, text).to_string();
+    }
+    // const handler = () => {...}
+    if let Some(parent) = node.parent() {
+        if parent.kind() == "variable_declarator" || parent.kind() == "pair" {
+            if let Some(name) = parent.child_by_field_name("name").or(parent.child_by_field_name("key")) {
+                return node_text(&name, text).to_string();
+            }
+        }
+    }
+    "<anonymous>".to_string()
+}
+
+fn push_children<'a>(node: &Node<'a>, stack: &mut Vec<Node<'a>>) {
+    for i in (0..node.child_count()).rev() {
+        if let Some(child) = node.child(i) {
+            stack.push(child);
+        }
+    }
+}
+
+pub fn functions_complexity(language_id: LanguageId, text: &str) -> Result<Vec<FunctionComplexity>, String> {
+    let tree = parse_text_to_tree(language_id, text)?;
+
+    let mut result = vec![];
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if !is_function(&node) {
+            push_children(&node, &mut stack);
+            continue;
+        }
+        let mut complexity = 1;
+        let mut inside = vec![];
+        push_children(&node, &mut inside);
+        while let Some(n) = inside.pop() {
+            if is_function(&n) {
+                stack.push(n);  // a nested function gets its own number
+                continue;
+            }
+            if is_decision(&n) {
+                complexity += 1;
+            }
+            push_children(&n, &mut inside);
+        }
+        result.push(FunctionComplexity {
+            name: function_name(&node, text),
+            line1: node.start_position().row + 1,
+            line2: node.end_position().row + 1,
+            complexity,
+        });
+    }
+    result.sort_by_key(|f| f.line1);
+    Ok(result)
+}
+
+pub fn format_complexity_report(path: &str, functions: &Vec<FunctionComplexity>, threshold: usize) -> String {
+    let name_width = functions.iter().map(|f| f.name.len()).max().unwrap_or(0);
+    let mut report = format!("Cyclomatic complexity of functions in {} (threshold {}):\n", path, threshold);
+    for f in functions.iter() {
+        let lines = format!("{}-{}", f.line1, f.line2);
+        report.push_str(&format!("  {:<name_width$}  lines {:<9}  complexity {}{}\n",
+            f.name, lines, f.complexity,
+            if f.complexity > threshold { "  <-- above threshold" } else { "" },
+        ));
+    }
+    let above = functions.iter().filter(|f| f.complexity > threshold).count();
+    report.push_str(&format!("{} functions, {} above the threshold\n", functions.len(), above));
+    report
+}
+
+#[async_trait]
+impl Tool for ToolComplexity {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let path = match args.get("path") {
+            Some(Value::String(s)) if !s.trim().is_empty() => s.trim().to_string(),
+            Some(v) if !v.is_string() => return Err(format!("argument `path` is not a string: {:?}", v)),
+            _ => return Err("Missing argument `path`".to_string()),
+        };
+        let symbol = match args.get("symbol") {
+            Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim().to_string()),
+            Some(Value::String(_)) | None => None,
+            Some(v) => return Err(format!("argument `symbol` is not a string: {:?}", v)),
+        };
+        let threshold = match args.get("threshold") {
+            Some(Value::Number(n)) => n.as_u64().ok_or(format!("argument `threshold` is not a positive integer: {}", n))? as usize,
+            Some(Value::String(s)) if !s.trim().is_empty() => s.trim().parse::<usize>().map_err(|_| format!("argument `threshold` is not a positive integer: {:?}", s))?,
+            _ => COMPLEXITY_DEFAULT_THRESHOLD,
+        };
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let candidates = file_repair_candidates(gcx.clone(), &path, 10, false).await;
+        let file_path = return_one_candidate_or_a_good_error(gcx.clone(), &path, &candidates, &get_project_dirs(gcx.clone()).await, false).await?;
+        let language_id = tree_sitter_language_by_filename(&PathBuf::from(&file_path))
+            .map_err(|e| format!("cannot compute complexity for {}: {}", file_path, e))?;
+        let text = get_file_text_from_memory_or_disk(gcx.clone(), &PathBuf::from(&file_path)).await?;
+        let mut functions = functions_complexity(language_id, &text)?;
+        if let Some(symbol) = &symbol {
+            // "Class.method" or "method" both work, the tree only knows the short name
+            let short = symbol.rsplit(['.', ':']).next().unwrap_or(symbol);
+            let all_names: Vec<String> = functions.iter().map(|f| f.name.clone()).collect();
+            functions.retain(|f| f.name == *symbol || f.name == short);
+            if functions.is_empty() {
+                return Err(format!("no function {:?} in {}, functions there: {}", symbol, file_path,
+                    if all_names.is_empty() { "none".to_string() } else { all_names.into_iter().take(30).collect::<Vec<_>>().join(", ") }));
+            }
+        }
+        let report = if functions.is_empty() {
+            format!("No functions in {}", file_path)
+        } else {
+            format_complexity_report(&file_path, &functions, threshold)
+        };
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BRANCHY_PY: &str = r#"
+def classify(order, customer):
+    if order.total > 1000 and customer.vip:
+        level = "gold"
+    elif order.total > 100 or customer.returning:
+        level = "silver"
+    else:
+        level = "bronze"
+    for item in order.items:
+        while item.qty > 10:
+            item.qty -= 1
+    try:
+        notify(customer)
+    except TimeoutError:
+        pass
+
+    def fallback(x):
+        return x or 0
+
+    discount = 0.1 if level == "gold" else 0.0
+    return [i for i in order.items if i.qty], fallback(discount)
+
+
+def simple():
+    return 1
+"#;
+
+    #[test]
+    fn test_python_branchy_function() {
+        let functions = functions_complexity(LanguageId::Python, BRANCHY_PY).unwrap();
+        let by_name: HashMap<String, FunctionComplexity> = functions.iter().map(|f| (f.name.clone(), f.clone())).collect();
+        // 1 + if, and, elif, or, for, while, except, conditional, comprehension for and if
+        assert_eq!(by_name["classify"].complexity, 11, "{:?}", functions);
+        assert_eq!(by_name["classify"].line1, 2);
+        assert_eq!(by_name["fallback"].complexity, 2);
+        assert_eq!(by_name["simple"].complexity, 1);
+
+        let report = format_complexity_report("orders.py", &functions, COMPLEXITY_DEFAULT_THRESHOLD);
+        assert!(report.lines().any(|l| l.contains("classify") && l.contains("complexity 11") && l.contains("above threshold")), "{}", report);
+        assert!(report.lines().any(|l| l.contains("simple") && !l.contains("above threshold")), "{}", report);
+        assert!(report.ends_with("3 functions, 1 above the threshold\n"), "{}", report);
+
+        assert!(functions_complexity(LanguageId::Kotlin, "fun main() {}").unwrap_err().contains("no parser"));
+    }
+
+    const BRANCHY_GO: &str = r#"package orders
+
+func classify(n int, ok bool) string {
+	if n > 0 && ok {
+		return "pos"
+	}
+	switch {
+	case n < 0:
+		return "neg"
+	case n == 0:
+		return "zero"
+	}
+	for i := 0; i < n; i++ {
+	}
+	return ""
+}
+
+func (o *Order) Total() int {
+	return o.qty
+}
+"#;
+
+    #[test]
+    fn test_go_functions_and_methods() {
+        let functions = functions_complexity(LanguageId::Go, BRANCHY_GO).unwrap();
+        let by_name: HashMap<String, FunctionComplexity> = functions.iter().map(|f| (f.name.clone(), f.clone())).collect();
+        // 1 + if, &&, two cases, for
+        assert_eq!(by_name["classify"].complexity, 6, "{:?}", functions);
+        assert_eq!(by_name["classify"].line1, 3);
+        assert_eq!(by_name["Total"].complexity, 1);
+    }
+
+    const BRANCHY_JS: &str = r#"
+function total(items) {
+  let sum = 0;
+  for (const item of items) {
+    if (item.qty > 0 && item.price) {
+      sum += item.qty * item.price;
+    }
+  }
+  return sum;
+}
+
+const label = (n) => n > 0 ? "pos" : "neg";
+
+const api = {
+  fetch: function (url) { return url || "/"; },
+};
+
+class Cart {
+  add(item) {
+    if (!item) { throw new Error("empty"); }
+    this.items.push(item);
+  }
+}
+"#;
+
+    const BRANCHY_TS: &str = r#"
+export function parse(input: string): number {
+  switch (input) {
+    case "a": return 1;
+    case "b": return 2;
+    default: return 0;
+  }
+}
+
+class Parser {
+  run(x: number): number {
+    return x ?? 0;
+  }
+}
+"#;
+
+    #[test]
+    fn test_js_ts_functions_listed_once() {
+        let listed = |language_id: LanguageId, text: &str| functions_complexity(language_id, text).unwrap()
+            .into_iter().map(|f| (f.name, f.line1, f.complexity)).collect::<Vec<_>>();
+        assert_eq!(listed(LanguageId::JavaScript, BRANCHY_JS), vec![
+            ("total".to_string(), 2, 4),   // for, if, &&
+            ("label".to_string(), 12, 2),
+            ("fetch".to_string(), 15, 2),
+            ("add".to_string(), 19, 2),
+        ]);
+        assert_eq!(listed(LanguageId::TypeScript, BRANCHY_TS), vec![
+            ("parse".to_string(), 2, 3),   // two cases, default is not a decision
+            ("run".to_string(), 11, 1),
+        ]);
+    }
+}
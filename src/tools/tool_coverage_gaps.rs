@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::at_commands::at_file::{file_repair_candidates, return_one_candidate_or_a_good_error};
+use crate::ast::ast_db::{doc_defs, usages};
+use crate::ast::ast_structs::{AstDB, AstDefinition};
+use crate::ast::treesitter::structs::SymbolType;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_project_dirs;
+use crate::tools::tool_extract_strings::looks_like_test_file;
+use crate::tools::tools_description::Tool;
+
+
+const USAGES_PER_FUNCTION: usize = 1000;
+
+pub struct ToolCoverageGaps;
+
+pub struct CoverageGaps {
+    pub tested: Vec<Arc<AstDefinition>>,
+    pub untested: Vec<Arc<AstDefinition>>,
+}
+
+// A test file, a `mod tests` inside a regular file, or a test_* function anywhere
+fn is_test_definition(def: &AstDefinition) -> bool {
+    looks_like_test_file(&def.cpath)
+        || def.official_path.iter().any(|p| p == "tests" || p == "test" || p.starts_with("test_") || p.starts_with("Test"))
+}
+
+// Entry points and dunders are called by the runtime, tests rarely call them by name
+fn worth_testing(def: &AstDefinition) -> bool {
+    let name = def.name();
+    def.symbol_type == SymbolType::FunctionDeclaration
+        && !is_test_definition(def)
+        && name != "main"
+        && !(name.starts_with("__") && name.ends_with("__"))
+}
+
+// Heuristic: a function counts as tested when something in a test calls it, the test is not run
+pub async fn coverage_gaps(ast_index: Arc<AMutex<AstDB>>, cpath: &String) -> CoverageGaps {
+    let mut gaps = CoverageGaps { tested: vec![], untested: vec![] };
+    let mut defs = doc_defs(ast_index.clone(), cpath).await;
+    defs.sort_by_key(|d| d.full_line1());
+    for def in defs.into_iter().filter(|d| worth_testing(d)) {
+        let used_in = usages(ast_index.clone(), def.path(), USAGES_PER_FUNCTION).await;
+        if used_in.iter().any(|(usedin, _)| is_test_definition(usedin)) {
+            gaps.tested.push(def);
+        } else {
+            gaps.untested.push(def);
+        }
+    }
+    gaps
+}
+
+pub fn format_coverage_gaps(path: &str, gaps: &CoverageGaps) -> String {
+    let total = gaps.tested.len() + gaps.untested.len();
+    if total == 0 {
+        return format!("No functions in {}, or the file isn't indexed yet\n", path);
+    }
+    if gaps.untested.is_empty() {
+        return format!("All {} functions in {} are called from tests\n", total, path);
+    }
+    let mut report = format!("{} of {} functions in {} are not called from any test:\n", gaps.untested.len(), total, path);
+    for def in gaps.untested.iter() {
+        report.push_str(&format!("  {} lines {}-{}\n", def.path_drop0(), def.full_line1(), def.full_line2()));
+    }
+    report.push_str("Found by looking for calls in test files, indirect coverage through other functions doesn't count.\n");
+    report
+}
+
+#[async_trait]
+impl Tool for ToolCoverageGaps {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let path = match args.get("path") {
+            Some(Value::String(s)) if !s.trim().is_empty() => s.trim().to_string(),
+            Some(v) if !v.is_string() => return Err(format!("argument `path` is not a string: {:?}", v)),
+            _ => return Err("Missing argument `path`".to_string()),
+        };
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let candidates = file_repair_candidates(gcx.clone(), &path, 10, false).await;
+        let file_path = return_one_candidate_or_a_good_error(gcx.clone(), &path, &candidates, &get_project_dirs(gcx.clone()).await, false).await?;
+        if looks_like_test_file(&file_path) {
+            return Err(format!("{} is a test file itself, call it for the code under test", file_path));
+        }
+        let ast_service = gcx.read().await.ast_service.clone().ok_or("AST is turned off".to_string())?;
+        crate::ast::ast_indexer_thread::ast_indexer_block_until_finished(ast_service.clone(), 20_000, true).await;
+        let ast_index = ast_service.lock().await.ast_index.clone();
+        let gaps = coverage_gaps(ast_index, &file_path).await;
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(format_coverage_gaps(&file_path, &gaps)),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec!["ast".to_string()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ast_db::{ast_index_init, connect_usages, connect_usages_look_if_full_reset_needed, doc_add, flush_sled_batch};
+    use crate::ast::ast_structs::AstErrorStats;
+
+    const PRICING_PY: &str = "def compute_total(items):\n    return sum(items)\n\n\ndef apply_discount(total, percent):\n    return total * (100 - percent) / 100\n";
+    const TEST_PRICING_PY: &str = "from pricing import compute_total\n\n\ndef test_compute_total():\n    assert compute_total([1, 2]) == 3\n";
+
+    #[tokio::test]
+    async fn test_untested_function_flagged() {
+        let ast_index = ast_index_init("".to_string(), 10, false).await;
+        let lib_path = "coverage_fixture/pricing.py".to_string();
+        let test_path = "coverage_fixture/test_pricing.py".to_string();
+        let mut errstats = AstErrorStats::default();
+        doc_add(ast_index.clone(), &lib_path, &PRICING_PY.to_string(), &mut errstats).await.unwrap();
+        doc_add(ast_index.clone(), &test_path, &TEST_PRICING_PY.to_string(), &mut errstats).await.unwrap();
+        let mut ucx = connect_usages_look_if_full_reset_needed(ast_index.clone()).await;
+        while connect_usages(ast_index.clone(), &mut ucx).await {}
+        flush_sled_batch(ast_index.clone(), 0).await;
+
+        let gaps = coverage_gaps(ast_index.clone(), &lib_path).await;
+        assert_eq!(gaps.tested.iter().map(|d| d.name()).collect::<Vec<_>>(), vec!["compute_total"]);
+        assert_eq!(gaps.untested.iter().map(|d| d.name()).collect::<Vec<_>>(), vec!["apply_discount"]);
+
+        let report = format_coverage_gaps(&lib_path, &gaps);
+        assert!(report.starts_with("1 of 2 functions in coverage_fixture/pricing.py are not called from any test:\n"), "{}", report);
+        assert!(report.contains("apply_discount lines 5-6"), "{}", report);
+        assert!(!report.contains("compute_total"), "{}", report);
+    }
+}
@@ -0,0 +1,561 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::at_commands::at_file::{file_repair_candidates, return_one_candidate_or_a_good_error};
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::{correct_to_nearest_dir_path, get_project_dirs, to_pathbuf_normalize};
+use crate::files_in_workspace::get_file_text_from_memory_or_disk;
+use crate::privacy::{check_web_domain_allowed, load_privacy_if_needed, PrivacySettings};
+use crate::tools::tools_description::Tool;
+
+
+const MANIFEST_NAMES: [&str; 4] = ["Cargo.toml", "package.json", "pyproject.toml", "requirements.txt"];
+const REGISTRY_MAX_QUERIES: usize = 60;
+const REGISTRY_CONCURRENCY: usize = 8;
+const REGISTRY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ecosystem {
+    Cargo,
+    Npm,
+    PyPI,
+}
+
+impl Ecosystem {
+    fn registry_name(&self) -> &'static str {
+        match self {
+            Ecosystem::Cargo => "crates.io",
+            Ecosystem::Npm => "npm",
+            Ecosystem::PyPI => "PyPI",
+        }
+    }
+
+    fn registry_url(&self, name: &str) -> String {
+        match self {
+            Ecosystem::Cargo => format!("https://crates.io/api/v1/crates/{}", name),
+            Ecosystem::Npm => format!("https://registry.npmjs.org/{}/latest", name.replace('/', "%2F")),
+            Ecosystem::PyPI => format!("https://pypi.org/pypi/{}/json", name),
+        }
+    }
+
+    fn latest_from_registry_json(&self, json: &Value) -> Option<String> {
+        let v = match self {
+            Ecosystem::Cargo => json["crate"]["max_stable_version"].as_str().or(json["crate"]["max_version"].as_str()),
+            Ecosystem::Npm => json["version"].as_str(),
+            Ecosystem::PyPI => json["info"]["version"].as_str(),
+        };
+        v.map(|v| v.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub name: String,
+    pub requirement: String,   // as written, or "path", "git", "workspace" when there's no version to compare
+    pub section: String,       // dependencies, dev-dependencies, ...
+}
+
+#[async_trait]
+pub trait RegistryFetcher: Send + Sync {
+    async fn fetch_json(&self, url: &str) -> Result<Value, String>;
+}
+
+pub struct HttpRegistryFetcher {
+    pub http_client: reqwest::Client,
+    pub privacy_settings: Arc<PrivacySettings>,
+}
+
+#[async_trait]
+impl RegistryFetcher for HttpRegistryFetcher {
+    async fn fetch_json(&self, url: &str) -> Result<Value, String> {
+        check_web_domain_allowed(self.privacy_settings.clone(), url)?;
+        // crates.io refuses requests without a user agent
+        let response = self.http_client.get(url)
+            .timeout(REGISTRY_TIMEOUT)
+            .header("User-Agent", "refact-lsp (dependency versions tool)")
+            .send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("status {}", response.status()));
+        }
+        response.json::<Value>().await.map_err(|e| e.to_string())
+    }
+}
+
+pub struct ToolDeps;
+
+fn dep(name: &str, requirement: &str, section: &str) -> Dependency {
+    Dependency { name: name.to_string(), requirement: requirement.to_string(), section: section.to_string() }
+}
+
+fn strip_toml_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn quoted_strings(s: &str) -> Vec<String> {
+    let mut result = vec![];
+    let mut rest = s;
+    while let Some(start) = rest.find(['"', '\'']) {
+        let quote = &rest[start..start + 1];
+        let after = &rest[start + 1..];
+        match after.find(quote) {
+            Some(end) => {
+                result.push(after[..end].to_string());
+                rest = &after[end + 1..];
+            }
+            None => break,
+        }
+    }
+    result
+}
+
+fn toml_string(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.starts_with('"') || value.starts_with('\'') {
+        return quoted_strings(value).into_iter().next();
+    }
+    None
+}
+
+// `{ version = "1.0", features = ["derive"] }` -> value of the key, good enough for the flat tables manifests use
+fn inline_table_get(table: &str, key: &str) -> Option<String> {
+    table.trim().trim_start_matches('{').trim_end_matches('}').split(',')
+        .filter_map(|part| part.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .and_then(|(_, v)| toml_string(v))
+}
+
+fn toml_requirement(value: &str) -> String {
+    if let Some(version) = toml_string(value) {
+        return version;
+    }
+    if let Some(version) = inline_table_get(value, "version") {
+        return version;
+    }
+    for key in ["path", "git", "workspace"] {
+        if value.contains(key) && value.split(',').any(|p| p.split_once('=').map(|(k, _)| k.trim().trim_start_matches('{').trim() == key).unwrap_or(false)) {
+            return key.to_string();
+        }
+    }
+    String::new()
+}
+
+fn parse_cargo_toml(text: &str) -> Vec<Dependency> {
+    let mut result: Vec<Dependency> = vec![];
+    let mut section = String::new();        // a [*dependencies] table we're in, or empty
+    let mut table_dep: Option<usize> = None; // [dependencies.serde] style, index in result
+    for line in text.lines() {
+        let line = strip_toml_comment(line).trim();
+        if line.starts_with('[') {
+            let header = line.trim_matches(['[', ']']).trim();
+            section.clear();
+            table_dep = None;
+            // [dependencies], [dev-dependencies], [workspace.dependencies], [target.'cfg(unix)'.dependencies]
+            let last = header.rsplit('.').next().unwrap_or("");
+            if last.ends_with("dependencies") {
+                section = last.to_string();
+            } else if let Some((prefix, name)) = header.rsplit_once('.') {
+                let prefix_last = prefix.rsplit('.').next().unwrap_or("");
+                if prefix_last.ends_with("dependencies") {
+                    result.push(dep(name.trim_matches(['"', '\'']), "", prefix_last));
+                    table_dep = Some(result.len() - 1);
+                }
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let key = key.trim().trim_matches(['"', '\'']);
+        if let Some(i) = table_dep {
+            if ["version", "path", "git", "workspace"].contains(&key) && result[i].requirement.is_empty() {
+                result[i].requirement = if key == "version" { toml_string(value).unwrap_or_default() } else { key.to_string() };
+            }
+        } else if !section.is_empty() {
+            result.push(dep(key, &toml_requirement(value), &section));
+        }
+    }
+    result
+}
+
+// PEP 508: `requests[security] >= 2.8.1, < 3 ; python_version < "3.8"`
+fn parse_pep508(spec: &str, section: &str) -> Option<Dependency> {
+    let spec = spec.split(';').next().unwrap_or("").trim();
+    let name_end = spec.find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_' || c == '.')).unwrap_or(spec.len());
+    let name = &spec[..name_end];
+    if name.is_empty() {
+        return None;
+    }
+    let mut rest = spec[name_end..].trim();
+    if rest.starts_with('[') {
+        rest = rest.split_once(']').map(|(_, r)| r.trim()).unwrap_or("");
+    }
+    let requirement = if rest.starts_with('@') { "url" } else { rest.trim_matches(['(', ')']).trim() };
+    Some(dep(name, requirement, section))
+}
+
+fn parse_requirements_txt(text: &str) -> Vec<Dependency> {
+    text.lines()
+        .map(|l| l.split(" #").next().unwrap_or("").trim())
+        // -r other.txt, -e ., --index-url, direct links
+        .filter(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with('-') && !l.contains("://"))
+        .filter_map(|l| parse_pep508(l, "requirements"))
+        .collect()
+}
+
+fn parse_pyproject_toml(text: &str) -> Vec<Dependency> {
+    let mut result = vec![];
+    let mut header = String::new();
+    let mut array: Option<(String, String)> = None;  // (section, collected text) of a multiline array
+    for line in text.lines() {
+        let line = strip_toml_comment(line).trim();
+        if let Some((section, collected)) = array.as_mut() {
+            collected.push_str(line);
+            if line.contains(']') {
+                let section = section.clone();
+                result.extend(quoted_strings(collected).iter().filter_map(|s| parse_pep508(s, &section)));
+                array = None;
+            }
+            continue;
+        }
+        if line.starts_with('[') {
+            header = line.trim_matches(['[', ']']).trim().to_string();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let (key, value) = (key.trim().trim_matches(['"', '\'']), value.trim());
+        let section = match header.as_str() {
+            "project" if key == "dependencies" => "dependencies".to_string(),
+            "project.optional-dependencies" => format!("optional-dependencies.{}", key),
+            h if h.starts_with("tool.poetry") && h.ends_with("dependencies") => {
+                // [tool.poetry.dependencies] lists the interpreter too
+                if key != "python" {
+                    result.push(dep(key, &toml_requirement(value), h.trim_start_matches("tool.poetry.")));
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        if value.starts_with('[') && !value.contains(']') {
+            array = Some((section, value.to_string()));
+        } else {
+            result.extend(quoted_strings(value).iter().filter_map(|s| parse_pep508(s, &section)));
+        }
+    }
+    result
+}
+
+fn parse_package_json(text: &str) -> Result<Vec<Dependency>, String> {
+    let json: Value = serde_json::from_str(text).map_err(|e| format!("cannot parse package.json: {}", e))?;
+    let mut result = vec![];
+    for section in ["dependencies", "devDependencies", "peerDependencies", "optionalDependencies"] {
+        if let Some(deps) = json.get(section).and_then(|d| d.as_object()) {
+            for (name, version) in deps {
+                result.push(dep(name, version.as_str().unwrap_or(""), section));
+            }
+        }
+    }
+    Ok(result)
+}
+
+pub fn parse_manifest(file_name: &str, text: &str) -> Result<(Ecosystem, Vec<Dependency>), String> {
+    match file_name {
+        "Cargo.toml" => Ok((Ecosystem::Cargo, parse_cargo_toml(text))),
+        "package.json" => Ok((Ecosystem::Npm, parse_package_json(text)?)),
+        "pyproject.toml" => Ok((Ecosystem::PyPI, parse_pyproject_toml(text))),
+        f if f.ends_with(".txt") && f.starts_with("requirements") => Ok((Ecosystem::PyPI, parse_requirements_txt(text))),
+        _ => Err(format!("{} is not a manifest, supported: {}", file_name, MANIFEST_NAMES.join(", "))),
+    }
+}
+
+// "^1.2", ">=2.8.1,<3", "~=3.0" -> the first version mentioned, that's the lowest one the requirement allows
+fn version_numbers(s: &str) -> Vec<u64> {
+    let start = match s.find(|c: char| c.is_ascii_digit()) {
+        Some(start) => start,
+        None => return vec![],
+    };
+    s[start..].split(|c: char| !c.is_ascii_digit() && c != '.').next().unwrap_or("")
+        .split('.')
+        .map_while(|x| x.parse::<u64>().ok())
+        .collect()
+}
+
+// PyPI has versions like "2.0" or "2024.1" that semver won't take as they are
+fn lenient_version(s: &str) -> Option<semver::Version> {
+    if let Ok(v) = semver::Version::parse(s.trim()) {
+        return Some(v);
+    }
+    match version_numbers(s)[..] {
+        [major] => Some(semver::Version::new(major, 0, 0)),
+        [major, minor] => Some(semver::Version::new(major, minor, 0)),
+        [major, minor, patch, ..] => Some(semver::Version::new(major, minor, patch)),
+        [] => None,
+    }
+}
+
+// Outdated means the latest version is outside of the requirement: "^1.2" and ">=0.27" already take 1.9 and 0.27.2.
+// Requirements semver can't parse (PyPI "~=3.0", "==1.2") are only as precise as they are written.
+// None when there's nothing to compare, like "*", "latest" or a git dependency
+pub fn is_outdated(requirement: &str, latest: &str) -> Option<bool> {
+    if let (Ok(req), Some(latest)) = (semver::VersionReq::parse(requirement.trim()), lenient_version(latest)) {
+        if !version_numbers(requirement).is_empty() {
+            return Some(!req.matches(&latest));
+        }
+    }
+    let declared = version_numbers(requirement);
+    let latest = version_numbers(latest);
+    if declared.is_empty() || latest.is_empty() || !requirement.chars().next().map(|c| c.is_ascii_digit() || "^~=<>v ".contains(c)).unwrap_or(false) {
+        return None;
+    }
+    let n = declared.len().min(latest.len());
+    Some(declared[..n] < latest[..n])
+}
+
+pub async fn latest_versions(
+    fetcher: &dyn RegistryFetcher,
+    ecosystem: Ecosystem,
+    deps: &Vec<Dependency>,
+) -> HashMap<String, Result<String, String>> {
+    let names: Vec<String> = deps.iter()
+        .filter(|d| !version_numbers(&d.requirement).is_empty())
+        .map(|d| d.name.clone())
+        .take(REGISTRY_MAX_QUERIES)
+        .collect();
+    futures::stream::iter(names)
+        .map(|name| async move {
+            let latest = fetcher.fetch_json(&ecosystem.registry_url(&name)).await.and_then(|json| {
+                ecosystem.latest_from_registry_json(&json).ok_or("no version in the registry response".to_string())
+            });
+            (name, latest)
+        })
+        .buffer_unordered(REGISTRY_CONCURRENCY)
+        .collect::<HashMap<_, _>>()
+        .await
+}
+
+pub fn format_deps_report(
+    manifest: &str,
+    ecosystem: Ecosystem,
+    deps: &Vec<Dependency>,
+    latest: Option<&HashMap<String, Result<String, String>>>,
+) -> String {
+    let outdated_n = deps.iter()
+        .filter(|d| latest.and_then(|l| l.get(&d.name)).and_then(|v| v.as_ref().ok()).and_then(|v| is_outdated(&d.requirement, v)) == Some(true))
+        .count();
+    let mut report = format!("{}: {} dependencies from {}", manifest, deps.len(), ecosystem.registry_name());
+    if latest.is_some() {
+        report.push_str(&format!(", {} outdated", outdated_n));
+    }
+    report.push('\n');
+    let mut section = "";
+    for d in deps.iter() {
+        if d.section != section {
+            section = &d.section;
+            report.push_str(&format!("{}:\n", section));
+        }
+        let requirement = if d.requirement.is_empty() { "*" } else { d.requirement.as_str() };
+        report.push_str(&format!("  {} {}", d.name, requirement));
+        match latest.and_then(|l| l.get(&d.name)) {
+            Some(Ok(v)) => match is_outdated(&d.requirement, v) {
+                Some(true) => report.push_str(&format!(" -> {} OUTDATED", v)),
+                _ => report.push_str(&format!(" (latest {})", v)),
+            },
+            Some(Err(e)) => report.push_str(&format!(" (latest unknown: {})", e)),
+            None => {}
+        }
+        report.push('\n');
+    }
+    report
+}
+
+fn get_bool_arg(args: &HashMap<String, Value>, name: &str) -> Result<bool, String> {
+    match args.get(name) {
+        Some(Value::Bool(b)) => Ok(*b),
+        Some(Value::String(s)) => match s.trim().to_lowercase().as_str() {
+            "true" | "yes" | "1" => Ok(true),
+            "false" | "no" | "0" | "" => Ok(false),
+            _ => Err(format!("argument `{}` should be true or false, got {:?}", name, s)),
+        },
+        None | Some(Value::Null) => Ok(false),
+        Some(v) => Err(format!("argument `{}` should be true or false, got {:?}", name, v)),
+    }
+}
+
+#[async_trait]
+impl Tool for ToolDeps {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let path = match args.get("path") {
+            Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim().to_string()),
+            Some(Value::String(_)) | None => None,
+            Some(v) => return Err(format!("argument `path` is not a string: {:?}", v)),
+        };
+        let check_latest = get_bool_arg(args, "check_latest")?;
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let project_dirs = get_project_dirs(gcx.clone()).await;
+        // a manifest itself, or a directory to look for manifests in
+        let manifests: Vec<PathBuf> = {
+            let roots = match &path {
+                Some(path) => {
+                    let file_candidates = file_repair_candidates(gcx.clone(), path, 10, false).await;
+                    if !file_candidates.is_empty() {
+                        let file = return_one_candidate_or_a_good_error(gcx.clone(), path, &file_candidates, &project_dirs, false).await?;
+                        vec![PathBuf::from(file)]
+                    } else {
+                        let dir_candidates = correct_to_nearest_dir_path(gcx.clone(), path, false, 10).await;
+                        let dir = return_one_candidate_or_a_good_error(gcx.clone(), path, &dir_candidates, &project_dirs, true).await?;
+                        vec![to_pathbuf_normalize(&dir)]
+                    }
+                }
+                None => project_dirs.iter().take(1).cloned().collect(),
+            };
+            roots.into_iter().flat_map(|p| {
+                if p.is_dir() {
+                    MANIFEST_NAMES.iter().map(|m| p.join(m)).filter(|m| m.is_file()).collect::<Vec<_>>()
+                } else {
+                    vec![p]
+                }
+            }).collect()
+        };
+        if manifests.is_empty() {
+            return Err(format!("no {} found, give the path to a manifest or to the directory that has one", MANIFEST_NAMES.join(", ")));
+        }
+
+        let fetcher = HttpRegistryFetcher {
+            http_client: gcx.read().await.http_client.clone(),
+            privacy_settings: load_privacy_if_needed(gcx.clone()).await,
+        };
+        let mut reports = vec![];
+        for manifest in manifests.iter() {
+            let file_name = manifest.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+            // checks privacy
+            let text = get_file_text_from_memory_or_disk(gcx.clone(), manifest).await?;
+            let (ecosystem, deps) = parse_manifest(&file_name, &text)?;
+            let latest = if check_latest { Some(latest_versions(&fetcher, ecosystem, &deps).await) } else { None };
+            reports.push(format_deps_report(&manifest_display(manifest, &project_dirs), ecosystem, &deps, latest.as_ref()));
+        }
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(reports.join("\n")),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+fn manifest_display(manifest: &Path, project_dirs: &Vec<PathBuf>) -> String {
+    project_dirs.iter()
+        .find_map(|d| manifest.strip_prefix(d).ok())
+        .unwrap_or(manifest)
+        .to_string_lossy()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockRegistry {
+        responses: HashMap<String, Value>,
+    }
+
+    #[async_trait]
+    impl RegistryFetcher for MockRegistry {
+        async fn fetch_json(&self, url: &str) -> Result<Value, String> {
+            self.responses.get(url).cloned().ok_or("status 404 Not Found".to_string())
+        }
+    }
+
+    const CARGO_TOML: &str = r#"
+[package]
+name = "shop"
+version = "0.3.0"
+
+[dependencies]
+serde = { version = "1.0.100", features = ["derive"] }  # comment
+tokio = "1"
+shop-core = { path = "../core" }
+
+[dependencies.regex]
+version = "1.5"
+default-features = false
+
+[dev-dependencies]
+tempfile = "3.8"
+"#;
+
+    #[tokio::test]
+    async fn test_cargo_manifest_outdated() {
+        let (ecosystem, deps) = parse_manifest("Cargo.toml", CARGO_TOML).unwrap();
+        assert_eq!(ecosystem, Ecosystem::Cargo);
+        assert_eq!(deps, vec![
+            dep("serde", "1.0.100", "dependencies"),
+            dep("tokio", "1", "dependencies"),
+            dep("shop-core", "path", "dependencies"),
+            dep("regex", "1.5", "dependencies"),
+            dep("tempfile", "3.8", "dev-dependencies"),
+        ]);
+
+        let registry = MockRegistry { responses: HashMap::from([
+            ("https://crates.io/api/v1/crates/serde".to_string(), serde_json::json!({"crate": {"max_stable_version": "2.0.1", "max_version": "2.0.1"}})),
+            ("https://crates.io/api/v1/crates/tokio".to_string(), serde_json::json!({"crate": {"max_stable_version": "1.40.0"}})),
+            ("https://crates.io/api/v1/crates/regex".to_string(), serde_json::json!({"crate": {"max_stable_version": "1.5.0"}})),
+        ])};
+        let latest = latest_versions(&registry, ecosystem, &deps).await;
+        assert!(!latest.contains_key("shop-core"), "path dependencies are not looked up");
+
+        let report = format_deps_report("Cargo.toml", ecosystem, &deps, Some(&latest));
+        assert!(report.starts_with("Cargo.toml: 5 dependencies from crates.io, 1 outdated\n"), "{}", report);
+        assert!(report.contains("  serde 1.0.100 -> 2.0.1 OUTDATED\n"), "{}", report);
+        assert!(report.contains("  tokio 1 (latest 1.40.0)\n"), "{}", report);
+        assert!(report.contains("  regex 1.5 (latest 1.5.0)\n"), "{}", report);
+        assert!(report.contains("  shop-core path\n"), "{}", report);
+        assert!(report.contains("dev-dependencies:\n  tempfile 3.8 (latest unknown: status 404 Not Found)\n"), "{}", report);
+    }
+
+    #[test]
+    fn test_python_and_npm_manifests() {
+        let (_, deps) = parse_manifest("requirements.txt", "# pinned\nrequests[security]==2.31.0 ; python_version >= '3.8'\n-r dev.txt\nflask>=2.0,<3\n").unwrap();
+        assert_eq!(deps, vec![dep("requests", "==2.31.0", "requirements"), dep("flask", ">=2.0,<3", "requirements")]);
+
+        let pyproject = "[project]\nname = \"shop\"\ndependencies = [\n  \"httpx>=0.27\",\n  \"pydantic~=2.5\",\n]\n\n[project.optional-dependencies]\ndev = [\"pytest>=8\"]\n";
+        let (_, deps) = parse_manifest("pyproject.toml", pyproject).unwrap();
+        assert_eq!(deps, vec![dep("httpx", ">=0.27", "dependencies"), dep("pydantic", "~=2.5", "dependencies"), dep("pytest", ">=8", "optional-dependencies.dev")]);
+
+        let (_, deps) = parse_manifest("package.json", r#"{"dependencies": {"react": "^18.2.0"}, "devDependencies": {"vite": "workspace:*"}}"#).unwrap();
+        assert_eq!(deps, vec![dep("react", "^18.2.0", "dependencies"), dep("vite", "workspace:*", "devDependencies")]);
+        assert_eq!(is_outdated("^18.2.0", "18.3.1"), Some(false));
+        assert_eq!(is_outdated("^18.2.0", "19.0.0"), Some(true));
+        assert_eq!(is_outdated("workspace:*", "5.0.0"), None);
+        assert_eq!(is_outdated(">=0.27", "0.27.2"), Some(false));
+        assert_eq!(is_outdated(">=1.2", "3.0.0"), Some(false));
+        assert_eq!(is_outdated(">=2.8.1,<3", "3.1"), Some(true));
+        assert_eq!(is_outdated("~=3.0", "4.1"), Some(true));
+    }
+}
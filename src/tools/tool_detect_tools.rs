@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::integrations::integr_abstract::IntegrationConfirmation;
+use crate::tools::tools_description::Tool;
+
+
+const KNOWN_TOOLS: &[&str] = &[
+    "python3", "python", "pip", "uv", "poetry",
+    "node", "npm", "yarn", "pnpm", "deno", "bun",
+    "cargo", "rustc", "go", "java", "mvn", "gradle", "dotnet", "gcc", "clang", "make", "cmake",
+    "git", "gh", "docker", "docker-compose", "podman", "kubectl", "helm",
+    "psql", "mysql", "sqlite3", "redis-cli", "mongosh",
+    "rust-analyzer", "pyright", "pylsp", "typescript-language-server", "gopls", "clangd",
+];
+const VERSION_TIMEOUT_MS: u64 = 5000;
+const VERSION_MAX_CHARS: usize = 100;
+
+pub struct ToolDetectTools;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedTool {
+    pub name: String,
+    pub path: Option<PathBuf>,
+    pub version: Option<String>,
+}
+
+async fn version_of(path: &PathBuf) -> Option<String> {
+    let output = tokio::time::timeout(
+        tokio::time::Duration::from_millis(VERSION_TIMEOUT_MS),
+        tokio::process::Command::new(path).arg("--version").stdin(std::process::Stdio::null()).kill_on_drop(true).output(),
+    ).await.ok()?.ok()?;
+    // java and a few others print the version to stderr
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let line = stdout.lines().chain(stderr.lines()).map(|l| l.trim()).find(|l| !l.is_empty())?;
+    Some(line.chars().take(VERSION_MAX_CHARS).collect())
+}
+
+pub async fn detect_tools<F>(names: &Vec<String>, lookup: F) -> Vec<DetectedTool>
+where
+    F: Fn(&str) -> Option<PathBuf>,
+{
+    let found: Vec<(String, Option<PathBuf>)> = names.iter().map(|name| (name.clone(), lookup(name))).collect();
+    let versions = futures::future::join_all(found.iter().map(|(_, path)| async move {
+        match path {
+            Some(path) => version_of(path).await,
+            None => None,
+        }
+    })).await;
+    found.into_iter().zip(versions.into_iter())
+        .map(|((name, path), version)| DetectedTool { name, path, version })
+        .collect()
+}
+
+fn parse_names(args: &HashMap<String, Value>) -> Result<Vec<String>, String> {
+    let mut names: Vec<String> = KNOWN_TOOLS.iter().map(|x| x.to_string()).collect();
+    match args.get("names") {
+        Some(Value::String(s)) => {
+            for name in s.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+                if name.contains(|c: char| c.is_whitespace() || c == '/' || c == '\\') {
+                    return Err(format!("`{}` should be a plain command name", name));
+                }
+                if !names.contains(&name.to_string()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        Some(v) => return Err(format!("argument `names` is not a string: {:?}", v)),
+        None => {}
+    };
+    Ok(names)
+}
+
+pub fn detected_tools_report(detected: &Vec<DetectedTool>) -> String {
+    let mut present = vec![];
+    let mut absent = vec![];
+    for t in detected.iter() {
+        match &t.path {
+            Some(path) => present.push(format!("{} {} {}", t.name, path.display(), t.version.clone().unwrap_or("(version unknown)".to_string()))),
+            None => absent.push(t.name.clone()),
+        }
+    }
+    let mut report = format!("Present:\n{}\n", if present.is_empty() { "none".to_string() } else { present.join("\n") });
+    if !absent.is_empty() {
+        report.push_str(&format!("\nNot found in PATH: {}\n", absent.join(", ")));
+    }
+    report
+}
+
+#[async_trait]
+impl Tool for ToolDetectTools {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        _ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let names = parse_names(args)?;
+        let detected = detect_tools(&names, |name| which::which(name).ok()).await;
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(detected_tools_report(&detected)),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    // every binary found on PATH gets executed with --version, the user sees the list first
+    fn command_to_match_against_confirm_deny(
+        &self,
+        args: &HashMap<String, Value>,
+    ) -> Result<String, String> {
+        Ok(format!("detect_tools {}", parse_names(args)?.join(" ")))
+    }
+
+    fn confirm_deny_rules(&self) -> Option<IntegrationConfirmation> {
+        Some(IntegrationConfirmation {
+            ask_user: vec!["detect_tools*".to_string()],
+            deny: vec![],
+        })
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_detect_tools_present_and_absent() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let fake = dir.path().join("fakedb");
+        std::fs::write(&fake, "#!/bin/sh\necho\necho 'fakedb 1.2.3'\n").unwrap();
+        std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let names = vec!["fakedb".to_string(), "nosuchtool".to_string()];
+        let fake_clone = fake.clone();
+        let detected = detect_tools(&names, move |name| if name == "fakedb" { Some(fake_clone.clone()) } else { None }).await;
+        assert_eq!(detected, vec![
+            DetectedTool { name: "fakedb".to_string(), path: Some(fake.clone()), version: Some("fakedb 1.2.3".to_string()) },
+            DetectedTool { name: "nosuchtool".to_string(), path: None, version: None },
+        ]);
+        let report = detected_tools_report(&detected);
+        assert!(report.contains(&format!("fakedb {} fakedb 1.2.3", fake.display())), "{}", report);
+        assert!(report.contains("Not found in PATH: nosuchtool"), "{}", report);
+
+        let tool = ToolDetectTools;
+        let args = HashMap::from([("names".to_string(), Value::String("fakedb".to_string()))]);
+        let command = tool.command_to_match_against_confirm_deny(&args).unwrap();
+        assert!(command.starts_with("detect_tools python3 ") && command.ends_with(" fakedb"), "{}", command);
+        let verdict = crate::tools::tools_description::match_command_against_rules(&command, tool.confirm_deny_rules());
+        assert!(matches!(verdict.result, crate::tools::tools_description::MatchConfirmDenyResult::CONFIRMATION));
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+use indexmap::IndexMap;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::at_commands::at_file::{file_repair_candidates, return_one_candidate_or_a_good_error};
+use crate::ast::ast_db::doc_defs;
+use crate::ast::ast_structs::AstDefinition;
+use crate::ast::treesitter::structs::SymbolType;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::{correct_to_nearest_dir_path, get_project_dirs, to_pathbuf_normalize};
+use crate::privacy::{check_file_privacy, load_privacy_if_needed, FilePrivacyLevel};
+use crate::tools::tools_description::Tool;
+
+
+const DIAGRAM_MAX_FILES: usize = 50;
+const DIAGRAM_MAX_CLASSES: usize = 60;
+const DIAGRAM_MAX_MEMBERS: usize = 15;
+
+pub struct ToolDiagram;
+
+// Mermaid class names are identifiers, generics and namespaces would break the syntax
+fn mermaid_id(name: &str) -> String {
+    name.chars().map(|c| if c.is_alphanumeric() || c == '_' { c } else { '_' }).collect()
+}
+
+// this_class_derived_from comes from the parser's inherited_types as "py🔎Animal", sometimes with a module in front
+fn base_class_name(derived_from: &str) -> String {
+    let name = derived_from.rsplit('🔎').next().unwrap_or(derived_from);
+    name.rsplit("::").next().unwrap_or(name).rsplit('.').next().unwrap_or(name).to_string()
+}
+
+struct DiagramClass {
+    fields: Vec<String>,
+    methods: Vec<String>,
+    bases: Vec<String>,
+}
+
+pub fn mermaid_class_diagram(defs: &Vec<Arc<AstDefinition>>) -> String {
+    let mut classes: IndexMap<String, DiagramClass> = IndexMap::new();
+    let mut sorted = defs.clone();
+    sorted.sort_by(|a, b| (&a.cpath, a.full_line1()).cmp(&(&b.cpath, b.full_line1())));
+    for class_def in sorted.iter().filter(|d| d.symbol_type == SymbolType::StructDeclaration) {
+        if classes.len() >= DIAGRAM_MAX_CLASSES {
+            break;
+        }
+        let class = classes.entry(mermaid_id(&class_def.name())).or_insert(DiagramClass { fields: vec![], methods: vec![], bases: vec![] });
+        for base in class_def.this_class_derived_from.iter().map(|b| mermaid_id(&base_class_name(b))) {
+            if !base.is_empty() && !class.bases.contains(&base) {
+                class.bases.push(base);
+            }
+        }
+        let members = sorted.iter().filter(|d| {
+            d.cpath == class_def.cpath && d.official_path.len() == class_def.official_path.len() + 1 && d.official_path.starts_with(&class_def.official_path)
+        });
+        for member in members {
+            let name = member.name();
+            match member.symbol_type {
+                SymbolType::FunctionDeclaration if !class.methods.contains(&name) => class.methods.push(name),
+                SymbolType::ClassFieldDeclaration if !class.fields.contains(&name) => class.fields.push(name),
+                _ => {}
+            }
+        }
+    }
+
+    let mut lines = vec!["classDiagram".to_string()];
+    for (name, class) in classes.iter() {
+        if class.fields.is_empty() && class.methods.is_empty() {
+            lines.push(format!("    class {}", name));
+            continue;
+        }
+        lines.push(format!("    class {} {{", name));
+        let members: Vec<String> = class.fields.iter().map(|f| format!("+{}", f))
+            .chain(class.methods.iter().map(|m| format!("+{}()", m)))
+            .collect();
+        for m in members.iter().take(DIAGRAM_MAX_MEMBERS) {
+            lines.push(format!("        {}", m));
+        }
+        if members.len() > DIAGRAM_MAX_MEMBERS {
+            lines.push(format!("        +...{} more", members.len() - DIAGRAM_MAX_MEMBERS));
+        }
+        lines.push("    }".to_string());
+    }
+    // bases outside of the given files still get an edge, mermaid draws them as empty boxes
+    for (name, class) in classes.iter() {
+        for base in class.bases.iter() {
+            lines.push(format!("    {} <|-- {}", base, name));
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+async fn files_for_diagram(
+    gcx: Arc<tokio::sync::RwLock<crate::global_context::GlobalContext>>,
+    paths: &Vec<String>,
+) -> Result<Vec<String>, String> {
+    let project_dirs = get_project_dirs(gcx.clone()).await;
+    let mut files = vec![];
+    for path in paths {
+        let candidates = file_repair_candidates(gcx.clone(), path, 10, false).await;
+        if !candidates.is_empty() {
+            files.push(return_one_candidate_or_a_good_error(gcx.clone(), path, &candidates, &project_dirs, false).await?);
+            continue;
+        }
+        // a directory means a module, all the workspace files under it
+        let dir_candidates = correct_to_nearest_dir_path(gcx.clone(), path, false, 10).await;
+        let dir = to_pathbuf_normalize(&return_one_candidate_or_a_good_error(gcx.clone(), path, &dir_candidates, &project_dirs, true).await?);
+        let workspace_files = gcx.read().await.documents_state.workspace_files.lock().unwrap().clone();
+        let mut in_dir: Vec<String> = workspace_files.iter()
+            .filter(|f| f.starts_with(&dir))
+            .map(|f| f.to_string_lossy().to_string())
+            .collect();
+        if in_dir.is_empty() {
+            return Err(format!("no indexed files in {}", dir.display()));
+        }
+        in_dir.sort();
+        files.extend(in_dir);
+    }
+    // the same file can come from a path and a directory, the first mention decides the order in the diagram
+    let mut seen = HashSet::new();
+    files.retain(|f| seen.insert(f.clone()));
+    let privacy = load_privacy_if_needed(gcx.clone()).await;
+    files.retain(|f| check_file_privacy(privacy.clone(), &PathBuf::from(f), &FilePrivacyLevel::OnlySendToServersIControl).is_ok());
+    if files.len() > DIAGRAM_MAX_FILES {
+        return Err(format!("{} files is too many for one diagram, max is {}, give a narrower directory or a list of files", files.len(), DIAGRAM_MAX_FILES));
+    }
+    Ok(files)
+}
+
+#[async_trait]
+impl Tool for ToolDiagram {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let paths: Vec<String> = match args.get("paths") {
+            Some(Value::String(s)) => s.split(',').map(|p| p.trim().trim_end_matches(&['/', '\\'][..]).to_string()).filter(|p| !p.is_empty()).collect(),
+            Some(v) => return Err(format!("argument `paths` is not a string: {:?}", v)),
+            None => vec![],
+        };
+        if paths.is_empty() {
+            return Err("Missing argument `paths`".to_string());
+        }
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let files = files_for_diagram(gcx.clone(), &paths).await?;
+        let ast_service = gcx.read().await.ast_service.clone().ok_or("AST is turned off".to_string())?;
+        crate::ast::ast_indexer_thread::ast_indexer_block_until_finished(ast_service.clone(), 20_000, true).await;
+        let ast_index = ast_service.lock().await.ast_index.clone();
+        let mut defs = vec![];
+        for f in files.iter() {
+            defs.extend(doc_defs(ast_index.clone(), f).await);
+        }
+        let report = if defs.iter().any(|d| d.symbol_type == SymbolType::StructDeclaration) {
+            format!("Mermaid class diagram for {} files, paste it into a ```mermaid block:\n\n{}", files.len(), mermaid_class_diagram(&defs))
+        } else {
+            format!("No classes in {}, or the files aren't indexed yet", paths.join(", "))
+        };
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec!["ast".to_string()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ast_db::{ast_index_init, doc_add, flush_sled_batch};
+    use crate::ast::ast_structs::AstErrorStats;
+
+    const ANIMALS_PY: &str = "class Animal:\n    def __init__(self, name):\n        self.name = name\n\n    def speak(self):\n        return '...'\n\n\nclass Dog(Animal):\n    def speak(self):\n        return 'woof'\n\n    def fetch(self, thing):\n        return thing\n";
+
+    #[tokio::test]
+    async fn test_diagram_has_inheritance_edge() {
+        let ast_index = ast_index_init("".to_string(), 10, false).await;
+        let cpath = "diagram_fixture/animals.py".to_string();
+        let mut errstats = AstErrorStats::default();
+        doc_add(ast_index.clone(), &cpath, &ANIMALS_PY.to_string(), &mut errstats).await.unwrap();
+        flush_sled_batch(ast_index.clone(), 0).await;
+
+        let diagram = mermaid_class_diagram(&doc_defs(ast_index.clone(), &cpath).await);
+        assert!(diagram.starts_with("classDiagram\n"), "{}", diagram);
+        assert!(diagram.contains("    Animal <|-- Dog\n"), "{}", diagram);
+        assert!(diagram.contains("    class Dog {\n"), "{}", diagram);
+        assert!(diagram.contains("        +fetch()\n"), "{}", diagram);
+        assert!(diagram.find("class Animal").unwrap() < diagram.find("class Dog").unwrap(), "{}", diagram);
+
+        assert_eq!(base_class_name("py🔎models.base.Model"), "Model");
+        assert_eq!(base_class_name("cpp🔎std::exception"), "exception");
+        assert_eq!(mermaid_id("List<String>"), "List_String_");
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::at_commands::at_file::return_one_candidate_or_a_good_error;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::file_filter::{BLACKLISTED_DIRS, SOURCE_FILE_EXTENSIONS};
+use crate::files_correction::{correct_to_nearest_dir_path, get_project_dirs, to_pathbuf_normalize};
+use crate::privacy::{check_file_privacy, load_privacy_if_needed, FilePrivacyLevel, PrivacySettings};
+use crate::tools::tools_description::Tool;
+
+
+const OVERVIEW_DEFAULT_DEPTH: usize = 2;
+const OVERVIEW_MAX_DEPTH: usize = 5;
+const OVERVIEW_MAX_ENTRIES: usize = 200;
+const PURPOSE_MAX_CHARS: usize = 100;
+const HEAD_BYTES: u64 = 4096;   // the leading comment is at the top, no need to read the whole file
+
+pub struct ToolDirOverview;
+
+// Lines that start almost every file and say nothing about what the file does
+fn is_boilerplate(line: &str) -> bool {
+    let lower = line.to_lowercase();
+    line.starts_with("#!") || lower.contains("copyright") || lower.contains("spdx-license") || lower.contains("-*- coding")
+        || lower.starts_with("eslint-") || lower.starts_with("@ts-") || lower.starts_with("pylint:")
+}
+
+fn one_line(s: &str) -> Option<String> {
+    let s = s.trim().trim_end_matches("*/").trim();
+    if s.is_empty() || is_boilerplate(s) {
+        return None;
+    }
+    let mut result: String = s.chars().take(PURPOSE_MAX_CHARS).collect();
+    if s.chars().count() > PURPOSE_MAX_CHARS {
+        result.push_str("...");
+    }
+    Some(result)
+}
+
+// The first meaningful line of a docstring or a comment at the top of the file, before any code
+pub fn leading_comment(text: &str) -> Option<String> {
+    let mut lines = text.lines().map(|l| l.trim()).peekable();
+    while let Some(line) = lines.next() {
+        if line.is_empty() || is_boilerplate(line) {
+            continue;
+        }
+        for quote in ["\"\"\"", "'''"] {
+            if let Some(rest) = line.strip_prefix(quote).or_else(|| line.strip_prefix(&format!("r{}", quote))) {
+                if let Some(first) = one_line(rest.split(quote).next().unwrap_or("")) {
+                    return Some(first);
+                }
+                // """\n    Text on the next line
+                while let Some(next) = lines.next() {
+                    let end = next.contains(quote);
+                    if let Some(first) = one_line(next.split(quote).next().unwrap_or("")) {
+                        return Some(first);
+                    }
+                    if end {
+                        break;
+                    }
+                }
+                return None;
+            }
+        }
+        if line.starts_with("/*") {
+            let mut block = line.trim_start_matches('/').trim_start_matches('*').to_string();
+            loop {
+                let end = block.contains("*/");
+                if let Some(first) = one_line(block.split("*/").next().unwrap_or("")) {
+                    return Some(first);
+                }
+                if end {
+                    break;
+                }
+                match lines.next() {
+                    Some(next) => block = next.trim_start_matches('*').to_string(),
+                    None => return None,
+                }
+            }
+            continue;  // the block was a license or empty, look further
+        }
+        let comment = ["//!", "///", "//", "#", "--", ";;", "%"].iter().find_map(|p| line.strip_prefix(p));
+        match comment {
+            Some(c) => {
+                if let Some(first) = one_line(c.trim_start_matches(['/', '!', '#', '-'])) {
+                    return Some(first);
+                }
+            }
+            None => return None,  // code before any comment
+        }
+    }
+    None
+}
+
+fn is_source_file(path: &Path) -> bool {
+    let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+    SOURCE_FILE_EXTENSIONS.contains(&ext.as_str()) || SOURCE_FILE_EXTENSIONS.contains(&name.as_str())
+}
+
+fn file_purpose(path: &Path) -> Option<String> {
+    if !is_source_file(path) {
+        return None;
+    }
+    let mut head = vec![];
+    std::fs::File::open(path).ok()?.take(HEAD_BYTES).read_to_end(&mut head).ok()?;
+    leading_comment(&String::from_utf8_lossy(&head))
+}
+
+struct Walker {
+    privacy: Arc<PrivacySettings>,
+    max_depth: usize,
+    entries_left: usize,
+    skipped: usize,
+    out: String,
+}
+
+impl Walker {
+    fn walk(&mut self, dir: &Path, depth: usize) {
+        let mut children: Vec<PathBuf> = match std::fs::read_dir(dir) {
+            Ok(rd) => rd.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+            Err(_) => return,
+        };
+        children.retain(|p| {
+            let name = p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            !name.starts_with('.')
+                && !(p.is_dir() && BLACKLISTED_DIRS.contains(&name.as_str()))
+                && check_file_privacy(self.privacy.clone(), p, &FilePrivacyLevel::AllowToSendAnywhere).is_ok()
+        });
+        // directories first, then files, both alphabetically
+        children.sort_by_key(|p| (!p.is_dir(), p.file_name().map(|n| n.to_string_lossy().to_lowercase())));
+
+        let indent = "  ".repeat(depth);
+        for child in children.iter() {
+            if self.entries_left == 0 {
+                self.skipped += 1;
+                continue;
+            }
+            self.entries_left -= 1;
+            let name = child.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            if child.is_dir() {
+                if depth + 1 < self.max_depth {
+                    self.out.push_str(&format!("{}{}/\n", indent, name));
+                    self.walk(child, depth + 1);
+                } else {
+                    let n = std::fs::read_dir(child).map(|rd| rd.count()).unwrap_or(0);
+                    self.out.push_str(&format!("{}{}/  ({} entries)\n", indent, name, n));
+                }
+            } else {
+                match file_purpose(child) {
+                    Some(purpose) => self.out.push_str(&format!("{}{}  -- {}\n", indent, name, purpose)),
+                    None => self.out.push_str(&format!("{}{}\n", indent, name)),
+                }
+            }
+        }
+    }
+}
+
+pub fn dir_overview(root: &Path, max_depth: usize, max_entries: usize, privacy: Arc<PrivacySettings>) -> String {
+    let mut walker = Walker { privacy, max_depth, entries_left: max_entries, skipped: 0, out: String::new() };
+    walker.out.push_str(&format!("{}/\n", root.display()));
+    walker.walk(root, 1);
+    if walker.skipped > 0 {
+        walker.out.push_str(&format!("...{} more entries not shown, call it for a subdirectory to see them\n", walker.skipped));
+    }
+    walker.out
+}
+
+#[async_trait]
+impl Tool for ToolDirOverview {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let path = match args.get("path") {
+            Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim().trim_end_matches(&['/', '\\'][..]).to_string()),
+            Some(Value::String(_)) | None => None,
+            Some(v) => return Err(format!("argument `path` is not a string: {:?}", v)),
+        };
+        let depth = match args.get("depth") {
+            Some(Value::Number(n)) => n.as_u64().ok_or(format!("argument `depth` is not a positive integer: {}", n))? as usize,
+            Some(Value::String(s)) if !s.trim().is_empty() => s.trim().parse::<usize>().map_err(|_| format!("argument `depth` is not a positive integer: {:?}", s))?,
+            _ => OVERVIEW_DEFAULT_DEPTH,
+        }.clamp(1, OVERVIEW_MAX_DEPTH);
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let project_dirs = get_project_dirs(gcx.clone()).await;
+        let roots = match path {
+            Some(path) => {
+                let dir_candidates = correct_to_nearest_dir_path(gcx.clone(), &path, false, 10).await;
+                let candidate = return_one_candidate_or_a_good_error(gcx.clone(), &path, &dir_candidates, &project_dirs, true).await?;
+                let true_path = to_pathbuf_normalize(&candidate);
+                if !project_dirs.iter().any(|p| true_path.starts_with(p)) && !gcx.read().await.cmdline.inside_container {
+                    return Err(format!("'{}' is not within the project directories", path));
+                }
+                vec![true_path]
+            }
+            None => project_dirs,
+        };
+        if roots.is_empty() {
+            return Err("no project directories, open a folder in the IDE first".to_string());
+        }
+
+        let privacy = load_privacy_if_needed(gcx.clone()).await;
+        let entries_each = OVERVIEW_MAX_ENTRIES / roots.len();
+        let report = roots.iter()
+            .map(|root| dir_overview(root, depth, entries_each.max(1), privacy.clone()))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::privacy::FilePrivacySettings;
+
+    #[test]
+    fn test_overview_of_fixture_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let files = [
+            ("main.py", "#!/usr/bin/env python3\n# Entry point, starts the order service\nimport api\n"),
+            ("api/handlers.py", "\"\"\"\nHTTP handlers for orders and refunds.\n\"\"\"\nimport db\n"),
+            ("api/deep/more/hidden_by_depth.py", "# too deep\n"),
+            ("db.rs", "// Copyright 2024 Example Inc.\n//! Connection pool and queries\nuse std::sync::Arc;\n"),
+            ("README.md", "Order service\n"),
+            ("node_modules/left-pad/index.js", "// not interesting\n"),
+            ("secret_dir/keys.py", "# do not show\n"),
+        ];
+        for (name, text) in files.iter() {
+            let path = root.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        let privacy = Arc::new(PrivacySettings {
+            privacy_rules: FilePrivacySettings {
+                only_send_to_servers_I_control: vec![],
+                blocked: vec!["*/secret_dir".to_string(), "*/secret_dir/*".to_string()],
+            },
+            web_allowed_domains: vec![],
+            loaded_ts: 0,
+        });
+
+        let overview = dir_overview(root, 3, OVERVIEW_MAX_ENTRIES, privacy.clone());
+        let lines: Vec<&str> = overview.lines().skip(1).collect();
+        assert_eq!(lines, vec![
+            "  api/",
+            "    deep/  (1 entries)",
+            "    handlers.py  -- HTTP handlers for orders and refunds.",
+            "  db.rs  -- Connection pool and queries",
+            "  main.py  -- Entry point, starts the order service",
+            "  README.md",
+        ], "{}", overview);
+
+        let capped = dir_overview(root, 3, 2, privacy);
+        assert!(capped.ends_with("...4 more entries not shown, call it for a subdirectory to see them\n"), "{}", capped);
+    }
+
+    #[test]
+    fn test_leading_comment() {
+        assert_eq!(leading_comment("/*\n * Parses the config file.\n */\nint x;"), Some("Parses the config file.".to_string()));
+        assert_eq!(leading_comment("import os\n# not at the top\n"), None);
+        assert_eq!(leading_comment("'''Utilities.'''\n"), Some("Utilities.".to_string()));
+    }
+}
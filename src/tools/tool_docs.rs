@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use select::document::Document;
+use select::predicate::{Attr, Name};
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::at_commands::at_web::fetch_html;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::privacy::{check_web_domain_allowed, load_privacy_if_needed, PrivacySettings};
+use crate::tools::tools_description::Tool;
+
+
+const DOCS_MAX_CHARS: usize = 8000;
+const DOCS_CACHE_TTL: Duration = Duration::from_secs(600);
+const RUST_DOCS_ON_RUST_LANG_ORG: [&str; 4] = ["std", "core", "alloc", "proc_macro"];
+
+lazy_static! {
+    static ref DOCS_CACHE: StdMutex<HashMap<String, (Instant, String)>> = StdMutex::new(HashMap::new());
+}
+
+#[async_trait]
+pub trait DocsFetcher: Send + Sync {
+    async fn fetch(&self, url: &str) -> Result<String, String>;
+}
+
+pub struct HttpDocsFetcher {
+    pub privacy_settings: Arc<PrivacySettings>,
+}
+
+#[async_trait]
+impl DocsFetcher for HttpDocsFetcher {
+    async fn fetch(&self, url: &str) -> Result<String, String> {
+        check_web_domain_allowed(self.privacy_settings.clone(), url)?;
+        fetch_html(url, Duration::from_secs(10)).await
+    }
+}
+
+pub struct ToolDocs;
+
+fn html_to_markdown(html: &str) -> Result<String, String> {
+    html2text::config::plain()
+        .string_from_read(html.as_bytes(), 120)
+        .map_err(|_| "unable to convert html to text".to_string())
+}
+
+fn main_content(html: &str) -> String {
+    let document = Document::from(html);
+    if let Some(node) = document.find(Attr("id", "main-content")).next() {
+        return node.html();
+    }
+    if let Some(node) = document.find(Attr("role", "main")).next() {
+        return node.html();
+    }
+    if let Some(node) = document.find(Name("main")).next() {
+        return node.html();
+    }
+    html.to_string()
+}
+
+fn element_by_id_with_docs(html: &str, id: &str) -> Option<String> {
+    // the id sits on the signature, the text is in the enclosing <details> (rustdoc) or <dl> (sphinx)
+    let document = Document::from(html);
+    let node = document.find(Attr("id", id)).next()?;
+    let mut parent = node.parent();
+    for _ in 0..3 {
+        match parent {
+            Some(p) if p.name() == Some("details") || p.name() == Some("dl") => return Some(p.html()),
+            Some(p) => parent = p.parent(),
+            None => break,
+        }
+    }
+    Some(node.html())
+}
+
+fn rust_docs_base_url(krate: &str) -> String {
+    if RUST_DOCS_ON_RUST_LANG_ORG.contains(&krate) {
+        format!("https://doc.rust-lang.org/{}/", krate)
+    } else {
+        format!("https://docs.rs/{}/latest/{}/", krate, krate.replace('-', "_"))
+    }
+}
+
+fn find_rust_item_href(all_html: &str, symbol_path: &[&str]) -> Option<String> {
+    // all.html lists every item as a link like "collections/hash_map/struct.HashMap.html"
+    let name = symbol_path.last()?;
+    let module_path = symbol_path[..symbol_path.len() - 1].join("/");
+    let suffix = format!(".{}.html", name);
+    let document = Document::from(all_html);
+    let hrefs: Vec<String> = document.find(Name("a"))
+        .filter_map(|a| a.attr("href").map(|x| x.to_string()))
+        .filter(|href| href.ends_with(&suffix))
+        .collect();
+    hrefs.iter().find(|href| !module_path.is_empty() && href.starts_with(&format!("{}/", module_path)))
+        .or(hrefs.first())
+        .cloned()
+}
+
+async fn rust_docs(fetcher: &dyn DocsFetcher, krate: &str, symbol: &str) -> Result<(String, String), String> {
+    let base_url = rust_docs_base_url(krate);
+    if symbol.is_empty() {
+        let html = fetcher.fetch(&base_url).await?;
+        return Ok((base_url, main_content(&html)));
+    }
+    let all_html = fetcher.fetch(&format!("{}all.html", base_url)).await?;
+    let symbol_path: Vec<&str> = symbol.split("::").filter(|x| !x.is_empty()).collect();
+    if let Some(href) = find_rust_item_href(&all_html, &symbol_path) {
+        let url = format!("{}{}", base_url, href);
+        let html = fetcher.fetch(&url).await?;
+        return Ok((url, main_content(&html)));
+    }
+    // methods are not in all.html, try Type::method as a section of the type page
+    if symbol_path.len() >= 2 {
+        let method = symbol_path[symbol_path.len() - 1];
+        if let Some(href) = find_rust_item_href(&all_html, &symbol_path[..symbol_path.len() - 1]) {
+            let url = format!("{}{}", base_url, href);
+            let html = fetcher.fetch(&url).await?;
+            for id in [format!("method.{}", method), format!("tymethod.{}", method), format!("associatedconstant.{}", method)] {
+                if let Some(section) = element_by_id_with_docs(&html, &id) {
+                    return Ok((format!("{}#{}", url, id), section));
+                }
+            }
+        }
+    }
+    Err(format!("symbol `{}` not found in the docs of crate `{}`, check the spelling or try the crate overview without a symbol", symbol, krate))
+}
+
+async fn python_docs(fetcher: &dyn DocsFetcher, module: &str, symbol: &str) -> Result<(String, String), String> {
+    // submodules like os.path often have their own page, otherwise they're documented on the top module page
+    let top_module = module.split('.').next().unwrap_or(module);
+    let mut candidates = vec![module.to_string()];
+    if top_module != module {
+        candidates.push(top_module.to_string());
+    }
+    let mut last_error = String::new();
+    for page in candidates {
+        let url = format!("https://docs.python.org/3/library/{}.html", page);
+        let html = match fetcher.fetch(&url).await {
+            Ok(html) => html,
+            Err(e) => { last_error = e; continue; }
+        };
+        if symbol.is_empty() {
+            return Ok((url, main_content(&html)));
+        }
+        let id = format!("{}.{}", module, symbol);
+        if let Some(section) = element_by_id_with_docs(&html, &id) {
+            return Ok((format!("{}#{}", url, id), section));
+        }
+        last_error = format!("symbol `{}` not found on {}", id, url);
+    }
+    Err(last_error)
+}
+
+pub async fn docs_lookup(
+    fetcher: &dyn DocsFetcher,
+    language: &str,
+    module: &str,
+    symbol: &str,
+) -> Result<String, String> {
+    let (url, html) = match language {
+        "rust" => rust_docs(fetcher, module, symbol).await?,
+        "python" => python_docs(fetcher, module, symbol).await?,
+        _ => return Err(format!("language `{}` is not supported, use `rust` or `python`", language)),
+    };
+    let mut text = html_to_markdown(&html)?;
+    if text.chars().count() > DOCS_MAX_CHARS {
+        text = text.chars().take(DOCS_MAX_CHARS).collect();
+        text.push_str("\n\n...truncated, ask for a more specific symbol to see less\n");
+    }
+    Ok(format!("Documentation from {}\n\n{}", url, text))
+}
+
+fn get_string_arg(args: &HashMap<String, Value>, name: &str, required: bool) -> Result<String, String> {
+    match args.get(name) {
+        Some(Value::String(s)) => Ok(s.trim().to_string()),
+        Some(v) => Err(format!("argument `{}` is not a string: {:?}", name, v)),
+        None if required => Err(format!("argument `{}` is missing", name)),
+        None => Ok("".to_string()),
+    }
+}
+
+#[async_trait]
+impl Tool for ToolDocs {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let language = get_string_arg(args, "language", true)?.to_lowercase();
+        let module = get_string_arg(args, "module", true)?;
+        let symbol = get_string_arg(args, "symbol", false)?;
+
+        let cache_key = format!("{}:{}:{}", language, module, symbol);
+        let cached = DOCS_CACHE.lock().unwrap().get(&cache_key)
+            .filter(|(ts, _)| ts.elapsed() < DOCS_CACHE_TTL)
+            .map(|(_, text)| text.clone());
+        let text = match cached {
+            Some(text) => text,
+            None => {
+                let gcx = ccx.lock().await.global_context.clone();
+                let fetcher = HttpDocsFetcher { privacy_settings: load_privacy_if_needed(gcx).await };
+                let text = docs_lookup(&fetcher, &language, &module, &symbol).await?;
+                let mut cache = DOCS_CACHE.lock().unwrap();
+                cache.retain(|_, (ts, _)| ts.elapsed() < DOCS_CACHE_TTL);
+                cache.insert(cache_key, (Instant::now(), text.clone()));
+                text
+            }
+        };
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(text),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockFetcher {
+        pages: HashMap<String, String>,
+    }
+
+    #[async_trait]
+    impl DocsFetcher for MockFetcher {
+        async fn fetch(&self, url: &str) -> Result<String, String> {
+            self.pages.get(url).cloned().ok_or(format!("404 {}", url))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_docs_lookup_symbol() {
+        let fetcher = MockFetcher { pages: HashMap::from([
+            ("https://docs.python.org/3/library/os.path.html".to_string(), r#"<html><body><div role="main">
+                <dl class="py function"><dt class="sig sig-object py" id="os.path.exists">os.path.exists(path)</dt>
+                <dd><p>Return True if path refers to an existing path.</p></dd></dl>
+                <dl class="py function"><dt class="sig sig-object py" id="os.path.join">os.path.join(path, *paths)</dt>
+                <dd><p>Join one or more path segments intelligently.</p></dd></dl>
+            </div></body></html>"#.to_string()),
+            ("https://docs.rs/serde-json/latest/serde_json/all.html".to_string(), r#"<html><body>
+                <a href="fn.to_string.html">to_string</a><a href="value/enum.Value.html">value::Value</a>
+            </body></html>"#.to_string()),
+            ("https://docs.rs/serde-json/latest/serde_json/value/enum.Value.html".to_string(), r#"<html><body>
+                <nav>sidebar junk</nav><section id="main-content"><h1>Enum Value</h1><p>Represents any valid JSON value.</p>
+                <details class="toggle method-toggle"><summary><section id="method.is_null">pub fn is_null(&amp;self) -&gt; bool</section></summary>
+                <div class="docblock"><p>Returns true if the Value is a Null.</p></div></details></section>
+            </body></html>"#.to_string()),
+        ])};
+
+        let py = docs_lookup(&fetcher, "python", "os.path", "join").await.unwrap();
+        assert!(py.contains("Join one or more path segments"), "{}", py);
+        assert!(!py.contains("existing path"), "{}", py);
+
+        let rs = docs_lookup(&fetcher, "rust", "serde-json", "Value").await.unwrap();
+        assert!(rs.contains("Represents any valid JSON value"), "{}", rs);
+        assert!(!rs.contains("sidebar junk"), "{}", rs);
+
+        let method = docs_lookup(&fetcher, "rust", "serde-json", "Value::is_null").await.unwrap();
+        assert!(method.contains("Returns true if the Value is a Null"), "{}", method);
+        assert!(!method.contains("Represents any valid JSON value"), "{}", method);
+
+        assert!(docs_lookup(&fetcher, "python", "os.path", "nonexistent").await.is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+use tree_sitter::Node;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::at_commands::at_file::{file_repair_candidates, return_one_candidate_or_a_good_error};
+use crate::ast::treesitter::language_id::LanguageId;
+use crate::ast::treesitter::parsers::{parse_text_to_tree, tree_sitter_language_by_filename};
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_project_dirs;
+use crate::files_in_workspace::get_file_text_from_memory_or_disk;
+use crate::tools::tools_description::Tool;
+
+
+const EXTRACT_DEFAULT_MIN_LEN: usize = 4;
+const EXTRACT_MAX_RESULTS: usize = 200;
+
+const STRING_KINDS: &[&str] = &["string", "string_literal", "raw_string_literal", "template_string", "text_block"];
+// strings under these are module paths, compiler hints or annotations, never shown to a user
+const SKIP_KINDS: &[&str] = &[
+    "import_statement", "import_from_statement", "future_import_statement", "import_declaration",
+    "use_declaration", "extern_crate_declaration", "preproc_include", "linkage_specification",
+    "attribute_item", "inner_attribute_item", "comment",
+];
+const CALL_KINDS: &[&str] = &["call", "call_expression", "method_invocation", "macro_invocation"];
+const IMPORT_CALLEES: &[&str] = &["require", "import", "__import__", "import_module"];
+const LOGGER_NAMES: &[&str] = &["log", "logger", "logging", "console", "tracing", "_log", "_logger"];
+const LOG_MACROS: &[&str] = &["trace", "debug", "info", "warn", "error"];
+
+pub struct ToolExtractStrings;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractedString {
+    pub line: usize,      // starts from 1
+    pub text: String,
+}
+
+pub fn looks_like_test_file(path: &str) -> bool {
+    let path = format!("/{}", path.replace('\\', "/").to_lowercase());
+    let name = path.rsplit('/').next().unwrap_or("");
+    let stem = name.split('.').next().unwrap_or("");
+    ["/test/", "/tests/", "/__tests__/", "/spec/"].iter().any(|d| path.contains(d))
+        || stem.starts_with("test_") || stem.ends_with("_test") || stem.ends_with("_tests")
+        || name.contains(".test.") || name.contains(".spec.")
+}
+
+fn is_logging_or_import_call(node: &Node, text: &str) -> bool {
+    // the callee is everything before the arguments: logger.info, console.log, tracing::warn!, require
+    let call_text = text.get(node.byte_range()).unwrap_or("");
+    let callee = call_text.split(|c| c == '(' || c == '!').next().unwrap_or("").trim();
+    let segments: Vec<&str> = callee.split(|c: char| !c.is_alphanumeric() && c != '_').filter(|s| !s.is_empty()).collect();
+    let last = segments.last().copied().unwrap_or("");
+    if IMPORT_CALLEES.contains(&last) {
+        return true;
+    }
+    if segments.iter().any(|s| LOGGER_NAMES.contains(&s.to_lowercase().as_str())) {
+        return true;
+    }
+    node.kind() == "macro_invocation" && LOG_MACROS.contains(&last)
+}
+
+fn is_docstring(node: &Node) -> bool {
+    node.parent().map(|p| p.kind() == "expression_statement" && p.named_child_count() == 1).unwrap_or(false)
+}
+
+fn is_rust_tests_module(node: &Node, text: &str) -> bool {
+    node.kind() == "mod_item" && node.child_by_field_name("name").and_then(|n| text.get(n.byte_range())) == Some("tests")
+}
+
+fn unquote(literal: &str) -> String {
+    // prefixes: r"" b"" f"" u"" L"" @"" and rust r#""#
+    let s = literal.trim_start_matches(|c: char| c.is_ascii_alphabetic() || c == '@' || c == '$').trim_matches('#');
+    for quote in ["\"\"\"", "'''", "\"", "'", "`"] {
+        if s.len() >= 2 * quote.len() && s.starts_with(quote) && s.ends_with(quote) {
+            return s[quote.len()..s.len() - quote.len()].to_string();
+        }
+    }
+    s.to_string()
+}
+
+fn visit(node: Node, text: &str, min_len: usize, out: &mut Vec<ExtractedString>) {
+    let kind = node.kind();
+    if SKIP_KINDS.contains(&kind) || is_rust_tests_module(&node, text) {
+        return;
+    }
+    if CALL_KINDS.contains(&kind) && is_logging_or_import_call(&node, text) {
+        return;
+    }
+    if STRING_KINDS.contains(&kind) {
+        if is_docstring(&node) {
+            return;
+        }
+        let value = unquote(text.get(node.byte_range()).unwrap_or(""));
+        // "%s: %d", "\n", "-" are formatting, not words a translator would see
+        if value.chars().count() >= min_len && value.chars().any(|c| c.is_alphabetic()) {
+            out.push(ExtractedString { line: node.start_position().row + 1, text: value });
+        }
+        return;  // an f-string interpolation inside is not a separate string
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit(child, text, min_len, out);
+    }
+}
+
+pub fn extract_strings(language_id: LanguageId, text: &str, min_len: usize) -> Result<Vec<ExtractedString>, String> {
+    let tree = parse_text_to_tree(language_id, text)?;
+    let mut result = vec![];
+    visit(tree.root_node(), text, min_len, &mut result);
+    Ok(result)
+}
+
+pub fn format_extracted_strings(path: &str, strings: &Vec<ExtractedString>) -> String {
+    if strings.is_empty() {
+        return format!("No user-facing strings found in {}\n", path);
+    }
+    let mut report = format!("{} strings in {}:\n", strings.len(), path);
+    for s in strings.iter().take(EXTRACT_MAX_RESULTS) {
+        report.push_str(&format!("{}:{}: {:?}\n", path, s.line, s.text));
+    }
+    if strings.len() > EXTRACT_MAX_RESULTS {
+        report.push_str(&format!("...and {} more, raise min_len to skip the short ones\n", strings.len() - EXTRACT_MAX_RESULTS));
+    }
+    report
+}
+
+#[async_trait]
+impl Tool for ToolExtractStrings {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let path = match args.get("path") {
+            Some(Value::String(s)) if !s.trim().is_empty() => s.trim().to_string(),
+            Some(v) if !v.is_string() => return Err(format!("argument `path` is not a string: {:?}", v)),
+            _ => return Err("Missing argument `path`".to_string()),
+        };
+        let min_len = match args.get("min_len") {
+            Some(Value::Number(n)) => n.as_u64().ok_or(format!("argument `min_len` is not a positive integer: {}", n))? as usize,
+            Some(Value::String(s)) if !s.trim().is_empty() => s.trim().parse::<usize>().map_err(|_| format!("argument `min_len` is not a positive integer: {:?}", s))?,
+            _ => EXTRACT_DEFAULT_MIN_LEN,
+        };
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let candidates = file_repair_candidates(gcx.clone(), &path, 10, false).await;
+        let file_path = return_one_candidate_or_a_good_error(gcx.clone(), &path, &candidates, &get_project_dirs(gcx.clone()).await, false).await?;
+        let language_id = tree_sitter_language_by_filename(&PathBuf::from(&file_path))
+            .map_err(|e| format!("cannot extract strings from {}: {}", file_path, e))?;
+        let report = if looks_like_test_file(&file_path) {
+            format!("{} looks like a test file, strings in tests are not user-facing, skipped\n", file_path)
+        } else {
+            // checks privacy
+            let text = get_file_text_from_memory_or_disk(gcx.clone(), &PathBuf::from(&file_path)).await?;
+            format_extracted_strings(&file_path, &extract_strings(language_id, &text, min_len)?)
+        };
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE_PY: &str = r#""""Checkout page handlers."""
+import os
+from payments import gateway
+import logging
+
+logger = logging.getLogger("checkout")
+mod = __import__("payments.stripe")
+
+def checkout(cart):
+    if not cart.items:
+        raise ValueError("Your cart is empty, add something first")
+    logger.info("checkout started for %s", cart.id)
+    logging.warning("slow path taken")
+    sep = ", "
+    return {"title": "Order confirmed", "ok": "ok"}
+"#;
+
+    #[test]
+    fn test_user_facing_strings_found() {
+        let found = extract_strings(LanguageId::Python, FIXTURE_PY, EXTRACT_DEFAULT_MIN_LEN).unwrap();
+        let texts: Vec<&str> = found.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, vec!["Your cart is empty, add something first", "title", "Order confirmed"], "{:?}", found);
+        assert_eq!(found[0].line, 11);
+
+        // a higher min_len leaves only the sentences
+        let found = extract_strings(LanguageId::Python, FIXTURE_PY, 10).unwrap();
+        assert_eq!(found.iter().map(|s| s.text.as_str()).collect::<Vec<_>>(), vec!["Your cart is empty, add something first", "Order confirmed"]);
+        let report = format_extracted_strings("shop/checkout.py", &found);
+        assert!(report.contains("shop/checkout.py:11: \"Your cart is empty, add something first\""), "{}", report);
+
+        assert!(looks_like_test_file("shop/tests/test_checkout.py"));
+        assert!(looks_like_test_file("web/src/cart.spec.ts"));
+        assert!(!looks_like_test_file("shop/checkout.py"));
+    }
+}
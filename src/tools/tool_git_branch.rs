@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::process::Command;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_active_project_path;
+use crate::files_in_workspace::detect_vcs_for_a_file_path;
+use crate::integrations::integr_abstract::IntegrationConfirmation;
+use crate::tools::tools_description::{MatchConfirmDeny, MatchConfirmDenyResult, Tool};
+use crate::tools::tools_execute::{command_should_be_confirmed_by_user, command_should_be_denied};
+
+
+const GIT_TIMEOUT_SECS: u64 = 30;
+
+pub struct ToolGitBranch;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BranchOp {
+    List,
+    Create { name: String, start_point: Option<String>, checkout: bool },
+    Checkout { name: String, stash: bool },
+    Delete { name: String, force: bool },
+}
+
+impl BranchOp {
+    pub fn from_args(args: &HashMap<String, Value>) -> Result<BranchOp, String> {
+        let get_str = |name: &str| -> Result<Option<String>, String> {
+            match args.get(name) {
+                Some(Value::String(s)) if !s.trim().is_empty() => Ok(Some(s.trim().to_string())),
+                Some(Value::String(_)) | Some(Value::Null) | None => Ok(None),
+                Some(v) => Err(format!("argument `{}` is not a string: {:?}", name, v)),
+            }
+        };
+        let get_bool = |name: &str| -> Result<bool, String> {
+            match args.get(name) {
+                Some(Value::Bool(b)) => Ok(*b),
+                Some(Value::String(s)) if s == "true" => Ok(true),
+                Some(Value::String(s)) if s == "false" || s.is_empty() => Ok(false),
+                Some(Value::Null) | None => Ok(false),
+                Some(v) => Err(format!("argument `{}` is not a bool: {:?}", name, v)),
+            }
+        };
+        let operation = get_str("operation")?.ok_or("argument `operation` is missing".to_string())?;
+        let name = || get_str("name")?.ok_or(format!("argument `name` is required for `{}`", operation));
+        Ok(match operation.as_str() {
+            "list" => BranchOp::List,
+            "create" => BranchOp::Create { name: name()?, start_point: get_str("start_point")?, checkout: get_bool("checkout")? },
+            "checkout" => BranchOp::Checkout { name: name()?, stash: get_bool("stash")? },
+            "delete" => BranchOp::Delete { name: name()?, force: get_bool("force")? },
+            _ => return Err(format!("unknown operation {:?}, use list, create, checkout or delete", operation)),
+        })
+    }
+
+    pub fn command_line(&self) -> String {
+        match self {
+            BranchOp::List => "git_branch list".to_string(),
+            BranchOp::Create { name, start_point, checkout } => format!("git_branch create {}{}{}",
+                name, start_point.as_ref().map(|s| format!(" {}", s)).unwrap_or_default(), if *checkout { " --checkout" } else { "" }),
+            BranchOp::Checkout { name, stash } => format!("git_branch checkout {}{}", name, if *stash { " --stash" } else { "" }),
+            BranchOp::Delete { name, force } => format!("git_branch delete {}{}", name, if *force { " --force" } else { "" }),
+        }
+    }
+}
+
+pub async fn git(repo: &Path, args: &[&str]) -> Result<String, String> {
+    let output = tokio::time::timeout(
+        tokio::time::Duration::from_secs(GIT_TIMEOUT_SECS),
+        Command::new("git").arg("-C").arg(repo).args(args).stdin(Stdio::null()).kill_on_drop(true).output(),
+    ).await
+        .map_err(|_| format!("git {} timed out after {}s", args.join(" "), GIT_TIMEOUT_SECS))?
+        .map_err(|e| format!("cannot run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+async fn check_branch_name(repo: &Path, name: &str) -> Result<(), String> {
+    if name.starts_with('-') {
+        return Err(format!("{:?} is not a valid branch name", name));
+    }
+    git(repo, &["check-ref-format", "--branch", name]).await
+        .map(|_| ()).map_err(|_| format!("{:?} is not a valid branch name", name))
+}
+
+async fn checkout(repo: &Path, name: &str, stash: bool) -> Result<String, String> {
+    let dirty = dirty_files(repo).await?;
+    let mut report = String::new();
+    if !dirty.is_empty() {
+        if !stash {
+            return Err(format!("The working tree has uncommitted changes, refusing to switch to {}:\n{}\nCommit them first, or call again with stash=true to stash them.", name, dirty.join("\n")));
+        }
+        git(repo, &["stash", "push", "-m", &format!("before switching to {}", name)]).await?;
+        report.push_str(&format!("Stashed changes in {} files, `git stash pop` brings them back.\n", dirty.len()));
+    }
+    git(repo, &["checkout", name, "--"]).await?;
+    report.push_str(&format!("Switched to branch {}.\n", name));
+    Ok(report)
+}
+
+async fn dirty_files(repo: &Path) -> Result<Vec<String>, String> {
+    // untracked files survive a checkout, only changes to tracked files are in the way
+    let status = git(repo, &["status", "--porcelain", "--untracked-files=no"]).await?;
+    Ok(status.lines().map(|l| l.to_string()).filter(|l| !l.trim().is_empty()).collect())
+}
+
+pub async fn git_branch_op(repo: &Path, op: &BranchOp) -> Result<String, String> {
+    match op {
+        BranchOp::List => {
+            let out = git(repo, &["branch", "--list", "--no-color", "--format=%(HEAD) %(refname:short) %(objectname:short) %(contents:subject)"]).await?;
+            if out.trim().is_empty() {
+                return Ok("No branches yet, the repository has no commits.".to_string());
+            }
+            Ok(format!("Branches, * marks the current one:\n{}", out))
+        }
+        BranchOp::Create { name, start_point, checkout: and_checkout } => {
+            check_branch_name(repo, name).await?;
+            let mut args = vec!["branch", "--", name.as_str()];
+            if let Some(start_point) = start_point {
+                args.push(start_point.as_str());
+            }
+            git(repo, &args).await?;
+            let mut report = format!("Created branch {}{}.\n", name, start_point.as_ref().map(|s| format!(" at {}", s)).unwrap_or_default());
+            if *and_checkout {
+                report.push_str(&checkout(repo, name, false).await?);
+            }
+            Ok(report)
+        }
+        BranchOp::Checkout { name, stash } => {
+            check_branch_name(repo, name).await?;
+            checkout(repo, name, *stash).await
+        }
+        BranchOp::Delete { name, force } => {
+            check_branch_name(repo, name).await?;
+            git(repo, &["branch", if *force { "-D" } else { "-d" }, "--", name.as_str()]).await?;
+            Ok(format!("Deleted branch {}.\n", name))
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for ToolGitBranch {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let op = BranchOp::from_args(args)?;
+        let gcx = ccx.lock().await.global_context.clone();
+        let project_path: PathBuf = get_active_project_path(gcx.clone()).await.ok_or("no workspace folder is open".to_string())?;
+        let repo = match detect_vcs_for_a_file_path(&project_path).await {
+            Some((repo, "git")) => repo,
+            _ => return Err(format!("{} is not inside a git repository", project_path.display())),
+        };
+        let report = git_branch_op(&repo, &op).await?;
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    async fn match_against_confirm_deny(
+        &self,
+        _ccx: Arc<AMutex<AtCommandsContext>>,
+        args: &HashMap<String, Value>,
+    ) -> Result<MatchConfirmDeny, String> {
+        let command_to_match = self.command_to_match_against_confirm_deny(args)?;
+        if let Some(rules) = &self.confirm_deny_rules() {
+            let (is_denied, deny_rule) = command_should_be_denied(&command_to_match, &rules.deny);
+            if is_denied {
+                return Ok(MatchConfirmDeny {
+                    result: MatchConfirmDenyResult::DENY,
+                    command: command_to_match,
+                    rule: deny_rule,
+                });
+            }
+            let (needs_confirmation, confirmation_rule) = command_should_be_confirmed_by_user(&command_to_match, &rules.ask_user);
+            if needs_confirmation {
+                return Ok(MatchConfirmDeny {
+                    result: MatchConfirmDenyResult::CONFIRMATION,
+                    command: command_to_match,
+                    rule: confirmation_rule,
+                });
+            }
+        }
+        Ok(MatchConfirmDeny {
+            result: MatchConfirmDenyResult::PASS,
+            command: command_to_match,
+            rule: "".to_string(),
+        })
+    }
+
+    fn command_to_match_against_confirm_deny(
+        &self,
+        args: &HashMap<String, Value>,
+    ) -> Result<String, String> {
+        Ok(BranchOp::from_args(args)?.command_line())
+    }
+
+    fn confirm_deny_rules(&self) -> Option<IntegrationConfirmation> {
+        Some(IntegrationConfirmation {
+            ask_user: vec!["git_branch delete * --force".to_string()],
+            deny: vec![],
+        })
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn temp_repo() -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        git(&dir, &["init", "-q", "-b", "main"]).await.unwrap();
+        std::fs::write(dir.join("a.txt"), "hello\n").unwrap();
+        git(&dir, &["config", "user.name", "test"]).await.unwrap();
+        git(&dir, &["config", "user.email", "test@example.com"]).await.unwrap();
+        git(&dir, &["add", "a.txt"]).await.unwrap();
+        git(&dir, &["commit", "-q", "-m", "first"]).await.unwrap();
+        tmp
+    }
+
+    #[tokio::test]
+    async fn test_git_branch_create_and_list() {
+        let tmp = temp_repo().await;
+        let repo = tmp.path().to_path_buf();
+        let report = git_branch_op(&repo, &BranchOp::Create { name: "feature/x".to_string(), start_point: None, checkout: true }).await.unwrap();
+        assert!(report.contains("Created branch feature/x"), "{}", report);
+        assert!(report.contains("Switched to branch feature/x"), "{}", report);
+
+        let list = git_branch_op(&repo, &BranchOp::List).await.unwrap();
+        assert!(list.contains("* feature/x"), "{}", list);
+        assert!(list.contains("  main"), "{}", list);
+        assert!(list.contains("first"), "{}", list);
+
+        assert!(git_branch_op(&repo, &BranchOp::Create { name: "bad..name".to_string(), start_point: None, checkout: false }).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_git_branch_checkout_refuses_dirty_tree() {
+        let tmp = temp_repo().await;
+        let repo = tmp.path().to_path_buf();
+        git_branch_op(&repo, &BranchOp::Create { name: "other".to_string(), start_point: None, checkout: false }).await.unwrap();
+        std::fs::write(repo.join("a.txt"), "changed\n").unwrap();
+
+        let err = git_branch_op(&repo, &BranchOp::Checkout { name: "other".to_string(), stash: false }).await.unwrap_err();
+        assert!(err.contains("uncommitted changes"), "{}", err);
+        assert!(err.contains("a.txt"), "{}", err);
+        assert!(git(&repo, &["branch", "--show-current"]).await.unwrap().trim() == "main");
+
+        let report = git_branch_op(&repo, &BranchOp::Checkout { name: "other".to_string(), stash: true }).await.unwrap();
+        assert!(report.contains("Stashed changes in 1 files"), "{}", report);
+        assert_eq!(git(&repo, &["branch", "--show-current"]).await.unwrap().trim(), "other");
+        assert_eq!(std::fs::read_to_string(repo.join("a.txt")).unwrap(), "hello\n");
+    }
+
+    #[test]
+    fn test_git_branch_force_delete_needs_confirmation() {
+        let args = HashMap::from([
+            ("operation".to_string(), Value::String("delete".to_string())),
+            ("name".to_string(), Value::String("old".to_string())),
+            ("force".to_string(), Value::Bool(true)),
+        ]);
+        let command = ToolGitBranch{}.command_to_match_against_confirm_deny(&args).unwrap();
+        assert_eq!(command, "git_branch delete old --force");
+        let rules = ToolGitBranch{}.confirm_deny_rules().unwrap();
+        assert!(command_should_be_confirmed_by_user(&command, &rules.ask_user).0);
+        assert!(!command_should_be_confirmed_by_user(&"git_branch delete old".to_string(), &rules.ask_user).0);
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::at_commands::at_file::{file_repair_candidates, return_one_candidate_or_a_good_error};
+use crate::ast::ast_db::{definitions_at_position, doc_defs, word_at_position};
+use crate::ast::ast_parse_anything::filesystem_path_to_double_colon_path;
+use crate::ast::ast_structs::AstDB;
+use crate::ast::treesitter::ast_instance_structs::{ImportDeclaration, ImportType};
+use crate::ast::treesitter::parsers::get_ast_parser_by_filename;
+use crate::ast::treesitter::structs::SymbolType;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_project_dirs;
+use crate::files_in_workspace::get_file_text_from_memory_or_disk;
+use crate::tools::tool_module_graph::resolve_import_among;
+use crate::tools::tools_description::Tool;
+
+
+pub struct ToolGotoDefinition;
+
+#[derive(Debug, PartialEq)]
+pub struct DefinitionLocation {
+    pub cpath: String,
+    pub line1: usize,
+    pub line2: usize,
+    pub what: String,  // symbol path, or "module" when the cursor was on an import of a whole file
+}
+
+// Workspace-local import that covers the line, stdlib and libraries have nothing to jump to
+fn import_at_line(cpath: &PathBuf, text: &str, line0: usize) -> Option<Vec<String>> {
+    let (mut parser, _language_id) = get_ast_parser_by_filename(cpath).ok()?;
+    for symbol in parser.parse(text, cpath) {
+        let symbol = symbol.read();
+        if symbol.symbol_type() != SymbolType::ImportDeclaration {
+            continue;
+        }
+        let range = symbol.full_range();
+        if line0 < range.start_point.row || line0 > range.end_point.row {
+            continue;
+        }
+        if let Some(import) = symbol.as_any().downcast_ref::<ImportDeclaration>() {
+            if import.import_type != ImportType::System && import.import_type != ImportType::Library && !import.path_components.is_empty() {
+                return Some(import.path_components.clone());
+            }
+        }
+    }
+    None
+}
+
+pub async fn goto_definition(
+    ast_index: Arc<AMutex<AstDB>>,
+    cpath: &String,
+    file_text: &str,
+    line0: usize,
+    col0: usize,
+    workspace_files: &Vec<PathBuf>,
+) -> Result<(Vec<DefinitionLocation>, bool), String> {
+    // line0 and col0 start from 0; returns the locations and whether they were found by name only
+    let line_text = file_text.lines().nth(line0).ok_or(format!("line {} is outside of the file", line0 + 1))?;
+    let symbol = word_at_position(line_text, col0).ok_or(format!("no symbol at {}:{}", line0 + 1, col0 + 1))?;
+
+    if let Some(components) = import_at_line(&PathBuf::from(cpath), file_text, line0) {
+        if let Some(module_file) = resolve_import_among(&PathBuf::from(cpath), &components, workspace_files) {
+            let module_cpath = module_file.to_string_lossy().to_string();
+            // `from m import f` with the cursor on f goes to f itself, on m to the file
+            let top_level_len = filesystem_path_to_double_colon_path(&module_cpath).len() + 1;
+            let imported: Vec<DefinitionLocation> = doc_defs(ast_index.clone(), &module_cpath).await.iter()
+                .filter(|d| d.name() == symbol && d.official_path.len() == top_level_len)
+                .map(|d| DefinitionLocation { cpath: d.cpath.clone(), line1: d.full_line1(), line2: d.full_line2(), what: d.path_drop0() })
+                .collect();
+            if !imported.is_empty() {
+                return Ok((imported, false));
+            }
+            return Ok((vec![DefinitionLocation { cpath: module_cpath, line1: 1, line2: 1, what: "module".to_string() }], false));
+        }
+    }
+
+    let (_symbol, targets, resolved_by_name_only) = definitions_at_position(ast_index.clone(), cpath, file_text, line0, col0).await?;
+    let locations = targets.iter()
+        .map(|d| DefinitionLocation { cpath: d.cpath.clone(), line1: d.full_line1(), line2: d.full_line2(), what: d.path_drop0() })
+        .collect();
+    Ok((locations, resolved_by_name_only))
+}
+
+#[async_trait]
+impl Tool for ToolGotoDefinition {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let path = match args.get("path") {
+            Some(Value::String(s)) if !s.trim().is_empty() => s.trim().to_string(),
+            Some(Value::String(_)) | None => return Err("Missing argument `path`".to_string()),
+            Some(v) => return Err(format!("argument `path` is not a string: {:?}", v)),
+        };
+        let mut position = vec![];
+        for name in ["line", "col"] {
+            let n = match args.get(name) {
+                Some(Value::Number(n)) => n.as_u64().map(|n| n as usize),
+                Some(Value::String(s)) => s.trim().parse::<usize>().ok(),
+                None => return Err(format!("Missing argument `{}`", name)),
+                Some(v) => return Err(format!("argument `{}` is not a number: {:?}", name, v)),
+            };
+            match n {
+                Some(n) if n >= 1 => position.push(n - 1),
+                _ => return Err(format!("argument `{}` should be a number starting from 1", name)),
+            }
+        }
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let project_dirs = get_project_dirs(gcx.clone()).await;
+        let candidates = file_repair_candidates(gcx.clone(), &path, 10, false).await;
+        let cpath = return_one_candidate_or_a_good_error(gcx.clone(), &path, &candidates, &project_dirs, false).await?;
+        let file_text = get_file_text_from_memory_or_disk(gcx.clone(), &PathBuf::from(&cpath)).await?;
+
+        let ast_service = gcx.read().await.ast_service.clone().ok_or("AST is turned off".to_string())?;
+        crate::ast::ast_indexer_thread::ast_indexer_block_until_finished(ast_service.clone(), 20_000, true).await;
+        let ast_index = ast_service.lock().await.ast_index.clone();
+        let workspace_files: Vec<PathBuf> = gcx.read().await.documents_state.workspace_files.lock().unwrap().clone();
+
+        let (locations, by_name_only) = goto_definition(ast_index, &cpath, &file_text, position[0], position[1], &workspace_files).await?;
+        let short_paths = crate::files_correction::shortify_paths(gcx.clone(), &locations.iter().map(|l| l.cpath.clone()).collect()).await;
+        let mut report = String::new();
+        if locations.is_empty() {
+            report.push_str(&format!("No definition found for the symbol at {}:{}:{}, the file might not be indexed yet\n", path, position[0] + 1, position[1] + 1));
+        }
+        for (location, short_path) in locations.iter().zip(short_paths.iter()) {
+            if location.what == "module" {
+                report.push_str(&format!("imported module {}\n", short_path));
+            } else {
+                report.push_str(&format!("{} defined at {}:{}-{}\n", location.what, short_path, location.line1, location.line2));
+            }
+        }
+        if by_name_only && !locations.is_empty() {
+            report.push_str("The symbol is not resolved by the indexer, these definitions only have the same name\n");
+        }
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec!["ast".to_string()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ast_db::{ast_index_init, connect_usages, connect_usages_look_if_full_reset_needed, doc_add, flush_sled_batch};
+    use crate::ast::ast_structs::AstErrorStats;
+
+    #[tokio::test]
+    async fn test_goto_definition_in_another_file() {
+        let ast_index = ast_index_init("".to_string(), 10, false).await;
+        let lib_path = "src/ast/alt_testsuite/py_refs_lib.py".to_string();
+        let main_path = "src/ast/alt_testsuite/py_refs_main.py".to_string();
+        let lib_text = std::fs::read_to_string(&lib_path).unwrap();
+        let main_text = std::fs::read_to_string(&main_path).unwrap();
+        let mut errstats = AstErrorStats::default();
+        doc_add(ast_index.clone(), &lib_path, &lib_text, &mut errstats).await.unwrap();
+        doc_add(ast_index.clone(), &main_path, &main_text, &mut errstats).await.unwrap();
+        let mut ucx = connect_usages_look_if_full_reset_needed(ast_index.clone()).await;
+        while connect_usages(ast_index.clone(), &mut ucx).await {}
+        flush_sled_batch(ast_index.clone(), 0).await;
+        let workspace_files = vec![PathBuf::from(&lib_path), PathBuf::from(&main_path)];
+
+        // `total = compute_total(items)`, the call resolves to the function in the lib
+        let (locations, by_name_only) = goto_definition(ast_index.clone(), &main_path, &main_text, 4, 14, &workspace_files).await.unwrap();
+        assert!(!by_name_only);
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].cpath, lib_path);
+        assert_eq!((locations[0].line1, locations[0].line2), (1, 2));
+        assert!(locations[0].what.ends_with("compute_total"), "{:?}", locations);
+
+        // `from py_refs_lib import compute_total`: on the module and on the imported name
+        let (on_module, _) = goto_definition(ast_index.clone(), &main_path, &main_text, 0, 8, &workspace_files).await.unwrap();
+        assert_eq!(on_module, vec![DefinitionLocation { cpath: lib_path.clone(), line1: 1, line2: 1, what: "module".to_string() }]);
+        let (on_name, _) = goto_definition(ast_index.clone(), &main_path, &main_text, 0, 30, &workspace_files).await.unwrap();
+        assert_eq!(on_name.len(), 1);
+        assert_eq!((on_name[0].cpath.as_str(), on_name[0].line1), (lib_path.as_str(), 1));
+
+        assert!(goto_definition(ast_index.clone(), &main_path, &main_text, 1, 0, &workspace_files).await.is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+use regex::Regex;
+use serde_json::{json, Value};
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_project_dirs;
+use crate::files_in_workspace::read_file_from_disk;
+use crate::privacy::{check_file_privacy, load_privacy_if_needed, FilePrivacyLevel, PrivacySettings};
+use crate::tools::scan_files_streaming::{scan_files_json_lines, JsonLinesOutput};
+use crate::tools::tools_description::Tool;
+use crate::tools::tools_execute::ToolOutputStreamer;
+use crate::tools::tools_utils::{is_under_subdir, short_path};
+
+
+pub const GREP_MAX_MATCHES: usize = 1000;
+const GREP_MAX_MATCHES_IN_MESSAGE: usize = 200;
+const GREP_MAX_LINE_CHARS: usize = 200;
+
+pub struct ToolGrep;
+
+// Workspace files under `subdir` (relative to a project dir, or absolute) that privacy allows to read
+pub async fn files_to_scan(ccx: Arc<AMutex<AtCommandsContext>>, subdir: &Option<String>) -> Result<(Vec<PathBuf>, Arc<PrivacySettings>), String> {
+    let gcx = ccx.lock().await.global_context.clone();
+    let project_dirs = get_project_dirs(gcx.clone()).await;
+    let privacy = load_privacy_if_needed(gcx.clone()).await;
+    let workspace_files = gcx.read().await.documents_state.workspace_files.lock().unwrap().clone();
+    let mut files: Vec<PathBuf> = workspace_files.into_iter()
+        .filter(|p| subdir.as_ref().map_or(true, |d| is_under_subdir(p, d, &project_dirs)))
+        .filter(|p| check_file_privacy(privacy.clone(), p, &FilePrivacyLevel::AllowToSendAnywhere).is_ok())
+        .collect();
+    if files.is_empty() {
+        return Err(format!("no files to scan{}", subdir.as_ref().map(|d| format!(" under {:?}", d)).unwrap_or_default()));
+    }
+    files.sort();
+    Ok((files, privacy))
+}
+
+// One JSON line {"path", "line", "text"} per matching line goes out as soon as the file is scanned, then the summary
+pub async fn grep_files(
+    privacy: Arc<PrivacySettings>,
+    files: &Vec<PathBuf>,
+    re: &Regex,
+    max_matches: usize,
+    output: &mut JsonLinesOutput,
+) -> (Vec<Value>, Value) {
+    scan_files_json_lines(files, |path| {
+        let privacy = privacy.clone();
+        async move {
+            let text = read_file_from_disk(privacy, &path).await?;
+            let path_str = path.to_string_lossy().to_string();
+            Ok(text.lines().enumerate()
+                .filter_map(|(i, line)| {
+                    let line = line.to_string();
+                    let line = line.trim_end_matches(['\n', '\r']);
+                    re.is_match(line).then(|| json!({
+                        "path": path_str,
+                        "line": i + 1,
+                        "text": line.trim().chars().take(GREP_MAX_LINE_CHARS).collect::<String>(),
+                    }))
+                })
+                .collect())
+        }
+    }, max_matches, output).await
+}
+
+pub fn format_grep_results(results: &Vec<Value>, summary: &Value, project_dirs: &Vec<PathBuf>) -> String {
+    let mut report = String::new();
+    for r in results.iter().take(GREP_MAX_MATCHES_IN_MESSAGE) {
+        let path = short_path(&PathBuf::from(r["path"].as_str().unwrap_or_default()), project_dirs);
+        report.push_str(&format!("{}:{}: {}\n", path, r["line"], r["text"].as_str().unwrap_or_default()));
+    }
+    if results.len() > GREP_MAX_MATCHES_IN_MESSAGE {
+        report.push_str(&format!("...and {} more matches\n", results.len() - GREP_MAX_MATCHES_IN_MESSAGE));
+    }
+    if summary["truncated"].as_bool().unwrap_or(false) {
+        report.push_str(&format!("Stopped after {} matches, narrow down the pattern or the path\n", results.len()));
+    }
+    report.push_str(&format!("{}\n", json!({"summary": summary})));
+    report
+}
+
+pub fn parse_scan_args(args: &HashMap<String, Value>) -> Result<(Option<String>, bool), String> {
+    let subdir = match args.get("path") {
+        Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim().trim_start_matches("./").to_string()),
+        Some(Value::String(_)) | None => None,
+        Some(v) => return Err(format!("argument `path` is not a string: {:?}", v)),
+    };
+    let stream = match args.get("stream") {
+        Some(Value::Bool(b)) => *b,
+        Some(Value::String(s)) => s.trim() != "false",
+        _ => true,
+    };
+    Ok((subdir, stream))
+}
+
+#[async_trait]
+impl Tool for ToolGrep {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let pattern = match args.get("pattern") {
+            Some(Value::String(s)) if !s.is_empty() => s.clone(),
+            Some(v) if !v.is_string() => return Err(format!("argument `pattern` is not a string: {:?}", v)),
+            _ => return Err("Missing argument `pattern`".to_string()),
+        };
+        let re = Regex::new(&pattern).map_err(|e| format!("cannot compile regex {:?}: {}", pattern, e))?;
+        let (subdir, stream) = parse_scan_args(args)?;
+
+        let (files, privacy) = files_to_scan(ccx.clone(), &subdir).await?;
+        let streamer = if stream { Some(ToolOutputStreamer::new(ccx.clone(), tool_call_id).await) } else { None };
+        let mut output = JsonLinesOutput::new(streamer);
+        let (results, summary) = grep_files(privacy, &files, &re, GREP_MAX_MATCHES, &mut output).await;
+        tracing::info!("grep {:?}: {}, {} lines streamed", pattern, summary, output.lines_sent);
+
+        let project_dirs = get_project_dirs(ccx.lock().await.global_context.clone()).await;
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(format_grep_results(&results, &summary, &project_dirs)),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_grep_streams_lines_before_the_scan_completes() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = (0..200).map(|i| {
+            let path = dir.path().join(format!("file_{:03}.py", i));
+            std::fs::write(&path, format!("import os\n\ndef f{}():\n    return {}  # answer\n", i, i)).unwrap();
+            path
+        }).collect();
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+        let output = JsonLinesOutput::new(Some(ToolOutputStreamer {
+            subchat_tx: Arc::new(AMutex::new(tx)),
+            tool_call_id: "call_grep".to_string(),
+            chunks_sent: 0,
+        }));
+        let files_clone = files.clone();
+        let scan = tokio::spawn(async move {
+            let mut output = output;
+            let re = Regex::new(r"return \d+").unwrap();
+            let (results, summary) = grep_files(Arc::new(PrivacySettings::default()), &files_clone, &re, GREP_MAX_MATCHES, &mut output).await;
+            (results, summary, output.lines_sent)
+        });
+
+        // each file read yields to the runtime, so the first line is here while most files are still to go
+        let first = rx.recv().await.unwrap();
+        assert!(!scan.is_finished(), "the first line should arrive before the scan is over");
+        let first_line: Value = serde_json::from_str(first["tool_output_partial"]["content"].as_str().unwrap().trim()).unwrap();
+        assert_eq!(first_line, json!({"path": files[0].to_string_lossy(), "line": 4, "text": "return 0  # answer"}));
+
+        let (results, summary, lines_sent) = scan.await.unwrap();
+        assert_eq!(results.len(), 200);
+        assert_eq!(summary, json!({"files_scanned": 200, "matches": 200, "errors": 0}));
+
+        let mut rest = vec![first];
+        while let Ok(m) = rx.try_recv() {
+            rest.push(m);
+        }
+        let (finished, partials) = rest.split_last().unwrap();
+        assert_eq!(partials.len(), 201);
+        assert_eq!(finished["tool_output_finished"]["chunks_total"], lines_sent);
+        let last_line: Value = serde_json::from_str(partials.last().unwrap()["tool_output_partial"]["content"].as_str().unwrap().trim()).unwrap();
+        assert_eq!(last_line, json!({"summary": summary}));
+
+        let report = format_grep_results(&results, &summary, &vec![dir.path().to_path_buf()]);
+        assert!(report.starts_with("file_000.py:4: return 0  # answer\n"), "{}", report);
+        assert!(report.ends_with("{\"summary\":{\"files_scanned\":200,\"matches\":200,\"errors\":0}}\n"), "{}", report);
+
+        let (results, summary) = grep_files(Arc::new(PrivacySettings::default()), &files, &Regex::new(r"return \d+").unwrap(), 50, &mut JsonLinesOutput::new(None)).await;
+        assert_eq!(summary, json!({"files_scanned": 50, "matches": 50, "errors": 0, "truncated": true}));
+        let report = format_grep_results(&results, &summary, &vec![dir.path().to_path_buf()]);
+        assert!(report.contains("Stopped after 50 matches"), "{}", report);
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::at_commands::at_file::{file_repair_candidates, return_one_candidate_or_a_good_error};
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_project_dirs;
+use crate::files_in_workspace::get_file_text_from_memory_or_disk;
+use crate::tools::tools_description::Tool;
+
+
+const JSON_QUERY_MAX_CHARS: usize = 8000;
+
+pub struct ToolJsonQuery;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterOp { Exists, Eq, Ne, Lt, Le, Gt, Ge }
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Key(String),
+    Index(i64),
+    Wildcard,
+    Descendant(String),
+    Filter(Vec<String>, FilterOp, Value),
+}
+
+// A subset of JSONPath: $.a.b, ['a'], [0], [-1], [*], .*, ..name, [?(@.a.b < 10)], [?(@.a)].
+// jq-style ".a.b" and ".[0]" are accepted too, the leading $ is optional.
+pub fn parse_json_path(expr: &str) -> Result<Vec<Step>, String> {
+    let chars: Vec<char> = expr.trim().chars().collect();
+    let err = |pos: usize, what: &str| format!("invalid expression {:?} at position {}: {}", expr.trim(), pos, what);
+    let mut steps = vec![];
+    let mut i = 0;
+    if chars.first() == Some(&'$') {
+        i = 1;
+    }
+    let read_name = |i: &mut usize| -> String {
+        let start = *i;
+        while *i < chars.len() && (chars[*i].is_alphanumeric() || chars[*i] == '_' || chars[*i] == '-') {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect()
+    };
+    while i < chars.len() {
+        match chars[i] {
+            '.' if chars.get(i + 1) == Some(&'.') => {
+                i += 2;
+                let name = read_name(&mut i);
+                if name.is_empty() {
+                    return Err(err(i, "expected a key name after .."));
+                }
+                steps.push(Step::Descendant(name));
+            }
+            '.' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                steps.push(Step::Wildcard);
+            }
+            '.' if chars.get(i + 1) == Some(&'[') => {
+                i += 1;  // jq style .[0]
+            }
+            '.' => {
+                i += 1;
+                let name = read_name(&mut i);
+                if name.is_empty() {
+                    if i == chars.len() && steps.is_empty() {
+                        break;  // jq identity "."
+                    }
+                    return Err(err(i, "expected a key name after ."));
+                }
+                steps.push(Step::Key(name));
+            }
+            '[' => {
+                let close = chars[i..].iter().position(|c| *c == ']').map(|p| i + p).ok_or(err(i, "unclosed ["))?;
+                let inside: String = chars[i + 1..close].iter().collect();
+                let inside = inside.trim();
+                if inside == "*" {
+                    steps.push(Step::Wildcard);
+                } else if let Ok(n) = inside.parse::<i64>() {
+                    steps.push(Step::Index(n));
+                } else if inside.len() >= 2 && (inside.starts_with('\'') && inside.ends_with('\'') || inside.starts_with('"') && inside.ends_with('"')) {
+                    steps.push(Step::Key(inside[1..inside.len() - 1].to_string()));
+                } else if inside.starts_with("?(") && inside.ends_with(')') {
+                    steps.push(parse_filter(&inside[2..inside.len() - 1]).map_err(|e| err(i + 1, &e))?);
+                } else {
+                    return Err(err(i + 1, "expected a number, '*', a quoted key or ?(...) inside []"));
+                }
+                i = close + 1;
+            }
+            c => return Err(err(i, &format!("unexpected {:?}", c))),
+        }
+    }
+    Ok(steps)
+}
+
+fn parse_filter(cond: &str) -> Result<Step, String> {
+    let cond = cond.trim();
+    let rest = cond.strip_prefix("@.").ok_or("a filter must start with @.field".to_string())?;
+    let op_at = rest.find(|c: char| "=!<>".contains(c));
+    let (field, op, literal) = match op_at {
+        None => (rest.trim(), FilterOp::Exists, Value::Null),
+        Some(at) => {
+            let (field, tail) = rest.split_at(at);
+            let (op, literal) = if let Some(l) = tail.strip_prefix("==") { (FilterOp::Eq, l) }
+                else if let Some(l) = tail.strip_prefix("!=") { (FilterOp::Ne, l) }
+                else if let Some(l) = tail.strip_prefix("<=") { (FilterOp::Le, l) }
+                else if let Some(l) = tail.strip_prefix(">=") { (FilterOp::Ge, l) }
+                else if let Some(l) = tail.strip_prefix('<') { (FilterOp::Lt, l) }
+                else if let Some(l) = tail.strip_prefix('>') { (FilterOp::Gt, l) }
+                else { return Err(format!("unknown operator in {:?}, use == != < <= > >=", cond)); };
+            let literal = literal.trim();
+            let literal = if literal.starts_with('\'') && literal.ends_with('\'') && literal.len() >= 2 {
+                Value::String(literal[1..literal.len() - 1].to_string())
+            } else {
+                serde_json::from_str(literal).map_err(|_| format!("cannot read the value {:?}, use a number, a quoted string, true, false or null", literal))?
+            };
+            (field.trim(), op, literal)
+        }
+    };
+    let path: Vec<String> = field.split('.').map(|s| s.to_string()).collect();
+    if path.iter().any(|p| p.is_empty()) {
+        return Err(format!("bad field name in {:?}", cond));
+    }
+    Ok(Step::Filter(path, op, literal))
+}
+
+fn filter_matches(v: &Value, path: &Vec<String>, op: &FilterOp, literal: &Value) -> bool {
+    let mut cur = v;
+    for p in path {
+        match cur.get(p) {
+            Some(x) => cur = x,
+            None => return false,
+        }
+    }
+    let ord = match (cur, literal) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64().zip(b.as_f64()).and_then(|(a, b)| a.partial_cmp(&b)),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    match op {
+        FilterOp::Exists => true,
+        FilterOp::Eq => cur == literal || ord == Some(std::cmp::Ordering::Equal),
+        FilterOp::Ne => cur != literal && ord != Some(std::cmp::Ordering::Equal),
+        FilterOp::Lt => ord.map_or(false, |o| o.is_lt()),
+        FilterOp::Le => ord.map_or(false, |o| o.is_le()),
+        FilterOp::Gt => ord.map_or(false, |o| o.is_gt()),
+        FilterOp::Ge => ord.map_or(false, |o| o.is_ge()),
+    }
+}
+
+fn collect_descendants<'a>(v: &'a Value, name: &str, out: &mut Vec<&'a Value>) {
+    match v {
+        Value::Object(map) => {
+            for (k, x) in map {
+                if k == name {
+                    out.push(x);
+                }
+                collect_descendants(x, name, out);
+            }
+        }
+        Value::Array(arr) => arr.iter().for_each(|x| collect_descendants(x, name, out)),
+        _ => {}
+    }
+}
+
+pub fn json_query<'a>(root: &'a Value, steps: &Vec<Step>) -> Vec<&'a Value> {
+    let mut current: Vec<&Value> = vec![root];
+    for step in steps {
+        let mut next = vec![];
+        for v in current {
+            match step {
+                Step::Key(k) => next.extend(v.get(k.as_str())),
+                Step::Index(n) => if let Value::Array(arr) = v {
+                    let idx = if *n < 0 { arr.len() as i64 + n } else { *n };
+                    if idx >= 0 {
+                        next.extend(arr.get(idx as usize));
+                    }
+                },
+                Step::Wildcard => match v {
+                    Value::Array(arr) => next.extend(arr.iter()),
+                    Value::Object(map) => next.extend(map.values()),
+                    _ => {}
+                },
+                Step::Descendant(name) => collect_descendants(v, name, &mut next),
+                Step::Filter(path, op, literal) => match v {
+                    Value::Array(arr) => next.extend(arr.iter().filter(|x| filter_matches(x, path, op, literal))),
+                    Value::Object(map) => next.extend(map.values().filter(|x| filter_matches(x, path, op, literal))),
+                    _ => {}
+                },
+            }
+        }
+        current = next;
+    }
+    current
+}
+
+pub fn json_query_report(json_text: &str, expression: &str) -> Result<String, String> {
+    let root: Value = serde_json::from_str(json_text)
+        .map_err(|e| format!("invalid JSON at line {} column {}: {}", e.line(), e.column(), e))?;
+    let steps = parse_json_path(expression)?;
+    let matches = json_query(&root, &steps);
+    if matches.is_empty() {
+        return Ok(format!("No matches for {}", expression.trim()));
+    }
+    let mut report = format!("{} matches for {}:\n", matches.len(), expression.trim());
+    for m in matches.iter() {
+        report.push_str(&serde_json::to_string_pretty(m).unwrap());
+        report.push('\n');
+    }
+    if report.len() > JSON_QUERY_MAX_CHARS {
+        let cut = (0..=JSON_QUERY_MAX_CHARS).rev().find(|i| report.is_char_boundary(*i)).unwrap_or(0);
+        let total = report.len();
+        report.truncate(cut);
+        report.push_str(&format!("\n...output is {} chars, the rest is cut, make the expression more specific\n", total));
+    }
+    Ok(report)
+}
+
+fn get_string_arg(args: &HashMap<String, Value>, name: &str) -> Result<Option<String>, String> {
+    match args.get(name) {
+        Some(Value::String(s)) if !s.trim().is_empty() => Ok(Some(s.clone())),
+        Some(Value::String(_)) | Some(Value::Null) | None => Ok(None),
+        Some(v) => Err(format!("argument `{}` is not a string: {:?}", name, v)),
+    }
+}
+
+#[async_trait]
+impl Tool for ToolJsonQuery {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let expression = get_string_arg(args, "expression")?.unwrap_or("$".to_string());
+        let json_text = match (get_string_arg(args, "path")?, get_string_arg(args, "json")?) {
+            (Some(path), None) => {
+                let gcx = ccx.lock().await.global_context.clone();
+                let candidates = file_repair_candidates(gcx.clone(), &path, 10, false).await;
+                let file_path = return_one_candidate_or_a_good_error(gcx.clone(), &path, &candidates, &get_project_dirs(gcx.clone()).await, false).await?;
+                get_file_text_from_memory_or_disk(gcx.clone(), &PathBuf::from(&file_path)).await?
+            }
+            (None, Some(json)) => json,
+            (Some(_), Some(_)) => return Err("give either `path` or `json`, not both".to_string()),
+            (None, None) => return Err("argument `path` or `json` is required".to_string()),
+        };
+        let report = json_query_report(&json_text, &expression)?;
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STORE: &str = r#"{"store": {"book": [
+        {"title": "Sayings of the Century", "price": 8.95, "tags": ["quotes"]},
+        {"title": "Sword of Honour", "price": 12.99},
+        {"title": "Moby Dick", "price": 8.99, "isbn": "0-553-21311-3"}
+    ], "bicycle": {"color": "red", "price": 19.95}}}"#;
+
+    #[test]
+    fn test_json_query_simple_path() {
+        let report = json_query_report(STORE, "$.store.book[0].title").unwrap();
+        assert_eq!(report, "1 matches for $.store.book[0].title:\n\"Sayings of the Century\"\n");
+        let report = json_query_report(STORE, ".store.book[-1]['title']").unwrap();
+        assert!(report.contains("\"Moby Dick\""), "{}", report);
+        let report = json_query_report(STORE, "$..price").unwrap();
+        assert!(report.starts_with("4 matches"), "{}", report);
+        assert_eq!(json_query_report(STORE, "$.store.car").unwrap(), "No matches for $.store.car");
+    }
+
+    #[test]
+    fn test_json_query_array_filter() {
+        let report = json_query_report(STORE, "$.store.book[?(@.price < 10)].title").unwrap();
+        assert_eq!(report, "2 matches for $.store.book[?(@.price < 10)].title:\n\"Sayings of the Century\"\n\"Moby Dick\"\n");
+        let report = json_query_report(STORE, "$.store.book[?(@.isbn)].title").unwrap();
+        assert!(report.contains("Moby Dick") && report.starts_with("1 matches"), "{}", report);
+        let report = json_query_report(STORE, "$.store.book[?(@.title == 'Sword of Honour')].price").unwrap();
+        assert!(report.contains("12.99"), "{}", report);
+    }
+
+    #[test]
+    fn test_json_query_errors() {
+        let e = json_query_report(STORE, "$.store.book[0").unwrap_err();
+        assert!(e.contains("unclosed [") && e.contains("position 12"), "{}", e);
+        let e = json_query_report(STORE, "$.store.book[?(@.price ~ 10)]").unwrap_err();
+        assert!(e.contains("unknown operator"), "{}", e);
+        let e = json_query_report("{\"a\": [1, 2,]}", "$.a").unwrap_err();
+        assert!(e.starts_with("invalid JSON at line 1 column"), "{}", e);
+
+        let long = format!("[{}]", (0..5000).map(|i| i.to_string()).collect::<Vec<_>>().join(","));
+        let report = json_query_report(&long, "$[*]").unwrap();
+        assert!(report.len() < JSON_QUERY_MAX_CHARS + 200 && report.contains("the rest is cut"), "{}", report.len());
+    }
+}
@@ -8,7 +8,7 @@ use async_trait::async_trait;
 use crate::at_commands::at_commands::AtCommandsContext;
 use crate::tools::tools_description::Tool;
 use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
-use crate::vecdb::vdb_highlev::memories_search;
+use crate::vecdb::vdb_highlev::memories_search_with_tag;
 
 
 pub struct ToolGetKnowledge;
@@ -51,12 +51,17 @@ impl Tool for ToolGetKnowledge {
             Some(v) => { return Err(format!("argument `language_slash_framework` is not a string: {:?}", v)) },
             None => { return Err("argument `language_slash_framework` is missing".to_string()) }
         };
+        let tag = match args.get("tag") {
+            Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim().to_string()),
+            Some(Value::String(_)) | None => None,
+            Some(v) => { return Err(format!("argument `tag` is not a string: {:?}", v)) },
+        };
 
         let mem_top_n = 3;
-        let memories1: crate::vecdb::vdb_structs::MemoSearchResult = memories_search(gcx.clone(), &im_going_to_use_tools, mem_top_n).await?;
-        let memories2: crate::vecdb::vdb_structs::MemoSearchResult = memories_search(gcx.clone(), &im_going_to_apply_to, mem_top_n).await?;
-        let memories3: crate::vecdb::vdb_structs::MemoSearchResult = memories_search(gcx.clone(), &goal, mem_top_n).await?;
-        let memories4: crate::vecdb::vdb_structs::MemoSearchResult = memories_search(gcx.clone(), &language_slash_framework, mem_top_n).await?;
+        let memories1: crate::vecdb::vdb_structs::MemoSearchResult = memories_search_with_tag(gcx.clone(), &im_going_to_use_tools, mem_top_n, tag.as_deref()).await?;
+        let memories2: crate::vecdb::vdb_structs::MemoSearchResult = memories_search_with_tag(gcx.clone(), &im_going_to_apply_to, mem_top_n, tag.as_deref()).await?;
+        let memories3: crate::vecdb::vdb_structs::MemoSearchResult = memories_search_with_tag(gcx.clone(), &goal, mem_top_n, tag.as_deref()).await?;
+        let memories4: crate::vecdb::vdb_structs::MemoSearchResult = memories_search_with_tag(gcx.clone(), &language_slash_framework, mem_top_n, tag.as_deref()).await?;
         let combined_memories = [memories1.results, memories2.results, memories3.results, memories4.results].concat();
         let mut seen_memids = HashSet::new();
         let unique_memories: Vec<_> = combined_memories.into_iter()
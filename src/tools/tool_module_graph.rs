@@ -0,0 +1,276 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::ast::treesitter::ast_instance_structs::{ImportDeclaration, ImportType};
+use crate::ast::treesitter::parsers::{get_ast_parser_by_filename, get_language_id_by_filename};
+use crate::ast::treesitter::structs::SymbolType;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_project_dirs;
+use crate::files_in_workspace::get_file_text_from_memory_or_disk;
+use crate::tools::tools_description::Tool;
+use crate::tools::tools_utils::{is_under_subdir, short_path};
+
+
+const MODULE_GRAPH_MAX_FILES: usize = 3000;
+const SUFFIX_INDEX_DEPTH: usize = 6;
+const INDEX_FILE_STEMS: &[&str] = &["__init__", "index", "mod"];
+
+pub struct ToolModuleGraph;
+
+pub type ModuleGraph = BTreeMap<PathBuf, BTreeSet<PathBuf>>;
+
+// Python "a.b", Rust "crate::a::b", Java "a.b.C", JS "./a/b" and C++ "a/b.h" all come out of the parsers
+// as path_components, only workspace-local imports are kept, stdlib and libraries are not part of the graph
+pub fn file_imports(path: &PathBuf, text: &str) -> Result<Vec<Vec<String>>, String> {
+    let (mut parser, _language_id) = get_ast_parser_by_filename(path).map_err(|e| e.message)?;
+    let mut result = vec![];
+    for symbol in parser.parse(text, path) {
+        let symbol = symbol.read();
+        if symbol.symbol_type() != SymbolType::ImportDeclaration {
+            continue;
+        }
+        if let Some(import) = symbol.as_any().downcast_ref::<ImportDeclaration>() {
+            if import.import_type == ImportType::System || import.import_type == ImportType::Library || import.path_components.is_empty() {
+                continue;
+            }
+            result.push(import.path_components.clone());
+        }
+    }
+    Ok(result)
+}
+
+fn path_without_extension(path: &Path) -> PathBuf {
+    path.with_extension("")
+}
+
+struct ModuleIndex {
+    full: HashMap<PathBuf, PathBuf>,          // with and without extension -> file
+    suffixes: HashMap<String, Vec<PathBuf>>,  // "pkg/b", "b.h" -> files ending like that
+}
+
+impl ModuleIndex {
+    fn new(files: &Vec<PathBuf>) -> Self {
+        let mut full = HashMap::new();
+        let mut suffixes: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for file in files.iter() {
+            for variant in [file.clone(), path_without_extension(file)] {
+                full.insert(variant.clone(), file.clone());
+                let parts: Vec<String> = variant.iter().map(|x| x.to_string_lossy().to_string()).collect();
+                for depth in 1..=SUFFIX_INDEX_DEPTH.min(parts.len()) {
+                    let entry = suffixes.entry(parts[parts.len() - depth..].join("/")).or_default();
+                    if !entry.contains(file) {
+                        entry.push(file.clone());
+                    }
+                }
+            }
+        }
+        ModuleIndex { full, suffixes }
+    }
+
+    fn lookup_full(&self, candidate: &PathBuf) -> Option<PathBuf> {
+        if let Some(file) = self.full.get(candidate) {
+            return Some(file.clone());
+        }
+        INDEX_FILE_STEMS.iter().find_map(|stem| self.full.get(&candidate.join(stem)).cloned())
+    }
+
+    fn lookup_suffix(&self, importer: &Path, candidate: &str) -> Option<PathBuf> {
+        let mut found: Vec<&PathBuf> = self.suffixes.get(candidate).map(|x| x.iter().collect()).unwrap_or_default();
+        for stem in INDEX_FILE_STEMS.iter() {
+            if let Some(files) = self.suffixes.get(&format!("{}/{}", candidate, stem)) {
+                found.extend(files.iter());
+            }
+        }
+        // several files with the same name: the one closest to the importer
+        found.into_iter()
+            .max_by_key(|f| (f.components().zip(importer.components()).take_while(|(a, b)| a == b).count(), std::cmp::Reverse(f.components().count())))
+            .cloned()
+    }
+}
+
+fn resolve_import(importer: &PathBuf, components: &Vec<String>, index: &ModuleIndex) -> Option<PathBuf> {
+    let importer_dir = importer.parent()?.to_path_buf();
+    let is_rust_mod_root = matches!(importer.file_stem().and_then(|s| s.to_str()), Some("mod") | Some("lib") | Some("main"));
+    let mut base = importer_dir.clone();
+    let mut relative = false;
+    let mut rest: Vec<&str> = vec![];
+    for (i, c) in components.iter().enumerate() {
+        match c.as_str() {
+            "." | "self" if rest.is_empty() => { relative = true; }
+            ".." if rest.is_empty() => { relative = true; base = base.parent().map(|p| p.to_path_buf()).unwrap_or(base); }
+            "super" if rest.is_empty() => {
+                relative = true;
+                if is_rust_mod_root || i > 0 {
+                    base = base.parent().map(|p| p.to_path_buf()).unwrap_or(base);
+                }
+            }
+            "crate" if i == 0 => {}
+            _ => rest.push(c.as_str()),
+        }
+    }
+    // "from pkg.b import f" gives pkg, b, f: the longest prefix that is a file wins
+    for k in (0..=rest.len()).rev() {
+        let candidate = rest[..k].join("/");
+        if relative || k > 0 {
+            if let Some(file) = index.lookup_full(&base.join(&candidate)) {
+                return Some(file);
+            }
+        }
+        if !relative && k > 0 {
+            if let Some(file) = index.lookup_suffix(importer, &candidate) {
+                return Some(file);
+            }
+        }
+    }
+    None
+}
+
+// One import, resolved against the given workspace files
+pub fn resolve_import_among(importer: &PathBuf, components: &Vec<String>, files: &Vec<PathBuf>) -> Option<PathBuf> {
+    resolve_import(importer, components, &ModuleIndex::new(files))
+}
+
+pub fn build_module_graph(files: &Vec<(PathBuf, String)>) -> (ModuleGraph, usize) {
+    let index = ModuleIndex::new(&files.iter().map(|(p, _)| p.clone()).collect());
+    let mut graph = ModuleGraph::new();
+    let mut unresolved_n = 0;
+    for (path, text) in files.iter() {
+        let deps = graph.entry(path.clone()).or_default();
+        for components in file_imports(path, text).unwrap_or_default() {
+            match resolve_import(path, &components, &index) {
+                Some(target) if target != *path => { deps.insert(target); }
+                Some(_) => {}
+                None => unresolved_n += 1,
+            }
+        }
+    }
+    (graph, unresolved_n)
+}
+
+pub fn format_module_graph(graph: &ModuleGraph, project_dirs: &Vec<PathBuf>, dot: bool) -> String {
+    let edges_n: usize = graph.values().map(|deps| deps.len()).sum();
+    let mut out = String::new();
+    if dot {
+        out.push_str("digraph modules {\n");
+        for (file, deps) in graph.iter() {
+            if deps.is_empty() {
+                out.push_str(&format!("  {:?};\n", short_path(file, project_dirs)));
+            }
+            for dep in deps.iter() {
+                out.push_str(&format!("  {:?} -> {:?};\n", short_path(file, project_dirs), short_path(dep, project_dirs)));
+            }
+        }
+        out.push_str("}\n");
+        return out;
+    }
+    out.push_str(&format!("{} files, {} dependencies, each line is a file and the files it imports:\n", graph.len(), edges_n));
+    for (file, deps) in graph.iter().filter(|(_, deps)| !deps.is_empty()) {
+        out.push_str(&format!("{} -> {}\n", short_path(file, project_dirs), deps.iter().map(|d| short_path(d, project_dirs)).collect::<Vec<_>>().join(", ")));
+    }
+    let standalone: Vec<String> = graph.iter().filter(|(_, deps)| deps.is_empty()).map(|(f, _)| short_path(f, project_dirs)).collect();
+    if !standalone.is_empty() {
+        out.push_str(&format!("Files that import nothing from the workspace: {}\n", standalone.join(", ")));
+    }
+    out
+}
+
+#[async_trait]
+impl Tool for ToolModuleGraph {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let subdir = match args.get("path") {
+            Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim().trim_start_matches("./").to_string()),
+            Some(Value::String(_)) | None => None,
+            Some(v) => return Err(format!("argument `path` is not a string: {:?}", v)),
+        };
+        let dot = match args.get("format") {
+            Some(Value::String(s)) if s == "dot" => true,
+            Some(Value::String(s)) if s == "text" || s.is_empty() => false,
+            None => false,
+            Some(v) => return Err(format!("argument `format` should be \"text\" or \"dot\", got {:?}", v)),
+        };
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let project_dirs = get_project_dirs(gcx.clone()).await;
+        let workspace_files = gcx.read().await.documents_state.workspace_files.lock().unwrap().clone();
+        let mut paths: Vec<PathBuf> = workspace_files.into_iter()
+            .filter(|p| get_language_id_by_filename(p).is_some())
+            .filter(|p| subdir.as_ref().map_or(true, |d| is_under_subdir(p, d, &project_dirs)))
+            .collect();
+        if paths.is_empty() {
+            return Err(format!("no source files with a parser{}", subdir.map(|d| format!(" under {:?}", d)).unwrap_or_default()));
+        }
+        paths.sort();
+        let truncated = paths.len() > MODULE_GRAPH_MAX_FILES;
+        paths.truncate(MODULE_GRAPH_MAX_FILES);
+
+        let mut files = vec![];
+        for path in paths {
+            if let Ok(text) = get_file_text_from_memory_or_disk(gcx.clone(), &path).await {
+                files.push((path, text));
+            }
+        }
+        // thousands of files through the parsers is CPU work, it shouldn't stall the other tasks on the runtime
+        let (graph, unresolved_n) = tokio::task::spawn_blocking(move || build_module_graph(&files))
+            .await
+            .map_err(|e| format!("module graph task failed: {}", e))?;
+        let mut report = format_module_graph(&graph, &project_dirs, dot);
+        if !dot && unresolved_n > 0 {
+            report.push_str(&format!("{} imports not resolved to workspace files (external packages or generated code)\n", unresolved_n));
+        }
+        if truncated {
+            report.push_str(&format!("Only the first {} files are in the graph, use `path` to look at a part of the project\n", MODULE_GRAPH_MAX_FILES));
+        }
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_python_modules_graph() {
+        let root = PathBuf::from("/project");
+        let files = vec![
+            (root.join("app/main.py"), "import os\nfrom app import orders\nfrom .utils import slugify\n\ndef main():\n    orders.run()\n".to_string()),
+            (root.join("app/orders.py"), "import json\nimport app.db as db\n\ndef run():\n    db.save(json.dumps({}))\n".to_string()),
+            (root.join("app/db.py"), "import sqlite3\n\ndef save(x):\n    pass\n".to_string()),
+            (root.join("app/utils.py"), "def slugify(s):\n    return s.lower()\n".to_string()),
+        ];
+        let (graph, _unresolved_n) = build_module_graph(&files);
+        let deps_of = |name: &str| graph[&root.join(name)].iter().map(|p| p.strip_prefix(&root).unwrap().to_string_lossy().to_string()).collect::<Vec<_>>();
+        assert_eq!(deps_of("app/main.py"), vec!["app/orders.py", "app/utils.py"]);
+        assert_eq!(deps_of("app/orders.py"), vec!["app/db.py"]);
+        assert!(deps_of("app/db.py").is_empty());
+
+        let text = format_module_graph(&graph, &vec![root.clone()], false);
+        assert!(text.contains("app/main.py -> app/orders.py, app/utils.py\n"), "{}", text);
+        assert!(text.starts_with("4 files, 3 dependencies"), "{}", text);
+        let dot = format_module_graph(&graph, &vec![root.clone()], true);
+        assert!(dot.contains("  \"app/orders.py\" -> \"app/db.py\";\n"), "{}", dot);
+        assert!(dot.starts_with("digraph modules {\n") && dot.ends_with("}\n"), "{}", dot);
+    }
+}
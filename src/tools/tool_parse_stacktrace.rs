@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::{correct_to_nearest_filename, paths_from_anywhere, shortify_paths};
+use crate::files_in_workspace::get_file_text_from_memory_or_disk;
+use crate::tools::tools_description::Tool;
+
+
+pub struct ToolParseStacktrace;
+
+lazy_static! {
+    static ref PYTHON_FRAME_RE: Regex = Regex::new(r#"File "([^"]+)", line (\d+)(?:, in (\S+))?"#).unwrap();
+    static ref JAVA_FRAME_RE: Regex = Regex::new(r"at\s+([\w$.<>/]+)\(([\w$]+\.(?:java|kt|scala|groovy)):(\d+)\)").unwrap();
+    static ref NODE_FRAME_RE: Regex = Regex::new(r"at\s+(?:(\S.*?)\s+\()?((?:[A-Za-z]:)?[^\s():]+\.(?:js|cjs|mjs|jsx|ts|tsx)):(\d+)(?::\d+)?\)?").unwrap();
+    static ref RUST_FRAME_RE: Regex = Regex::new(r"(?:\bat|panicked at(?: '.*?',)?)\s+((?:[A-Za-z]:)?[^\s:']+\.rs):(\d+)").unwrap();
+    static ref RUST_FUNC_RE: Regex = Regex::new(r"^\s*\d+:\s+(?:0x[0-9a-f]+ - )?(\S+)").unwrap();
+}
+
+// Frames from these never point at the user's code
+const LIBRARY_MARKERS: &[&str] = &["site-packages", "dist-packages", "node_modules", "node:internal", "/rustc/", ".cargo/registry", "<frozen", "/lib/python"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StacktraceFormat {
+    Python,
+    Java,
+    Node,
+    Rust,
+}
+
+#[derive(Debug, Clone)]
+pub struct StackFrame {
+    pub file: String,
+    pub line1: usize,
+    pub func: Option<String>,
+    pub format: StacktraceFormat,
+}
+
+impl StackFrame {
+    pub fn is_library(&self) -> bool {
+        let file = self.file.replace('\\', "/");
+        LIBRARY_MARKERS.iter().any(|m| file.contains(m))
+    }
+}
+
+// Frames in the order they appear in the text
+pub fn parse_stacktrace(text: &str) -> Vec<StackFrame> {
+    let mut frames = vec![];
+    let mut rust_func: Option<String> = None;
+    for line in text.lines() {
+        if let Some(caps) = PYTHON_FRAME_RE.captures(line) {
+            frames.push(StackFrame {
+                file: caps[1].to_string(),
+                line1: caps[2].parse().unwrap_or(0),
+                func: caps.get(3).map(|m| m.as_str().to_string()),
+                format: StacktraceFormat::Python,
+            });
+        } else if let Some(caps) = JAVA_FRAME_RE.captures(line) {
+            // only the file name is printed, the package of the class gives the directories
+            let qualified = caps[1].to_string();
+            let mut package: Vec<&str> = qualified.split('.').collect();
+            package.truncate(package.len().saturating_sub(2));
+            package.push(&caps[2]);
+            frames.push(StackFrame {
+                file: package.join("/"),
+                line1: caps[3].parse().unwrap_or(0),
+                func: Some(qualified.rsplit('.').take(2).collect::<Vec<_>>().into_iter().rev().collect::<Vec<_>>().join(".")),
+                format: StacktraceFormat::Java,
+            });
+        } else if let Some(caps) = NODE_FRAME_RE.captures(line) {
+            frames.push(StackFrame {
+                file: caps[2].trim_start_matches("file://").to_string(),
+                line1: caps[3].parse().unwrap_or(0),
+                func: caps.get(1).map(|m| m.as_str().to_string()),
+                format: StacktraceFormat::Node,
+            });
+        } else if let Some(caps) = RUST_FRAME_RE.captures(line) {
+            frames.push(StackFrame {
+                file: caps[1].to_string(),
+                line1: caps[2].parse().unwrap_or(0),
+                func: rust_func.take(),
+                format: StacktraceFormat::Rust,
+            });
+        } else if let Some(caps) = RUST_FUNC_RE.captures(line) {
+            rust_func = Some(caps[1].to_string());
+        }
+    }
+    frames.retain(|f| f.line1 > 0);
+    frames
+}
+
+fn path_components(p: &str) -> Vec<String> {
+    p.split(|c| c == '/' || c == '\\').filter(|x| !x.is_empty() && *x != "." && *x != "..").map(|x| x.to_string()).collect()
+}
+
+// The workspace file sharing the longest tail with the frame path, the trace often comes from another machine or a container
+pub fn resolve_frame_path(frame_file: &str, workspace_files: &Vec<PathBuf>) -> Option<PathBuf> {
+    let wanted = path_components(frame_file);
+    if wanted.is_empty() {
+        return None;
+    }
+    let mut best: Option<PathBuf> = None;
+    let mut best_len = 0;
+    let mut best_ambiguous = false;
+    for f in workspace_files {
+        let have = path_components(&f.to_string_lossy());
+        let common = wanted.iter().rev().zip(have.iter().rev()).take_while(|(a, b)| a == b).count();
+        if common == 0 {
+            continue;
+        }
+        if common > best_len {
+            best = Some(f.clone());
+            best_len = common;
+            best_ambiguous = false;
+        } else if common == best_len {
+            best_ambiguous = true;
+        }
+    }
+    if best_ambiguous { None } else { best }
+}
+
+// Lines around line1 with numbers, the frame line is marked
+pub fn source_lines(text: &str, line1: usize, context: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    if line1 == 0 || line1 > lines.len() {
+        return String::new();
+    }
+    let from = line1.saturating_sub(context).max(1);
+    let to = (line1 + context).min(lines.len());
+    (from..=to).map(|n| format!("{}{:>5} | {}\n", if n == line1 { ">" } else { " " }, n, lines[n - 1])).collect()
+}
+
+// Python prints the innermost frame last, the others first
+pub fn likely_culprit(frames: &Vec<(StackFrame, Option<PathBuf>)>) -> Option<usize> {
+    let mut resolved = frames.iter().enumerate().filter(|(_, (_, path))| path.is_some()).map(|(i, _)| i);
+    match frames.first().map(|(f, _)| f.format.clone()) {
+        Some(StacktraceFormat::Python) => resolved.last(),
+        _ => resolved.next(),
+    }
+}
+
+#[async_trait]
+impl Tool for ToolParseStacktrace {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let stacktrace = match args.get("stacktrace") {
+            Some(Value::String(s)) if !s.trim().is_empty() => s.clone(),
+            Some(Value::String(_)) | None => return Err("Missing argument `stacktrace`".to_string()),
+            Some(v) => return Err(format!("argument `stacktrace` is not a string: {:?}", v)),
+        };
+        let frames = parse_stacktrace(&stacktrace);
+        if frames.is_empty() {
+            return Err("No file:line frames found, supported are Python, Java, Node and Rust stack traces".to_string());
+        }
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let workspace_files = paths_from_anywhere(gcx.clone()).await;
+        let mut resolved: Vec<(StackFrame, Option<PathBuf>)> = vec![];
+        for frame in frames {
+            if frame.is_library() {
+                resolved.push((frame, None));
+                continue;
+            }
+            let mut path = None;
+            let candidates = correct_to_nearest_filename(gcx.clone(), &frame.file, false, 2).await;
+            if candidates.len() == 1 {
+                path = Some(PathBuf::from(&candidates[0]));
+            }
+            if path.is_none() {
+                path = resolve_frame_path(&frame.file, &workspace_files);
+            }
+            resolved.push((frame, path));
+        }
+
+        let culprit = likely_culprit(&resolved);
+        let resolved_paths = resolved.iter().filter_map(|(_, p)| p.as_ref().map(|p| p.to_string_lossy().to_string())).collect::<Vec<_>>();
+        let short_paths = shortify_paths(gcx.clone(), &resolved_paths).await;
+        let mut short_paths_iter = short_paths.iter();
+        let mut report = String::new();
+        let mut unresolved = vec![];
+        for (i, (frame, path)) in resolved.iter().enumerate() {
+            let func = frame.func.as_ref().map(|f| format!(" in {}", f)).unwrap_or_default();
+            let Some(path) = path else {
+                unresolved.push(format!("{}:{}{}{}", frame.file, frame.line1, func, if frame.is_library() { " (library)" } else { "" }));
+                continue;
+            };
+            let short_path = short_paths_iter.next().cloned().unwrap_or(path.to_string_lossy().to_string());
+            let marker = if Some(i) == culprit { " <- likely culprit" } else { "" };
+            report.push_str(&format!("{}:{}{}{}\n", short_path, frame.line1, func, marker));
+            match get_file_text_from_memory_or_disk(gcx.clone(), path).await {
+                Ok(text) => report.push_str(&source_lines(&text, frame.line1, if Some(i) == culprit { 3 } else { 1 })),
+                Err(e) => report.push_str(&format!("  cannot read: {}\n", e)),
+            }
+            report.push('\n');
+        }
+        if report.is_empty() {
+            report.push_str("None of the frames are workspace files\n");
+        }
+        if !unresolved.is_empty() {
+            report.push_str(&format!("Not in the workspace:\n{}\n", unresolved.join("\n")));
+        }
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_python_traceback_resolves_to_workspace_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        std::fs::create_dir_all(project.join("app")).unwrap();
+        std::fs::create_dir_all(project.join("tests")).unwrap();
+        std::fs::write(project.join("app").join("main.py"), "from app.lib import compute_total\n\n\ndef report(items):\n    total = compute_total(items)\n    return total\n").unwrap();
+        std::fs::write(project.join("app").join("lib.py"), "def compute_total(items):\n    return sum(items)\n").unwrap();
+        std::fs::write(project.join("tests").join("lib.py"), "def test_nothing():\n    pass\n").unwrap();
+        let gcx = crate::global_context::create_test_global_context(dir.path(), &["--workspace-folder", &project.to_string_lossy()]).await;
+        crate::files_in_workspace::enqueue_all_files_from_workspace_folders(gcx.clone(), false, false).await;
+
+        // the first frame comes from the machine where it crashed, the second is relative like `python -m` prints it
+        let traceback = r#"Traceback (most recent call last):
+  File "/home/dev/project/app/main.py", line 5, in report
+    total = compute_total(items)
+  File "app/lib.py", line 2, in compute_total
+    return sum(items)
+  File "/usr/lib/python3.11/site-packages/numbers/fsum.py", line 10, in fsum
+TypeError: unsupported operand type(s) for +: 'int' and 'str'
+"#;
+        let frames = parse_stacktrace(traceback);
+        assert_eq!(frames.len(), 3);
+        assert_eq!((frames[1].line1, frames[1].func.as_deref()), (2, Some("compute_total")));
+        assert!(frames[2].is_library());
+
+        let ccx = Arc::new(AMutex::new(AtCommandsContext::new(gcx.clone(), 8192, 5, false, vec![], "".to_string(), false).await));
+        let args = HashMap::from([("stacktrace".to_string(), Value::String(traceback.to_string()))]);
+        let (_, results) = ToolParseStacktrace {}.tool_execute(ccx, &"call_1".to_string(), &args).await.unwrap();
+        let report = match &results[0] {
+            ContextEnum::ChatMessage(m) => m.content.content_text_only(),
+            _ => panic!("expected a tool message"),
+        };
+        assert!(report.contains("main.py:5 in report\n"), "{}", report);
+        assert!(report.contains("    5 |     total = compute_total(items)"), "{}", report);
+        assert!(report.contains("lib.py:2 in compute_total <- likely culprit"), "{}", report);
+        assert!(report.contains(">    2 |     return sum(items)"), "{}", report);
+        assert!(!report.contains("test_nothing"), "{}", report);
+        assert!(report.contains("Not in the workspace:\n/usr/lib/python3.11/site-packages/numbers/fsum.py:10 in fsum (library)"), "{}", report);
+
+        // only the file name in common with two files is ambiguous
+        let workspace_files = paths_from_anywhere(gcx.clone()).await;
+        assert_eq!(resolve_frame_path("/elsewhere/lib.py", &workspace_files), None);
+    }
+
+    #[test]
+    fn test_java_node_rust_frames() {
+        let java = "Exception in thread \"main\" java.lang.NullPointerException\n\tat com.acme.shop.Cart.total(Cart.java:42)\n\tat com.acme.shop.Main.main(Main.java:7)\n";
+        let frames = parse_stacktrace(java);
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].file.as_str(), frames[0].line1, frames[0].func.as_deref()), ("com/acme/shop/Cart.java", 42, Some("Cart.total")));
+
+        let node = "TypeError: x is undefined\n    at render (/app/src/components/List.tsx:12:5)\n    at /app/src/index.js:3:1\n    at Module._compile (node:internal/modules/cjs/loader:1105:14)\n";
+        let frames = parse_stacktrace(node);
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].file.as_str(), frames[0].func.as_deref()), ("/app/src/components/List.tsx", Some("render")));
+        assert_eq!((frames[1].file.as_str(), frames[1].line1, frames[1].func.as_deref()), ("/app/src/index.js", 3, None));
+
+        let rust = "thread 'main' panicked at src/parser.rs:88:14:\nindex out of bounds\nstack backtrace:\n   0: rust_begin_unwind\n             at /rustc/abc/library/std/src/panicking.rs:645:5\n   1: mycrate::parser::parse\n             at ./src/parser.rs:88:14\n";
+        let frames = parse_stacktrace(rust);
+        assert_eq!(frames.len(), 3);
+        assert_eq!((frames[0].file.as_str(), frames[0].line1), ("src/parser.rs", 88));
+        assert!(frames[1].is_library());
+        assert_eq!(frames[2].func.as_deref(), Some("mycrate::parser::parse"));
+    }
+}
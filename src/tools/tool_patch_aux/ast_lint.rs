@@ -47,7 +47,7 @@ pub fn lint_and_get_error_messages(
     let new_filename = dummy_filename.with_extension(
         path.extension().unwrap_or_default()
     );
-    let doc = Document { doc_path: new_filename.clone(), doc_text: Some(Rope::from_str(file_text)) };
+    let doc = Document { doc_text: Some(Rope::from_str(file_text)), ..Document::new(&new_filename) };
     match lint(&doc) {
         Ok(_) => vec![],
         Err(problems) => problems,
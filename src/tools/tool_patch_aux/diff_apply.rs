@@ -150,6 +150,10 @@ pub async fn diff_apply(
     chunks: &mut Vec<DiffChunk>,
 ) -> Result<(), String> {
     correct_and_validate_chunks(gcx.clone(), chunks).await?;
+    let file_locks = gcx.read().await.file_locks.clone();
+    let locked = file_locks.lock_paths(
+        chunks.iter().flat_map(|c| [Some(c.file_name.clone()), c.file_name_rename.clone()]).flatten().map(PathBuf::from)
+    ).await;
     let (results, outputs) = read_files_n_apply_diff_chunks(
         gcx.clone(),
         &chunks,
@@ -160,6 +164,7 @@ pub async fn diff_apply(
     let new_documents = write_results_on_disk(
         gcx.clone(), results.clone(),
     ).await?;
+    drop(locked);  // indexing below can take a while, other patches don't need to wait for it
     let outputs_unwrapped = unwrap_diff_apply_outputs(outputs, chunks.clone());
     set_chunks_detail_and_sync_documents_ast_vecdb(gcx.clone(), new_documents, outputs_unwrapped, chunks).await
 }
@@ -0,0 +1,105 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex as StdMutex, Weak};
+use tokio::sync::{Mutex as AMutex, OwnedMutexGuard};
+
+
+// Edits of the same file go one after another, so a patch never reads text that another patch is about to overwrite.
+// Edits of different files still run in parallel.
+#[derive(Default)]
+pub struct FileLocks {
+    locks: StdMutex<HashMap<PathBuf, Weak<AMutex<()>>>>,
+}
+
+pub struct FileLocksGuard {
+    _guards: Vec<OwnedMutexGuard<()>>,
+}
+
+impl FileLocks {
+    pub async fn lock_paths<I: IntoIterator<Item = PathBuf>>(&self, paths: I) -> FileLocksGuard {
+        // always in the same order, two patches touching the same two files can't deadlock
+        let paths: BTreeSet<PathBuf> = paths.into_iter().collect();
+        let mutexes: Vec<Arc<AMutex<()>>> = {
+            let mut locks = self.locks.lock().unwrap();
+            // holders and waiters keep a strong reference, so what's left is files nobody edits anymore
+            locks.retain(|_, weak| weak.strong_count() > 0);
+            paths.into_iter().map(|path| {
+                if let Some(mutex) = locks.get(&path).and_then(|weak| weak.upgrade()) {
+                    return mutex;
+                }
+                let mutex = Arc::new(AMutex::new(()));
+                locks.insert(path, Arc::downgrade(&mutex));
+                mutex
+            }).collect()
+        };
+        let mut guards = Vec::with_capacity(mutexes.len());
+        for mutex in mutexes {
+            guards.push(mutex.lock_owned().await);
+        }
+        FileLocksGuard { _guards: guards }
+    }
+
+    #[cfg(test)]
+    fn entries_n(&self) -> usize {
+        self.locks.lock().unwrap().len()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::call_validation::DiffChunk;
+    use crate::global_context::create_test_global_context;
+    use crate::tools::tool_patch_aux::diff_apply::diff_apply;
+
+    fn edit(file_name: &str, line1: usize, lines_remove: &str, lines_add: &str) -> DiffChunk {
+        DiffChunk {
+            file_name: file_name.to_string(),
+            file_action: "edit".to_string(),
+            line1,
+            line2: line1 + 1,
+            lines_remove: lines_remove.to_string(),
+            lines_add: lines_add.to_string(),
+            file_name_rename: None,
+            is_file: true,
+            application_details: "".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_patches_to_the_same_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().join("workspace");
+        std::fs::create_dir_all(&workspace).unwrap();
+        let path = workspace.join("orders.py");
+        let other = workspace.join("other.py");
+        tokio::fs::write(&path, "a = 1\nb = 2\nc = 3\n").await.unwrap();
+        tokio::fs::write(&other, "x = 1\n").await.unwrap();
+        let p = path.to_string_lossy().to_string();
+        let gcx = create_test_global_context(dir.path(), &["--workspace-folder", &workspace.to_string_lossy()]).await;
+
+        let mut chunks1 = vec![edit(&p, 1, "a = 1\n", "a = 10\n")];
+        let mut chunks2 = vec![edit(&p, 3, "c = 3\n", "c = 30\n")];
+        let mut chunks3 = vec![edit(&other.to_string_lossy(), 1, "x = 1\n", "x = 5\n")];
+        let (r1, r2, r3) = tokio::join!(
+            diff_apply(gcx.clone(), &mut chunks1),
+            diff_apply(gcx.clone(), &mut chunks2),
+            diff_apply(gcx.clone(), &mut chunks3),
+        );
+        r1.unwrap();
+        r2.unwrap();
+        r3.unwrap();
+        // whichever went second read the text the first one wrote
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), "a = 10\nb = 2\nc = 30\n");
+        assert_eq!(tokio::fs::read_to_string(&other).await.unwrap(), "x = 5\n");
+        for c in chunks1.iter().chain(chunks2.iter()).chain(chunks3.iter()) {
+            assert_eq!(c.application_details, "Chunk applied successfully");
+        }
+
+        // nothing is locked now, the next lock clears what's left
+        let locks = gcx.read().await.file_locks.clone();
+        let _locked = locks.lock_paths(vec![workspace.join("third.py")]).await;
+        assert_eq!(locks.entries_n(), 1);
+    }
+}
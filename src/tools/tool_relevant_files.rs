@@ -95,6 +95,7 @@ impl Tool for ToolRelevantFiles {
             ).await;
             t.subchat_tx = ccx_lock.subchat_tx.clone();
             t.subchat_rx = ccx_lock.subchat_rx.clone();
+            t.tools_permissions = ccx_lock.tools_permissions.clone();
             Arc::new(AMutex::new(t))
         };
 
@@ -0,0 +1,335 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::sync::Mutex as AMutex;
+
+use crate::ast::ast_db::{definitions, usages};
+use crate::ast::ast_structs::AstDB;
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum, DiffChunk};
+use crate::files_in_workspace::get_file_text_from_memory_or_disk;
+use crate::integrations::integr_abstract::IntegrationConfirmation;
+use crate::tools::tool_patch_aux::diff_apply::diff_apply;
+use crate::tools::tools_description::{MatchConfirmDeny, MatchConfirmDenyResult, Tool};
+
+
+const USAGES_LIMIT: usize = 1000;
+
+pub struct ToolRenameSymbol;
+
+#[derive(Debug, Default)]
+pub struct RenamePlan {
+    pub chunks: Vec<DiffChunk>,
+    pub renamed_n: usize,
+    pub unresolved: Vec<String>,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_identifier(s: &str) -> bool {
+    !s.is_empty() && !s.starts_with(|c: char| c.is_ascii_digit()) && s.chars().all(is_ident_char)
+}
+
+fn lexical_rules(cpath: &str) -> (&'static str, &'static str) {
+    // (line comment, string quotes), single quote in rust is a char literal or a lifetime, too ambiguous to track
+    let ext = cpath.rsplit('.').next().unwrap_or("").to_lowercase();
+    match ext.as_str() {
+        "py" | "pyi" | "rb" | "sh" | "yaml" | "yml" | "toml" => ("#", "'\""),
+        "rs" => ("//", "\""),
+        "sql" => ("--", "'"),
+        _ => ("//", "'\"`"),
+    }
+}
+
+pub fn rename_in_code_line(line: &str, old: &str, new: &str, comment: &str, quotes: &str) -> (String, usize) {
+    let mut result = String::with_capacity(line.len());
+    let mut renamed_n = 0;
+    let mut in_string: Option<char> = None;
+    let mut prev: Option<char> = None;
+    let mut i = 0;
+    while i < line.len() {
+        let rest = &line[i..];
+        let c = rest.chars().next().unwrap();
+        if let Some(q) = in_string {
+            if c == '\\' {
+                let escaped_len = rest.chars().take(2).map(|x| x.len_utf8()).sum::<usize>();
+                result.push_str(&rest[..escaped_len]);
+                i += escaped_len;
+                prev = None;
+                continue;
+            }
+            if c == q {
+                in_string = None;
+            }
+        } else if rest.starts_with(comment) {
+            result.push_str(rest);
+            break;
+        } else if quotes.contains(c) {
+            in_string = Some(c);
+        } else if rest.starts_with(old)
+            && !prev.map(is_ident_char).unwrap_or(false)
+            && !rest[old.len()..].chars().next().map(is_ident_char).unwrap_or(false)
+        {
+            result.push_str(new);
+            renamed_n += 1;
+            i += old.len();
+            prev = old.chars().last();
+            continue;
+        }
+        result.push(c);
+        prev = Some(c);
+        i += c.len_utf8();
+    }
+    (result, renamed_n)
+}
+
+fn is_import_line(line: &str) -> bool {
+    let t = line.trim_start();
+    t.starts_with("from ") || t.starts_with("import ") || t.starts_with("use ") || t.starts_with("pub use ")
+}
+
+pub fn plan_rename(
+    sites: &Vec<(String, usize)>,
+    file_texts: &HashMap<String, String>,
+    old: &str,
+    new: &str,
+) -> RenamePlan {
+    // sites are (cpath, line starting from 0), imports in the same files are renamed as well: they are not usages in the index
+    let mut plan = RenamePlan::default();
+    let mut lines_to_rename: BTreeMap<String, BTreeMap<usize, String>> = BTreeMap::new();
+    for (cpath, line0) in sites.iter() {
+        let text = match file_texts.get(cpath) {
+            Some(t) => t,
+            None => {
+                plan.unresolved.push(format!("{}:{} file not available", cpath, line0 + 1));
+                continue;
+            }
+        };
+        let (comment, quotes) = lexical_rules(cpath);
+        let lines: Vec<&str> = text.lines().collect();
+        // python parser counts usage lines from 0, other parsers from 1, try both
+        let candidates = [Some(*line0), line0.checked_sub(1)];
+        let found = candidates.iter().flatten().find_map(|l| {
+            let line = lines.get(*l)?;
+            let (renamed, n) = rename_in_code_line(line, old, new, comment, quotes);
+            if n > 0 { Some((*l, renamed)) } else { None }
+        });
+        match found {
+            Some((l, renamed)) => { lines_to_rename.entry(cpath.clone()).or_default().insert(l, renamed); }
+            None => plan.unresolved.push(format!("{}:{} no `{}` in code on this line", cpath, line0 + 1, old)),
+        }
+    }
+
+    for (cpath, text) in file_texts.iter() {
+        let (comment, quotes) = lexical_rules(cpath);
+        for (l, line) in text.lines().enumerate() {
+            if lines_to_rename.get(cpath).map(|x| x.contains_key(&l)).unwrap_or(false) {
+                continue;
+            }
+            let (renamed, n) = rename_in_code_line(line, old, new, comment, quotes);
+            if n == 0 {
+                continue;
+            }
+            if is_import_line(line) {
+                lines_to_rename.entry(cpath.clone()).or_default().insert(l, renamed);
+            } else {
+                plan.unresolved.push(format!("{}:{} `{}` not known to AST as this symbol, left as is: {}", cpath, l + 1, old, line.trim()));
+            }
+        }
+    }
+
+    for (cpath, renamed_lines) in lines_to_rename.iter() {
+        let lines: Vec<&str> = file_texts[cpath].lines().collect();
+        for (l, renamed) in renamed_lines.iter() {
+            plan.renamed_n += 1;
+            plan.chunks.push(DiffChunk {
+                file_name: cpath.clone(),
+                file_action: "edit".to_string(),
+                line1: l + 1,
+                line2: l + 2,
+                lines_remove: format!("{}\n", lines[*l]),
+                lines_add: format!("{}\n", renamed),
+                file_name_rename: None,
+                is_file: true,
+                application_details: "".to_string(),
+            });
+        }
+    }
+    plan.unresolved.sort();
+    plan
+}
+
+pub async fn rename_sites(ast_index: Arc<AMutex<AstDB>>, symbol: &str) -> Result<(String, Vec<(String, usize)>), String> {
+    let defs = definitions(ast_index.clone(), symbol).await;
+    let def = match defs.len() {
+        0 => return Err(format!("no definitions for `{}` found, try the definition tool to get the exact name", symbol)),
+        1 => defs[0].clone(),
+        _ => return Err(format!(
+            "`{}` is ambiguous, give a more specific name, one of:\n{}",
+            symbol,
+            defs.iter().map(|d| d.path_drop0()).collect::<Vec<_>>().join("\n")
+        )),
+    };
+    let mut sites = vec![(def.cpath.clone(), def.decl_line1.saturating_sub(1))];
+    for (usedin, uline) in usages(ast_index.clone(), def.path(), USAGES_LIMIT).await {
+        sites.push((usedin.cpath.clone(), uline));
+    }
+    Ok((def.name(), sites))
+}
+
+#[async_trait]
+impl Tool for ToolRenameSymbol {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let symbol = match args.get("symbol") {
+            Some(Value::String(s)) => s.trim().replace('.', "::"),
+            Some(v) => return Err(format!("argument `symbol` is not a string: {:?}", v)),
+            None => return Err("argument `symbol` is missing".to_string()),
+        };
+        let new_name = match args.get("new_name") {
+            Some(Value::String(s)) => s.trim().to_string(),
+            Some(v) => return Err(format!("argument `new_name` is not a string: {:?}", v)),
+            None => return Err("argument `new_name` is missing".to_string()),
+        };
+        if !is_identifier(&new_name) {
+            return Err(format!("argument `new_name` should be a plain identifier, got {:?}", new_name));
+        }
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let ast_service = gcx.read().await.ast_service.clone().ok_or("AST support is turned off".to_string())?;
+        let ast_index = ast_service.lock().await.ast_index.clone();
+        crate::ast::ast_indexer_thread::ast_indexer_block_until_finished(ast_service.clone(), 20_000, true).await;
+
+        let (old_name, sites) = rename_sites(ast_index.clone(), &symbol).await?;
+        if old_name == new_name {
+            return Err(format!("`{}` already has this name", symbol));
+        }
+        let mut file_texts = HashMap::new();
+        for (cpath, _) in sites.iter() {
+            if file_texts.contains_key(cpath) {
+                continue;
+            }
+            if let Ok(text) = get_file_text_from_memory_or_disk(gcx.clone(), &PathBuf::from(cpath)).await {
+                file_texts.insert(cpath.clone(), text);
+            }
+        }
+
+        let mut plan = plan_rename(&sites, &file_texts, &old_name, &new_name);
+        if plan.chunks.is_empty() {
+            return Err(format!("nothing to rename for `{}`:\n{}", symbol, plan.unresolved.join("\n")));
+        }
+        diff_apply(gcx.clone(), &mut plan.chunks).await.map_err(
+            |err| format!("Couldn't apply the diff: {}", err)
+        )?;
+
+        let mut results = vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "diff".to_string(),
+            content: ChatContent::SimpleText(json!(plan.chunks).to_string()),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })];
+        if !plan.unresolved.is_empty() {
+            results.push(ContextEnum::ChatMessage(ChatMessage::new(
+                "cd_instruction".to_string(),
+                format!(
+                    "💿 Renamed `{}` to `{}` on {} lines. These occurrences were not renamed, check them by hand:\n{}",
+                    old_name, new_name, plan.renamed_n, plan.unresolved.join("\n")
+                ),
+            )));
+        }
+        Ok((false, results))
+    }
+
+    async fn match_against_confirm_deny(&self, _ccx: Arc<AMutex<AtCommandsContext>>, _args: &HashMap<String, Value>) -> Result<MatchConfirmDeny, String> {
+        // changes many files at once, same as patch it should go through the user
+        Ok(MatchConfirmDeny {
+            result: MatchConfirmDenyResult::CONFIRMATION,
+            command: "rename_symbol".to_string(),
+            rule: "default".to_string(),
+        })
+    }
+
+    fn command_to_match_against_confirm_deny(
+        &self,
+        _args: &HashMap<String, Value>,
+    ) -> Result<String, String> {
+        Ok("rename_symbol".to_string())
+    }
+
+    fn confirm_deny_rules(&self) -> Option<IntegrationConfirmation> {
+        Some(IntegrationConfirmation {
+            ask_user: vec!["rename_symbol*".to_string()],
+            deny: vec![],
+        })
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec!["ast".to_string()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ast_db::{ast_index_init, doc_add, connect_usages, connect_usages_look_if_full_reset_needed, flush_sled_batch};
+    use crate::ast::ast_structs::AstErrorStats;
+
+    #[test]
+    fn test_rename_in_code_line() {
+        let (s, n) = rename_in_code_line("x = f(f_g, 'f', \"a f\") # f", "f", "g", "#", "'\"");
+        assert_eq!(s, "x = g(f_g, 'f', \"a f\") # f");
+        assert_eq!(n, 1);
+        let (s, n) = rename_in_code_line("let s = \"\\\"f\"; f(); // f", "f", "g", "//", "\"");
+        assert_eq!(s, "let s = \"\\\"f\"; g(); // f");
+        assert_eq!(n, 1);
+    }
+
+    #[tokio::test]
+    async fn test_rename_across_two_py_files() {
+        let ast_index = ast_index_init("".to_string(), 10, false).await;
+        let lib_path = "src/ast/alt_testsuite/py_rename_lib.py".to_string();
+        let main_path = "src/ast/alt_testsuite/py_rename_main.py".to_string();
+        let mut file_texts = HashMap::new();
+        let mut errstats = AstErrorStats::default();
+        for cpath in [&lib_path, &main_path] {
+            let text = std::fs::read_to_string(cpath).unwrap();
+            doc_add(ast_index.clone(), cpath, &text, &mut errstats).await.unwrap();
+            file_texts.insert(cpath.clone(), text);
+        }
+        let mut ucx = connect_usages_look_if_full_reset_needed(ast_index.clone()).await;
+        while connect_usages(ast_index.clone(), &mut ucx).await {}
+        flush_sled_batch(ast_index.clone(), 0).await;
+
+        let (old_name, sites) = rename_sites(ast_index.clone(), "compute_total").await.unwrap();
+        assert_eq!(old_name, "compute_total");
+        let plan = plan_rename(&sites, &file_texts, &old_name, "sum_items");
+
+        let changed: Vec<(String, usize, String)> = plan.chunks.iter()
+            .map(|c| (c.file_name.clone(), c.line1, c.lines_add.trim_end().to_string()))
+            .collect();
+        assert_eq!(changed, vec![
+            (lib_path.clone(), 1, "def sum_items(items):".to_string()),
+            (lib_path.clone(), 7, "    return sum_items(items) ** 2".to_string()),
+            (main_path.clone(), 1, "from py_rename_lib import sum_items".to_string()),
+            (main_path.clone(), 6, "    total = sum_items(items)".to_string()),
+        ]);
+        for c in plan.chunks.iter() {
+            assert_eq!(c.line2, c.line1 + 1);
+            assert!(!c.lines_add.contains("compute_total"), "{:?}", c);
+        }
+        // docstrings, comments and strings are left alone, and not reported since they are not code
+        assert!(plan.unresolved.is_empty(), "{:?}", plan.unresolved);
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::process::Command;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::at_commands::at_file::{file_repair_candidates, return_one_candidate_or_a_good_error};
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_project_dirs;
+use crate::files_in_workspace::get_file_text_from_memory_or_disk;
+use crate::integrations::integr_abstract::IntegrationConfirmation;
+use crate::integrations::integr_cmdline::format_output;
+use crate::integrations::process_io_utils::last_n_chars;
+use crate::tools::tools_description::{MatchConfirmDeny, MatchConfirmDenyResult, Tool};
+use crate::tools::tools_execute::command_should_be_denied;
+
+
+const BLOCK_TIMEOUT_SECS: u64 = 30;
+const BLOCK_OUTPUT_MAX_CHARS: usize = 2000;
+
+pub struct ToolRunDocExamples;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocBlock {
+    pub language: String,  // as written after the fence
+    pub line1: usize,      // first line of code, starts from 1
+    pub code: String,
+}
+
+pub fn canonical_language(tag: &str) -> String {
+    let tag = tag.trim().to_lowercase();
+    match tag.as_str() {
+        "sh" | "bash" | "shell" | "zsh" | "console" => "shell".to_string(),
+        "py" | "python" | "python3" => "python".to_string(),
+        "js" | "javascript" | "node" => "javascript".to_string(),
+        _ => tag,
+    }
+}
+
+fn runner_for(language: &str) -> Option<(&'static str, &'static str)> {
+    match canonical_language(language).as_str() {
+        "shell" => Some(("sh", "-c")),
+        "python" => Some(("python3", "-c")),
+        "javascript" => Some(("node", "-e")),
+        _ => None,
+    }
+}
+
+pub fn parse_fenced_blocks(markdown: &str) -> Vec<DocBlock> {
+    let mut blocks = vec![];
+    let mut open: Option<(char, usize, String, usize, Vec<&str>)> = None;  // fence char, fence length, language, line1, code lines
+    for (i, line) in markdown.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+        let fence_len = fence_char.map(|c| trimmed.chars().take_while(|x| *x == c).count()).unwrap_or(0);
+        match open.as_mut() {
+            None => {
+                if fence_len >= 3 {
+                    let info = trimmed[fence_len..].trim();
+                    let language = info.split_whitespace().next().unwrap_or("").to_string();
+                    open = Some((fence_char.unwrap(), fence_len, language, i + 2, vec![]));
+                }
+            }
+            Some((c, len, _, _, lines)) => {
+                if fence_char == Some(*c) && fence_len >= *len && trimmed[fence_len..].trim().is_empty() {
+                    let (_, _, language, line1, lines) = open.take().unwrap();
+                    blocks.push(DocBlock { language, line1, code: lines.iter().map(|l| format!("{}\n", l)).collect() });
+                } else {
+                    lines.push(line);
+                }
+            }
+        }
+    }
+    blocks
+}
+
+fn blocks_of_language(blocks: &Vec<DocBlock>, language: &str) -> Vec<DocBlock> {
+    let want = canonical_language(language);
+    blocks.iter().filter(|b| canonical_language(&b.language) == want).cloned().collect()
+}
+
+fn parse_block_numbers(s: &str, total: usize) -> Result<Vec<usize>, String> {
+    let mut numbers = vec![];
+    for part in s.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        let n = part.parse::<usize>().map_err(|_| format!("block number {:?} is not a number", part))?;
+        if n == 0 || n > total {
+            return Err(format!("there's no block #{}, the file has {} blocks in this language", n, total));
+        }
+        if !numbers.contains(&n) {
+            numbers.push(n);
+        }
+    }
+    Ok(numbers)
+}
+
+fn code_for_runner(block: &DocBlock) -> String {
+    // console blocks show the prompt, the command is what follows "$ "
+    if canonical_language(&block.language) == "shell" && block.code.lines().any(|l| l.starts_with("$ ")) {
+        return block.code.lines().filter_map(|l| l.strip_prefix("$ ")).map(|l| format!("{}\n", l)).collect();
+    }
+    block.code.clone()
+}
+
+pub fn list_blocks(blocks: &Vec<DocBlock>, language: &str) -> String {
+    let mut out = format!("{} blocks in {}:\n", blocks.len(), language);
+    for (i, b) in blocks.iter().enumerate() {
+        let first_line = b.code.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+        out.push_str(&format!("#{} line {}: {}\n", i + 1, b.line1, first_line.trim()));
+    }
+    out
+}
+
+pub async fn run_blocks<F, Fut>(blocks: &Vec<DocBlock>, numbers: &Vec<usize>, run_one: F) -> String
+where
+    F: Fn(DocBlock) -> Fut,
+    Fut: Future<Output = Result<(bool, String), String>>,
+{
+    let mut report = String::new();
+    let mut passed = 0;
+    for n in numbers.iter() {
+        let block = &blocks[n - 1];
+        let (verdict, output) = match run_one(block.clone()).await {
+            Ok((true, output)) => { passed += 1; ("PASS", output) }
+            Ok((false, output)) => ("FAIL", output),
+            Err(e) => ("FAIL", e),
+        };
+        report.push_str(&format!("block #{} at line {}: {}\n{}\n", n, block.line1, verdict, last_n_chars(&output, BLOCK_OUTPUT_MAX_CHARS)));
+    }
+    report.push_str(&format!("{} of {} blocks passed\n", passed, numbers.len()));
+    report
+}
+
+async fn run_block_in_dir(block: DocBlock, workdir: PathBuf) -> Result<(bool, String), String> {
+    let (program, flag) = runner_for(&block.language).ok_or(format!("don't know how to run {:?}", block.language))?;
+    let mut cmd = Command::new(program);
+    cmd.arg(flag).arg(code_for_runner(&block));
+    cmd.current_dir(workdir);
+    cmd.stdin(Stdio::null());
+    cmd.kill_on_drop(true);
+    let output = tokio::time::timeout(tokio::time::Duration::from_secs(BLOCK_TIMEOUT_SECS), cmd.output())
+        .await
+        .map_err(|_| format!("timed out after {} seconds", BLOCK_TIMEOUT_SECS))?
+        .map_err(|e| format!("cannot run {}: {}", program, e))?;
+    let mut out = format_output(&String::from_utf8_lossy(&output.stdout), &String::from_utf8_lossy(&output.stderr));
+    out.push_str(&format!("exit code {}\n", output.status.code().unwrap_or_default()));
+    Ok((output.status.success(), out))
+}
+
+fn parse_args(args: &HashMap<String, Value>) -> Result<(String, String, Option<String>), String> {
+    let get = |name: &str| -> Result<Option<String>, String> {
+        match args.get(name) {
+            Some(Value::String(s)) if !s.trim().is_empty() => Ok(Some(s.trim().to_string())),
+            Some(Value::String(_)) | None => Ok(None),
+            Some(v) => Err(format!("argument `{}` is not a string: {:?}", name, v)),
+        }
+    };
+    let path = get("path")?.ok_or("argument `path` is missing".to_string())?;
+    let language = get("language")?.ok_or("argument `language` is missing".to_string())?;
+    Ok((path, language, get("blocks")?))
+}
+
+#[async_trait]
+impl Tool for ToolRunDocExamples {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let (path, language, blocks_arg) = parse_args(args)?;
+        if runner_for(&language).is_none() {
+            return Err(format!("running {:?} blocks is not supported, use sh, python or javascript", language));
+        }
+        let gcx = ccx.lock().await.global_context.clone();
+        let candidates = file_repair_candidates(gcx.clone(), &path, 10, false).await;
+        let file_path = return_one_candidate_or_a_good_error(gcx.clone(), &path, &candidates, &get_project_dirs(gcx.clone()).await, false).await?;
+        let text = get_file_text_from_memory_or_disk(gcx.clone(), &PathBuf::from(&file_path)).await?;
+        let blocks = blocks_of_language(&parse_fenced_blocks(&text), &language);
+
+        // without `blocks` only list them, nothing runs unless asked for by number
+        let report = match blocks_arg {
+            None => list_blocks(&blocks, &language),
+            Some(blocks_arg) => {
+                let numbers = parse_block_numbers(&blocks_arg, blocks.len())?;
+                let workdir = PathBuf::from(&file_path).parent().map(|p| p.to_path_buf()).unwrap_or_default();
+                run_blocks(&blocks, &numbers, |block| run_block_in_dir(block, workdir.clone())).await
+            }
+        };
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    async fn match_against_confirm_deny(
+        &self,
+        _ccx: Arc<AMutex<AtCommandsContext>>,
+        args: &HashMap<String, Value>,
+    ) -> Result<MatchConfirmDeny, String> {
+        let command_to_match = self.command_to_match_against_confirm_deny(args)?;
+        if parse_args(args)?.2.is_none() {
+            // listing blocks doesn't run anything
+            return Ok(MatchConfirmDeny {
+                result: MatchConfirmDenyResult::PASS,
+                command: command_to_match,
+                rule: "".to_string(),
+            });
+        }
+        if let Some(rules) = &self.confirm_deny_rules() {
+            let (is_denied, deny_rule) = command_should_be_denied(&command_to_match, &rules.deny);
+            if is_denied {
+                return Ok(MatchConfirmDeny {
+                    result: MatchConfirmDenyResult::DENY,
+                    command: command_to_match,
+                    rule: deny_rule,
+                });
+            }
+        }
+        Ok(MatchConfirmDeny {
+            result: MatchConfirmDenyResult::CONFIRMATION,
+            command: command_to_match,
+            rule: "run_doc_examples*".to_string(),
+        })
+    }
+
+    fn command_to_match_against_confirm_deny(
+        &self,
+        args: &HashMap<String, Value>,
+    ) -> Result<String, String> {
+        let (path, language, blocks) = parse_args(args)?;
+        Ok(format!("run_doc_examples {} {} {}", path, language, blocks.unwrap_or_default()).trim_end().to_string())
+    }
+
+    fn confirm_deny_rules(&self) -> Option<IntegrationConfirmation> {
+        Some(IntegrationConfirmation {
+            ask_user: vec!["run_doc_examples*".to_string()],
+            deny: vec![],
+        })
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("testsuite/doc_examples.md");
+
+    #[test]
+    fn test_parse_fenced_blocks() {
+        let blocks = parse_fenced_blocks(FIXTURE);
+        let languages: Vec<&str> = blocks.iter().map(|b| b.language.as_str()).collect();
+        assert_eq!(languages, vec!["sh", "python", "bash", ""]);
+        assert_eq!(blocks[0].line1, 6);
+        assert_eq!(blocks[0].code, "echo \"installing frogs\"\nmkdir -p frogs\n");
+        assert_eq!(blocks[3].code, "plain text, no language\n");
+
+        let shell = blocks_of_language(&blocks, "shell");
+        assert_eq!(shell.len(), 2);
+        assert_eq!(shell[1].code, "echo \"2 frogs\"\n");
+        assert!(parse_block_numbers("3", shell.len()).is_err());
+        assert_eq!(parse_block_numbers("2, 2,1", shell.len()).unwrap(), vec![2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_run_only_requested_blocks() {
+        let shell = blocks_of_language(&parse_fenced_blocks(FIXTURE), "sh");
+        let ran = Arc::new(std::sync::Mutex::new(vec![]));
+        let ran_clone = ran.clone();
+        let report = run_blocks(&shell, &vec![2], move |block| {
+            let ran = ran_clone.clone();
+            async move {
+                ran.lock().unwrap().push(code_for_runner(&block));
+                Ok((block.code.contains("frogs"), "2 frogs\n".to_string()))
+            }
+        }).await;
+        assert_eq!(*ran.lock().unwrap(), vec!["echo \"2 frogs\"\n".to_string()]);
+        assert!(report.contains("block #2 at line 20: PASS"), "{}", report);
+        assert!(report.contains("1 of 1 blocks passed"), "{}", report);
+
+        let report = run_blocks(&shell, &vec![1], |_| async { Err::<(bool, String), String>("exit code 1".to_string()) }).await;
+        assert!(report.contains("block #1 at line 6: FAIL"), "{}", report);
+    }
+}
@@ -9,7 +9,7 @@ use tokio::sync::Mutex as AMutex;
 
 use crate::at_commands::at_commands::{vec_context_file_to_context_tools, AtCommandsContext};
 use crate::at_commands::at_file::{file_repair_candidates, return_one_candidate_or_a_good_error};
-use crate::at_commands::at_search::execute_at_search;
+use crate::at_commands::at_search::{active_workspace_folder_filter, execute_at_search};
 use crate::files_correction::{correct_to_nearest_dir_path, get_project_dirs};
 use crate::tools::tools_description::Tool;
 use crate::call_validation::{ChatMessage, ChatContent, ContextEnum, ContextFile};
@@ -24,6 +24,10 @@ async fn execute_att_search(
 ) -> Result<Vec<ContextFile>, String> {
     let gcx = ccx.lock().await.global_context.clone();
     if scope == "workspace" {
+        let filter = active_workspace_folder_filter(gcx.clone()).await;
+        return execute_at_search(ccx.clone(), &query, filter).await
+    }
+    if scope == "all_workspaces" {
         return execute_at_search(ccx.clone(), &query, None).await
     }
     let scope_is_dir = scope.ends_with('/') || scope.ends_with('\\');
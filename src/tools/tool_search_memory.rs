@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+use tracing::info;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::tools::tools_description::Tool;
+use crate::vecdb::vdb_highlev::{memories_search_with_tag, memories_select_by_tag};
+use crate::vecdb::vdb_structs::MemoRecord;
+
+
+const SEARCH_MEMORY_DEFAULT_TOP_N: usize = 5;
+const SEARCH_MEMORY_MAX_TOP_N: usize = 20;
+
+pub struct ToolSearchMemory;
+
+pub fn format_memories(query: &str, memories: &Vec<MemoRecord>) -> String {
+    if memories.is_empty() {
+        return format!("No memories found for {:?}", query);
+    }
+    let mut out = format!("{} memories most relevant to {:?}, closest first:\n\n", memories.len(), query);
+    for m in memories.iter() {
+        out.push_str(&format!("🗃️{} type={} distance={:.3}\n", m.memid, m.m_type, m.distance));
+        push_goal_tags_payload(&mut out, m);
+    }
+    out
+}
+
+pub fn format_tagged_memories(tag: &str, memories: &Vec<MemoRecord>, limit: usize) -> String {
+    if memories.is_empty() {
+        return format!("No memories tagged {:?}", tag);
+    }
+    let mut out = format!("{} memories tagged {:?}, most used first:\n\n", memories.len(), tag);
+    for m in memories.iter().take(limit) {
+        out.push_str(&format!("🗃️{} type={}\n", m.memid, m.m_type));
+        push_goal_tags_payload(&mut out, m);
+    }
+    if memories.len() > limit {
+        out.push_str(&format!("...and {} more, add a query to find the relevant ones\n", memories.len() - limit));
+    }
+    out
+}
+
+fn push_goal_tags_payload(out: &mut String, m: &MemoRecord) {
+    if !m.m_goal.is_empty() {
+        out.push_str(&format!("goal: {}\n", m.m_goal));
+    }
+    if !m.m_tags.is_empty() {
+        out.push_str(&format!("tags: {}\n", m.m_tags.join(", ")));
+    }
+    out.push_str(&m.m_payload);
+    out.push_str("\n\n");
+}
+
+#[async_trait]
+impl Tool for ToolSearchMemory {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        info!("run @search_memory {:?}", args);
+        let query = match args.get("query") {
+            Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim().to_string()),
+            Some(v) if !v.is_string() => return Err(format!("argument `query` is not a string: {:?}", v)),
+            _ => None,
+        };
+        let tag = match args.get("tag") {
+            Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim().to_string()),
+            Some(v) if !v.is_string() => return Err(format!("argument `tag` is not a string: {:?}", v)),
+            _ => None,
+        };
+        let top_n = match args.get("top_n") {
+            Some(Value::Number(n)) => Some(n.as_u64().ok_or(format!("argument `top_n` is not a positive integer: {}", n))? as usize),
+            Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim().parse::<usize>().map_err(|_| format!("argument `top_n` is not a positive integer: {:?}", s))?),
+            _ => None,
+        };
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let report = match (query, tag) {
+            (Some(query), tag) => {
+                let top_n = top_n.unwrap_or(SEARCH_MEMORY_DEFAULT_TOP_N).clamp(1, SEARCH_MEMORY_MAX_TOP_N);
+                let memories = memories_search_with_tag(gcx.clone(), &query, top_n, tag.as_deref()).await?;
+                format_memories(&query, &memories.results)
+            },
+            (None, Some(tag)) => {
+                // listing a tag, "all decisions tagged architecture", so the default is as many as allowed
+                let limit = top_n.unwrap_or(SEARCH_MEMORY_MAX_TOP_N).clamp(1, SEARCH_MEMORY_MAX_TOP_N);
+                format_tagged_memories(&tag, &memories_select_by_tag(gcx.clone(), &tag).await?, limit)
+            },
+            (None, None) => return Err("Missing argument `query` or `tag`, at least one is needed".to_string()),
+        };
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec!["vecdb".to_string()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::knowledge::{lance_add_memory_vectors, memories_search_by_embedding, MemoriesDatabase};
+    use crate::vecdb::vdb_structs::{SimpleTextHashVector, VecdbConstants};
+
+    fn test_constants() -> VecdbConstants {
+        VecdbConstants {
+            embedding_model: "test".to_string(),
+            embedding_size: 3,
+            embedding_batch: 64,
+            tokenizer: None,
+            vectorizer_n_ctx: 512,
+            endpoint_embeddings_template: "".to_string(),
+            endpoint_embeddings_style: "".to_string(),
+            splitter_window_size: 512,
+            vecdb_max_files: 100,
+            vecdb_include_globs: vec![],
+            vecdb_exclude_globs: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_found_by_query() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let constants = test_constants();
+        let memdb = Arc::new(AMutex::new(MemoriesDatabase::init(&config_dir.path().to_path_buf(), &constants, false).await.unwrap()));
+
+        // vectors stand in for the embedding model: axis 0 is "docker", axis 1 is "database"
+        let memories = [
+            ("run the service in docker", "use `docker compose up -d`, not `docker run`", vec![1.0, 0.1, 0.0]),
+            ("migrate the database", "migrations live in db/migrations, apply with `make migrate`", vec![0.1, 1.0, 0.0]),
+        ];
+        let mut memids = vec![];
+        let mut todo = vec![];
+        for (goal, payload, vector) in memories.iter() {
+            memids.push(memdb.lock().await.permdb_add("proj-fact", goal, "proj1", payload, "local-test", &vec![]).unwrap());
+            todo.push(SimpleTextHashVector { window_text: goal.to_string(), window_text_hash: "".to_string(), vector: Some(vector.clone()) });
+        }
+        lance_add_memory_vectors(memdb.clone(), memids.clone(), &todo).await.unwrap();
+
+        let found = memories_search_by_embedding(memdb.clone(), &vec![0.2, 0.9, 0.1], 2, None).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].memid, memids[1]);
+        assert_eq!(found[0].m_goal, "migrate the database");
+        assert!(found[0].distance < found[1].distance);
+
+        let report = format_memories("how do I apply migrations", &found);
+        assert!(report.starts_with("2 memories most relevant to"), "{}", report);
+        let first = report.find(&format!("🗃️{}", memids[1])).unwrap();
+        let second = report.find(&format!("🗃️{}", memids[0])).unwrap();
+        assert!(first < second, "{}", report);
+        assert!(report.contains("make migrate"), "{}", report);
+        assert_eq!(format_memories("nothing", &vec![]), "No memories found for \"nothing\"");
+    }
+
+    #[tokio::test]
+    async fn test_memories_retrieved_by_tag() {
+        let config_dir = tempfile::tempdir().unwrap();
+        // a database from before tags, its memories must keep working untagged
+        {
+            let conn = rusqlite::Connection::open(config_dir.path().join("memories.sqlite")).unwrap();
+            conn.execute_batch("CREATE TABLE memories (memid TEXT PRIMARY KEY, m_type TEXT NOT NULL, m_goal TEXT NOT NULL, m_project TEXT NOT NULL, m_payload TEXT NOT NULL,
+                mstat_correct REAL NOT NULL DEFAULT 0, mstat_relevant REAL NOT NULL DEFAULT 0, mstat_times_used INTEGER NOT NULL DEFAULT 0);
+                INSERT INTO memories (memid, m_type, m_goal, m_project, m_payload) VALUES ('0ld', 'proj-fact', 'old habit', 'proj1', 'tabs, not spaces');").unwrap();
+        }
+        let memdb = Arc::new(AMutex::new(MemoriesDatabase::init(&config_dir.path().to_path_buf(), &test_constants(), false).await.unwrap()));
+
+        let memories = [
+            ("queue between services", "services talk through the NATS queue, no direct HTTP calls", vec!["Architecture", "decision"], vec![1.0, 0.0, 0.0]),
+            ("one database per service", "each service owns its postgres schema", vec!["architecture", "database"], vec![0.0, 1.0, 0.0]),
+            ("release on tuesdays", "releases go out on tuesdays after the standup", vec!["process"], vec![0.0, 0.0, 1.0]),
+        ];
+        let mut memids = vec![];
+        let mut todo = vec![];
+        for (goal, payload, tags, vector) in memories.iter() {
+            let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+            memids.push(memdb.lock().await.permdb_add("decision", goal, "proj1", payload, "local-test", &tags).unwrap());
+            todo.push(SimpleTextHashVector { window_text: goal.to_string(), window_text_hash: "".to_string(), vector: Some(vector.clone()) });
+        }
+        lance_add_memory_vectors(memdb.clone(), memids.clone(), &todo).await.unwrap();
+
+        let tagged = memdb.lock().await.permdb_select_by_tag(" ARCHITECTURE ").unwrap();
+        let mut tagged_ids: Vec<String> = tagged.iter().map(|m| m.memid.clone()).collect();
+        tagged_ids.sort();
+        let mut expected = vec![memids[0].clone(), memids[1].clone()];
+        expected.sort();
+        assert_eq!(tagged_ids, expected);
+        assert_eq!(tagged.iter().find(|m| m.memid == memids[0]).unwrap().m_tags, vec!["architecture", "decision"]);
+        assert!(memdb.lock().await.permdb_select_by_tag("arch").unwrap().is_empty());
+
+        // semantic search within the tag: the "process" memory is the closest overall but doesn't have the tag
+        let only = tagged_ids.clone();
+        let found = memories_search_by_embedding(memdb.clone(), &vec![0.1, 0.3, 0.9], 1, Some(&only)).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].memid, memids[1]);
+        let found = memories_search_by_embedding(memdb.clone(), &vec![0.1, 0.3, 0.9], 1, None).await.unwrap();
+        assert_eq!(found[0].memid, memids[2]);
+
+        let old = memdb.lock().await.permdb_select_all(Some("memid = '0ld'")).await.unwrap();
+        assert_eq!((old[0].m_payload.as_str(), old[0].m_tags.len()), ("tabs, not spaces", 0));
+
+        let report = format_tagged_memories("architecture", &tagged, 1);
+        assert!(report.starts_with("2 memories tagged \"architecture\""), "{}", report);
+        assert!(report.contains("...and 1 more"), "{}", report);
+    }
+}
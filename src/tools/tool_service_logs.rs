@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::integrations::integr_cmdline_service::{CmdlineSession, ServiceOutputLine};
+use crate::tools::tools_description::Tool;
+
+
+const SERVICE_SESSION_PREFIX: &str = "custom_service_";
+const LOGS_DEFAULT_LINES: usize = 100;
+const LOGS_MAX_LINES: usize = 1000;
+const LOGS_MAX_CHARS: usize = 8000;
+
+pub struct ToolServiceLogs;
+
+fn looks_like_error(text: &str) -> bool {
+    let lower = text.to_lowercase();
+    ["error", "exception", "traceback", "panic", "fatal", "failed", "critical"].iter().any(|w| lower.contains(w))
+}
+
+fn format_line(line: &ServiceOutputLine) -> String {
+    format!("{}{}\n", if line.is_stderr { "[stderr] " } else { "" }, line.text)
+}
+
+pub fn format_service_logs(lines: &Vec<ServiceOutputLine>, max_chars: usize) -> String {
+    let total_chars: usize = lines.iter().map(|l| format_line(l).len()).sum();
+    if total_chars <= max_chars {
+        return lines.iter().map(format_line).collect();
+    }
+    // too long: errors first, then the most recent lines with what's left, original order is kept
+    let mut keep = vec![false; lines.len()];
+    let mut budget = max_chars;
+    let error_lines = lines.iter().enumerate().filter(|(_, l)| looks_like_error(&l.text)).map(|(i, _)| i).rev();
+    let recent_lines = (0..lines.len()).rev();
+    for i in error_lines.chain(recent_lines) {
+        let len = format_line(&lines[i]).len();
+        if keep[i] || len > budget {
+            continue;
+        }
+        keep[i] = true;
+        budget -= len;
+    }
+    let mut out = String::new();
+    let mut skipped = 0;
+    for (i, line) in lines.iter().enumerate() {
+        if keep[i] {
+            if skipped > 0 {
+                out.push_str(&format!("...{} lines skipped...\n", skipped));
+                skipped = 0;
+            }
+            out.push_str(&format_line(line));
+        } else {
+            skipped += 1;
+        }
+    }
+    if skipped > 0 {
+        out.push_str(&format!("...{} lines skipped...\n", skipped));
+    }
+    out
+}
+
+#[async_trait]
+impl Tool for ToolServiceLogs {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let service = match args.get("service") {
+            Some(Value::String(s)) if !s.trim().is_empty() => s.trim().to_string(),
+            Some(v) if !v.is_string() => return Err(format!("argument `service` is not a string: {:?}", v)),
+            _ => return Err("Missing argument `service`".to_string()),
+        };
+        let lines_n = match args.get("lines") {
+            Some(Value::Number(n)) => n.as_u64().ok_or(format!("argument `lines` is not a positive integer: {}", n))? as usize,
+            Some(Value::String(s)) if !s.trim().is_empty() => s.trim().parse::<usize>().map_err(|_| format!("argument `lines` is not a positive integer: {:?}", s))?,
+            _ => LOGS_DEFAULT_LINES,
+        }.clamp(1, LOGS_MAX_LINES);
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let service_name = if service.starts_with("service_") { service.clone() } else { format!("service_{}", service) };
+        let session_mb = gcx.read().await.integration_sessions.get(&format!("{}{}", SERVICE_SESSION_PREFIX, service_name)).cloned();
+        let session = match session_mb {
+            Some(session) => session,
+            None => {
+                let running = gcx.read().await.integration_sessions.keys()
+                    .filter_map(|k| k.strip_prefix(SERVICE_SESSION_PREFIX).map(|x| x.to_string()))
+                    .collect::<Vec<_>>();
+                return Err(format!("Service {:?} is not running. Running services: {}", service_name,
+                    if running.is_empty() { "none".to_string() } else { running.join(", ") }));
+            }
+        };
+        let output = {
+            let mut session_locked = session.lock().await;
+            let cmdline_session = session_locked.as_any_mut().downcast_mut::<CmdlineSession>()
+                .ok_or(format!("{:?} is not a service session", service_name))?;
+            cmdline_session.output()
+        };
+        let tail = output.lock().unwrap().tail(lines_n);
+
+        let report = if tail.is_empty() {
+            format!("Service {} is running, but it hasn't printed anything yet.", service_name)
+        } else {
+            format!("Last {} lines of {} output:\n{}", tail.len(), service_name, format_service_logs(&tail, LOGS_MAX_CHARS))
+        };
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+    use std::sync::Mutex as StdMutex;
+    use tokio::io::BufReader;
+    use crate::integrations::integr_cmdline_service::{spawn_output_collector, ServiceOutputBuffer};
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_fake_service_tail() {
+        let mut child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg("i=1; while [ $i -le 300 ]; do echo \"request $i ok\"; i=$((i+1)); done; echo 'ERROR: database is gone' >&2; sleep 30")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let output = Arc::new(StdMutex::new(ServiceOutputBuffer::default()));
+        let collector = spawn_output_collector(
+            BufReader::new(child.stdout.take().unwrap()),
+            BufReader::new(child.stderr.take().unwrap()),
+            output.clone(),
+        );
+
+        // the service keeps running, the logs must be readable while it does
+        let t0 = tokio::time::Instant::now();
+        while output.lock().unwrap().tail(1000).len() < 301 {
+            assert!(t0.elapsed() < tokio::time::Duration::from_secs(10), "service output never arrived");
+            tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+        }
+        let tail = output.lock().unwrap().tail(5);
+        assert_eq!(tail.len(), 5);
+        assert!(tail.contains(&ServiceOutputLine { is_stderr: false, text: "request 300 ok".to_string() }));
+        assert!(tail.contains(&ServiceOutputLine { is_stderr: true, text: "ERROR: database is gone".to_string() }));
+
+        // a tight limit keeps the error and the most recent lines
+        let all = output.lock().unwrap().tail(1000);
+        let logs = format_service_logs(&all, 200);
+        assert!(logs.len() <= 200 + 100, "{}", logs);
+        assert!(logs.contains("[stderr] ERROR: database is gone\n"), "{}", logs);
+        assert!(logs.contains("request 300 ok\n"), "{}", logs);
+        assert!(logs.starts_with("...") && logs.contains("lines skipped"), "{}", logs);
+        assert!(!logs.contains("request 1 ok\n"), "{}", logs);
+
+        let (stdout, stderr) = output.lock().unwrap().take_since_last_checkout();
+        assert_eq!(stdout.lines().count(), 300);
+        assert_eq!(stderr, "ERROR: database is gone\n");
+        assert_eq!(output.lock().unwrap().take_since_last_checkout(), ("".to_string(), "".to_string()));
+
+        collector.abort();
+        child.kill().await.ok();
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Mutex as AMutex;
+use tree_sitter::Node;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::at_commands::at_file::{file_repair_candidates, return_one_candidate_or_a_good_error};
+use crate::ast::treesitter::language_id::LanguageId;
+use crate::ast::treesitter::parsers::{parse_text_to_tree, tree_sitter_language_by_filename};
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_project_dirs;
+use crate::files_in_workspace::get_file_text_from_memory_or_disk;
+use crate::tools::tools_description::Tool;
+
+
+const SYNTAX_ERRORS_MAX: usize = 20;
+const SNIPPET_MAX_CHARS: usize = 40;
+const EXTERNAL_CHECKER_TIMEOUT_SECS: u64 = 10;
+
+// Reads the source from stdin, so nothing is written next to the file, unlike py_compile that leaves __pycache__ behind
+const PYTHON_COMPILE_SCRIPT: &str = "import sys; compile(sys.stdin.read(), sys.argv[1], 'exec')";
+
+pub struct ToolSyntaxCheck;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub line: usize,      // starts from 1
+    pub column: usize,    // starts from 1, in bytes
+    pub message: String,
+}
+
+fn error_from_node(node: &Node, text: &str) -> SyntaxError {
+    let message = if node.is_missing() {
+        format!("missing `{}`", node.kind())
+    } else {
+        let snippet = text.get(node.byte_range()).unwrap_or("").lines().next().unwrap_or("").trim();
+        if snippet.is_empty() {
+            "unexpected end of input".to_string()
+        } else {
+            format!("unexpected `{}`", snippet.chars().take(SNIPPET_MAX_CHARS).collect::<String>())
+        }
+    };
+    SyntaxError {
+        line: node.start_position().row + 1,
+        column: node.start_position().column + 1,
+        message,
+    }
+}
+
+pub fn syntax_errors(language_id: LanguageId, text: &str) -> Result<Vec<SyntaxError>, String> {
+    let tree = parse_text_to_tree(language_id, text)?;
+
+    let mut result = vec![];
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if node.is_error() || node.is_missing() {
+            // what's inside an ERROR node is the same mistake, don't report it twice
+            result.push(error_from_node(&node, text));
+            continue;
+        }
+        if !node.has_error() {
+            continue;
+        }
+        for i in (0..node.child_count()).rev() {
+            if let Some(child) = node.child(i) {
+                stack.push(child);
+            }
+        }
+    }
+    result.sort_by_key(|e| (e.line, e.column));
+    Ok(result)
+}
+
+pub fn format_syntax_report(path: &str, errors: &Vec<SyntaxError>) -> String {
+    if errors.is_empty() {
+        return format!("OK: {} parses without errors\n", path);
+    }
+    let mut report = format!("{} syntax errors in {}:\n", errors.len(), path);
+    for e in errors.iter().take(SYNTAX_ERRORS_MAX) {
+        report.push_str(&format!("  line {} column {}: {}\n", e.line, e.column, e.message));
+    }
+    if errors.len() > SYNTAX_ERRORS_MAX {
+        report.push_str(&format!("  ...and {} more\n", errors.len() - SYNTAX_ERRORS_MAX));
+    }
+    report
+}
+
+// None if there's no cheap checker for this language or it's not installed
+async fn external_check(language_id: LanguageId, path: &str, text: &str) -> Option<Result<(), String>> {
+    if language_id != LanguageId::Python {
+        return None;
+    }
+    let python = which::which("python3").or_else(|_| which::which("python")).ok()?;
+    let run = async {
+        let mut child = Command::new(&python)
+            .arg("-c").arg(PYTHON_COMPILE_SCRIPT).arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("cannot run {}: {}", python.display(), e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).await.map_err(|e| e.to_string())?;
+        }
+        child.wait_with_output().await.map_err(|e| e.to_string())
+    };
+    let output = match tokio::time::timeout(tokio::time::Duration::from_secs(EXTERNAL_CHECKER_TIMEOUT_SECS), run).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Some(Err(e)),
+        Err(_) => return Some(Err(format!("timed out after {} seconds", EXTERNAL_CHECKER_TIMEOUT_SECS))),
+    };
+    if output.status.success() {
+        return Some(Ok(()));
+    }
+    // the traceback ends with "  File ..., line N", the offending line, a caret and "SyntaxError: ..."
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    let start = lines.iter().rposition(|l| l.trim_start().starts_with("File ")).unwrap_or(0);
+    Some(Err(lines[start..].join("\n")))
+}
+
+#[async_trait]
+impl Tool for ToolSyntaxCheck {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let path = match args.get("path") {
+            Some(Value::String(s)) if !s.trim().is_empty() => s.trim().to_string(),
+            Some(v) if !v.is_string() => return Err(format!("argument `path` is not a string: {:?}", v)),
+            _ => return Err("Missing argument `path`".to_string()),
+        };
+
+        let gcx = ccx.lock().await.global_context.clone();
+        let candidates = file_repair_candidates(gcx.clone(), &path, 10, false).await;
+        let file_path = return_one_candidate_or_a_good_error(gcx.clone(), &path, &candidates, &get_project_dirs(gcx.clone()).await, false).await?;
+        let language_id = tree_sitter_language_by_filename(&PathBuf::from(&file_path))
+            .map_err(|e| format!("cannot check {}: {}", file_path, e))?;
+        // checks privacy, and takes the unsaved text from the IDE if there is one
+        let text = get_file_text_from_memory_or_disk(gcx.clone(), &PathBuf::from(&file_path)).await?;
+        let errors = syntax_errors(language_id, &text)?;
+        let mut report = format_syntax_report(&file_path, &errors);
+        match external_check(language_id, &file_path, &text).await {
+            Some(Ok(())) => report.push_str("python compile(): OK\n"),
+            Some(Err(e)) => report.push_str(&format!("python compile() failed:\n{}\n", e)),
+            None => {}
+        }
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(report),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BROKEN_PY: &str = "def total(items):\n    s = 0\n    for i in items\n        s += i\n    return s\n";
+    const CLEAN_PY: &str = "def total(items):\n    s = 0\n    for i in items:\n        s += i\n    return s\n";
+
+    #[test]
+    fn test_python_syntax_errors() {
+        let errors = syntax_errors(LanguageId::Python, BROKEN_PY).unwrap();
+        assert!(!errors.is_empty());
+        assert_eq!(errors[0].line, 3, "{:?}", errors);
+        let report = format_syntax_report("calc.py", &errors);
+        assert!(report.contains("syntax errors in calc.py"), "{}", report);
+        assert!(report.contains("line 3 column"), "{}", report);
+
+        let errors = syntax_errors(LanguageId::Python, CLEAN_PY).unwrap();
+        assert_eq!(errors, vec![]);
+        assert_eq!(format_syntax_report("calc.py", &errors), "OK: calc.py parses without errors\n");
+    }
+
+    #[test]
+    fn test_supported_list_follows_the_grammars() {
+        assert_eq!(tree_sitter_language_by_filename(&PathBuf::from("/ws/main.go")), Ok(LanguageId::Go));
+        let err = tree_sitter_language_by_filename(&PathBuf::from("/ws/Main.kt")).unwrap_err();
+        assert!(err.contains("Supported:") && err.contains("go") && err.contains("sql") && !err.contains("kotlin"), "{}", err);
+    }
+}
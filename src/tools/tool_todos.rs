@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use regex::Regex;
+use serde_json::Value;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_project_dirs;
+use crate::tools::scan_files_streaming::JsonLinesOutput;
+use crate::tools::tool_grep::{files_to_scan, format_grep_results, grep_files, parse_scan_args, GREP_MAX_MATCHES};
+use crate::tools::tools_description::Tool;
+use crate::tools::tools_execute::ToolOutputStreamer;
+
+
+pub struct ToolTodos;
+
+lazy_static::lazy_static! {
+    // uppercase only, "todo" in prose or a `todo!()` macro isn't a note someone left
+    static ref TODO_MARKER: Regex = Regex::new(r"\b(TODO|FIXME|XXX|HACK)\b").unwrap();
+}
+
+#[async_trait]
+impl Tool for ToolTodos {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let (subdir, stream) = parse_scan_args(args)?;
+
+        let (files, privacy) = files_to_scan(ccx.clone(), &subdir).await?;
+        let streamer = if stream { Some(ToolOutputStreamer::new(ccx.clone(), tool_call_id).await) } else { None };
+        let mut output = JsonLinesOutput::new(streamer);
+        let (results, summary) = grep_files(privacy, &files, &TODO_MARKER, GREP_MAX_MATCHES, &mut output).await;
+        tracing::info!("todos: {}, {} lines streamed", summary, output.lines_sent);
+
+        let project_dirs = get_project_dirs(ccx.lock().await.global_context.clone()).await;
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(format_grep_results(&results, &summary, &project_dirs)),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
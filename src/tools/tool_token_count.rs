@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock as StdRwLock};
+use async_trait::async_trait;
+use serde_json::Value;
+use tokenizers::Tokenizer;
+use tokio::sync::Mutex as AMutex;
+
+use crate::at_commands::at_commands::AtCommandsContext;
+use crate::at_commands::at_file::{colon_lines_range_from_arg, file_repair_candidates, return_one_candidate_or_a_good_error, RangeKind};
+use crate::cached_tokenizers::cached_tokenizer;
+use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
+use crate::files_correction::get_project_dirs;
+use crate::files_in_workspace::get_file_text_from_memory_or_disk;
+use crate::global_context::try_load_caps_quickly_if_not_present;
+use crate::scratchpads::scratchpad_utils::count_tokens;
+use crate::tools::tools_description::Tool;
+
+
+const APPROX_CHARS_PER_TOKEN: usize = 4;
+
+pub struct ToolTokenCount;
+
+pub fn token_count_report(
+    tokenizer: Result<Arc<StdRwLock<Tokenizer>>, String>,
+    model: &str,
+    what: &str,
+    text: &str,
+) -> String {
+    let lines_n = text.lines().count();
+    let chars_n = text.chars().count();
+    match tokenizer {
+        Ok(tokenizer) => {
+            let tokens_n = count_tokens(&tokenizer.read().unwrap(), text);
+            format!("{}: {} tokens for model {} ({} lines, {} chars)", what, tokens_n, model, lines_n, chars_n)
+        }
+        Err(e) => {
+            // no tokenizer is not a reason to fail, a rough estimate is still useful to decide what fits
+            let tokens_n = (chars_n + APPROX_CHARS_PER_TOKEN - 1) / APPROX_CHARS_PER_TOKEN;
+            format!("{}: ~{} tokens, approximate because the tokenizer for model {} is not available: {} ({} lines, {} chars)",
+                what, tokens_n, model, e, lines_n, chars_n)
+        }
+    }
+}
+
+fn get_string_arg(args: &HashMap<String, Value>, name: &str) -> Result<Option<String>, String> {
+    match args.get(name) {
+        Some(Value::String(s)) if !s.trim().is_empty() => Ok(Some(s.clone())),
+        Some(Value::String(_)) | None => Ok(None),
+        Some(v) => Err(format!("argument `{}` is not a string: {:?}", name, v)),
+    }
+}
+
+#[async_trait]
+impl Tool for ToolTokenCount {
+    fn as_any(&self) -> &dyn std::any::Any { self }
+
+    async fn tool_execute(
+        &mut self,
+        ccx: Arc<AMutex<AtCommandsContext>>,
+        tool_call_id: &String,
+        args: &HashMap<String, Value>,
+    ) -> Result<(bool, Vec<ContextEnum>), String> {
+        let path = get_string_arg(args, "path")?;
+        let text = get_string_arg(args, "text")?;
+        let (gcx, current_model) = {
+            let ccx_locked = ccx.lock().await;
+            (ccx_locked.global_context.clone(), ccx_locked.current_model.clone())
+        };
+        let model = get_string_arg(args, "model")?.map(|x| x.trim().to_string()).unwrap_or(current_model);
+        if model.is_empty() {
+            return Err("argument `model` is missing and there's no current model to count for".to_string());
+        }
+
+        let (what, text) = match (path, text) {
+            (Some(path), None) => {
+                let mut path = path.trim().to_string();
+                let range = colon_lines_range_from_arg(&mut path);
+                let candidates = file_repair_candidates(gcx.clone(), &path, 10, false).await;
+                let file_path = return_one_candidate_or_a_good_error(gcx.clone(), &path, &candidates, &get_project_dirs(gcx.clone()).await, false).await?;
+                let file_text = get_file_text_from_memory_or_disk(gcx.clone(), &PathBuf::from(&file_path)).await?;
+                match range {
+                    Some(range) if range.kind == RangeKind::Range => {
+                        let selected = file_text.lines()
+                            .skip(range.line1.saturating_sub(1))
+                            .take(range.line2.saturating_sub(range.line1.saturating_sub(1)))
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        (format!("{}:{}-{}", file_path, range.line1, range.line2), selected)
+                    }
+                    Some(_) => return Err("only line1-line2 ranges are supported, like file.py:10-40".to_string()),
+                    None => (file_path, file_text),
+                }
+            }
+            (None, Some(text)) => ("text".to_string(), text),
+            (Some(_), Some(_)) => return Err("give either `path` or `text`, not both".to_string()),
+            (None, None) => return Err("argument `path` or `text` is required".to_string()),
+        };
+
+        let tokenizer = match try_load_caps_quickly_if_not_present(gcx.clone(), 0).await {
+            Ok(caps) => cached_tokenizer(caps, gcx.clone(), model.clone()).await,
+            Err(e) => Err(format!("no caps: {}", e.message)),
+        };
+
+        Ok((false, vec![ContextEnum::ChatMessage(ChatMessage {
+            role: "tool".to_string(),
+            content: ChatContent::SimpleText(token_count_report(tokenizer, &model, &what, &text)),
+            tool_calls: None,
+            tool_call_id: tool_call_id.clone(),
+            ..Default::default()
+        })]))
+    }
+
+    fn tool_depends_on(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use super::*;
+
+    // every character is a token in this one
+    const DUMMY_TOKENIZER: &str = include_str!("../ast/dummy_tokenizer.json");
+    const FIXTURE: &str = include_str!("../ast/alt_testsuite/py_refs_lib.py");
+
+    #[test]
+    fn test_token_count_report() {
+        let tokenizer = Arc::new(StdRwLock::new(Tokenizer::from_str(DUMMY_TOKENIZER).unwrap()));
+        let report = token_count_report(Ok(tokenizer), "dummy", "py_refs_lib.py", FIXTURE);
+        assert!(report.starts_with(&format!("py_refs_lib.py: {} tokens for model dummy", FIXTURE.len())), "{}", report);
+
+        let report = token_count_report(Err("404".to_string()), "dummy", "text", "abcdefghi");
+        assert!(report.starts_with("text: ~3 tokens, approximate"), "{}", report);
+        assert!(report.contains("404"), "{}", report);
+    }
+}
@@ -6,6 +6,7 @@ use tokio::sync::Mutex as AMutex;
 
 use crate::at_commands::at_commands::AtCommandsContext;
 use crate::at_commands::at_web::execute_at_web;
+use crate::privacy::{check_web_domain_allowed, load_privacy_if_needed};
 use crate::tools::tools_description::Tool;
 use crate::call_validation::{ChatMessage, ChatContent, ContextEnum};
 
@@ -18,7 +19,7 @@ impl Tool for ToolWeb {
 
     async fn tool_execute(
         &mut self,
-        _ccx: Arc<AMutex<AtCommandsContext>>,
+        ccx: Arc<AMutex<AtCommandsContext>>,
         tool_call_id: &String,
         args: &HashMap<String, Value>,
     ) -> Result<(bool, Vec<ContextEnum>), String> {
@@ -28,6 +29,8 @@ impl Tool for ToolWeb {
             None => return Err("Missing argument `url` for att_web".to_string())
         };
 
+        let gcx = ccx.lock().await.global_context.clone();
+        check_web_domain_allowed(load_privacy_if_needed(gcx).await, &url)?;
         let text = execute_at_web(&url).await?;
 
         let mut results = vec![];
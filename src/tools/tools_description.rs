@@ -8,7 +8,7 @@ use tokio::sync::RwLock as ARwLock;
 use tokio::sync::Mutex as AMutex;
 
 use crate::at_commands::at_commands::AtCommandsContext;
-use crate::call_validation::{ChatUsage, ContextEnum};
+use crate::call_validation::{ChatUsage, ContextEnum, ToolsPermissions};
 use crate::global_context::GlobalContext;
 use crate::integrations::integr_abstract::IntegrationConfirmation;
 use crate::tools::tools_execute::{command_should_be_confirmed_by_user, command_should_be_denied};
@@ -48,35 +48,7 @@ pub trait Tool: Send + Sync {
         let command_to_match = self.command_to_match_against_confirm_deny(&args).map_err(|e| {
             format!("Error getting tool command to match: {}", e)
         })?;
-
-        if !command_to_match.is_empty() {
-            if let Some(rules) = &self.confirm_deny_rules() {
-                tracing::info!("confirmation: match {:?} against {:?}", command_to_match, rules);
-                let (is_denied, deny_rule) = command_should_be_denied(&command_to_match, &rules.deny);
-                if is_denied {
-                    return Ok(MatchConfirmDeny {
-                        result: MatchConfirmDenyResult::DENY,
-                        command: command_to_match.clone(),
-                        rule: deny_rule.clone(),
-                    });
-                }
-                let (needs_confirmation, confirmation_rule) = command_should_be_confirmed_by_user(&command_to_match, &rules.ask_user);
-                if needs_confirmation {
-                    return Ok(MatchConfirmDeny {
-                        result: MatchConfirmDenyResult::CONFIRMATION,
-                        command: command_to_match.clone(),
-                        rule: confirmation_rule.clone(),
-                    });
-                }
-            } else {
-                tracing::error!("No confirmation info available for {:?}", command_to_match);
-            }
-        }
-        Ok(MatchConfirmDeny {
-            result: MatchConfirmDenyResult::PASS,
-            command: command_to_match.clone(),
-            rule: "".to_string(),
-        })
+        Ok(match_command_against_rules(&command_to_match, self.confirm_deny_rules()))
     }
 
     fn command_to_match_against_confirm_deny(
@@ -113,26 +85,85 @@ pub trait Tool: Send + Sync {
     }
 }
 
+// Deny rules go first, a command that matches both deny and ask_user is denied
+pub fn match_command_against_rules(command_to_match: &String, rules: Option<IntegrationConfirmation>) -> MatchConfirmDeny {
+    if !command_to_match.is_empty() {
+        if let Some(rules) = &rules {
+            tracing::info!("confirmation: match {:?} against {:?}", command_to_match, rules);
+            let (is_denied, deny_rule) = command_should_be_denied(&command_to_match, &rules.deny);
+            if is_denied {
+                return MatchConfirmDeny {
+                    result: MatchConfirmDenyResult::DENY,
+                    command: command_to_match.clone(),
+                    rule: deny_rule.clone(),
+                };
+            }
+            let (needs_confirmation, confirmation_rule) = command_should_be_confirmed_by_user(&command_to_match, &rules.ask_user);
+            if needs_confirmation {
+                return MatchConfirmDeny {
+                    result: MatchConfirmDenyResult::CONFIRMATION,
+                    command: command_to_match.clone(),
+                    rule: confirmation_rule.clone(),
+                };
+            }
+        } else {
+            tracing::error!("No confirmation info available for {:?}", command_to_match);
+        }
+    }
+    MatchConfirmDeny {
+        result: MatchConfirmDenyResult::PASS,
+        command: command_to_match.clone(),
+        rule: "".to_string(),
+    }
+}
+
 pub async fn tools_merged_and_filtered(
     gcx: Arc<ARwLock<GlobalContext>>,
     _supports_clicks: bool,  // XXX
 ) -> Result<IndexMap<String, Box<dyn Tool + Send>>, String> {
-    let (ast_on, vecdb_on, allow_experimental) = {
+    let (ast_on, vecdb_on, allow_experimental, tools_permissions) = {
         let gcx_locked = gcx.read().await;
         #[cfg(feature="vecdb")]
         let vecdb_on = gcx_locked.vec_db.lock().await.is_some();
         #[cfg(not(feature="vecdb"))]
         let vecdb_on = false;
-        (gcx_locked.ast_service.is_some(), vecdb_on, gcx_locked.cmdline.experimental)
+        let tools_permissions = ToolsPermissions::from_comma_separated(&gcx_locked.cmdline.tools_allow, &gcx_locked.cmdline.tools_deny);
+        (gcx_locked.ast_service.is_some(), vecdb_on, gcx_locked.cmdline.experimental, tools_permissions)
     };
 
     let mut tools_all = IndexMap::from([
         ("definition".to_string(), Box::new(crate::tools::tool_ast_definition::ToolAstDefinition{}) as Box<dyn Tool + Send>),
         ("references".to_string(), Box::new(crate::tools::tool_ast_reference::ToolAstReference{}) as Box<dyn Tool + Send>),
+        ("call_graph".to_string(), Box::new(crate::tools::tool_call_graph::ToolCallGraph{}) as Box<dyn Tool + Send>),
+        ("rename_symbol".to_string(), Box::new(crate::tools::tool_rename_symbol::ToolRenameSymbol{}) as Box<dyn Tool + Send>),
         ("tree".to_string(), Box::new(crate::tools::tool_tree::ToolTree{}) as Box<dyn Tool + Send>),
         ("patch".to_string(), Box::new(crate::tools::tool_patch::ToolPatch::new()) as Box<dyn Tool + Send>),
         ("web".to_string(), Box::new(crate::tools::tool_web::ToolWeb{}) as Box<dyn Tool + Send>),
+        ("docs".to_string(), Box::new(crate::tools::tool_docs::ToolDocs{}) as Box<dyn Tool + Send>),
         ("cat".to_string(), Box::new(crate::tools::tool_cat::ToolCat{}) as Box<dyn Tool + Send>),
+        ("token_count".to_string(), Box::new(crate::tools::tool_token_count::ToolTokenCount{}) as Box<dyn Tool + Send>),
+        ("detect_tools".to_string(), Box::new(crate::tools::tool_detect_tools::ToolDetectTools{}) as Box<dyn Tool + Send>),
+        ("decode_jwt".to_string(), Box::new(crate::tools::tool_jwt::ToolJwt{}) as Box<dyn Tool + Send>),
+        ("complexity".to_string(), Box::new(crate::tools::tool_complexity::ToolComplexity{}) as Box<dyn Tool + Send>),
+        ("module_graph".to_string(), Box::new(crate::tools::tool_module_graph::ToolModuleGraph{}) as Box<dyn Tool + Send>),
+        ("syntax_check".to_string(), Box::new(crate::tools::tool_syntax_check::ToolSyntaxCheck{}) as Box<dyn Tool + Send>),
+        ("dir_overview".to_string(), Box::new(crate::tools::tool_dir_overview::ToolDirOverview{}) as Box<dyn Tool + Send>),
+        ("extract_strings".to_string(), Box::new(crate::tools::tool_extract_strings::ToolExtractStrings{}) as Box<dyn Tool + Send>),
+        ("deps".to_string(), Box::new(crate::tools::tool_deps::ToolDeps{}) as Box<dyn Tool + Send>),
+        ("coverage_gaps".to_string(), Box::new(crate::tools::tool_coverage_gaps::ToolCoverageGaps{}) as Box<dyn Tool + Send>),
+        ("secret_scan".to_string(), Box::new(crate::tools::tool_secret_scan::ToolSecretScan{}) as Box<dyn Tool + Send>),
+        ("compare_symbols".to_string(), Box::new(crate::tools::tool_compare_symbols::ToolCompareSymbols{}) as Box<dyn Tool + Send>),
+        ("json_query".to_string(), Box::new(crate::tools::tool_json_query::ToolJsonQuery{}) as Box<dyn Tool + Send>),
+        ("diagram".to_string(), Box::new(crate::tools::tool_diagram::ToolDiagram{}) as Box<dyn Tool + Send>),
+        ("goto_definition".to_string(), Box::new(crate::tools::tool_goto_definition::ToolGotoDefinition{}) as Box<dyn Tool + Send>),
+        ("parse_stacktrace".to_string(), Box::new(crate::tools::tool_parse_stacktrace::ToolParseStacktrace{}) as Box<dyn Tool + Send>),
+        ("run_doc_examples".to_string(), Box::new(crate::tools::tool_run_doc_examples::ToolRunDocExamples{}) as Box<dyn Tool + Send>),
+        ("bisect_diff".to_string(), Box::new(crate::tools::tool_bisect_diff::ToolBisectDiff{}) as Box<dyn Tool + Send>),
+        ("git_branch".to_string(), Box::new(crate::tools::tool_git_branch::ToolGitBranch{}) as Box<dyn Tool + Send>),
+        ("commit_message".to_string(), Box::new(crate::tools::tool_commit_message::ToolCommitMessage{}) as Box<dyn Tool + Send>),
+        ("service_logs".to_string(), Box::new(crate::tools::tool_service_logs::ToolServiceLogs{}) as Box<dyn Tool + Send>),
+        ("grep".to_string(), Box::new(crate::tools::tool_grep::ToolGrep{}) as Box<dyn Tool + Send>),
+        ("todos".to_string(), Box::new(crate::tools::tool_todos::ToolTodos{}) as Box<dyn Tool + Send>),
         // ("locate".to_string(), Box::new(crate::tools::tool_locate::ToolLocate{}) as Box<dyn Tool + Send>))),
         // ("locate".to_string(), Box::new(crate::tools::tool_relevant_files::ToolRelevantFiles{}) as Box<dyn Tool + Send>))),
         #[cfg(feature="vecdb")]
@@ -143,11 +174,15 @@ pub async fn tools_merged_and_filtered(
 
     #[cfg(feature="vecdb")]
     tools_all.insert("knowledge".to_string(), Box::new(crate::tools::tool_knowledge::ToolGetKnowledge{}) as Box<dyn Tool + Send>);
+    #[cfg(feature="vecdb")]
+    tools_all.insert("search_memory".to_string(), Box::new(crate::tools::tool_search_memory::ToolSearchMemory{}) as Box<dyn Tool + Send>);
 
-    let integrations = crate::integrations::running_integrations::load_integration_tools(
+    let mut integrations = crate::integrations::running_integrations::load_integration_tools(
         gcx.clone(),
         allow_experimental,
     ).await;
+    // config files come in whatever order the filesystem lists them, the tools part of the prompt should not change between runs
+    integrations.sort_keys();
     tools_all.extend(integrations);
 
     let mut filtered_tools = IndexMap::new();
@@ -159,6 +194,9 @@ pub async fn tools_merged_and_filtered(
         if dependencies.contains(&"vecdb".to_string()) && !vecdb_on {
             continue;
         }
+        if tools_permissions.check(&tool_name).is_err() {
+            continue;
+        }
         filtered_tools.insert(tool_name, tool);
     }
 
@@ -175,7 +213,7 @@ tools:
         description: "Single line, paragraph or code sample to search for similar content."
       - name: "scope"
         type: "string"
-        description: "'workspace' to search all files in workspace, 'dir/subdir/' to search in files within a directory, 'dir/file.ext' to search in a single file."
+        description: "'workspace' to search files in the workspace folder of the active file, 'all_workspaces' to search all open workspace folders, 'dir/subdir/' to search in files within a directory, 'dir/file.ext' to search in a single file."
     parameters_required:
       - "query"
       - "scope"
@@ -204,6 +242,31 @@ tools:
     parameters_required:
       - "symbol"
 
+  - name: "call_graph"
+    description: "Show who calls a function and what it calls, using AST cross-references. Use it to estimate the impact of a change."
+    parameters:
+      - name: "symbol"
+        type: "string"
+        description: "The exact name of a function or method. No spaces allowed."
+      - name: "depth"
+        type: "integer"
+        description: "How many levels of callers and callees to show, 1 to 4, default 2."
+    parameters_required:
+      - "symbol"
+
+  - name: "rename_symbol"
+    description: "Rename a function, method, class or variable everywhere it's used, using AST references. Strings and comments are not touched, occurrences that could not be renamed safely are reported."
+    parameters:
+      - name: "symbol"
+        type: "string"
+        description: "The exact name of the symbol to rename, like my_function or MyClass::method."
+      - name: "new_name"
+        type: "string"
+        description: "The new name, a plain identifier without the class or module part."
+    parameters_required:
+      - "symbol"
+      - "new_name"
+
   - name: "tree"
     description: "Get a files tree with symbols for the project. Use it to get familiar with the project, file names and symbols"
     parameters:
@@ -224,6 +287,22 @@ tools:
     parameters_required:
       - "url"
 
+  - name: "docs"
+    description: "Read official API documentation: docs.rs for Rust crates, docs.python.org for the Python standard library."
+    parameters:
+      - name: "language"
+        type: "string"
+        description: "Either rust or python."
+      - name: "module"
+        type: "string"
+        description: "Rust crate name like serde_json or std, or Python module like os.path"
+      - name: "symbol"
+        type: "string"
+        description: "Optional item inside the module, for example Value, Value::is_null for Rust or join, OrderedDict.popitem for Python. Skip it to read the module overview."
+    parameters_required:
+      - "language"
+      - "module"
+
   - name: "cat"
     description: "Like cat in console, but better: it can read multiple files and skeletonize them. Give it AST symbols important for the goal (classes, functions, variables, etc) to see them in full. It can also read images just fine."
     parameters:
@@ -236,11 +315,317 @@ tools:
       - name: "skeleton"
         type: "boolean"
         description: "if true, files will be skeletonized - mostly only AST symbols will be visible"
+      - name: "line1"
+        type: "integer"
+        description: "Optional first line to show, starts from 1. Applies to whole files, not to symbols."
+      - name: "line2"
+        type: "integer"
+        description: "Optional last line to show, inclusive."
+      - name: "line_numbers"
+        type: "boolean"
+        description: "if true, each line is prefixed with its number, useful before making a patch"
+    parameters_required:
+      - "paths"
+
+  - name: "token_count"
+    description: "Count how many tokens a file, a range of lines or a piece of text takes for a model, to decide what fits into the context."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "File name, optionally with a line range like dir/file.py:10-40. Give either path or text."
+      - name: "text"
+        type: "string"
+        description: "Text to count tokens in, instead of a file."
+      - name: "model"
+        type: "string"
+        description: "Optional model name, the current chat model is used if not set."
+    parameters_required: []
+
+  - name: "detect_tools"
+    description: "Find out which command line tools are installed: interpreters, compilers, package managers, docker, database clients, language servers. Shows the path and version of each one found. Use it before setting up an integration."
+    parameters:
+      - name: "names"
+        type: "string"
+        description: "Optional comma separated extra commands to look for, in addition to the usual ones, like terraform, ansible"
+    parameters_required: []
+
+  - name: "decode_jwt"
+    description: "Decode a JWT to debug authentication: shows the header and the claims, with exp and iat as dates. The signature is not verified."
+    parameters:
+      - name: "token"
+        type: "string"
+        description: "The token, with or without the Bearer prefix"
+    parameters_required:
+      - "token"
+
+  - name: "complexity"
+    description: "Cyclomatic complexity of each function in a file: 1 plus the number of if/elif, loops, catch, switch cases, match arms, ternaries and &&/|| operators. Functions above the threshold are flagged, use it to find code that needs refactoring or more tests."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "Source file, for example src/orders.py"
+      - name: "symbol"
+        type: "string"
+        description: "Optional, only this function or method"
+      - name: "threshold"
+        type: "integer"
+        description: "Flag functions with complexity above this, 10 by default"
+    parameters_required:
+      - "path"
+
+  - name: "module_graph"
+    description: "Dependency graph of the workspace source files built from their imports: which file imports which. Stdlib and third-party imports are left out. Use it to understand the architecture of an unfamiliar project."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "Optional, only files under this directory, for example src/api"
+      - name: "format"
+        type: "string"
+        description: "\"text\" (default) or \"dot\" for Graphviz"
+    parameters_required: []
+
+  - name: "syntax_check"
+    description: "Check that a source file still parses, call it after editing a file. Reports the line and column of each syntax error, or OK. Python files are also checked with python's compile() if python is installed."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "Source file, for example src/orders.py"
+    parameters_required:
+      - "path"
+
+  - name: "dir_overview"
+    description: "What's in a directory: subdirectories and files as a compact tree, each source file with the first line of its top comment or docstring as a description. Use it to get oriented in an unfamiliar project."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "Optional, a directory, for example src/api. The whole project if not given."
+      - name: "depth"
+        type: "integer"
+        description: "How many levels to show, 2 by default, 5 at most"
+    parameters_required: []
+
+  - name: "extract_strings"
+    description: "Find hardcoded user-facing strings in a source file, for localization work. Lists string literals with their line numbers, skipping imports, logging calls, docstrings and test files."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "Source file to look at"
+      - name: "min_len"
+        type: "integer"
+        description: "Skip strings shorter than this, 4 by default"
+    parameters_required:
+      - "path"
+
+  - name: "deps"
+    description: "List the dependencies declared in Cargo.toml, package.json, pyproject.toml or requirements.txt with their versions. With check_latest it asks crates.io, npm or PyPI for the latest versions and marks the outdated ones."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "Optional, a manifest file or a directory with one. The project root if not given."
+      - name: "check_latest"
+        type: "boolean"
+        description: "Look up the latest versions in the package registry, false by default"
+    parameters_required: []
+
+  - name: "coverage_gaps"
+    description: "Functions and methods in a file that no test calls, found through the AST references. Use it to decide what to write tests for first."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "Source file with the code under test, not the test file"
+    parameters_required:
+      - "path"
+
+  - name: "secret_scan"
+    description: "Look for credentials committed by accident: AWS keys, private keys, tokens, passwords in config. Reports file:line with the value redacted."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "File to scan"
+      - name: "changed_only"
+        type: "boolean"
+        description: "Only scan lines added or changed since the last commit, false by default"
+    parameters_required:
+      - "path"
+
+  - name: "compare_symbols"
+    description: "Compare two functions or classes by their syntax tree and tell if they do the same thing, formatting and comments don't matter. Useful to find duplicates and to check that a refactoring kept the logic."
+    parameters:
+      - name: "symbol1"
+        type: "string"
+        description: "Name of the first symbol, a longer path like MyClass::method if the name is ambiguous"
+      - name: "symbol2"
+        type: "string"
+        description: "Name of the second symbol"
+      - name: "ignore_names"
+        type: "boolean"
+        description: "Treat a consistent renaming of variables as equivalent, false by default"
+    parameters_required:
+      - "symbol1"
+      - "symbol2"
+
+  - name: "json_query"
+    description: "Pretty-print parts of a JSON document selected by a JSONPath expression, like $.items[0].name, $..id, $.items[*].name or $.items[?(@.price < 10)].name. Use it on large JSON files and tool outputs instead of reading them whole."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "JSON file to query, give either path or json"
+      - name: "json"
+        type: "string"
+        description: "JSON text to query, give either path or json"
+      - name: "expression"
+        type: "string"
+        description: "JSONPath expression, $ for the whole document"
+    parameters_required:
+      - "expression"
+
+  - name: "diagram"
+    description: "Make a Mermaid class diagram of classes, their methods and inheritance from the syntax tree of some files or a directory. Returns the diagram source, not an image. Good for documentation and for explaining architecture."
+    parameters:
+      - name: "paths"
+        type: "string"
+        description: "Comma-separated files or directories, a directory means all files in it"
     parameters_required:
       - "paths"
 
+  - name: "goto_definition"
+    description: "Find where the symbol at a position in a file is defined, like go to definition in an IDE. On an import, goes to the imported file or name. Returns file and line range."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "File where the symbol is used"
+      - name: "line"
+        type: "integer"
+        description: "Line number, starting from 1"
+      - name: "col"
+        type: "integer"
+        description: "Column of any character of the symbol, starting from 1"
+    parameters_required:
+      - "path"
+      - "line"
+      - "col"
+
+  - name: "parse_stacktrace"
+    description: "Map a pasted stack trace to workspace files. Understands Python, Java, Node and Rust traces, returns each frame that is in the workspace with its source lines and marks the likely culprit. Library frames and files outside the workspace are listed separately."
+    parameters:
+      - name: "stacktrace"
+        type: "string"
+        description: "The stack trace text as it was printed"
+    parameters_required:
+      - "stacktrace"
+
   # -- agentic tools below --
 
+  - name: "run_doc_examples"
+    agentic: true
+    description: "Check that code examples in a markdown file work. Call it without blocks to list the fenced code blocks of a language, then call it again with the block numbers to run them. Each block reports PASS or FAIL with its output."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "Markdown file, for example README.md or docs/usage.md"
+      - name: "language"
+        type: "string"
+        description: "Language of the blocks: sh, python or javascript."
+      - name: "blocks"
+        type: "string"
+        description: "Comma separated block numbers from the list, like 1,3. Skip it to only list the blocks."
+    parameters_required:
+      - "path"
+      - "language"
+
+  - name: "bisect_diff"
+    agentic: true
+    description: "Find which hunks of a large diff cause a test failure. Applies subsets of the hunks to the files, runs the test command after each, and narrows down to the smallest set of hunks that still fails. The files are restored afterwards."
+    parameters:
+      - name: "diff"
+        type: "string"
+        description: "Unified diff like `git diff` prints, it should apply to the files as they are now"
+      - name: "test_command"
+        type: "string"
+        description: "Shell command that exits with non-zero code when the problem is there, for example `cargo test parser::`"
+      - name: "workdir"
+        type: "string"
+        description: "Directory to run the test in, paths in the diff are relative to it. The active project if not set."
+      - name: "timeout"
+        type: "string"
+        description: "Seconds for one test run, 120 by default. A run that times out counts as a failure."
+    parameters_required:
+      - "diff"
+      - "test_command"
+
+  - name: "git_branch"
+    agentic: true
+    description: "Manage git branches in the current project: list them, create a new one, switch to one, or delete one. Switching refuses to run over uncommitted changes unless stash is set."
+    parameters:
+      - name: "operation"
+        type: "string"
+        description: "One of: list, create, checkout, delete"
+      - name: "name"
+        type: "string"
+        description: "Branch name, required for everything except list"
+      - name: "start_point"
+        type: "string"
+        description: "For create: commit or branch to start from, the current commit if not set"
+      - name: "checkout"
+        type: "boolean"
+        description: "For create: switch to the new branch right away"
+      - name: "stash"
+        type: "boolean"
+        description: "For checkout: stash uncommitted changes first instead of refusing"
+      - name: "force"
+        type: "boolean"
+        description: "For delete: delete even if the branch is not merged, asks the user first"
+    parameters_required:
+      - "operation"
+
+  - name: "commit_message"
+    agentic: true
+    description: "Draft a conventional commit message (type, scope, subject, list of files) from the staged changes of the current project, together with the staged diff. It doesn't commit anything. Stage the files with git add first."
+    parameters:
+      - name: "hint"
+        type: "string"
+        description: "Optional, what the change is for in a few words, becomes the subject instead of the list of files"
+    parameters_required: []
+
+  - name: "service_logs"
+    agentic: true
+    description: "Read the recent output of a background service started by one of the service_* tools, for example to see why a web server returns errors. If the output is long, lines with errors are kept first."
+    parameters:
+      - name: "service"
+        type: "string"
+        description: "Service tool name, like service_frontend"
+      - name: "lines"
+        type: "integer"
+        description: "How many last lines to show, 100 by default"
+    parameters_required:
+      - "service"
+
+  - name: "grep"
+    description: "Search all workspace files for lines matching a regular expression. Results show up one by one while the scan runs, the last line is a summary with the number of files and matches."
+    parameters:
+      - name: "pattern"
+        type: "string"
+        description: "Regular expression, Rust regex syntax"
+      - name: "path"
+        type: "string"
+        description: "Optional, only search under this directory"
+      - name: "stream"
+        type: "boolean"
+        description: "Show matches while the scan runs, true by default"
+    parameters_required:
+      - "pattern"
+
+  - name: "todos"
+    description: "List TODO, FIXME, XXX and HACK notes left in the workspace files. Results show up one by one while the scan runs, the last line is a summary with the number of files and notes."
+    parameters:
+      - name: "path"
+        type: "string"
+        description: "Optional, only scan under this directory"
+      - name: "stream"
+        type: "boolean"
+        description: "Show notes while the scan runs, true by default"
+    parameters_required: []
+
   - name: "locate"
     agentic: true
     description: "Get a list of files that are relevant to solve a particular task."
@@ -320,8 +705,15 @@ tools:
           Don't forget semicolon at the end, examples:
           SELECT * FROM table_name;
           CREATE INDEX my_index_users_email ON my_users (email);
-    parameters_required:
-      - "query"
+          With mode="migrate" this is the up statement, it runs in a transaction.
+          Required except with mode="rollback".
+      - name: "mode"
+        type: "string"
+        description: "\"query\" (default), \"migrate\" to apply a migration and keep its `down`, or \"rollback\" to run the `down` of the latest migration, `query` is ignored then."
+      - name: "down"
+        type: "string"
+        description: "Only with mode=\"migrate\": the statement that undoes the migration, for example DROP TABLE my_users; Without it the migration can't be rolled back."
+    parameters_required: []
 
   - name: "mysql"
     agentic: true
@@ -333,8 +725,16 @@ tools:
           Don't forget semicolon at the end, examples:
           SELECT * FROM table_name;
           CREATE INDEX my_index_users_email ON my_users (email);
-    parameters_required:
-      - "query"
+          With mode="migrate" this is the up statement, it runs in a transaction.
+          Required except with mode="rollback".
+          MySQL commits CREATE, ALTER and DROP on its own, the transaction only protects data changes.
+      - name: "mode"
+        type: "string"
+        description: "\"query\" (default), \"migrate\" to apply a migration and keep its `down`, or \"rollback\" to run the `down` of the latest migration, `query` is ignored then."
+      - name: "down"
+        type: "string"
+        description: "Only with mode=\"migrate\": the statement that undoes the migration, for example DROP TABLE my_users; Without it the migration can't be rolled back."
+    parameters_required: []
 
   - name: "docker"
     agentic: true
@@ -363,11 +763,29 @@ tools:
       - name: "language_slash_framework"
         type: "string"
         description: "What programming language and framework is the current project using? Use lowercase, dashes and dots. Examples: python/django, typescript/node.js, rust/tokio, ruby/rails, php/laravel, c++/boost-asio"
+      - name: "tag"
+        type: "string"
+        description: "Optional, only look at memories with this tag, for example architecture or deployment"
     parameters_required:
       - "im_going_to_use_tools"
       - "im_going_to_apply_to"
       - "goal"
       - "language_slash_framework"
+
+  - name: "search_memory"
+    agentic: true
+    description: "Semantic search over the stored memories, returns the closest ones with their ids. Use it to recall facts and past decisions about the project. With a tag and no query it lists the memories with that tag."
+    parameters:
+      - name: "query"
+        type: "string"
+        description: "What you want to remember, in a few words."
+      - name: "tag"
+        type: "string"
+        description: "Only memories with this tag, for example architecture. Give a tag, a query, or both."
+      - name: "top_n"
+        type: "string"
+        description: "How many memories to return, default 5 for a query and 20 for a tag listing, at most 20."
+    parameters_required: []
 "####;
 
 
@@ -466,17 +884,84 @@ pub async fn tool_description_list_from_yaml(
     let tool_desc_deser: ToolDictDeserialize = serde_yaml::from_str(BUILT_IN_TOOLS)
         .map_err(|e|format!("Failed to parse BUILT_IN_TOOLS: {}", e))?;
 
+    // built-in tools in BUILT_IN_TOOLS order, then the rest by name, whatever order the caller collected them in
     let mut tool_desc_vec = vec![];
     tool_desc_vec.extend(tool_desc_deser.tools.iter().cloned());
 
+    let mut other_tools = vec![];
     for (tool_name, tool) in tools {
         if !tool_desc_vec.iter().any(|desc| desc.name == tool_name) {
-            tool_desc_vec.push(tool.tool_description());
+            other_tools.push(tool.tool_description());
         }
     }
+    other_tools.sort_by(|a, b| a.name.cmp(&b.name));
+    tool_desc_vec.extend(other_tools);
 
     Ok(tool_desc_vec.iter()
         .filter(|x| turned_on.contains(&x.name) && (allow_experimental || !x.experimental))
         .cloned()
         .collect::<Vec<_>>())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeIntegrationTool {
+        name: String,
+    }
+
+    #[async_trait]
+    impl Tool for FakeIntegrationTool {
+        fn as_any(&self) -> &dyn std::any::Any { self }
+
+        async fn tool_execute(
+            &mut self,
+            _ccx: Arc<AMutex<AtCommandsContext>>,
+            _tool_call_id: &String,
+            _args: &HashMap<String, Value>,
+        ) -> Result<(bool, Vec<ContextEnum>), String> {
+            Err(format!("{} is a fake integration, it only has a description", self.name))
+        }
+
+        fn tool_description(&self) -> ToolDesc {
+            ToolDesc {
+                name: self.name.clone(),
+                agentic: false,
+                experimental: false,
+                description: format!("{} integration", self.name),
+                parameters: vec![],
+                parameters_required: vec![],
+            }
+        }
+    }
+
+    fn tools_in_order(names: &[&str]) -> IndexMap<String, Box<dyn Tool + Send>> {
+        names.iter().map(|name| {
+            let tool: Box<dyn Tool + Send> = match *name {
+                "cat" => Box::new(crate::tools::tool_cat::ToolCat{}),
+                "tree" => Box::new(crate::tools::tool_tree::ToolTree{}),
+                _ => Box::new(FakeIntegrationTool { name: name.to_string() }),
+            };
+            (name.to_string(), tool)
+        }).collect()
+    }
+
+    #[tokio::test]
+    async fn test_tool_order_is_stable() {
+        let turned_on: Vec<String> = ["postgres", "cat", "chrome", "tree", "github"].iter().map(|s| s.to_string()).collect();
+        let mut orders = vec![];
+        for names in [
+            ["postgres", "cat", "chrome", "tree", "github"],
+            ["github", "tree", "postgres", "chrome", "cat"],
+            ["chrome", "github", "cat", "postgres", "tree"],
+        ] {
+            let descs = tool_description_list_from_yaml(tools_in_order(&names), &turned_on, false).await.unwrap();
+            orders.push(descs.iter().map(|d| d.name.clone()).collect::<Vec<_>>());
+        }
+        // built-ins as BUILT_IN_TOOLS lists them, then integrations alphabetically
+        assert_eq!(orders[0], vec!["tree", "cat", "chrome", "github", "postgres"]);
+        assert_eq!(orders[0], orders[1]);
+        assert_eq!(orders[0], orders[2]);
+    }
+}
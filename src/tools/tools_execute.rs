@@ -1,8 +1,10 @@
 use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use glob::Pattern;
 use indexmap::IndexMap;
 use tokio::sync::Mutex as AMutex;
+use tokio::sync::mpsc;
 use serde_json::{json, Value};
 use tokenizers::Tokenizer;
 use tracing::{info, warn};
@@ -18,6 +20,8 @@ use crate::scratchpads::scratchpad_utils::{HasRagResults, max_tokens_for_rag_cha
 use crate::tools::tools_description::{MatchConfirmDenyResult, Tool};
 use crate::yaml_configs::customization_loader::load_customization;
 use crate::caps::get_model_record;
+use crate::files_correction::get_project_dirs;
+use crate::global_context::GlobalContext;
 use crate::http::routers::v1::at_tools::{ToolExecuteResponse, ToolsExecutePost};
 
 
@@ -68,7 +72,7 @@ pub async fn run_tools_remotely(
     style: &Option<String>,
     tools_confirmation: bool,
 ) -> Result<(Vec<ChatMessage>, bool), String> {
-    let (n_ctx, subchat_tool_parameters, postprocess_parameters, gcx, chat_id) = {
+    let (n_ctx, subchat_tool_parameters, postprocess_parameters, gcx, chat_id, tools_permissions) = {
         let ccx_locked = ccx.lock().await;
         (
             ccx_locked.n_ctx,
@@ -76,6 +80,7 @@ pub async fn run_tools_remotely(
             ccx_locked.postprocess_parameters.clone(),
             ccx_locked.global_context.clone(),
             ccx_locked.chat_id.clone(),
+            ccx_locked.tools_permissions.clone(),
         )
     };
 
@@ -89,6 +94,7 @@ pub async fn run_tools_remotely(
         chat_id: chat_id.clone(),
         style: style.clone(),
         tools_confirmation: tools_confirmation.clone(),
+        tools_permissions,
     };
 
     let port = docker_container_get_host_lsp_port_to_connect(gcx.clone(), &chat_id).await?;
@@ -162,8 +168,18 @@ pub async fn run_tools(
     let mut generated_tool = vec![];  // tool results must go first
     let mut generated_other = vec![];
     let mut any_corrections = false;
+    let tools_permissions = ccx.lock().await.tools_permissions.clone();
+    let safe_roots = {
+        let gcx = ccx.lock().await.global_context.clone();
+        safe_roots_if_confined(gcx).await
+    };
 
     for t_call in last_msg_tool_calls {
+        if let Err(e) = tools_permissions.check(&t_call.function.name) {
+            warn!("{}", e);
+            generated_tool.push(tool_answer(e, t_call.id.to_string()));
+            continue;
+        }
         let cmd = match tools.get_mut(&t_call.function.name) {
             Some(cmd) => cmd,
             None => {
@@ -188,6 +204,14 @@ pub async fn run_tools(
         };
         info!("tool use {}({:?})", &t_call.function.name, args);
 
+        if let Some(roots) = &safe_roots {
+            if let Err(e) = check_paths_under_safe_roots(&args, roots) {
+                warn!("tool use {}: {}", &t_call.function.name, e);
+                generated_tool.push(tool_answer(e, t_call.id.to_string()));
+                continue;
+            }
+        }
+
         match cmd.match_against_confirm_deny(ccx.clone(), &args).await {
             Ok(res) => {
                 match res.result {
@@ -388,6 +412,78 @@ async fn pp_run_tools(
 }
 
 
+// Arguments of any tool that are file or directory paths, "paths" can be comma separated;
+// "workdir" is where shell, pdb and bisect_diff run their commands
+const PATH_ARGS: &[&str] = &["path", "paths", "file_path", "project_dir", "workdir"];
+
+fn normalize_path_lexically(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !result.pop() {
+                    result.push("..");
+                }
+            }
+            _ => result.push(component.as_os_str()),
+        }
+    }
+    result
+}
+
+fn resolve_for_safe_root(path: &Path) -> PathBuf {
+    // symlinks are followed if the path exists, so a link can't be used to get out
+    path.canonicalize().unwrap_or_else(|_| normalize_path_lexically(path))
+}
+
+pub async fn safe_roots_if_confined(gcx: Arc<tokio::sync::RwLock<GlobalContext>>) -> Option<Vec<PathBuf>> {
+    let (confine_paths, inside_container, allowed_dirs) = {
+        let gcx_locked = gcx.read().await;
+        (gcx_locked.cmdline.confine_paths.clone(), gcx_locked.cmdline.inside_container, gcx_locked.cmdline.allowed_dirs.clone())
+    };
+    let confined = match confine_paths.as_str() {
+        "on" => true,
+        "off" => false,
+        _ => inside_container,
+    };
+    if !confined {
+        return None;
+    }
+    let mut roots = get_project_dirs(gcx.clone()).await;
+    roots.extend(allowed_dirs.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()).map(PathBuf::from));
+    Some(roots)
+}
+
+pub fn check_paths_under_safe_roots(args: &HashMap<String, Value>, roots: &Vec<PathBuf>) -> Result<(), String> {
+    let roots_resolved: Vec<PathBuf> = roots.iter().map(|r| resolve_for_safe_root(r)).collect();
+    for arg_name in PATH_ARGS {
+        let value = match args.get(*arg_name) {
+            Some(Value::String(s)) => s,
+            _ => continue,
+        };
+        for p in value.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            let path = PathBuf::from(p);
+            let inside = if path.is_absolute() {
+                let resolved = resolve_for_safe_root(&path);
+                roots_resolved.iter().any(|r| resolved.starts_with(r))
+            } else {
+                // relative paths are looked up inside the workspace later, they only need to stay inside
+                roots_resolved.iter().any(|r| resolve_for_safe_root(&r.join(&path)).starts_with(r))
+            };
+            if !inside {
+                let roots_list = if roots.is_empty() {
+                    "(none, no workspace folders are open)".to_string()
+                } else {
+                    roots.iter().map(|r| r.to_string_lossy().to_string()).collect::<Vec<_>>().join("\n")
+                };
+                return Err(format!("Path {:?} is outside of the allowed directories, tools can only access files under:\n{}", p, roots_list));
+            }
+        }
+    }
+    Ok(())
+}
+
 fn tool_answer(content: String, tool_call_id: String) -> ChatMessage {
     ChatMessage {
         role: "tool".to_string(),
@@ -398,6 +494,57 @@ fn tool_answer(content: String, tool_call_id: String) -> ChatMessage {
     }
 }
 
+pub const TOOL_OUTPUT_CHUNK_MAX_CHARS: usize = 2048;
+
+pub fn split_tool_output_into_chunks(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+    let mut current_chars = 0;
+    for c in text.chars() {
+        current.push(c);
+        current_chars += 1;
+        if current_chars >= max_chars {
+            chunks.push(std::mem::take(&mut current));
+            current_chars = 0;
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+// Sends partial tool output to the user while the tool is still running, via the same subchat_tx channel
+// restream uses to relay subchats. The final tool message still goes the usual way, this is only for display.
+pub struct ToolOutputStreamer {
+    pub subchat_tx: Arc<AMutex<mpsc::UnboundedSender<Value>>>,
+    pub tool_call_id: String,
+    pub chunks_sent: usize,
+}
+
+impl ToolOutputStreamer {
+    pub async fn new(ccx: Arc<AMutex<AtCommandsContext>>, tool_call_id: &String) -> Self {
+        ToolOutputStreamer {
+            subchat_tx: ccx.lock().await.subchat_tx.clone(),
+            tool_call_id: tool_call_id.clone(),
+            chunks_sent: 0,
+        }
+    }
+
+    pub async fn partial(&mut self, text: &str) {
+        for chunk in split_tool_output_into_chunks(text, TOOL_OUTPUT_CHUNK_MAX_CHARS) {
+            let message = json!({"tool_call_id": self.tool_call_id, "tool_output_partial": {"chunk_n": self.chunks_sent, "content": chunk}});
+            let _ = self.subchat_tx.lock().await.send(message);
+            self.chunks_sent += 1;
+        }
+    }
+
+    pub async fn finished(&self) {
+        let message = json!({"tool_call_id": self.tool_call_id, "tool_output_finished": {"chunks_total": self.chunks_sent}});
+        let _ = self.subchat_tx.lock().await.send(message);
+    }
+}
+
 pub fn command_should_be_confirmed_by_user(
     command: &String,
     commands_need_confirmation_rules: &Vec<String>,
@@ -424,3 +571,85 @@ pub fn command_should_be_denied(
 
     (false, "".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::call_validation::{ChatToolCall, ChatToolFunction, ToolsPermissions};
+
+    fn tool_call(id: &str, name: &str, arguments: Value) -> ChatToolCall {
+        ChatToolCall {
+            id: id.to_string(),
+            function: ChatToolFunction { name: name.to_string(), arguments: arguments.to_string() },
+            tool_type: "function".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forbidden_tool_call_rejected_by_run_tools() {
+        const DUMMY_TOKENIZER: &str = include_str!("../ast/dummy_tokenizer.json");
+        let dir = tempfile::tempdir().unwrap();
+        let secret = dir.path().join("secret.txt");
+        std::fs::write(&secret, "the password is hunter2\n").unwrap();
+        let gcx = crate::global_context::create_test_global_context(dir.path(), &[
+            "--workspace-folder", &dir.path().to_string_lossy(),
+            "--tools-deny", "cat",
+        ]).await;
+        let ccx = Arc::new(AMutex::new(AtCommandsContext::new(gcx.clone(), 8192, 5, false, vec![], "".to_string(), false).await));
+        // this request narrows it further, tree only
+        let narrowed = ccx.lock().await.tools_permissions.narrowed_by(&ToolsPermissions { allow: Some(vec!["tree".to_string()]), deny: vec![] });
+        ccx.lock().await.tools_permissions = narrowed;
+
+        // the model calls the tools anyway, and they are right there in the map
+        let mut tools: IndexMap<String, Box<dyn Tool + Send>> = IndexMap::from([
+            ("cat".to_string(), Box::new(crate::tools::tool_cat::ToolCat {}) as Box<dyn Tool + Send>),
+            ("grep".to_string(), Box::new(crate::tools::tool_grep::ToolGrep {}) as Box<dyn Tool + Send>),
+        ]);
+        let messages = vec![
+            ChatMessage::new("user".to_string(), "what's in secret.txt?".to_string()),
+            ChatMessage {
+                role: "assistant".to_string(),
+                tool_calls: Some(vec![
+                    tool_call("call_cat", "cat", json!({"paths": secret.to_string_lossy()})),
+                    tool_call("call_grep", "grep", json!({"pattern": "password"})),
+                ]),
+                ..Default::default()
+            },
+        ];
+        let tokenizer = Arc::new(RwLock::new(Tokenizer::from_str(DUMMY_TOKENIZER).unwrap()));
+        let (generated, _) = run_tools(ccx.clone(), &mut tools, tokenizer, 1000, &messages, &None, false).await.unwrap();
+
+        let answer = |id: &str| generated.iter().find(|m| m.tool_call_id == id).expect(id).content.content_text_only();
+        assert!(answer("call_cat").contains("forbidden by the tools deny list"), "{}", answer("call_cat"));
+        assert!(answer("call_grep").contains("not in the tools allow list"), "{}", answer("call_grep"));
+        assert!(generated.iter().all(|m| !m.content.content_text_only().contains("hunter2")), "{:?}", generated);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_safe_roots_refuse_paths_outside() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().to_path_buf();
+        std::fs::create_dir_all(workspace.join("src")).unwrap();
+        std::fs::write(workspace.join("src").join("main.rs"), "fn main() {}\n").unwrap();
+        let roots = vec![workspace.clone()];
+        let args = |name: &str, value: &str| HashMap::from([(name.to_string(), Value::String(value.to_string()))]);
+
+        let err = check_paths_under_safe_roots(&args("path", "/etc/passwd"), &roots).unwrap_err();
+        assert!(err.contains("/etc/passwd"), "{}", err);
+        assert!(err.contains(&workspace.to_string_lossy().to_string()), "{}", err);
+
+        let inside = workspace.join("src").join("main.rs").to_string_lossy().to_string();
+        assert!(check_paths_under_safe_roots(&args("path", &inside), &roots).is_ok());
+        assert!(check_paths_under_safe_roots(&args("paths", &format!("{}, src/main.rs, main.rs:1-5", inside)), &roots).is_ok());
+        assert!(check_paths_under_safe_roots(&args("paths", &format!("{}, ../../etc/passwd", inside)), &roots).is_err());
+        assert!(check_paths_under_safe_roots(&args("file_path", &format!("{}/src/../../outside.txt", workspace.display())), &roots).is_err());
+        assert!(check_paths_under_safe_roots(&args("symbol", "/etc/passwd"), &roots).is_ok());
+        assert!(check_paths_under_safe_roots(&args("workdir", "/etc"), &roots).is_err());
+        assert!(check_paths_under_safe_roots(&args("workdir", "src"), &roots).is_ok());
+
+        std::os::unix::fs::symlink("/etc", workspace.join("etc_link")).ok();
+        assert!(check_paths_under_safe_roots(&args("path", "etc_link/passwd"), &roots).is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use std::path::{Path, PathBuf};
+use tree_sitter::Node;
+
+
+// Relative to the project dir it is in, or as is
+pub fn short_path(path: &PathBuf, project_dirs: &Vec<PathBuf>) -> String {
+    project_dirs.iter()
+        .find_map(|dir| path.strip_prefix(dir).ok())
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+// `subdir` is relative to a project dir or absolute, compared by path components: "src" is not a prefix of "src2/main.rs"
+pub fn is_under_subdir(path: &PathBuf, subdir: &str, project_dirs: &Vec<PathBuf>) -> bool {
+    path.starts_with(subdir) || project_dirs.iter().any(|dir| path.strip_prefix(dir).map_or(false, |rel| rel.starts_with(Path::new(subdir))))
+}
+
+// The source of a syntax tree node, empty if the node doesn't belong to `text`
+pub fn node_text<'a>(node: &Node, text: &'a str) -> &'a str {
+    text.get(node.byte_range()).unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subdir_matches_whole_components() {
+        let project_dirs = vec![PathBuf::from("/home/user/project")];
+        let under = |p: &str, d: &str| is_under_subdir(&PathBuf::from(p), d, &project_dirs);
+        assert!(under("/home/user/project/src/main.rs", "src"));
+        assert!(under("/home/user/project/src/util/io.rs", "src/util"));
+        assert!(!under("/home/user/project/src2/main.rs", "src"));
+        assert!(!under("/home/user/project/srcfile.rs", "src"));
+        assert!(under("/home/user/project/src2/main.rs", "/home/user/project/src2"));
+        assert!(!under("/home/user/project/src2/main.rs", "/home/user/project/src"));
+
+        assert_eq!(short_path(&PathBuf::from("/home/user/project/src/main.rs"), &project_dirs), "src/main.rs");
+        assert_eq!(short_path(&PathBuf::from("/elsewhere/main.rs"), &project_dirs), "/elsewhere/main.rs");
+    }
+}
@@ -96,6 +96,7 @@ pub async fn try_to_download_trajectories(gcx: Arc<ARwLock<GlobalContext>>) -> R
             m_project,
             m_payload,
             m_origin,
+            &vec![],
         ).await {
             Ok(memid) => info!("memory added with ID: {}", memid),
             Err(err) => info!("failed to add memory: {}", err),
@@ -9,12 +9,12 @@ use crate::background_tasks::BackgroundTasksHolder;
 use crate::caps::get_custom_embedding_api_key;
 use crate::fetch_embedding;
 use crate::global_context::{CommandLine, GlobalContext};
-use crate::knowledge::{lance_search, MemoriesDatabase};
+use crate::knowledge::{memories_search_by_embedding, MemoriesDatabase};
 use crate::trajectories::try_to_download_trajectories;
 use crate::vecdb::vdb_cache::VecDBCache;
 use crate::vecdb::vdb_lance::VecDBHandler;
 use crate::vecdb::vdb_structs::{MemoRecord, MemoSearchResult, SearchResult, VecDbStatus, VecdbConstants, VecdbSearch};
-use crate::vecdb::vdb_thread::{vecdb_start_background_tasks, vectorizer_enqueue_dirty_memory, vectorizer_enqueue_files, FileVectorizerService};
+use crate::vecdb::vdb_thread::{vecdb_globs_from_arg, vecdb_start_background_tasks, vectorizer_enqueue_dirty_memory, vectorizer_enqueue_files, FileVectorizerService};
 
 
 fn model_to_rejection_threshold(embedding_model: &str) -> f32 {
@@ -129,7 +129,10 @@ async fn do_i_need_to_reload_vecdb(
         }
     };
 
-    let vecdb_max_files = gcx.read().await.cmdline.vecdb_max_files;
+    let (vecdb_max_files, vecdb_include_globs, vecdb_exclude_globs) = {
+        let gcx_locked = gcx.read().await;
+        (gcx_locked.cmdline.vecdb_max_files, vecdb_globs_from_arg(&gcx_locked.cmdline.vecdb_include), vecdb_globs_from_arg(&gcx_locked.cmdline.vecdb_exclude))
+    };
     let mut consts = {
         let caps_locked = caps.read().unwrap();
         let mut b = caps_locked.embedding_batch;
@@ -150,6 +153,8 @@ async fn do_i_need_to_reload_vecdb(
             endpoint_embeddings_style: caps_locked.endpoint_embeddings_style.clone(),
             splitter_window_size: caps_locked.embedding_n_ctx / 2,
             vecdb_max_files: vecdb_max_files,
+            vecdb_include_globs,
+            vecdb_exclude_globs,
         }
     };
 
@@ -287,7 +292,8 @@ pub async fn memories_add(
     m_goal: &str,
     m_project: &str,
     m_payload: &str,    // TODO: upgrade to serde_json::Value
-    m_origin: &str
+    m_origin: &str,
+    m_tags: &Vec<String>,
 ) -> Result<String, String> {
     let (memdb, vectorizer_service) = {
         let vec_db_guard = vec_db.lock().await;
@@ -297,7 +303,7 @@ pub async fn memories_add(
 
     let memid = {
         let mut memdb_locked = memdb.lock().await;
-        let x = memdb_locked.permdb_add(m_type, m_goal, m_project, m_payload, m_origin)?;
+        let x = memdb_locked.permdb_add(m_type, m_goal, m_project, m_payload, m_origin, m_tags)?;
         memdb_locked.dirty_memids.push(x.clone());
         x
     };
@@ -428,17 +434,36 @@ pub async fn memories_update(
     Ok(updated_cnt)
 }
 
+pub async fn memories_select_by_tag(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    tag: &str,
+) -> Result<Vec<MemoRecord>, String> {
+    let vec_db = gcx.read().await.vec_db.clone();
+    let memdb = {
+        let vec_db_guard = vec_db.lock().await;
+        let vec_db = vec_db_guard.as_ref().ok_or("VecDb is not initialized")?;
+        vec_db.memdb.clone()
+    };
+    let memdb_locked = memdb.lock().await;
+    memdb_locked.permdb_select_by_tag(tag)
+}
+
 pub async fn memories_search(
     gcx: Arc<ARwLock<GlobalContext>>,
     query: &String,
     top_n: usize,
 ) -> Result<MemoSearchResult, String> {
-    let vec_db = gcx.read().await.vec_db.clone();
-    fn calculate_score(distance: f32, _times_used: i32) -> f32 {
-        distance
-        // distance - (times_used as f32) * 0.01
-    }
+    memories_search_with_tag(gcx, query, top_n, None).await
+}
 
+// With a tag, only memories carrying it compete for top_n, untagged ones are never found this way
+pub async fn memories_search_with_tag(
+    gcx: Arc<ARwLock<GlobalContext>>,
+    query: &String,
+    top_n: usize,
+    tag: Option<&str>,
+) -> Result<MemoSearchResult, String> {
+    let vec_db = gcx.read().await.vec_db.clone();
     let t0 = std::time::Instant::now();
     let (memdb, vecdb_emb_client, constants) = {
         let vec_db_guard = vec_db.lock().await;
@@ -469,16 +494,11 @@ pub async fn memories_search(
     }
     info!("search query {:?}, it took {:.3}s to vectorize the query", query, t0.elapsed().as_secs_f64());
 
-    let lance_results = match lance_search(memdb.clone(), &embedding[0], top_n).await {
-        Ok(res) => res,
-        Err(err) => { return Err(err.to_string()) }
+    let only_memids = match tag {
+        Some(tag) => Some(memdb.lock().await.permdb_select_by_tag(tag)?.into_iter().map(|m| m.memid).collect::<Vec<_>>()),
+        None => None,
     };
-    let mut results: Vec<MemoRecord> = memdb.lock().await.permdb_fillout_records(lance_results).await?;
-    results.sort_by(|a, b| {
-        let score_a = calculate_score(a.distance, a.mstat_times_used);
-        let score_b = calculate_score(b.distance, b.mstat_times_used);
-        score_a.partial_cmp(&score_b).unwrap_or(std::cmp::Ordering::Equal)
-    });
+    let results = memories_search_by_embedding(memdb.clone(), &embedding[0], top_n, only_memids.as_ref()).await?;
     Ok(MemoSearchResult { query_text: query.clone(), results })
 }
 
@@ -550,6 +570,7 @@ impl VecdbSearch for VecDb {
         api_key: &String,
     ) -> Result<SearchResult, String> {
         // TODO: move out of struct, replace self with Arc
+        crate::metrics::inc(&crate::metrics::METRICS.vecdb_searches);
         let t0 = std::time::Instant::now();
         let embedding_mb = fetch_embedding::get_embedding_with_retry(
             self.vecdb_emb_client.clone(),
@@ -44,6 +44,28 @@ pub fn cosine_distance(vec1: &Vec<f32>, vec2: &Vec<f32>) -> f32 {
     1.0 - cosine_similarity(vec1, vec2)
 }
 
+// Nested workspace folders are possible, the innermost one wins
+pub fn workspace_folder_of(file_path: &PathBuf, workspace_folders: &Vec<PathBuf>) -> String {
+    workspace_folders.iter()
+        .filter(|folder| file_path.starts_with(folder))
+        .max_by_key(|folder| folder.components().count())
+        .map(|folder| folder.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+// Default search scope: the workspace folder of the active file. No filter if there's only one folder,
+// no active file, or the active file is outside of the workspace -- then it's the whole index as before.
+pub fn workspace_scope_filter(active_file: &Option<PathBuf>, workspace_folders: &Vec<PathBuf>) -> Option<String> {
+    if workspace_folders.len() < 2 {
+        return None;
+    }
+    let folder = workspace_folder_of(active_file.as_ref()?, workspace_folders);
+    if folder.is_empty() {
+        return None;
+    }
+    Some(format!("(workspace_folder = '{}')", folder.replace("'", "''")))
+}
+
 
 impl VecDBHandler {
     pub async fn init(embedding_size: i32) -> Result<VecDBHandler, String> {
@@ -66,6 +88,7 @@ impl VecDBHandler {
             Field::new("scope", DataType::Utf8, true),
             Field::new("start_line", DataType::UInt64, true),
             Field::new("end_line", DataType::UInt64, true),
+            Field::new("workspace_folder", DataType::Utf8, true),
         ]));
 
         let batches_iter = RecordBatchIterator::new(vec![].into_iter().map(Ok), schema.clone());
@@ -133,6 +156,7 @@ impl VecDBHandler {
         let scopes: Vec<String> = records.iter().map(|x| x.file_path.to_str().unwrap_or("No filename").to_string()).collect();
         let start_lines: Vec<u64> = records.iter().map(|x| x.start_line).collect();
         let end_lines: Vec<u64> = records.iter().map(|x| x.end_line).collect();
+        let workspace_folders: Vec<String> = records.iter().map(|x| x.workspace_folder.clone()).collect();
         let data_batches_iter = RecordBatchIterator::new(
             vec![RecordBatch::try_new(
                 self.schema.clone(),
@@ -141,6 +165,7 @@ impl VecDBHandler {
                     Arc::new(StringArray::from(scopes.clone())),
                     Arc::new(UInt64Array::from(start_lines.clone())),
                     Arc::new(UInt64Array::from(end_lines.clone())),
+                    Arc::new(StringArray::from(workspace_folders)),
                 ],
             )],
             self.schema.clone(),
@@ -243,6 +268,10 @@ impl VecDBHandler {
                     .value(idx),
                 distance,
                 usefulness: 0.0,
+                workspace_folder: as_string_array(record_batch.column_by_name("workspace_folder")
+                    .expect("Missing column 'workspace_folder'"))
+                    .value(idx)
+                    .to_string(),
             })
         }).collect()
     }
@@ -284,3 +313,48 @@ impl VecDBHandler {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(file: &str, vector: Vec<f32>, workspace_folders: &Vec<PathBuf>) -> VecdbRecord {
+        VecdbRecord {
+            vector: Some(vector),
+            file_path: PathBuf::from(file),
+            start_line: 0,
+            end_line: 10,
+            distance: -1.0,
+            usefulness: 0.0,
+            workspace_folder: workspace_folder_of(&PathBuf::from(file), workspace_folders),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_default_search_stays_in_active_workspace_folder() {
+        let workspace_folders = vec![PathBuf::from("/home/user/backend"), PathBuf::from("/home/user/frontend")];
+        let mut handler = VecDBHandler::init(2).await.unwrap();
+        handler.vecdb_records_add(&vec![
+            record("/home/user/backend/src/auth.rs", vec![1.0, 0.2], &workspace_folders),
+            record("/home/user/backend/src/db.rs", vec![0.2, 1.0], &workspace_folders),
+            record("/home/user/frontend/src/login.ts", vec![1.0, 0.0], &workspace_folders),
+        ]).await;
+
+        let active_file = Some(PathBuf::from("/home/user/backend/src/main.rs"));
+        let filter = workspace_scope_filter(&active_file, &workspace_folders);
+        assert_eq!(filter, Some("(workspace_folder = '/home/user/backend')".to_string()));
+        let results = handler.vecdb_search(&vec![1.0, 0.0], 10, filter).await.unwrap();
+        let files: Vec<String> = results.iter().map(|r| r.file_path.to_string_lossy().to_string()).collect();
+        assert_eq!(files, vec!["/home/user/backend/src/auth.rs", "/home/user/backend/src/db.rs"]);
+        assert!(results.iter().all(|r| r.workspace_folder == "/home/user/backend"));
+
+        // searching all folders is still there, the closest match is in the other folder
+        let results = handler.vecdb_search(&vec![1.0, 0.0], 10, None).await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].file_path, PathBuf::from("/home/user/frontend/src/login.ts"));
+
+        assert_eq!(workspace_scope_filter(&None, &workspace_folders), None);
+        assert_eq!(workspace_scope_filter(&Some(PathBuf::from("/tmp/scratch.rs")), &workspace_folders), None);
+        assert_eq!(workspace_scope_filter(&active_file, &vec![PathBuf::from("/home/user/backend")]), None);
+    }
+}
@@ -31,6 +31,8 @@ pub struct VecdbConstants {
     pub endpoint_embeddings_style: String,
     pub splitter_window_size: usize,
     pub vecdb_max_files: usize,
+    pub vecdb_include_globs: Vec<String>,  // empty means everything
+    pub vecdb_exclude_globs: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -56,6 +58,8 @@ pub struct VecdbRecord {
     pub end_line: u64,
     pub distance: f32,
     pub usefulness: f32,
+    #[serde(default)]
+    pub workspace_folder: String,   // the folder this file belongs to, "" if it's outside of all of them
 }
 
 #[derive(Debug, Clone)]
@@ -94,6 +98,8 @@ pub struct MemoRecord {
     pub mstat_correct: f64,
     pub mstat_relevant: f64,
     pub mstat_times_used: i32,
+    #[serde(default)]
+    pub m_tags: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -1,26 +1,30 @@
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use std::collections::HashSet;
 use std::collections::{HashMap, VecDeque};
 use std::io::Write;
 use std::ops::Div;
 use std::option::Option;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::SystemTime;
+use glob::Pattern;
 use tokio::sync::{Mutex as AMutex, Notify as ANotify, RwLock as ARwLock};
 use tokio::task::JoinHandle;
 use tracing::{info, warn};
 
 use crate::ast::file_splitter::AstBasedFileSplitter;
-use crate::fetch_embedding::get_embedding_with_retry;
+use crate::fetch_embedding::get_embedding_per_item;
 use crate::files_in_workspace::{is_path_to_enqueue_valid, Document};
 use crate::global_context::GlobalContext;
 use crate::knowledge::{vectorize_dirty_memories, MemoriesDatabase};
+use crate::scratchpads::comments_parser::{strip_comments, CommentStripping};
 use crate::vecdb::vdb_cache::VecDBCache;
-use crate::vecdb::vdb_lance::VecDBHandler;
+use crate::vecdb::vdb_lance::{workspace_folder_of, VecDBHandler};
 use crate::vecdb::vdb_structs::{SimpleTextHashVector, SplitResult, VecDbStatus, VecdbConstants, VecdbRecord};
 
 const DEBUG_WRITE_VECDB_FILES: bool = false;
 const COOLDOWN_SECONDS: u64 = 10;
+const VECTORIZE_REQUEUE_MAX: usize = 3;
 
 
 enum MessageToVecdbThread {
@@ -40,6 +44,7 @@ pub struct FileVectorizerService {
     vecdb_todo: Arc<AMutex<VecDeque<MessageToVecdbThread>>>,
 }
 
+// Returns the splits that didn't get a vector, the caller should put their files back in the queue
 async fn vectorize_batch_from_q(
     run_actual_model_on_these: &mut Vec<SplitResult>,
     ready_to_vecdb: &mut Vec<VecdbRecord>,
@@ -50,11 +55,11 @@ async fn vectorize_batch_from_q(
     vecdb_cache_arc: Arc<AMutex<VecDBCache>>,
     #[allow(non_snake_case)]
     B: usize,
-) -> Result<(), String> {
+) -> Vec<SplitResult> {
     let batch = run_actual_model_on_these.drain(..B.min(run_actual_model_on_these.len())).collect::<Vec<_>>();
     assert!(batch.len() > 0);
 
-    let batch_result = match get_embedding_with_retry(
+    let batch_result = get_embedding_per_item(
         client.clone(),
         &constants.endpoint_embeddings_style.clone(),
         &constants.embedding_model.clone(),
@@ -62,48 +67,46 @@ async fn vectorize_batch_from_q(
         batch.iter().map(|x| x.window_text.clone()).collect(),
         api_key,
         10,
-    ).await {
-        Ok(res) => res,
-        Err(e) => {
-            let mut vstatus_locked = vstatus.lock().await;
-            vstatus_locked.vecdb_errors.entry(e.clone()).and_modify(|counter| *counter += 1).or_insert(1);
-            return Err(e);
-        }
-    };
-
-    if batch_result.len() != batch.len() {
-        return Err(format!("vectorize: batch_result.len() != batch.len(): {} vs {}", batch_result.len(), batch.len()));
-    }
+    ).await;
 
+    let mut failed = vec![];
+    let mut send_to_cache = vec![];
     {
         let mut vstatus_locked = vstatus.lock().await;
         vstatus_locked.requests_made_since_start += 1;
-        vstatus_locked.vectors_made_since_start += batch_result.len();
-    }
-
-    let mut send_to_cache = vec![];
-    for (i, data_res) in batch.iter().enumerate() {
-        if batch_result[i].is_empty() {
-            info!("skipping an empty embedding split");
-            continue;
-        }
-        ready_to_vecdb.push(
-            VecdbRecord {
-                vector: Some(batch_result[i].clone()),
-                file_path: data_res.file_path.clone(),
-                start_line: data_res.start_line,
-                end_line: data_res.end_line,
-                distance: -1.0,
-                usefulness: 0.0,
-            }
-        );
-        send_to_cache.push(
-            SimpleTextHashVector {
-                vector: Some(batch_result[i].clone()),
-                window_text: data_res.window_text.clone(),
-                window_text_hash: data_res.window_text_hash.clone(),
+        vstatus_locked.vectors_made_since_start += batch_result.iter().filter(|x| x.is_ok()).count();
+        for (data_res, vector_mb) in batch.into_iter().zip(batch_result.into_iter()) {
+            let vector = match vector_mb {
+                Ok(vector) => vector,
+                Err(e) => {
+                    vstatus_locked.vecdb_errors.entry(e).and_modify(|counter| *counter += 1).or_insert(1);
+                    failed.push(data_res);
+                    continue;
+                }
+            };
+            if vector.is_empty() {
+                info!("skipping an empty embedding split");
+                continue;
             }
-        );
+            ready_to_vecdb.push(
+                VecdbRecord {
+                    vector: Some(vector.clone()),
+                    file_path: data_res.file_path.clone(),
+                    start_line: data_res.start_line,
+                    end_line: data_res.end_line,
+                    distance: -1.0,
+                    usefulness: 0.0,
+                    workspace_folder: String::new(),   // tagged in _send_to_vecdb
+                }
+            );
+            send_to_cache.push(
+                SimpleTextHashVector {
+                    vector: Some(vector),
+                    window_text: data_res.window_text,
+                    window_text_hash: data_res.window_text_hash,
+                }
+            );
+        }
     }
 
     if send_to_cache.len() > 0 {
@@ -117,7 +120,28 @@ async fn vectorize_batch_from_q(
 
     tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;  // be nice to the server: up to 60 requests per minute
 
-    Ok(())
+    failed
+}
+
+// Vectors that worked are in the cache now, so the next pass over the file only asks the model about the failed splits.
+// Returns files we gave up on.
+fn requeue_failed_splits(
+    failed: &Vec<SplitResult>,
+    requeue_attempts: &mut HashMap<String, usize>,
+    last_updated: &mut HashMap<String, SystemTime>,
+) -> Vec<String> {
+    let mut gave_up = vec![];
+    let cpaths: IndexSet<String> = failed.iter().map(|x| x.file_path.to_string_lossy().to_string()).collect();
+    for cpath in cpaths {
+        let attempts = requeue_attempts.entry(cpath.clone()).or_insert(0);
+        *attempts += 1;
+        if *attempts > VECTORIZE_REQUEUE_MAX {
+            gave_up.push(cpath);
+            continue;
+        }
+        last_updated.insert(cpath, SystemTime::now());
+    }
+    gave_up
 }
 
 async fn from_splits_to_vecdb_records_applying_cache(
@@ -147,6 +171,7 @@ async fn from_splits_to_vecdb_records_applying_cache(
                     end_line: split.end_line,
                     distance: -1.0,
                     usefulness: 0.0,
+                    workspace_folder: String::new(),   // tagged in _send_to_vecdb
                 });
             }
         } else if let Err(err) = vectors_maybe {
@@ -188,7 +213,13 @@ async fn vectorize_thread(
         )
     };
 
+    let comment_stripping = CommentStripping::from_arg(&gcx.read().await.cmdline.vecdb_strip_comments).unwrap_or_else(|e| {
+        warn!("{}, comments are not stripped", e);
+        CommentStripping::Off
+    });
+
     let mut last_updated: HashMap<String, SystemTime> = HashMap::new();
+    let mut requeue_attempts: HashMap<String, usize> = HashMap::new();
     loop {
         let mut work_on_one: Option<MessageToVecdbThread> = None;
         let current_time = SystemTime::now();
@@ -244,7 +275,7 @@ async fn vectorize_thread(
             run_actual_model_on_these.len() > 0 && flush ||
                 run_actual_model_on_these.len() >= constants.embedding_batch
             {
-                if let Err(err) = vectorize_batch_from_q(
+                let failed = vectorize_batch_from_q(
                     &mut run_actual_model_on_these,
                     &mut ready_to_vecdb,
                     vstatus.clone(),
@@ -253,9 +284,14 @@ async fn vectorize_thread(
                     &api_key,
                     vecdb_cache_arc.clone(),
                     constants.embedding_batch,
-                ).await {
-                    tracing::error!("{}", err);
-                    continue;
+                ).await;
+                if !failed.is_empty() {
+                    tracing::error!("{} splits didn't get vectors, their files will be vectorized again", failed.len());
+                    for cpath in requeue_failed_splits(&failed, &mut requeue_attempts, &mut last_updated) {
+                        warn!("giving up on {} after {} attempts, some of it is not in vecdb", cpath, VECTORIZE_REQUEUE_MAX + 1);
+                        let mut vstatus_locked = vstatus.lock().await;
+                        vstatus_locked.vecdb_errors.entry(format!("gave up on {}", cpath)).and_modify(|counter| *counter += 1).or_insert(1);
+                    }
                 }
             } else {
                 break;
@@ -265,7 +301,8 @@ async fn vectorize_thread(
         if flush {
             assert!(run_actual_model_on_these.len() == 0);
             // This function assumes it can delete records with the filenames mentioned, therefore assert above
-            _send_to_vecdb(vecdb_handler_arc.clone(), &mut ready_to_vecdb).await;
+            let workspace_folders = gcx.read().await.documents_state.workspace_folders.lock().unwrap().clone();
+            _send_to_vecdb(vecdb_handler_arc.clone(), &mut ready_to_vecdb, &workspace_folders).await;
         }
 
         if (files_unprocessed + 99).div(100) != (reported_unprocessed + 99).div(100) {
@@ -300,6 +337,7 @@ async fn vectorize_thread(
                         let done = vstatus_locked.state == "done";
                         if !done {
                             files_total = 0;
+                            requeue_attempts.clear();
                             vstatus_locked.files_unprocessed = 0;
                             vstatus_locked.files_total = 0;
                             vstatus_locked.state = "done".to_string();
@@ -339,7 +377,7 @@ async fn vectorize_thread(
         let last_30_chars = crate::nicer_logs::last_n_chars(&cpath, 30);
 
         // Not from memory, vecdb works on files from disk, because they change less
-        let mut doc: Document = Document { doc_path: cpath.clone().into(), doc_text: None };
+        let mut doc: Document = Document::new(&cpath.clone().into());
         if let Err(_) = doc.update_text_from_disk(gcx.clone()).await {
             info!("{} cannot read, deleting from index", last_30_chars);  // don't care what the error is, trivial (or privacy)
             vecdb_handler_arc.lock().await.vecdb_records_remove(vec![doc.doc_path.to_string_lossy().to_string()]).await;
@@ -351,6 +389,12 @@ async fn vectorize_thread(
             continue;
         }
 
+        if comment_stripping != CommentStripping::Off {
+            if let Ok(text) = doc.text_as_string() {
+                doc.update_text(&strip_comments(&text, &doc.doc_path, comment_stripping));
+            }
+        }
+
         let file_splitter = AstBasedFileSplitter::new(constants.splitter_window_size);
         let mut splits = file_splitter.vectorization_split(&doc, None, gcx.clone(), constants.vectorizer_n_ctx).await.unwrap_or_else(|err| {
             info!("{}", err);
@@ -395,7 +439,11 @@ async fn vectorize_thread(
 async fn _send_to_vecdb(
     vecdb_handler_arc: Arc<AMutex<VecDBHandler>>,
     ready_to_vecdb: &mut Vec<VecdbRecord>,
+    workspace_folders: &Vec<PathBuf>,
 ) {
+    for rec in ready_to_vecdb.iter_mut() {
+        rec.workspace_folder = workspace_folder_of(&rec.file_path, workspace_folders);
+    }
     while !ready_to_vecdb.is_empty() {
         let unique_file_paths: HashSet<String> = ready_to_vecdb.iter()
             .map(|x| x.file_path.to_str().unwrap_or("No filename").to_string())
@@ -480,13 +528,36 @@ pub async fn vectorizer_enqueue_dirty_memory(
     vstatus_notify.notify_waiters();
 }
 
-fn _filter_docs_to_enqueue(docs: &Vec<String>) -> Vec<String> {
+// Bad globs are dropped with a warning, the rest still work
+pub fn vecdb_globs_from_arg(arg: &str) -> Vec<String> {
+    arg.split(',').map(|g| g.trim()).filter(|g| !g.is_empty()).filter_map(|g| match Pattern::new(g) {
+        Ok(_) => Some(g.to_string()),
+        Err(e) => {
+            warn!("vecdb: ignoring bad glob {:?}: {}", g, e);
+            None
+        }
+    }).collect()
+}
+
+// Only decides what gets embedded, the AST queue gets the same files unfiltered
+fn vecdb_globs_allow(path: &PathBuf, include_globs: &Vec<String>, exclude_globs: &Vec<String>) -> Result<(), String> {
+    let matches = |globs: &Vec<String>| globs.iter().any(|g| Pattern::new(g).map(|p| p.matches_path(path)).unwrap_or(false));
+    if matches(exclude_globs) {
+        return Err("excluded by --vecdb-exclude".to_string());
+    }
+    if !include_globs.is_empty() && !matches(include_globs) {
+        return Err("not in --vecdb-include".to_string());
+    }
+    Ok(())
+}
+
+fn _filter_docs_to_enqueue(docs: &Vec<String>, include_globs: &Vec<String>, exclude_globs: &Vec<String>) -> Vec<String> {
     let mut rejected_reasons = HashMap::new();
     let mut filtered_docs = vec![];
 
     for d in docs {
         let path: std::path::PathBuf = d.clone().into();
-        match is_path_to_enqueue_valid(&path) {
+        match is_path_to_enqueue_valid(&path).and_then(|_| vecdb_globs_allow(&path, include_globs, exclude_globs)) {
             Ok(_) => {
                 filtered_docs.push(d.clone());
             }
@@ -510,16 +581,18 @@ pub async fn vectorizer_enqueue_files(
     process_immediately: bool,
 ) {
     info!("adding {} files", documents.len());
-    let documents = _filter_docs_to_enqueue(documents);
-    let (vecdb_todo, vstatus, vstatus_notify, vecdb_max_files) = {
+    let (vecdb_todo, vstatus, vstatus_notify, vecdb_max_files, include_globs, exclude_globs) = {
         let service = vservice.lock().await;
         (
             service.vecdb_todo.clone(),
             service.vstatus.clone(),
             service.vstatus_notify.clone(),
-            service.constants.vecdb_max_files
+            service.constants.vecdb_max_files,
+            service.constants.vecdb_include_globs.clone(),
+            service.constants.vecdb_exclude_globs.clone(),
         )
     };
+    let documents = _filter_docs_to_enqueue(documents, &include_globs, &exclude_globs);
     let mut documents_my_copy = documents.clone();
     if documents_my_copy.len() > vecdb_max_files {
         info!("that's more than {} allowed in the command line, reduce the number", vecdb_max_files);
@@ -544,3 +617,128 @@ pub async fn vectorizer_enqueue_files(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    // openai-style /v1/embeddings on the mockito server, answers exactly this batch once
+    fn embeddings_mock(input: &[&str], accept: bool) -> mockito::Mock {
+        let mock = mockito::mock("POST", "/v1/embeddings")
+            .match_body(mockito::Matcher::Json(json!({"input": input, "model": "mock-embeddings"})));
+        let mock = if accept {
+            let data: Vec<Value> = input.iter().enumerate().map(|(i, x)| json!({"index": i, "embedding": [x.len() as f32, 1.0]})).collect();
+            mock.with_header("content-type", "application/json").with_body(json!({"data": data}).to_string())
+        } else {
+            mock.with_status(400).with_body(json!({"error": "cannot tokenize the input"}).to_string())
+        };
+        mock.expect(1).create()
+    }
+
+    fn split(file: &str, text: &str, line: u64) -> SplitResult {
+        SplitResult {
+            file_path: PathBuf::from(file),
+            window_text: text.to_string(),
+            window_text_hash: format!("hash-{}", text),
+            start_line: line,
+            end_line: line,
+            symbol_path: "".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_one_failed_item_is_requeued_and_retried() {
+        // whole batch, then halves, then the two items of the bad half; a 400 is not retried as is
+        let upstream = vec![
+            embeddings_mock(&["fn a() {}", "poison", "fn c() {}", "fn d() {}"], false),
+            embeddings_mock(&["fn a() {}", "poison"], false),
+            embeddings_mock(&["fn c() {}", "fn d() {}"], true),
+            embeddings_mock(&["fn a() {}"], true),
+            embeddings_mock(&["poison"], false),
+        ];
+        let constants = VecdbConstants {
+            embedding_model: "mock-embeddings".to_string(),
+            embedding_size: 2,
+            embedding_batch: 4,
+            tokenizer: None,
+            vectorizer_n_ctx: 512,
+            endpoint_embeddings_template: format!("{}/v1/embeddings", mockito::server_url()),
+            endpoint_embeddings_style: "openai".to_string(),
+            splitter_window_size: 512,
+            vecdb_max_files: 100,
+            vecdb_include_globs: vec![],
+            vecdb_exclude_globs: vec![],
+        };
+        let client = Arc::new(AMutex::new(reqwest::Client::new()));
+        let cache_dir = tempfile::tempdir().unwrap();
+        let vecdb_cache = Arc::new(AMutex::new(VecDBCache::init(&cache_dir.path().to_path_buf(), &constants.embedding_model, constants.embedding_size).await.unwrap()));
+        let vstatus = Arc::new(AMutex::new(VecDbStatus {
+            files_unprocessed: 0,
+            files_total: 0,
+            requests_made_since_start: 0,
+            vectors_made_since_start: 0,
+            db_size: 0,
+            db_cache_size: 0,
+            state: "parsing".to_string(),
+            queue_additions: false,
+            vecdb_max_files_hit: false,
+            vecdb_errors: IndexMap::new(),
+        }));
+
+        let mut run_actual_model_on_these = vec![
+            split("/ws/a.rs", "fn a() {}", 1),
+            split("/ws/b.rs", "poison", 1),
+            split("/ws/a.rs", "fn c() {}", 5),
+            split("/ws/a.rs", "fn d() {}", 9),
+        ];
+        let mut ready_to_vecdb = vec![];
+        let failed = vectorize_batch_from_q(&mut run_actual_model_on_these, &mut ready_to_vecdb, vstatus.clone(), client.clone(), &constants, &"key".to_string(), vecdb_cache.clone(), 4).await;
+        assert!(run_actual_model_on_these.is_empty());
+        assert_eq!(ready_to_vecdb.iter().map(|r| r.start_line).collect::<Vec<_>>(), vec![1, 5, 9]);
+        assert!(ready_to_vecdb.iter().all(|r| r.file_path == PathBuf::from("/ws/a.rs")));
+        assert_eq!(failed.iter().map(|s| s.window_text.clone()).collect::<Vec<_>>(), vec!["poison"]);
+        upstream.iter().for_each(|m| m.assert());
+        assert_eq!(vstatus.lock().await.vectors_made_since_start, 3);
+
+        // the failed split's file goes back in the queue, not dropped
+        let mut requeue_attempts = HashMap::new();
+        let mut last_updated = HashMap::new();
+        let gave_up = requeue_failed_splits(&failed, &mut requeue_attempts, &mut last_updated);
+        assert!(gave_up.is_empty());
+        assert_eq!(last_updated.keys().cloned().collect::<Vec<_>>(), vec!["/ws/b.rs"]);
+
+        // next pass, the endpoint takes it now
+        drop(upstream);
+        let upstream = embeddings_mock(&["poison"], true);
+        let mut run_actual_model_on_these = failed.clone();
+        let failed_again = vectorize_batch_from_q(&mut run_actual_model_on_these, &mut ready_to_vecdb, vstatus.clone(), client.clone(), &constants, &"key".to_string(), vecdb_cache.clone(), 4).await;
+        assert!(failed_again.is_empty());
+        assert_eq!(ready_to_vecdb.len(), 4);
+        assert_eq!(ready_to_vecdb[3].file_path, PathBuf::from("/ws/b.rs"));
+        upstream.assert();
+
+        // a split that never works is given up on eventually
+        for _ in 1..VECTORIZE_REQUEUE_MAX {
+            assert!(requeue_failed_splits(&failed, &mut requeue_attempts, &mut last_updated).is_empty());
+        }
+        assert_eq!(requeue_failed_splits(&failed, &mut requeue_attempts, &mut last_updated), vec!["/ws/b.rs"]);
+    }
+
+    #[tokio::test]
+    async fn test_excluded_files_are_not_vectorized_but_ast_indexed() {
+        let files: Vec<String> = ["/ws/src/main.rs", "/ws/tests/test_main.rs", "/ws/vendor/lib/dep.py", "/ws/docs/intro.md", "/ws/src/util.py"]
+            .iter().map(|x| x.to_string()).collect();
+        let exclude = vecdb_globs_from_arg("*/tests/*, */vendor/*, [bad");
+        assert_eq!(exclude, vec!["*/tests/*", "*/vendor/*"]);
+        assert_eq!(_filter_docs_to_enqueue(&files, &vec![], &exclude), vec!["/ws/src/main.rs", "/ws/docs/intro.md", "/ws/src/util.py"]);
+        assert_eq!(_filter_docs_to_enqueue(&files, &vecdb_globs_from_arg("*/src/*"), &exclude), vec!["/ws/src/main.rs", "/ws/src/util.py"]);
+        assert_eq!(_filter_docs_to_enqueue(&files, &vec![], &vec![]), files);
+
+        // the same list goes to the AST queue unfiltered, see enqueue_some_docs()
+        let ast_service = crate::ast::ast_indexer_thread::ast_service_init("".to_string(), 100).await;
+        crate::ast::ast_indexer_thread::ast_indexer_enqueue_files(ast_service.clone(), &files, false).await;
+        let ast_todo: Vec<String> = ast_service.lock().await.ast_todo.iter().cloned().collect();
+        assert!(ast_todo.contains(&"/ws/tests/test_main.rs".to_string()) && ast_todo.contains(&"/ws/vendor/lib/dep.py".to_string()), "{:?}", ast_todo);
+    }
+}